@@ -0,0 +1,102 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Benchmark evaluation of comprehensions with temporary lists.
+//!
+//! Compare the scratch arena against the default by running this twice:
+//!
+//!     cargo bench --bench comprehension
+//!     cargo bench --bench comprehension --features arena
+//!
+//! For every case, this prints the number of heap allocations of one
+//! evaluation, and its minimum and median time. The allocation count is
+//! deterministic, timings vary between runs.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rcl::eval::StrictMode;
+use rcl::loader::Loader;
+use rcl::tracer::VoidTracer;
+
+const CASES: &[(&str, &str)] = &[
+    (
+        "pairs",
+        "[for x in std.range(0, 3000): for y in [x, x + 1]: y]",
+    ),
+    (
+        "nested",
+        "[for x in std.range(0, 100): for y in [for z in std.range(0, 50): z * x]: y]",
+    ),
+    (
+        "dict",
+        "{for i in std.range(0, 2000): for k in [f\"a{i}\", f\"b{i}\", f\"c{i}\"]: k: i}",
+    ),
+    (
+        "no_temporaries",
+        "let ys = [0, 1]; [for x in std.range(0, 3000): for y in ys: x + y]",
+    ),
+];
+
+/// The system allocator, but it counts allocations.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Evaluate the input, and return how long it took.
+fn eval_once(input: &str) -> Duration {
+    let mut loader = Loader::new();
+    let mut tracer = VoidTracer;
+    let doc = loader.load_string(input.to_string());
+    let mut type_env = loader.type_prelude();
+    let mut value_env = loader.value_prelude();
+    let start = Instant::now();
+    let result = loader.evaluate(
+        &mut type_env,
+        &mut value_env,
+        doc,
+        &mut tracer,
+        StrictMode::Off,
+    );
+    let duration = start.elapsed();
+    assert!(result.is_ok(), "Benchmark input should evaluate.");
+    duration
+}
+
+fn main() {
+    let feature = if cfg!(feature = "arena") {
+        "arena"
+    } else {
+        "default"
+    };
+    for (name, input) in CASES {
+        let allocs_before = ALLOCATIONS.load(Ordering::Relaxed);
+        eval_once(input);
+        let allocs = ALLOCATIONS.load(Ordering::Relaxed) - allocs_before;
+        let mut samples: Vec<Duration> = (0..201).map(|_| eval_once(input)).collect();
+        samples.sort();
+        let min = samples[0].as_secs_f64() * 1e3;
+        let median = samples[samples.len() / 2].as_secs_f64() * 1e3;
+        println!(
+            "{name:>16} {feature:>8} {allocs:>8} allocs  min {min:>7.3} ms  median {median:>7.3} ms"
+        );
+    }
+}
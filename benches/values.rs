@@ -0,0 +1,107 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Benchmark evaluation of documents with many small leaf values.
+//!
+//!     cargo bench --bench values
+//!
+//! The cases are the self-contained examples, and two larger generated
+//! documents where most values are short strings. For every case, this prints
+//! the number of heap allocations of one evaluation, including parsing and
+//! typechecking, and its minimum and median time. The allocation count is
+//! deterministic, timings vary between runs.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rcl::eval::StrictMode;
+use rcl::loader::Loader;
+use rcl::tracer::VoidTracer;
+
+const CASES: &[(&str, &str)] = &[
+    ("buckets", include_str!("../examples/buckets.rcl")),
+    (
+        "github_actions",
+        include_str!("../examples/github_actions.rcl"),
+    ),
+    ("invert_map", include_str!("../examples/invert_map.rcl")),
+    ("tags", include_str!("../examples/tags.rcl")),
+    ("unique_uids", include_str!("../examples/unique_uids.rcl")),
+    (
+        "inventory",
+        r#"{
+          for i in std.range(0, 1000):
+          let name = f"web-{i}";
+          name: {
+            name = name,
+            fqdn = f"{name}.example.com",
+            region = if i < 500: "eu-west-1" else "us-east-2",
+            port = 8000 + i,
+            tags = ["web", f"rack-{i}", f"host-{i}"],
+          }
+        }"#,
+    ),
+    (
+        "csv",
+        r#"
+        let lines = [for i in std.range(0, 1000): f"host-{i},zone-{i},{i}"];
+        std.parse_csv(lines.join("\n"), { columns = ["host", "zone", "index"] })
+        "#,
+    ),
+];
+
+/// The system allocator, but it counts allocations.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Evaluate the input, and return how long it took.
+fn eval_once(input: &str) -> Duration {
+    let start = Instant::now();
+    let mut loader = Loader::new();
+    let mut tracer = VoidTracer;
+    let doc = loader.load_string(input.to_string());
+    let mut type_env = loader.type_prelude();
+    let mut value_env = loader.value_prelude();
+    let result = loader.evaluate(
+        &mut type_env,
+        &mut value_env,
+        doc,
+        &mut tracer,
+        StrictMode::Off,
+    );
+    let duration = start.elapsed();
+    assert!(result.is_ok(), "Benchmark input should evaluate.");
+    duration
+}
+
+fn main() {
+    for (name, input) in CASES {
+        let allocs_before = ALLOCATIONS.load(Ordering::Relaxed);
+        eval_once(input);
+        let allocs = ALLOCATIONS.load(Ordering::Relaxed) - allocs_before;
+        let mut samples: Vec<Duration> = (0..201).map(|_| eval_once(input)).collect();
+        samples.sort();
+        let min = samples[0].as_secs_f64() * 1e3;
+        let median = samples[samples.len() / 2].as_secs_f64() * 1e3;
+        println!("{name:>16} {allocs:>8} allocs  min {min:>7.3} ms  median {median:>7.3} ms");
+    }
+}
@@ -0,0 +1,157 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Derive macros for the `ToRcl` and `FromRcl` traits in `rcl::native`.
+//!
+//! A struct with named fields converts to a dict with a key per field. To avoid
+//! a dependency on `syn` and `quote`, we parse the struct definition from the
+//! token stream directly. This supports the structs that configuration types
+//! are made of, but not generic structs, tuple structs, or enums.
+
+use proc_macro::{Delimiter, Spacing, TokenStream, TokenTree};
+
+struct Struct {
+    name: String,
+    fields: Vec<String>,
+}
+
+/// Parse a struct definition into its name and the names of its fields.
+fn parse_struct(input: TokenStream) -> Result<Struct, &'static str> {
+    let unsupported = "Only structs with named fields can be converted to and from RCL values.";
+    let mut tokens = input.into_iter();
+
+    // Skip over attributes and visibility, up to the `struct` keyword.
+    loop {
+        match tokens.next() {
+            Some(TokenTree::Ident(ident)) => match ident.to_string().as_ref() {
+                "struct" => break,
+                "enum" | "union" => return Err(unsupported),
+                _ => continue,
+            },
+            Some(_) => continue,
+            None => return Err(unsupported),
+        }
+    }
+
+    let name = match tokens.next() {
+        Some(TokenTree::Ident(ident)) => ident.to_string(),
+        _ => return Err(unsupported),
+    };
+
+    match tokens.next() {
+        Some(TokenTree::Group(body)) if body.delimiter() == Delimiter::Brace => Ok(Struct {
+            name,
+            fields: parse_fields(body.stream())?,
+        }),
+        Some(TokenTree::Punct(p)) if p.as_char() == '<' => {
+            Err("Generic structs cannot be converted to and from RCL values.")
+        }
+        _ => Err(unsupported),
+    }
+}
+
+/// Parse the names of the fields in the body of a struct.
+fn parse_fields(body: TokenStream) -> Result<Vec<String>, &'static str> {
+    let mut fields = Vec::new();
+    // Whether we are before the `:` of a field, as opposed to in its type.
+    let mut in_name = true;
+    let mut name = None;
+    // Commas inside type arguments do not separate fields, but `<` and `>`
+    // are not delimiters, so we track their nesting ourselves.
+    let mut angle_depth = 0_u32;
+    let mut prev_is_joint_minus = false;
+
+    for token in body {
+        let is_joint_minus = matches!(
+            &token,
+            TokenTree::Punct(p) if p.as_char() == '-' && p.spacing() == Spacing::Joint
+        );
+        match token {
+            TokenTree::Ident(ident) if in_name => name = Some(ident.to_string()),
+            TokenTree::Punct(p) if in_name && p.as_char() == ':' => {
+                let name = name.take().ok_or("Expected a field name before ':'.")?;
+                let name = name.strip_prefix("r#").unwrap_or(&name).to_string();
+                fields.push(name);
+                in_name = false;
+            }
+            TokenTree::Punct(p) if p.as_char() == '<' => angle_depth += 1,
+            // The `>` in `->` of a function type does not close a `<`.
+            TokenTree::Punct(p) if p.as_char() == '>' && !prev_is_joint_minus => {
+                angle_depth = angle_depth.saturating_sub(1)
+            }
+            TokenTree::Punct(p) if p.as_char() == ',' && angle_depth == 0 => in_name = true,
+            _ => {}
+        }
+        prev_is_joint_minus = is_joint_minus;
+    }
+
+    Ok(fields)
+}
+
+fn compile_error(message: &str) -> TokenStream {
+    format!("::core::compile_error!({message:?});")
+        .parse()
+        .expect("A compile_error invocation is valid Rust.")
+}
+
+/// Derive `rcl::native::ToRcl`, converting the struct into a dict.
+#[proc_macro_derive(ToRcl)]
+pub fn derive_to_rcl(input: TokenStream) -> TokenStream {
+    let Struct { name, fields } = match parse_struct(input) {
+        Ok(s) => s,
+        Err(message) => return compile_error(message),
+    };
+    let mut inserts = String::new();
+    for field in fields.iter() {
+        inserts.push_str(&format!(
+            "fields.insert(::rcl::runtime::Value::from({field:?}), \
+            ::rcl::native::ToRcl::to_rcl(&self.r#{field}));"
+        ));
+    }
+    format!(
+        "impl ::rcl::native::ToRcl for {name} {{
+            fn to_rcl(&self) -> ::rcl::runtime::Value {{
+                let mut fields = ::rcl::small_collection::SmallDict::new();
+                {inserts}
+                ::rcl::runtime::Value::Dict(fields)
+            }}
+        }}"
+    )
+    .parse()
+    .expect("The generated impl is valid Rust.")
+}
+
+/// Derive `rcl::native::FromRcl`, extracting the struct from a dict.
+///
+/// Fields of type `Option` may be absent from the dict, other fields must be
+/// present. Keys that are not fields of the struct are an error.
+#[proc_macro_derive(FromRcl)]
+pub fn derive_from_rcl(input: TokenStream) -> TokenStream {
+    let Struct { name, fields } = match parse_struct(input) {
+        Ok(s) => s,
+        Err(message) => return compile_error(message),
+    };
+    let mut names = String::new();
+    let mut inits = String::new();
+    for field in fields.iter() {
+        names.push_str(&format!("{field:?}, "));
+        inits.push_str(&format!(
+            "r#{field}: ::rcl::native::field(fields, {field:?})?, "
+        ));
+    }
+    format!(
+        "impl ::rcl::native::FromRcl for {name} {{
+            fn from_rcl(value: &::rcl::runtime::Value) -> ::rcl::native::Result<Self> {{
+                let fields = ::rcl::native::fields(value)?;
+                ::rcl::native::check_fields(fields, &[{names}])?;
+                ::core::result::Result::Ok({name} {{ {inits} }})
+            }}
+        }}"
+    )
+    .parse()
+    .expect("The generated impl is valid Rust.")
+}
@@ -0,0 +1,87 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+use std::collections::BTreeMap;
+
+use rcl::loader::Loader;
+use rcl::native::{FromRcl, ToRcl};
+use rcl::runtime::Value;
+
+#[derive(Debug, PartialEq, FromRcl, ToRcl)]
+struct Listener {
+    host: String,
+    port: u16,
+    tls: Option<bool>,
+    r#type: String,
+}
+
+#[derive(Debug, PartialEq, FromRcl, ToRcl)]
+pub struct Config {
+    /// Comments and attributes on fields are fine.
+    pub(crate) listeners: Vec<Listener>,
+    labels: BTreeMap<String, (i64, Vec<String>)>,
+}
+
+fn eval(loader: &mut Loader, source: &str) -> rcl::error::Result<Value> {
+    let doc = loader.load_string(source.to_string());
+    let mut type_env = loader.type_prelude();
+    let mut value_env = loader.value_prelude();
+    let mut tracer = rcl::tracer::VoidTracer;
+    loader.evaluate(
+        &mut type_env,
+        &mut value_env,
+        doc,
+        &mut tracer,
+        rcl::eval::StrictMode::Off,
+    )
+}
+
+#[test]
+fn derive_round_trips_through_evaluation() {
+    let listener = Listener {
+        host: "localhost".to_string(),
+        port: 8080,
+        tls: None,
+        r#type: "http".to_string(),
+    };
+    let mut loader = Loader::new();
+    loader.register_global("base", listener.to_rcl());
+    let result = eval(
+        &mut loader,
+        r#"{
+          listeners = [base, base | { port = 8443, tls = true }],
+          labels = { web = (1, ["a", "b"]) },
+        }"#,
+    )
+    .expect("Evaluation should succeed.");
+
+    let config = Config::from_rcl(&result).expect("The result should be a Config.");
+    assert_eq!(config.listeners[0], listener);
+    assert_eq!(config.listeners[1].port, 8443);
+    assert_eq!(config.listeners[1].tls, Some(true));
+    assert_eq!(config.labels["web"].1, ["a", "b"]);
+    assert_eq!(Config::from_rcl(&config.to_rcl()).unwrap(), config);
+}
+
+#[test]
+fn derive_reports_missing_and_unknown_fields() {
+    let mut loader = Loader::new();
+    let result = eval(
+        &mut loader,
+        r#"{ host = "a", port = 1, type = "b", tsl = true }"#,
+    )
+    .unwrap();
+    let err = Listener::from_rcl(&result).unwrap_err();
+    assert_eq!(err.to_string(), "Unknown field 'tsl'.");
+
+    let result = eval(
+        &mut loader,
+        r#"{ listeners = [{ host = "a", type = "b" }], labels = {} }"#,
+    );
+    let err = Config::from_rcl(&result.unwrap()).unwrap_err();
+    assert_eq!(err.to_string(), "At .listeners[0]: Missing field 'port'.");
+}
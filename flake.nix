@@ -144,6 +144,7 @@
             inherit name version;
             src = rustSources;
             cargoLock.lockFile = ./Cargo.lock;
+            buildFeatures = [ "line-editor" ];
           };
 
           coverageBuild = rcl.overrideAttrs (old: {
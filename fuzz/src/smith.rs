@@ -37,6 +37,7 @@ const BUILTINS: &[&str] = &[
     "flat_map",
     "fold",
     "get",
+    "graphemes",
     "group_by",
     "join",
     "key_by",
@@ -44,25 +45,78 @@ const BUILTINS: &[&str] = &[
     "len",
     "map",
     "parse_int",
+    "parse_key_value",
+    "parse_utf8",
     "remove_prefix",
     "remove_suffix",
     "replace",
     "reverse",
+    "sorted_items",
     "split",
     "split_lines",
     "starts_with",
+    "strip_comments",
     "sum",
+    "to_base64",
+    "to_hex",
     "to_lowercase",
     "to_uppercase",
+    "to_utf8",
     "values",
     // Stdlib and its functions
+    "bytes_from_base64",
+    "bytes_from_hex",
+    "char_from_codepoint",
+    "check",
+    "matches",
+    "one_of",
+    "fail",
+    "fetch_url",
+    "file_sha256",
+    "format_float",
+    "format_int",
+    "format_rcl",
+    "is_bool",
+    "is_bytes",
+    "is_dict",
+    "is_float",
+    "is_function",
+    "is_int",
+    "is_list",
+    "is_null",
+    "is_set",
+    "is_string",
+    "list",
+    "chunks",
+    "transpose",
+    "windows",
+    "memoize",
+    "net",
+    "cidr_contains",
+    "cidr_hosts",
+    "ip_add",
+    "parse_ip",
+    "parse_csv",
+    "partial",
     "range",
     "read_file_utf8",
+    "secret",
+    "semver",
+    "compare",
+    "satisfies",
     "std",
+    "template",
+    "to_float",
+    "to_int",
+    "type_of",
+    "url",
+    "build",
+    "encode_query",
 ];
 
 const BUILTIN_TYPES: &[&str] = &[
-    "Any", "Bool", "Dict", "Int", "List", "Null", "Set", "String", "Union", "Void",
+    "Any", "Bool", "Bytes", "Dict", "Float", "Int", "List", "Never", "Null", "Set", "String",
+    "Tuple", "Union", "Void",
 ];
 
 const LITERALS: &[&str] = &["true", "false", "null"];
@@ -70,7 +124,7 @@ const LITERALS: &[&str] = &["true", "false", "null"];
 const UNOPS: &[&str] = &["not", "-"];
 
 const BINOPS: &[&str] = &[
-    "and", "or", "|", "+", "-", "*", "/", "<", ">", "<=", ">=", "==", "!=",
+    "and", "or", "|", "+", "-", "*", "/", "<", ">", "<=", ">=", "==", "!=", "|>",
 ];
 
 /// Return a copy of the nth last element of the array, clamping to the first.
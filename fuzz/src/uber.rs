@@ -34,7 +34,7 @@
 //! corpus. That's why all these checks live together in this “über” module.
 
 use rcl::error::Result;
-use rcl::eval::Evaluator;
+use rcl::eval::{Evaluator, StrictMode};
 use rcl::loader::{Loader, VoidFilesystem};
 use rcl::pprint;
 use rcl::runtime::Value;
@@ -118,7 +118,7 @@ fn eval(loader: &mut Loader, input: &str) -> Result<(Span, Value)> {
 fn run_fmt(loader: &mut Loader, input: &str, cfg: &pprint::Config) -> Result<String> {
     let id = loader.load_string(input.to_string());
     let cst = loader.get_cst(id)?;
-    let doc = rcl::fmt_cst::format_expr(input, &cst);
+    let doc = rcl::fmt_cst::format_expr(input, &cst, Default::default());
     Ok(doc.println(cfg).to_string_no_markup())
 }
 
@@ -143,14 +143,26 @@ fn fuzz_eval_json_idempotent(loader: &mut Loader, input: &str, cfg: pprint::Conf
     let mut type_env = rcl::typecheck::prelude();
     let mut value_env = rcl::runtime::prelude();
     let doc_1 = loader.load_string(input.to_string());
-    let val_1 = loader.evaluate(&mut type_env, &mut value_env, doc_1, &mut tracer)?;
+    let val_1 = loader.evaluate(
+        &mut type_env,
+        &mut value_env,
+        doc_1,
+        &mut tracer,
+        StrictMode::Off,
+    )?;
 
     let body_span = loader.get_span(doc_1);
     let json = rcl::fmt_json::format_json(body_span, &val_1)?;
 
     let out_1 = json.println(&cfg).to_string_no_markup();
     let doc_2 = loader.load_string(out_1);
-    let val_2 = loader.evaluate(&mut type_env, &mut value_env, doc_2, &mut tracer)?;
+    let val_2 = loader.evaluate(
+        &mut type_env,
+        &mut value_env,
+        doc_2,
+        &mut tracer,
+        StrictMode::Off,
+    )?;
 
     let body_span = loader.get_span(doc_2);
     let json = rcl::fmt_json::format_json(body_span, &val_2)?;
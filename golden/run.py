@@ -82,11 +82,48 @@ def test_one(fname: str, fname_friendly: str, *, rewrite_output: bool) -> Option
     # Allow overriding the binary that we run.
     rcl_bin = os.getenv("RCL_BIN", default="target/debug/rcl")
 
+    # The input is stdin, which for most commands is the last argument.
+    input_args = ["-"]
+
     # Decide which subcommand to test based on the test directory.
     match os.path.basename(os.path.dirname(fname)):
         case "build":
             cmd = ["build", "--dry-run"]
 
+        case "build_jobs":
+            # Split the targets of the build file over multiple threads.
+            cmd = ["build", "--dry-run", "--jobs=3"]
+
+        case "bundle":
+            cmd = ["bundle"]
+
+        case "check":
+            cmd = ["check"]
+
+        case "convert_json":
+            cmd = ["convert", "--from=json"]
+
+        case "convert_toml":
+            cmd = ["convert", "--from=toml"]
+
+        case "convert_yaml":
+            cmd = ["convert", "--from=yaml"]
+
+        case "csv":
+            cmd = ["eval", "--format=csv"]
+
+        case "doc":
+            cmd = ["doc"]
+
+        case "doc_html":
+            cmd = ["doc", "--format=html"]
+
+        case "elide":
+            cmd = ["eval", "--max-depth=2", "--max-items=3"]
+
+        case "elide_json":
+            cmd = ["eval", "--format=json", "--max-depth=2", "--max-items=3"]
+
         case "error" | "types":
             cmd = ["eval"]
 
@@ -96,21 +133,71 @@ def test_one(fname: str, fname_friendly: str, *, rewrite_output: bool) -> Option
         case "error_raw":
             cmd = ["eval", "--format=raw"]
 
+        case "expr":
+            # The input is the expression, passed on the command line. Tests
+            # named "with_input" additionally pass stdin as an input file.
+            cmd = ["eval", "--expr", "".join(input_lines).rstrip("\n")]
+            input_args = ["-"] if "with_input" in os.path.basename(fname) else []
+
         case "fmt":
             cmd = ["fmt"]
 
+        case "fmt_normalize":
+            cmd = ["fmt", "--normalize-strings"]
+
+        case "lint":
+            # The input is the entry point, the other files are the candidates
+            # for unused files.
+            cmd = ["lint", "--unused-exports", "_lib.rcl", "_orphan.rcl", "_util.rcl", "--entry"]
+
+        case "lock":
+            cmd = ["lock", "--dry-run"]
+
+        case "locked":
+            cmd = ["eval", "--locked", "--lockfile=_imports.lock"]
+
+        case "github_actions":
+            cmd = ["eval", "--format=github-actions"]
+
         case "json":
             cmd = ["eval", "--format=json"]
 
+        case "json_ascii":
+            cmd = ["eval", "--format=json", "--output-encoding=ascii"]
+
         case "html":
             cmd = ["format", "--color=html"]
 
+        case "prometheus_rules":
+            cmd = ["eval", "--format=prometheus-rules"]
+
         case "raw":
             cmd = ["eval", "--format=raw"]
 
         case "rcl":
             cmd = ["eval", "--format=rcl"]
 
+        case "stdin_filename":
+            cmd = ["eval", "--stdin-filename=config/input.rcl"]
+
+        case "strict":
+            cmd = ["eval", "--strict=error"]
+
+        case "strict_warn":
+            cmd = ["eval", "--strict=warn"]
+
+        case "terraform_json":
+            cmd = ["eval", "--format=terraform-json"]
+
+        case "serve":
+            # The input is a stream of requests, one per line, on stdin.
+            cmd = ["serve"]
+            input_args = []
+
+        case "source_map":
+            # Write the source map to stdout, it precedes the output.
+            cmd = ["eval", "--format=json", "--source-map=/dev/stdout"]
+
         case "toml":
             cmd = ["eval", "--format=toml"]
             # For TOML, when the test case is not an error, we additionally test
@@ -122,6 +209,11 @@ def test_one(fname: str, fname_friendly: str, *, rewrite_output: bool) -> Option
                 except Exception as err:
                     raise Exception(f"Invalid TOML in {fname}") from err
 
+        case "trace_output":
+            # The output path follows the input file.
+            cmd = ["trace-output"]
+            input_args = ["-", "result"]
+
         case "yaml_stream":
             cmd = ["eval", "--format=yaml-stream"]
 
@@ -129,7 +221,7 @@ def test_one(fname: str, fname_friendly: str, *, rewrite_output: bool) -> Option
             raise ValueError(f"No command-line known for {unknown}.")
 
     result = subprocess.run(
-        [rcl_bin, "-C", os.path.dirname(fname), *cmd, "-"],
+        [rcl_bin, "-C", os.path.dirname(fname), *cmd, *input_args],
         input="".join(input_lines),
         capture_output=True,
         encoding="utf-8",
@@ -25,7 +25,7 @@ expr
 
 expr_stmt: stmt expr;
 expr_if: "if" expr_op ':' expr "else" expr;
-expr_import: "import" expr;
+expr_import: "import" expr | "import" expr "as" IDENT;
 
 // There is no operator precedence, so if there is an operator, its args must
 // not themselves contain operators. One exception is that if the operator is
@@ -100,9 +100,19 @@ fstring
   ;
 
 stmt
-  : "let" IDENT optional_type_hint '=' expr ';'
-  | "assert" expr ',' expr ';'
-  | "trace" expr ';'
+  : "let" IDENT optional_type_hint '=' expr semicolons
+  | "assert" expr ',' expr optional_comma semicolons
+  | "trace" expr semicolons
+  ;
+
+semicolons
+  : ';'
+  | semicolons ';'
+  ;
+
+optional_comma
+  : %empty
+  | ','
   ;
 
 optional_type_hint
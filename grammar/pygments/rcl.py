@@ -20,8 +20,13 @@ __all__ = ["RclLexer"]
 _root_base = [
     (r"#!.*?$", token.Comment.Hashbang),
     (r"//.*?$", token.Comment),
+    (r'0x"[0-9a-fA-F]*"', token.String),
+    (r'b64"[0-9A-Za-z+/=]*"', token.String),
+    (r"#[_a-zA-Z][_a-zA-Z0-9-]*", token.Keyword.Type),
+    (r'r"""', token.String, "raw_triple"),
     (r'f"""', token.String, "format_triple"),
     (r'"""', token.String, "string_triple"),
+    (r'r"', token.String, "raw_double"),
     (r'f"', token.String, "format_double"),
     (r'"', token.String, "string_double"),
     # Note, the `}` we handle in each of the specializations of this state.
@@ -44,6 +49,7 @@ _root_base = [
                 "import",
                 "in",
                 "let",
+                "match",
                 "not",
                 "null",
                 "or",
@@ -73,6 +79,7 @@ _root_base = [
                 "len",
                 "map",
                 "parse_int",
+                "parse_utf8",
                 "remove_prefix",
                 "remove_suffix",
                 "replace",
@@ -82,8 +89,11 @@ _root_base = [
                 "starts_with",
                 "std",
                 "sum",
+                "to_base64",
+                "to_hex",
                 "to_lowercase",
                 "to_uppercase",
+                "to_utf8",
                 "values",
             ),
             suffix=r"\b",
@@ -95,12 +105,15 @@ _root_base = [
             (
                 "Any",
                 "Bool",
+                "Bytes",
                 "Dict",
+                "Float",
                 "Int",
                 "List",
                 "Null",
                 "Set",
                 "String",
+                "Tuple",
                 "Union",
                 "Void",
             ),
@@ -116,7 +129,7 @@ _root_base = [
     # In the Rust lexer the punctuation is split out, and then further
     # into digraphs and monographs. Here we instead split them out by
     # token type.
-    (r"<=|>=|==|!=|=>|<|>|\+|-|\*|/|\|", token.Operator),
+    (r"<=|>=|==|!=|=>|\|>|<|>|\+|-|\*|/|\|", token.Operator),
     (r"[)(\]\[=,.:;]", token.Token),
     (r"{", token.Token, "in_brace"),
     (r"#", token.Error),
@@ -174,9 +187,19 @@ class RclLexer(RegexLexer):
             (r'""|"', token.String),
             (r"\\", token.String.Escape, "escape"),
         ],
+        "raw_double": [
+            (r'[^"]+', token.String),
+            (r'"', token.String, "#pop"),
+        ],
+        "raw_triple": [
+            (r'[^"]+', token.String),
+            (r'"""', token.String, "#pop"),
+            (r'""|"', token.String),
+        ],
         "escape": [
             (r'["\\/bfnrt}{]', token.String.Escape, "#pop"),
             (r"u\{[0-9a-fA-F]+\}", token.String.Escape, "#pop"),
+            (r"x[0-9a-fA-F]{2}", token.String.Escape, "#pop"),
             (r"u[0-9a-fA-F]{4}", token.String.Escape, "#pop"),
         ],
     }
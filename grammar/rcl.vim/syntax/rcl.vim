@@ -7,7 +7,7 @@
 
 syn keyword rclNull         null
 syn keyword rclBoolean      true false
-syn keyword rclConditional  if else
+syn keyword rclConditional  if else match
 syn keyword rclRepeat       for
 syn keyword rclOperator     and not or
 syn keyword rclKeyword      in let import
@@ -21,6 +21,7 @@ syn match rclOperator '!='
 syn match rclOperator '=>'
 syn match rclOperator '->'
 syn match rclOperator '|'
+syn match rclOperator '|>'
 syn match rclOperator '+'
 syn match rclOperator '-'
 syn match rclOperator '*'
@@ -38,6 +39,9 @@ syn cluster rclNumber      contains=rclFloat,rclHexadecimal,rclBinary,rclDecimal
 
 syn region  rclStringDouble start='"' end='"' skip='\\"'
 syn region  rclStringTriple start='"""' end='"""' skip='\\"'
+syn region  rclRawDouble    start='r"'   end='"'
+syn region  rclRawTriple    start='r"""' end='"""'
+syn match   rclBytes        '\<\(0x\|b64\)"[0-9A-Za-z+/=]*"'
 
 syn region  rclInterpolation matchgroup=rclInterpolationDelimiter start="{" end="}" contained contains=@rclExpr
 syn region  rclFormatDouble  start='f"'   end='"'   skip='\\"\|\\{' contains=rclInterpolation
@@ -45,17 +49,21 @@ syn region  rclFormatTriple  start='f"""' end='"""' skip='\\"\|\\{' contains=rcl
 
 " See also https://vi.stackexchange.com/questions/5966/ for why the `contains`
 " needs to end in `[]`.
-syn keyword rclBuiltin chars contains[] ends_with except filter flat_map fold get group_by join key_by keys len map parse_int remove_prefix remove_suffix replace reverse split split_lines starts_with std sum to_lowercase to_uppercase values
+syn keyword rclBuiltin chars contains[] ends_with except filter flat_map fold get group_by join key_by keys len map parse_int parse_utf8 remove_prefix remove_suffix replace reverse split split_lines starts_with std sum to_base64 to_hex to_lowercase to_uppercase to_utf8 values
 
-syn match   rclType '\<\(Any\|Bool\|Dict\|Int\|List\|Null\|Set\|String\|Void\)\>'
+syn match   rclTag  '#[_a-zA-Z][_a-zA-Z0-9-]*'
+syn match   rclType '\<\(Any\|Bool\|Bytes\|Dict\|Float\|Int\|List\|Null\|Set\|String\|Tuple\|Void\)\>'
 
-syn cluster rclString contains=rclStringDouble,rclStringTriple,rclFormatDouble,rclFormatTriple
+syn cluster rclString contains=rclStringDouble,rclStringTriple,rclRawDouble,rclRawTriple,rclBytes,rclFormatDouble,rclFormatTriple
 highlight link rclStringDouble rclString
 highlight link rclStringTriple rclString
+highlight link rclRawDouble    rclString
+highlight link rclRawTriple    rclString
+highlight link rclBytes        rclString
 highlight link rclFormatDouble rclString
 highlight link rclFormatTriple rclString
 
-syn cluster rclExpr contains=@rclKeyword,rclOperator,@rclNumber,rclComment,rclBuiltin,rclType,@rclString
+syn cluster rclExpr contains=@rclKeyword,rclOperator,@rclNumber,rclComment,rclBuiltin,rclType,rclTag,@rclString
 
 highlight link rclBoolean     Boolean
 highlight link rclConditional Conditional
@@ -68,6 +76,7 @@ highlight link rclComment     Comment
 highlight link rclTodo        Todo
 highlight link rclBuiltin     Function
 highlight link rclType        Type
+highlight link rclTag         Type
 highlight link rclString      String
 highlight link rclFloat       Float
 highlight link rclHexadecimal Number
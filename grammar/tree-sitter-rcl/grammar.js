@@ -21,6 +21,7 @@ module.exports = grammar({
 
   conflicts: $ => [
     [ $.function_args, $._expr_term ],
+    [ $.function_args, $.expr_term_tuple ],
   ],
 
   rules: {
@@ -30,12 +31,15 @@ module.exports = grammar({
     shebang: $ => /#![^\n]*\n/,
 
     ident: $ => /[_A-Za-z][-_A-Za-z0-9]*/,
+    tag: $ => /#[_A-Za-z][-_A-Za-z0-9]*/,
 
     string: $ => choice(
       $.fstring_double,
       $.fstring_triple,
       $.string_double,
       $.string_triple,
+      $.rstring_double,
+      $.rstring_triple,
     ),
     string_escape: $ => token.immediate(seq(
       "\\",
@@ -51,6 +55,7 @@ module.exports = grammar({
     // doesn't work and we can get comments inside strings.
     _string_char: $ => token.immediate(prec(1, /[^\\"]+/)),
     _fstring_char: $ => token.immediate(prec(1, /[^\\{"]+/)),
+    _rstring_char: $ => token.immediate(prec(1, /[^"]+/)),
 
     string_double: $ => seq(
       "\"",
@@ -69,6 +74,16 @@ module.exports = grammar({
       )),
       "\"\"\"",
     ),
+    rstring_double: $ => seq(
+      "r\"",
+      repeat($._rstring_char),
+      "\"",
+    ),
+    rstring_triple: $ => seq(
+      "r\"\"\"",
+      repeat(choice($._rstring_char, "\"")),
+      "\"\"\"",
+    ),
     fstring_double: $ => seq(
       "f\"",
       repeat(choice(
@@ -92,6 +107,7 @@ module.exports = grammar({
     number: $ => choice($.num_binary, $.num_hexadecimal, $.num_decimal),
     num_binary: $ => /0b[01_]*/,
     num_hexadecimal: $ => /0x[0-9a-fA-F_]*/,
+    bytes: $ => /(0x|b64)"[0-9A-Za-z+\/=]*"/,
     num_decimal: $ => /(0|[1-9][0-9_]*)(\.[0-9][0-9_]*)?([eE][-+]?[0-9][0-9_]*)?/,
 
     "null": $ => "null",
@@ -104,6 +120,7 @@ module.exports = grammar({
     binop: $ => choice(
       $.binop_keyword,
       "|",
+      "|>",
       "*",
       "+",
       "-",
@@ -120,7 +137,7 @@ module.exports = grammar({
       $.expr_stmt,
       $._expr_op,
     ),
-    expr_stmt: $ => seq($._stmt, ";", $._expr),
+    expr_stmt: $ => seq($._stmt, repeat1(";"), $._expr),
 
     _expr_op: $ => choice(
       $.expr_import,
@@ -131,7 +148,11 @@ module.exports = grammar({
       $._expr_not_op,
     ),
 
-    expr_import: $ => seq("import", $._expr),
+    expr_import: $ => seq(
+      "import",
+      $._expr,
+      optional(seq("as", field("format", $.ident))),
+    ),
 
     expr_if: $ => seq(
       "if",
@@ -200,8 +221,12 @@ module.exports = grammar({
       $.expr_term_braces,
       $.expr_term_brackets,
       $.expr_term_parens,
+      $.expr_term_tuple,
+      $.expr_term_tagged,
+      $.expr_match,
       $.string,
       $.number,
+      $.bytes,
       $.ident,
       $.bool,
       $["null"],
@@ -209,20 +234,58 @@ module.exports = grammar({
     expr_term_braces:   $ => seq("{", optional($._seqs), "}"),
     expr_term_brackets: $ => seq("[", optional($._seqs), "]"),
     expr_term_parens:   $ => seq("(", $._expr, ")"),
+    // A tuple has at least one comma, unless it is the empty tuple.
+    expr_term_tuple:    $ => choice(
+      seq("(", ")"),
+      seq("(", $._expr, ",", optional($._call_args), ")"),
+    ),
+
+    // The payload is optional, when a term follows the tag, it is the payload.
+    expr_term_tagged: $ => prec.right(seq(
+      field("tag", $.tag),
+      optional(field("value", $._expr_not_op)),
+    )),
+    expr_match: $ => seq(
+      "match",
+      field("scrutinee", $._expr_op),
+      "{",
+      optional(seq($.match_arm, repeat(seq(",", $.match_arm)), optional(","))),
+      "}",
+    ),
+    match_arm: $ => seq(
+      field("pattern", $.pattern),
+      "=>",
+      field("body", $._expr),
+    ),
+    pattern: $ => choice(
+      seq(field("tag", $.tag), optional(field("inner", $.pattern))),
+      // A name binds the value, except for `_`, which is a wildcard.
+      $.ident,
+      $["null"],
+      $.bool,
+      $.string,
+      seq(optional("-"), $.number),
+    ),
 
     _stmt: $ => choice($.stmt_let, $.stmt_assert, $.stmt_trace),
     stmt_let: $ => seq(
       "let",
-      field("ident", $.ident),
+      choice(field("ident", $.ident), field("idents", $.let_idents)),
       optional(seq(":", field("type", $._type_expr))),
       "=",
       field("value", $._expr),
     ),
+    let_idents: $ => seq(
+      "(",
+      optional(seq($.ident, repeat(seq(",", $.ident)), optional(","))),
+      ")",
+    ),
     stmt_assert: $ => seq(
       "assert",
       field("condition", $._expr),
       ",",
       field("message", $._expr),
+      optional(","),
     ),
     stmt_trace: $ => seq(
       "trace",
@@ -257,7 +320,7 @@ module.exports = grammar({
       "=",
       field("value", $._expr),
     ),
-    seq_stmt: $ => seq($._stmt, ";", $._seq),
+    seq_stmt: $ => seq($._stmt, repeat1(";"), $._seq),
     seq_for: $ => seq(
       "for",
       field("idents", $._idents),
@@ -277,6 +340,7 @@ module.exports = grammar({
     _idents: $ => seq($.ident, repeat(seq(",", $.ident))),
 
     _type_expr: $ => choice(
+      $.type_tagged,
       $.type_term,
       $.type_apply,
       $.type_function,
@@ -288,6 +352,10 @@ module.exports = grammar({
       optional(field("args", $._types)),
       "]",
     ),
+    type_tagged: $ => prec.right(seq(
+      field("tag", $.tag),
+      optional(field("value", $._type_expr)),
+    )),
     type_function: $ => seq(
       "(",
       optional(field("args", $._types)),
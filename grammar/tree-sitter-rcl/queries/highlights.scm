@@ -1,7 +1,7 @@
 ["for" "in"] @keyword
 ["assert" "trace"] @keyword
-["if" "else"] @keyword
-["import"] @keyword
+["if" "else" "match"] @keyword
+["import" "as"] @keyword
 ["let"] @keyword
 (unop_keyword) @keyword
 (binop_keyword) @keyword
@@ -12,6 +12,7 @@
 (null) @constant
 
 (string) @string
+(bytes) @string
 (string_escape) @string.special
 (string_hole (["{" "}"] @string.special))
 
@@ -19,3 +20,4 @@
 (seq_assoc_ident field: (ident) @property)
 
 (type_term) @type
+(tag) @type
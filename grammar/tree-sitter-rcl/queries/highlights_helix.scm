@@ -1,6 +1,6 @@
 ["for" "in"] @keyword.control.repeat
 ["assert" "trace"] @keyword.exception
-["if" "else"] @keyword.control.conditional
+["if" "else" "match"] @keyword.control.conditional
 ["import"] @keyword.control.import
 ["let"] @keyword.storage.let
 (unop_keyword) @keyword.operator
@@ -12,6 +12,7 @@
 (null) @constant.builtin
 
 (string) @string
+(bytes) @string
 (string_escape) @string.special
 (string_hole (["{" "}"] @string.special))
 
@@ -19,3 +20,4 @@
 (seq_assoc_ident field: (ident) @attribute)
 
 (type_term) @type
+(tag) @type
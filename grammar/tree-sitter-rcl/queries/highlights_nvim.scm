@@ -1,6 +1,6 @@
 ["for" "in"] @keyword.repeat
 ["assert" "trace"] @keyword.debug
-["if" "else"] @keyword.conditional
+["if" "else" "match"] @keyword.conditional
 ["import"] @keyword.import
 ["let"] @keyword.storage
 (unop_keyword) @keyword.operator
@@ -12,6 +12,7 @@
 (null) @constant.builtin
 
 (string) @string
+(bytes) @string
 (string_escape) @string.escape
 (string_hole (["{" "}"] @string.escape))
 
@@ -19,3 +20,4 @@
 (seq_assoc_ident field: (ident) @property)
 
 (type_term) @type
+(tag) @type
@@ -5,7 +5,15 @@
 # you may not use this file except in compliance with the License.
 # A copy of the License has been included in the root of the repository.
 
-from typing import Any, Union
+from typing import Any, Optional, Union
 
+class Error(Exception):
+    message: str
+    path: Optional[str]
+    line: Optional[int]
+    column: Optional[int]
+
+def check(src: str) -> None: ...
 def load_file(path: str) -> Any: ...
 def loads(src: str) -> Any: ...
+def query(src: str, query: str) -> Any: ...
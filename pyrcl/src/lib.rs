@@ -7,21 +7,98 @@
 
 use pyo3::prelude::*;
 use rcl::cli::Target;
-use rcl::error::Result;
+use rcl::eval::StrictMode;
 use rcl::loader::{Loader, SandboxMode};
-use rcl::runtime::{self, Value};
+use rcl::pprint;
+use rcl::runtime::Value;
 use rcl::source::DocId;
+use rcl::source_map::line_column;
 use rcl::tracer::StderrTracer;
-use rcl::typecheck;
 
-fn evaluate<F: FnOnce(&mut Loader) -> Result<DocId>>(load: F) -> Result<Value> {
+pyo3::create_exception!(
+    rcl,
+    Error,
+    pyo3::exceptions::PyException,
+    "An error in an RCL document, with the location where it occurred."
+);
+
+/// Convert an RCL error into a Python `rcl.Error` exception.
+///
+/// The exception's string is the full report, like the command-line prints
+/// it. The main message and the location are available as attributes too.
+fn build_python_error(py: Python, loader: &Loader, err: Box<rcl::error::Error>) -> PyErr {
+    let cfg = pprint::Config { width: 80 };
+    let message = err.message.println(&cfg).to_string_no_markup();
+    let location = err.origin.map(|span| {
+        let doc = loader.get_doc(span.doc());
+        let (line, column) = line_column(doc.data, span.start());
+        (doc.name.to_string(), line, column)
+    });
+    let inputs = loader.as_inputs();
+    let report = err.report(&inputs).println(&cfg).to_string_no_markup();
+
+    let py_err = Error::new_err(report.trim_end().to_string());
+    let set_attributes = || -> PyResult<()> {
+        let value = py_err.value(py);
+        value.setattr("message", message.trim_end())?;
+        let (fname, line, column) = match location {
+            Some((fname, line, column)) => (Some(fname), Some(line), Some(column)),
+            None => (None, None, None),
+        };
+        value.setattr("path", fname)?;
+        value.setattr("line", line)?;
+        value.setattr("column", column)?;
+        Ok(())
+    };
+    match set_attributes() {
+        Ok(()) => py_err,
+        Err(err) => err,
+    }
+}
+
+/// Load a document and evaluate it, optionally followed by a query.
+///
+/// When there is a query, the result of the document is bound to `input` in
+/// the query, like `rcl query` does.
+fn evaluate<F: FnOnce(&mut Loader) -> rcl::error::Result<DocId>>(
+    py: Python,
+    load: F,
+    query: Option<String>,
+) -> PyResult<PyObject> {
     let mut loader = Loader::new();
-    loader.initialize_filesystem(SandboxMode::Workdir, None)?;
-    let doc = load(&mut loader)?;
-    let mut tracer = StderrTracer::new(None);
-    let mut type_env = typecheck::prelude();
-    let mut value_env = runtime::prelude();
-    loader.evaluate(&mut type_env, &mut value_env, doc, &mut tracer)
+    let result = (|| {
+        loader.initialize_filesystem(SandboxMode::Workdir, None)?;
+        let doc = load(&mut loader)?;
+        let mut tracer = StderrTracer::new(None);
+        let mut type_env = loader.type_prelude();
+        let mut value_env = loader.value_prelude();
+        let value = loader.evaluate(
+            &mut type_env,
+            &mut value_env,
+            doc,
+            &mut tracer,
+            StrictMode::Off,
+        )?;
+        match query {
+            None => Ok(value),
+            Some(query) => {
+                let query_doc = loader.load_string(query);
+                type_env.push("input".into(), rcl::typecheck::type_any().clone());
+                value_env.push("input".into(), value);
+                loader.evaluate(
+                    &mut type_env,
+                    &mut value_env,
+                    query_doc,
+                    &mut tracer,
+                    StrictMode::Off,
+                )
+            }
+        }
+    })();
+    match result {
+        Ok(v) => build_python_value(py, &v),
+        Err(err) => Err(build_python_error(py, &loader, err)),
+    }
 }
 
 fn runtime_error(message: &'static str) -> PyErr {
@@ -29,12 +106,14 @@ fn runtime_error(message: &'static str) -> PyErr {
 }
 
 fn build_python_value(py: Python, v: &Value) -> PyResult<PyObject> {
-    use pyo3::types::{PyDict, PyList, PyNone, PySet};
+    use pyo3::types::{PyBytes, PyDict, PyList, PyNone, PySet, PyTuple};
     let result = match v {
         Value::Null => PyNone::get(py).into(),
         Value::Bool(b) => b.to_object(py),
         Value::Int(i) => i.to_object(py),
+        Value::Float(x) => x.get().to_object(py),
         Value::String(s) => s.to_object(py),
+        Value::Bytes(b) => PyBytes::new(py, b).into(),
         Value::List(xs) => {
             let values = xs
                 .iter()
@@ -42,6 +121,13 @@ fn build_python_value(py: Python, v: &Value) -> PyResult<PyObject> {
                 .collect::<PyResult<Vec<_>>>()?;
             PyList::new(py, values).into()
         }
+        Value::Tuple(xs) => {
+            let values = xs
+                .iter()
+                .map(|x| build_python_value(py, x))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyTuple::new(py, values).into()
+        }
         Value::Set(xs) => {
             let set = PySet::empty(py)?;
             for x in xs.iter() {
@@ -56,9 +142,21 @@ fn build_python_value(py: Python, v: &Value) -> PyResult<PyObject> {
             }
             dict.into()
         }
-        Value::Function(..) | Value::BuiltinFunction(..) | Value::BuiltinMethod { .. } => {
+        Value::Tagged(..) => {
+            return Err(runtime_error("Tagged values cannot be exported to Python."))
+        }
+        Value::Function(..)
+        | Value::BuiltinFunction(..)
+        | Value::BuiltinMethod { .. }
+        | Value::Partial(..)
+        | Value::Memoized(..) => {
             return Err(runtime_error("Functions cannot be exported to Python."))
         }
+        Value::Secret(..) => {
+            return Err(runtime_error(
+                "Secrets must be resolved before exporting to Python.",
+            ))
+        }
     };
     Ok(result)
 }
@@ -68,18 +166,35 @@ fn build_python_value(py: Python, v: &Value) -> PyResult<PyObject> {
 fn load_file(py: Python, path: String) -> PyResult<PyObject> {
     // Behavior of the file paths for this function is the same as on the
     // command line; it's *not* the same as for import expressions.
-    match evaluate(|loader| loader.load_cli_target(&Target::File(path))) {
-        Ok(v) => build_python_value(py, &v),
-        Err(..) => Err(runtime_error("Evaluation failed.")),
-    }
+    evaluate(
+        py,
+        |loader| loader.load_cli_target(&Target::File(path)),
+        None,
+    )
 }
 
 /// Evaluate an RCL expression.
 #[pyfunction]
 fn loads(py: Python, src: String) -> PyResult<PyObject> {
-    match evaluate(|loader| Ok(loader.load_string(src))) {
-        Ok(v) => build_python_value(py, &v),
-        Err(..) => Err(runtime_error("Evaluation failed.")),
+    evaluate(py, |loader| Ok(loader.load_string(src)), None)
+}
+
+/// Evaluate an RCL expression, then evaluate the query with `input` bound to it.
+#[pyfunction]
+fn query(py: Python, src: String, query: String) -> PyResult<PyObject> {
+    evaluate(py, |loader| Ok(loader.load_string(src)), Some(query))
+}
+
+/// Parse and typecheck an RCL expression without evaluating it.
+#[pyfunction]
+fn check(py: Python, src: String) -> PyResult<()> {
+    let mut loader = Loader::new();
+    let doc = loader.load_string(src);
+    let mut tracer = StderrTracer::new(None);
+    let mut type_env = loader.type_prelude();
+    match loader.get_typechecked_ast(&mut type_env, doc, &mut tracer) {
+        Ok(..) => Ok(()),
+        Err(err) => Err(build_python_error(py, &loader, err)),
     }
 }
 
@@ -87,8 +202,11 @@ fn loads(py: Python, src: String) -> PyResult<PyObject> {
 // with the name `rcl` of the imported Rust crate.
 #[pymodule]
 #[pyo3(name = "rcl")]
-fn pyrcl(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+fn pyrcl(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add("Error", py.get_type::<Error>())?;
+    m.add_function(wrap_pyfunction!(check, m)?)?;
     m.add_function(wrap_pyfunction!(load_file, m)?)?;
     m.add_function(wrap_pyfunction!(loads, m)?)?;
+    m.add_function(wrap_pyfunction!(query, m)?)?;
     Ok(())
 }
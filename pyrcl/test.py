@@ -18,6 +18,29 @@ assert rcl.loads("true") == True
 assert rcl.loads('["foobar"]') == ["foobar"]
 assert rcl.loads("{1, 2, 3}") == {1, 2, 3}
 assert rcl.loads('{1: "one", "two": 2}') == {1: "one", "two": 2}
+assert rcl.loads('(1, "two")') == (1, "two")
+assert rcl.loads('0x"72636c"') == b"rcl"
+
+# Test that queries see the document as `input`.
+assert rcl.query("{ answer = 42 }", "input.answer") == 42
+
+# Test that errors carry the location where they occurred.
+try:
+    rcl.loads("let x = 1;\n  x + true")
+    assert False, "Expected an rcl.Error."
+except rcl.Error as err:
+    assert err.path == "input", err.path
+    assert err.line == 2, err.line
+    assert err.column == 7, err.column
+    assert "x + true" in str(err)
+
+# Test that checking reports type errors without evaluating.
+assert rcl.check("[1, 2, 3]") == None
+try:
+    rcl.check('let n: Int = "one"; n')
+    assert False, "Expected an rcl.Error."
+except rcl.Error as err:
+    assert err.line == 1, err.line
 
 # Test that loading files works. This needs to be executed from this directory.
 assert rcl.load_file("test.rcl") == {
@@ -13,12 +13,21 @@
 //! * Converting literals in the source code into values in the runtime.
 //! * Removing syntactical differences (e.g. converting `k = v;` into `"k": v`).
 
+use std::rc::Rc;
+
 use crate::ast::{
-    CallArg, Expr as AExpr, Expr, FormatFragment, Seq as ASeq, Stmt as AStmt, Type as AType, Yield,
+    CallArg, Expr as AExpr, Expr, FormatFragment, MatchArm as AMatchArm, Pattern as APattern,
+    Seq as ASeq, Stmt as AStmt, Type as AType, UnOp, Yield,
+};
+use crate::bytes::{self, DecodeError};
+use crate::cst::{
+    Chain, Expr as CExpr, MatchArm as CMatchArm, Pattern as CPattern, PipelineStage, Seq as CSeq,
+    Stmt as CStmt, StringPart, Type as CType,
 };
-use crate::cst::{Chain, Expr as CExpr, Seq as CSeq, Stmt as CStmt, StringPart, Type as CType};
 use crate::error::{IntoError, Result};
 use crate::lexer::QuoteStyle;
+use crate::number::Float;
+use crate::runtime::Value;
 use crate::source::Span;
 use crate::string;
 
@@ -32,6 +41,11 @@ struct Abstractor<'a> {
     input: &'a str,
 }
 
+/// Return whether a decimal literal is a float, i.e. has a fraction or exponent.
+fn is_float_literal(num_str: &str) -> bool {
+    num_str.contains(['.', 'e', 'E'])
+}
+
 impl<'a> Abstractor<'a> {
     pub fn new(input: &'a str) -> Self {
         Self { input }
@@ -122,6 +136,27 @@ impl<'a> Abstractor<'a> {
                 value_span: *value_span,
                 value: Box::new(self.expr(value)?),
             },
+            CStmt::LetTuple {
+                open,
+                close,
+                idents,
+                type_,
+                value_span,
+                value,
+            } => AStmt::LetTuple {
+                idents_span: open.union(*close),
+                idents: idents
+                    .elements
+                    .iter()
+                    .map(|ident| (ident.inner, ident.inner.resolve(self.input).into()))
+                    .collect(),
+                type_: match type_ {
+                    None => None,
+                    Some(t) => Some(Box::new(self.type_expr(t)?)),
+                },
+                value_span: *value_span,
+                value: Box::new(self.expr(value)?),
+            },
             CStmt::Assert {
                 condition_span,
                 condition,
@@ -168,9 +203,14 @@ impl<'a> Abstractor<'a> {
                 body
             }
 
-            CExpr::Import { path_span, path } => AExpr::Import {
+            CExpr::Import {
+                path_span,
+                path,
+                format,
+            } => AExpr::Import {
                 path_span: *path_span,
                 path: Box::new(self.expr(path)?),
+                format: *format,
             },
 
             CExpr::BraceLit { open, elements, .. } => AExpr::BraceLit {
@@ -193,6 +233,14 @@ impl<'a> Abstractor<'a> {
 
             CExpr::Parens { body, .. } => self.expr(body)?,
 
+            CExpr::TupleLit { elements, .. } => AExpr::TupleLit {
+                elements: elements
+                    .elements
+                    .iter()
+                    .map(|(span, elem)| Ok((*span, self.expr(elem)?)))
+                    .collect::<Result<Vec<_>>>()?,
+            },
+
             CExpr::NullLit(_span) => AExpr::NullLit,
 
             CExpr::BoolLit(_span, b) => AExpr::BoolLit(*b),
@@ -223,9 +271,51 @@ impl<'a> Abstractor<'a> {
                 }
             }
 
+            CExpr::BytesHexadecimal(span) => {
+                // Cut off the 0x and quotes, then decode the rest.
+                let digits = span.trim_start(3).trim_end(1);
+                match bytes::decode_hex(digits.resolve(self.input)) {
+                    Ok(b) => AExpr::BytesLit(b.into()),
+                    Err(DecodeError::InvalidDigit(i)) => {
+                        let err = digits
+                            .trim_start(i)
+                            .take(1)
+                            .error("Invalid hexadecimal digit in bytes literal.");
+                        return Err(err.into());
+                    }
+                    Err(DecodeError::InvalidLength) => {
+                        let err = span
+                            .error("Odd number of digits in bytes literal.")
+                            .with_help("Every byte is written as two hexadecimal digits.");
+                        return Err(err.into());
+                    }
+                }
+            }
+
+            CExpr::BytesBase64(span) => {
+                // Cut off the b64 and quotes, then decode the rest.
+                let digits = span.trim_start(4).trim_end(1);
+                match bytes::decode_base64(digits.resolve(self.input)) {
+                    Ok(b) => AExpr::BytesLit(b.into()),
+                    Err(DecodeError::InvalidDigit(i)) => {
+                        let err = digits
+                            .trim_start(i)
+                            .take(1)
+                            .error("Invalid base64 digit in bytes literal.");
+                        return Err(err.into());
+                    }
+                    Err(DecodeError::InvalidLength) => {
+                        let err = span.error("Invalid length for base64 data in bytes literal.");
+                        return Err(err.into());
+                    }
+                }
+            }
+
             CExpr::NumDecimal(span) => {
-                // TODO: Handle floats.
                 let num_str = span.resolve(self.input).replace('_', "");
+                if is_float_literal(&num_str) {
+                    return self.float_lit(*span, &num_str).map(AExpr::FloatLit);
+                }
                 match i64::from_str_radix(&num_str, 10) {
                     Ok(i) => AExpr::IntegerLit(i),
                     Err(..) => {
@@ -257,6 +347,46 @@ impl<'a> Abstractor<'a> {
                 ident: span.resolve(self.input).into(),
             },
 
+            CExpr::Tagged { tag, value } => AExpr::TaggedLit {
+                tag_span: *tag,
+                tag: self.tag(*tag),
+                value_span: value.as_ref().map(|(span, _)| *span).unwrap_or(*tag),
+                value: match value {
+                    Some((_, value)) => Box::new(self.expr(value)?),
+                    None => Box::new(AExpr::NullLit),
+                },
+            },
+
+            CExpr::Match {
+                scrutinee_span,
+                scrutinee,
+                arms,
+                ..
+            } => {
+                let mut result_arms: Vec<AMatchArm> = Vec::with_capacity(arms.elements.len());
+                for arm in arms.elements.iter() {
+                    let arm = self.match_arm(&arm.inner)?;
+                    for prev in result_arms.iter() {
+                        if prev.pattern.covers(&arm.pattern) {
+                            return arm
+                                .pattern_span
+                                .error("This arm is unreachable.")
+                                .with_note(
+                                    prev.pattern_span,
+                                    "An earlier arm already matches every value that this arm matches.",
+                                )
+                                .err();
+                        }
+                    }
+                    result_arms.push(arm);
+                }
+                AExpr::Match {
+                    scrutinee_span: *scrutinee_span,
+                    scrutinee: Box::new(self.expr(scrutinee)?),
+                    arms: result_arms,
+                }
+            }
+
             CExpr::Function {
                 args,
                 body_span,
@@ -272,6 +402,19 @@ impl<'a> Abstractor<'a> {
                 body: Box::new(self.expr(body)?),
             },
 
+            // Floats have no arithmetic, a negative float literal is a literal.
+            CExpr::UnOp {
+                op_span,
+                op: UnOp::Neg,
+                body_span,
+                body,
+            } if matches!(body.as_ref(), CExpr::NumDecimal(span) if is_float_literal(span.resolve(self.input))) =>
+            {
+                let num_str = body_span.resolve(self.input).replace('_', "");
+                let x = self.float_lit(op_span.union(*body_span), &num_str)?;
+                AExpr::FloatLit(Float::new(-x.get()).expect("Negating a finite float is finite."))
+            }
+
             CExpr::UnOp {
                 op_span,
                 op,
@@ -307,6 +450,67 @@ impl<'a> Abstractor<'a> {
                 }
                 inner_expr
             }
+
+            CExpr::Pipeline {
+                base_span,
+                base,
+                stages,
+            } => {
+                let mut arg_span = *base_span;
+                let mut arg = self.expr(base)?;
+                for stage in stages.iter() {
+                    arg = self.pipeline_stage(stage, arg_span, arg)?;
+                    arg_span = arg_span.union(stage.function_span);
+                }
+                arg
+            }
+        };
+        Ok(result)
+    }
+
+    /// Desugar a pipeline stage `|> f(y)` into a call `f(arg, y)`.
+    ///
+    /// When the stage is not a call, such as in `|> f`, it becomes `f(arg)`.
+    fn pipeline_stage(&self, stage: &PipelineStage, arg_span: Span, arg: AExpr) -> Result<AExpr> {
+        let arg = CallArg {
+            span: arg_span,
+            value: arg,
+        };
+        let result = match &stage.function {
+            CExpr::Chain { base_expr, chain }
+                if matches!(chain.last(), Some((_, Chain::Call { .. }))) =>
+            {
+                let (call, init) = chain.split_last().expect("Chain is not empty.");
+                let mut function = self.expr(base_expr)?;
+                for (span, chain_expr) in init.iter() {
+                    function = self.chain(chain_expr, *span, function)?;
+                }
+                let (function_span, Chain::Call { open, close, args }) = call else {
+                    unreachable!("We matched on the call above.");
+                };
+                let mut call_args = Vec::with_capacity(args.elements.len() + 1);
+                call_args.push(arg);
+                for (span, a) in args.elements.iter() {
+                    call_args.push(CallArg {
+                        span: *span,
+                        value: self.expr(a)?,
+                    });
+                }
+                AExpr::Call {
+                    open: *open,
+                    close: *close,
+                    function_span: *function_span,
+                    function: Box::new(function),
+                    args: call_args,
+                }
+            }
+            function => AExpr::Call {
+                open: stage.op_span,
+                close: stage.op_span,
+                function_span: stage.function_span,
+                function: Box::new(self.expr(function)?),
+                args: vec![arg],
+            },
         };
         Ok(result)
     }
@@ -441,6 +645,71 @@ impl<'a> Abstractor<'a> {
         Ok(result)
     }
 
+    /// Return the name of a tag, without the leading `#`.
+    /// Parse a float literal, with the underscores already removed.
+    fn float_lit(&self, span: Span, num_str: &str) -> Result<Float> {
+        match Float::parse(num_str) {
+            Some(x) => Ok(x),
+            None => span
+                .error("Overflow in float literal.")
+                .with_help("Floats must be finite, the largest float is about 1.8e308.")
+                .err(),
+        }
+    }
+
+    fn tag(&self, span: Span) -> Rc<str> {
+        span.resolve(self.input)[1..].into()
+    }
+
+    /// Abstract an arm of a `match` expression.
+    fn match_arm(&self, arm: &CMatchArm) -> Result<AMatchArm> {
+        let result = AMatchArm {
+            pattern_span: arm.pattern.span(),
+            pattern: self.pattern(&arm.pattern)?,
+            body_span: arm.body_span,
+            body: self.expr(&arm.body)?,
+        };
+        Ok(result)
+    }
+
+    /// Abstract a pattern, evaluating literals to values.
+    fn pattern(&self, pattern: &CPattern) -> Result<APattern> {
+        let result = match pattern {
+            CPattern::Wildcard(..) => APattern::Wildcard,
+            CPattern::Binding(span) => APattern::Binding(*span, span.resolve(self.input).into()),
+            CPattern::Literal { span, value } => {
+                let value = match self.expr(value)? {
+                    AExpr::NullLit => Value::Null,
+                    AExpr::BoolLit(b) => Value::Bool(b),
+                    AExpr::IntegerLit(i) => Value::Int(i),
+                    AExpr::FloatLit(x) => Value::Float(x),
+                    AExpr::StringLit(s) => Value::String(s.into()),
+                    AExpr::UnOp {
+                        op: UnOp::Neg,
+                        body,
+                        ..
+                    } => match *body {
+                        AExpr::IntegerLit(i) => match i.checked_neg() {
+                            Some(n) => Value::Int(n),
+                            None => return span.error("Overflow in integer literal.").err(),
+                        },
+                        _ => unreachable!("The parser only allows negated numbers."),
+                    },
+                    _ => unreachable!("The parser only allows literals in patterns."),
+                };
+                APattern::Literal(value)
+            }
+            CPattern::Tag { tag, inner } => APattern::Tag {
+                tag: self.tag(*tag),
+                inner: match inner {
+                    Some(inner) => Some(Box::new(self.pattern(inner)?)),
+                    None => None,
+                },
+            },
+        };
+        Ok(result)
+    }
+
     /// Abstract a type expression.
     pub fn type_expr(&self, type_: &CType) -> Result<AType> {
         let result = match type_ {
@@ -457,6 +726,14 @@ impl<'a> Abstractor<'a> {
                     .map(|arg| self.type_expr(&arg.inner))
                     .collect::<Result<Box<_>>>()?,
             },
+            CType::Tagged { span, tag, value } => AType::Tagged {
+                span: *span,
+                tag: self.tag(*tag),
+                value: match value {
+                    Some(value) => Some(Box::new(self.type_expr(value)?)),
+                    None => None,
+                },
+            },
             CType::Function { span, args, result } => AType::Function {
                 span: *span,
                 args: args
@@ -0,0 +1,194 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Scratch storage for values that do not outlive a comprehension.
+//!
+//! Most values are reference-counted, because they can end up anywhere in the
+//! result. But some values provably cannot escape. In `for x in [a, b]: body`,
+//! the list literal is only iterated over; the body can refer to the elements,
+//! but not to the list itself. For such a collection, the evaluator pushes the
+//! elements onto a bump-allocated stack instead of allocating a new list, and
+//! resets the stack when the loop is done. In nested comprehensions that saves
+//! an allocation and a reference count per iteration of the outer loop.
+//!
+//! The escape analysis is syntactic, see [`scratch_list`].
+//!
+//! `cargo bench --bench comprehension` counts allocations, which does not
+//! depend on the machine. With and without the `arena` feature:
+//!
+//! | Case             | Default | Arena |
+//! |------------------|--------:|------:|
+//! | `pairs`          |    6155 |  3157 |
+//! | `nested`         |    1176 |   582 |
+//! | `dict`           |   23087 | 21089 |
+//! | `no_temporaries` |     165 |   166 |
+//!
+//! The timings are noisy on a shared machine, the minimum over 201 runs went
+//! from 0.84 to 0.79 ms for `pairs`, and from 2.83 to 2.76 ms for `dict`.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Seq};
+use crate::runtime::Value;
+use crate::source::Span;
+
+/// When the stack is empty, shrink it to this many elements if it grew larger.
+///
+/// A single large temporary list should not hold on to memory for the rest of
+/// the evaluation.
+const MAX_IDLE_CAPACITY: usize = 4096;
+
+/// A stack of the elements of temporary lists, that is used like a bump allocator.
+///
+/// A comprehension pushes the elements of its list on top, and resets the
+/// stack to where it started when the loop is done. Nested comprehensions nest
+/// on the stack, so all temporary lists share one allocation. The evaluator
+/// collects elements through closures that cannot borrow the evaluator, so
+/// the stack is shared through a cheap handle.
+#[derive(Clone, Default)]
+pub struct ScratchArena {
+    values: Rc<RefCell<Vec<Value>>>,
+}
+
+impl ScratchArena {
+    pub fn new() -> ScratchArena {
+        ScratchArena::default()
+    }
+
+    /// Return the current top of the stack, to reset to later.
+    pub fn mark(&self) -> usize {
+        self.values.borrow().len()
+    }
+
+    pub fn push(&self, value: Value) {
+        self.values.borrow_mut().push(value);
+    }
+
+    /// Return the element at `index`, which must be below the top of the stack.
+    pub fn get(&self, index: usize) -> Value {
+        self.values.borrow()[index].clone()
+    }
+
+    /// Drop all elements above `mark`, keeping the allocation.
+    pub fn reset(&self, mark: usize) {
+        // Take the elements out before we drop them, dropping a value should
+        // not need the arena, but we don't need to hold the borrow either.
+        let dropped = {
+            let mut values = self.values.borrow_mut();
+            let dropped = values.split_off(mark);
+            if mark == 0 && values.capacity() > MAX_IDLE_CAPACITY {
+                values.shrink_to(MAX_IDLE_CAPACITY);
+            }
+            dropped
+        };
+        drop(dropped);
+    }
+}
+
+/// Return the elements of a `for` loop collection that can use scratch storage.
+///
+/// Only list literals qualify. A name or call can evaluate to a value that is
+/// referenced elsewhere, and set and dict literals need their elements sorted
+/// and deduplicated, which a stack does not do. The elements are pushed as the
+/// list yields them, so the list must not itself loop over a list that lives
+/// in the arena, because that loop would reset the stack below our elements.
+pub fn scratch_list(collection: &Expr) -> Option<(Span, &[Seq])> {
+    match collection {
+        Expr::BracketLit { open, elements } if !elements.iter().any(uses_arena) => {
+            Some((*open, elements))
+        }
+        _ => None,
+    }
+}
+
+/// Return whether the seq uses the arena while it yields its elements.
+///
+/// Temporary lists in the expressions inside the seq are fine, they are gone
+/// by the time the seq yields. But the collection of a `for` is not.
+fn uses_arena(seq: &Seq) -> bool {
+    match seq {
+        Seq::Yield(..) => false,
+        Seq::If { body, .. } | Seq::Stmt { body, .. } => uses_arena(body),
+        Seq::For {
+            collection, body, ..
+        } => scratch_list(collection).is_some() || uses_arena(body),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ScratchArena;
+    use crate::eval::{Evaluator, StrictMode};
+    use crate::loader::Loader;
+    use crate::runtime::Value;
+    use crate::tracer::VoidTracer;
+
+    #[test]
+    fn scratch_arena_resets_to_mark() {
+        let arena = ScratchArena::new();
+        arena.push(Value::Int(1));
+        let mark = arena.mark();
+        arena.push(Value::Int(2));
+        arena.push(Value::Int(3));
+        assert_eq!(arena.get(2), Value::Int(3));
+        arena.reset(mark);
+        assert_eq!(arena.mark(), 1);
+        assert_eq!(arena.get(0), Value::Int(1));
+
+        // A stack that grew too large releases its memory once it is empty.
+        for i in 0..2 * super::MAX_IDLE_CAPACITY {
+            arena.push(Value::Int(i as i64));
+        }
+        arena.reset(0);
+        assert!(arena.values.borrow().capacity() <= super::MAX_IDLE_CAPACITY);
+    }
+
+    #[test]
+    fn scratch_arena_is_reset_on_every_path() {
+        let mut loader = Loader::new();
+        let mut tracer = VoidTracer;
+        // The lists depend on `n`, so constant folding does not unroll them.
+        let cases = [
+            // Nested temporary lists.
+            (
+                "[for n in std.range(1, 3): for x in [n, n + 1]: for y in [x, x * 10]: y]",
+                Some("[1, 10, 2, 20, 2, 20, 3, 30]"),
+            ),
+            // A temporary list that is itself a comprehension over one.
+            (
+                "[for n in std.range(1, 3): for x in [for y in [n, n + 1]: y * 10]: x]",
+                Some("[10, 20, 20, 30]"),
+            ),
+            // Fails in the body of the inner loop, after pushing both lists.
+            (
+                "[for n in std.range(1, 3): for x in [n, 0]: for y in [x, n]: n / x]",
+                None,
+            ),
+            // Fails while evaluating the elements of the temporary list.
+            ("[for n in std.range(1, 3): for x in [n, n / 0]: x]", None),
+        ];
+        for (input, expected) in cases {
+            let doc = loader.load_string(input.into());
+            let mut type_env = loader.type_prelude();
+            let mut value_env = loader.value_prelude();
+            let mut evaluator = Evaluator::new(&mut loader, &mut tracer);
+            evaluator.strict_mode = StrictMode::Off;
+            let result = evaluator.eval_doc(&mut type_env, &mut value_env, doc);
+            let cfg = crate::pprint::Config { width: 80 };
+            let result = result.ok().map(|v| {
+                let doc = crate::fmt_rcl::format_rcl(&v);
+                doc.println(&cfg)
+                    .to_string_no_markup()
+                    .trim_end()
+                    .to_string()
+            });
+            assert_eq!(result.as_deref(), expected, "{input}");
+            assert_eq!(evaluator.arena.mark(), 0, "{input}");
+        }
+    }
+}
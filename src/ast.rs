@@ -10,8 +10,10 @@
 use std::fmt;
 use std::rc::Rc;
 
-pub use crate::cst::{BinOp, UnOp};
+pub use crate::cst::{BinOp, ImportFormat, UnOp};
 
+use crate::number::Float;
+use crate::runtime::Value;
 use crate::source::Span;
 use crate::types::{self, SourcedType};
 
@@ -69,6 +71,16 @@ pub enum Stmt {
         value: Box<Expr>,
     },
 
+    /// A let-binding that destructures a tuple into multiple names.
+    LetTuple {
+        /// The span of the `()`-enclosed identifiers.
+        idents_span: Span,
+        idents: Vec<(Span, Ident)>,
+        type_: Option<Box<Type>>,
+        value_span: Span,
+        value: Box<Expr>,
+    },
+
     /// Evaluate to the body if true, fail with the message if false.
     Assert {
         /// The span of the condition. Here we report the error from.
@@ -110,6 +122,9 @@ pub enum Expr {
 
         /// An expression that evaluates to the path to import.
         path: Box<Expr>,
+
+        /// Whether to evaluate the file, or take its contents as a string.
+        format: ImportFormat,
     },
 
     /// A dict or set literal (depending on the element types) enclosed in `{}`.
@@ -126,6 +141,9 @@ pub enum Expr {
     /// A dict literal enclosed in `{}`.
     DictLit { open: Span, elements: Vec<Seq> },
 
+    /// A tuple literal enclosed in `()`.
+    TupleLit { elements: Vec<(Span, Expr)> },
+
     /// A null literal.
     NullLit,
 
@@ -139,6 +157,12 @@ pub enum Expr {
     /// TODO: This should be a bigint.
     IntegerLit(i64),
 
+    /// A float literal, one with a fraction or exponent.
+    FloatLit(Float),
+
+    /// A bytes literal.
+    BytesLit(Rc<[u8]>),
+
     /// A format string, with string literals and hole contents interleaved.
     Format(Vec<FormatFragment>),
 
@@ -155,6 +179,22 @@ pub enum Expr {
     /// Access a variable.
     Var { span: Span, ident: Ident },
 
+    /// A tagged value. When the payload is omitted in the source, it is null.
+    TaggedLit {
+        tag_span: Span,
+        /// The tag, without the leading `#`.
+        tag: Rc<str>,
+        value_span: Span,
+        value: Box<Expr>,
+    },
+
+    /// Select the first arm whose pattern matches the scrutinee.
+    Match {
+        scrutinee_span: Span,
+        scrutinee: Box<Expr>,
+        arms: Vec<MatchArm>,
+    },
+
     /// Access a field on the inner expression.
     Field {
         inner: Box<Expr>,
@@ -242,6 +282,71 @@ pub enum Expr {
     },
 }
 
+/// One arm in a `match` expression.
+// TODO: Should not be cloneable, make it GC'able instead.
+#[derive(Clone, Debug)]
+pub struct MatchArm {
+    pub pattern_span: Span,
+    pub pattern: Pattern,
+    pub body_span: Span,
+    pub body: Expr,
+}
+
+/// A pattern in a `match` arm.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// Match any value without binding it.
+    Wildcard,
+
+    /// Match any value and bind it to the name.
+    Binding(Span, Ident),
+
+    /// Match values equal to the literal.
+    Literal(Value),
+
+    /// Match a tagged value with the given tag, optionally matching the payload.
+    Tag {
+        /// The tag, without the leading `#`.
+        tag: Rc<str>,
+        inner: Option<Box<Pattern>>,
+    },
+}
+
+impl Pattern {
+    /// Whether the pattern matches any value.
+    pub fn is_irrefutable(&self) -> bool {
+        matches!(self, Pattern::Wildcard | Pattern::Binding(..))
+    }
+
+    /// Append the names that the pattern binds to `out`.
+    pub fn bound_names(&self, out: &mut Vec<Ident>) {
+        match self {
+            Pattern::Binding(_span, name) => out.push(name.clone()),
+            Pattern::Tag {
+                inner: Some(inner), ..
+            } => inner.bound_names(out),
+            _ => {}
+        }
+    }
+
+    /// Whether every value that `other` matches is also matched by `self`.
+    pub fn covers(&self, other: &Pattern) -> bool {
+        match (self, other) {
+            (p, _) if p.is_irrefutable() => true,
+            (Pattern::Literal(v1), Pattern::Literal(v2)) => v1 == v2,
+            (Pattern::Tag { tag: t1, inner: i1 }, Pattern::Tag { tag: t2, inner: i2 }) => {
+                t1 == t2
+                    && match (i1, i2) {
+                        (None, _) => true,
+                        (Some(i1), None) => i1.is_irrefutable(),
+                        (Some(i1), Some(i2)) => i1.covers(i2),
+                    }
+            }
+            _ => false,
+        }
+    }
+}
+
 /// The innermost part of comprehension ([`Seq`]).
 // TODO: Should not be cloneable, make it GC'able instead.
 #[derive(Clone, Debug)]
@@ -309,6 +414,14 @@ pub enum Type {
         args: Box<[Type]>,
     },
 
+    /// A tagged type. When the payload type is omitted, it is `Null`.
+    Tagged {
+        span: Span,
+        /// The tag, without the leading `#`.
+        tag: Rc<str>,
+        value: Option<Box<Type>>,
+    },
+
     /// A function type with zero or more arguments, and one result type.
     Function {
         span: Span,
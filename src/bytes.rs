@@ -0,0 +1,180 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Hexadecimal and base64 encodings for bytes values.
+//!
+//! Base64 uses the standard alphabet from RFC 4648 with `=` padding. When
+//! decoding, the padding is optional.
+
+/// An error that occurred while decoding.
+#[derive(Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// The byte at the given offset is not valid in the encoding.
+    InvalidDigit(usize),
+
+    /// The input has a length that no sequence of bytes encodes to.
+    InvalidLength,
+}
+
+/// Append the bytes as lowercase hexadecimal digits, two per byte.
+pub fn encode_hex(bytes: &[u8], out: &mut String) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    out.reserve(bytes.len() * 2);
+    for b in bytes {
+        out.push(DIGITS[(b >> 4) as usize] as char);
+        out.push(DIGITS[(b & 0xf) as usize] as char);
+    }
+}
+
+/// Decode hexadecimal digits, two per byte. Both cases are accepted.
+pub fn decode_hex(input: &str) -> Result<Vec<u8>, DecodeError> {
+    let input = input.as_bytes();
+    let mut result = Vec::with_capacity(input.len() / 2);
+    let digit = |i: usize| -> Result<u8, DecodeError> {
+        match input[i] {
+            ch @ b'0'..=b'9' => Ok(ch - b'0'),
+            ch @ b'a'..=b'f' => Ok(ch - b'a' + 10),
+            ch @ b'A'..=b'F' => Ok(ch - b'A' + 10),
+            _ => Err(DecodeError::InvalidDigit(i)),
+        }
+    };
+    for i in (0..input.len()).step_by(2) {
+        let high = digit(i)?;
+        if i + 1 == input.len() {
+            return Err(DecodeError::InvalidLength);
+        }
+        result.push((high << 4) | digit(i + 1)?);
+    }
+    Ok(result)
+}
+
+const BASE64_DIGITS: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Append the base64 encoding of the bytes, including padding.
+pub fn encode_base64(bytes: &[u8], out: &mut String) {
+    out.reserve((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | (b[2] as u32);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let digit = (n >> (18 - 6 * i)) & 0x3f;
+                out.push(BASE64_DIGITS[digit as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+}
+
+/// Return the base64 encoding of the bytes, including padding.
+pub fn to_base64(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    encode_base64(bytes, &mut result);
+    result
+}
+
+/// Decode base64 data, with or without padding.
+pub fn decode_base64(input: &str) -> Result<Vec<u8>, DecodeError> {
+    let input = input.as_bytes();
+    let data = match input {
+        [rest @ .., b'=', b'='] | [rest @ .., b'='] => {
+            if input.len() % 4 != 0 {
+                return Err(DecodeError::InvalidLength);
+            }
+            rest
+        }
+        _ => input,
+    };
+    if data.len() % 4 == 1 {
+        return Err(DecodeError::InvalidLength);
+    }
+
+    let mut result = Vec::with_capacity(data.len() * 3 / 4);
+    let mut acc: u32 = 0;
+    let mut n_bits = 0;
+    for (i, ch) in data.iter().enumerate() {
+        let digit = match ch {
+            b'A'..=b'Z' => ch - b'A',
+            b'a'..=b'z' => ch - b'a' + 26,
+            b'0'..=b'9' => ch - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(DecodeError::InvalidDigit(i)),
+        };
+        acc = (acc << 6) | digit as u32;
+        n_bits += 6;
+        if n_bits >= 8 {
+            n_bits -= 8;
+            result.push((acc >> n_bits) as u8);
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_base64, decode_hex, encode_hex, to_base64, DecodeError};
+
+    fn hex(bytes: &[u8]) -> String {
+        let mut out = String::new();
+        encode_hex(bytes, &mut out);
+        out
+    }
+
+    #[test]
+    fn hex_roundtrips() {
+        assert_eq!(hex(b""), "");
+        assert_eq!(hex(&[0x00, 0x7f, 0xde, 0xad]), "007fdead");
+        assert_eq!(
+            decode_hex("007fDEad").unwrap(),
+            vec![0x00, 0x7f, 0xde, 0xad]
+        );
+        assert_eq!(decode_hex("").unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn hex_reports_errors() {
+        assert_eq!(decode_hex("abc"), Err(DecodeError::InvalidLength));
+        assert_eq!(decode_hex("a_"), Err(DecodeError::InvalidDigit(1)));
+        assert_eq!(decode_hex("0g12"), Err(DecodeError::InvalidDigit(1)));
+    }
+
+    #[test]
+    fn base64_roundtrips() {
+        // Test vectors from RFC 4648, section 10.
+        let cases = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (plain, encoded) in cases {
+            assert_eq!(to_base64(plain.as_bytes()), encoded);
+            assert_eq!(decode_base64(encoded).unwrap(), plain.as_bytes());
+            let unpadded = encoded.trim_end_matches('=');
+            assert_eq!(decode_base64(unpadded).unwrap(), plain.as_bytes());
+        }
+        assert_eq!(to_base64(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn base64_reports_errors() {
+        assert_eq!(decode_base64("Zm9vY"), Err(DecodeError::InvalidLength));
+        assert_eq!(decode_base64("Zm8=="), Err(DecodeError::InvalidLength));
+        assert_eq!(decode_base64("Zm=v"), Err(DecodeError::InvalidDigit(2)));
+        assert_eq!(decode_base64("Zm9-"), Err(DecodeError::InvalidDigit(3)));
+    }
+}
@@ -11,10 +11,19 @@ use std::str::FromStr;
 
 use crate::cli_utils::{match_option, parse_option, Arg, ArgIter};
 use crate::cmd_build::BuildMode;
+use crate::cmd_convert::InputFormat;
+use crate::cmd_doc::DocFormat;
+use crate::debugger::Breakpoint;
 use crate::error::{Error, Result};
+use crate::eval::StrictMode;
+use crate::fmt_cst::FormatOptions;
+use crate::fmt_rcl::Limits;
 use crate::loader::SandboxMode;
 use crate::markup::{Markup, MarkupMode};
 use crate::pprint::{concat, Doc};
+use crate::profile::ProfileFormat;
+use crate::secret::{SecretOptions, SecretSource};
+use crate::stdlib::Capabilities;
 
 const USAGE_MAIN: &str = r#"
 RCL -- A reasonable configuration language.
@@ -24,10 +33,21 @@ Usage:
 
 Commands:
   build        Write formatted evaluation results to files.
+  bundle       Inline all imports of a document into a single document.
+  check        Evaluate one or more documents and report any errors.
+  convert      Convert a JSON, TOML, or YAML document to RCL.
+  crash-repro  Run an input through every stage, to reproduce a crash.
+  debug        Evaluate a document interactively, pausing at breakpoints.
+  doc          Render reference docs for a library document.
   evaluate     Evaluate a document to an output format.
   format       Auto-format an RCL document.
+  formats      List the output formats and what they support.
   highlight    Print a document with syntax highlighting.
+  lint         Report exports and files that no entry point uses.
+  lock         Record the hashes of all documents that a document imports.
   query        Evaluate an expression against an input document.
+  serve        Answer requests from editor tooling, keeping documents loaded.
+  trace-output Print the expressions that produced a value in the output.
 
 Command shorthands:
   e, eval      Alias for 'evaluate'.
@@ -40,8 +60,17 @@ Command shorthands:
 Global options:
   -h --help             Show this screen, or command-specific help.
   --version             Show version.
+  --allow-shadowing     Do not warn when a 'let' shadows an outer binding.
   --color <mode>        Set how output is colored, see modes below.
   -C --directory <dir>  Change the working directory.
+  --full-errors         Print values in error messages in full, instead of
+                        eliding elements of large collections.
+  --no-opt              Do not fold constant expressions before evaluating.
+  --stdin-filename <name>
+                        When reading a document from stdin, report it as <name>
+                        in diagnostics, and resolve imports relative to it.
+  --tab-width <n>       Columns between tab stops in diagnostics, from 1 to 16.
+                        Defaults to 8.
 
 Color modes:
   ansi    Always color output using ANSI escape codes.
@@ -55,7 +84,7 @@ const USAGE_BUILD: &str = r##"
 RCL -- A reasonable configuration language.
 
 Usage:
-  rcl [<options>] build [<buildfile>]
+  rcl [<options>] build [<options>] [<buildfile>...]
 
 The 'build' command writes formatted values to files. It can be used to update
 many generated files in one command, similar to a build tool like Make or Ninja,
@@ -63,16 +92,43 @@ but with the build targets specified in RCL rather than a makefile. The build
 file is an RCL document that should evaluate to a dict that maps output file
 paths to targets. Targets are dicts with fields as described below.
 
+With multiple build files, the files are evaluated in parallel. Every build
+file must write to different output files. Progress and errors are reported in
+the order of the inputs, and the first error stops the build.
+
 Arguments:
-  <buildfile>       The file with build targets to process, or '-' for stdin.
+  <buildfile>...    The files with build targets to process, or '-' for stdin.
                     Defaults to 'build.rcl' when no file is specified.
 
 Options:
+  --allow-network   Enable functions that access the network, see 'rcl
+                    evaluate --help'.
   --dry-run         Print what files we would write to stdout, instead of
                     writing to the file system, which would overwrite existing
                     files.
+  -j --jobs <n>     Number of threads to build with. Defaults to the number of
+                    CPUs. The targets of a single build file are split over
+                    threads too when this is set.
+  --locked          Fail if imported documents changed since 'rcl lock', see
+                    'rcl evaluate --help'.
+  --lockfile <file> The lockfile to check with '--locked'.
+  --mode <mode>     Unix permissions for output files in octal, e.g. '0644'.
+                    Defaults to the permissions of the file being replaced, or
+                    the default permissions for new files.
+  --profile <file>  Write an evaluation profile to <file>, see 'rcl evaluate
+                    --help'.
+  --profile-format <format>
+                    Format of the profile, 'hotspots' or 'folded'.
+  --resolve-secrets <src>
+                    Where to read the contents of secrets from, see 'rcl
+                    evaluate --help'. Defaults to 'placeholder'.
   --sandbox <mode>  Sandboxing mode, see 'rcl evaluate --help' for an
                     explanation of the modes. Defaults to 'workdir'.
+  --secret-placeholder <template>
+                    What unresolved secrets export as, see 'rcl evaluate
+                    --help'.
+  --strict <mode>   How to treat implicit conversions, see 'rcl evaluate
+                    --help' for an explanation of the modes. Defaults to 'off'.
 
 See also --help for global options.
 
@@ -106,16 +162,311 @@ Build target fields:
                     Optional, defaults to 80.
 "##;
 
+const USAGE_LOCK: &str = r#"
+RCL -- A reasonable configuration language.
+
+Usage:
+  rcl [<options>] lock [<options>] [<file>]
+
+The 'lock' command writes a lockfile that records the SHA-256 hash of every
+document that the input document imports, directly or indirectly. When
+evaluating with '--locked', evaluation fails if any of those documents changed.
+
+Arguments:
+  <file>                The input file to process, or '-' for stdin. Defaults
+                        to stdin when no file is specified.
+
+Options:
+  --dry-run             Print the lockfile to stdout instead of writing it.
+  --lockfile <file>     The lockfile to write. Defaults to the input file with
+                        '.lock' appended. Required when the input is stdin.
+  --sandbox <mode>      Sandboxing mode, see 'rcl evaluate --help' for an
+                        explanation of the modes. Defaults to 'workdir'.
+
+See also --help for global options.
+"#;
+
+const USAGE_LINT: &str = r#"
+RCL -- A reasonable configuration language.
+
+Usage:
+  rcl [<options>] lint --unused-exports --entry <entry>... [<file>...]
+
+The 'lint' command follows the imports of one or more entry points, and reports
+code that is never used. With '--unused-exports', it reports the fields of
+imported documents that no document accesses, and files among <file> that the
+entry points do not import, directly or indirectly. The exit code is 1 if there
+are any findings, and 0 otherwise.
+
+Arguments:
+  <file>...          Files to report when the entry points do not import them.
+
+Options:
+  --entry <entry>    An entry point, such as a document that 'rcl build'
+                     evaluates, or '-' for stdin. Can be repeated.
+  --sandbox <mode>   Sandboxing mode, see 'rcl evaluate --help' for an
+                     explanation of the modes. Defaults to 'workdir'.
+  --unused-exports   Report exports and files that are never used.
+
+See also --help for global options.
+"#;
+
+const USAGE_FORMATS: &str = r#"
+RCL -- A reasonable configuration language.
+
+Usage:
+  rcl [<options>] formats
+
+The 'formats' command lists the output formats that '--format' accepts. For
+every format it shows which dict keys the format can represent, which output
+options it supports, and a short description.
+
+Columns:
+  KEYS      'string' if dict keys must be strings, 'any' otherwise.
+  OPTIONS   'limits' if the format supports --max-depth and --max-items,
+            'ascii' if it supports --output-encoding=ascii.
+
+See also --help for global options.
+"#;
+
+const USAGE_DEBUG: &str = r#"
+RCL -- A reasonable configuration language.
+
+Usage:
+  rcl [<options>] debug [<options>] <file>
+
+The 'debug' command evaluates the input document like 'evaluate', but pauses
+evaluation to let you inspect it. While paused, it reads commands from stdin.
+Enter 'help' at the prompt for a list of commands. Without breakpoints, the
+debugger pauses at the first statement.
+
+Arguments:
+  <file>                The input file to evaluate. Reading the input from stdin
+                        is not supported, because stdin is used for commands.
+
+Options:
+  --break <file>:<line> Pause when evaluation reaches the given line of a file
+                        whose path ends in <file>. Can be repeated.
+  --break-on-trace      Pause after every 'trace' statement.
+  --allow-network       Enable functions that access the network, see 'rcl
+                        evaluate --help'.
+  -f --format <format>  Output format for the final value, see 'rcl evaluate
+                        --help'. Defaults to 'rcl'.
+  --sandbox <mode>      Sandboxing mode, see 'rcl evaluate --help' for an
+                        explanation of the modes. Defaults to 'workdir'.
+  --strict <mode>       How to treat implicit conversions, see 'rcl evaluate
+                        --help' for an explanation of the modes. Defaults to
+                        'off'.
+  -w --width <width>    Target width for pretty-printing the final value.
+                        Defaults to 80.
+
+See also --help for global options.
+"#;
+
+const USAGE_CRASH_REPRO: &str = r#"
+RCL -- A reasonable configuration language.
+
+Usage:
+  rcl [<options>] crash-repro [<options>] [<file>]
+
+The 'crash-repro' command reads raw bytes and runs them through every stage of
+the pipeline in turn: decode, lex, parse, abstract, typecheck, evaluate, and
+format. It prints which stages succeeded, and the error of the stage that
+failed. If RCL crashes instead, it prints the stage that was running. Use it to
+find a minimal input before reporting a crash.
+
+The input cannot access the filesystem or network, and evaluation is subject to
+the usual limits on depth and number of steps. Inputs from the fuzz corpus can
+be used directly, the fuzzer's header line is stripped.
+
+Arguments:
+  <file>        The input file to replay, or '-' for stdin. Defaults to stdin
+                when no file is specified.
+
+Options:
+  -w --width <width>  Target width for the format stage, defaults to 80. For
+                      inputs from the fuzz corpus, the header sets the width.
+
+See also --help for global options.
+"#;
+
+const USAGE_TRACE_OUTPUT: &str = r#"
+RCL -- A reasonable configuration language.
+
+Usage:
+  rcl [<options>] trace-output [<options>] [<file>] <path>
+
+The 'trace-output' command evaluates the input document, and prints the chain
+of expressions that produced the value at <path> in the output: the expression
+that defined the value, the comprehension that yielded it, and for values that
+were merged with '|', the values that they override.
+
+Arguments:
+  <file>                The input file to evaluate, or '-' for stdin. Defaults
+                        to stdin when no file is specified.
+  <path>                The location in the output, as keys and list indices
+                        separated by dots, for example 'spec.ports.0'. A path
+                        that starts with '/' is a JSON pointer instead.
+
+Options:
+  --allow-network       Enable functions that access the network, see 'rcl
+                        evaluate --help'.
+  --sandbox <mode>      Sandboxing mode, see 'rcl evaluate --help' for an
+                        explanation of the modes. Defaults to 'workdir'.
+  --strict <mode>       How to treat implicit conversions, see 'rcl evaluate
+                        --help' for an explanation of the modes. Defaults to
+                        'off'.
+
+See also --help for global options.
+"#;
+
+const USAGE_BUNDLE: &str = r#"
+RCL -- A reasonable configuration language.
+
+Usage:
+  rcl [<options>] bundle [<options>] [<file>]
+
+The 'bundle' command inlines the documents that the input document imports,
+directly or indirectly, and prints the result as a single formatted document
+that has no imports. Documents that are imported multiple times are included
+once. This is useful to ship a configuration to an environment that does not
+have the full source tree.
+
+Arguments:
+  <file>                 The input file to process, or '-' for stdin. Defaults
+                         to stdin when no file is specified.
+
+Options:
+  -o --output <outfile>  Write to the given file instead of stdout.
+  --sandbox <mode>       Sandboxing mode, see 'rcl evaluate --help' for an
+                         explanation of the modes. Defaults to 'workdir'.
+  -w --width <width>     Target width for pretty-printing, must be an integer.
+                         Defaults to 80.
+
+See also --help for global options.
+"#;
+
+const USAGE_CHECK: &str = r#"
+RCL -- A reasonable configuration language.
+
+Usage:
+  rcl [<options>] check [<options>] [<file>...]
+
+The 'check' command evaluates one or more documents, and reports errors for
+every document that fails to evaluate. The documents are evaluated in a single
+process, which is faster than invoking 'rcl evaluate' for every file. The exit
+code is 1 if any document failed to evaluate, and 0 otherwise. Documents are
+evaluated in parallel, errors are reported in the order of the inputs.
+
+Arguments:
+  <file>...         The input files to process, or '-' for stdin. Defaults to
+                    stdin when no file is specified.
+
+Options:
+  -j --jobs <n>     Number of documents to evaluate in parallel. Defaults to
+                    the number of CPUs.
+  --sandbox <mode>  Sandboxing mode, see 'rcl evaluate --help' for an
+                    explanation of the modes. Defaults to 'workdir'.
+  --strict <mode>   How to treat implicit conversions, see 'rcl evaluate
+                    --help' for an explanation of the modes. Defaults to 'off'.
+
+See also --help for global options.
+"#;
+
+const USAGE_SERVE: &str = r#"
+RCL -- A reasonable configuration language.
+
+Usage:
+  rcl [<options>] serve [<options>]
+
+The 'serve' command starts a long-running server for editor tooling and
+watch-mode builds. It reads JSON-RPC 2.0 requests, one per line, and writes one
+response per line. Loaded documents stay in memory between requests, and only
+files that changed on disk, and the documents that import them, get loaded and
+typechecked again.
+
+Methods:
+  check      Evaluate the document and report whether it is valid.
+  evaluate   Evaluate the document, and return the formatted result.
+  format     Return the document formatted in standard style.
+  query      Evaluate an expression against the document.
+  shutdown   Stop the server.
+
+Options:
+  --sandbox <mode>  Sandboxing mode, see 'rcl evaluate --help' for an
+                    explanation of the modes. Defaults to 'workdir'.
+  --socket <path>   Listen on a Unix domain socket at <path>, instead of
+                    serving a single client on stdin and stdout.
+
+See also --help for global options.
+"#;
+
+const USAGE_CONVERT: &str = r#"
+RCL -- A reasonable configuration language.
+
+Usage:
+  rcl [<options>] convert [<options>] [<file>]
+
+The 'convert' command parses a document in another data format, and prints it
+as formatted RCL. This is useful to migrate existing configuration to RCL.
+
+Arguments:
+  <file>                 The input file to process, or '-' for stdin. Defaults
+                         to stdin when no file is specified.
+
+Options:
+  --from <format>        Input format, one of 'json', 'jsonnet', 'toml', or
+                         'yaml'. When not specified, it is inferred from the
+                         file extension. Jsonnet support is an optional feature
+                         that translates the program, rather than its output.
+  -o --output <outfile>  Write to the given file instead of stdout.
+  --to <format>          Output format, see 'rcl evaluate --help' for the
+                         available formats. Defaults to 'rcl'.
+  -w --width <width>     Target width for pretty-printing, must be an integer.
+                         Defaults to 80.
+
+See also --help for global options.
+"#;
+
+const USAGE_DOC: &str = r#"
+RCL -- A reasonable configuration language.
+
+Usage:
+  rcl [<options>] doc [<options>] [<file>]
+
+The 'doc' command renders reference documentation for a library document. The
+document should evaluate to a dict, where the keys are the names of exported
+bindings. For every binding, the output includes its type, and the doc comment
+('///') of the field that defines it.
+
+Arguments:
+  <file>                 The input file to process, or '-' for stdin. Defaults
+                         to stdin when no file is specified.
+
+Options:
+  -f --format <format>   Output format, 'markdown' or 'html'. Defaults to
+                         'markdown'. This option must follow the command.
+  -o --output <outfile>  Write to the given file instead of stdout.
+  --sandbox <mode>       Sandboxing mode, see 'rcl evaluate --help' for an
+                         explanation of the modes. Defaults to 'workdir'.
+
+See also --help for global options.
+"#;
+
 const USAGE_EVAL_QUERY: &str = r#"
 RCL -- A reasonable configuration language.
 
 Usage:
   rcl [<options>] evaluate [<options>] [<file>]
+  rcl [<options>] evaluate [<options>] --expr <expr>
   rcl [<options>] query    [<options>] [<file>] <query>
+  rcl [<options>] query    [<options>] --expr <expr> <query>
+  rcl [<options>] query    [<options>] --repl <file>
 
 The 'evaluate' command evaluates the expression in the input file and prints it
 to stdout. The 'query' command additionally evaluates an expression against the
-result.
+result. With '--repl', it evaluates the input once, and then reads queries from
+stdin interactively.
 
 Arguments:
   <file>     The input file to process, or '-' for stdin. Defaults to stdin when
@@ -124,24 +475,73 @@ Arguments:
              file is bound to the variable 'input'.
 
 Options:
+  --allow-network          Enable functions that access the network, such as
+                           'std.fetch_url'. By default, evaluation is hermetic.
   --banner <message>       Prepend the message to the output. This can be useful
                            to add headings or comments to generated files.
+  --expr <expr>            Evaluate <expr> instead of reading an input file.
+                           Errors refer to the expression as '<expr>'.
   -f --format <format>     Output format, see below for the available formats.
                            Defaults to 'rcl'.
+  --locked                 Before evaluating, check that the documents that the
+                           input imports match the hashes in the lockfile that
+                           'rcl lock' wrote, and fail if they do not.
+  --lockfile <file>        The lockfile to check with '--locked'. Defaults to
+                           the input file with '.lock' appended.
+  --max-depth <depth>      Elide collections nested deeper than <depth> levels.
+                           Only supported for the rcl and json formats.
+  --max-items <count>      Elide elements of collections beyond the first
+                           <count>. Only supported for the rcl and json formats.
   -o --output <outfile>    Write to the given file instead of stdout.
+  --output-encoding <enc>  Character encoding of the output, 'utf8' or 'ascii'.
+                           With 'ascii', strings escape all non-ASCII
+                           characters. Only supported for the json and
+                           YAML-based formats. Defaults to 'utf8'.
   --output-depfile <file>  Write all dependencies that were loaded during
                            evaluation to <file> in Makefile syntax. This can be
                            used by e.g. the Ninja build system.
+  --output-docs <file>     Write the doc comments ('///') of the input document
+                           to <file> as JSON.
+  --profile <file>         Measure where evaluation spends its time, and write
+                           the profile to <file>.
+  --profile-format <format>
+                           Format of the profile, 'hotspots' or 'folded'.
+                           Defaults to 'hotspots'.
+  --repl                   For 'query', read queries from stdin interactively,
+                           and evaluate them against the cached input. Enter
+                           ':help' at the prompt for the available commands.
+  --resolve-secrets <src>  Where to read the contents of 'std.secret' values
+                           from, see below. Defaults to 'placeholder'.
   --sandbox <mode>         Sandboxing mode, see below. Defaults to 'workdir'.
+  --secret-placeholder <template>
+                           What unresolved secrets export as, where '{name}'
+                           expands to the secret name. Defaults to
+                           '${SECRET:{name}}'.
+  --source-map <file>      Write a source map to <file> as JSON. It maps every
+                           location in the output, as a JSON pointer, to the
+                           span of the RCL source that produced the value.
+  --strict <mode>          How to treat implicit conversions, see below.
+                           Defaults to 'off'.
   -w --width <width>       Target width for pretty-printing, must be an integer.
                            Defaults to 80.
 
 Output format:
+  csv           If the document is a list of dicts, output a header with the
+                keys, and one line of comma-separated values per dict.
+  github-actions
+                Output YAML for GitHub Actions workflows, with keys in the
+                conventional order, and quotes where YAML would be ambiguous.
   json          Output pretty-printed JSON.
+  prometheus-rules
+                Output YAML for Prometheus rule files, with keys of groups and
+                rules in the conventional order, and all strings quoted.
   raw           If the document is a string, output the string itself. If the
                 document is a list or set of strings, output each string on its
                 own line.
   rcl           Output pretty-printed RCL.
+  terraform-json
+                Output JSON for Terraform '.tf.json' files, after checking that
+                the top-level keys are block types, and blocks are nested dicts.
   toml          Output TOML.
   yaml-stream   If the document is a list, output every element as a JSON
                 document, prefixed by the '---' YAML document separator.
@@ -152,6 +552,17 @@ Sandboxing modes:
                 subdirectories.
   unrestricted  Grant unrestricted filesystem access, allow importing any file.
 
+Secret sources:
+  placeholder   Export secrets as the placeholder, do not read their contents.
+  env           Read secret <name> from the environment variable <name>.
+  file:<dir>    Read secret <name> from the file <dir>/<name>.
+
+Strict modes:
+  off           Allow implicit conversions, such as formatting null or a bool
+                into a string, or comparing values of different types.
+  warn          Allow implicit conversions, but print a warning to stderr.
+  error         Report implicit conversions as errors.
+
 See also --help for global options.
 "#;
 
@@ -171,6 +582,10 @@ Arguments:
 Options:
   -i --in-place          Rewrite files in-place instead of writing to stdout.
                          By default the formatted result is written to stdout.
+  -j --jobs <n>          Number of files to format in parallel with --check or
+                         --in-place. Defaults to the number of CPUs.
+  --normalize-strings    Rewrite string literals to use as few escape sequences
+                         as possible.
   -o --output <outfile>  Write to the given file instead of stdout. This is
                          incompatible with --in-place.
   -w --width <width>     Target width in number of columns, must be an integer.
@@ -191,25 +606,69 @@ pub struct GlobalOptions {
 
     /// Alter the working directory for filesystem access.
     pub workdir: Option<String>,
+
+    /// The name to use for a document read from stdin.
+    ///
+    /// This name shows up in diagnostics, and relative imports in the document
+    /// are resolved relative to it, as if the document was that file.
+    pub stdin_name: Option<String>,
+
+    /// Whether to print values in error messages without eliding anything.
+    pub full_errors: bool,
+
+    /// The number of columns between tab stops in diagnostics, if overridden.
+    pub tab_width: Option<u32>,
+
+    /// Whether a `let` may shadow an outer binding without a warning.
+    pub allow_shadowing: bool,
+
+    /// Whether to skip constant folding before evaluation.
+    pub no_opt: bool,
 }
 
 /// The available output formats (JSON, RCL).
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub enum OutputFormat {
+    Csv,
+    GithubActions,
     Json,
+    PrometheusRules,
     Raw,
     #[default]
     Rcl,
+    TerraformJson,
     Toml,
     YamlStream,
 }
 
+impl OutputFormat {
+    /// Look up a format by the name that `--format` accepts, e.g. `yaml-stream`.
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        use crate::output::{FormatBackend, BUILTIN_FORMATS};
+        BUILTIN_FORMATS.iter().find(|f| f.name() == name).copied()
+    }
+}
+
+/// The character encoding of the output.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum OutputEncoding {
+    /// Output UTF-8, non-ASCII characters in strings are written as-is.
+    #[default]
+    Utf8,
+
+    /// Output only ASCII, non-ASCII characters in strings are escaped.
+    Ascii,
+}
+
 /// Options for commands that evaluate expressions.
 #[derive(Debug, Default, Eq, PartialEq)]
 pub struct EvalOptions {
     /// The format to output in.
     pub format: OutputFormat,
 
+    /// The character encoding to output in.
+    pub output_encoding: OutputEncoding,
+
     /// Policy for what files can be imported.
     pub sandbox: SandboxMode,
 
@@ -219,8 +678,45 @@ pub struct EvalOptions {
     /// <https://ninja-build.org/manual.html#_depfile>.
     pub output_depfile: Option<String>,
 
+    /// File to write the doc comments of the input document to, as json.
+    pub output_docs: Option<String>,
+
     /// A banner message to prepend to the output.
     pub banner: Option<String>,
+
+    /// How to treat implicit conversions during evaluation.
+    pub strict: StrictMode,
+
+    /// Whether to enable builtins that access the network.
+    pub allow_network: bool,
+
+    /// How to export secrets.
+    pub secrets: SecretOptions,
+
+    /// Whether to check the imports against a lockfile before evaluating.
+    pub locked: bool,
+
+    /// The lockfile to check, if not the default one for the input.
+    pub lockfile: Option<String>,
+
+    /// File to write the source map of the output to, as json.
+    pub source_map: Option<String>,
+
+    /// File to write the evaluation profile to.
+    pub profile: Option<String>,
+
+    /// The format of the profile.
+    pub profile_format: ProfileFormat,
+}
+
+impl EvalOptions {
+    /// Return the capabilities that builtins are allowed to use.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            network: self.allow_network,
+            ..Capabilities::default()
+        }
+    }
 }
 
 /// Options for commands that pretty-print their output.
@@ -228,11 +724,17 @@ pub struct EvalOptions {
 pub struct StyleOptions {
     /// Target width (number of columns) to try to not exceed.
     pub width: u32,
+
+    /// How much of large values to print, for interactive inspection.
+    pub limits: Limits,
 }
 
 impl Default for StyleOptions {
     fn default() -> Self {
-        Self { width: 80 }
+        Self {
+            width: 80,
+            limits: Limits::default(),
+        }
     }
 }
 
@@ -252,6 +754,9 @@ pub enum Target {
     /// unintentional, and it looks like the application hangs when the
     /// user doesn't realize it's waiting for input.
     StdinDefault,
+
+    /// An expression passed on the command line with `--expr`.
+    Expr(String),
 }
 
 /// For the `fmt` command, which documents to format, and in what mode.
@@ -271,13 +776,40 @@ pub enum OutputTarget {
     Stdout,
 }
 
+/// Parse the number of parallel jobs, which must be at least 1.
+fn parse_jobs(jobs: &str) -> std::result::Result<Option<u32>, ()> {
+    match u32::from_str(jobs) {
+        Ok(n) if n > 0 => Ok(Some(n)),
+        _ => Err(()),
+    }
+}
+
+/// Parse the distance between tab stops, which must be between 1 and 16.
+fn parse_tab_width(width: &str) -> std::result::Result<Option<u32>, ()> {
+    match u32::from_str(width) {
+        Ok(n) if (1..=16).contains(&n) => Ok(Some(n)),
+        _ => Err(()),
+    }
+}
+
+/// Parse Unix file permissions in octal notation, e.g. `0644`.
+fn parse_file_mode(mode: &str) -> std::result::Result<u32, ()> {
+    match u32::from_str_radix(mode, 8) {
+        Ok(m) if m <= 0o7777 => Ok(m),
+        _ => Err(()),
+    }
+}
+
 /// The different subcommands supported by the main program.
 #[derive(Debug, Eq, PartialEq)]
 pub enum Cmd {
     Build {
         eval_opts: EvalOptions,
         build_mode: BuildMode,
-        fname: Target,
+        /// Unix permissions for output files, e.g. `0o644`.
+        file_mode: Option<u32>,
+        jobs: Option<u32>,
+        fnames: Vec<Target>,
     },
     Evaluate {
         eval_opts: EvalOptions,
@@ -292,14 +824,83 @@ pub enum Cmd {
         query: String,
         output: OutputTarget,
     },
+    Repl {
+        eval_opts: EvalOptions,
+        style_opts: StyleOptions,
+        fname: Target,
+    },
     Format {
         style_opts: StyleOptions,
+        format_opts: FormatOptions,
+        /// Number of files to process in parallel, defaults to the CPU count.
+        jobs: Option<u32>,
         target: FormatTarget,
         output: OutputTarget,
     },
+    Convert {
+        eval_opts: EvalOptions,
+        style_opts: StyleOptions,
+        from: Option<InputFormat>,
+        fname: Target,
+        output: OutputTarget,
+    },
+    Bundle {
+        sandbox: SandboxMode,
+        style_opts: StyleOptions,
+        fname: Target,
+        output: OutputTarget,
+    },
+    Lock {
+        sandbox: SandboxMode,
+        build_mode: BuildMode,
+        fname: Target,
+        lockfile: Option<String>,
+    },
+    TraceOutput {
+        eval_opts: EvalOptions,
+        fname: Target,
+        path: String,
+    },
+    Debug {
+        eval_opts: EvalOptions,
+        style_opts: StyleOptions,
+        fname: Target,
+        breakpoints: Vec<Breakpoint>,
+        break_on_trace: bool,
+    },
+    Lint {
+        sandbox: SandboxMode,
+        /// Documents that are used as a whole, where the analysis starts.
+        entries: Vec<Target>,
+        /// Files to report when the entry points do not import them.
+        fnames: Vec<Target>,
+    },
+    Check {
+        sandbox: SandboxMode,
+        strict: StrictMode,
+        /// Number of documents to evaluate in parallel, defaults to the CPU count.
+        jobs: Option<u32>,
+        fnames: Vec<Target>,
+    },
+    Doc {
+        sandbox: SandboxMode,
+        format: DocFormat,
+        fname: Target,
+        output: OutputTarget,
+    },
     Highlight {
         fname: Target,
     },
+    CrashRepro {
+        fname: Target,
+        width: u32,
+    },
+    Serve {
+        sandbox: SandboxMode,
+        /// Path of a Unix domain socket to listen on, instead of stdio.
+        socket: Option<String>,
+    },
+    Formats,
     Help {
         usage: &'static str,
     },
@@ -320,10 +921,22 @@ pub fn parse(args: Vec<String>) -> Result<(GlobalOptions, Cmd)> {
     let mut eval_opts = EvalOptions::default();
     let mut in_place = false;
     let mut check = false;
+    let mut format_opts = FormatOptions::default();
     let mut is_version = false;
     let mut targets: Vec<Target> = Vec::new();
+    let mut expr: Option<String> = None;
     let mut output = OutputTarget::Stdout;
     let mut build_mode = BuildMode::WriteFilesystem;
+    let mut file_mode = None;
+    let mut jobs = None;
+    let mut socket = None;
+    let mut doc_format = DocFormat::default();
+    let mut input_format = None;
+    let mut breakpoints = Vec::new();
+    let mut break_on_trace = false;
+    let mut entries = Vec::new();
+    let mut unused_exports = false;
+    let mut repl = false;
 
     while let Some(arg) = args.next() {
         match arg.as_ref() {
@@ -333,6 +946,12 @@ pub fn parse(args: Vec<String>) -> Result<(GlobalOptions, Cmd)> {
                     |x: &str| Ok::<_, std::convert::Infallible>(Some(x.to_string()))
                 };
             }
+            Arg::Long("break") => {
+                breakpoints.push(parse_option! { args: arg, Breakpoint::from_str });
+            }
+            Arg::Long("break-on-trace") => {
+                break_on_trace = true;
+            }
             Arg::Long("check") => {
                 check = true;
             }
@@ -351,40 +970,183 @@ pub fn parse(args: Vec<String>) -> Result<(GlobalOptions, Cmd)> {
                     |x: &str| Ok::<_, std::convert::Infallible>(Some(x.to_string()))
                 };
             }
+            Arg::Long("entry") => match args.next() {
+                // Unlike other options that take a file, `--entry` accepts
+                // `-` for stdin, so the entry point can be piped in.
+                Some(Arg::StdInOut) => entries.push(Target::Stdin),
+                Some(Arg::Plain(fname)) if fname == "-" => entries.push(Target::Stdin),
+                Some(Arg::Plain(fname)) => entries.push(Target::File(fname.to_string())),
+                _ => return Error::new(concat! {
+                    "Expected a value after " Doc::highlight("--entry") ". See --help for usage."
+                })
+                .err(),
+            },
+            Arg::Long("expr") => {
+                expr = parse_option! {
+                    args: arg,
+                    |x: &str| Ok::<_, std::convert::Infallible>(Some(x.to_string()))
+                };
+            }
+            Arg::Long("normalize-strings") => {
+                format_opts.normalize_strings = true;
+            }
+            Arg::Long("full-errors") => {
+                global_opts.full_errors = true;
+            }
+            Arg::Long("allow-shadowing") => {
+                global_opts.allow_shadowing = true;
+            }
+            Arg::Long("no-opt") => {
+                global_opts.no_opt = true;
+            }
+            Arg::Long("unused-exports") => {
+                unused_exports = true;
+            }
+            Arg::Long("repl") => {
+                repl = true;
+            }
+            Arg::Long("tab-width") => {
+                global_opts.tab_width = parse_option! { args: arg, parse_tab_width };
+            }
+            Arg::Long("stdin-filename") => {
+                global_opts.stdin_name = parse_option! {
+                    args: arg,
+                    |x: &str| Ok::<_, std::convert::Infallible>(Some(x.to_string()))
+                };
+            }
             Arg::Long("dry-run") => {
                 build_mode = BuildMode::DryRun;
             }
-            Arg::Long("format") | Arg::Short("f") => {
-                eval_opts.format = match_option! {
+            // The doc command has its own output formats. Because we don't
+            // know the command in advance, this requires --format to follow
+            // the command for `rcl doc`.
+            Arg::Long("format") | Arg::Short("f") if cmd == Some("doc") => {
+                doc_format = match_option! {
                     args: arg,
-                    "json" => OutputFormat::Json,
-                    "raw" => OutputFormat::Raw,
-                    "rcl" => OutputFormat::Rcl,
-                    "toml" => OutputFormat::Toml,
-                    "yaml-stream" => OutputFormat::YamlStream,
+                    "html" => DocFormat::Html,
+                    "markdown" => DocFormat::Markdown,
+                }
+            }
+            Arg::Long("from") => {
+                input_format = match_option! {
+                    args: arg,
+                    "json" => Some(InputFormat::Json),
+                    "jsonnet" => Some(InputFormat::Jsonnet),
+                    "toml" => Some(InputFormat::Toml),
+                    "yaml" => Some(InputFormat::Yaml),
+                }
+            }
+            Arg::Long("format") | Arg::Short("f") | Arg::Long("to") => {
+                eval_opts.format = match args.next() {
+                    Some(Arg::Plain(name)) => match OutputFormat::from_name(&name) {
+                        Some(format) => format,
+                        None => return unknown_format_error(arg),
+                    },
+                    _ => return unknown_format_error(arg),
                 }
             }
+            Arg::Long("allow-network") => {
+                eval_opts.allow_network = true;
+            }
+            Arg::Long("locked") => {
+                eval_opts.locked = true;
+            }
+            Arg::Long("lockfile") => {
+                eval_opts.lockfile = parse_option! {
+                    args: arg,
+                    |x: &str| Ok::<_, std::convert::Infallible>(Some(x.to_string()))
+                };
+            }
+            Arg::Long("jobs") | Arg::Short("j") => {
+                jobs = parse_option! { args: arg, parse_jobs };
+            }
+            Arg::Long("mode") if cmd == Some("build") => {
+                file_mode = Some(parse_option! { args: arg, parse_file_mode });
+            }
+            Arg::Long("max-depth") => {
+                style_opts.limits.max_depth =
+                    parse_option! { args: arg, |x: &str| u32::from_str(x).map(Some) };
+            }
+            Arg::Long("max-items") => {
+                style_opts.limits.max_items =
+                    parse_option! { args: arg, |x: &str| u32::from_str(x).map(Some) };
+            }
             Arg::Long("output") | Arg::Short("o") => {
                 output = parse_option! {
                     args: arg,
                     |x: &str| Ok::<_, std::convert::Infallible>(OutputTarget::File(x.to_string()))
                 };
             }
+            Arg::Long("output-encoding") => {
+                eval_opts.output_encoding = match_option! {
+                    args: arg,
+                    "ascii" => OutputEncoding::Ascii,
+                    "utf8" => OutputEncoding::Utf8,
+                }
+            }
             Arg::Long("output-depfile") => {
                 eval_opts.output_depfile = parse_option! {
                     args: arg,
                     |x: &str| Ok::<_, std::convert::Infallible>(Some(x.to_string()))
                 };
             }
-            Arg::Long("sandbox") => {
-                eval_opts.sandbox = match_option! {
+            Arg::Long("output-docs") => {
+                eval_opts.output_docs = parse_option! {
                     args: arg,
-                    "workdir" => SandboxMode::Workdir,
-                    "unrestricted" => SandboxMode::Unrestricted,
-                }
+                    |x: &str| Ok::<_, std::convert::Infallible>(Some(x.to_string()))
+                };
             }
-            Arg::Long("width") | Arg::Short("w") => {
-                style_opts.width = parse_option! { args: arg, u32::from_str };
+            Arg::Long("profile") => {
+                eval_opts.profile = parse_option! {
+                    args: arg,
+                    |x: &str| Ok::<_, std::convert::Infallible>(Some(x.to_string()))
+                };
+            }
+            Arg::Long("profile-format") => {
+                eval_opts.profile_format = match_option! {
+                    args: arg,
+                    "folded" => ProfileFormat::Folded,
+                    "hotspots" => ProfileFormat::Hotspots,
+                }
+            }
+            Arg::Long("resolve-secrets") => {
+                eval_opts.secrets.source = parse_option! { args: arg, SecretSource::from_str };
+            }
+            Arg::Long("secret-placeholder") => {
+                eval_opts.secrets.placeholder = parse_option! {
+                    args: arg,
+                    |x: &str| Ok::<_, std::convert::Infallible>(x.to_string())
+                };
+            }
+            Arg::Long("sandbox") => {
+                eval_opts.sandbox = match_option! {
+                    args: arg,
+                    "workdir" => SandboxMode::Workdir,
+                    "unrestricted" => SandboxMode::Unrestricted,
+                }
+            }
+            Arg::Long("socket") => {
+                socket = parse_option! {
+                    args: arg,
+                    |x: &str| Ok::<_, std::convert::Infallible>(Some(x.to_string()))
+                };
+            }
+            Arg::Long("source-map") => {
+                eval_opts.source_map = parse_option! {
+                    args: arg,
+                    |x: &str| Ok::<_, std::convert::Infallible>(Some(x.to_string()))
+                };
+            }
+            Arg::Long("strict") => {
+                eval_opts.strict = match_option! {
+                    args: arg,
+                    "error" => StrictMode::Error,
+                    "off" => StrictMode::Off,
+                    "warn" => StrictMode::Warn,
+                }
+            }
+            Arg::Long("width") | Arg::Short("w") => {
+                style_opts.width = parse_option! { args: arg, u32::from_str };
             }
             Arg::Long("in-place") | Arg::Short("i") => {
                 in_place = true;
@@ -403,6 +1165,24 @@ pub fn parse(args: Vec<String>) -> Result<(GlobalOptions, Cmd)> {
             Arg::Plain("build") if cmd.is_none() => {
                 cmd = Some("build");
             }
+            Arg::Plain("bundle") if cmd.is_none() => {
+                cmd = Some("bundle");
+            }
+            Arg::Plain("check") if cmd.is_none() => {
+                cmd = Some("check");
+            }
+            Arg::Plain("convert") if cmd.is_none() => {
+                cmd = Some("convert");
+            }
+            Arg::Plain("crash-repro") if cmd.is_none() => {
+                cmd = Some("crash-repro");
+            }
+            Arg::Plain("debug") if cmd.is_none() => {
+                cmd = Some("debug");
+            }
+            Arg::Plain("doc") if cmd.is_none() => {
+                cmd = Some("doc");
+            }
             Arg::Plain("evaluate") | Arg::Plain("eval") | Arg::Plain("e") if cmd.is_none() => {
                 cmd = Some("evaluate");
             }
@@ -410,6 +1190,15 @@ pub fn parse(args: Vec<String>) -> Result<(GlobalOptions, Cmd)> {
                 cmd = Some("evaluate");
                 eval_opts.format = OutputFormat::Json;
             }
+            Arg::Plain("formats") if cmd.is_none() => {
+                cmd = Some("formats");
+            }
+            Arg::Plain("lint") if cmd.is_none() => {
+                cmd = Some("lint");
+            }
+            Arg::Plain("lock") if cmd.is_none() => {
+                cmd = Some("lock");
+            }
             Arg::Plain("query") | Arg::Plain("q") if cmd.is_none() => {
                 cmd = Some("query");
             }
@@ -417,6 +1206,12 @@ pub fn parse(args: Vec<String>) -> Result<(GlobalOptions, Cmd)> {
                 cmd = Some("query");
                 eval_opts.format = OutputFormat::Json;
             }
+            Arg::Plain("serve") if cmd.is_none() => {
+                cmd = Some("serve");
+            }
+            Arg::Plain("trace-output") if cmd.is_none() => {
+                cmd = Some("trace-output");
+            }
             Arg::Plain("format") | Arg::Plain("fmt") | Arg::Plain("f") if cmd.is_none() => {
                 cmd = Some("format");
             }
@@ -454,24 +1249,72 @@ pub fn parse(args: Vec<String>) -> Result<(GlobalOptions, Cmd)> {
 
     let help_opt = match cmd_help {
         Some("build") => Some(Cmd::Help { usage: USAGE_BUILD }),
+        Some("bundle") => Some(Cmd::Help {
+            usage: USAGE_BUNDLE,
+        }),
+        Some("check") => Some(Cmd::Help { usage: USAGE_CHECK }),
+        Some("convert") => Some(Cmd::Help {
+            usage: USAGE_CONVERT,
+        }),
+        Some("crash-repro") => Some(Cmd::Help {
+            usage: USAGE_CRASH_REPRO,
+        }),
+        Some("debug") => Some(Cmd::Help { usage: USAGE_DEBUG }),
+        Some("doc") => Some(Cmd::Help { usage: USAGE_DOC }),
         Some("evaluate") => Some(Cmd::Help {
             usage: USAGE_EVAL_QUERY,
         }),
         Some("format") => Some(Cmd::Help {
             usage: USAGE_FORMAT,
         }),
+        Some("formats") => Some(Cmd::Help {
+            usage: USAGE_FORMATS,
+        }),
+        Some("lint") => Some(Cmd::Help { usage: USAGE_LINT }),
+        Some("lock") => Some(Cmd::Help { usage: USAGE_LOCK }),
         // TODO: Add usage for highlight.
         Some("highlight") => Some(Cmd::Help { usage: USAGE_MAIN }),
         Some("main") => Some(Cmd::Help { usage: USAGE_MAIN }),
         Some("query") => Some(Cmd::Help {
             usage: USAGE_EVAL_QUERY,
         }),
+        Some("serve") => Some(Cmd::Help { usage: USAGE_SERVE }),
+        Some("trace-output") => Some(Cmd::Help {
+            usage: USAGE_TRACE_OUTPUT,
+        }),
         _ => None,
     };
     if let Some(help) = help_opt {
         return Ok((global_opts, help));
     }
 
+    // With `--expr`, the expression takes the place of the input file. Other
+    // commands operate on files, so there it would be ambiguous.
+    if let Some(expr) = expr {
+        // For `rcl query`, the first positional argument is the query.
+        let n_files = match cmd {
+            Some("query") => targets.len().saturating_sub(1),
+            _ => targets.len(),
+        };
+        match cmd {
+            Some("evaluate" | "query") if n_files > 0 => {
+                return Error::new(concat! {
+                    "Option '" Doc::highlight("--expr") "' takes the place of the input file, "
+                    "it cannot be combined with one."
+                })
+                .err()
+            }
+            Some("evaluate" | "query") => targets.insert(0, Target::Expr(expr)),
+            _ => {
+                return Error::new(concat! {
+                    "Option '" Doc::highlight("--expr") "' is only supported by "
+                    "'" Doc::highlight("rcl evaluate") "' and '" Doc::highlight("rcl query") "'."
+                })
+                .err()
+            }
+        }
+    }
+
     let result = match cmd {
         Some("build") => {
             // Unlike other commands, for `rcl build` the input file defaults to
@@ -482,47 +1325,112 @@ pub fn parse(args: Vec<String>) -> Result<(GlobalOptions, Cmd)> {
             Cmd::Build {
                 eval_opts,
                 build_mode,
-                fname: get_unique_target(targets)?,
+                file_mode,
+                jobs,
+                fnames: targets,
+            }
+        }
+        Some("bundle") => Cmd::Bundle {
+            sandbox: eval_opts.sandbox,
+            style_opts,
+            fname: get_unique_target(targets)?,
+            output,
+        },
+        Some("check") => {
+            if targets.is_empty() {
+                targets.push(Target::StdinDefault);
+            }
+            Cmd::Check {
+                sandbox: eval_opts.sandbox,
+                strict: eval_opts.strict,
+                jobs,
+                fnames: targets,
+            }
+        }
+        Some("convert") => Cmd::Convert {
+            eval_opts,
+            style_opts,
+            from: input_format,
+            fname: get_unique_target(targets)?,
+            output,
+        },
+        Some("crash-repro") => Cmd::CrashRepro {
+            fname: get_unique_target(targets)?,
+            width: style_opts.width,
+        },
+        Some("debug") => Cmd::Debug {
+            eval_opts,
+            style_opts,
+            fname: get_unique_target(targets)?,
+            breakpoints,
+            break_on_trace,
+        },
+        Some("doc") => Cmd::Doc {
+            sandbox: eval_opts.sandbox,
+            format: doc_format,
+            fname: get_unique_target(targets)?,
+            output,
+        },
+        Some("lint") => {
+            if !unused_exports {
+                return Error::new(concat! {
+                    "Specify the lint to run, such as '" Doc::highlight("--unused-exports") "'."
+                })
+                .err();
+            }
+            if entries.is_empty() {
+                return Error::new(concat! {
+                    "Specify at least one entry point with '" Doc::highlight("--entry <file>") "'."
+                })
+                .err();
+            }
+            Cmd::Lint {
+                sandbox: eval_opts.sandbox,
+                entries,
+                fnames: targets,
             }
         }
+        Some("lock") => Cmd::Lock {
+            sandbox: eval_opts.sandbox,
+            build_mode,
+            fname: get_unique_target(targets)?,
+            lockfile: eval_opts.lockfile,
+        },
         Some("evaluate") => Cmd::Evaluate {
             eval_opts,
             style_opts,
             fname: get_unique_target(targets)?,
             output,
         },
+        Some("query") if repl => {
+            if output != OutputTarget::Stdout {
+                return Error::new(concat! {
+                    "Option '" Doc::highlight("--output") "' is not supported with '"
+                    Doc::highlight("--repl") "', results are printed at the prompt."
+                })
+                .err();
+            }
+            if targets.len() > 1 {
+                return Error::new(
+                    "Expected an input file and no query with --repl. See --help for usage.",
+                )
+                .err();
+            }
+            let fname = targets.pop().unwrap_or(Target::StdinDefault);
+            if matches!(fname, Target::Stdin | Target::StdinDefault) {
+                return Error::new(
+                    "With --repl, queries are read from stdin, it can't read the input from there.",
+                )
+                .err();
+            }
+            Cmd::Repl {
+                eval_opts,
+                style_opts,
+                fname,
+            }
+        }
         Some("query") => {
-            let (fname, query) = match targets.len() {
-                2 => (
-                    targets.remove(0),
-                    // Not a file, but a consequence of the CLI parsing that
-                    // handles - being stdin/stdout.
-                    match targets.remove(0) {
-                        Target::File(query) => query,
-                        Target::Stdin => "-".to_string(),
-                        Target::StdinDefault => {
-                            unreachable!("Only produced through absence of args.")
-                        }
-                    },
-                ),
-                1 => (
-                    Target::StdinDefault,
-                    match targets.remove(0) {
-                        Target::File(query) => query,
-                        Target::Stdin => "-".to_string(),
-                        Target::StdinDefault => {
-                            unreachable!("Only produced through absence of args.")
-                        }
-                    },
-                ),
-                _ => {
-                    return Error::new(
-                        "Expected an input file and a query. \
-                        See --help for usage.",
-                    )
-                    .err()
-                }
-            };
+            let (fname, query) = get_target_and_arg(targets, "a query")?;
             Cmd::Query {
                 eval_opts,
                 style_opts,
@@ -531,8 +1439,18 @@ pub fn parse(args: Vec<String>) -> Result<(GlobalOptions, Cmd)> {
                 output,
             }
         }
+        Some("trace-output") => {
+            let (fname, path) = get_target_and_arg(targets, "an output path")?;
+            Cmd::TraceOutput {
+                eval_opts,
+                fname,
+                path,
+            }
+        }
         Some("format") => Cmd::Format {
             style_opts,
+            format_opts,
+            jobs,
             target: if in_place {
                 FormatTarget::InPlace { fnames: targets }
             } else if check {
@@ -544,15 +1462,54 @@ pub fn parse(args: Vec<String>) -> Result<(GlobalOptions, Cmd)> {
             },
             output,
         },
+        Some("formats") => {
+            if !targets.is_empty() {
+                return Error::new(
+                    "The formats command does not take input files. See --help for usage.",
+                )
+                .err();
+            }
+            Cmd::Formats
+        }
         Some("highlight") => Cmd::Highlight {
             fname: get_unique_target(targets)?,
         },
+        Some("serve") => {
+            if !targets.is_empty() {
+                return Error::new(
+                    "The serve command does not take input files. See --help for usage.",
+                )
+                .err();
+            }
+            Cmd::Serve {
+                sandbox: eval_opts.sandbox,
+                socket,
+            }
+        }
         None => Cmd::Help { usage: USAGE_MAIN },
         _ => panic!("Should have returned an error before getting here."),
     };
     Ok((global_opts, result))
 }
 
+/// Report that `--format` is not followed by the name of an output format.
+fn unknown_format_error<T>(option: Arg<String>) -> Result<T> {
+    use crate::output::{FormatBackend, BUILTIN_FORMATS};
+    let mut err = vec![
+        "Expected ".into(),
+        Doc::from(option.to_string()).with_markup(Markup::Highlight),
+        " to be followed by one of ".into(),
+    ];
+    for (i, format) in BUILTIN_FORMATS.iter().enumerate() {
+        if i > 0 {
+            err.push(", ".into());
+        }
+        err.push(Doc::highlight(format.name()));
+    }
+    err.push(". See --help for usage.".into());
+    Error::new(Doc::Concat(err)).err()
+}
+
 fn get_unique_target(mut targets: Vec<Target>) -> Result<Target> {
     match targets.pop() {
         None => Ok(Target::StdinDefault),
@@ -563,15 +1520,48 @@ fn get_unique_target(mut targets: Vec<Target>) -> Result<Target> {
     }
 }
 
+/// Split the targets into the input file and one more argument.
+///
+/// The input file is optional and defaults to stdin. The argument is what
+/// follows the input file, such as the query for `rcl query`.
+fn get_target_and_arg(mut targets: Vec<Target>, arg_name: &str) -> Result<(Target, String)> {
+    let fname = match targets.len() {
+        2 => targets.remove(0),
+        1 => Target::StdinDefault,
+        _ => {
+            return Error::new(format!(
+                "Expected an input file and {arg_name}. See --help for usage."
+            ))
+            .err()
+        }
+    };
+    // Not a file, but a consequence of the CLI parsing that handles - being
+    // stdin/stdout.
+    let arg = match targets.remove(0) {
+        Target::File(arg) => arg,
+        Target::Stdin => "-".to_string(),
+        Target::StdinDefault => unreachable!("Only produced through absence of args."),
+        Target::Expr(..) => unreachable!("The expression is always the first target."),
+    };
+    Ok((fname, arg))
+}
+
 #[cfg(test)]
 mod test {
     use crate::cli::{
-        Cmd, EvalOptions, FormatTarget, GlobalOptions, OutputFormat, OutputTarget, SandboxMode,
-        StyleOptions, Target,
+        Cmd, EvalOptions, FormatTarget, GlobalOptions, OutputEncoding, OutputFormat, OutputTarget,
+        SandboxMode, StyleOptions, Target,
     };
     use crate::cmd_build::BuildMode;
+    use crate::cmd_convert::InputFormat;
+    use crate::cmd_doc::DocFormat;
+    use crate::debugger::Breakpoint;
+    use crate::eval::StrictMode;
+    use crate::fmt_cst::FormatOptions;
     use crate::markup::MarkupMode;
     use crate::pprint::Config;
+    use crate::profile::ProfileFormat;
+    use crate::secret::{SecretOptions, SecretSource};
 
     fn fail_parse(args: &[&'static str]) -> String {
         let args_vec: Vec<_> = args.iter().map(|a| a.to_string()).collect();
@@ -590,6 +1580,11 @@ mod test {
         let expected_opt = GlobalOptions {
             markup: None,
             workdir: None,
+            stdin_name: None,
+            full_errors: false,
+            tab_width: None,
+            allow_shadowing: false,
+            no_opt: false,
         };
         let expected_cmd = Cmd::Evaluate {
             eval_opts: EvalOptions::default(),
@@ -604,6 +1599,39 @@ mod test {
         assert_eq!(parse(&["rcl", "eval", "infile"]), expected);
         assert_eq!(parse(&["rcl", "e", "infile"]), expected);
 
+        // Test --full-errors, a global option.
+        expected.0.full_errors = true;
+        assert_eq!(parse(&["rcl", "--full-errors", "e", "infile"]), expected);
+        assert_eq!(parse(&["rcl", "e", "infile", "--full-errors"]), expected);
+        expected.0.full_errors = false;
+
+        // Test --allow-shadowing, also a global option.
+        expected.0.allow_shadowing = true;
+        assert_eq!(
+            parse(&["rcl", "--allow-shadowing", "e", "infile"]),
+            expected
+        );
+        expected.0.allow_shadowing = false;
+
+        // Test --no-opt, also a global option.
+        expected.0.no_opt = true;
+        assert_eq!(parse(&["rcl", "e", "--no-opt", "infile"]), expected);
+        expected.0.no_opt = false;
+
+        // Test --tab-width, also a global option.
+        expected.0.tab_width = Some(4);
+        assert_eq!(parse(&["rcl", "--tab-width=4", "e", "infile"]), expected);
+        assert_eq!(parse(&["rcl", "e", "infile", "--tab-width", "4"]), expected);
+        expected.0.tab_width = None;
+        assert_eq!(
+            fail_parse(&["rcl", "e", "infile", "--tab-width=0"]),
+            "Error: '0' is not valid for --tab-width. See --help for usage.\n"
+        );
+        assert_eq!(
+            fail_parse(&["rcl", "e", "infile", "--tab-width=17"]),
+            "Error: '17' is not valid for --tab-width. See --help for usage.\n"
+        );
+
         // Test that --color works.
         assert_eq!(parse(&["rcl", "--color=auto", "e", "infile"]), expected);
         expected.0.markup = Some(MarkupMode::None);
@@ -647,6 +1675,27 @@ mod test {
             expected
         );
 
+        // Test that --max-depth and --max-items work.
+        if let Cmd::Evaluate { style_opts, .. } = &mut expected.1 {
+            style_opts.limits.max_depth = Some(2);
+            style_opts.limits.max_items = Some(0);
+        }
+        assert_eq!(
+            parse(&[
+                "rcl",
+                "e",
+                "-w42",
+                "--max-depth=2",
+                "--max-items",
+                "0",
+                "infile"
+            ]),
+            expected
+        );
+        if let Cmd::Evaluate { style_opts, .. } = &mut expected.1 {
+            style_opts.limits = Default::default();
+        }
+
         // Test that --format works. We don't have to be as thorough, it's using
         // the same parser, if it works for the other options it should work here.
         if let Cmd::Evaluate {
@@ -694,13 +1743,116 @@ mod test {
         }
         assert_eq!(parse(&["rcl", "e", "infile", "--banner=prefix"]), expected);
 
+        // Test --allow-network.
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.banner = None;
+            eval_opts.allow_network = true;
+            assert!(eval_opts.capabilities().network);
+        }
+        assert_eq!(parse(&["rcl", "e", "infile", "--allow-network"]), expected);
+
+        // Test --resolve-secrets and --secret-placeholder.
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.allow_network = false;
+            eval_opts.secrets.source = SecretSource::Dir("/run/secrets".into());
+            eval_opts.secrets.placeholder = "<{name}>".into();
+        }
+        assert_eq!(
+            parse(&[
+                "rcl",
+                "e",
+                "infile",
+                "--resolve-secrets=file:/run/secrets",
+                "--secret-placeholder=<{name}>",
+            ]),
+            expected
+        );
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.secrets = SecretOptions::default();
+        }
+
+        // Test --source-map.
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.source_map = Some("out.map".into());
+        }
+        assert_eq!(
+            parse(&["rcl", "e", "infile", "--source-map", "out.map"]),
+            expected
+        );
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.source_map = None;
+        }
+
+        // Test --locked and --lockfile.
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.locked = true;
+            eval_opts.lockfile = Some("deps.lock".into());
+        }
+        assert_eq!(
+            parse(&["rcl", "e", "infile", "--locked", "--lockfile=deps.lock"]),
+            expected
+        );
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.locked = false;
+            eval_opts.lockfile = None;
+        }
+
+        // Test --profile and --profile-format.
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.profile = Some("eval.folded".into());
+            eval_opts.profile_format = ProfileFormat::Folded;
+        }
+        assert_eq!(
+            parse(&[
+                "rcl",
+                "e",
+                "infile",
+                "--profile=eval.folded",
+                "--profile-format=folded",
+            ]),
+            expected
+        );
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.profile = None;
+            eval_opts.profile_format = ProfileFormat::Hotspots;
+        }
+
+        // Test --output-encoding.
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.output_encoding = OutputEncoding::Ascii;
+        }
+        assert_eq!(
+            parse(&["rcl", "e", "infile", "--output-encoding=ascii"]),
+            expected
+        );
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.output_encoding = OutputEncoding::Utf8;
+        }
+        assert_eq!(
+            parse(&["rcl", "e", "infile", "--output-encoding", "utf8"]),
+            expected
+        );
+
+        // Test --strict.
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.strict = StrictMode::Warn;
+        }
+        assert_eq!(parse(&["rcl", "e", "infile", "--strict=warn"]), expected);
+        if let Cmd::Evaluate { eval_opts, .. } = &mut expected.1 {
+            eval_opts.strict = StrictMode::Error;
+        }
+        assert_eq!(
+            parse(&["rcl", "e", "--strict", "error", "infile"]),
+            expected
+        );
+
         // Test that defaulting to stdin works. If '-' is there we get it
         // explicitly, if it's not, we get it implicitly.
         if let Cmd::Evaluate {
             fname, eval_opts, ..
         } = &mut expected.1
         {
-            eval_opts.banner = None;
+            eval_opts.strict = StrictMode::Off;
             *fname = Target::Stdin;
         }
         assert_eq!(parse(&["rcl", "e", "-"]), expected);
@@ -729,7 +1881,7 @@ mod test {
         );
         assert_eq!(
             fail_parse(&["rcl", "eval", "infile", "--format=yamr"]),
-            "Error: Expected --format to be followed by one of json, raw, rcl, toml, yaml-stream. See --help for usage.\n"
+            "Error: Expected --format to be followed by one of csv, github-actions, json, prometheus-rules, raw, rcl, terraform-json, toml, yaml-stream. See --help for usage.\n"
         );
         assert_eq!(
             fail_parse(&["rcl", "frobnicate", "infile"]),
@@ -741,14 +1893,318 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_cmd_bundle() {
+        let expected_cmd = Cmd::Bundle {
+            sandbox: SandboxMode::Workdir,
+            style_opts: StyleOptions::default(),
+            fname: Target::File("main.rcl".into()),
+            output: OutputTarget::File("bundle.rcl".into()),
+        };
+        assert_eq!(
+            parse(&["rcl", "bundle", "main.rcl", "-o", "bundle.rcl"]).1,
+            expected_cmd
+        );
+    }
+
+    #[test]
+    fn parse_cmd_trace_output() {
+        let expected_cmd = Cmd::TraceOutput {
+            eval_opts: EvalOptions::default(),
+            fname: Target::File("main.rcl".into()),
+            path: "spec.replicas".into(),
+        };
+        assert_eq!(
+            parse(&["rcl", "trace-output", "main.rcl", "spec.replicas"]).1,
+            expected_cmd
+        );
+
+        let expected_cmd = Cmd::TraceOutput {
+            eval_opts: EvalOptions::default(),
+            fname: Target::StdinDefault,
+            path: "/spec".into(),
+        };
+        assert_eq!(parse(&["rcl", "trace-output", "/spec"]).1, expected_cmd);
+        assert_eq!(
+            fail_parse(&["rcl", "trace-output"]),
+            "Error: Expected an input file and an output path. See --help for usage.\n"
+        );
+    }
+
+    #[test]
+    fn parse_cmd_debug() {
+        let expected_cmd = Cmd::Debug {
+            eval_opts: EvalOptions::default(),
+            style_opts: StyleOptions::default(),
+            fname: Target::File("main.rcl".into()),
+            breakpoints: vec![
+                Breakpoint {
+                    file: "main.rcl".into(),
+                    line: 3,
+                },
+                Breakpoint {
+                    file: "lib/ports.rcl".into(),
+                    line: 12,
+                },
+            ],
+            break_on_trace: true,
+        };
+        assert_eq!(
+            parse(&[
+                "rcl",
+                "debug",
+                "main.rcl",
+                "--break",
+                "main.rcl:3",
+                "--break=lib/ports.rcl:12",
+                "--break-on-trace",
+            ])
+            .1,
+            expected_cmd
+        );
+        assert_eq!(
+            fail_parse(&["rcl", "debug", "main.rcl", "--break", "main.rcl"]),
+            "Error: 'main.rcl' is not valid for --break. See --help for usage.\n"
+        );
+    }
+
+    #[test]
+    fn parse_cmd_lock() {
+        let expected_cmd = Cmd::Lock {
+            sandbox: SandboxMode::Workdir,
+            build_mode: BuildMode::WriteFilesystem,
+            fname: Target::File("main.rcl".into()),
+            lockfile: None,
+        };
+        assert_eq!(parse(&["rcl", "lock", "main.rcl"]).1, expected_cmd);
+
+        let expected_cmd = Cmd::Lock {
+            sandbox: SandboxMode::Workdir,
+            build_mode: BuildMode::DryRun,
+            fname: Target::StdinDefault,
+            lockfile: Some("deps.lock".into()),
+        };
+        assert_eq!(
+            parse(&["rcl", "lock", "--dry-run", "--lockfile", "deps.lock"]).1,
+            expected_cmd
+        );
+    }
+
+    #[test]
+    fn parse_cmd_lint() {
+        let expected_cmd = Cmd::Lint {
+            sandbox: SandboxMode::Workdir,
+            entries: vec![Target::File("main.rcl".into()), Target::Stdin],
+            fnames: vec![Target::File("lib/a.rcl".into())],
+        };
+        assert_eq!(
+            parse(&[
+                "rcl",
+                "lint",
+                "--unused-exports",
+                "--entry",
+                "main.rcl",
+                "--entry=-",
+                "lib/a.rcl",
+            ])
+            .1,
+            expected_cmd
+        );
+        assert_eq!(
+            fail_parse(&["rcl", "lint", "--entry", "main.rcl"]),
+            "Error: Specify the lint to run, such as '--unused-exports'.\n"
+        );
+        assert_eq!(
+            fail_parse(&["rcl", "lint", "--unused-exports", "lib/a.rcl"]),
+            "Error: Specify at least one entry point with '--entry <file>'.\n"
+        );
+    }
+
+    #[test]
+    fn parse_cmd_query_repl() {
+        let expected_cmd = Cmd::Repl {
+            eval_opts: EvalOptions {
+                format: OutputFormat::Json,
+                ..EvalOptions::default()
+            },
+            style_opts: StyleOptions::default(),
+            fname: Target::File("big.rcl".into()),
+        };
+        assert_eq!(parse(&["rcl", "jq", "--repl", "big.rcl"]).1, expected_cmd);
+        assert_eq!(
+            fail_parse(&["rcl", "query", "--repl"]),
+            "Error: With --repl, queries are read from stdin, it can't read the input from there.\n"
+        );
+        assert_eq!(
+            fail_parse(&["rcl", "query", "--repl", "big.rcl", "input.a"]),
+            "Error: Expected an input file and no query with --repl. See --help for usage.\n"
+        );
+        assert_eq!(
+            fail_parse(&["rcl", "query", "--repl", "big.rcl", "-o", "out.rcl"]),
+            "Error: Option '--output' is not supported with '--repl', results are printed at the prompt.\n"
+        );
+    }
+
+    #[test]
+    fn parse_cmd_formats() {
+        assert_eq!(parse(&["rcl", "formats"]).1, Cmd::Formats);
+        assert!(matches!(
+            parse(&["rcl", "formats", "--help"]).1,
+            Cmd::Help { .. }
+        ));
+        assert_eq!(
+            fail_parse(&["rcl", "formats", "a.rcl"]),
+            "Error: The formats command does not take input files. See --help for usage.\n"
+        );
+    }
+
+    #[test]
+    fn parse_format_uses_registry_names() {
+        let (_, cmd) = parse(&["rcl", "eval", "--format", "yaml-stream", "a.rcl"]);
+        match cmd {
+            Cmd::Evaluate { eval_opts, .. } => {
+                assert_eq!(eval_opts.format, OutputFormat::YamlStream)
+            }
+            _ => panic!("Expected an evaluate command."),
+        }
+        assert!(fail_parse(&["rcl", "eval", "--format", "yaml", "a.rcl"])
+            .starts_with("Error: Expected --format to be followed by one of csv, github-actions,"));
+    }
+
+    #[test]
+    fn parse_cmd_check() {
+        let expected_cmd = Cmd::Check {
+            sandbox: SandboxMode::Workdir,
+            strict: StrictMode::Off,
+            jobs: None,
+            fnames: vec![Target::StdinDefault],
+        };
+        assert_eq!(parse(&["rcl", "check"]).1, expected_cmd);
+
+        let expected_cmd = Cmd::Check {
+            sandbox: SandboxMode::Unrestricted,
+            strict: StrictMode::Error,
+            jobs: Some(4),
+            fnames: vec![
+                Target::File("a.rcl".into()),
+                Target::Stdin,
+                Target::File("b.rcl".into()),
+            ],
+        };
+        assert_eq!(
+            parse(&[
+                "rcl",
+                "check",
+                "a.rcl",
+                "--sandbox=unrestricted",
+                "-",
+                "--strict=error",
+                "-j4",
+                "b.rcl"
+            ])
+            .1,
+            expected_cmd
+        );
+        assert_eq!(
+            fail_parse(&["rcl", "check", "--jobs=0", "a.rcl"]),
+            "Error: '0' is not valid for --jobs. See --help for usage.\n"
+        );
+    }
+
+    #[test]
+    fn parse_cmd_convert() {
+        let expected_opt = GlobalOptions {
+            markup: None,
+            workdir: None,
+            stdin_name: None,
+            full_errors: false,
+            tab_width: None,
+            allow_shadowing: false,
+            no_opt: false,
+        };
+        let expected_cmd = Cmd::Convert {
+            eval_opts: EvalOptions::default(),
+            style_opts: StyleOptions::default(),
+            from: None,
+            fname: Target::File("config.yaml".into()),
+            output: OutputTarget::Stdout,
+        };
+        let mut expected = (expected_opt, expected_cmd);
+        assert_eq!(parse(&["rcl", "convert", "config.yaml"]), expected);
+        assert_eq!(
+            parse(&["rcl", "convert", "config.yaml", "--to", "rcl"]),
+            expected
+        );
+
+        if let Cmd::Convert { from, fname, .. } = &mut expected.1 {
+            *from = Some(InputFormat::Toml);
+            *fname = Target::Stdin;
+        }
+        assert_eq!(parse(&["rcl", "convert", "--from=toml", "-"]), expected);
+
+        if let Cmd::Convert { eval_opts, .. } = &mut expected.1 {
+            eval_opts.format = OutputFormat::Json;
+        }
+        assert_eq!(
+            parse(&["rcl", "convert", "--from=toml", "--to=json", "-"]),
+            expected
+        );
+
+        assert_eq!(
+            fail_parse(&["rcl", "convert", "--from=ini", "config.ini"]),
+            "Error: Expected --from to be followed by one of json, jsonnet, toml, yaml. See --help for usage.\n"
+        );
+    }
+
+    #[test]
+    fn parse_cmd_doc() {
+        let expected_opt = GlobalOptions {
+            markup: None,
+            workdir: None,
+            stdin_name: None,
+            full_errors: false,
+            tab_width: None,
+            allow_shadowing: false,
+            no_opt: false,
+        };
+        let expected_cmd = Cmd::Doc {
+            sandbox: SandboxMode::Workdir,
+            format: DocFormat::Markdown,
+            fname: Target::File("lib.rcl".into()),
+            output: OutputTarget::Stdout,
+        };
+        let mut expected = (expected_opt, expected_cmd);
+        assert_eq!(parse(&["rcl", "doc", "lib.rcl"]), expected);
+        assert_eq!(parse(&["rcl", "doc", "-fmarkdown", "lib.rcl"]), expected);
+
+        if let Cmd::Doc { format, .. } = &mut expected.1 {
+            *format = DocFormat::Html;
+        }
+        assert_eq!(parse(&["rcl", "doc", "--format=html", "lib.rcl"]), expected);
+        assert_eq!(parse(&["rcl", "doc", "lib.rcl", "-fhtml"]), expected);
+
+        // The doc formats are not valid for evaluation, and vice versa.
+        assert_eq!(
+            fail_parse(&["rcl", "doc", "--format=json", "lib.rcl"]),
+            "Error: Expected --format to be followed by one of html, markdown. See --help for usage.\n"
+        );
+    }
+
     #[test]
     fn parse_cmd_fmt() {
         let expected_opt = GlobalOptions {
             markup: None,
             workdir: None,
+            stdin_name: None,
+            full_errors: false,
+            tab_width: None,
+            allow_shadowing: false,
+            no_opt: false,
         };
         let expected_cmd = Cmd::Format {
             style_opts: StyleOptions::default(),
+            format_opts: FormatOptions::default(),
+            jobs: None,
             target: FormatTarget::Stdout {
                 fname: Target::File("infile".into()),
             },
@@ -781,6 +2237,35 @@ mod test {
             };
         }
         assert_eq!(parse(&["rcl", "f", "--check", "f1", "f2"]), expected);
+
+        if let Cmd::Format {
+            ref mut format_opts,
+            ..
+        } = &mut expected.1
+        {
+            format_opts.normalize_strings = true;
+        }
+        assert_eq!(
+            parse(&["rcl", "f", "--check", "--normalize-strings", "f1", "f2"]),
+            expected
+        );
+
+        if let Cmd::Format { ref mut jobs, .. } = &mut expected.1 {
+            *jobs = Some(2);
+        }
+        assert_eq!(
+            parse(&[
+                "rcl",
+                "f",
+                "--check",
+                "--normalize-strings",
+                "-j",
+                "2",
+                "f1",
+                "f2"
+            ]),
+            expected
+        );
     }
 
     #[test]
@@ -806,6 +2291,11 @@ mod test {
         let expected_opt = GlobalOptions {
             markup: None,
             workdir: None,
+            stdin_name: None,
+            full_errors: false,
+            tab_width: None,
+            allow_shadowing: false,
+            no_opt: false,
         };
         let expected_cmd = Cmd::Highlight {
             fname: Target::File("infile".into()),
@@ -814,11 +2304,60 @@ mod test {
         assert_eq!(parse(&["rcl", "highlight", "infile"]), expected);
     }
 
+    #[test]
+    fn parse_cmd_serve() {
+        let expected_cmd = Cmd::Serve {
+            sandbox: SandboxMode::Workdir,
+            socket: None,
+        };
+        assert_eq!(parse(&["rcl", "serve"]).1, expected_cmd);
+
+        let expected_cmd = Cmd::Serve {
+            sandbox: SandboxMode::Unrestricted,
+            socket: Some("rcl.sock".into()),
+        };
+        assert_eq!(
+            parse(&[
+                "rcl",
+                "serve",
+                "--socket",
+                "rcl.sock",
+                "--sandbox=unrestricted"
+            ])
+            .1,
+            expected_cmd
+        );
+        assert_eq!(
+            fail_parse(&["rcl", "serve", "a.rcl"]),
+            "Error: The serve command does not take input files. See --help for usage.\n"
+        );
+    }
+
+    #[test]
+    fn parse_cmd_crash_repro() {
+        let expected_cmd = Cmd::CrashRepro {
+            fname: Target::File("crash.rcl".into()),
+            width: 80,
+        };
+        assert_eq!(parse(&["rcl", "crash-repro", "crash.rcl"]).1, expected_cmd);
+
+        let expected_cmd = Cmd::CrashRepro {
+            fname: Target::StdinDefault,
+            width: 40,
+        };
+        assert_eq!(parse(&["rcl", "crash-repro", "-w40"]).1, expected_cmd);
+    }
+
     #[test]
     fn parse_cmd_query() {
         let expected_opt = GlobalOptions {
             markup: None,
             workdir: None,
+            stdin_name: None,
+            full_errors: false,
+            tab_width: None,
+            allow_shadowing: false,
+            no_opt: false,
         };
         let expected_cmd = Cmd::Query {
             eval_opts: EvalOptions::default(),
@@ -848,6 +2387,22 @@ mod test {
             *query = "infile".to_string();
         };
         assert_eq!(parse(&["rcl", "q", "infile"]), expected);
+
+        if let Cmd::Query { fname, query, .. } = &mut expected.1 {
+            *fname = Target::Expr("{ name = 1 }".to_string());
+            *query = "input.name".to_string();
+        };
+        assert_eq!(
+            parse(&["rcl", "q", "--expr", "{ name = 1 }", "input.name"]),
+            expected
+        );
+        assert_eq!(
+            parse(&["rcl", "q", "input.name", "--expr={ name = 1 }"]),
+            expected
+        );
+        assert!(fail_parse(&["rcl", "fmt", "--expr", "1"]).contains("--expr"));
+        assert!(fail_parse(&["rcl", "e", "--expr", "1", "infile"]).contains("cannot be combined"));
+        assert!(fail_parse(&["rcl", "q", "--expr", "1", "infile", "input"]).contains("--expr"));
     }
 
     #[test]
@@ -855,18 +2410,25 @@ mod test {
         let expected_opt = GlobalOptions {
             markup: None,
             workdir: None,
+            stdin_name: None,
+            full_errors: false,
+            tab_width: None,
+            allow_shadowing: false,
+            no_opt: false,
         };
         let expected_cmd = Cmd::Build {
             eval_opts: EvalOptions::default(),
             build_mode: BuildMode::WriteFilesystem,
-            fname: Target::File("build.rcl".to_string()),
+            file_mode: None,
+            jobs: None,
+            fnames: vec![Target::File("build.rcl".to_string())],
         };
         let mut expected = (expected_opt, expected_cmd);
         assert_eq!(parse(&["rcl", "build"]), expected);
         assert_eq!(parse(&["rcl", "build", "build.rcl"]), expected);
 
-        if let Cmd::Build { fname, .. } = &mut expected.1 {
-            *fname = Target::File("other.rcl".to_string());
+        if let Cmd::Build { fnames, .. } = &mut expected.1 {
+            *fnames = vec![Target::File("other.rcl".to_string())];
         };
         assert_eq!(parse(&["rcl", "build", "other.rcl"]), expected);
 
@@ -874,6 +2436,57 @@ mod test {
             *build_mode = BuildMode::DryRun;
         };
         assert_eq!(parse(&["rcl", "build", "--dry-run", "other.rcl"]), expected);
+
+        if let Cmd::Build { file_mode, .. } = &mut expected.1 {
+            *file_mode = Some(0o644);
+        };
+        assert_eq!(
+            parse(&["rcl", "build", "--dry-run", "--mode=0644", "other.rcl"]),
+            expected
+        );
+        assert_eq!(
+            parse(&["rcl", "build", "--dry-run", "--mode", "644", "other.rcl"]),
+            expected
+        );
+        assert_eq!(
+            fail_parse(&["rcl", "build", "--mode=rw-r--r--"]),
+            "Error: 'rw-r--r--' is not valid for --mode. See --help for usage.\n"
+        );
+        assert_eq!(
+            fail_parse(&["rcl", "build", "--mode=17777"]),
+            "Error: '17777' is not valid for --mode. See --help for usage.\n"
+        );
+        assert_eq!(
+            fail_parse(&["rcl", "eval", "--mode=0644", "other.rcl"]),
+            "Error: Unknown option '--mode'. See --help for usage.\n"
+        );
+
+        if let Cmd::Build {
+            fnames,
+            jobs,
+            file_mode,
+            ..
+        } = &mut expected.1
+        {
+            *fnames = vec![
+                Target::File("a/build.rcl".to_string()),
+                Target::File("b/build.rcl".to_string()),
+            ];
+            *jobs = Some(8);
+            *file_mode = None;
+        };
+        assert_eq!(
+            parse(&[
+                "rcl",
+                "build",
+                "--dry-run",
+                "-j",
+                "8",
+                "a/build.rcl",
+                "b/build.rcl"
+            ]),
+            expected
+        );
     }
 
     #[test]
@@ -902,5 +2515,20 @@ mod test {
                 fname: Target::File("-".into()),
             }
         );
+        let (opts, cmd) = parse(&["rcl", "fmt", "--stdin-filename=src/a.rcl", "-"]);
+        assert_eq!(opts.stdin_name, Some("src/a.rcl".into()));
+        assert!(!opts.full_errors);
+        assert_eq!(
+            cmd,
+            Cmd::Format {
+                style_opts: StyleOptions::default(),
+                format_opts: FormatOptions::default(),
+                jobs: None,
+                target: FormatTarget::Stdout {
+                    fname: Target::Stdin
+                },
+                output: OutputTarget::Stdout,
+            }
+        );
     }
 }
@@ -7,14 +7,19 @@
 
 //! Implementation of the `rcl build` subcommand.
 
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-use crate::cli::OutputFormat;
+use crate::cli::{OutputEncoding, OutputFormat};
 use crate::error::{Error, PathElement, Result};
-use crate::fmt_rcl::format_rcl;
+use crate::fmt_rcl::{format_rcl, Limits};
 use crate::loader::Loader;
 use crate::pprint::{concat, Config, Doc};
 use crate::runtime::Value;
+use crate::secret::SecretOptions;
 use crate::source::{DocId, Span};
 use crate::type_source::Source;
 use crate::types::{Dict, SourcedType, Type};
@@ -28,6 +33,123 @@ pub enum BuildMode {
     WriteFilesystem,
 }
 
+/// Write `contents` to `path`, unless the file already has these contents.
+///
+/// Leaving an up-to-date file untouched preserves its mtime, so build systems
+/// and config reloaders that watch the file don't see a spurious change. When
+/// the file does change, we write to a temporary file in the same directory and
+/// rename it over the target, so readers never observe a partial write. The
+/// new file gets permissions `file_mode` if set, or else those of the file it
+/// replaces. The temporary file is created with those permissions, so there is
+/// no moment where e.g. a file with secrets is readable by other users.
+///
+/// When `path` is a symlink, we write to the file it points to, the link itself
+/// stays in place.
+fn write_if_changed(path: &Path, contents: &[u8], file_mode: Option<u32>) -> io::Result<()> {
+    let path = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => fs::canonicalize(path)?,
+        _ => path.to_path_buf(),
+    };
+    let existing = match fs::metadata(&path) {
+        Ok(metadata) => Some(metadata),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+
+    if let Some(metadata) = existing.as_ref() {
+        let mode_ok = file_mode.map_or(true, |m| has_file_mode(&metadata.permissions(), m));
+        if mode_ok && metadata.len() == contents.len() as u64 && fs::read(&path)? == contents {
+            return Ok(());
+        }
+    }
+
+    let file_name = path
+        .file_name()
+        .expect("Output paths have a file name.")
+        .to_string_lossy();
+    let tmp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let permissions = match (file_mode, existing.as_ref()) {
+        (Some(m), _) => Some(m),
+        (None, Some(metadata)) => Some(get_file_mode(&metadata.permissions())),
+        (None, None) => None,
+    };
+
+    let write_tmp = || -> io::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        if let Some(m) = permissions {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(m);
+        }
+        let mut tmp_file = options.open(&tmp_path)?;
+        // The mode at creation is subject to the umask, which can only remove
+        // permissions, so set the exact permissions before we write anything.
+        if let Some(m) = permissions {
+            let mut file_permissions = tmp_file.metadata()?.permissions();
+            set_file_mode(&mut file_permissions, m);
+            tmp_file.set_permissions(file_permissions)?;
+        }
+        tmp_file.write_all(contents)?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, &path)
+    };
+
+    match write_tmp() {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            // Don't leave the temporary file behind, but report the original
+            // error, not any error from the cleanup.
+            let _ = fs::remove_file(&tmp_path);
+            Err(err)
+        }
+    }
+}
+
+/// Return the Unix-style file mode that corresponds to the permissions.
+fn get_file_mode(permissions: &fs::Permissions) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.mode() & 0o7777
+    }
+    #[cfg(not(unix))]
+    {
+        match permissions.readonly() {
+            true => 0o444,
+            false => 0o644,
+        }
+    }
+}
+
+/// Return whether the permissions match the Unix-style `file_mode`.
+fn has_file_mode(permissions: &fs::Permissions, file_mode: u32) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.mode() & 0o7777 == file_mode
+    }
+    // Outside of Unix, the only permission we have is read-only, which
+    // corresponds to the absence of any write bits.
+    #[cfg(not(unix))]
+    {
+        permissions.readonly() == (file_mode & 0o222 == 0)
+    }
+}
+
+/// Update the permissions to match the Unix-style `file_mode`.
+fn set_file_mode(permissions: &mut fs::Permissions, file_mode: u32) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(file_mode);
+    }
+    #[cfg(not(unix))]
+    {
+        permissions.set_readonly(file_mode & 0o222 == 0);
+    }
+}
 /// Return the type to typecheck a build file against.
 fn get_build_file_type() -> SourcedType {
     // TODO: Once we have record types, we can turn this into a record.
@@ -65,23 +187,10 @@ struct Target {
     width: u32,
 }
 
-fn parse_format(format: &str) -> Option<OutputFormat> {
-    // Note, this is duplicated between the CLI parser.
-    let f = match format {
-        "json" => OutputFormat::Json,
-        "raw" => OutputFormat::Raw,
-        "rcl" => OutputFormat::Rcl,
-        "toml" => OutputFormat::Toml,
-        "yaml-stream" => OutputFormat::YamlStream,
-        _ => return None,
-    };
-    Some(f)
-}
-
-fn parse_targets(doc_span: Span, targets_value: Value) -> Result<Vec<Target>> {
+fn parse_targets(doc_span: Span, targets_value: Value, limits: Limits) -> Result<Vec<Target>> {
     // Confirm the high-level shape of the value: a dict of dicts.
     // TODO: Would be better to feed in the requirement already during doc evaluation.
-    targets_value.is_instance_of(doc_span, &get_build_file_type())?;
+    targets_value.is_instance_of(doc_span, &get_build_file_type(), limits)?;
 
     let banner: Option<Rc<str>> = None;
 
@@ -110,14 +219,14 @@ fn parse_targets(doc_span: Span, targets_value: Value) -> Result<Vec<Target>> {
             };
             match k.expect_string() {
                 "banner" => match v {
-                    Value::String(banner) => target.banner = Some(banner.clone()),
+                    Value::String(banner) => target.banner = Some(banner.to_rc()),
                     Value::Null => target.banner = None,
                     _not_str => return make_error("Banner must be a string or null.".into()).err(),
                 },
                 "contents" => contents = Some(v.clone()),
                 "format" => {
                     if let Value::String(format_str) = v {
-                        if let Some(f) = parse_format(format_str.as_ref()) {
+                        if let Some(f) = OutputFormat::from_name(format_str.as_ref()) {
                             format = Some(f);
                             continue;
                         }
@@ -171,23 +280,49 @@ fn parse_targets(doc_span: Span, targets_value: Value) -> Result<Vec<Target>> {
     Ok(result)
 }
 
-/// Take a build specification and write the outputs to files.
-pub fn execute_build(
+/// A build target, formatted and ready to be written.
+///
+/// This does not reference the loader, so a worker thread can build it and
+/// send it to the thread that writes the outputs.
+#[derive(Debug, Eq, PartialEq)]
+pub struct BuildOutput {
+    /// The output path as written in the build file.
+    pub name: String,
+
+    /// Where to write the output to, or `None` in dry-run mode.
+    pub path: Option<PathBuf>,
+
+    pub contents: Vec<u8>,
+}
+
+/// Take a build specification and format the outputs.
+///
+/// In [`BuildMode::WriteFilesystem`], this resolves the output paths against
+/// the build file, which creates their parent directories.
+pub fn render_targets(
     loader: &Loader,
     mode: BuildMode,
+    secrets: &SecretOptions,
     buildfile: DocId,
     doc_span: Span,
     targets_value: Value,
-) -> Result<()> {
-    let targets = parse_targets(doc_span, targets_value).map_err(|mut err| {
-        err.origin = Some(err.origin.unwrap_or(doc_span));
-        err
-    })?;
+) -> Result<Vec<BuildOutput>> {
+    let targets =
+        parse_targets(doc_span, targets_value, loader.error_limits()).map_err(|mut err| {
+            err.origin = Some(err.origin.unwrap_or(doc_span));
+            err
+        })?;
 
-    for (i, target) in targets.iter().enumerate() {
-        println!("[{}/{}] {}", i + 1, targets.len(), target.out_path);
-
-        let mut doc = crate::cmd_eval::format_value(target.format, doc_span, &target.contents)?;
+    let mut outputs = Vec::with_capacity(targets.len());
+    for target in targets {
+        let mut doc = crate::cmd_eval::format_value(
+            target.format,
+            Limits::default(),
+            OutputEncoding::Utf8,
+            secrets,
+            doc_span,
+            &target.contents,
+        )?;
 
         if let Some(banner) = target.banner.as_ref() {
             doc = concat! {
@@ -200,32 +335,218 @@ pub fn execute_build(
         let print_cfg = Config {
             width: target.width,
         };
-        let result = doc.println(&print_cfg);
+        let mut contents = Vec::new();
+        doc.println(&print_cfg)
+            .write_bytes_no_markup(&mut contents)
+            .expect("Writing to a Vec does not fail.");
 
-        match mode {
+        let path = match mode {
             BuildMode::WriteFilesystem => {
-                // coverage:off -- We don't test writing to the file system in tests.
-                let mut out_file = loader.open_build_output(target.out_path.as_ref(), buildfile)?;
-                match result.write_bytes_no_markup(&mut out_file) {
-                    Ok(()) => continue,
-                    Err(err) => {
-                        return Error::new(concat! {
-                            "Failed to write to '" Doc::path(target.out_path.as_ref()) "': "
-                            err.to_string()
-                        })
-                        .err()
-                    }
+                Some(loader.resolve_build_output(target.out_path.as_ref(), buildfile)?)
+            }
+            BuildMode::DryRun => None,
+        };
+        outputs.push(BuildOutput {
+            name: target.out_path.to_string(),
+            path,
+            contents,
+        });
+    }
+
+    Ok(outputs)
+}
+
+/// Write the outputs to their files, or in dry-run mode, to `out`.
+///
+/// Progress is written to `out` as well.
+pub fn write_outputs(
+    outputs: &[BuildOutput],
+    file_mode: Option<u32>,
+    out: &mut dyn Write,
+) -> Result<()> {
+    for (i, output) in outputs.iter().enumerate() {
+        // If we fail to write to stdout, then we have no good way of reporting
+        // the error anyway, so ignore the result.
+        let _ = writeln!(out, "[{}/{}] {}", i + 1, outputs.len(), output.name);
+
+        match output.path.as_ref() {
+            Some(path) => {
+                if let Err(err) = write_if_changed(path, &output.contents, file_mode) {
+                    return Error::new(concat! {
+                        "Failed to write to '" Doc::path(output.name.as_str()) "': "
+                        err.to_string()
+                    })
+                    .err();
                 }
-                // coverage:on
             }
-            BuildMode::DryRun => {
-                let mut stdout = std::io::stdout().lock();
-                // Ignore the result here, if we fail to write to stdout,
-                // then we have no good way of reporting the error anyway.
-                let _ = result.write_bytes_no_markup(&mut stdout);
+            None => {
+                let _ = out.write_all(&output.contents);
             }
         }
     }
 
     Ok(())
 }
+
+/// Take a build specification and write the outputs to files.
+///
+/// Progress, and in dry-run mode the outputs themselves, are written to `out`.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_build(
+    loader: &Loader,
+    mode: BuildMode,
+    file_mode: Option<u32>,
+    secrets: &SecretOptions,
+    buildfile: DocId,
+    doc_span: Span,
+    targets_value: Value,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let outputs = render_targets(loader, mode, secrets, buildfile, doc_span, targets_value)?;
+    write_outputs(&outputs, file_mode, out)
+}
+
+/// Records which build file writes to every output path.
+///
+/// When multiple build files are built in one command, they must not write to
+/// the same file, because then the result would depend on the order of the
+/// build files.
+#[derive(Default)]
+pub struct OutputOwners {
+    owners: HashMap<PathBuf, String>,
+}
+
+impl OutputOwners {
+    /// Claim the paths of the outputs for `buildfile`, or report a path that is taken.
+    ///
+    /// In dry-run mode nothing gets written, so there is nothing to claim.
+    pub fn claim(&mut self, buildfile: &str, outputs: &[BuildOutput]) -> Result<()> {
+        for output in outputs {
+            let path = match output.path.as_ref() {
+                Some(path) => path,
+                None => continue,
+            };
+            match self.owners.get(path) {
+                Some(owner) if owner != buildfile => {
+                    return Error::new(concat! {
+                        "Output '" Doc::path(output.name.as_str()) "' is also written by '"
+                        Doc::path(owner.as_str()) "'."
+                    })
+                    .with_help("Every output must be written by a single build file.")
+                    .err();
+                }
+                _ => {
+                    self.owners.insert(path.clone(), buildfile.to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::{write_if_changed, BuildOutput, OutputOwners};
+
+    /// Create an empty directory for a test, unique to the test and process.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rcl-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Return the names of the files in the directory, sorted.
+    fn list_dir(dir: &PathBuf) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn write_if_changed_skips_unchanged_contents() {
+        let dir = test_dir("write-unchanged");
+        let path = dir.join("out.json");
+        write_if_changed(&path, b"{}\n", None).unwrap();
+        let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+
+        write_if_changed(&path, b"{}\n", None).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), mtime);
+
+        write_if_changed(&path, b"[]\n", None).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"[]\n");
+        assert_eq!(list_dir(&dir), vec!["out.json"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_if_changed_applies_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = test_dir("write-mode");
+        let path = dir.join("secret.env");
+        let mode = |path: &PathBuf| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+        write_if_changed(&path, b"a", Some(0o600)).unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        // Without a mode, a rewrite preserves the mode of the existing file.
+        write_if_changed(&path, b"b", None).unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        // A different mode alone is a reason to rewrite.
+        write_if_changed(&path, b"b", Some(0o640)).unwrap();
+        assert_eq!(mode(&path), 0o640);
+        assert_eq!(list_dir(&dir), vec!["secret.env"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_if_changed_writes_through_symlinks() {
+        let dir = test_dir("write-symlink");
+        let target = dir.join("target.json");
+        let link = dir.join("link.json");
+        fs::write(&target, b"old").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_if_changed(&link, b"new", None).unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        assert_eq!(list_dir(&dir), vec!["link.json", "target.json"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn output_owners_reject_outputs_of_other_build_files() {
+        let output = |name: &str, path: Option<&str>| BuildOutput {
+            name: name.to_string(),
+            path: path.map(PathBuf::from),
+            contents: Vec::new(),
+        };
+        let mut owners = OutputOwners::default();
+        let a = [
+            output("a.txt", Some("/w/a.txt")),
+            output("b.txt", Some("/w/b.txt")),
+        ];
+        assert!(owners.claim("x/build.rcl", &a).is_ok());
+        // The same build file twice writes the same contents, that's fine.
+        assert!(owners.claim("x/build.rcl", &a).is_ok());
+        // A different name that resolves to the same file is not.
+        assert!(owners
+            .claim("y.rcl", &[output("../w/a.txt", Some("/w/a.txt"))])
+            .is_err());
+        // In dry-run mode, nothing is written, so there is no conflict.
+        assert!(owners.claim("y.rcl", &[output("a.txt", None)]).is_ok());
+    }
+}
@@ -0,0 +1,326 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Implementation of the `rcl bundle` subcommand.
+//!
+//! A bundle is a single document that inlines all documents that the entry
+//! point imports, directly or indirectly. Every imported document becomes a
+//! let-binding at the top of the bundle, and the import expressions that
+//! referenced it become variables that refer to the binding. Documents that
+//! are imported from multiple places are included only once. Documents that
+//! are imported `as text` become a binding to a string literal.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::error::{IntoError, Result};
+use crate::lexer::{self, QuoteStyle, StringPrefix, Token};
+use crate::loader::Loader;
+use crate::source::{DocId, Span};
+
+/// An `import` expression in a document.
+struct ImportSite {
+    /// The span of the full expression, from `import` to the closing quote,
+    /// or to the format for `import "path" as text`.
+    span: Span,
+    /// The document that the import resolves to, and whether it is imported as text.
+    target: (DocId, bool),
+}
+
+/// A document to include in the bundle, with its imports resolved.
+struct Module {
+    doc: DocId,
+    imports: Vec<ImportSite>,
+    /// Whether the document is imported `as text`, rather than evaluated.
+    as_text: bool,
+}
+
+impl Module {
+    fn key(&self) -> (DocId, bool) {
+        (self.doc, self.as_text)
+    }
+}
+
+struct Bundler<'a> {
+    loader: &'a mut Loader,
+
+    /// Documents in the order in which they should be bound.
+    ///
+    /// Every document comes after the documents that it imports, so the entry
+    /// point is last.
+    modules: Vec<Module>,
+
+    /// Documents that we are currently collecting imports for, and where they
+    /// were imported from, to detect and report cycles.
+    stack: Vec<(DocId, Option<Span>)>,
+
+    /// Identifiers that occur in any of the documents, or that we assigned.
+    taken_names: HashSet<String>,
+}
+
+/// Inline all imports of the entry point, and return the bundled source code.
+///
+/// The result is not formatted.
+pub fn bundle(loader: &mut Loader, entry: DocId) -> Result<String> {
+    let mut bundler = Bundler {
+        loader,
+        modules: Vec::new(),
+        stack: Vec::new(),
+        taken_names: HashSet::new(),
+    };
+    bundler.collect(entry, None)?;
+    Ok(bundler.render())
+}
+
+/// Return the documents that the entry point imports, directly or indirectly.
+///
+/// Every document comes after the documents that it imports, so the entry
+/// point itself is last. Unlike evaluation, this includes imports in branches
+/// that evaluation would not take.
+pub fn collect_imports(loader: &mut Loader, entry: DocId) -> Result<Vec<DocId>> {
+    let mut bundler = Bundler {
+        loader,
+        modules: Vec::new(),
+        stack: Vec::new(),
+        taken_names: HashSet::new(),
+    };
+    bundler.collect(entry, None)?;
+    let mut docs: Vec<DocId> = Vec::new();
+    for module in bundler.modules.iter() {
+        if !docs.contains(&module.doc) {
+            docs.push(module.doc);
+        }
+    }
+    Ok(docs)
+}
+
+impl<'a> Bundler<'a> {
+    /// Collect the imports of the document and its dependencies.
+    fn collect(&mut self, doc: DocId, imported_from: Option<Span>) -> Result<()> {
+        // We don't need the syntax tree, but parsing validates the document.
+        self.loader.get_cst(doc)?;
+        let tokens = self.loader.get_tokens(doc)?;
+        let input = self.loader.get_doc(doc).data;
+
+        for (token, span) in tokens.iter() {
+            if *token == Token::Ident {
+                self.taken_names.insert(span.resolve(input).to_string());
+            }
+        }
+
+        self.stack.push((doc, imported_from));
+        let mut imports = Vec::new();
+        for (i, (token, span)) in tokens.iter().enumerate() {
+            if *token != Token::KwImport {
+                continue;
+            }
+            let (path_span, path) = self.parse_import_path(doc, &tokens[i + 1..])?;
+            let target = self.loader.load_path(&path, Some(doc)).map_err(|mut err| {
+                if err.origin.is_none() {
+                    err.origin = Some(path_span);
+                }
+                err
+            })?;
+
+            // The parser already validated the format, so if there is an `as`,
+            // it is followed by `text`. Text documents need no further bundling.
+            if let Some(format_span) = self.parse_import_as(doc, &tokens[i + 1..], path_span) {
+                if !self.modules.iter().any(|m| m.key() == (target, true)) {
+                    self.modules.push(Module {
+                        doc: target,
+                        imports: Vec::new(),
+                        as_text: true,
+                    });
+                }
+                imports.push(ImportSite {
+                    span: span.union(format_span),
+                    target: (target, true),
+                });
+                continue;
+            }
+
+            if let Some(i) = self.stack.iter().position(|(d, _)| *d == target) {
+                let mut error = path_span.error("This import creates a cycle.");
+                for (_, src) in self.stack[i..].iter().rev() {
+                    if let Some(src) = src {
+                        error.add_note(*src, "Imported here.");
+                    }
+                }
+                return Err(error.into());
+            }
+            if !self.modules.iter().any(|m| m.key() == (target, false)) {
+                self.collect(target, Some(path_span))?;
+            }
+
+            imports.push(ImportSite {
+                span: span.union(path_span),
+                target: (target, false),
+            });
+        }
+        self.stack.pop();
+
+        self.modules.push(Module {
+            doc,
+            imports,
+            as_text: false,
+        });
+        Ok(())
+    }
+
+    /// Return the span of the format, if the import path is followed by `as text`.
+    fn parse_import_as(
+        &self,
+        doc: DocId,
+        tokens: &[lexer::Lexeme],
+        path_span: Span,
+    ) -> Option<Span> {
+        let input = self.loader.get_doc(doc).data;
+        let mut tokens = tokens
+            .iter()
+            .skip_while(|(_token, span)| span.start() < path_span.end())
+            .filter(|(token, _span)| !matches!(token, Token::Blank | Token::LineComment));
+        match tokens.next() {
+            Some((Token::Ident, span)) if span.resolve(input) == "as" => {
+                tokens.next().map(|(_token, span)| *span)
+            }
+            _ => None,
+        }
+    }
+
+    /// Extract the path from the tokens that follow an `import` keyword.
+    fn parse_import_path(&self, doc: DocId, tokens: &[lexer::Lexeme]) -> Result<(Span, String)> {
+        let input = self.loader.get_doc(doc).data;
+        let mut tokens = tokens
+            .iter()
+            .filter(|(token, _span)| !matches!(token, Token::Blank | Token::LineComment));
+
+        let (open, mut path_span) = match tokens.next() {
+            Some((Token::QuoteOpen(prefix, style), span)) => ((*prefix, *style), *span),
+            Some((_, span)) => return span.error("Import path must be a string literal.").err(),
+            None => unreachable!("The parser rejects an import without path."),
+        };
+        let mut path = String::new();
+        for (token, span) in tokens.by_ref() {
+            path_span = path_span.union(*span);
+            match token {
+                Token::StringInner => path.push_str(span.resolve(input)),
+                Token::Escape(esc) => crate::string::unescape_into(input, *span, *esc, &mut path)?,
+                Token::QuoteClose => break,
+                _ => {
+                    return path_span
+                        .error("Import path must be a string literal without holes.")
+                        .err()
+                }
+            }
+        }
+
+        match open {
+            (StringPrefix::None | StringPrefix::Raw, QuoteStyle::Double) => Ok((path_span, path)),
+            (StringPrefix::Format, _) => path_span
+                .error("Import path must be a string literal without holes.")
+                .err(),
+            (_, QuoteStyle::Triple) => path_span
+                .error("Bundling does not support triple-quoted import paths.")
+                .err(),
+        }
+    }
+
+    /// Pick an identifier to bind the document to, based on its file name.
+    fn binding_name(&mut self, doc: DocId) -> String {
+        let name = self.loader.get_doc(doc).name;
+        let stem = Path::new(name)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let stem: String = stem
+            .chars()
+            .map(|ch| if ch.is_ascii_alphanumeric() { ch } else { '_' })
+            .collect();
+        let base = format!("import_{}", stem.trim_matches('_'));
+
+        let mut candidate = base.clone();
+        let mut n = 2;
+        while self.taken_names.contains(&candidate) {
+            candidate = format!("{base}_{n}");
+            n += 1;
+        }
+        self.taken_names.insert(candidate.clone());
+        candidate
+    }
+
+    /// Return the source of the document, with imports replaced by bindings.
+    fn rewrite(&self, module: &Module, names: &[((DocId, bool), String)], end: usize) -> String {
+        let input = self.loader.get_doc(module.doc).data;
+        let mut result = String::with_capacity(input.len());
+        let mut pos = 0;
+        for import in module.imports.iter() {
+            let name = names
+                .iter()
+                .find(|(key, _)| *key == import.target)
+                .map(|(_, name)| name)
+                .expect("Imports are bound before the documents that import them.");
+            result.push_str(&input[pos..import.span.start()]);
+            result.push_str(name);
+            pos = import.span.end();
+        }
+        result.push_str(&input[pos..end]);
+        result
+    }
+
+    fn render(&mut self) -> String {
+        let modules = std::mem::take(&mut self.modules);
+        let (entry, deps) = modules.split_last().expect("The entry point is a module.");
+        let names: Vec<((DocId, bool), String)> = deps
+            .iter()
+            .map(|module| (module.key(), self.binding_name(module.doc)))
+            .collect();
+
+        let mut out = String::new();
+        let entry_input = self.loader.get_doc(entry.doc).data;
+
+        // A shebang line must remain the first line of the bundle.
+        let entry_shebang_len = shebang_len(entry_input);
+        out.push_str(&entry_input[..entry_shebang_len]);
+
+        for (module, (_key, name)) in deps.iter().zip(names.iter()) {
+            let doc = self.loader.get_doc(module.doc);
+            if module.as_text {
+                let mut literal = String::new();
+                crate::string::escape_json(doc.data, &mut literal);
+                out.push_str("// Bundled from ");
+                out.push_str(doc.name);
+                out.push_str(".\nlet ");
+                out.push_str(name);
+                out.push_str(" = \"");
+                out.push_str(&literal);
+                out.push_str("\";\n\n");
+                continue;
+            }
+            let source = self.rewrite(module, &names, doc.data.len());
+            let source = source[shebang_len(&source)..].trim();
+            out.push_str("// Bundled from ");
+            out.push_str(doc.name);
+            out.push_str(".\nlet ");
+            out.push_str(name);
+            out.push_str(" = (\n");
+            out.push_str(source);
+            out.push_str("\n);\n\n");
+        }
+
+        let entry_source = self.rewrite(entry, &names, entry_input.len());
+        out.push_str(&entry_source[entry_shebang_len..]);
+        out
+    }
+}
+
+/// Return the length of the `#!` line at the start of the input, if any.
+fn shebang_len(input: &str) -> usize {
+    match input.starts_with("#!") {
+        true => input.find('\n').map_or(input.len(), |n| n + 1),
+        false => 0,
+    }
+}
@@ -0,0 +1,90 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Implementation of `rcl convert`, turning data in other formats into RCL.
+
+use crate::convert_json::parse_json;
+use crate::convert_toml::parse_toml;
+use crate::convert_yaml::parse_yaml;
+use crate::error::{Error, Result};
+use crate::pprint::{concat, Doc};
+use crate::runtime::Value;
+use crate::source::DocId;
+
+/// The format of the document to convert.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum InputFormat {
+    Json,
+    /// Jsonnet source code, translated into RCL source code.
+    Jsonnet,
+    Toml,
+    Yaml,
+}
+
+impl InputFormat {
+    /// Infer the input format from the extension of a file name.
+    pub fn from_file_name(name: &str) -> Option<InputFormat> {
+        let (_, extension) = name.rsplit_once('.')?;
+        match extension {
+            "json" => Some(InputFormat::Json),
+            "jsonnet" | "libsonnet" => Some(InputFormat::Jsonnet),
+            "toml" => Some(InputFormat::Toml),
+            "yaml" | "yml" => Some(InputFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// The result of converting a document.
+pub enum Converted {
+    /// Data, which can be printed in any output format.
+    Value(Value),
+    /// RCL source code, translated from a program in another language.
+    Source(String),
+}
+
+/// Convert the input document.
+///
+/// When no format is given, we infer it from the document name.
+pub fn convert(
+    format: Option<InputFormat>,
+    doc: DocId,
+    name: &str,
+    data: &str,
+) -> Result<Converted> {
+    let format = match format.or_else(|| InputFormat::from_file_name(name)) {
+        Some(f) => f,
+        None => {
+            let message = concat! {
+                "Cannot infer the input format of '"
+                Doc::highlight(name).into_owned()
+                "'."
+            };
+            return Error::new(message)
+                .with_help("Specify the format with '--from', one of json, jsonnet, toml, yaml.")
+                .err();
+        }
+    };
+    match format {
+        InputFormat::Json => parse_json(doc, data).map(Converted::Value),
+        InputFormat::Yaml => parse_yaml(doc, data).map(Converted::Value),
+        InputFormat::Toml => parse_toml(doc, data).map(Converted::Value),
+        InputFormat::Jsonnet => convert_jsonnet(doc, data),
+    }
+}
+
+#[cfg(feature = "jsonnet")]
+fn convert_jsonnet(doc: DocId, data: &str) -> Result<Converted> {
+    crate::convert_jsonnet::jsonnet_to_rcl(doc, data).map(Converted::Source)
+}
+
+#[cfg(not(feature = "jsonnet"))]
+fn convert_jsonnet(_doc: DocId, _data: &str) -> Result<Converted> {
+    Error::new("This build of RCL does not support converting Jsonnet.")
+        .with_help("Rebuild RCL with the 'jsonnet' feature enabled.")
+        .err()
+}
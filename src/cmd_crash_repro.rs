@@ -0,0 +1,282 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Implementation of `rcl crash-repro`, to replay an input stage by stage.
+//!
+//! When RCL crashes on an input, the most useful bug report is the input and
+//! the stage of the pipeline where it crashed. This command runs raw bytes
+//! through every stage separately, and reports how far each one got. Inputs can
+//! come straight from the fuzz corpus: when the input starts with the header
+//! line that the `fuzz_source` target expects, we strip it, like the fuzzer.
+//!
+//! The input runs without access to the filesystem or network, so a reproducer
+//! cannot depend on the environment of the person who found it. Evaluation is
+//! subject to the usual limits on stack depth and number of steps.
+//!
+//! A crash is a panic, and release builds abort on panic, so we can't recover
+//! from it. Instead, a panic hook prints the stage that was running.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::{Error, Result};
+use crate::eval::StrictMode;
+use crate::fmt_cst::{self, FormatOptions};
+use crate::fmt_json::format_json;
+use crate::fmt_rcl::format_rcl;
+use crate::loader::{decode_source, Loader, VoidFilesystem};
+use crate::markup::Markup;
+use crate::pprint::{self, concat, Doc};
+use crate::stdlib::Capabilities;
+use crate::tracer::VoidTracer;
+
+/// A stage of the pipeline, in the order that an input goes through them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Stage {
+    Decode,
+    Lex,
+    Parse,
+    Abstract,
+    Typecheck,
+    Evaluate,
+    Format,
+}
+
+const STAGES: [Stage; 7] = [
+    Stage::Decode,
+    Stage::Lex,
+    Stage::Parse,
+    Stage::Abstract,
+    Stage::Typecheck,
+    Stage::Evaluate,
+    Stage::Format,
+];
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Decode => "decode",
+            Stage::Lex => "lex",
+            Stage::Parse => "parse",
+            Stage::Abstract => "abstract",
+            Stage::Typecheck => "typecheck",
+            Stage::Evaluate => "evaluate",
+            Stage::Format => "format",
+        }
+    }
+}
+
+/// The index into [`STAGES`] of the stage that is running, for the panic hook.
+static CURRENT_STAGE: AtomicUsize = AtomicUsize::new(0);
+
+/// Print the running stage when we panic, before the default panic message.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let stage = STAGES[CURRENT_STAGE.load(Ordering::Relaxed)];
+        eprintln!(
+            "\nRCL crashed in the '{}' stage. This is a bug, please report it at \
+            https://github.com/ruuda/rcl/issues, and include the input file.\n",
+            stage.name()
+        );
+        default_hook(info);
+    }));
+}
+
+/// The header line of inputs for the `fuzz_source` target.
+#[derive(Debug, Eq, PartialEq)]
+pub struct FuzzHeader {
+    /// The mode character, e.g. `j` for the json idempotency check.
+    pub mode: char,
+    /// The width to format at.
+    pub width: u32,
+}
+
+/// Split off the fuzzer header line, if the input has one.
+///
+/// The header is `//`, a mode character, a width byte, and a newline.
+pub fn strip_fuzz_header(bytes: &[u8]) -> (Option<FuzzHeader>, &[u8]) {
+    match bytes {
+        [b'/', b'/', mode @ (b'a' | b'f' | b'j' | b'k' | b't'), width, b'\n', data @ ..]
+            if *width > b'\n' =>
+        {
+            let header = FuzzHeader {
+                mode: *mode as char,
+                width: (width - b'\n') as u32,
+            };
+            (Some(header), data)
+        }
+        _ => (None, bytes),
+    }
+}
+
+/// The outcome of replaying an input.
+pub struct Repro {
+    /// A description of every stage that ran, and the error if one failed.
+    pub report: Doc<'static>,
+
+    /// The stage that reported an error, if any.
+    pub failed: Option<Stage>,
+}
+
+/// Run the input through the pipeline, one stage at a time.
+pub fn run(name: String, bytes: &[u8], width: u32) -> Repro {
+    let (header, data) = strip_fuzz_header(bytes);
+    let width = header.as_ref().map(|h| h.width).unwrap_or(width);
+
+    let mut summary = vec![concat! {
+        "Input " Doc::highlight(&name).into_owned() ", "
+        data.len().to_string() " bytes"
+    }];
+    if let Some(header) = &header {
+        summary.push(concat! {
+            ", fuzz mode '" Doc::string(header.mode.to_string()) "'"
+        });
+    }
+    summary.push(concat! { ", width " width.to_string() "." });
+    let mut lines = vec![Doc::Concat(summary), Doc::HardBreak, Doc::HardBreak];
+
+    let mut loader = Loader::new();
+    loader.set_filesystem(Box::new(VoidFilesystem));
+    loader.set_capabilities(Capabilities::pure());
+    let cfg = pprint::Config { width };
+
+    let mut ok_stage = |stage: Stage| {
+        lines.push(concat! {
+            Doc::string(format!("  {:<10} ", stage.name()))
+            "ok"
+            Doc::HardBreak
+        });
+    };
+    let result = run_stages(&mut loader, name, data, &cfg, &mut ok_stage);
+    CURRENT_STAGE.store(0, Ordering::Relaxed);
+
+    match result {
+        Ok(()) => Repro {
+            report: Doc::Concat(lines),
+            failed: None,
+        },
+        Err((stage, err)) => {
+            let inputs = loader.as_inputs();
+            lines.push(concat! {
+                Doc::string(format!("  {:<10} ", stage.name()))
+                Doc::from("error").with_markup(Markup::Error)
+                Doc::HardBreak
+                Doc::HardBreak
+                err.report(&inputs).into_owned()
+            });
+            Repro {
+                report: Doc::Concat(lines),
+                failed: Some(stage),
+            }
+        }
+    }
+}
+
+/// Run every stage, call `on_ok` after each one that succeeds.
+fn run_stages(
+    loader: &mut Loader,
+    name: String,
+    data: &[u8],
+    cfg: &pprint::Config,
+    on_ok: &mut dyn FnMut(Stage),
+) -> std::result::Result<(), (Stage, Box<Error>)> {
+    // Every stage repeats the ones before it, that is cheap compared to the
+    // time it takes a human to read the report, and it keeps stages separate.
+    fn enter(stage: Stage) {
+        let index = STAGES.iter().position(|s| *s == stage).unwrap_or(0);
+        CURRENT_STAGE.store(index, Ordering::Relaxed);
+    }
+    fn at<T>(stage: Stage, result: Result<T>) -> std::result::Result<T, (Stage, Box<Error>)> {
+        result.map_err(|err| (stage, err))
+    }
+
+    enter(Stage::Decode);
+    let context = concat! { "Failed to decode " Doc::highlight(&name).into_owned() ": " };
+    let text = at(Stage::Decode, decode_source(context, data.to_vec()))?;
+    let id = loader.load_named_string(name, text);
+    on_ok(Stage::Decode);
+
+    enter(Stage::Lex);
+    at(Stage::Lex, loader.get_tokens(id))?;
+    on_ok(Stage::Lex);
+
+    enter(Stage::Parse);
+    let cst = at(Stage::Parse, loader.get_cst(id))?;
+    on_ok(Stage::Parse);
+
+    enter(Stage::Abstract);
+    at(Stage::Abstract, loader.get_unchecked_ast(id))?;
+    on_ok(Stage::Abstract);
+
+    let mut tracer = VoidTracer;
+    enter(Stage::Typecheck);
+    let mut type_env = loader.type_prelude();
+    at(
+        Stage::Typecheck,
+        loader.get_typechecked_ast(&mut type_env, id, &mut tracer),
+    )?;
+    on_ok(Stage::Typecheck);
+
+    enter(Stage::Evaluate);
+    let mut type_env = loader.type_prelude();
+    let mut value_env = loader.value_prelude();
+    let value = at(
+        Stage::Evaluate,
+        loader.evaluate(
+            &mut type_env,
+            &mut value_env,
+            id,
+            &mut tracer,
+            StrictMode::Off,
+        ),
+    )?;
+    on_ok(Stage::Evaluate);
+
+    enter(Stage::Format);
+    let doc = loader.get_doc(id);
+    let _ = fmt_cst::format_expr(doc.data, &cst, FormatOptions::default()).println(cfg);
+    let _ = format_rcl(&value).println(cfg);
+    let json = at(Stage::Format, format_json(loader.get_span(id), &value))?;
+    let _ = json.println(cfg);
+    on_ok(Stage::Format);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{run, strip_fuzz_header, FuzzHeader, Stage};
+
+    #[test]
+    fn strip_fuzz_header_handles_corpus_inputs() {
+        let (header, data) = strip_fuzz_header(b"//j\x1a\n[1, 2]");
+        let expected = FuzzHeader {
+            mode: 'j',
+            width: 16,
+        };
+        assert_eq!(header, Some(expected));
+        assert_eq!(data, b"[1, 2]");
+
+        // A regular comment is not a header.
+        let input = b"// Hello\n[1, 2]";
+        assert_eq!(strip_fuzz_header(input), (None, &input[..]));
+    }
+
+    #[test]
+    fn run_reports_failing_stage() {
+        let failed = |input: &[u8]| run("input".into(), input, 80).failed;
+        assert_eq!(failed(b"{ a = 1 }"), None);
+        assert_eq!(failed(b"\xff"), Some(Stage::Decode));
+        assert_eq!(failed(b"\"unclosed"), Some(Stage::Lex));
+        assert_eq!(failed(b"[1, 2 3]"), Some(Stage::Parse));
+        assert_eq!(failed(b"let x: Int = \"x\"; x"), Some(Stage::Typecheck));
+        assert_eq!(failed(b"[1][2]"), Some(Stage::Evaluate));
+        // Without capabilities, reading files is not possible.
+        assert_eq!(failed(b"std.read_file_utf8(\"x\")"), Some(Stage::Evaluate));
+        assert_eq!(failed(b"x => x"), Some(Stage::Format));
+    }
+}
@@ -0,0 +1,203 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Implementation of `rcl doc`, rendering reference docs for a library document.
+//!
+//! A library document is a document that evaluates to a dict, where the keys
+//! are the names of the exported bindings. For every binding, we render its
+//! type and its doc comment, if it has one.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::cst::Expr;
+use crate::doc_comment::{collect_doc_comments, DocKind};
+use crate::error::{IntoError, Result};
+use crate::fmt_type::format_type;
+use crate::pprint::{self, concat};
+use crate::runtime::Value;
+use crate::source::Span;
+use crate::types::Type;
+
+/// The format to render documentation in.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum DocFormat {
+    Html,
+    #[default]
+    Markdown,
+}
+
+/// An exported binding of a library document.
+pub struct Entry {
+    /// The name of the binding, the key in the exported dict.
+    pub name: Rc<str>,
+
+    /// The type of the value, formatted.
+    pub signature: String,
+
+    /// The doc comment of the field that defines the binding, if any.
+    pub doc: Option<String>,
+}
+
+/// Return the innermost body expression of a document.
+///
+/// This skips over let-bindings and other statements, and parentheses.
+fn get_body(mut expr: &Expr) -> &Expr {
+    loop {
+        match expr {
+            Expr::Statements { body, .. } => expr = &body.inner,
+            Expr::Parens { body, .. } => expr = body,
+            _ => return expr,
+        }
+    }
+}
+
+/// Format the type of a value to document it.
+///
+/// For functions we have a precise type. For other values, the type is not
+/// tracked at runtime, so we only name the kind of value.
+fn format_signature(value: &Value) -> String {
+    let type_ = match value.function_type() {
+        Some(f) => Type::Function(f),
+        None => return value.type_name().to_string(),
+    };
+    let cfg = pprint::Config { width: 76 };
+    let mut result = format_type(&type_).println(&cfg).to_string_no_markup();
+    result.truncate(result.trim_end().len());
+    result
+}
+
+/// Collect the exported bindings of a library document.
+///
+/// The `input` and `cst` are those of the document, the `value` is the result
+/// of evaluating it, and `value_span` is the span to blame errors on.
+pub fn collect_entries(
+    input: &str,
+    cst: &Expr,
+    value_span: Span,
+    value: &Value,
+) -> Result<Vec<Entry>> {
+    let exports = match value {
+        Value::Dict(kvs) => kvs,
+        _ => {
+            return value_span
+                .error(concat! {
+                    "Expected the document to evaluate to a dict of exported bindings, but got "
+                    value.type_name()
+                    "."
+                })
+                .err()
+        }
+    };
+
+    // If the body of the document is a variable, then the exported fields are
+    // defined in the let-binding for that variable.
+    let export_prefix: Vec<Rc<str>> = match get_body(cst) {
+        Expr::Var(span) => vec![span.resolve(input).into()],
+        _ => Vec::new(),
+    };
+
+    let mut docs: BTreeMap<Rc<str>, String> = BTreeMap::new();
+    for doc in collect_doc_comments(input, cst) {
+        let is_export = match doc.path.split_last() {
+            Some((_name, parents)) => parents == &export_prefix[..],
+            None => false,
+        };
+        if !is_export {
+            continue;
+        }
+        // A binding can be documented on the field itself, or on a let-binding
+        // of the same name inside the dict. The field takes precedence.
+        let name = doc.path.last().expect("Path is not empty.").clone();
+        match doc.kind {
+            DocKind::Field => {
+                docs.insert(name, doc.text);
+            }
+            DocKind::Let => {
+                docs.entry(name).or_insert(doc.text);
+            }
+        }
+    }
+
+    let mut entries = Vec::with_capacity(exports.len());
+    for (key, value) in exports.iter() {
+        let name = match key {
+            Value::String(name) => name.to_rc(),
+            _ => {
+                return value_span
+                    .error(concat! {
+                        "Expected the exported bindings to have string keys, but got "
+                        key.type_name()
+                        "."
+                    })
+                    .err()
+            }
+        };
+        entries.push(Entry {
+            doc: docs.get(&name).cloned(),
+            signature: format_signature(value),
+            name,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Escape text for inclusion in html.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn render_markdown(title: &str, entries: &[Entry]) -> String {
+    let mut out = String::new();
+    write!(out, "# {title}").unwrap();
+    for entry in entries {
+        write!(out, "\n\n## {}\n\n", entry.name).unwrap();
+        for (i, line) in entry.signature.lines().enumerate() {
+            let prefix = if i == 0 { entry.name.as_ref() } else { "" };
+            let sep = if i == 0 { ": " } else { "" };
+            writeln!(out, "    {prefix}{sep}{line}").unwrap();
+        }
+        out.pop();
+        if let Some(doc) = &entry.doc {
+            write!(out, "\n\n{doc}").unwrap();
+        }
+    }
+    out
+}
+
+fn render_html(title: &str, entries: &[Entry]) -> String {
+    let mut out = String::new();
+    write!(out, "<h1>{}</h1>", escape_html(title)).unwrap();
+    for entry in entries {
+        let name = escape_html(&entry.name);
+        write!(out, "\n<h2 id=\"{name}\">{name}</h2>").unwrap();
+        write!(
+            out,
+            "\n<pre><code>{name}: {}</code></pre>",
+            escape_html(&entry.signature)
+        )
+        .unwrap();
+        if let Some(doc) = &entry.doc {
+            for paragraph in doc.split("\n\n") {
+                write!(out, "\n<p>{}</p>", escape_html(paragraph.trim())).unwrap();
+            }
+        }
+    }
+    out
+}
+
+/// Render the reference documentation for a library document.
+pub fn render_docs(format: DocFormat, title: &str, entries: &[Entry]) -> String {
+    match format {
+        DocFormat::Html => render_html(title, entries),
+        DocFormat::Markdown => render_markdown(title, entries),
+    }
+}
@@ -7,19 +7,65 @@
 
 //! Functions for implementing parts of `rcl evaluate` and `rcl query`.
 
-use crate::cli::OutputFormat;
-use crate::error::Result;
+use crate::cli::{OutputEncoding, OutputFormat};
+use crate::error::{Error, Result};
+use crate::fmt_rcl::Limits;
+use crate::fmt_yaml::Profile;
+use crate::output::FormatBackend;
 use crate::pprint::Doc;
 use crate::runtime::Value;
+use crate::secret::{resolve_secrets, SecretOptions};
 use crate::source::Span;
 
-pub fn format_value(format: OutputFormat, value_span: Span, value: &Value) -> Result<Doc> {
+/// Format a value in the given output format.
+///
+/// The [`FormatCapabilities`](crate::output::FormatCapabilities) of the format
+/// determine whether it supports eliding parts of the value and ASCII output,
+/// when it does not, the limits must be unset and the encoding UTF-8. Secrets
+/// in the value are resolved according to the secret options before formatting.
+pub fn format_value(
+    format: OutputFormat,
+    limits: Limits,
+    encoding: OutputEncoding,
+    secrets: &SecretOptions,
+    value_span: Span,
+    value: &Value,
+) -> Result<Doc<'static>> {
+    let value = &resolve_secrets(value_span, value, secrets)?;
+    let ascii_only = encoding == OutputEncoding::Ascii;
+    let caps = format.capabilities();
+    if ascii_only && !caps.ascii_output {
+        return Error::new(
+            "The option --output-encoding=ascii is only supported \
+            for the json and YAML-based output formats.",
+        )
+        .err();
+    }
+    if !limits.is_unlimited() && !caps.limits {
+        return Error::new(
+            "The options --max-depth and --max-items are only supported \
+            for the rcl and json output formats.",
+        )
+        .err();
+    }
     let result = match format {
-        OutputFormat::Json => crate::fmt_json::format_json(value_span, value)?,
-        OutputFormat::Raw => crate::fmt_raw::format_raw(value_span, value)?,
-        OutputFormat::Rcl => crate::fmt_rcl::format_rcl(value),
-        OutputFormat::Toml => crate::fmt_toml::format_toml(value_span, value)?,
-        OutputFormat::YamlStream => crate::fmt_yaml_stream::format_yaml_stream(value_span, value)?,
+        OutputFormat::Json => {
+            crate::fmt_json::format_json_limited(value_span, value, limits, ascii_only)?
+        }
+        OutputFormat::Rcl => crate::fmt_rcl::format_rcl_limited(value, limits),
+        OutputFormat::GithubActions => {
+            crate::fmt_yaml::format_yaml(value_span, Profile::GithubActions, value, ascii_only)?
+        }
+        OutputFormat::PrometheusRules => {
+            crate::fmt_yaml::format_yaml(value_span, Profile::PrometheusRules, value, ascii_only)?
+        }
+        OutputFormat::TerraformJson => {
+            crate::fmt_terraform::format_terraform_json(value_span, value, ascii_only)?
+        }
+        OutputFormat::YamlStream => {
+            crate::fmt_yaml_stream::format_yaml_stream(value_span, value, ascii_only)?
+        }
+        _ => format.format(value_span, value)?,
     };
-    Ok(result)
+    Ok(result.into_owned())
 }
@@ -0,0 +1,394 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Implementation of the `rcl lint` subcommand.
+//!
+//! With `--unused-exports`, the linter follows the imports of a set of entry
+//! points, and reports the exports of imported documents that no document
+//! uses, as well as files that no entry point imports. The exports of a
+//! document are the fields of the dict that it evaluates to, when it is a
+//! dict literal, possibly preceded by let-bindings.
+//!
+//! The analysis is syntactic and conservative. An export counts as used when
+//! an import is followed by a field access or an index with a string literal,
+//! either directly or through a let-binding. When the value of an import is
+//! used in any other way, for example passed to a function or re-exported as
+//! a field, then all of its exports count as used.
+
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Ident, ImportFormat, Seq, Stmt, Yield};
+use crate::error::{IntoError, Result};
+use crate::loader::Loader;
+use crate::pprint::{concat, Doc};
+use crate::source::{DocId, Span};
+use crate::tracer::Warning;
+
+/// The findings of the unused exports lint.
+pub struct UnusedExports {
+    /// Fields of imported documents that no document uses.
+    pub exports: Vec<Warning>,
+
+    /// Files that none of the entry points import, directly or indirectly.
+    pub files: Vec<Warning>,
+}
+
+/// Report the unused exports of documents that the entry points import.
+///
+/// Files in `files` that the entry points do not import are reported as well.
+pub fn find_unused_exports(
+    loader: &mut Loader,
+    entries: &[DocId],
+    files: &[DocId],
+) -> Result<UnusedExports> {
+    let mut analyzer = Analyzer {
+        loader,
+        doc: entries.first().copied().unwrap_or(DocId(0)),
+        env: Vec::new(),
+        reachable: Vec::new(),
+        text_imports: BTreeSet::new(),
+        all_used: BTreeSet::new(),
+        fields_used: BTreeSet::new(),
+    };
+
+    // The entry points are used as a whole, by whoever evaluates them.
+    for entry in entries {
+        analyzer.enqueue(*entry);
+        analyzer.all_used.insert(*entry);
+    }
+
+    // Analyzing a document can discover new documents, which get appended.
+    let mut i = 0;
+    while i < analyzer.reachable.len() {
+        let doc = analyzer.reachable[i];
+        let ast = analyzer.loader.get_unchecked_ast(doc)?;
+        analyzer.doc = doc;
+        analyzer.expr(&ast)?;
+        debug_assert!(analyzer.env.is_empty());
+        i += 1;
+    }
+
+    let mut exports = Vec::new();
+    for doc in analyzer.reachable.iter() {
+        if analyzer.all_used.contains(doc) {
+            continue;
+        }
+        let ast = analyzer.loader.get_unchecked_ast(*doc)?;
+        for (name, span) in get_exports(&ast) {
+            if !analyzer.fields_used.contains(&(*doc, name.clone())) {
+                let message = concat! {
+                    "Export '" Doc::highlight(&name).into_owned() "' is never used."
+                };
+                exports.push(Warning::new(span, message));
+            }
+        }
+    }
+
+    let unused_files = files
+        .iter()
+        .filter(|doc| !analyzer.reachable.contains(doc) && !analyzer.text_imports.contains(doc))
+        .map(|doc| {
+            Warning::new(
+                Span::new(*doc, 0, 0),
+                "This file is never imported by the entry points.",
+            )
+        })
+        .collect();
+
+    Ok(UnusedExports {
+        exports,
+        files: unused_files,
+    })
+}
+
+/// Return the fields of the dict literal that the document evaluates to.
+///
+/// Fields with keys that are not string literals, or that are defined in
+/// comprehensions, are not exports.
+fn get_exports(expr: &Expr) -> Vec<(Rc<str>, Span)> {
+    match expr {
+        Expr::Stmt { body, .. } => get_exports(body),
+        Expr::BraceLit { elements, .. } => elements
+            .iter()
+            .filter_map(|seq| match seq {
+                Seq::Yield(Yield::Assoc { key, key_span, .. }) => match key.as_ref() {
+                    Expr::StringLit(name) => Some((name.clone(), *key_span)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+struct Analyzer<'a> {
+    loader: &'a mut Loader,
+
+    /// The document that we are analyzing.
+    doc: DocId,
+
+    /// The names in scope, innermost last, with the document they import.
+    env: Vec<(Ident, Option<DocId>)>,
+
+    /// Documents that the entry points import, in the order we found them.
+    reachable: Vec<DocId>,
+
+    /// Documents that are imported `as text`, which we do not analyze.
+    text_imports: BTreeSet<DocId>,
+
+    /// Documents whose value is used as a whole, so all exports are used.
+    all_used: BTreeSet<DocId>,
+
+    /// Exports that are accessed by name.
+    fields_used: BTreeSet<(DocId, Rc<str>)>,
+}
+
+impl<'a> Analyzer<'a> {
+    fn enqueue(&mut self, doc: DocId) {
+        if !self.reachable.contains(&doc) {
+            self.reachable.push(doc);
+        }
+    }
+
+    fn bind(&mut self, name: &Ident, doc: Option<DocId>) {
+        self.env.push((name.clone(), doc));
+    }
+
+    /// Resolve an import of an RCL document, and queue it for analysis.
+    ///
+    /// Returns `None` for imports `as text`, which have no exports.
+    fn import(
+        &mut self,
+        path_span: Span,
+        path: &Expr,
+        format: ImportFormat,
+    ) -> Result<Option<DocId>> {
+        let path = match path {
+            Expr::StringLit(path) => path,
+            _ => {
+                return path_span
+                    .error("Import path must be a string literal.")
+                    .err()
+            }
+        };
+        let target = self
+            .loader
+            .load_path(path, Some(self.doc))
+            .map_err(|mut err| {
+                if err.origin.is_none() {
+                    err.origin = Some(path_span);
+                }
+                err
+            })?;
+        match format {
+            ImportFormat::Rcl => {
+                self.enqueue(target);
+                Ok(Some(target))
+            }
+            ImportFormat::Text => {
+                self.text_imports.insert(target);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Return the document that the expression evaluates to, if it is an import.
+    ///
+    /// This does not mark the document as used, the caller decides how it is used.
+    fn imported_doc(&mut self, expr: &Expr) -> Result<Option<DocId>> {
+        match expr {
+            Expr::Import {
+                path_span,
+                path,
+                format,
+            } => self.import(*path_span, path, *format),
+            Expr::Var { ident, .. } => Ok(self
+                .env
+                .iter()
+                .rev()
+                .find(|(name, _doc)| name == ident)
+                .and_then(|(_name, doc)| *doc)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Analyze the statement, and bind the names it defines.
+    fn stmt(&mut self, stmt: &Stmt) -> Result<()> {
+        match stmt {
+            Stmt::Let { ident, value, .. } => {
+                let doc = match value.as_ref() {
+                    Expr::Import { .. } => self.imported_doc(value)?,
+                    _ => {
+                        self.expr(value)?;
+                        None
+                    }
+                };
+                self.bind(ident, doc);
+            }
+            Stmt::LetTuple { idents, value, .. } => {
+                self.expr(value)?;
+                for (_span, ident) in idents.iter() {
+                    self.bind(ident, None);
+                }
+            }
+            Stmt::Assert {
+                condition, message, ..
+            } => {
+                self.expr(condition)?;
+                self.expr(message)?;
+            }
+            Stmt::Trace { message, .. } => self.expr(message)?,
+        }
+        Ok(())
+    }
+
+    /// Analyze an expression whose value may be used in any way.
+    fn expr(&mut self, expr: &Expr) -> Result<()> {
+        let n = self.env.len();
+        match expr {
+            Expr::Stmt { stmt, body, .. } => {
+                self.stmt(stmt)?;
+                self.expr(body)?;
+            }
+            Expr::Import { .. } | Expr::Var { .. } => {
+                if let Some(doc) = self.imported_doc(expr)? {
+                    self.all_used.insert(doc);
+                }
+            }
+            Expr::Field { inner, field, .. } => match self.imported_doc(inner)? {
+                Some(doc) => {
+                    self.fields_used.insert((doc, field.0.clone()));
+                }
+                None => self.expr(inner)?,
+            },
+            Expr::Index {
+                collection, index, ..
+            } => match (self.imported_doc(collection)?, index.as_ref()) {
+                (Some(doc), Expr::StringLit(field)) => {
+                    self.fields_used.insert((doc, field.clone()));
+                }
+                (Some(doc), _) => {
+                    self.all_used.insert(doc);
+                    self.expr(index)?;
+                }
+                (None, _) => {
+                    self.expr(collection)?;
+                    self.expr(index)?;
+                }
+            },
+            Expr::BraceLit { elements, .. }
+            | Expr::BracketLit { elements, .. }
+            | Expr::SetLit { elements, .. }
+            | Expr::DictLit { elements, .. } => {
+                for seq in elements.iter() {
+                    self.seq(seq)?;
+                }
+            }
+            Expr::TupleLit { elements } => {
+                for (_span, element) in elements.iter() {
+                    self.expr(element)?;
+                }
+            }
+            Expr::NullLit
+            | Expr::BoolLit(..)
+            | Expr::StringLit(..)
+            | Expr::IntegerLit(..)
+            | Expr::FloatLit(..)
+            | Expr::BytesLit(..) => {}
+            Expr::Format(fragments) => {
+                for fragment in fragments.iter() {
+                    self.expr(&fragment.body)?;
+                }
+            }
+            Expr::IfThenElse {
+                condition,
+                body_then,
+                body_else,
+                ..
+            } => {
+                self.expr(condition)?;
+                self.expr(body_then)?;
+                self.expr(body_else)?;
+            }
+            Expr::TaggedLit { value, .. } => self.expr(value)?,
+            Expr::Match {
+                scrutinee, arms, ..
+            } => {
+                self.expr(scrutinee)?;
+                let mut names = Vec::new();
+                for arm in arms.iter() {
+                    names.clear();
+                    arm.pattern.bound_names(&mut names);
+                    names.iter().for_each(|name| self.bind(name, None));
+                    self.expr(&arm.body)?;
+                    self.env.truncate(n);
+                }
+            }
+            Expr::Function { args, body, .. } => {
+                args.iter().for_each(|(_span, name)| self.bind(name, None));
+                self.expr(body)?;
+            }
+            Expr::TypedFunction { body, type_, .. } => {
+                for arg in type_.args.iter() {
+                    if let Some(name) = &arg.name {
+                        self.bind(name, None);
+                    }
+                }
+                self.expr(body)?;
+            }
+            Expr::Call { function, args, .. } => {
+                self.expr(function)?;
+                for arg in args.iter() {
+                    self.expr(&arg.value)?;
+                }
+            }
+            Expr::UnOp { body, .. } => self.expr(body)?,
+            Expr::BinOp { lhs, rhs, .. } => {
+                self.expr(lhs)?;
+                self.expr(rhs)?;
+            }
+            Expr::CheckType { body, .. } => self.expr(body)?,
+        }
+        self.env.truncate(n);
+        Ok(())
+    }
+
+    fn seq(&mut self, seq: &Seq) -> Result<()> {
+        let n = self.env.len();
+        match seq {
+            Seq::Yield(Yield::Elem { value, .. }) => self.expr(value)?,
+            Seq::Yield(Yield::Assoc { key, value, .. }) => {
+                self.expr(key)?;
+                self.expr(value)?;
+            }
+            Seq::Stmt { stmt, body } => {
+                self.stmt(stmt)?;
+                self.seq(body)?;
+            }
+            Seq::For {
+                idents,
+                collection,
+                body,
+                ..
+            } => {
+                self.expr(collection)?;
+                idents.iter().for_each(|name| self.bind(name, None));
+                self.seq(body)?;
+            }
+            Seq::If {
+                condition, body, ..
+            } => {
+                self.expr(condition)?;
+                self.seq(body)?;
+            }
+        }
+        self.env.truncate(n);
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Implementation of `rcl lock`, and of checking a lockfile with `--locked`.
+//!
+//! A lockfile is an RCL document that maps the path of every document that the
+//! entry point imports, directly or indirectly, to the SHA-256 hash of its
+//! contents. Evaluating with `--locked` fails when the imports no longer match.
+
+use std::collections::BTreeMap;
+
+use crate::cli::Target;
+use crate::error::{Error, IntoError, Result};
+use crate::fmt_rcl::format_rcl;
+use crate::loader::Loader;
+use crate::pprint::{concat, Doc};
+use crate::runtime::Value;
+use crate::small_collection::SmallDict;
+use crate::source::{DocId, Span};
+
+/// Return the path of the lockfile for the given entry point.
+pub fn get_lockfile_path(target: &Target, lockfile: Option<&str>) -> Result<String> {
+    match (lockfile, target) {
+        (Some(path), _) => Ok(path.to_string()),
+        (None, Target::File(fname)) => Ok(format!("{fname}.lock")),
+        (None, Target::Stdin | Target::StdinDefault) => {
+            Error::new("When the input is stdin, the lockfile must be specified.")
+                .with_help(concat! {
+                    "Specify the lockfile with '" Doc::highlight("--lockfile <file>") "'."
+                })
+                .err()
+        }
+        (None, Target::Expr(..)) => {
+            Error::new("When the input is an expression, the lockfile must be specified.")
+                .with_help(concat! {
+                    "Specify the lockfile with '" Doc::highlight("--lockfile <file>") "'."
+                })
+                .err()
+        }
+    }
+}
+
+/// Return the content hash of every document that the entry point imports.
+pub fn compute_lock(loader: &mut Loader, entry: DocId) -> Result<BTreeMap<String, String>> {
+    let docs = crate::cmd_bundle::collect_imports(loader, entry)?;
+    let result = docs
+        .iter()
+        .filter(|doc| **doc != entry)
+        .map(|doc| {
+            let doc = loader.get_doc(*doc);
+            let hash = crate::sha256::sha256_hex(doc.data.as_bytes());
+            (doc.name.to_string(), format!("sha256:{hash}"))
+        })
+        .collect();
+    Ok(result)
+}
+
+/// Format the lockfile for the given hashes.
+pub fn format_lockfile(lock: &BTreeMap<String, String>) -> Doc<'static> {
+    let value: BTreeMap<Value, Value> = lock
+        .iter()
+        .map(|(k, v)| (Value::String(k[..].into()), Value::String(v[..].into())))
+        .collect();
+    concat! {
+        "// This file is generated by 'rcl lock', do not edit."
+        Doc::HardBreak
+        format_rcl(&Value::Dict(SmallDict::from(value))).into_owned()
+    }
+}
+
+/// Confirm that the imports of the entry point match the evaluated lockfile.
+pub fn verify_lock(
+    loader: &mut Loader,
+    entry: DocId,
+    lockfile_span: Span,
+    locked: &Value,
+) -> Result<()> {
+    let locked = match locked {
+        Value::Dict(kvs) if kvs.iter().all(|(k, v)| is_string(k) && is_string(v)) => kvs,
+        _ => {
+            return lockfile_span
+                .error("A lockfile should be a dict that maps paths to hashes.")
+                .err()
+        }
+    };
+    let current = compute_lock(loader, entry)?;
+    let help = "If the change is intended, update the lockfile with 'rcl lock'.";
+
+    for (path, hash) in current.iter() {
+        match locked.get(&Value::String(path[..].into())) {
+            None => {
+                return Error::new(concat! {
+                    "'" Doc::path(path).into_owned() "' is imported, but it is not in the lockfile."
+                })
+                .with_help(help)
+                .err()
+            }
+            Some(Value::String(locked_hash)) if locked_hash.as_ref() != hash => {
+                return Error::new(concat! {
+                    "'" Doc::path(path).into_owned() "' changed since it was locked."
+                })
+                .with_help(help)
+                .err()
+            }
+            Some(_) => continue,
+        }
+    }
+
+    for path in locked.keys() {
+        let path = path.expect_string();
+        if !current.contains_key(path) {
+            return Error::new(concat! {
+                "'" Doc::path(path).into_owned() "' is in the lockfile, but it is no longer imported."
+            })
+            .with_help(help)
+            .err();
+        }
+    }
+
+    Ok(())
+}
+
+fn is_string(v: &Value) -> bool {
+    matches!(v, Value::String(..))
+}
@@ -0,0 +1,453 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Implementation of `rcl serve`, a long-running server for editor tooling.
+//!
+//! The server reads JSON-RPC 2.0 requests, one per line, and writes one
+//! response per line. It keeps a single loader alive between requests, so
+//! imported files stay loaded, and only files that changed on disk get loaded
+//! again. This saves editor plugins and watch-mode builds from paying the cost
+//! of starting a process for every keystroke.
+//!
+//! Failing to evaluate or format a document is not a protocol error, the result
+//! of the request reports it with `ok = false`. Protocol errors, such as
+//! malformed requests, use the standard JSON-RPC error codes.
+
+use std::io::{self, BufRead, Write};
+
+use crate::cli::{OutputEncoding, OutputFormat, Target};
+use crate::convert_json::parse_json;
+use crate::error::{Error, Result};
+use crate::eval::StrictMode;
+use crate::fmt_cst::FormatOptions;
+use crate::fmt_rcl::Limits;
+use crate::loader::Loader;
+use crate::markup::MarkupMode;
+use crate::pprint::{self, concat, Doc};
+use crate::runtime::Value;
+use crate::secret::SecretOptions;
+use crate::small_collection::SmallDict;
+use crate::source::{self, DocId, Span};
+use crate::tracer::BufferTracer;
+use crate::typecheck;
+
+/// When the loader holds more documents than this, start over with an empty one.
+///
+/// Files that change on disk take the place of their old document when they are
+/// loaded again, but every request for an editor buffer adds a document, so
+/// without a bound the server would grow indefinitely.
+const MAX_DOCUMENTS: usize = 1000;
+
+// Error codes defined by the JSON-RPC 2.0 specification.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Why the server stopped reading requests from a connection.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Disconnect {
+    /// The client closed its end of the connection.
+    EndOfInput,
+    /// The client sent a `shutdown` request.
+    Shutdown,
+}
+
+/// An error in a request itself, as opposed to in the document it refers to.
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: String) -> RpcError {
+        RpcError {
+            code: INVALID_PARAMS,
+            message,
+        }
+    }
+}
+
+type RpcResult<T> = std::result::Result<T, RpcError>;
+
+/// The document that a request operates on.
+enum Input {
+    /// A file on disk, resolved like a file on the command line.
+    File(String),
+    /// The contents of an editor buffer, which may not have been saved.
+    Text { name: String, text: String },
+}
+
+/// Serve requests from stdin, and write responses to stdout.
+pub fn serve_stdio(loader: &mut Loader) -> Result<()> {
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    serve(loader, &mut input, &mut output)
+        .map_err(|err| Error::new(concat! { "Failed to serve on stdio: " err.to_string() }))?;
+    Ok(())
+}
+
+/// Listen on a Unix domain socket, and serve connections one at a time.
+///
+/// The loader is shared between connections, so they all benefit from the
+/// documents loaded earlier. The socket is removed after a `shutdown` request.
+#[cfg(unix)]
+pub fn serve_unix_socket(loader: &mut Loader, path: &std::path::Path) -> Result<()> {
+    use std::os::unix::net::UnixListener;
+
+    let socket_error = |err: io::Error| {
+        Error::new(concat! {
+            "Failed to serve on socket '"
+            Doc::path(path)
+            "': "
+            err.to_string()
+        })
+    };
+    let listener = UnixListener::bind(path).map_err(socket_error)?;
+
+    for stream in listener.incoming() {
+        let stream = stream.map_err(socket_error)?;
+        let mut input = io::BufReader::new(&stream);
+        let mut output = io::BufWriter::new(&stream);
+        // An IO error on one connection, such as a client that disconnects
+        // before reading its response, should not take down the server.
+        if let Ok(Disconnect::Shutdown) = serve(loader, &mut input, &mut output) {
+            break;
+        }
+    }
+
+    std::fs::remove_file(path).map_err(socket_error)?;
+    Ok(())
+}
+
+/// Handle requests from `input` until it ends or a client requests shutdown.
+pub fn serve(
+    loader: &mut Loader,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<Disconnect> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(Disconnect::EndOfInput);
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (response, disconnect) = handle_request(loader, line.trim_end());
+        write_json(output, &response)?;
+        output.flush()?;
+        if let Some(reason) = disconnect {
+            return Ok(reason);
+        }
+    }
+}
+
+/// Write a value as json on a single line.
+fn write_json(output: &mut dyn Write, value: &Value) -> io::Result<()> {
+    let caller = Span::new(DocId(0), 0, 0);
+    // Responses contain only values that can be exported as json, but if one
+    // does not, report it rather than bringing down the server.
+    let doc = crate::fmt_json::format_json(caller, value).map_err(|_| {
+        io::Error::new(io::ErrorKind::Other, "Response cannot be exported as json.")
+    })?;
+    // With an unbounded width, every collection fits on one line.
+    let cfg = pprint::Config { width: u32::MAX };
+    doc.println(&cfg).write_bytes_no_markup(output)
+}
+
+fn string(s: &str) -> Value {
+    Value::String(s.into())
+}
+
+fn dict(fields: Vec<(&str, Value)>) -> Value {
+    let map: SmallDict = fields.into_iter().map(|(k, v)| (string(k), v)).collect();
+    Value::Dict(map)
+}
+
+fn success_response(id: Value, result: Value) -> Value {
+    dict(vec![
+        ("jsonrpc", string("2.0")),
+        ("id", id),
+        ("result", result),
+    ])
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    let error = dict(vec![
+        ("code", Value::Int(error.code)),
+        ("message", string(&error.message)),
+    ]);
+    dict(vec![
+        ("jsonrpc", string("2.0")),
+        ("id", id),
+        ("error", error),
+    ])
+}
+
+/// Handle one request line, return the response, and whether to disconnect.
+fn handle_request(loader: &mut Loader, line: &str) -> (Value, Option<Disconnect>) {
+    // The request is not part of the loader's documents, we parse it as its
+    // own single input, so errors in it can be reported without a loader.
+    let request = match parse_json(DocId(0), line) {
+        Ok(request) => request,
+        Err(err) => {
+            let inputs = [source::Doc {
+                name: "request",
+                data: line,
+                span: Span::new(DocId(0), 0, line.len()),
+                tab_width: 8,
+            }];
+            let error = RpcError {
+                code: PARSE_ERROR,
+                message: render_plain(err.report(&inputs)),
+            };
+            return (error_response(Value::Null, error), None);
+        }
+    };
+    let fields = match &request {
+        Value::Dict(fields) => fields,
+        _ => {
+            let error = RpcError {
+                code: INVALID_REQUEST,
+                message: "The request must be an object.".to_string(),
+            };
+            return (error_response(Value::Null, error), None);
+        }
+    };
+    let id = fields.get(&string("id")).cloned().unwrap_or(Value::Null);
+    let method = match fields.get(&string("method")) {
+        Some(Value::String(method)) => method.clone(),
+        _ => {
+            let error = RpcError {
+                code: INVALID_REQUEST,
+                message: "The request must have a string 'method'.".to_string(),
+            };
+            return (error_response(id, error), None);
+        }
+    };
+    let empty = SmallDict::new();
+    let params = match fields.get(&string("params")) {
+        None => &empty,
+        Some(Value::Dict(params)) => params,
+        Some(_) => {
+            let error = RpcError::invalid_params("The params must be an object.".to_string());
+            return (error_response(id, error), None);
+        }
+    };
+
+    // Files may have changed on disk since the previous request.
+    if loader.num_documents() > MAX_DOCUMENTS {
+        loader.clear_documents();
+    } else {
+        loader.forget_changed_files();
+    }
+
+    let result = match method.as_ref() {
+        "check" => handle_check(loader, params),
+        "evaluate" => handle_evaluate(loader, params, false),
+        "format" => handle_format(loader, params),
+        "query" => handle_evaluate(loader, params, true),
+        "shutdown" => {
+            let response = success_response(id, Value::Null);
+            return (response, Some(Disconnect::Shutdown));
+        }
+        _ => Err(RpcError {
+            code: METHOD_NOT_FOUND,
+            message: format!("Unknown method '{method}'."),
+        }),
+    };
+    let response = match result {
+        Ok(result) => success_response(id, result),
+        Err(error) => error_response(id, error),
+    };
+    (response, None)
+}
+
+fn get_string<'a>(params: &'a SmallDict, key: &str) -> RpcResult<Option<&'a str>> {
+    match params.get(&string(key)) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.as_ref())),
+        Some(_) => Err(RpcError::invalid_params(format!(
+            "Parameter '{key}' must be a string."
+        ))),
+    }
+}
+
+fn get_input(params: &SmallDict) -> RpcResult<Input> {
+    let path = get_string(params, "path")?;
+    let text = get_string(params, "text")?;
+    match (path, text) {
+        (path, Some(text)) => Ok(Input::Text {
+            name: path.unwrap_or("input").to_string(),
+            text: text.to_string(),
+        }),
+        (Some(path), None) => Ok(Input::File(path.to_string())),
+        (None, None) => Err(RpcError::invalid_params(
+            "Expected parameter 'path', 'text', or both.".to_string(),
+        )),
+    }
+}
+
+fn get_width(params: &SmallDict) -> RpcResult<u32> {
+    match params.get(&string("width")) {
+        None | Some(Value::Null) => Ok(80),
+        Some(Value::Int(n)) if *n > 0 && *n <= u32::MAX as i64 => Ok(*n as u32),
+        Some(_) => Err(RpcError::invalid_params(
+            "Parameter 'width' must be a positive integer.".to_string(),
+        )),
+    }
+}
+
+fn get_strict(params: &SmallDict) -> RpcResult<StrictMode> {
+    match get_string(params, "strict")? {
+        None | Some("off") => Ok(StrictMode::Off),
+        Some("warn") => Ok(StrictMode::Warn),
+        Some("error") => Ok(StrictMode::Error),
+        Some(_) => Err(RpcError::invalid_params(
+            "Parameter 'strict' must be one of off, warn, error.".to_string(),
+        )),
+    }
+}
+
+fn get_format(params: &SmallDict) -> RpcResult<OutputFormat> {
+    match get_string(params, "format")? {
+        None => Ok(OutputFormat::Rcl),
+        Some(name) => OutputFormat::from_name(name)
+            .ok_or_else(|| RpcError::invalid_params(format!("Unknown output format '{name}'."))),
+    }
+}
+
+fn load_input(loader: &mut Loader, input: Input) -> Result<DocId> {
+    match input {
+        Input::File(path) => loader.load_cli_target(&Target::File(path)),
+        Input::Text { name, text } => Ok(loader.load_named_string(name, text)),
+    }
+}
+
+/// Render a diagnostic without markup, the way it would print on a terminal.
+fn render_plain(doc: Doc) -> String {
+    let cfg = pprint::Config { width: 80 };
+    doc.println(&cfg).to_string_no_markup()
+}
+
+/// Describe an error as a json object, with its location if it has one.
+fn diagnostic(loader: &Loader, err: Error) -> Value {
+    let origin = err.origin;
+    let inputs = loader.as_inputs();
+    let mut fields = vec![("message", string(&render_plain(err.report(&inputs))))];
+    if let Some(span) = origin {
+        let doc = &inputs[span.doc().0 as usize];
+        let line = doc.data[..span.start()].matches('\n').count() + 1;
+        fields.push(("document", string(doc.name)));
+        fields.push(("line", Value::Int(line as i64)));
+        fields.push(("start", Value::Int(span.start() as i64)));
+        fields.push(("end", Value::Int(span.end() as i64)));
+    }
+    dict(fields)
+}
+
+/// Build the result for a request that may have failed on the document.
+fn outcome(loader: &Loader, result: Result<Vec<(&str, Value)>>, messages: Vec<u8>) -> Value {
+    let messages = string(&String::from_utf8_lossy(&messages));
+    match result {
+        Ok(mut fields) => {
+            fields.push(("ok", Value::Bool(true)));
+            fields.push(("messages", messages));
+            dict(fields)
+        }
+        Err(err) => dict(vec![
+            ("ok", Value::Bool(false)),
+            ("error", diagnostic(loader, *err)),
+            ("messages", messages),
+        ]),
+    }
+}
+
+fn handle_check(loader: &mut Loader, params: &SmallDict) -> RpcResult<Value> {
+    let input = get_input(params)?;
+    let strict = get_strict(params)?;
+    let mut tracer = BufferTracer::new(MarkupMode::None);
+    let result = load_input(loader, input).and_then(|doc| {
+        let mut type_env = loader.type_prelude();
+        let mut value_env = loader.value_prelude();
+        loader.evaluate(&mut type_env, &mut value_env, doc, &mut tracer, strict)?;
+        Ok(Vec::new())
+    });
+    Ok(outcome(loader, result, tracer.take()))
+}
+
+/// Handle `evaluate`, or `query` which evaluates an expression against the input.
+fn handle_evaluate(loader: &mut Loader, params: &SmallDict, is_query: bool) -> RpcResult<Value> {
+    let input = get_input(params)?;
+    let strict = get_strict(params)?;
+    let format = get_format(params)?;
+    let width = get_width(params)?;
+    let query = match get_string(params, "query")? {
+        Some(query) if is_query => Some(query.to_string()),
+        None if is_query => {
+            return Err(RpcError::invalid_params(
+                "Expected parameter 'query'.".to_string(),
+            ))
+        }
+        _ => None,
+    };
+
+    let mut tracer = BufferTracer::new(MarkupMode::None);
+    let result = load_input(loader, input).and_then(|doc| {
+        let mut type_env = loader.type_prelude();
+        let mut value_env = loader.value_prelude();
+        let mut value = loader.evaluate(&mut type_env, &mut value_env, doc, &mut tracer, strict)?;
+        let mut value_span = loader.get_span(doc);
+        if let Some(query) = query {
+            // Like `rcl query`, bind the input to `input` and evaluate the query.
+            let query_doc = loader.load_string(query);
+            type_env.push("input".into(), typecheck::type_any().clone());
+            value_env.push("input".into(), value);
+            value = loader.evaluate(
+                &mut type_env,
+                &mut value_env,
+                query_doc,
+                &mut tracer,
+                strict,
+            )?;
+            value_span = loader.get_span(query_doc);
+        }
+        let out_doc = crate::cmd_eval::format_value(
+            format,
+            Limits::default(),
+            OutputEncoding::Utf8,
+            &SecretOptions::default(),
+            value_span,
+            &value,
+        )?;
+        let output = out_doc
+            .println(&pprint::Config { width })
+            .to_string_no_markup();
+        Ok(vec![("output", string(&output))])
+    });
+    Ok(outcome(loader, result, tracer.take()))
+}
+
+fn handle_format(loader: &mut Loader, params: &SmallDict) -> RpcResult<Value> {
+    let input = get_input(params)?;
+    let width = get_width(params)?;
+    let result = load_input(loader, input).and_then(|doc| {
+        let cst = loader.get_cst(doc)?;
+        let data = loader.get_doc(doc).data;
+        let fmt_doc = crate::fmt_cst::format_expr(data, &cst, FormatOptions::default());
+        let formatted = fmt_doc
+            .println(&pprint::Config { width })
+            .to_string_no_markup();
+        Ok(vec![
+            ("changed", Value::Bool(data != formatted)),
+            ("output", string(&formatted)),
+        ])
+    });
+    Ok(outcome(loader, result, Vec::new()))
+}
@@ -0,0 +1,362 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! A parser for JSON documents.
+//!
+//! This is used by `rcl convert` to turn JSON into RCL values, and by
+//! `rcl serve` to parse requests. JSON is a subset of YAML 1.2, but the YAML
+//! parser is lenient where JSON is strict: it reads `garbage` as a string.
+//! This parser accepts exactly RFC 8259 JSON. Later keys in an object replace
+//! earlier ones with the same name.
+
+use crate::error::{IntoError, Result};
+use crate::number::Float;
+use crate::runtime::Value;
+use crate::small_collection::{SmallDict, SmallList};
+use crate::source::{DocId, Span};
+
+/// Parse a JSON document into a value.
+pub fn parse_json(doc: DocId, input: &str) -> Result<Value> {
+    let mut parser = Parser { doc, input, pos: 0 };
+    parser.skip_whitespace();
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(value),
+        Some(_) => parser
+            .span_here()
+            .error("Unexpected content after the value.")
+            .err(),
+    }
+}
+
+struct Parser<'a> {
+    doc: DocId,
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(self.doc, start, end)
+    }
+
+    /// Return a span of one character at the current position.
+    fn span_here(&self) -> Span {
+        let len = self.input[self.pos..]
+            .chars()
+            .next()
+            .map(|ch| ch.len_utf8())
+            .unwrap_or(0);
+        self.span(self.pos, self.pos + len)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Value::String(self.string()?.into())),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => {
+                for (word, value) in [
+                    ("null", Value::Null),
+                    ("true", Value::Bool(true)),
+                    ("false", Value::Bool(false)),
+                ] {
+                    if self.rest().starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                self.span_here().error("Expected a value here.").err()
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        let digits = |p: &mut Parser| {
+            let n = p.rest().bytes().take_while(|b| b.is_ascii_digit()).count();
+            p.pos += n;
+            n
+        };
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        let n_int = match self.peek() {
+            // Leading zeros are not allowed, a zero stands on its own.
+            Some(b'0') => {
+                self.pos += 1;
+                1
+            }
+            _ => digits(self),
+        };
+        let mut is_float = false;
+        let mut is_valid = n_int > 0;
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            is_float = true;
+            is_valid &= digits(self) > 0;
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'-' | b'+') = self.peek() {
+                self.pos += 1;
+            }
+            is_float = true;
+            is_valid &= digits(self) > 0;
+        }
+
+        let span = self.span(start, self.pos);
+        let text = &self.input[start..self.pos];
+        if !is_valid {
+            return span.error("Invalid number.").err();
+        }
+        if is_float {
+            return match Float::parse(text) {
+                Some(x) => Ok(Value::Float(x)),
+                None => span.error("RCL only supports finite floats.").err(),
+            };
+        }
+        match text.parse::<i64>() {
+            Ok(i) => Ok(Value::Int(i)),
+            Err(..) => span
+                .error("This integer is too large to be represented.")
+                .err(),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let open = self.span_here();
+        self.pos += 1;
+        let mut result = String::new();
+        loop {
+            match self.rest().chars().next() {
+                None => {
+                    return open
+                        .error("Unexpected end of input, string is not closed.")
+                        .err()
+                }
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(result);
+                }
+                Some('\\') => self.escape_sequence(&mut result)?,
+                Some(ch) if (ch as u32) < 0x20 => {
+                    return self
+                        .span_here()
+                        .error("Control characters must be escaped in strings.")
+                        .err()
+                }
+                Some(ch) => {
+                    result.push(ch);
+                    self.pos += ch.len_utf8();
+                }
+            }
+        }
+    }
+
+    /// Parse the four hex digits of a `\u` escape at the current position.
+    fn hex4(&self, at: usize) -> Option<u32> {
+        let hex = self.input.get(at..at + 4)?;
+        u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+    }
+
+    fn escape_sequence(&mut self, result: &mut String) -> Result<()> {
+        let start = self.pos;
+        self.pos += 1;
+        let ch = match self.rest().chars().next() {
+            Some(ch) => ch,
+            None => {
+                return self
+                    .span(start, self.pos)
+                    .error("Unexpected end of input after '\\'.")
+                    .err()
+            }
+        };
+        self.pos += ch.len_utf8();
+        let unescaped = match ch {
+            'b' => '\x08',
+            't' => '\t',
+            'n' => '\n',
+            'f' => '\x0c',
+            'r' => '\r',
+            '"' => '"',
+            '\\' => '\\',
+            '/' => '/',
+            'u' => '\0',
+            _ => {
+                return self
+                    .span(start, self.pos)
+                    .error("Invalid escape sequence.")
+                    .err()
+            }
+        };
+        if ch != 'u' {
+            result.push(unescaped);
+            return Ok(());
+        }
+        let code_point = self.hex4(self.pos);
+        if let Some(high @ 0xd800..=0xdbff) = code_point {
+            // Code points outside the basic multilingual plane are written as
+            // a surrogate pair, the low half follows as another '\u'.
+            let low = match self.rest().get(4..6) {
+                Some("\\u") => self.hex4(self.pos + 6),
+                _ => None,
+            };
+            if let Some(low @ 0xdc00..=0xdfff) = low {
+                let u = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                result.push(char::from_u32(u).expect("Surrogate pairs encode valid code points."));
+                self.pos += 10;
+                return Ok(());
+            }
+        }
+        match code_point.and_then(char::from_u32) {
+            Some(ch) => {
+                result.push(ch);
+                self.pos += 4;
+                Ok(())
+            }
+            None => self
+                .span(start, self.pos)
+                .error("Invalid escape sequence, expected a valid code point in hexadecimal.")
+                .err(),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        let open = self.span_here();
+        self.pos += 1;
+        let mut result = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::List(SmallList::from(result)));
+        }
+        loop {
+            self.skip_whitespace();
+            result.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::List(SmallList::from(result)));
+                }
+                None => {
+                    return open
+                        .error("Unexpected end of input, '[' is not closed.")
+                        .err()
+                }
+                _ => return self.span_here().error("Expected ',' or ']' here.").err(),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        let open = self.span_here();
+        self.pos += 1;
+        let mut result = SmallDict::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Dict(result));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return self.span_here().error("Expected a string key here.").err();
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if self.peek() != Some(b':') {
+                return self.span_here().error("Expected ':' after the key.").err();
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let value = self.value()?;
+            result.insert(Value::String(key.into()), value);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Dict(result));
+                }
+                None => {
+                    return open
+                        .error("Unexpected end of input, '{' is not closed.")
+                        .err()
+                }
+                _ => return self.span_here().error("Expected ',' or '}' here.").err(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_json;
+    use crate::fmt_rcl::format_rcl;
+    use crate::pprint;
+    use crate::source::DocId;
+
+    fn parse(input: &str) -> String {
+        let value = parse_json(DocId(0), input).unwrap();
+        let cfg = pprint::Config { width: 1000 };
+        format_rcl(&value)
+            .println(&cfg)
+            .to_string_no_markup()
+            .trim_end()
+            .to_string()
+    }
+
+    #[test]
+    fn parse_json_handles_documents() {
+        assert_eq!(
+            parse(r#" {"a": [1, -2.5e1, true, null], "b": {}, "c": "é\/\n"} "#),
+            r#"{ a = [1, -25.0, true, null], b = {}, c = "é/\n" }"#,
+        );
+        assert_eq!(parse(r#""😀""#), r#""😀""#);
+        assert_eq!(parse("0"), "0");
+    }
+
+    #[test]
+    fn parse_json_rejects_invalid_documents() {
+        for input in [
+            "garbage",
+            "",
+            "[1, 2,]",
+            "{\"a\": 1,}",
+            "{a: 1}",
+            "01",
+            "1.",
+            "'x'",
+            "\"\t\"",
+            "[1] [2]",
+            "99999999999999999999",
+        ] {
+            assert!(parse_json(DocId(0), input).is_err(), "{input:?}");
+        }
+    }
+}
@@ -0,0 +1,1583 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! A translator from Jsonnet source code to RCL source code.
+//!
+//! This is used by `rcl convert` to ease migrating from Jsonnet. Unlike the
+//! other converters, it does not produce a value, it translates the program.
+//! It handles the common subset of both languages: objects, arrays,
+//! comprehensions, locals, simple functions, conditionals, string formatting,
+//! and a few functions from the Jsonnet standard library. Constructs that have
+//! no counterpart in RCL, such as `self` and hidden fields, are replaced with a
+//! call to `std.fail` with a message that starts with `TODO:`, so evaluating
+//! the result points at the places that need manual attention.
+//!
+//! The output is not formatted, the caller should run it through the RCL
+//! formatter, which also confirms that the result is syntactically valid.
+
+use crate::error::{IntoError, Result};
+use crate::source::{DocId, Span};
+use crate::string::escape_json;
+
+/// Translate a Jsonnet document into RCL source code.
+pub fn jsonnet_to_rcl(doc: DocId, input: &str) -> Result<String> {
+    let mut parser = Parser { doc, input, pos: 0 };
+    let expr = parser.expr()?;
+    parser.skip_trivia()?;
+    if parser.pos < input.len() {
+        return parser
+            .span_here()
+            .error("Expected the end of the document here.")
+            .err();
+    }
+    let (mut result, _) = emit(&expr);
+    result.push('\n');
+    Ok(result)
+}
+
+/// The subset of the Jsonnet syntax tree that we can translate.
+#[derive(Debug)]
+enum Expr {
+    Null,
+    Bool(bool),
+    Int(i64),
+    /// A number literal with a fraction or exponent, with its source text.
+    Float(String),
+    Str(String),
+    Var(String),
+    Array {
+        elements: Vec<Expr>,
+        is_tall: bool,
+    },
+    ArrayComp {
+        body: Box<Expr>,
+        specs: Vec<CompSpec>,
+    },
+    Object {
+        members: Vec<Member>,
+        is_tall: bool,
+    },
+    ObjectComp {
+        locals: Vec<(String, Expr)>,
+        key: Box<Expr>,
+        value: Box<Expr>,
+        specs: Vec<CompSpec>,
+    },
+    Field(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    Local(Vec<(String, Expr)>, Box<Expr>),
+    Function(Vec<String>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Unary(&'static str, Box<Expr>),
+    Import(String),
+    Error(Box<Expr>),
+    Assert(Box<Expr>, Option<Box<Expr>>, Box<Expr>),
+
+    /// A construct that we cannot translate, with its Jsonnet source.
+    Todo(String),
+}
+
+#[derive(Debug)]
+enum CompSpec {
+    For(String, Expr),
+    If(Expr),
+}
+
+#[derive(Debug)]
+enum Member {
+    Local(String, Expr),
+    Assert(Expr, Option<Expr>),
+    Field(Key, Expr),
+}
+
+#[derive(Debug)]
+enum Key {
+    Name(String),
+    Computed(Expr),
+}
+
+const KEYWORDS: [&str; 17] = [
+    "assert",
+    "else",
+    "error",
+    "false",
+    "for",
+    "function",
+    "if",
+    "import",
+    "importbin",
+    "importstr",
+    "in",
+    "local",
+    "null",
+    "self",
+    "super",
+    "tailstrict",
+    "then",
+];
+
+/// Binary operators, from lowest to highest precedence.
+const BINARY_OPERATORS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<=", ">=", "<", ">", "in"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+fn is_ident_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_'
+}
+
+fn is_ident_continue(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+struct Parser<'a> {
+    doc: DocId,
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(self.doc, start, end)
+    }
+
+    /// Return a span of one character at the current position.
+    fn span_here(&self) -> Span {
+        let len = self
+            .rest()
+            .chars()
+            .next()
+            .map(|ch| ch.len_utf8())
+            .unwrap_or(0);
+        self.span(self.pos, self.pos + len)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    /// Return the source text from `start` up to the current position.
+    fn source_since(&self, start: usize) -> String {
+        let text = &self.input[start..self.pos];
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Skip whitespace and comments.
+    fn skip_trivia(&mut self) -> Result<()> {
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("//") || rest.starts_with('#') {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                match comment.find("*/") {
+                    Some(i) => self.pos += i + 4,
+                    None => {
+                        return self
+                            .span(self.pos, self.pos + 2)
+                            .error("Unexpected end of input, comment is not closed.")
+                            .err()
+                    }
+                }
+            } else if let Some(b' ' | b'\t' | b'\r' | b'\n') = self.peek() {
+                self.pos += 1;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Consume the token if it is next, after skipping trivia.
+    fn eat(&mut self, token: &str) -> Result<bool> {
+        self.skip_trivia()?;
+        let is_match = self.rest().starts_with(token)
+            && !(token.bytes().all(is_ident_continue)
+                && self
+                    .input
+                    .as_bytes()
+                    .get(self.pos + token.len())
+                    .map(|b| is_ident_continue(*b))
+                    == Some(true));
+        if is_match {
+            self.pos += token.len();
+        }
+        Ok(is_match)
+    }
+
+    fn expect(&mut self, token: &'static str) -> Result<()> {
+        if self.eat(token)? {
+            Ok(())
+        } else {
+            let message = match token {
+                ")" => "Expected ')' here.",
+                "]" => "Expected ']' here.",
+                "}" => "Expected '}' here.",
+                ":" => "Expected ':' here.",
+                ";" => "Expected ';' here.",
+                "=" => "Expected '=' here.",
+                "in" => "Expected 'in' here.",
+                "then" => "Expected 'then' here.",
+                _ => "Unexpected token here.",
+            };
+            self.span_here().error(message).err()
+        }
+    }
+
+    /// Parse an identifier that is not a keyword.
+    fn ident(&mut self) -> Result<String> {
+        self.skip_trivia()?;
+        let start = self.pos;
+        if self.peek().map(is_ident_start) != Some(true) {
+            return self.span_here().error("Expected an identifier here.").err();
+        }
+        while self.peek().map(is_ident_continue) == Some(true) {
+            self.pos += 1;
+        }
+        let name = &self.input[start..self.pos];
+        if KEYWORDS.contains(&name) {
+            return self
+                .span(start, self.pos)
+                .error("Expected an identifier here, but this is a keyword.")
+                .err();
+        }
+        Ok(name.to_string())
+    }
+
+    /// Whether the next token is the given keyword, without consuming it.
+    fn is_keyword(&mut self, keyword: &str) -> Result<bool> {
+        let start = self.pos;
+        let result = self.eat(keyword)?;
+        self.pos = start;
+        Ok(result)
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        if level == BINARY_OPERATORS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        'outer: loop {
+            self.skip_trivia()?;
+            for op in BINARY_OPERATORS[level] {
+                // Avoid mistaking `||` for `|`, `&&` for `&`, and `|||` for a
+                // binary operator.
+                let rest = self.rest();
+                let is_longer_op = (*op == "|" && rest.starts_with("||"))
+                    || (*op == "&" && rest.starts_with("&&"))
+                    || (*op == "<" && rest.starts_with("<<"))
+                    || (*op == ">" && rest.starts_with(">>"));
+                if is_longer_op {
+                    continue;
+                }
+                if *op == "in" && self.is_keyword("in")? {
+                    // `in super` is handled as an untranslatable construct.
+                    self.eat("in")?;
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::Binary("in", Box::new(lhs), Box::new(rhs));
+                    continue 'outer;
+                }
+                if *op != "in" && rest.starts_with(op) {
+                    self.pos += op.len();
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        self.skip_trivia()?;
+        let start = self.pos;
+        let op = match self.peek() {
+            Some(b'-') => "-",
+            Some(b'+') => "+",
+            Some(b'!') => "!",
+            Some(b'~') => "~",
+            _ => return self.postfix(),
+        };
+        self.pos += 1;
+        let operand = self.unary()?;
+        Ok(match op {
+            "+" => operand,
+            "~" => Expr::Todo(self.source_since(start)),
+            _ => Expr::Unary(op, Box::new(operand)),
+        })
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        self.skip_trivia()?;
+        let start = self.pos;
+        let mut result = self.primary()?;
+        // When any part of a chain cannot be translated, the entire chain
+        // becomes a TODO, for example `self.name`.
+        let mut is_todo = matches!(result, Expr::Todo(..));
+        loop {
+            if self.eat(".")? {
+                let name = self.ident()?;
+                result = Expr::Field(Box::new(result), name);
+            } else if self.eat("[")? {
+                // Slices have no counterpart in RCL.
+                let is_slice = self.eat(":")? || self.eat("::")?;
+                if is_slice {
+                    self.skip_to_closing(b'[', b']')?;
+                    result = Expr::Todo(self.source_since(start));
+                    continue;
+                }
+                let index = self.expr()?;
+                if self.is_keyword(":")? {
+                    self.skip_to_closing(b'[', b']')?;
+                    result = Expr::Todo(self.source_since(start));
+                    continue;
+                }
+                self.expect("]")?;
+                result = Expr::Index(Box::new(result), Box::new(index));
+            } else if self.eat("(")? {
+                match self.call_args()? {
+                    Some(args) => result = Expr::Call(Box::new(result), args),
+                    None => result = Expr::Todo(self.source_since(start)),
+                }
+                self.eat("tailstrict")?;
+            } else if self.is_keyword("{")? {
+                // Object application, `a { ... }` means `a + { ... }`.
+                let rhs = self.primary()?;
+                result = Expr::Binary("+", Box::new(result), Box::new(rhs));
+            } else {
+                break;
+            }
+            is_todo = is_todo || matches!(result, Expr::Todo(..));
+            if is_todo {
+                result = Expr::Todo(self.source_since(start));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Skip ahead to the matching closing bracket, after the opening one.
+    fn skip_to_closing(&mut self, open: u8, close: u8) -> Result<()> {
+        let mut depth = 1;
+        while depth > 0 {
+            self.skip_trivia()?;
+            match self.peek() {
+                None => return self.span_here().error("Unexpected end of input.").err(),
+                Some(b'"' | b'\'') => {
+                    self.string()?;
+                }
+                Some(b) if b == open => {
+                    depth += 1;
+                    self.pos += 1;
+                }
+                Some(b) if b == close => {
+                    depth -= 1;
+                    self.pos += 1;
+                }
+                Some(_) => self.pos += self.span_here().len(),
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse call arguments after the `(`.
+    ///
+    /// Returns `None` when the call uses named arguments, which RCL lacks.
+    fn call_args(&mut self) -> Result<Option<Vec<Expr>>> {
+        let mut args = Vec::new();
+        let mut is_named = false;
+        loop {
+            if self.eat(")")? {
+                return Ok(if is_named { None } else { Some(args) });
+            }
+            self.skip_trivia()?;
+            let before_arg = self.pos;
+            if self.peek().map(is_ident_start) == Some(true) {
+                let name_ok = self.ident().is_ok();
+                if name_ok && self.eat("=")? && !self.rest().starts_with('=') {
+                    is_named = true;
+                } else {
+                    self.pos = before_arg;
+                }
+            }
+            args.push(self.expr()?);
+            if !self.eat(",")? {
+                self.expect(")")?;
+                return Ok(if is_named { None } else { Some(args) });
+            }
+        }
+    }
+
+    /// Parse function parameters after the `(`.
+    ///
+    /// Returns `None` when a parameter has a default value, which RCL lacks.
+    fn params(&mut self) -> Result<Option<Vec<String>>> {
+        let mut params = Vec::new();
+        let mut has_default = false;
+        loop {
+            if self.eat(")")? {
+                return Ok(if has_default { None } else { Some(params) });
+            }
+            params.push(self.ident()?);
+            if self.eat("=")? {
+                self.expr()?;
+                has_default = true;
+            }
+            if !self.eat(",")? {
+                self.expect(")")?;
+                return Ok(if has_default { None } else { Some(params) });
+            }
+        }
+    }
+
+    /// Parse a `name = value` or `name(params) = body` bind.
+    fn bind(&mut self) -> Result<(String, Expr)> {
+        let start = self.pos;
+        let name = self.ident()?;
+        if self.eat("(")? {
+            let params = self.params()?;
+            self.expect("=")?;
+            let body = self.expr()?;
+            let value = match params {
+                Some(params) => Expr::Function(params, Box::new(body)),
+                None => Expr::Todo(self.source_since(start)),
+            };
+            return Ok((name, value));
+        }
+        self.expect("=")?;
+        Ok((name, self.expr()?))
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        self.skip_trivia()?;
+        let start = self.pos;
+        let rest = self.rest();
+        match self.peek() {
+            None => return self.span_here().error("Unexpected end of input.").err(),
+            Some(b'"' | b'\'') => return Ok(Expr::Str(self.string()?)),
+            Some(b'@') if rest[1..].starts_with(['"', '\'']) => {
+                return Ok(Expr::Str(self.verbatim_string()?))
+            }
+            Some(b'|') if rest.starts_with("|||") => return Ok(Expr::Str(self.text_block()?)),
+            Some(b'0'..=b'9') => return self.number(),
+            Some(b'(') => {
+                self.pos += 1;
+                let inner = self.expr()?;
+                self.expect(")")?;
+                return Ok(inner);
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                return self.array(start);
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                return self.object(start);
+            }
+            Some(b'$') => {
+                self.pos += 1;
+                return Ok(Expr::Todo("$".to_string()));
+            }
+            Some(b) if is_ident_start(b) => {}
+            Some(_) => return self.span_here().error("Expected an expression here.").err(),
+        }
+
+        while self.peek().map(is_ident_continue) == Some(true) {
+            self.pos += 1;
+        }
+        let word = &self.input[start..self.pos];
+        match word {
+            "null" => Ok(Expr::Null),
+            "true" => Ok(Expr::Bool(true)),
+            "false" => Ok(Expr::Bool(false)),
+            "self" | "super" => Ok(Expr::Todo(word.to_string())),
+            "local" => {
+                let mut binds = vec![self.bind()?];
+                while self.eat(",")? {
+                    binds.push(self.bind()?);
+                }
+                self.expect(";")?;
+                let body = self.expr()?;
+                Ok(Expr::Local(binds, Box::new(body)))
+            }
+            "if" => {
+                let condition = self.expr()?;
+                self.expect("then")?;
+                let then_body = self.expr()?;
+                let else_body = if self.eat("else")? {
+                    Some(Box::new(self.expr()?))
+                } else {
+                    None
+                };
+                Ok(Expr::If(
+                    Box::new(condition),
+                    Box::new(then_body),
+                    else_body,
+                ))
+            }
+            "function" => {
+                self.expect_open_paren()?;
+                let params = self.params()?;
+                let body = self.expr()?;
+                Ok(match params {
+                    Some(params) => Expr::Function(params, Box::new(body)),
+                    None => Expr::Todo(self.source_since(start)),
+                })
+            }
+            "assert" => {
+                let condition = self.expr()?;
+                let message = if self.eat(":")? {
+                    Some(Box::new(self.expr()?))
+                } else {
+                    None
+                };
+                self.expect(";")?;
+                let body = self.expr()?;
+                Ok(Expr::Assert(Box::new(condition), message, Box::new(body)))
+            }
+            "error" => Ok(Expr::Error(Box::new(self.expr()?))),
+            "import" => {
+                self.skip_trivia()?;
+                Ok(Expr::Import(self.string()?))
+            }
+            "importstr" | "importbin" => {
+                self.skip_trivia()?;
+                self.string()?;
+                Ok(Expr::Todo(self.source_since(start)))
+            }
+            _ if KEYWORDS.contains(&word) => self
+                .span(start, self.pos)
+                .error("Expected an expression here, but this is a keyword.")
+                .err(),
+            _ => Ok(Expr::Var(word.to_string())),
+        }
+    }
+
+    fn expect_open_paren(&mut self) -> Result<()> {
+        if self.eat("(")? {
+            Ok(())
+        } else {
+            self.span_here().error("Expected '(' here.").err()
+        }
+    }
+
+    fn number(&mut self) -> Result<Expr> {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        let mut is_float = false;
+        if self.peek() == Some(b'.') {
+            is_float = true;
+            self.pos += 1;
+            while let Some(b'0'..=b'9') = self.peek() {
+                self.pos += 1;
+            }
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            is_float = true;
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            while let Some(b'0'..=b'9') = self.peek() {
+                self.pos += 1;
+            }
+        }
+        let text = &self.input[start..self.pos];
+        if is_float {
+            return Ok(Expr::Float(text.to_string()));
+        }
+        match text.parse() {
+            Ok(i) => Ok(Expr::Int(i)),
+            Err(..) => self
+                .span(start, self.pos)
+                .error("This integer is too large to be represented.")
+                .err(),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let start = self.pos;
+        let quote = match self.peek() {
+            Some(q @ (b'"' | b'\'')) => q as char,
+            _ => return self.span_here().error("Expected a string here.").err(),
+        };
+        self.pos += 1;
+        let mut result = String::new();
+        loop {
+            let ch = match self.rest().chars().next() {
+                Some(ch) => ch,
+                None => {
+                    return self
+                        .span(start, start + 1)
+                        .error("Unexpected end of input, string is not closed.")
+                        .err()
+                }
+            };
+            self.pos += ch.len_utf8();
+            match ch {
+                _ if ch == quote => return Ok(result),
+                '\\' => self.escape_sequence(&mut result)?,
+                _ => result.push(ch),
+            }
+        }
+    }
+
+    fn escape_sequence(&mut self, result: &mut String) -> Result<()> {
+        let start = self.pos - 1;
+        let ch = match self.rest().chars().next() {
+            Some(ch) => ch,
+            None => {
+                return self
+                    .span(start, self.pos)
+                    .error("Unexpected end of input after '\\'.")
+                    .err()
+            }
+        };
+        self.pos += ch.len_utf8();
+        let unescaped = match ch {
+            '"' => '"',
+            '\'' => '\'',
+            '\\' => '\\',
+            '/' => '/',
+            'b' => '\x08',
+            'f' => '\x0c',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => '\0',
+            _ => {
+                return self
+                    .span(start, self.pos)
+                    .error("Invalid escape sequence.")
+                    .err()
+            }
+        };
+        if ch != 'u' {
+            result.push(unescaped);
+            return Ok(());
+        }
+        let high = self.code_unit();
+        // A surrogate pair encodes a code point outside the Basic Multilingual Plane.
+        let code_point = match high {
+            Some(h @ 0xd800..=0xdbff) if self.rest().starts_with("\\u") => {
+                self.pos += 2;
+                self.code_unit()
+                    .filter(|l| (0xdc00..=0xdfff).contains(l))
+                    .map(|l| 0x10000 + ((h - 0xd800) << 10) + (l - 0xdc00))
+            }
+            h => h,
+        };
+        match code_point.and_then(char::from_u32) {
+            Some(ch) => {
+                result.push(ch);
+                Ok(())
+            }
+            None => self
+                .span(start, self.pos)
+                .error("Invalid escape sequence, expected a Unicode scalar value in hexadecimal.")
+                .err(),
+        }
+    }
+
+    /// Parse the four hexadecimal digits of a `\u` escape sequence.
+    fn code_unit(&mut self) -> Option<u32> {
+        let hex = self.input.get(self.pos..self.pos + 4)?;
+        let unit = u32::from_str_radix(hex, 16).ok()?;
+        self.pos += 4;
+        Some(unit)
+    }
+
+    /// Parse an `@"..."` string, where only a doubled quote is special.
+    fn verbatim_string(&mut self) -> Result<String> {
+        let start = self.pos;
+        self.pos += 1;
+        let quote = self.rest().chars().next().expect("Checked by the caller.");
+        self.pos += 1;
+        let mut result = String::new();
+        loop {
+            let ch = match self.rest().chars().next() {
+                Some(ch) => ch,
+                None => {
+                    return self
+                        .span(start, start + 2)
+                        .error("Unexpected end of input, string is not closed.")
+                        .err()
+                }
+            };
+            self.pos += ch.len_utf8();
+            if ch != quote {
+                result.push(ch);
+            } else if self.rest().starts_with(quote) {
+                result.push(quote);
+                self.pos += 1;
+            } else {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Parse a `|||` text block.
+    fn text_block(&mut self) -> Result<String> {
+        let start = self.pos;
+        self.pos += 3;
+        let is_chomped = self.peek() == Some(b'-');
+        if is_chomped {
+            self.pos += 1;
+        }
+        while let Some(b' ' | b'\t') = self.peek() {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'\n') => self.pos += 1,
+            Some(b'\r') if self.rest().starts_with("\r\n") => self.pos += 2,
+            _ => {
+                return self
+                    .span_here()
+                    .error("Expected a line break after '|||'.")
+                    .err()
+            }
+        }
+
+        // The indentation of the first line applies to the entire block.
+        let first_line = self.rest();
+        let indent_len = first_line.len() - first_line.trim_start_matches([' ', '\t']).len();
+        let indent = &first_line[..indent_len];
+        if indent.is_empty() {
+            return self
+                .span_here()
+                .error("Expected the text block to be indented.")
+                .err();
+        }
+
+        let mut result = String::new();
+        loop {
+            let line_end = self
+                .rest()
+                .find('\n')
+                .map(|i| i + 1)
+                .unwrap_or(self.rest().len());
+            let line = &self.rest()[..line_end];
+            if line.trim_end().is_empty() && !line.is_empty() {
+                result.push('\n');
+                self.pos += line_end;
+            } else if let Some(content) = line.strip_prefix(indent) {
+                result.push_str(&content.replace("\r\n", "\n"));
+                self.pos += line_end;
+            } else {
+                let after = line.trim_start_matches([' ', '\t']);
+                if !after.starts_with("|||") {
+                    return self
+                        .span(start, start + 3)
+                        .error("Expected the text block to be closed by '|||'.")
+                        .err();
+                }
+                self.pos += line.len() - after.len() + 3;
+                if is_chomped {
+                    let len = result.trim_end_matches('\n').len();
+                    result.truncate(len);
+                }
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Parse an array or array comprehension after the `[`.
+    fn array(&mut self, start: usize) -> Result<Expr> {
+        let mut elements = Vec::new();
+        loop {
+            if self.eat("]")? {
+                let is_tall = self.input[start..self.pos].contains('\n');
+                return Ok(Expr::Array { elements, is_tall });
+            }
+            let element = self.expr()?;
+            if elements.is_empty() && self.is_keyword("for")? {
+                let specs = self.comp_specs()?;
+                self.eat(",")?;
+                self.expect("]")?;
+                return Ok(Expr::ArrayComp {
+                    body: Box::new(element),
+                    specs,
+                });
+            }
+            elements.push(element);
+            if !self.eat(",")? {
+                self.expect("]")?;
+                let is_tall = self.input[start..self.pos].contains('\n');
+                return Ok(Expr::Array { elements, is_tall });
+            }
+        }
+    }
+
+    /// Parse the `for` and `if` clauses of a comprehension.
+    fn comp_specs(&mut self) -> Result<Vec<CompSpec>> {
+        let mut specs = Vec::new();
+        loop {
+            if self.eat("for")? {
+                let name = self.ident()?;
+                self.expect("in")?;
+                specs.push(CompSpec::For(name, self.expr()?));
+            } else if self.eat("if")? {
+                specs.push(CompSpec::If(self.expr()?));
+            } else {
+                return Ok(specs);
+            }
+        }
+    }
+
+    /// Parse an object or object comprehension after the `{`.
+    fn object(&mut self, start: usize) -> Result<Expr> {
+        let mut members = Vec::new();
+        loop {
+            if self.eat("}")? {
+                let is_tall = self.input[start..self.pos].contains('\n');
+                return Ok(Expr::Object { members, is_tall });
+            }
+            members.push(self.member()?);
+
+            if self.is_keyword("for")? {
+                return self.object_comp(members);
+            }
+            if !self.eat(",")? {
+                self.expect("}")?;
+                let is_tall = self.input[start..self.pos].contains('\n');
+                return Ok(Expr::Object { members, is_tall });
+            }
+        }
+    }
+
+    fn object_comp(&mut self, members: Vec<Member>) -> Result<Expr> {
+        let specs = self.comp_specs()?;
+        self.expect("}")?;
+
+        let mut locals = Vec::new();
+        let mut field = None;
+        for member in members {
+            match member {
+                Member::Local(name, value) => locals.push((name, value)),
+                Member::Field(Key::Computed(key), value) if field.is_none() => {
+                    field = Some((key, value))
+                }
+                _ => return self
+                    .span_here()
+                    .error(
+                        "An object comprehension must have exactly one field with a computed key.",
+                    )
+                    .err(),
+            }
+        }
+        match field {
+            Some((key, value)) => Ok(Expr::ObjectComp {
+                locals,
+                key: Box::new(key),
+                value: Box::new(value),
+                specs,
+            }),
+            None => self
+                .span_here()
+                .error("An object comprehension must have exactly one field with a computed key.")
+                .err(),
+        }
+    }
+
+    fn member(&mut self) -> Result<Member> {
+        self.skip_trivia()?;
+        let start = self.pos;
+        if self.eat("local")? {
+            let (name, value) = self.bind()?;
+            return Ok(Member::Local(name, value));
+        }
+        if self.eat("assert")? {
+            let condition = self.expr()?;
+            let message = if self.eat(":")? {
+                Some(self.expr()?)
+            } else {
+                None
+            };
+            return Ok(Member::Assert(condition, message));
+        }
+
+        let key = match self.peek() {
+            Some(b'[') => {
+                self.pos += 1;
+                let key = self.expr()?;
+                self.expect("]")?;
+                Key::Computed(key)
+            }
+            Some(b'"' | b'\'') => Key::Name(self.string()?),
+            Some(b'@') => Key::Name(self.verbatim_string()?),
+            Some(b'|') if self.rest().starts_with("|||") => Key::Name(self.text_block()?),
+            _ => {
+                // Field names may be keywords.
+                let name_start = self.pos;
+                while self.peek().map(is_ident_continue) == Some(true) {
+                    self.pos += 1;
+                }
+                if name_start == self.pos {
+                    return self.span_here().error("Expected a field name here.").err();
+                }
+                Key::Name(self.input[name_start..self.pos].to_string())
+            }
+        };
+
+        let params = if self.eat("(")? {
+            Some(self.params()?)
+        } else {
+            None
+        };
+
+        self.skip_trivia()?;
+        let is_plus = self.eat("+")?;
+        let visibility = if self.eat(":::")? {
+            ":::"
+        } else if self.eat("::")? {
+            "::"
+        } else {
+            self.expect(":")?;
+            ":"
+        };
+        let body = self.expr()?;
+
+        let value = match params {
+            _ if is_plus || visibility == "::" => Expr::Todo(self.source_since(start)),
+            Some(Some(params)) => Expr::Function(params, Box::new(body)),
+            Some(None) => Expr::Todo(self.source_since(start)),
+            None => body,
+        };
+        Ok(Member::Field(key, value))
+    }
+}
+
+/// How an emitted expression binds, to decide where parentheses are needed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Prec {
+    /// Literals, variables, collections, calls, and parenthesized expressions.
+    Atom,
+    /// A binary operator application with the given RCL operator.
+    Op(&'static str),
+    /// Expressions that extend as far right as possible, such as `let` and `if`.
+    Open,
+}
+
+fn is_rcl_keyword(name: &str) -> bool {
+    matches!(
+        name,
+        "and"
+            | "assert"
+            | "else"
+            | "false"
+            | "for"
+            | "if"
+            | "import"
+            | "in"
+            | "let"
+            | "match"
+            | "not"
+            | "null"
+            | "or"
+            | "trace"
+            | "true"
+    )
+}
+
+/// Return the RCL name for a Jsonnet variable, renaming RCL keywords.
+fn rcl_ident(name: &str) -> String {
+    if is_rcl_keyword(name) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Whether a string can be used as a record key or field name in RCL.
+fn is_rcl_ident(name: &str) -> bool {
+    let mut bytes = name.bytes();
+    let starts_ok = bytes.next().map(is_ident_start) == Some(true);
+    starts_ok && bytes.all(|b| is_ident_continue(b) || b == b'-') && !is_rcl_keyword(name)
+}
+
+fn emit_string(s: &str) -> String {
+    // Multi-line strings read better as """-strings, but those cannot
+    // represent every string, so only use them for the common case.
+    let use_triple = s.ends_with('\n')
+        && s.len() > 1
+        && !s.starts_with(['\n', ' ', '\t'])
+        && !s.contains("\"\"\"")
+        && !s.contains('\\')
+        && !s.contains(" \n")
+        && !s.contains("\t\n")
+        && s.chars().all(|ch| ch == '\n' || !ch.is_control());
+    if use_triple {
+        return format!("\"\"\"\n{s}\"\"\"");
+    }
+    let mut result = String::from("\"");
+    escape_json(s, &mut result);
+    result.push('"');
+    result
+}
+
+fn emit_todo(source: &str) -> String {
+    let message = format!("TODO: Translate from Jsonnet: {source}");
+    format!("std.fail({})", emit_string(&message))
+}
+
+/// Emit an expression, and wrap it in parentheses unless it is an atom.
+fn emit_atom(expr: &Expr) -> String {
+    match emit(expr) {
+        (s, Prec::Atom) => s,
+        (s, _) => format!("({s})"),
+    }
+}
+
+/// Emit an expression that may not be an `if` or `let` without parentheses.
+///
+/// This applies at the start of collection elements, where those keywords
+/// start a comprehension instead of an expression.
+fn emit_closed(expr: &Expr) -> String {
+    match emit(expr) {
+        (s, Prec::Open) => format!("({s})"),
+        (s, _) => s,
+    }
+}
+
+/// Emit an operand of a binary operator.
+fn emit_operand(expr: &Expr, op: &'static str) -> String {
+    match emit(expr) {
+        (s, Prec::Atom) => s,
+        (s, Prec::Op(inner)) if inner == op => s,
+        (s, _) => format!("({s})"),
+    }
+}
+
+fn emit_comp_specs(specs: &[CompSpec], out: &mut String) {
+    for spec in specs {
+        match spec {
+            CompSpec::For(name, collection) => out.push_str(&format!(
+                "for {} in {}: ",
+                rcl_ident(name),
+                emit_closed(collection)
+            )),
+            CompSpec::If(condition) => out.push_str(&format!("if {}: ", emit_closed(condition))),
+        }
+    }
+}
+
+fn emit_lets(binds: &[(String, Expr)], out: &mut String) {
+    for (name, value) in binds {
+        out.push_str(&format!("let {} = {}; ", rcl_ident(name), emit(value).0));
+    }
+}
+
+fn emit_collection(open: &str, elements: Vec<String>, close: &str, is_tall: bool) -> String {
+    if elements.is_empty() {
+        return format!("{open}{close}");
+    }
+    let mut result = open.to_string();
+    result.push_str(&elements.join(", "));
+    // A trailing comma makes the formatter keep the collection tall.
+    if is_tall {
+        result.push(',');
+    }
+    result.push_str(close);
+    result
+}
+
+/// Collect the parts of a string concatenation with `+`.
+///
+/// Jsonnet's `+` is left-associative, and converts the other operand to a
+/// string when either operand is a string. We only know this statically when
+/// one of the operands is a string literal.
+fn is_string_concat(expr: &Expr) -> bool {
+    match expr {
+        Expr::Str(..) => true,
+        Expr::Binary("+", lhs, rhs) => is_string_concat(lhs) || is_string_concat(rhs),
+        Expr::Binary("%", lhs, _) => matches!(**lhs, Expr::Str(..)),
+        _ => false,
+    }
+}
+
+enum Part<'a> {
+    Text(String),
+    Hole(&'a Expr),
+}
+
+fn string_parts<'a>(expr: &'a Expr, parts: &mut Vec<Part<'a>>) -> bool {
+    match expr {
+        Expr::Str(s) => parts.push(Part::Text(s.clone())),
+        Expr::Binary("+", lhs, rhs) if is_string_concat(expr) => {
+            if is_string_concat(lhs) {
+                if !string_parts(lhs, parts) {
+                    return false;
+                }
+            } else {
+                parts.push(Part::Hole(lhs));
+            }
+            if is_string_concat(rhs) {
+                if !string_parts(rhs, parts) {
+                    return false;
+                }
+            } else {
+                parts.push(Part::Hole(rhs));
+            }
+        }
+        Expr::Binary("%", lhs, rhs) => {
+            let template = match &**lhs {
+                Expr::Str(s) => s,
+                _ => return false,
+            };
+            let args: Vec<&Expr> = match &**rhs {
+                Expr::Array { elements, .. } => elements.iter().collect(),
+                single => vec![single],
+            };
+            return format_parts(template, &args, parts);
+        }
+        other => parts.push(Part::Hole(other)),
+    }
+    true
+}
+
+/// Split a `%`-format string into parts, for the `%s` and `%d` specifiers.
+fn format_parts<'a>(template: &str, args: &[&'a Expr], parts: &mut Vec<Part<'a>>) -> bool {
+    let mut args = args.iter();
+    let mut text = String::new();
+    let mut chars = template.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            text.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => text.push('%'),
+            Some('s' | 'd') => {
+                let arg = match args.next() {
+                    Some(arg) => arg,
+                    None => return false,
+                };
+                parts.push(Part::Text(std::mem::take(&mut text)));
+                parts.push(Part::Hole(arg));
+            }
+            _ => return false,
+        }
+    }
+    parts.push(Part::Text(text));
+    args.next().is_none()
+}
+
+fn emit_format_string(parts: &[Part]) -> String {
+    let mut result = String::from("f\"");
+    for part in parts {
+        match part {
+            Part::Text(s) => {
+                let mut escaped = String::new();
+                escape_json(s, &mut escaped);
+                result.push_str(&escaped.replace('{', "\\{"));
+            }
+            Part::Hole(expr) => {
+                result.push('{');
+                result.push_str(&emit(expr).0);
+                result.push('}');
+            }
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Emit a call to a function from the Jsonnet standard library.
+///
+/// Returns `None` for functions that we do not know how to translate.
+fn emit_std_call(name: &str, args: &[Expr]) -> Option<String> {
+    let result = match (name, args) {
+        ("length", [x]) => format!("{}.len()", emit_atom(x)),
+        ("join", [sep, xs]) => format!("{}.join({})", emit_atom(xs), emit(sep).0),
+        ("map", [f, xs]) => format!("{}.map({})", emit_atom(xs), emit(f).0),
+        ("filter", [f, xs]) => format!("{}.filter({})", emit_atom(xs), emit(f).0),
+        ("flatMap", [f, xs]) => format!("{}.flat_map({})", emit_atom(xs), emit(f).0),
+        ("foldl", [f, xs, init]) => {
+            format!("{}.fold({}, {})", emit_atom(xs), emit(init).0, emit(f).0)
+        }
+        ("objectFields", [x]) => format!("{}.keys()", emit_atom(x)),
+        ("objectHas", [x, k]) => format!("{}.contains({})", emit_atom(x), emit(k).0),
+        ("member", [xs, x]) => format!("{}.contains({})", emit_atom(xs), emit(x).0),
+        ("startsWith", [s, p]) => format!("{}.starts_with({})", emit_atom(s), emit(p).0),
+        ("endsWith", [s, p]) => format!("{}.ends_with({})", emit_atom(s), emit(p).0),
+        ("split", [s, sep]) => format!("{}.split({})", emit_atom(s), emit(sep).0),
+        ("strReplace", [s, a, b]) => {
+            format!("{}.replace({}, {})", emit_atom(s), emit(a).0, emit(b).0)
+        }
+        ("asciiUpper", [s]) => format!("{}.to_uppercase()", emit_atom(s)),
+        ("asciiLower", [s]) => format!("{}.to_lowercase()", emit_atom(s)),
+        ("reverse", [xs]) => format!("{}.reverse()", emit_atom(xs)),
+        ("parseInt", [s]) => format!("{}.parse_int()", emit_atom(s)),
+        ("toString", [x]) => emit_format_string(&[Part::Hole(x)]),
+        // Jsonnet's range includes the upper bound, RCL's excludes it.
+        ("range", [lower, upper]) => format!(
+            "std.range({}, {} + 1)",
+            emit(lower).0,
+            emit_operand(upper, "+")
+        ),
+        ("format", [Expr::Str(template), args]) => {
+            let args: Vec<&Expr> = match args {
+                Expr::Array { elements, .. } => elements.iter().collect(),
+                single => vec![single],
+            };
+            let mut parts = Vec::new();
+            if !format_parts(template, &args, &mut parts) {
+                return None;
+            }
+            emit_format_string(&parts)
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Translate an expression into RCL source code.
+fn emit(expr: &Expr) -> (String, Prec) {
+    match expr {
+        Expr::Null => ("null".to_string(), Prec::Atom),
+        Expr::Bool(b) => (b.to_string(), Prec::Atom),
+        Expr::Int(i) if *i < 0 => (i.to_string(), Prec::Op("unary")),
+        Expr::Int(i) => (i.to_string(), Prec::Atom),
+        Expr::Float(source) => (source.clone(), Prec::Atom),
+        Expr::Str(s) => (emit_string(s), Prec::Atom),
+        Expr::Var(name) => (rcl_ident(name), Prec::Atom),
+        Expr::Todo(source) => (emit_todo(source), Prec::Atom),
+
+        Expr::Array { elements, is_tall } => {
+            let elements = elements.iter().map(emit_closed).collect();
+            (emit_collection("[", elements, "]", *is_tall), Prec::Atom)
+        }
+        Expr::ArrayComp { body, specs } => {
+            let mut result = String::from("[");
+            emit_comp_specs(specs, &mut result);
+            result.push_str(&emit_closed(body));
+            result.push(']');
+            (result, Prec::Atom)
+        }
+        Expr::Object { members, is_tall } => emit_object(members, *is_tall),
+        Expr::ObjectComp {
+            locals,
+            key,
+            value,
+            specs,
+        } => {
+            let mut result = String::from("{");
+            emit_comp_specs(specs, &mut result);
+            emit_lets(locals, &mut result);
+            result.push_str(&format!("{}: {}", emit_atom(key), emit(value).0));
+            result.push('}');
+            (result, Prec::Atom)
+        }
+
+        Expr::Field(base, name) if is_rcl_ident(name) => {
+            (format!("{}.{}", emit_atom(base), name), Prec::Atom)
+        }
+        Expr::Field(base, name) => (
+            format!("{}[{}]", emit_atom(base), emit_string(name)),
+            Prec::Atom,
+        ),
+        Expr::Index(base, index) => (
+            format!("{}[{}]", emit_atom(base), emit(index).0),
+            Prec::Atom,
+        ),
+        Expr::Call(callee, args) => {
+            if let Expr::Field(base, name) = &**callee {
+                if let Expr::Var(std) = &**base {
+                    if std == "std" {
+                        return match emit_std_call(name, args) {
+                            Some(result) => (result, Prec::Atom),
+                            None => (emit_todo(&format!("std.{name}")), Prec::Atom),
+                        };
+                    }
+                }
+            }
+            let args: Vec<String> = args.iter().map(|a| emit(a).0).collect();
+            (
+                format!("{}({})", emit_atom(callee), args.join(", ")),
+                Prec::Atom,
+            )
+        }
+
+        Expr::Local(binds, body) => {
+            let mut result = String::new();
+            emit_lets(binds, &mut result);
+            result.push_str(&emit(body).0);
+            (result, Prec::Open)
+        }
+        Expr::Function(params, body) => {
+            let params: Vec<String> = params.iter().map(|p| rcl_ident(p)).collect();
+            let params = match params.len() {
+                1 => params[0].clone(),
+                _ => format!("({})", params.join(", ")),
+            };
+            (format!("{params} => {}", emit(body).0), Prec::Open)
+        }
+        Expr::If(condition, then_body, else_body) => {
+            let else_body = match else_body {
+                Some(e) => emit(e).0,
+                None => "null".to_string(),
+            };
+            (
+                format!(
+                    "if {}: {} else {}",
+                    emit_closed(condition),
+                    emit_closed(then_body),
+                    else_body
+                ),
+                Prec::Open,
+            )
+        }
+        Expr::Assert(condition, message, body) => {
+            let message = match message {
+                Some(m) => emit(m).0,
+                None => emit_string("Assertion failed."),
+            };
+            (
+                format!(
+                    "assert {}, {}; {}",
+                    emit_closed(condition),
+                    message,
+                    emit(body).0
+                ),
+                Prec::Open,
+            )
+        }
+        Expr::Error(message) => (format!("std.fail({})", emit(message).0), Prec::Atom),
+        Expr::Import(path) => {
+            // Libraries are expected to be translated alongside.
+            let path = match path.rsplit_once('.') {
+                Some((stem, "jsonnet" | "libsonnet")) => format!("{stem}.rcl"),
+                _ => path.clone(),
+            };
+            (format!("import {}", emit_string(&path)), Prec::Open)
+        }
+
+        Expr::Unary(op, operand) => {
+            let op = match *op {
+                "!" => "not ",
+                _ => "-",
+            };
+            (format!("{op}{}", emit_atom(operand)), Prec::Op("unary"))
+        }
+        Expr::Binary(op, lhs, rhs) => emit_binary(expr, op, lhs, rhs),
+    }
+}
+
+fn emit_binary(expr: &Expr, op: &'static str, lhs: &Expr, rhs: &Expr) -> (String, Prec) {
+    if is_string_concat(expr) {
+        let mut parts = Vec::new();
+        if string_parts(expr, &mut parts) {
+            return (emit_format_string(&parts), Prec::Atom);
+        }
+    }
+
+    let rcl_op = match op {
+        "+" => match kind_of(expr) {
+            Kind::Number => "+",
+            Kind::Object => "|",
+            Kind::Array => {
+                // RCL has no list concatenation, but comprehensions can express it.
+                let mut elements = Vec::new();
+                array_elements(expr, &mut elements);
+                return (emit_collection("[", elements, "]", false), Prec::Atom);
+            }
+            // Strings that we could not turn into a format string, and
+            // operands that may be anything, depend on the values at runtime.
+            Kind::String | Kind::Unknown => {
+                let source = format!("{} + {}", emit_atom(lhs), emit_atom(rhs));
+                return (emit_todo(&source), Prec::Atom);
+            }
+        },
+        "in" => {
+            return (
+                format!("{}.contains({})", emit_atom(rhs), emit(lhs).0),
+                Prec::Atom,
+            )
+        }
+        "&&" => "and",
+        "||" => "or",
+        "-" | "*" | "/" | "==" | "!=" | "<" | "<=" | ">" | ">=" => op,
+        _ => {
+            let source = format!("{} {op} {}", emit_atom(lhs), emit_atom(rhs));
+            return (emit_todo(&source), Prec::Atom);
+        }
+    };
+
+    // Comparisons do not chain in RCL, the same operator only chains for the
+    // associative ones.
+    let chains = matches!(rcl_op, "and" | "or" | "|" | "+" | "*");
+    let lhs_str = if chains {
+        emit_operand(lhs, rcl_op)
+    } else {
+        emit_operand(lhs, "")
+    };
+    let rhs_str = emit_operand(rhs, "");
+    (format!("{lhs_str} {rcl_op} {rhs_str}"), Prec::Op(rcl_op))
+}
+
+/// What Jsonnet's `+` does for an expression, as far as the syntax tells.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Kind {
+    Number,
+    String,
+    Array,
+    Object,
+    Unknown,
+}
+
+fn kind_of(expr: &Expr) -> Kind {
+    match expr {
+        Expr::Int(..) | Expr::Float(..) | Expr::Unary("-", ..) => Kind::Number,
+        Expr::Str(..) => Kind::String,
+        Expr::Array { .. } | Expr::ArrayComp { .. } => Kind::Array,
+        Expr::Object { .. } | Expr::ObjectComp { .. } => Kind::Object,
+        Expr::Binary("%", lhs, _) if matches!(**lhs, Expr::Str(..)) => Kind::String,
+        Expr::Binary("-" | "*" | "/" | "%", ..) => Kind::Number,
+        // When one side is a string, Jsonnet converts the other side to a
+        // string. Otherwise the sides have the same type, so one known side is
+        // enough, `i + 1` is an addition.
+        Expr::Binary("+", lhs, rhs) => match (kind_of(lhs), kind_of(rhs)) {
+            (Kind::String, _) | (_, Kind::String) => Kind::String,
+            (Kind::Unknown, known) => known,
+            (known, _) => known,
+        },
+        _ => Kind::Unknown,
+    }
+}
+
+/// Collect the elements of an array concatenation as RCL collection elements.
+fn array_elements(expr: &Expr, out: &mut Vec<String>) {
+    match expr {
+        Expr::Array { elements, .. } => out.extend(elements.iter().map(emit_closed)),
+        Expr::Binary("+", lhs, rhs) if kind_of(expr) == Kind::Array => {
+            array_elements(lhs, out);
+            array_elements(rhs, out);
+        }
+        other => out.push(format!("for x in {}: x", emit_closed(other))),
+    }
+}
+
+fn emit_object(members: &[Member], is_tall: bool) -> (String, Prec) {
+    // Object locals and assertions go before the object. This is valid as long
+    // as they do not refer to `self`, which we cannot translate anyway.
+    let mut prefix = String::new();
+    let mut elements = Vec::new();
+    for member in members {
+        match member {
+            Member::Local(name, value) => {
+                prefix.push_str(&format!("let {} = {}; ", rcl_ident(name), emit(value).0))
+            }
+            Member::Assert(condition, message) => {
+                let message = match message {
+                    Some(m) => emit(m).0,
+                    None => emit_string("Assertion failed."),
+                };
+                prefix.push_str(&format!("assert {}, {}; ", emit_closed(condition), message));
+            }
+            Member::Field(Key::Name(name), value) if is_rcl_ident(name) => {
+                elements.push(format!("{name} = {}", emit(value).0))
+            }
+            Member::Field(Key::Name(name), value) => {
+                elements.push(format!("{}: {}", emit_string(name), emit(value).0))
+            }
+            Member::Field(Key::Computed(key), value) => {
+                elements.push(format!("{}: {}", emit_atom(key), emit(value).0))
+            }
+        }
+    }
+    let object = emit_collection("{", elements, "}", is_tall);
+    if prefix.is_empty() {
+        (object, Prec::Atom)
+    } else {
+        (prefix + &object, Prec::Open)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::jsonnet_to_rcl;
+    use crate::source::DocId;
+
+    fn translate(input: &str) -> String {
+        jsonnet_to_rcl(DocId(0), input)
+            .unwrap()
+            .trim_end()
+            .to_string()
+    }
+
+    #[test]
+    fn jsonnet_to_rcl_translates_objects_and_arrays() {
+        assert_eq!(
+            translate("{ a: 1, 'b-c': [true, null], \"d e\": 'x', [k]: -2 }"),
+            r#"{a = 1, b-c = [true, null], "d e": "x", k: -2}"#,
+        );
+        assert_eq!(
+            translate("[x * 2 for x in xs if x > 1]"),
+            "[for x in xs: if x > 1: x * 2]",
+        );
+        assert_eq!(
+            translate("{ local v = k + 1, [k]: v for k in ks }"),
+            "{for k in ks: let v = k + 1; k: v}",
+        );
+    }
+
+    #[test]
+    fn jsonnet_to_rcl_translates_locals_and_functions() {
+        assert_eq!(
+            translate("local f(x, y) = x * y + 1, z = f(1, 2); if z == 3 then 'ok'"),
+            r#"let f = (x, y) => (x * y) + 1; let z = f(1, 2); if z == 3: "ok" else null"#,
+        );
+        assert_eq!(
+            translate("function(name) 'Hello %s!' % name"),
+            r#"name => f"Hello {name}!""#,
+        );
+        assert_eq!(
+            translate("'a-' + std.join(',', xs) + '-{b}'"),
+            r#"f"a-{xs.join(",")}-\{b}""#,
+        );
+    }
+
+    #[test]
+    fn jsonnet_to_rcl_marks_untranslatable_constructs() {
+        assert_eq!(
+            translate("{ a: self.b, b:: 1 }"),
+            r#"{a = std.fail("TODO: Translate from Jsonnet: self.b"), b = std.fail("TODO: Translate from Jsonnet: b:: 1")}"#,
+        );
+        assert_eq!(
+            translate("[a + b, a - b]"),
+            r#"[std.fail("TODO: Translate from Jsonnet: a + b"), a - b]"#,
+        );
+    }
+
+    #[test]
+    fn jsonnet_to_rcl_translates_plus_by_operand_kind() {
+        assert_eq!(translate("i + 1 + 0.5"), "i + 1 + 0.5");
+        assert_eq!(translate("[1e3, -2.5E-1]"), "[1e3, -2.5E-1]");
+        assert_eq!(
+            translate("a + [1] + b"),
+            "[for x in a: x, 1, for x in b: x]"
+        );
+        assert_eq!(translate("a + { b: 1 } + c"), "a | {b = 1} | c");
+    }
+}
@@ -0,0 +1,721 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! A parser for TOML documents.
+//!
+//! This is used by `rcl convert` to turn TOML into RCL values. It supports
+//! the full TOML 1.0 syntax for keys, tables, arrays of tables, strings,
+//! integers, floats, booleans, arrays, and inline tables. Dates and non-finite
+//! floats have no counterpart in RCL, they are reported as errors.
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::error::{IntoError, Result};
+use crate::number::Float;
+use crate::runtime::Value;
+use crate::small_collection::{SmallDict, SmallList};
+use crate::source::{DocId, Span};
+
+/// Parse a TOML document into a value.
+pub fn parse_toml(doc: DocId, input: &str) -> Result<Value> {
+    let mut parser = Parser {
+        doc,
+        input,
+        pos: 0,
+        root: Table::default(),
+    };
+    parser.document()?;
+    Ok(parser.root.into_value())
+}
+
+/// A table under construction.
+///
+/// Unlike values, tables can be extended after they are first defined, by
+/// dotted keys and table headers, so we keep them mutable until the end.
+#[derive(Default)]
+struct Table {
+    items: BTreeMap<Rc<str>, Item>,
+
+    /// Whether the table was defined by a `[header]` or by a dotted key.
+    ///
+    /// TOML forbids defining the same table twice, but it allows a header for
+    /// a table that was created implicitly as the parent of another header.
+    is_defined: bool,
+}
+
+enum Item {
+    /// A value that cannot be extended: a scalar, array, or inline table.
+    Value(Value),
+    Table(Table),
+    ArrayOfTables(Vec<Table>),
+}
+
+impl Table {
+    fn into_value(self) -> Value {
+        let kvs: SmallDict = self
+            .items
+            .into_iter()
+            .map(|(k, item)| {
+                let v = match item {
+                    Item::Value(v) => v,
+                    Item::Table(t) => t.into_value(),
+                    Item::ArrayOfTables(ts) => {
+                        Value::List(ts.into_iter().map(|t| t.into_value()).collect())
+                    }
+                };
+                (Value::String(k.into()), v)
+            })
+            .collect();
+        Value::Dict(kvs)
+    }
+}
+
+/// A key, with the span of every part, for error reporting.
+type Key = Vec<(Rc<str>, Span)>;
+
+struct Parser<'a> {
+    doc: DocId,
+    input: &'a str,
+    pos: usize,
+    root: Table,
+}
+
+fn is_bare_key_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'-'
+}
+
+impl<'a> Parser<'a> {
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(self.doc, start, end)
+    }
+
+    /// Return a span of one character at the current position.
+    fn span_here(&self) -> Span {
+        let len = self.input[self.pos..]
+            .chars()
+            .next()
+            .map(|ch| ch.len_utf8())
+            .unwrap_or(0);
+        self.span(self.pos, self.pos + len)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn is_eol(&self) -> bool {
+        matches!(self.peek(), None | Some(b'\n')) || self.rest().starts_with("\r\n")
+    }
+
+    /// Skip spaces and tabs, and a comment, but not the line break.
+    fn skip_inline(&mut self) {
+        while let Some(b' ' | b'\t') = self.peek() {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'#') {
+            while !self.is_eol() {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Skip whitespace, comments, and line breaks.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_inline();
+            match self.peek() {
+                Some(b'\n') => self.pos += 1,
+                Some(b'\r') if self.rest().starts_with("\r\n") => self.pos += 2,
+                _ => return,
+            }
+        }
+    }
+
+    fn expect_end_of_line(&mut self) -> Result<()> {
+        self.skip_inline();
+        if self.is_eol() {
+            Ok(())
+        } else {
+            self.span_here()
+                .error("Expected a line break after the value.")
+                .err()
+        }
+    }
+
+    fn document(&mut self) -> Result<()> {
+        // The path of the table that key-value pairs currently go into.
+        let mut current: Key = Vec::new();
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => return Ok(()),
+                Some(b'[') => {
+                    let is_array = self.rest().starts_with("[[");
+                    self.pos += if is_array { 2 } else { 1 };
+                    self.skip_inline();
+                    let key = self.key()?;
+                    self.skip_inline();
+                    let close = if is_array { "]]" } else { "]" };
+                    if !self.rest().starts_with(close) {
+                        return self
+                            .span_here()
+                            .error(if is_array {
+                                "Expected ']]' to close the array of tables header."
+                            } else {
+                                "Expected ']' to close the table header."
+                            })
+                            .err();
+                    }
+                    self.pos += close.len();
+                    self.expect_end_of_line()?;
+                    self.define_table(&key, is_array)?;
+                    current = key;
+                }
+                Some(_) => {
+                    let key = self.key()?;
+                    self.skip_inline();
+                    if self.peek() != Some(b'=') {
+                        return self.span_here().error("Expected '=' after the key.").err();
+                    }
+                    self.pos += 1;
+                    self.skip_inline();
+                    let value = self.value()?;
+                    self.expect_end_of_line()?;
+                    let table = self.resolve_table(&current)?;
+                    Self::insert(table, &key, value)?;
+                }
+            }
+        }
+    }
+
+    /// Return the table at the path, following the last element of arrays.
+    fn resolve_table(&mut self, path: &Key) -> Result<&mut Table> {
+        let mut table = &mut self.root;
+        for (name, span) in path {
+            table = match table.items.get_mut(name) {
+                Some(Item::Table(t)) => t,
+                Some(Item::ArrayOfTables(ts)) => {
+                    ts.last_mut().expect("Arrays of tables are not empty.")
+                }
+                _ => return span.error("This key does not refer to a table.").err(),
+            };
+        }
+        Ok(table)
+    }
+
+    /// Handle a `[table]` or `[[array]]` header.
+    fn define_table(&mut self, key: &Key, is_array: bool) -> Result<()> {
+        let (last, parents) = key.split_last().expect("Keys are not empty.");
+        let mut table = &mut self.root;
+        for (name, span) in parents {
+            let item = table
+                .items
+                .entry(name.clone())
+                .or_insert_with(|| Item::Table(Table::default()));
+            table = match item {
+                Item::Table(t) => t,
+                Item::ArrayOfTables(ts) => ts.last_mut().expect("Arrays of tables are not empty."),
+                Item::Value(..) => {
+                    return span
+                        .error("This key is already defined as a value, it cannot be a table.")
+                        .err()
+                }
+            };
+        }
+        let (name, span) = last;
+        match (table.items.get_mut(name), is_array) {
+            (None, false) => {
+                let new_table = Table {
+                    is_defined: true,
+                    ..Table::default()
+                };
+                table.items.insert(name.clone(), Item::Table(new_table));
+            }
+            (None, true) => {
+                let new_table = Table {
+                    is_defined: true,
+                    ..Table::default()
+                };
+                table
+                    .items
+                    .insert(name.clone(), Item::ArrayOfTables(vec![new_table]));
+            }
+            (Some(Item::Table(t)), false) if !t.is_defined => t.is_defined = true,
+            (Some(Item::ArrayOfTables(ts)), true) => ts.push(Table {
+                is_defined: true,
+                ..Table::default()
+            }),
+            _ => {
+                return span
+                    .error("This table is already defined.")
+                    .with_help("In TOML, every table can be defined only once.")
+                    .err()
+            }
+        }
+        Ok(())
+    }
+
+    /// Insert a value at a dotted key into a table.
+    fn insert(mut table: &mut Table, key: &Key, value: Value) -> Result<()> {
+        let (last, parents) = key.split_last().expect("Keys are not empty.");
+        for (name, span) in parents {
+            let item = table.items.entry(name.clone()).or_insert_with(|| {
+                Item::Table(Table {
+                    is_defined: true,
+                    ..Table::default()
+                })
+            });
+            table =
+                match item {
+                    Item::Table(t) => t,
+                    _ => return span
+                        .error(
+                            "This key is already defined, it cannot be extended with dotted keys.",
+                        )
+                        .err(),
+                };
+        }
+        let (name, span) = last;
+        if table.items.contains_key(name) {
+            return span.error("This key is already defined.").err();
+        }
+        table.items.insert(name.clone(), Item::Value(value));
+        Ok(())
+    }
+
+    /// Parse a possibly dotted key.
+    fn key(&mut self) -> Result<Key> {
+        let mut result = Vec::new();
+        loop {
+            let start = self.pos;
+            let part: Rc<str> = match self.peek() {
+                Some(b'"') => self.basic_string()?.into(),
+                Some(b'\'') => self.literal_string()?.into(),
+                Some(b) if is_bare_key_byte(b) => {
+                    while self.peek().map(is_bare_key_byte) == Some(true) {
+                        self.pos += 1;
+                    }
+                    self.input[start..self.pos].into()
+                }
+                _ => return self.span_here().error("Expected a key here.").err(),
+            };
+            result.push((part, self.span(start, self.pos)));
+            self.skip_inline_spaces();
+            if self.peek() != Some(b'.') {
+                return Ok(result);
+            }
+            self.pos += 1;
+            self.skip_inline_spaces();
+        }
+    }
+
+    fn skip_inline_spaces(&mut self) {
+        while let Some(b' ' | b'\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let rest = self.rest();
+        match self.peek() {
+            Some(b'"') if rest.starts_with("\"\"\"") => {
+                Ok(Value::String(self.ml_basic_string()?.into()))
+            }
+            Some(b'"') => Ok(Value::String(self.basic_string()?.into())),
+            Some(b'\'') if rest.starts_with("'''") => {
+                Ok(Value::String(self.ml_literal_string()?.into()))
+            }
+            Some(b'\'') => Ok(Value::String(self.literal_string()?.into())),
+            Some(b'[') => self.array(),
+            Some(b'{') => self.inline_table(),
+            Some(b't') if rest.starts_with("true") => {
+                self.pos += 4;
+                Ok(Value::Bool(true))
+            }
+            Some(b'f') if rest.starts_with("false") => {
+                self.pos += 5;
+                Ok(Value::Bool(false))
+            }
+            Some(b'+' | b'-' | b'0'..=b'9' | b'i' | b'n') => self.number(),
+            _ => self.span_here().error("Expected a value here.").err(),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        while let Some(b) = self.peek() {
+            if b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'_' | b'.' | b':') {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        let text = &self.input[start..self.pos];
+        let span = self.span(start, self.pos);
+
+        let unsigned = text.trim_start_matches(['+', '-']);
+        let is_negative = text.starts_with('-');
+        let (digits, radix) = match unsigned.get(..2) {
+            Some("0x") => (&unsigned[2..], 16),
+            Some("0o") => (&unsigned[2..], 8),
+            Some("0b") => (&unsigned[2..], 2),
+            _ => (unsigned, 10),
+        };
+        let digits = digits.replace('_', "");
+        let is_int = !digits.is_empty()
+            && digits.chars().all(|ch| ch.is_digit(radix))
+            && (radix == 10 || unsigned.len() == text.len())
+            && text.len() - unsigned.len() <= 1;
+
+        if is_int {
+            let magnitude = match i64::from_str_radix(&digits, radix) {
+                Ok(n) => n,
+                // The most negative number does not fit as a positive one.
+                Err(..) if is_negative && digits == "9223372036854775808" => {
+                    return Ok(Value::Int(i64::MIN))
+                }
+                Err(..) => {
+                    return span
+                        .error("This integer is too large to be represented.")
+                        .err()
+                }
+            };
+            return Ok(Value::Int(if is_negative { -magnitude } else { magnitude }));
+        }
+
+        let is_date = text.len() >= 5
+            && text.as_bytes()[..4].iter().all(|b| b.is_ascii_digit())
+            && text.as_bytes()[4] == b'-'
+            || text.contains(':');
+        if is_date {
+            return span
+                .error("RCL does not support TOML dates and times.")
+                .with_help("Enclose the date in quotes to convert it as a string.")
+                .err();
+        }
+
+        let is_float = matches!(unsigned, "inf" | "nan")
+            || unsigned.bytes().any(|b| b.is_ascii_digit())
+                && unsigned.bytes().all(|b| {
+                    b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'_' | b'+' | b'-')
+                });
+        if is_float {
+            return match Float::parse(&text.replace('_', "")) {
+                Some(x) => Ok(Value::Float(x)),
+                None => span.error("RCL only supports finite floats.").err(),
+            };
+        }
+
+        span.error("Expected a value here.").err()
+    }
+
+    fn basic_string(&mut self) -> Result<String> {
+        let open = self.span_here();
+        self.pos += 1;
+        let mut result = String::new();
+        loop {
+            match self.rest().chars().next() {
+                None | Some('\n') => {
+                    return open
+                        .error("Unexpected end of line, string is not closed.")
+                        .err()
+                }
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(result);
+                }
+                Some('\\') => self.escape_sequence(&mut result)?,
+                Some(ch) => {
+                    result.push(ch);
+                    self.pos += ch.len_utf8();
+                }
+            }
+        }
+    }
+
+    fn ml_basic_string(&mut self) -> Result<String> {
+        let open = self.span(self.pos, self.pos + 3);
+        self.pos += 3;
+        self.skip_first_newline();
+        let mut result = String::new();
+        loop {
+            if self.rest().starts_with("\"\"\"") {
+                // Up to two quotes can precede the closing delimiter.
+                let n_quotes = self
+                    .rest()
+                    .bytes()
+                    .take_while(|b| *b == b'"')
+                    .count()
+                    .min(5);
+                for _ in 3..n_quotes {
+                    result.push('"');
+                }
+                self.pos += n_quotes;
+                return Ok(result);
+            }
+            match self.rest().chars().next() {
+                None => {
+                    return open
+                        .error("Unexpected end of input, string is not closed.")
+                        .err()
+                }
+                Some('\\') => {
+                    // A backslash at the end of a line trims the line break and
+                    // the whitespace that follows it.
+                    let after = self.rest()[1..].trim_start_matches([' ', '\t']);
+                    if after.starts_with('\n') || after.starts_with("\r\n") {
+                        let trimmed = self.rest()[1..].trim_start_matches([' ', '\t', '\r', '\n']);
+                        self.pos = self.input.len() - trimmed.len();
+                    } else {
+                        self.escape_sequence(&mut result)?;
+                    }
+                }
+                Some(ch) => {
+                    result.push(ch);
+                    self.pos += ch.len_utf8();
+                }
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String> {
+        let open = self.span_here();
+        self.pos += 1;
+        match self.rest().find(['\'', '\n']) {
+            Some(i) if self.rest().as_bytes()[i] == b'\'' => {
+                let result = self.rest()[..i].to_string();
+                self.pos += i + 1;
+                Ok(result)
+            }
+            _ => open
+                .error("Unexpected end of line, string is not closed.")
+                .err(),
+        }
+    }
+
+    fn ml_literal_string(&mut self) -> Result<String> {
+        let open = self.span(self.pos, self.pos + 3);
+        self.pos += 3;
+        self.skip_first_newline();
+        match self.rest().find("'''") {
+            Some(i) => {
+                // Up to two quotes can precede the closing delimiter.
+                let n_extra = self.rest()[i + 3..]
+                    .bytes()
+                    .take_while(|b| *b == b'\'')
+                    .count()
+                    .min(2);
+                let result = self.rest()[..i + n_extra].to_string();
+                self.pos += i + 3 + n_extra;
+                Ok(result)
+            }
+            None => open
+                .error("Unexpected end of input, string is not closed.")
+                .err(),
+        }
+    }
+
+    /// A line break directly after the opening delimiter is not part of the string.
+    fn skip_first_newline(&mut self) {
+        if self.rest().starts_with('\n') {
+            self.pos += 1;
+        } else if self.rest().starts_with("\r\n") {
+            self.pos += 2;
+        }
+    }
+
+    fn escape_sequence(&mut self, result: &mut String) -> Result<()> {
+        let start = self.pos;
+        self.pos += 1;
+        let ch = match self.rest().chars().next() {
+            Some(ch) => ch,
+            None => {
+                return self
+                    .span(start, self.pos)
+                    .error("Unexpected end of input after '\\'.")
+                    .err()
+            }
+        };
+        self.pos += ch.len_utf8();
+        let unescaped = match ch {
+            'b' => '\x08',
+            't' => '\t',
+            'n' => '\n',
+            'f' => '\x0c',
+            'r' => '\r',
+            '"' => '"',
+            '\\' => '\\',
+            'u' | 'U' => '\0',
+            _ => {
+                return self
+                    .span(start, self.pos)
+                    .error("Invalid escape sequence.")
+                    .err()
+            }
+        };
+        let n_hex = match ch {
+            'u' => 4,
+            'U' => 8,
+            _ => {
+                result.push(unescaped);
+                return Ok(());
+            }
+        };
+        let hex = self.input.get(self.pos..self.pos + n_hex).unwrap_or("");
+        let code_point = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == n_hex);
+        match code_point.and_then(char::from_u32) {
+            Some(ch) => {
+                result.push(ch);
+                self.pos += n_hex;
+                Ok(())
+            }
+            None => self
+                .span(start, self.pos)
+                .error("Invalid escape sequence, expected a Unicode scalar value in hexadecimal.")
+                .err(),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        let open = self.span_here();
+        self.pos += 1;
+        let mut result = Vec::new();
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::List(SmallList::from(result)));
+                }
+                None => {
+                    return open
+                        .error("Unexpected end of input, '[' is not closed.")
+                        .err()
+                }
+                _ => {}
+            }
+            result.push(self.value()?);
+            self.skip_blank();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => continue,
+                _ => return self.span_here().error("Expected ',' or ']' here.").err(),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value> {
+        self.pos += 1;
+        let mut table = Table::default();
+        self.skip_inline_spaces();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(table.into_value());
+        }
+        loop {
+            self.skip_inline_spaces();
+            let key = self.key()?;
+            self.skip_inline_spaces();
+            if self.peek() != Some(b'=') {
+                return self.span_here().error("Expected '=' after the key.").err();
+            }
+            self.pos += 1;
+            self.skip_inline_spaces();
+            let value = self.value()?;
+            Self::insert(&mut table, &key, value)?;
+            self.skip_inline_spaces();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(table.into_value());
+                }
+                _ => return self
+                    .span_here()
+                    .error("Expected ',' or '}' here.")
+                    .with_help(
+                        "Inline tables must be on a single line, and cannot have a trailing comma.",
+                    )
+                    .err(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_toml;
+    use crate::fmt_rcl::format_rcl;
+    use crate::pprint;
+    use crate::source::DocId;
+
+    fn parse(input: &str) -> String {
+        let value = parse_toml(DocId(0), input).unwrap();
+        let cfg = pprint::Config { width: 1000 };
+        format_rcl(&value)
+            .println(&cfg)
+            .to_string_no_markup()
+            .trim_end()
+            .to_string()
+    }
+
+    #[test]
+    fn parse_toml_handles_tables() {
+        let input = r#"
+title = "x" # Comment.
+a.b = 1
+
+[server]
+port = 8_080
+"quoted key" = 'C:\path'
+
+[[users]]
+name = "a"
+[[users]]
+name = "b"
+tags = ["x", 'y',]
+[users.extra]
+flags = { debug = true, level = -16, mask = 0x1f }
+"#;
+        assert_eq!(
+            parse(input),
+            r#"{ a = { b = 1 }, server = { port = 8080, "quoted key": "C:\\path" }, title = "x", users = [{ name = "a" }, { extra = { flags = { debug = true, level = -16, mask = 31 } }, name = "b", tags = ["x", "y"] }] }"#,
+        );
+    }
+
+    #[test]
+    fn parse_toml_handles_multiline_strings() {
+        assert_eq!(parse("a = \"\"\"\nx\n\"y\"\"\"\""), r#"{ a = "x\n\"y\"" }"#);
+        assert_eq!(parse("a = \"\"\"x \\\n   y\"\"\""), r#"{ a = "x y" }"#);
+        assert_eq!(parse("a = '''\nx\\n'''"), r#"{ a = "x\\n" }"#);
+        assert_eq!(parse("a = \"\\u00e9\\t\""), r#"{ a = "é\t" }"#);
+    }
+
+    #[test]
+    fn parse_toml_preserves_floats() {
+        assert_eq!(
+            parse("a = 1.0\nb = -2_000.5\nc = 6e-3"),
+            "{ a = 1.0, b = -2000.5, c = 0.006 }"
+        );
+    }
+
+    #[test]
+    fn parse_toml_rejects_invalid_documents() {
+        assert!(parse_toml(DocId(0), "a = 1\na = 2").is_err());
+        assert!(parse_toml(DocId(0), "[a]\n[a]").is_err());
+        assert!(parse_toml(DocId(0), "a = inf").is_err());
+        assert!(parse_toml(DocId(0), "a = 1979-05-27").is_err());
+        assert!(parse_toml(DocId(0), "a = 1 b = 2").is_err());
+    }
+}
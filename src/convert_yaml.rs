@@ -0,0 +1,1028 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! A parser for the subset of YAML that is commonly used for configuration.
+//!
+//! This is used by `rcl convert` to turn YAML and json into RCL values. It
+//! supports block mappings and sequences, flow collections (`[...]` and
+//! `{...}`, which makes it accept json as well), plain, single-quoted, and
+//! double-quoted scalars, and literal (`|`) and folded (`>`) block scalars.
+//! Plain scalars are resolved with the YAML 1.2 core schema, so `on` and `no`
+//! are strings, not booleans.
+//!
+//! Features that have no counterpart in RCL (infinities, anchors, aliases, tags)
+//! or that are rarely used in configuration (multi-line plain scalars, complex
+//! keys, multiple documents) are reported as errors.
+
+use crate::error::{IntoError, Result};
+use crate::fmt_rcl::format_rcl;
+use crate::number::Float;
+use crate::pprint::concat;
+use crate::runtime::Value;
+use crate::small_collection::{SmallDict, SmallList};
+use crate::source::{DocId, Span};
+
+/// Parse a YAML document into a value.
+pub fn parse_yaml(doc: DocId, input: &str) -> Result<Value> {
+    let mut parser = Parser { doc, input, pos: 0 };
+    parser.document()
+}
+
+/// Whether a node is a value in a mapping, or an item in a sequence.
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Context {
+    Mapping,
+    Sequence,
+}
+
+struct Parser<'a> {
+    doc: DocId,
+    input: &'a str,
+    pos: usize,
+}
+
+/// Whether the byte can occur at the start of a plain scalar.
+fn is_plain_start(b: u8) -> bool {
+    !matches!(
+        b,
+        b'[' | b']'
+            | b'{'
+            | b'}'
+            | b','
+            | b'#'
+            | b'&'
+            | b'*'
+            | b'!'
+            | b'|'
+            | b'>'
+            | b'\''
+            | b'"'
+            | b'%'
+            | b'@'
+            | b'`'
+    )
+}
+
+/// Return whether the plain scalar looks like a float in the core schema.
+fn is_float(s: &str) -> bool {
+    let s = s.strip_prefix(['-', '+']).unwrap_or(s);
+    if matches!(s, ".inf" | ".Inf" | ".INF" | ".nan" | ".NaN" | ".NAN") {
+        return true;
+    }
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let (int_part, frac_part) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], Some(&mantissa[i + 1..])),
+        None => (mantissa, None),
+    };
+    let is_digits = |x: &str| x.bytes().all(|b| b.is_ascii_digit());
+    let mantissa_ok = match frac_part {
+        Some(frac) => {
+            is_digits(int_part) && is_digits(frac) && !(int_part.is_empty() && frac.is_empty())
+        }
+        None => !int_part.is_empty() && is_digits(int_part),
+    };
+    let exponent_ok = match exponent {
+        Some(e) => {
+            let e = e.strip_prefix(['-', '+']).unwrap_or(e);
+            !e.is_empty() && is_digits(e)
+        }
+        None => true,
+    };
+    // A plain integer is not a float, it needs a fraction or exponent.
+    mantissa_ok && exponent_ok && (frac_part.is_some() || exponent.is_some())
+}
+
+impl<'a> Parser<'a> {
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(self.doc, start, end)
+    }
+
+    /// Return a span of one character at the current position.
+    fn span_here(&self) -> Span {
+        let len = self.input[self.pos..]
+            .chars()
+            .next()
+            .map(|ch| ch.len_utf8())
+            .unwrap_or(0);
+        self.span(self.pos, self.pos + len)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.input.as_bytes().get(self.pos + offset).copied()
+    }
+
+    fn is_eof(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn is_eol(&self) -> bool {
+        matches!(self.peek(), None | Some(b'\n' | b'\r'))
+    }
+
+    /// Whether the byte at the offset is whitespace or the end of a line.
+    fn is_blank_at(&self, offset: usize) -> bool {
+        matches!(
+            self.peek_at(offset),
+            None | Some(b' ' | b'\t' | b'\n' | b'\r')
+        )
+    }
+
+    /// The column of the current position, counted in bytes.
+    fn column(&self) -> usize {
+        match self.input[..self.pos].rfind('\n') {
+            Some(i) => self.pos - i - 1,
+            None => self.pos,
+        }
+    }
+
+    /// Whether we are at a `-` that starts a sequence item.
+    fn is_sequence_item(&self) -> bool {
+        self.peek() == Some(b'-') && self.is_blank_at(1)
+    }
+
+    /// Whether we are at a document marker `---` or `...` at the start of a line.
+    fn is_document_marker(&self) -> bool {
+        let rest = &self.input[self.pos..];
+        self.column() == 0
+            && (rest.starts_with("---") || rest.starts_with("..."))
+            && self.is_blank_at(3)
+    }
+
+    /// Skip spaces and tabs, and a comment, but not the line break.
+    fn skip_inline(&mut self) {
+        while let Some(b' ' | b'\t') = self.peek() {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'#') {
+            while !self.is_eol() {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Skip a line break, if there is one.
+    fn skip_line_break(&mut self) {
+        if self.peek() == Some(b'\r') {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'\n') {
+            self.pos += 1;
+        }
+    }
+
+    /// Skip whitespace, comments, and line breaks, up to the next content.
+    fn skip_blank_lines(&mut self) -> Result<()> {
+        loop {
+            let line_start = self.pos;
+            self.skip_inline();
+            if self.is_eof() {
+                return Ok(());
+            }
+            if self.is_eol() {
+                self.skip_line_break();
+                continue;
+            }
+            // Tabs are not allowed for indentation, and they would throw off
+            // our column computation, so reject them early.
+            if self.input[line_start..self.pos].contains('\t')
+                && self.column() == self.pos - line_start
+            {
+                return self
+                    .span(line_start, self.pos)
+                    .error("Tabs cannot be used for indentation in YAML.")
+                    .err();
+            }
+            return Ok(());
+        }
+    }
+
+    fn document(&mut self) -> Result<Value> {
+        self.skip_blank_lines()?;
+        if self.peek() == Some(b'%') {
+            return self
+                .span_here()
+                .error("YAML directives are not supported.")
+                .err();
+        }
+        if self.is_document_marker() && self.input[self.pos..].starts_with("---") {
+            self.pos += 3;
+            self.skip_inline();
+            if !self.is_eol() {
+                return self
+                    .span_here()
+                    .error("Expected a line break after the document start marker.")
+                    .err();
+            }
+            self.skip_blank_lines()?;
+        }
+
+        let value = if self.is_eof() || self.is_document_marker() {
+            Value::Null
+        } else {
+            self.node(self.column(), Context::Mapping)?
+        };
+
+        self.skip_blank_lines()?;
+        if self.is_document_marker() && self.input[self.pos..].starts_with("...") {
+            self.pos += 3;
+            self.skip_blank_lines()?;
+        }
+        if self.is_document_marker() {
+            return self
+                .span(self.pos, self.pos + 3)
+                .error("Streams with multiple YAML documents are not supported.")
+                .with_help("Split the stream into separate files, and convert them one by one.")
+                .err();
+        }
+        if !self.is_eof() {
+            return self
+                .span_here()
+                .error("Expected the end of the document here.")
+                .with_help("This may be caused by inconsistent indentation.")
+                .err();
+        }
+        Ok(value)
+    }
+
+    /// Parse a node that starts at the current position, at the given column.
+    ///
+    /// The node can be a block collection, in which case the column is its
+    /// indentation, or a flow node or scalar that must end on this line.
+    fn node(&mut self, indent: usize, context: Context) -> Result<Value> {
+        if self.is_sequence_item() {
+            return self.block_sequence(indent);
+        }
+        if self.is_mapping_key()? {
+            return self.block_mapping(indent);
+        }
+        let value = self.block_scalar_or_flow_node(indent)?;
+        self.expect_end_of_line(context)?;
+        Ok(value)
+    }
+
+    /// Parse a flow node, or a plain scalar that may span multiple lines.
+    ///
+    /// Continuation lines of a plain scalar must start at `min_column` or
+    /// deeper. Like in YAML, a single line break folds into a space, and every
+    /// additional blank line becomes a newline.
+    fn block_scalar_or_flow_node(&mut self, min_column: usize) -> Result<Value> {
+        match self.peek() {
+            Some(b) if is_plain_start(b) => {}
+            _ => return self.flow_node(),
+        }
+        let (start, end) = self.plain_scalar_span(false);
+        let mut text = String::new();
+        loop {
+            let line_end = self.pos;
+            self.skip_inline_spaces();
+            if self.is_eof() || !self.is_eol() {
+                self.pos = line_end;
+                break;
+            }
+            let mut n_breaks = 0;
+            while self.is_eol() && !self.is_eof() {
+                self.skip_line_break();
+                self.skip_inline_spaces();
+                n_breaks += 1;
+            }
+            let is_continuation = !self.is_eof()
+                && self.column() >= min_column
+                && self.peek() != Some(b'#')
+                && !self.is_document_marker();
+            if !is_continuation {
+                self.pos = line_end;
+                break;
+            }
+            if text.is_empty() {
+                text.push_str(&self.input[start..end]);
+            }
+            match n_breaks {
+                1 => text.push(' '),
+                n => text.extend(std::iter::repeat('\n').take(n - 1)),
+            }
+            let (line_start, line_end) = self.plain_scalar_span(false);
+            text.push_str(&self.input[line_start..line_end]);
+        }
+        if text.is_empty() {
+            self.resolve_plain(start, end)
+        } else {
+            Ok(Value::String(text.into()))
+        }
+    }
+
+    /// Check that nothing but a comment follows on this line.
+    fn expect_end_of_line(&mut self, context: Context) -> Result<()> {
+        self.skip_inline();
+        if self.is_eol() {
+            return Ok(());
+        }
+        let help = match context {
+            Context::Mapping if self.peek() == Some(b':') => {
+                "A mapping cannot start on the same line as the key of its parent mapping."
+            }
+            _ => "If this was meant to be a single string, enclose it in quotes.",
+        };
+        self.span_here()
+            .error("Unexpected content after the value.")
+            .with_help(help)
+            .err()
+    }
+
+    /// Return whether the current line, from the current position, is `key:`.
+    fn is_mapping_key(&mut self) -> Result<bool> {
+        let start = self.pos;
+        let result = match self.peek() {
+            Some(b'"' | b'\'') => {
+                self.quoted_scalar()?;
+                self.skip_inline_spaces();
+                self.peek() == Some(b':') && self.is_blank_at(1)
+            }
+            Some(b) if is_plain_start(b) => {
+                self.plain_scalar_span(false);
+                self.peek() == Some(b':') && self.is_blank_at(1)
+            }
+            _ => false,
+        };
+        self.pos = start;
+        Ok(result)
+    }
+
+    fn skip_inline_spaces(&mut self) {
+        while let Some(b' ' | b'\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn block_mapping(&mut self, indent: usize) -> Result<Value> {
+        let mut result = SmallDict::new();
+        loop {
+            let key_start = self.pos;
+            if self.peek() == Some(b'?') && self.is_blank_at(1) {
+                return self
+                    .span_here()
+                    .error("Complex mapping keys are not supported.")
+                    .err();
+            }
+            let key = match self.peek() {
+                Some(b'"' | b'\'') => Value::String(self.quoted_scalar()?.into()),
+                _ => {
+                    let (start, end) = self.plain_scalar_span(false);
+                    self.resolve_plain(start, end)?
+                }
+            };
+            let key_span = self.span(key_start, self.pos);
+            self.skip_inline_spaces();
+            if !(self.peek() == Some(b':') && self.is_blank_at(1)) {
+                return self
+                    .span_here()
+                    .error("Expected ':' after the mapping key.")
+                    .err();
+            }
+            self.pos += 1;
+            let value = self.value_after_indicator(indent, Context::Mapping)?;
+            if result.contains_key(&key) {
+                return key_span
+                    .error(concat! {
+                        "Duplicate key " format_rcl(&key).into_owned() " in mapping."
+                    })
+                    .err();
+            }
+            result.insert(key, value);
+
+            self.skip_blank_lines()?;
+            if self.is_eof() || self.is_document_marker() {
+                break;
+            }
+            let column = self.column();
+            if column < indent || (column == indent && self.is_sequence_item()) {
+                break;
+            }
+            if column > indent {
+                return self
+                    .span_here()
+                    .error("Unexpected indentation, expected a key at the same level as the previous one.")
+                    .err();
+            }
+            if !self.is_mapping_key()? {
+                return self.span_here().error("Expected a mapping key here.").err();
+            }
+        }
+        Ok(Value::Dict(result))
+    }
+
+    fn block_sequence(&mut self, indent: usize) -> Result<Value> {
+        let mut result = Vec::new();
+        loop {
+            debug_assert!(self.is_sequence_item());
+            self.pos += 1;
+            result.push(self.value_after_indicator(indent, Context::Sequence)?);
+
+            self.skip_blank_lines()?;
+            if self.is_eof() || self.is_document_marker() {
+                break;
+            }
+            let column = self.column();
+            if column < indent {
+                break;
+            }
+            if column > indent {
+                return self
+                    .span_here()
+                    .error("Unexpected indentation, expected an item at the same level as the previous one.")
+                    .err();
+            }
+            if !self.is_sequence_item() {
+                // This can be the next key of a mapping that contains this
+                // sequence at the same indentation.
+                break;
+            }
+        }
+        Ok(Value::List(SmallList::from(result)))
+    }
+
+    /// Parse the value after a `key:` or `-` indicator.
+    ///
+    /// The value can be on the same line, or on the next lines, indented
+    /// deeper than `indent`.
+    fn value_after_indicator(&mut self, indent: usize, context: Context) -> Result<Value> {
+        self.skip_inline();
+
+        if self.is_eol() {
+            self.skip_blank_lines()?;
+            if self.is_eof() || self.is_document_marker() {
+                return Ok(Value::Null);
+            }
+            let column = self.column();
+            // A sequence that is the value of a mapping key may be at the same
+            // indentation as the key.
+            let is_nested = column > indent
+                || (column == indent && context == Context::Mapping && self.is_sequence_item());
+            return if is_nested {
+                self.node(column, context)
+            } else {
+                Ok(Value::Null)
+            };
+        }
+
+        match self.peek() {
+            Some(b'|' | b'>') => self.block_scalar(indent),
+            // In a sequence, the item can be a compact mapping or sequence,
+            // for example `- name: x`, with following keys aligned to `name`.
+            _ if context == Context::Sequence => self.node(self.column(), context),
+            _ => {
+                let value = self.block_scalar_or_flow_node(indent + 1)?;
+                self.expect_end_of_line(context)?;
+                Ok(value)
+            }
+        }
+    }
+
+    /// Parse a scalar or flow collection.
+    fn flow_node(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some(b'[') => self.flow_sequence(),
+            Some(b'{') => self.flow_mapping(),
+            Some(b'"' | b'\'') => Ok(Value::String(self.quoted_scalar()?.into())),
+            Some(b'&' | b'*') => self
+                .span_here()
+                .error("YAML anchors and aliases are not supported.")
+                .with_help("Repeat the value instead, RCL can then use a let-binding for it.")
+                .err(),
+            Some(b'!') => self.span_here().error("YAML tags are not supported.").err(),
+            Some(b'|' | b'>') => self
+                .span_here()
+                .error("Block scalars are only supported as values of mappings or sequences.")
+                .err(),
+            Some(b) if is_plain_start(b) => {
+                let (start, end) = self.plain_scalar_span(false);
+                self.resolve_plain(start, end)
+            }
+            _ => self.span_here().error("Expected a value here.").err(),
+        }
+    }
+
+    /// Scan a plain scalar on the current line, return its start and end.
+    ///
+    /// In flow context, the scalar additionally ends at flow indicators.
+    fn plain_scalar_span(&mut self, in_flow: bool) -> (usize, usize) {
+        let start = self.pos;
+        let mut end = self.pos;
+        while let Some(b) = self.peek() {
+            match b {
+                b'\n' | b'\r' => break,
+                b':' if self.is_blank_at(1) => break,
+                b':' if in_flow && matches!(self.peek_at(1), Some(b',' | b']' | b'}')) => break,
+                b',' | b'[' | b']' | b'{' | b'}' if in_flow => break,
+                b'#' if self.pos > start
+                    && matches!(self.input.as_bytes()[self.pos - 1], b' ' | b'\t') =>
+                {
+                    break
+                }
+                b' ' | b'\t' => self.pos += 1,
+                _ => {
+                    self.pos += 1;
+                    end = self.pos;
+                }
+            }
+        }
+        // Leave trailing whitespace for the caller to skip. We only stop at
+        // ASCII bytes, so `end` is always at a char boundary.
+        self.pos = end;
+        (start, end)
+    }
+
+    /// Interpret a plain scalar according to the YAML 1.2 core schema.
+    fn resolve_plain(&self, start: usize, end: usize) -> Result<Value> {
+        let s = &self.input[start..end];
+        let span = self.span(start, end);
+        let value = match s {
+            "" | "~" | "null" | "Null" | "NULL" => Value::Null,
+            "true" | "True" | "TRUE" => Value::Bool(true),
+            "false" | "False" | "FALSE" => Value::Bool(false),
+            _ if is_float(s) => match Float::parse(s) {
+                Some(x) => Value::Float(x),
+                None => {
+                    return span
+                        .error("RCL only supports finite floats.")
+                        .with_help("If this is not meant to be a number, enclose it in quotes.")
+                        .err()
+                }
+            },
+            _ => {
+                let (digits, radix) = if let Some(hex) = s.strip_prefix("0x") {
+                    (hex, 16)
+                } else if let Some(oct) = s.strip_prefix("0o") {
+                    (oct, 8)
+                } else {
+                    (s, 10)
+                };
+                let is_int = match radix {
+                    10 => {
+                        let unsigned = s.strip_prefix(['-', '+']).unwrap_or(s);
+                        !unsigned.is_empty() && unsigned.bytes().all(|b| b.is_ascii_digit())
+                    }
+                    _ => !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_hexdigit()),
+                };
+                if !is_int {
+                    return Ok(Value::String(s.into()));
+                }
+                match i64::from_str_radix(digits, radix) {
+                    Ok(i) => Value::Int(i),
+                    Err(..) if radix == 8 => Value::String(s.into()),
+                    Err(..) => {
+                        return span
+                            .error("This integer is too large to be represented.")
+                            .err()
+                    }
+                }
+            }
+        };
+        Ok(value)
+    }
+
+    /// Parse a single-quoted or double-quoted scalar.
+    fn quoted_scalar(&mut self) -> Result<String> {
+        let open = self.span_here();
+        let quote = self.peek().expect("Caller checked for quote.");
+        self.pos += 1;
+        let mut result = String::new();
+        loop {
+            let ch = match self.input[self.pos..].chars().next() {
+                Some(ch) => ch,
+                None => {
+                    return open
+                        .error("Unexpected end of input, string is not closed.")
+                        .err()
+                }
+            };
+            match ch {
+                '\'' if quote == b'\'' && self.peek_at(1) == Some(b'\'') => {
+                    result.push('\'');
+                    self.pos += 2;
+                }
+                '\'' if quote == b'\'' => {
+                    self.pos += 1;
+                    return Ok(result);
+                }
+                '"' if quote == b'"' => {
+                    self.pos += 1;
+                    return Ok(result);
+                }
+                '\\' if quote == b'"' => self.escape_sequence(&mut result)?,
+                '\n' | '\r' => self.fold_line_break(&mut result),
+                ch => {
+                    result.push(ch);
+                    self.pos += ch.len_utf8();
+                }
+            }
+        }
+    }
+
+    /// Fold a line break inside a quoted scalar.
+    ///
+    /// A single line break becomes a space, and `n + 1` line breaks become `n`
+    /// newlines. Whitespace around the line breaks is removed.
+    fn fold_line_break(&mut self, result: &mut String) {
+        result.truncate(result.trim_end_matches([' ', '\t']).len());
+        let mut n_breaks = 0;
+        loop {
+            self.skip_line_break();
+            n_breaks += 1;
+            self.skip_inline_spaces();
+            if !matches!(self.peek(), Some(b'\n' | b'\r')) {
+                break;
+            }
+        }
+        if n_breaks == 1 {
+            result.push(' ');
+        } else {
+            for _ in 1..n_breaks {
+                result.push('\n');
+            }
+        }
+    }
+
+    fn escape_sequence(&mut self, result: &mut String) -> Result<()> {
+        let start = self.pos;
+        self.pos += 1;
+        let ch = match self.input[self.pos..].chars().next() {
+            Some(ch) => ch,
+            None => {
+                return self
+                    .span(start, self.pos)
+                    .error("Unexpected end of input after '\\'.")
+                    .err()
+            }
+        };
+        self.pos += ch.len_utf8();
+        let unescaped = match ch {
+            '0' => '\0',
+            'a' => '\x07',
+            'b' => '\x08',
+            't' | '\t' => '\t',
+            'n' => '\n',
+            'v' => '\x0b',
+            'f' => '\x0c',
+            'r' => '\r',
+            'e' => '\x1b',
+            ' ' => ' ',
+            '"' => '"',
+            '/' => '/',
+            '\\' => '\\',
+            'N' => '\u{85}',
+            '_' => '\u{a0}',
+            'L' => '\u{2028}',
+            'P' => '\u{2029}',
+            'x' | 'u' | 'U' => '\0',
+            '\n' | '\r' => {
+                // An escaped line break joins the lines without a space.
+                self.pos = start + 1;
+                self.skip_line_break();
+                self.skip_inline_spaces();
+                return Ok(());
+            }
+            _ => {
+                return self
+                    .span(start, self.pos)
+                    .error("Invalid escape sequence.")
+                    .err()
+            }
+        };
+        let n_hex = match ch {
+            'x' => 2,
+            'u' => 4,
+            'U' => 8,
+            _ => {
+                result.push(unescaped);
+                return Ok(());
+            }
+        };
+        let hex = self.input.get(self.pos..self.pos + n_hex).unwrap_or("");
+        let code_point = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == n_hex);
+        if let Some(high @ 0xd800..=0xdbff) = code_point {
+            // A json-style surrogate pair, the low half follows as another '\u'.
+            let low = self
+                .input
+                .get(self.pos + n_hex..self.pos + n_hex + 6)
+                .and_then(|s| s.strip_prefix("\\u"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+            if let Some(low @ 0xdc00..=0xdfff) = low {
+                let u = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                result.push(char::from_u32(u).expect("Surrogate pairs encode valid code points."));
+                self.pos += n_hex + 6;
+                return Ok(());
+            }
+        }
+        match code_point.and_then(char::from_u32) {
+            Some(ch) => {
+                result.push(ch);
+                self.pos += n_hex;
+                Ok(())
+            }
+            None => self
+                .span(start, self.pos)
+                .error("Invalid escape sequence, expected a valid code point in hexadecimal.")
+                .err(),
+        }
+    }
+
+    /// Parse a literal (`|`) or folded (`>`) block scalar.
+    fn block_scalar(&mut self, parent_indent: usize) -> Result<Value> {
+        let header_start = self.pos;
+        let is_literal = self.peek() == Some(b'|');
+        self.pos += 1;
+
+        #[derive(Eq, PartialEq)]
+        enum Chomp {
+            Strip,
+            Clip,
+            Keep,
+        }
+        let mut chomp = Chomp::Clip;
+        let mut explicit_indent = None;
+        for _ in 0..2 {
+            match self.peek() {
+                Some(b'-') => chomp = Chomp::Strip,
+                Some(b'+') => chomp = Chomp::Keep,
+                Some(b @ b'1'..=b'9') => explicit_indent = Some((b - b'0') as usize),
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        self.skip_inline();
+        if !self.is_eol() {
+            return self
+                .span(header_start, self.pos)
+                .error("Expected a line break after the block scalar header.")
+                .err();
+        }
+        self.skip_line_break();
+
+        // Collect the lines, determine the indentation from the first
+        // non-empty line, unless it was given explicitly.
+        let mut indent = explicit_indent.map(|n| parent_indent + n);
+        let mut lines: Vec<&str> = Vec::new();
+        while !self.is_eof() {
+            let line_start = self.pos;
+            let line_end = self.input[self.pos..]
+                .find('\n')
+                .map(|i| self.pos + i)
+                .unwrap_or(self.input.len());
+            let line = self.input[line_start..line_end].trim_end_matches('\r');
+            let n_spaces = line.len() - line.trim_start_matches(' ').len();
+            let is_empty = line.trim_start_matches(' ').is_empty();
+
+            let line_indent = match indent {
+                Some(n) => n,
+                None if is_empty => {
+                    lines.push("");
+                    self.pos = line_end;
+                    self.skip_line_break();
+                    continue;
+                }
+                None if n_spaces <= parent_indent => break,
+                None => {
+                    indent = Some(n_spaces);
+                    n_spaces
+                }
+            };
+            if is_empty {
+                lines.push(line.get(line_indent..).unwrap_or(""));
+            } else if n_spaces < line_indent {
+                break;
+            } else {
+                lines.push(&line[line_indent..]);
+            }
+            self.pos = line_end;
+            self.skip_line_break();
+        }
+        // Trailing empty lines are subject to chomping.
+        let n_content = lines.len() - lines.iter().rev().take_while(|l| l.is_empty()).count();
+        let n_trailing = lines.len() - n_content;
+        let content = &lines[..n_content];
+
+        let mut result = String::new();
+        if is_literal {
+            result.push_str(&content.join("\n"));
+        } else {
+            // Folding: lines are joined with a space, empty lines become line
+            // breaks, and more-indented lines keep their line breaks.
+            let mut prev: Option<&str> = None;
+            for line in content {
+                if let Some(p) = prev {
+                    let keeps_break = p.is_empty()
+                        || line.is_empty()
+                        || p.starts_with([' ', '\t'])
+                        || line.starts_with([' ', '\t']);
+                    result.push(if keeps_break { '\n' } else { ' ' });
+                    if p.is_empty() && !line.is_empty() && !line.starts_with([' ', '\t']) {
+                        // The newline for an empty line replaces the space.
+                        result.pop();
+                    }
+                }
+                result.push_str(line);
+                prev = Some(line);
+            }
+        }
+
+        match chomp {
+            Chomp::Strip => {}
+            Chomp::Clip if n_content > 0 => result.push('\n'),
+            Chomp::Clip => {}
+            Chomp::Keep => {
+                if n_content > 0 {
+                    result.push('\n');
+                }
+                for _ in 0..n_trailing {
+                    result.push('\n');
+                }
+            }
+        }
+
+        Ok(Value::String(result.into()))
+    }
+
+    /// Skip whitespace, comments, and line breaks inside a flow collection.
+    fn skip_flow_space(&mut self) {
+        loop {
+            self.skip_inline();
+            if self.is_eof() || !self.is_eol() {
+                return;
+            }
+            self.skip_line_break();
+        }
+    }
+
+    fn flow_sequence(&mut self) -> Result<Value> {
+        let open = self.span_here();
+        self.pos += 1;
+        let mut result = Vec::new();
+        loop {
+            self.skip_flow_space();
+            match self.peek() {
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::List(SmallList::from(result)));
+                }
+                None => {
+                    return open
+                        .error("Unexpected end of input, '[' is not closed.")
+                        .err()
+                }
+                _ => {}
+            }
+            result.push(self.flow_value()?);
+            self.skip_flow_space();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => continue,
+                _ => return self.span_here().error("Expected ',' or ']' here.").err(),
+            }
+        }
+    }
+
+    fn flow_mapping(&mut self) -> Result<Value> {
+        let open = self.span_here();
+        self.pos += 1;
+        let mut result = SmallDict::new();
+        loop {
+            self.skip_flow_space();
+            match self.peek() {
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Dict(result));
+                }
+                None => {
+                    return open
+                        .error("Unexpected end of input, '{' is not closed.")
+                        .err()
+                }
+                _ => {}
+            }
+            let key_start = self.pos;
+            let key = self.flow_value()?;
+            let key_span = self.span(key_start, self.pos);
+            self.skip_flow_space();
+            let value = match self.peek() {
+                Some(b':') => {
+                    self.pos += 1;
+                    self.skip_flow_space();
+                    match self.peek() {
+                        Some(b',' | b'}') => Value::Null,
+                        _ => self.flow_value()?,
+                    }
+                }
+                _ => Value::Null,
+            };
+            if result.contains_key(&key) {
+                return key_span
+                    .error(concat! {
+                        "Duplicate key " format_rcl(&key).into_owned() " in mapping."
+                    })
+                    .err();
+            }
+            result.insert(key, value);
+            self.skip_flow_space();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => continue,
+                _ => return self.span_here().error("Expected ',' or '}' here.").err(),
+            }
+        }
+    }
+
+    /// Parse a node inside a flow collection.
+    fn flow_value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some(b) if is_plain_start(b) || b == b':' => {
+                let (start, end) = self.plain_scalar_span(true);
+                if start == end {
+                    return self.span_here().error("Expected a value here.").err();
+                }
+                self.resolve_plain(start, end)
+            }
+            _ => self.flow_node(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_yaml;
+    use crate::fmt_rcl::format_rcl;
+    use crate::pprint;
+    use crate::source::DocId;
+
+    fn parse(input: &str) -> String {
+        let value = parse_yaml(DocId(0), input).unwrap();
+        let cfg = pprint::Config { width: 1000 };
+        format_rcl(&value)
+            .println(&cfg)
+            .to_string_no_markup()
+            .trim_end()
+            .to_string()
+    }
+
+    #[test]
+    fn parse_yaml_handles_block_collections() {
+        let input = "
+a: 1
+b:
+  - x
+  - y: 2
+    z: [3, 'q''s']
+c:
+- on
+- ~
+d: {e: \"\\u00e9\", f: true}
+";
+        assert_eq!(
+            parse(input),
+            r#"{ a = 1, b = ["x", { y = 2, z = [3, "q's"] }], c = ["on", null], d = { e = "é", f = true } }"#,
+        );
+    }
+
+    #[test]
+    fn parse_yaml_handles_block_scalars() {
+        assert_eq!(
+            parse("a: |\n  x\n   y\n\nb: 1"),
+            r#"{ a = "x\n y\n", b = 1 }"#
+        );
+        assert_eq!(parse("a: |-\n  x\n  y\n"), r#"{ a = "x\ny" }"#);
+        assert_eq!(parse("a: |+\n  x\n\n"), r#"{ a = "x\n\n" }"#);
+        assert_eq!(parse("a: >\n  x\n  y\n\n  z\n"), r#"{ a = "x y\nz\n" }"#);
+        assert_eq!(parse("- |2\n    x\n"), r#"["  x\n"]"#);
+    }
+
+    #[test]
+    fn parse_yaml_handles_json() {
+        assert_eq!(
+            parse("{\"a\": [1, -2, {\"b\": null}], \"c\": \"d\\n\"}"),
+            r#"{ a = [1, -2, { b = null }], c = "d\n" }"#,
+        );
+        // Floats stay floats, even when they have no fractional part.
+        assert_eq!(parse("[1.0, 1, -0.5, 1e3]"), "[1.0, 1, -0.5, 1000.0]");
+    }
+
+    #[test]
+    fn parse_yaml_rejects_unsupported_features() {
+        assert!(parse_yaml(DocId(0), "a: .inf").is_err());
+        assert!(parse_yaml(DocId(0), "a: &x 1").is_err());
+        assert!(parse_yaml(DocId(0), "a: 1\n---\nb: 2").is_err());
+        assert!(parse_yaml(DocId(0), "a: 1\na: 2").is_err());
+        assert!(parse_yaml(DocId(0), "a:\n  b: 1\n c: 2").is_err());
+    }
+}
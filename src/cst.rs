@@ -31,6 +31,16 @@ pub enum UnOp {
     Neg,
 }
 
+/// How to interpret the contents of an imported file.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ImportFormat {
+    /// Evaluate the file as an RCL document.
+    Rcl,
+
+    /// Take the file contents as a string, with `import "path" as text`.
+    Text,
+}
+
 /// A binary operator.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum BinOp {
@@ -144,6 +154,16 @@ pub enum Stmt {
         value: Box<Expr>,
     },
 
+    /// A let-binding that destructures a tuple `value` into `idents` in `body`.
+    LetTuple {
+        open: Span,
+        close: Span,
+        idents: List<Prefixed<Span>>,
+        type_: Option<Box<Type>>,
+        value_span: Span,
+        value: Box<Expr>,
+    },
+
     /// An assertion with a failure message.
     Assert {
         condition_span: Span,
@@ -175,6 +195,9 @@ pub enum Expr {
 
         /// An expression that evaluates to the path to import.
         path: Box<Expr>,
+
+        /// Whether to evaluate the file, or take its contents as a string.
+        format: ImportFormat,
     },
 
     /// A `{}`-enclosed collection literal.
@@ -199,6 +222,13 @@ pub enum Expr {
         body: Box<Expr>,
     },
 
+    /// A `()`-enclosed tuple literal, with at least one comma unless empty.
+    TupleLit {
+        open: Span,
+        close: Span,
+        elements: List<(Span, Expr)>,
+    },
+
     /// A null literal.
     NullLit(Span),
 
@@ -228,9 +258,34 @@ pub enum Expr {
     /// A number in decimal notation.
     NumDecimal(Span),
 
+    /// A bytes literal in hexadecimal notation, including prefix and quotes.
+    BytesHexadecimal(Span),
+
+    /// A bytes literal in base64 notation, including prefix and quotes.
+    BytesBase64(Span),
+
     /// Access a variable.
     Var(Span),
 
+    /// A tagged value, a `#tag` optionally followed by a payload.
+    Tagged {
+        /// The tag, including the `#`.
+        tag: Span,
+        /// The payload, if there is one.
+        value: Option<(Span, Box<Expr>)>,
+    },
+
+    /// A `match` expression that selects the first arm whose pattern matches.
+    Match {
+        scrutinee_span: Span,
+        scrutinee: Box<Expr>,
+        /// The opening brace.
+        open: Span,
+        /// The closing brace.
+        close: Span,
+        arms: List<Prefixed<MatchArm>>,
+    },
+
     /// A conditional expression.
     IfThenElse {
         condition_span: Span,
@@ -283,6 +338,14 @@ pub enum Expr {
         rhs: Box<Expr>,
     },
 
+    /// A pipeline `x |> f(y) |> g`, syntactic sugar for `g(f(x, y))`.
+    Pipeline {
+        /// The span of the first expression, that gets piped into the stages.
+        base_span: Span,
+        base: Box<Expr>,
+        stages: Vec<PipelineStage>,
+    },
+
     /// A chained expression (field lookup, calls, indexes).
     ///
     /// We represent every chain as a node with a list of chained expressions
@@ -385,6 +448,60 @@ impl Seq {
     }
 }
 
+/// One arm in a `match` expression, `pattern => body`.
+#[derive(Debug)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body_span: Span,
+    pub body: Box<Expr>,
+}
+
+/// A pattern in a `match` arm.
+#[derive(Debug)]
+pub enum Pattern {
+    /// `_`, matches any value without binding it.
+    Wildcard(Span),
+
+    /// A name, matches any value and binds it to the name.
+    Binding(Span),
+
+    /// A null, boolean, integer, or string literal, matches an equal value.
+    Literal { span: Span, value: Box<Expr> },
+
+    /// Match a tagged value by its tag, and optionally match the payload.
+    Tag {
+        /// The tag, including the `#`.
+        tag: Span,
+        /// The pattern for the payload, if any.
+        inner: Option<Box<Pattern>>,
+    },
+}
+
+impl Pattern {
+    /// The span of the full pattern.
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Wildcard(span) => *span,
+            Pattern::Binding(span) => *span,
+            Pattern::Literal { span, .. } => *span,
+            Pattern::Tag { tag, inner: None } => *tag,
+            Pattern::Tag {
+                tag,
+                inner: Some(inner),
+            } => tag.union(inner.span()),
+        }
+    }
+}
+
+/// A stage `|> f(y)` in a pipeline.
+#[derive(Debug)]
+pub struct PipelineStage {
+    /// The span of the `|>` operator.
+    pub op_span: Span,
+    pub function_span: Span,
+    pub function: Expr,
+}
+
 /// A case in a chained non-operator expression (field lookup, call, index).
 #[derive(Debug)]
 pub enum Chain {
@@ -430,6 +547,15 @@ pub enum Type {
         args: List<Prefixed<Type>>,
     },
 
+    /// A tagged type, for example `#tcp Dict[String, Int]`.
+    ///
+    /// When the payload type is omitted, the payload is `Null`.
+    Tagged {
+        span: Span,
+        tag: Span,
+        value: Option<Box<Type>>,
+    },
+
     /// A function type with zero or more arguments, and one result type.
     Function {
         span: Span,
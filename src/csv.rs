@@ -0,0 +1,155 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Parser for comma-separated values, used by `std.parse_csv`.
+//!
+//! The parser follows RFC 4180: fields may be enclosed in double quotes, in
+//! which case they can contain separators, line breaks, and double quotes
+//! (written as `""`). Lines can end in `\n` or `\r\n`. In addition to the RFC,
+//! blank lines are skipped, and the separator is configurable.
+
+/// An error that occurred while parsing, together with the 1-based line number.
+#[derive(Debug, Eq, PartialEq)]
+pub struct CsvError {
+    pub line: usize,
+    pub message: &'static str,
+}
+
+/// A record, together with the 1-based line number where it starts.
+pub type Record = (usize, Vec<String>);
+
+/// Split CSV input into records, and every record into fields.
+pub fn parse_csv(input: &str, separator: char) -> Result<Vec<Record>, CsvError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut chars = input.chars().peekable();
+    let mut line = 1;
+    let mut record_line = 1;
+    // Whether the current record contains anything, to detect blank lines.
+    let mut is_blank = true;
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if field.is_empty() => {
+                let start_line = line;
+                is_blank = false;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(ch) => {
+                            if ch == '\n' {
+                                line += 1;
+                            }
+                            field.push(ch);
+                        }
+                        None => {
+                            return Err(CsvError {
+                                line: start_line,
+                                message: "Unclosed quoted field.",
+                            })
+                        }
+                    }
+                }
+                match chars.peek() {
+                    None | Some('\r' | '\n') => {}
+                    Some(ch) if *ch == separator => {}
+                    Some(_) => {
+                        return Err(CsvError {
+                            line,
+                            message: "Expected a separator or line break after a quoted field.",
+                        })
+                    }
+                }
+            }
+            '"' => {
+                return Err(CsvError {
+                    line,
+                    message:
+                        "Unexpected '\"' in unquoted field, quotes must enclose the entire field.",
+                })
+            }
+            ch if ch == separator => {
+                is_blank = false;
+                record.push(std::mem::take(&mut field));
+            }
+            '\r' if chars.peek() == Some(&'\n') => continue,
+            '\n' => {
+                if !is_blank {
+                    record.push(std::mem::take(&mut field));
+                    records.push((record_line, std::mem::take(&mut record)));
+                }
+                is_blank = true;
+                line += 1;
+                record_line = line;
+            }
+            ch => {
+                is_blank = false;
+                field.push(ch);
+            }
+        }
+    }
+
+    if !is_blank {
+        record.push(field);
+        records.push((record_line, record));
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_csv, CsvError};
+
+    fn parse(input: &str) -> Result<Vec<Vec<String>>, CsvError> {
+        parse_csv(input, ',').map(|records| records.into_iter().map(|(_, r)| r).collect())
+    }
+
+    #[test]
+    fn parse_csv_handles_plain_fields() {
+        let expected = vec![vec!["a", "b", ""], vec!["1", "2", "3"]];
+        assert_eq!(parse("a,b,\n1,2,3\n").unwrap(), expected);
+        assert_eq!(parse("a,b,\r\n1,2,3").unwrap(), expected);
+        assert_eq!(parse("\na,b,\n\n1,2,3\n\n").unwrap(), expected);
+        assert_eq!(parse("").unwrap(), Vec::<Vec<String>>::new());
+    }
+
+    #[test]
+    fn parse_csv_handles_quoted_fields() {
+        let expected = vec![vec!["a,b", "say \"hi\"", "x\ny", ""]];
+        assert_eq!(
+            parse("\"a,b\",\"say \"\"hi\"\"\",\"x\ny\",\"\"").unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_csv("a;\"b;c\"\n", ';').unwrap(),
+            vec![(1, vec!["a".to_string(), "b;c".to_string()])]
+        );
+    }
+
+    #[test]
+    fn parse_csv_tracks_record_lines() {
+        let lines: Vec<usize> = parse_csv("a\n\n\"b\nc\"\nd", ',')
+            .unwrap()
+            .into_iter()
+            .map(|(line, _)| line)
+            .collect();
+        assert_eq!(lines, vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn parse_csv_reports_errors_with_line() {
+        assert_eq!(parse("a\n\"b").unwrap_err().line, 2);
+        assert_eq!(parse("a\nb\"c\"").unwrap_err().line, 2);
+        assert_eq!(parse("\"x\ny\"z").unwrap_err().line, 2);
+    }
+}
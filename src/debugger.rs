@@ -0,0 +1,327 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! An interactive debugger that pauses evaluation at breakpoints.
+//!
+//! The evaluator asks the debugger whether to pause at every statement, every
+//! element of a collection literal, and every function call. When it pauses,
+//! the evaluator reads commands until the user continues; see
+//! [`Evaluator::debug_pause`](crate::eval::Evaluator::debug_pause).
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::source::{Doc, DocId, Span};
+
+pub const USAGE_COMMANDS: &str = "\
+Commands:
+  c, continue       Continue until the next breakpoint.
+  s, step           Continue until the next statement on a different line.
+  l, locals         Show the variables that are in scope.
+  bt, backtrace     Show the function calls and imports that led here.
+  p, print <expr>   Evaluate an expression in the current scope.
+  b, break <file>:<line>
+                    Add a breakpoint.
+  q, quit           Abort evaluation.
+  h, help           Show this help.";
+
+/// A location to pause at.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Breakpoint {
+    /// The path of the document, it matches documents whose path ends in it.
+    pub file: String,
+
+    /// The 1-based line number.
+    pub line: usize,
+}
+
+impl FromStr for Breakpoint {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s.rsplit_once(':') {
+            Some((file, line)) if !file.is_empty() => match usize::from_str(line) {
+                Ok(line) if line > 0 => Ok(Breakpoint {
+                    file: file.to_string(),
+                    line,
+                }),
+                _ => Err(()),
+            },
+            _ => Err(()),
+        }
+    }
+}
+
+/// Why the evaluator reached a potential pause point.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PausePoint {
+    /// Before evaluating a statement, element, or function body.
+    Step,
+
+    /// After printing the message of a `trace` statement.
+    Trace,
+}
+
+/// A command entered at the debugger prompt.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    Continue,
+    Step,
+    Locals,
+    Backtrace,
+    Print(String),
+    Break(Breakpoint),
+    Quit,
+    Help,
+    /// Input that is not a valid command, with an explanation.
+    Invalid(&'static str),
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Command {
+        let line = line.trim();
+        let (cmd, arg) = match line.split_once(char::is_whitespace) {
+            Some((cmd, arg)) => (cmd, arg.trim()),
+            None => (line, ""),
+        };
+        match (cmd, arg) {
+            ("c" | "continue", "") => Command::Continue,
+            ("s" | "step", "") => Command::Step,
+            ("l" | "locals", "") => Command::Locals,
+            ("bt" | "backtrace", "") => Command::Backtrace,
+            ("p" | "print", "") => Command::Invalid("Expected an expression to print."),
+            ("p" | "print", expr) => Command::Print(expr.to_string()),
+            ("b" | "break", loc) => match Breakpoint::from_str(loc) {
+                Ok(bp) => Command::Break(bp),
+                Err(..) => Command::Invalid("Expected a breakpoint as <file>:<line>."),
+            },
+            ("q" | "quit", "") => Command::Quit,
+            ("h" | "help", "") => Command::Help,
+            _ => Command::Invalid("Unknown command, enter 'help' for a list of commands."),
+        }
+    }
+}
+
+/// State of the debugger, and where it reads commands from and writes to.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+
+    /// Whether to pause after every `trace` statement.
+    break_on_trace: bool,
+
+    /// Whether to pause at the next pause point on a different line.
+    stepping: bool,
+
+    /// The line of the last pause point that we considered.
+    ///
+    /// A line usually contains multiple pause points, we pause at most once
+    /// until evaluation moves to a different line.
+    last_line: Option<(DocId, usize)>,
+
+    /// For every document, the byte offsets where lines start.
+    line_starts: BTreeMap<DocId, Vec<usize>>,
+
+    /// The function calls and imports that we are in, innermost last.
+    pub call_stack: Vec<(Span, &'static str)>,
+
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+}
+
+impl Debugger {
+    /// Create a debugger.
+    ///
+    /// Without breakpoints, and without pausing on traces, the debugger pauses
+    /// at the first statement.
+    pub fn new(
+        breakpoints: Vec<Breakpoint>,
+        break_on_trace: bool,
+        input: Box<dyn BufRead>,
+        output: Box<dyn Write>,
+    ) -> Debugger {
+        Debugger {
+            stepping: breakpoints.is_empty() && !break_on_trace,
+            breakpoints,
+            break_on_trace,
+            last_line: None,
+            line_starts: BTreeMap::new(),
+            call_stack: Vec::new(),
+            input,
+            output,
+        }
+    }
+
+    /// Return the 1-based line and column where the span starts.
+    pub fn line_column(&mut self, doc: &Doc, span: Span) -> (usize, usize) {
+        let starts = self.line_starts.entry(span.doc()).or_insert_with(|| {
+            let mut starts = vec![0];
+            starts.extend(doc.data.match_indices('\n').map(|(i, _)| i + 1));
+            starts
+        });
+        let line = starts.partition_point(|&start| start <= span.start());
+        (line, 1 + span.start() - starts[line - 1])
+    }
+
+    /// Return a `file:line:column` description of the span.
+    pub fn location(&mut self, doc: &Doc, span: Span) -> String {
+        let (line, column) = self.line_column(doc, span);
+        format!("{}:{line}:{column}", doc.name)
+    }
+
+    /// Return whether to pause at the span, and if so, why.
+    pub fn should_pause(
+        &mut self,
+        doc: &Doc,
+        span: Span,
+        point: PausePoint,
+    ) -> Option<&'static str> {
+        let (line, _column) = self.line_column(doc, span);
+        let is_new_line = self.last_line != Some((span.doc(), line));
+        self.last_line = Some((span.doc(), line));
+
+        if point == PausePoint::Trace && self.break_on_trace {
+            return Some("Paused at trace.");
+        }
+        if !is_new_line || point != PausePoint::Step {
+            return None;
+        }
+        if self.stepping {
+            return Some("Paused.");
+        }
+        let path = Path::new(doc.name);
+        let is_breakpoint = self
+            .breakpoints
+            .iter()
+            .any(|bp| bp.line == line && path.ends_with(&bp.file));
+        match is_breakpoint {
+            true => Some("Paused at breakpoint."),
+            false => None,
+        }
+    }
+
+    /// Continue until the next breakpoint, or step to the next line.
+    pub fn resume(&mut self, step: bool) {
+        self.stepping = step;
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    /// Print the prompt and read a command.
+    ///
+    /// At the end of the input, continue without further pauses.
+    pub fn read_command(&mut self) -> Command {
+        self.print("(rcl) ");
+        let _ = self.output.flush();
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) | Err(..) => {
+                self.breakpoints.clear();
+                self.break_on_trace = false;
+                Command::Continue
+            }
+            Ok(_) => Command::parse(&line),
+        }
+    }
+
+    pub fn print(&mut self, message: &str) {
+        // If we can't write to the output, there is nothing better to do than
+        // to carry on.
+        let _ = self.output.write_all(message.as_bytes());
+    }
+
+    pub fn println(&mut self, message: &str) {
+        self.print(message);
+        self.print("\n");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::io::{Cursor, Write};
+    use std::rc::Rc;
+
+    use super::{Breakpoint, Command, Debugger};
+    use crate::eval::StrictMode;
+    use crate::loader::Loader;
+    use crate::tracer::VoidTracer;
+
+    /// Output that we can still read after the debugger took ownership.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn command_parse_handles_aliases_and_arguments() {
+        assert_eq!(Command::parse("c\n"), Command::Continue);
+        assert_eq!(Command::parse("  step "), Command::Step);
+        assert_eq!(
+            Command::parse("p xs.len() + 1"),
+            Command::Print("xs.len() + 1".into())
+        );
+        assert_eq!(
+            Command::parse("break lib/ports.rcl:12"),
+            Command::Break(Breakpoint {
+                file: "lib/ports.rcl".into(),
+                line: 12,
+            })
+        );
+        assert!(matches!(
+            Command::parse("break ports.rcl:0"),
+            Command::Invalid(..)
+        ));
+        assert!(matches!(Command::parse("continue 2"), Command::Invalid(..)));
+        assert!(matches!(Command::parse("print"), Command::Invalid(..)));
+    }
+
+    #[test]
+    fn debugger_pauses_at_breakpoint_and_prints_in_scope() {
+        let mut loader = Loader::new();
+        let doc = loader.load_string("let a = 1;\nlet b = a + 1;\n[a, b]".to_string());
+        let input = "locals\nprint a * 10\ncontinue\n";
+        let output = SharedBuf::default();
+        let mut debugger = Debugger::new(
+            vec![Breakpoint {
+                file: "input".into(),
+                line: 2,
+            }],
+            false,
+            Box::new(Cursor::new(input)),
+            Box::new(output.clone()),
+        );
+        let mut type_env = loader.type_prelude();
+        let mut value_env = loader.value_prelude();
+        let result = loader.evaluate_debug(
+            &mut type_env,
+            &mut value_env,
+            doc,
+            &mut VoidTracer,
+            StrictMode::Off,
+            &mut debugger,
+        );
+        assert!(result.is_ok());
+        let output = String::from_utf8(output.0.take()).unwrap();
+        assert!(output.contains("Paused at breakpoint."), "{output}");
+        assert!(output.contains("(rcl) a = 1\n"), "{output}");
+        assert!(output.contains("(rcl) 10\n"), "{output}");
+        assert!(!output.contains("\nb = "), "{output}");
+    }
+}
@@ -0,0 +1,280 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Extraction of documentation comments from the Concrete Syntax Tree.
+//!
+//! A line comment that starts with exactly three slashes (`///`) is a doc
+//! comment. It documents the let-binding or dict field that directly follows
+//! it. Doc comments are regular comments as far as the parser and formatter are
+//! concerned; they are part of the non-code prefix of the node that follows,
+//! and we recover them from there.
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::cst::{Expr, NonCode, Seq, Stmt, StringPart};
+use crate::lexer::StringPrefix;
+use crate::runtime::Value;
+use crate::small_collection::{SmallDict, SmallList};
+use crate::source::Span;
+
+/// What kind of node a doc comment documents.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DocKind {
+    /// A let-binding, `let name = value;`.
+    Let,
+
+    /// A dict field, `name = value` or `"name": value`.
+    Field,
+}
+
+/// A doc comment together with the thing that it documents.
+#[derive(Debug)]
+pub struct DocComment {
+    /// What kind of node the comment documents.
+    pub kind: DocKind,
+
+    /// The names of the enclosing let-bindings and fields, outermost first.
+    ///
+    /// The last element is the name of the documented node itself.
+    pub path: Vec<Rc<str>>,
+
+    /// The span of the documented name.
+    pub span: Span,
+
+    /// The text of the comment, with the `///` markers removed.
+    ///
+    /// Multiple lines are joined with newlines.
+    pub text: String,
+}
+
+impl DocKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocKind::Let => "let",
+            DocKind::Field => "field",
+        }
+    }
+}
+
+/// Return the doc comment at the end of a non-code prefix, if there is one.
+///
+/// Only the doc comment lines directly above the node count. If there is a
+/// blank line or a regular comment between the doc comment and the node, then
+/// the doc comment does not belong to the node.
+pub fn get_doc_comment(input: &str, prefix: &[NonCode]) -> Option<String> {
+    let mut lines = Vec::new();
+    for non_code in prefix.iter().rev() {
+        let line = match non_code {
+            NonCode::LineComment(span) => span.resolve(input),
+            NonCode::Blank(..) | NonCode::Shebang(..) => break,
+        };
+        match line.strip_prefix("///") {
+            Some(text) if !text.starts_with('/') => {
+                lines.push(text.strip_prefix(' ').unwrap_or(text).trim_end());
+            }
+            _ => break,
+        }
+    }
+
+    if lines.is_empty() {
+        return None;
+    }
+
+    lines.reverse();
+    Some(lines.join("\n"))
+}
+
+/// Collect all doc comments in a document, in source order.
+///
+/// This includes let-bindings at any level of nesting, as long as they are not
+/// inside a function or comprehension. Fields are only included when their
+/// name is known statically, and also not inside comprehensions.
+pub fn collect_doc_comments(input: &str, expr: &Expr) -> Vec<DocComment> {
+    let mut collector = Collector {
+        input,
+        path: Vec::new(),
+        result: Vec::new(),
+    };
+    collector.visit_expr(expr);
+    collector.result
+}
+
+/// Convert doc comments into a value, for serialization.
+///
+/// The result is a list with a dict per comment, with keys `kind`, `path`,
+/// and `doc`.
+pub fn doc_comments_to_value(docs: &[DocComment]) -> Value {
+    let entries: SmallList = docs
+        .iter()
+        .map(|doc| {
+            let path: SmallList = doc
+                .path
+                .iter()
+                .map(|name| Value::String(name.into()))
+                .collect();
+            let mut entry = BTreeMap::new();
+            entry.insert("kind".into(), doc.kind.as_str().into());
+            entry.insert("path".into(), Value::List(path));
+            entry.insert("doc".into(), doc.text.as_str().into());
+            Value::Dict(SmallDict::from(entry))
+        })
+        .collect();
+    Value::List(entries)
+}
+
+/// If the expression is a plain string literal, return its contents.
+///
+/// This handles only string literals without escapes and holes, which covers
+/// the common case of a dict key written in json style, and raw strings.
+fn get_plain_string(input: &str, expr: &Expr) -> Option<Rc<str>> {
+    match expr {
+        Expr::StringLit {
+            prefix: StringPrefix::None | StringPrefix::Raw,
+            parts,
+            ..
+        } => {
+            let mut result = String::new();
+            for part in parts {
+                match part {
+                    StringPart::String(span) => result.push_str(span.resolve(input)),
+                    _ => return None,
+                }
+            }
+            Some(result.into())
+        }
+        _ => None,
+    }
+}
+
+struct Collector<'a> {
+    input: &'a str,
+    path: Vec<Rc<str>>,
+    result: Vec<DocComment>,
+}
+
+impl<'a> Collector<'a> {
+    /// Record the doc comment for `name`, if any, and visit its value.
+    fn visit_named(
+        &mut self,
+        kind: DocKind,
+        prefix: &[NonCode],
+        name: Rc<str>,
+        span: Span,
+        value: &Expr,
+    ) {
+        self.path.push(name);
+        if let Some(text) = get_doc_comment(self.input, prefix) {
+            self.result.push(DocComment {
+                kind,
+                path: self.path.clone(),
+                span,
+                text,
+            });
+        }
+        self.visit_expr(value);
+        self.path.pop();
+    }
+
+    fn visit_stmt(&mut self, prefix: &[NonCode], stmt: &Stmt) {
+        if let Stmt::Let { ident, value, .. } = stmt {
+            let name = ident.resolve(self.input).into();
+            self.visit_named(DocKind::Let, prefix, name, *ident, value);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Statements { stmts, body, .. } => {
+                for (_span, stmt) in stmts {
+                    self.visit_stmt(&stmt.prefix, &stmt.inner);
+                }
+                self.visit_expr(&body.inner);
+            }
+            Expr::BraceLit { elements, .. } => {
+                for elem in elements.elements.iter() {
+                    self.visit_seq(&elem.prefix, &elem.inner);
+                }
+            }
+            Expr::Parens { body, .. } => self.visit_expr(body),
+            _ => {}
+        }
+    }
+
+    fn visit_seq(&mut self, prefix: &[NonCode], seq: &Seq) {
+        match seq {
+            Seq::AssocIdent { field, value, .. } => {
+                let name = field.resolve(self.input).into();
+                self.visit_named(DocKind::Field, prefix, name, *field, value);
+            }
+            Seq::AssocExpr {
+                field_span,
+                field,
+                value,
+                ..
+            } => {
+                if let Some(name) = get_plain_string(self.input, field) {
+                    self.visit_named(DocKind::Field, prefix, name, *field_span, value);
+                }
+            }
+            Seq::Stmt { stmt, body, .. } => {
+                self.visit_stmt(prefix, stmt);
+                self.visit_seq(&body.prefix, &body.inner);
+            }
+            Seq::Elem { .. } | Seq::For { .. } | Seq::If { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{collect_doc_comments, DocKind};
+    use crate::source::DocId;
+
+    fn collect(input: &str) -> Vec<(DocKind, String, String)> {
+        let doc = DocId(0);
+        let tokens = crate::lexer::lex(doc, input).unwrap();
+        let (_span, expr) = crate::parser::parse(doc, input, &tokens).unwrap();
+        collect_doc_comments(input, &expr)
+            .into_iter()
+            .map(|d| (d.kind, d.path.join("."), d.text))
+            .collect()
+    }
+
+    #[test]
+    fn collect_doc_comments_on_lets_and_fields() {
+        let input = r#"
+/// The base port.
+let base = 8000;
+{
+  /// Port to listen on.
+  port = base,
+  /// Not attached, there is a blank line.
+
+  host = "localhost",
+  "tls": {
+    /// Multiple lines
+    ///   preserve indentation.
+    cert = "x.pem",
+  },
+  // Regular comments are not doc comments.
+  //// Neither are four slashes.
+  key = "x.key",
+}
+"#;
+        let expected = vec![
+            (DocKind::Let, "base".into(), "The base port.".into()),
+            (DocKind::Field, "port".into(), "Port to listen on.".into()),
+            (
+                DocKind::Field,
+                "tls.cert".into(),
+                "Multiple lines\n  preserve indentation.".into(),
+            ),
+        ];
+        assert_eq!(collect(input), expected);
+    }
+}
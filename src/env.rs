@@ -42,6 +42,11 @@ impl<T> Env<T> {
             .map(|(_k, v)| v)
     }
 
+    /// Return all bindings, including shadowed ones, innermost last.
+    pub fn bindings(&self) -> &[(Ident, T)] {
+        &self.bindings
+    }
+
     /// Return a checkpoint of the environment to later [`Env::pop`] to.
     ///
     /// Note, the environment is a stack and the pushes and pops have to be
@@ -12,12 +12,13 @@ use crate::markup::Markup;
 use crate::pprint::{concat, Doc};
 use crate::runtime::Value;
 use crate::source::{Inputs, Span};
+use crate::source_map::{key_segment, push_segment};
 
 pub type Result<T> = std::result::Result<T, Box<Error>>;
 
 /// Element of a path through a value.
 // TODO: Record the value itself as well, so we can *show* the thing that's wrong.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum PathElement {
     Key(Value),
     Index(usize),
@@ -67,6 +68,9 @@ pub struct Error {
     pub call_stack: Vec<(Span, Doc<'static>)>,
 
     /// For errors that originated from a value, the path in the value.
+    ///
+    /// The innermost element comes first, because errors collect the path as
+    /// they propagate outward.
     pub path: Vec<PathElement>,
 
     /// Any other relevant spans.
@@ -79,6 +83,12 @@ pub struct Error {
     /// For example, when the user writes a `#`, we can explain that comments
     /// are written with `//` instead.
     pub help: Option<Doc<'static>>,
+
+    /// Errors that were found after this one.
+    ///
+    /// Most errors are fatal, but the parser can recover from a syntax error
+    /// and continue, so it reports all syntax errors in a document at once.
+    pub further: Vec<Error>,
 }
 
 impl Error {
@@ -95,6 +105,7 @@ impl Error {
             path: Vec::new(),
             notes: Vec::new(),
             help: None,
+            further: Vec::new(),
         }
     }
 
@@ -180,8 +191,9 @@ impl Error {
         self
     }
 
-    /// Replace the value path with the given path.
-    pub fn with_path(mut self, path: Vec<PathElement>) -> Error {
+    /// Replace the value path with the given path, outermost element first.
+    pub fn with_path(mut self, mut path: Vec<PathElement>) -> Error {
+        path.reverse();
         self.path = path;
         self
     }
@@ -197,13 +209,38 @@ impl Error {
         Err(Box::new(self))
     }
 
+    /// Return the value path as a JSON pointer, like `/servers/3/ports/0`.
+    ///
+    /// Returns `None` when the path is empty, or when it contains a dict key
+    /// that has no JSON equivalent.
+    pub fn json_pointer(&self) -> Option<String> {
+        if self.path.is_empty() {
+            return None;
+        }
+        let mut pointer = String::new();
+        for elem in self.path.iter().rev() {
+            match elem {
+                PathElement::Key(k) => push_segment(&mut pointer, &key_segment(k)?),
+                PathElement::Index(i) => push_segment(&mut pointer, &i.to_string()),
+            }
+        }
+        Some(pointer)
+    }
+
     fn report_path(&self) -> Doc<'static> {
         // TODO: Find a prettier way to report the value path of an error.
         // For now this will do.
         if self.path.is_empty() {
             return Doc::Empty;
         }
-        let mut path_doc = vec![Doc::from("in value"), Doc::HardBreak];
+        // The pointer makes it easy to locate the value in large outputs, the
+        // list of elements below clarifies the keys that have no json form.
+        let mut path_doc = vec![Doc::from("in value")];
+        if let Some(pointer) = self.json_pointer() {
+            path_doc.push(" ".into());
+            path_doc.push(Doc::from(pointer).with_markup(Markup::Highlight));
+        }
+        path_doc.push(Doc::HardBreak);
         for elem in self.path.iter().rev() {
             match elem {
                 PathElement::Key(k) => {
@@ -283,6 +320,12 @@ impl Error {
             );
         }
 
+        for further in self.further {
+            result.push(Doc::HardBreak);
+            result.push(Doc::HardBreak);
+            result.push(further.report(inputs));
+        }
+
         Doc::Concat(result)
     }
 }
@@ -305,6 +348,42 @@ impl IntoError for Span {
     }
 }
 
+/// Return the display width of `s` when it starts at column `start`.
+///
+/// Tabs advance to the next multiple of `tab_width`, other characters take up
+/// their Unicode width.
+fn display_width(s: &str, start: usize, tab_width: usize) -> usize {
+    use unicode_width::UnicodeWidthChar;
+    let mut column = start;
+    for ch in s.chars() {
+        column = match ch {
+            '\t' => (column / tab_width.max(1) + 1) * tab_width.max(1),
+            _ => column + ch.width().unwrap_or(0),
+        };
+    }
+    column - start
+}
+
+/// Replace tabs with spaces up to the next tab stop.
+fn expand_tabs(s: &str, tab_width: usize) -> Doc {
+    if !s.contains('\t') {
+        return Doc::from(s);
+    }
+    let mut result = String::with_capacity(s.len());
+    let mut column = 0;
+    for ch in s.chars() {
+        let mut buf = [0; 4];
+        let ch_str: &str = ch.encode_utf8(&mut buf);
+        let width = display_width(ch_str, column, tab_width);
+        match ch {
+            '\t' => result.extend(std::iter::repeat(' ').take(width)),
+            _ => result.push(ch),
+        }
+        column += width;
+    }
+    Doc::from(result)
+}
+
 /// Highlight a span in a line.
 pub fn highlight_span<'a>(inputs: &'a Inputs, span: Span, markup: Markup) -> Doc<'a> {
     use std::cmp;
@@ -337,9 +416,10 @@ pub fn highlight_span<'a>(inputs: &'a Inputs, span: Span, markup: Markup) -> Doc
     }
 
     // Save this for reporting the error location, in case we adjust the line
-    // start below. Add 1 because the first column is column 1, not 0.
-    // TODO: This should measure width, not count bytes.
-    let column = 1 + span.start() - line_start;
+    // start below. Add 1 because the first column is column 1, not 0. Columns
+    // count display width, like the marker below, not bytes.
+    let tab_width = doc.tab_width as usize;
+    let column = 1 + display_width(&input[line_start..span.start()], 0, tab_width);
 
     // If there is a really long line (for example, because you are evaluating
     // a multi-megabyte json document that is formatted without whitespace, on
@@ -378,9 +458,13 @@ pub fn highlight_span<'a>(inputs: &'a Inputs, span: Span, markup: Markup) -> Doc
     let error_content = &as_of_error[..cmp::min(span.len(), as_of_error.len())];
 
     // The width of the error is not necessarily the number of bytes,
-    // measure the Unicode width of the span to underline.
-    let indent_width = indent_content.width() + trunc_prefix.width();
-    let mark_width = cmp::max(1, error_content.width());
+    // measure the Unicode width of the span to underline. We print tabs as
+    // spaces, so they line up with the marker regardless of the terminal.
+    let indent_width = display_width(indent_content, 0, tab_width);
+    let mark_width = display_width(error_content, indent_width, tab_width);
+    let line_content = expand_tabs(line_content, tab_width);
+    let indent_width = indent_width + trunc_prefix.width();
+    let mark_width = cmp::max(1, mark_width);
 
     let line_num_str = line.to_string();
     let line_num_pad: String = line_num_str.chars().map(|_| ' ').collect();
@@ -11,20 +11,30 @@ use std::collections::HashMap;
 use std::collections::{BTreeMap, BTreeSet};
 use std::rc::Rc;
 
-use crate::ast::{BinOp, CallArg, Expr, FormatFragment, Seq, Stmt, UnOp, Yield};
-use crate::error::{Error, IntoError, Result};
-use crate::fmt_rcl::{self, format_rcl};
+#[cfg(feature = "arena")]
+use crate::arena::{self, ScratchArena};
+use crate::ast::{
+    BinOp, CallArg, Expr, FormatFragment, Ident, ImportFormat, Pattern, Seq, Stmt, UnOp, Yield,
+};
+use crate::debugger::{self, Command, Debugger, PausePoint};
+use crate::error::{highlight_span, Error, IntoError, PathElement, Result};
+use crate::fmt_rcl::{self, format_rcl, format_rcl_limited, Limits};
 use crate::loader::Loader;
-use crate::pprint::{concat, indent, Doc};
+use crate::markup::Markup;
+use crate::parallel::Shard;
+use crate::pprint::{self, concat, indent, Doc};
+use crate::profile::Frame;
 use crate::runtime::{
-    self, BuiltinFunction, BuiltinMethod, Env, Function, FunctionCall, MethodCall, MethodInstance,
-    Value,
+    BuiltinFunction, BuiltinMethod, Env, Function, FunctionCall, MethodCall, MethodInstance, Value,
 };
+use crate::small_collection::{SmallDict, SmallList};
+use crate::small_str::SmallStr;
 use crate::source::{DocId, Span};
+use crate::source_map::{ElementSpans, YieldSite};
 use crate::stdlib;
-use crate::tracer::Tracer;
+use crate::tracer::{Tracer, VoidTracer, Warning};
 use crate::typecheck;
-use crate::types;
+use crate::types::{self, SourcedType};
 
 /// An entry on the evaluation stack.
 pub struct EvalContext {
@@ -109,6 +119,24 @@ impl EvalCount {
     }
 }
 
+/// How to treat implicit conversions during evaluation.
+///
+/// Implicit conversions are operations that succeed on values that the
+/// typechecker cannot always rule out, but which are likely unintended, such as
+/// interpolating `null` into a string, or comparing an integer to a string.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum StrictMode {
+    /// Allow implicit conversions silently.
+    #[default]
+    Off,
+
+    /// Allow implicit conversions, but print a warning for each one.
+    Warn,
+
+    /// Report implicit conversions as errors.
+    Error,
+}
+
 pub struct Evaluator<'a> {
     pub loader: &'a mut Loader,
     pub tracer: &'a mut dyn Tracer,
@@ -135,6 +163,67 @@ pub struct Evaluator<'a> {
     ///
     /// This is used to break infinite loops.
     pub eval_count: EvalCount,
+
+    /// How to treat implicit conversions.
+    pub strict_mode: StrictMode,
+
+    /// Spans for which we already warned about an implicit conversion.
+    ///
+    /// In warn mode, we report every location only once, even if it is visited
+    /// many times, e.g. inside a loop.
+    warned_spans: BTreeSet<Span>,
+
+    /// Results of calls to memoized functions, keyed by function and arguments.
+    ///
+    /// Values are immutable, so the cache is valid for the entire evaluation.
+    memo_cache: BTreeMap<(Value, Vec<Value>), Value>,
+
+    /// The keys and indices on the way to the value that we are evaluating.
+    ///
+    /// Validators from `std.check` attach this path to their errors, so a
+    /// failure says which field of the result it applies to. Set elements
+    /// have no key or index, they push `None`.
+    value_path: Vec<Option<PathElement>>,
+
+    /// The interactive debugger, if evaluating under `rcl debug`.
+    pub debugger: Option<&'a mut Debugger>,
+
+    /// Only evaluate the top-level dict values whose key is in this shard.
+    ///
+    /// See [`Evaluator::eval_shard_expr`] for which dict that applies to.
+    pub shard: Option<Shard>,
+
+    /// The evaluation depth of the dict that the shard applies to, once we enter it.
+    shard_depth: Option<u32>,
+
+    /// Do not report traces and warnings, see [`Evaluator::eval_shard_expr`].
+    is_muted: bool,
+
+    /// Stack storage for temporary lists that do not escape a comprehension.
+    #[cfg(feature = "arena")]
+    pub arena: ScratchArena,
+}
+
+/// Match the value against the pattern, and push any bindings if it matches.
+///
+/// When the pattern does not match, some bindings may have been pushed
+/// already, the caller is responsible for restoring the environment.
+fn match_pattern(env: &mut Env, pattern: &Pattern, value: &Value) -> bool {
+    match (pattern, value) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Binding(_span, name), _) => {
+            env.push(name.clone(), value.clone());
+            true
+        }
+        (Pattern::Literal(literal), _) => literal == value,
+        (Pattern::Tag { tag, inner }, Value::Tagged(value_tag, payload)) if tag == value_tag => {
+            match inner {
+                None => true,
+                Some(inner) => match_pattern(env, inner, payload),
+            }
+        }
+        (Pattern::Tag { .. }, _) => false,
+    }
 }
 
 impl<'a> Evaluator<'a> {
@@ -143,13 +232,65 @@ impl<'a> Evaluator<'a> {
             loader,
             tracer,
             import_stack: Vec::new(),
-            stdlib: stdlib::initialize(),
+            stdlib: stdlib::initialize(&stdlib::Capabilities::default()),
             function_type_cache: HashMap::new(),
             eval_depth: 0,
             eval_count: EvalCount::new(),
+            strict_mode: StrictMode::Off,
+            warned_spans: BTreeSet::new(),
+            memo_cache: BTreeMap::new(),
+            value_path: Vec::new(),
+            debugger: None,
+            shard: None,
+            shard_depth: None,
+            is_muted: false,
+            #[cfg(feature = "arena")]
+            arena: ScratchArena::new(),
         }
     }
 
+    /// Report an implicit conversion at `span`, according to the strict mode.
+    fn check_implicit(
+        &mut self,
+        span: Span,
+        message: Doc<'static>,
+        help: &'static str,
+    ) -> Result<()> {
+        match self.strict_mode {
+            StrictMode::Off => Ok(()),
+            StrictMode::Warn => {
+                if self.warned_spans.insert(span) && !self.is_muted {
+                    let warning = Warning::new(span, message);
+                    self.tracer.warn(&self.loader.as_inputs(), warning);
+                }
+                Ok(())
+            }
+            StrictMode::Error => span.error(message).with_help(help).err(),
+        }
+    }
+
+    /// Evaluate `f` with `element` appended to the value path.
+    fn with_value_path<T>(
+        &mut self,
+        element: Option<PathElement>,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        self.value_path.push(element);
+        let result = f(self);
+        self.value_path.pop();
+        result
+    }
+
+    /// Return the path to the value that we are evaluating, outermost first.
+    ///
+    /// Inside a set element, the path stops at the set.
+    pub fn value_path(&self) -> Vec<PathElement> {
+        self.value_path
+            .iter()
+            .map_while(|elem| elem.clone())
+            .collect()
+    }
+
     /// Return the type of a builtin function, cached.
     fn get_builtin_function_type(&mut self, builtin: &BuiltinFunction) -> &types::Function {
         self.function_type_cache
@@ -174,17 +315,35 @@ impl<'a> Evaluator<'a> {
         self.eval_depth += 1;
 
         if self.eval_depth >= max_eval_depth {
-            return at
-                .error(concat! {
-                    "Evaluation budget exceeded. "
-                    "This expression exceeds the maximum evaluation depth of "
-                    max_eval_depth.to_string()
-                    "."
-                })
-                .err();
+            let mut err = at.error(concat! {
+                "Evaluation budget exceeded. "
+                "This expression exceeds the maximum evaluation depth of "
+                max_eval_depth.to_string()
+                "."
+            });
+            self.add_import_chain(&mut err);
+            return err.err();
+        }
+
+        if let Err(mut err) = self.eval_count.inc(at) {
+            self.add_import_chain(&mut err);
+            return Err(err);
         }
 
-        self.eval_count.inc(at)
+        Ok(())
+    }
+
+    /// Add notes to the error for the chain of imports that led to it.
+    ///
+    /// For errors that can occur anywhere, such as exceeding the evaluation
+    /// budget, the call stack may be too deep to show how we got into the
+    /// offending document, so we point out the import chain explicitly.
+    fn add_import_chain(&self, err: &mut Error) {
+        for ctx in self.import_stack.iter().rev() {
+            if let Some(src) = ctx.imported_from {
+                err.add_note(src, "Imported here.");
+            }
+        }
     }
 
     #[inline]
@@ -200,17 +359,61 @@ impl<'a> Evaluator<'a> {
         doc: DocId,
     ) -> Result<Value> {
         debug_assert!(self.import_stack.is_empty());
-        let expr = self.loader.get_typechecked_ast(type_env, doc)?;
+        // Every shard typechecks the document, only the first one reports warnings.
+        let mut void_tracer = VoidTracer;
+        let tracer: &mut dyn Tracer = match self.shard {
+            Some(shard) if shard.index > 0 => &mut void_tracer,
+            _ => self.tracer,
+        };
+        let expr = self.loader.get_typechecked_ast(type_env, doc, tracer)?;
         let ctx = EvalContext {
             doc,
             imported_from: None,
         };
         self.import_stack.push(ctx);
-        let result = self.eval_expr(value_env, &expr)?;
+        let result = self.eval_profiled(Frame::Document(doc), |this| match this.shard {
+            Some(shard) => this.eval_shard_expr(value_env, &expr, shard),
+            None => this.eval_expr(value_env, &expr),
+        })?;
         self.import_stack.pop().expect("Push/pop are balanced.");
         Ok(result)
     }
 
+    /// Evaluate the top level of a document, with only the keys in `shard`.
+    ///
+    /// When the document is a dict literal, possibly after some statements,
+    /// then we skip the values of keys that are not in the shard, and those
+    /// keys are not in the result. Every shard evaluates the statements before
+    /// the dict, but only the first shard reports their traces and warnings,
+    /// so they are not printed once per shard. A document that is not a dict
+    /// literal is evaluated in full by the first shard, and other shards
+    /// return an empty dict.
+    fn eval_shard_expr(&mut self, env: &mut Env, expr: &Expr, shard: Shard) -> Result<Value> {
+        match expr {
+            Expr::Stmt { stmt, body, .. } => {
+                let ck = env.checkpoint();
+                self.is_muted = shard.index > 0;
+                let result = self.eval_stmt(env, stmt);
+                self.is_muted = false;
+                result?;
+                let result = self.eval_shard_expr(env, body, shard)?;
+                env.pop(ck);
+                Ok(result)
+            }
+            Expr::DictLit { .. } => {
+                // The dict literal increments the depth when we enter it. Dicts
+                // nested inside it are deeper, so the filter only applies to
+                // the keys of this dict.
+                self.shard_depth = Some(self.eval_depth + 1);
+                let result = self.eval_expr(env, expr);
+                self.shard_depth = None;
+                result
+            }
+            _ if shard.index == 0 => self.eval_expr(env, expr),
+            _ => Ok(Value::Dict(SmallDict::new())),
+        }
+    }
+
     /// Evaluate a document for an import.
     fn eval_import(&mut self, doc: DocId, imported_from: Span) -> Result<Value> {
         // Before we allow the import, check that this would not create a cycle.
@@ -241,19 +444,29 @@ impl<'a> Evaluator<'a> {
 
         // Evaluate the import in its own clean environment, it should not be
         // affected by the surrounding environment of the import statement.
-        let mut type_env = typecheck::prelude();
-        let mut value_env = runtime::prelude();
-
-        let expr = self.loader.get_typechecked_ast(&mut type_env, doc)?;
+        // The loader typechecks imports in the prelude, and caches the result.
+        let mut value_env = self.loader.value_prelude();
+        let expr = self.loader.get_import_ast(doc, self.tracer)?;
         let ctx = EvalContext {
             doc,
             imported_from: Some(imported_from),
         };
 
+        // Every document gets its own step budget. This way, the budget error
+        // points at the document that loops, not at whichever document happens
+        // to exhaust a budget that was partially consumed by others.
+        let outer_count = std::mem::replace(&mut self.eval_count, EvalCount::new());
+
         self.import_stack.push(ctx);
-        let result = self.eval_expr(&mut value_env, &expr)?;
+        let result = self.eval_framed(imported_from, "In import.", |this| {
+            this.eval_profiled(Frame::Document(doc), |this| {
+                this.eval_expr(&mut value_env, &expr)
+            })
+        })?;
         self.import_stack.pop().expect("Push/pop are balanced.");
 
+        self.eval_count = outer_count;
+
         Ok(result)
     }
 
@@ -262,6 +475,7 @@ impl<'a> Evaluator<'a> {
             Expr::Import {
                 path_span,
                 path: path_expr,
+                format,
             } => {
                 // We could allow an arbitrary expression that evaluates to a
                 // string, but for now we limit ourselves to string literals. It
@@ -293,7 +507,10 @@ impl<'a> Evaluator<'a> {
                         }
                         err
                     })?;
-                self.eval_import(doc, *path_span)
+                match format {
+                    ImportFormat::Rcl => self.eval_import(doc, *path_span),
+                    ImportFormat::Text => Ok(Value::String(self.loader.get_doc(doc).data.into())),
+                }
             }
 
             // coverage:off -- Code not expected to be reached.
@@ -304,42 +521,132 @@ impl<'a> Evaluator<'a> {
 
             // Brackets are syntactically lists, we already know that.
             Expr::BracketLit { open, elements } => {
-                let mut out = Vec::with_capacity(elements.len());
+                let mut out = SmallList::new();
+                let mut spans = Vec::new();
+                let record = self.loader.source_map_mut().is_some();
                 self.inc_eval_depth(*open)?;
-                for seq in elements {
-                    self.eval_seq(env, seq, &mut |v| out.push(v), &mut |_, _| {
-                        unreachable!("Typechecker ensures scalar elements.")
-                    })?;
-                }
+                self.with_value_path(Some(PathElement::Index(0)), |this| {
+                    for seq in elements {
+                        this.eval_seq(
+                            env,
+                            seq,
+                            None,
+                            &mut |site, v| {
+                                out.push(v);
+                                if record {
+                                    spans.push(site.provenance());
+                                }
+                            },
+                            &mut |_, _, _| unreachable!("Typechecker ensures scalar elements."),
+                        )?;
+                    }
+                    Ok(())
+                })?;
                 self.dec_eval_depth();
-                Ok(Value::List(Rc::new(out)))
+                self.count_alloc();
+                let result = Value::List(out);
+                if let Some(source_map) = self.loader.source_map_mut() {
+                    source_map.record(&result, ElementSpans::Seq(spans));
+                }
+                Ok(result)
             }
 
             Expr::SetLit { open, elements } => {
                 let mut out = BTreeSet::new();
+                let mut spans = BTreeMap::new();
+                let record = self.loader.source_map_mut().is_some();
                 self.inc_eval_depth(*open)?;
-                for seq in elements {
-                    self.eval_seq(env, seq, &mut |v| _ = out.insert(v), &mut |_, _| {
-                        unreachable!("Typechecker ensures scalar elements.")
-                    })?;
-                }
+                self.with_value_path(None, |this| {
+                    for seq in elements {
+                        this.eval_seq(
+                            env,
+                            seq,
+                            None,
+                            &mut |site, v| {
+                                if record {
+                                    spans.insert(v.clone(), site.provenance());
+                                }
+                                out.insert(v);
+                            },
+                            &mut |_, _, _| unreachable!("Typechecker ensures scalar elements."),
+                        )?;
+                    }
+                    Ok(())
+                })?;
                 self.dec_eval_depth();
-                Ok(Value::Set(Rc::new(out)))
+                self.count_alloc();
+                let result = Value::Set(Rc::new(out));
+                if let Some(source_map) = self.loader.source_map_mut() {
+                    source_map.record(&result, ElementSpans::Keyed(spans));
+                }
+                Ok(result)
             }
 
             Expr::DictLit { open, elements } => {
-                let mut out = BTreeMap::new();
+                let mut out = SmallDict::new();
+                let mut spans = BTreeMap::new();
+                let record = self.loader.source_map_mut().is_some();
                 self.inc_eval_depth(*open)?;
                 for seq in elements {
                     self.eval_seq(
                         env,
                         seq,
-                        &mut |_| unreachable!("Typechecker ensures assoc elements."),
-                        &mut |k, v| _ = out.insert(k, v),
+                        None,
+                        &mut |_, _| unreachable!("Typechecker ensures assoc elements."),
+                        &mut |site, k, v| {
+                            if record {
+                                spans.insert(k.clone(), site.provenance());
+                            }
+                            out.insert(k, v);
+                        },
                     )?;
                 }
                 self.dec_eval_depth();
-                Ok(Value::Dict(Rc::new(out)))
+                self.count_alloc();
+                let result = Value::Dict(out);
+                if let Some(source_map) = self.loader.source_map_mut() {
+                    source_map.record(&result, ElementSpans::Keyed(spans));
+                }
+                Ok(result)
+            }
+
+            Expr::TupleLit { elements } => {
+                let mut out = Vec::with_capacity(elements.len());
+                for (_span, elem) in elements {
+                    out.push(self.eval_expr(env, elem)?);
+                }
+                Ok(Value::Tuple(out.into()))
+            }
+
+            Expr::TaggedLit { tag, value, .. } => {
+                let v = self.eval_expr(env, value)?;
+                Ok(Value::Tagged(tag.clone(), Rc::new(v)))
+            }
+
+            Expr::Match {
+                scrutinee_span,
+                scrutinee,
+                arms,
+            } => {
+                self.inc_eval_depth(*scrutinee_span)?;
+                let value = self.eval_expr(env, scrutinee)?;
+                for arm in arms {
+                    let ck = env.checkpoint();
+                    if !match_pattern(env, &arm.pattern, &value) {
+                        env.pop(ck);
+                        continue;
+                    }
+                    let result = self.eval_expr(env, &arm.body)?;
+                    env.pop(ck);
+                    self.dec_eval_depth();
+                    return Ok(result);
+                }
+                scrutinee_span
+                    .error("No arm matches this value.")
+                    .with_body(concat! {
+                        "Found: " format_rcl_limited(&value, self.loader.error_limits()).into_owned() "."
+                    })
+                    .err()
             }
 
             Expr::NullLit => Ok(Value::Null),
@@ -348,7 +655,10 @@ impl<'a> Evaluator<'a> {
 
             Expr::IntegerLit(i) => Ok(Value::Int(*i)),
 
-            Expr::StringLit(s) => Ok(Value::String(s.clone())),
+            Expr::FloatLit(x) => Ok(Value::Float(*x)),
+
+            Expr::StringLit(s) => Ok(Value::String(s.into())),
+            Expr::BytesLit(b) => Ok(Value::Bytes(b.clone())),
 
             Expr::Format(fragments) => self.eval_format(env, fragments),
 
@@ -388,46 +698,61 @@ impl<'a> Evaluator<'a> {
                 self.inc_eval_depth(*inner_span)?;
                 let inner = self.eval_expr(env, inner_expr)?;
                 self.dec_eval_depth();
-                let field_name_value = Value::String(field_name.0.clone());
+                let field_name_value = Value::String((&field_name.0).into());
 
                 let builtin = match (&inner, field_name.as_ref()) {
                     (Value::String(_), "chars") => Some(&stdlib::STRING_CHARS),
                     (Value::String(_), "contains") => Some(&stdlib::STRING_CONTAINS),
                     (Value::String(_), "ends_with") => Some(&stdlib::STRING_ENDS_WITH),
+                    (Value::String(_), "graphemes") => Some(&stdlib::STRING_GRAPHEMES),
                     (Value::String(_), "len") => Some(&stdlib::STRING_LEN),
                     (Value::String(_), "parse_int") => Some(&stdlib::STRING_PARSE_INT),
+                    (Value::String(_), "parse_key_value") => Some(&stdlib::STRING_PARSE_KEY_VALUE),
                     (Value::String(_), "remove_prefix") => Some(&stdlib::STRING_REMOVE_PREFIX),
                     (Value::String(_), "remove_suffix") => Some(&stdlib::STRING_REMOVE_SUFFIX),
                     (Value::String(_), "replace") => Some(&stdlib::STRING_REPLACE),
                     (Value::String(_), "split") => Some(&stdlib::STRING_SPLIT),
                     (Value::String(_), "split_lines") => Some(&stdlib::STRING_SPLIT_LINES),
                     (Value::String(_), "starts_with") => Some(&stdlib::STRING_STARTS_WITH),
+                    (Value::String(_), "strip_comments") => Some(&stdlib::STRING_STRIP_COMMENTS),
                     (Value::String(_), "to_lowercase") => Some(&stdlib::STRING_TO_LOWERCASE),
                     (Value::String(_), "to_uppercase") => Some(&stdlib::STRING_TO_UPPERCASE),
+                    (Value::String(_), "to_utf8") => Some(&stdlib::STRING_TO_UTF8),
+
+                    (Value::Bytes(_), "len") => Some(&stdlib::BYTES_LEN),
+                    (Value::Bytes(_), "parse_utf8") => Some(&stdlib::BYTES_PARSE_UTF8),
+                    (Value::Bytes(_), "to_base64") => Some(&stdlib::BYTES_TO_BASE64),
+                    (Value::Bytes(_), "to_hex") => Some(&stdlib::BYTES_TO_HEX),
 
                     (Value::Dict(_), "contains") => Some(&stdlib::DICT_CONTAINS),
                     (Value::Dict(_), "except") => Some(&stdlib::DICT_EXCEPT),
                     (Value::Dict(_), "get") => Some(&stdlib::DICT_GET),
                     (Value::Dict(_), "keys") => Some(&stdlib::DICT_KEYS),
                     (Value::Dict(_), "len") => Some(&stdlib::DICT_LEN),
+                    (Value::Dict(_), "sorted_items") => Some(&stdlib::DICT_SORTED_ITEMS),
                     (Value::Dict(_), "values") => Some(&stdlib::DICT_VALUES),
                     (Value::Dict(fields), _field_name) => {
                         // If it wasn't a builtin, look for a key in the dict.
                         return match fields.get(&field_name_value) {
                             Some(v) => Ok(v.clone()),
                             None => {
-                                return field_span
-                                    .error("Unknown field.")
-                                    .with_note(
-                                        *inner_span,
-                                        concat! {
-                                            // TODO: Printing the full value may be overkill,
-                                            // the full value could be very large. We
-                                            // could print the dict keys here.
-                                            "On value: " format_rcl(&inner).into_owned()
-                                        },
-                                    )
-                                    .err();
+                                let mut error = field_span.error("Unknown field.").with_note(
+                                    *inner_span,
+                                    concat! {
+                                        "On value: " format_rcl_limited(&inner, self.loader.error_limits()).into_owned()
+                                    },
+                                );
+                                // If this is a builtin that is absent because
+                                // its capability is not enabled, explain that.
+                                if let (Expr::Var { ident, .. }, Some(capability)) = (
+                                    inner_expr.as_ref(),
+                                    stdlib::builtin_capability(field_name.as_ref()),
+                                ) {
+                                    if ident.as_ref() == "std" {
+                                        error = error.with_help(capability.disabled_help());
+                                    }
+                                }
+                                return error.err();
                             }
                         };
                     }
@@ -473,9 +798,7 @@ impl<'a> Evaluator<'a> {
                             .with_note(
                                 *inner_span,
                                 concat! {
-                                    // TODO: Printing the full value may be overkill,
-                                    // the full value could be very large.
-                                    "On value: " format_rcl(&inner).into_owned()
+                                    "On value: " format_rcl_limited(&inner, self.loader.error_limits()).into_owned()
                                 },
                             )
                             .err()
@@ -571,21 +894,38 @@ impl<'a> Evaluator<'a> {
             Expr::BinOp {
                 op,
                 op_span,
+                lhs_span,
                 lhs: lhs_expr,
+                rhs_span,
                 rhs: rhs_expr,
-                ..
             } => {
                 self.inc_eval_depth(*op_span)?;
                 let lhs = self.eval_expr(env, lhs_expr)?;
                 let rhs = self.eval_expr(env, rhs_expr)?;
+                // To record where the elements of a union came from, we need
+                // the operands after the operation, the clones are cheap.
+                let operands = match (op, self.loader.source_map_mut()) {
+                    (BinOp::Union, Some(..)) => Some((lhs.clone(), rhs.clone())),
+                    _ => None,
+                };
                 let result = self.eval_binop(*op, *op_span, lhs, rhs)?;
+                if let (Some((lhs, rhs)), Some(source_map)) =
+                    (operands, self.loader.source_map_mut())
+                {
+                    source_map.record_union(
+                        *op_span,
+                        (*lhs_span, &lhs),
+                        (*rhs_span, &rhs),
+                        &result,
+                    );
+                }
                 self.dec_eval_depth();
                 Ok(result)
             }
 
             Expr::CheckType { span, type_, body } => {
                 let v = self.eval_expr(env, body)?;
-                v.is_instance_of(*span, type_)?;
+                v.is_instance_of(*span, type_, self.loader.error_limits())?;
                 Ok(v)
             }
         }
@@ -640,11 +980,44 @@ impl<'a> Evaluator<'a> {
                     .into()
                 })
             }
+            Value::Partial(partial) => {
+                partial
+                    .type_
+                    .check_arity(None, call.args, call.call_close)?;
+                let mut args = Vec::with_capacity(call.args.len() + 1);
+                args.push(CallArg {
+                    span: partial.arg_span,
+                    value: partial.arg.clone(),
+                });
+                args.extend(call.args.iter().cloned());
+                let inner_call = FunctionCall {
+                    call_open: call.call_open,
+                    call_close: call.call_close,
+                    args: &args,
+                };
+                self.eval_call(partial.function_span, &partial.function, inner_call)
+            }
+            Value::Memoized(memo) => {
+                let args = call.args.iter().map(|arg| arg.value.clone()).collect();
+                let key = (memo.function.clone(), args);
+                match self.memo_cache.get(&key) {
+                    Some(result) => Ok(result.clone()),
+                    None => self
+                        .eval_call(memo.function_span, &memo.function, call)
+                        .map(|result| {
+                            self.memo_cache.insert(key, result.clone());
+                            result
+                        }),
+                }
+            }
             Value::Function(fun) => {
                 fun.type_.check_arity(None, call.args, call.call_close)?;
                 // TODO: Also perform typechecks of the arguments.
 
-                self.eval_function_call(fun, call).map_err(|err| {
+                self.eval_framed(call_open, "In call to function.", |this| {
+                    this.eval_function_call(fun, call)
+                })
+                .map_err(|err| {
                     err.with_call_frame(call_open, "In call to function.")
                         .into()
                 })
@@ -675,34 +1048,61 @@ impl<'a> Evaluator<'a> {
             env.push(arg_name.clone(), value.clone());
         }
 
-        self.eval_expr(&mut env, fun.body.as_ref())
+        self.debug_check(&mut env, fun.span, PausePoint::Step)?;
+        self.eval_profiled(Frame::Function(fun.span), |this| {
+            this.eval_expr(&mut env, fun.body.as_ref())
+        })
     }
 
     /// While joining values for string formatting, push one fragment.
     ///
     /// This powers both format strings as well as `List.join`.
-    pub fn push_format_fragment(out: &mut Vec<Rc<str>>, span: Span, value: &Value) -> Result<()> {
+    pub fn push_format_fragment(
+        &mut self,
+        out: &mut Vec<SmallStr>,
+        span: Span,
+        value: &Value,
+    ) -> Result<()> {
+        if let Value::Bool(..) | Value::Null = value {
+            self.check_implicit(
+                span,
+                concat! {
+                    "Implicit conversion of "
+                    Doc::highlight(value.type_name())
+                    " to "
+                    Doc::highlight("String")
+                    "."
+                },
+                "In strict mode, only strings and integers can be formatted into strings.",
+            )?;
+        }
         match value {
             Value::Bool(b) => out.push((if *b { "true" } else { "false" }).into()),
             Value::Int(i) => out.push(i.to_string().into()),
+            Value::Float(x) => out.push(x.to_string().into()),
             Value::Null => out.push("null".into()),
             Value::String(s) => out.push(s.clone()),
             not_formattable => {
-                return span
-                    .error(concat! {
-                        "This value cannot be interpolated into a string:"
-                        Doc::HardBreak
-                        Doc::HardBreak
-                        indent! { format_rcl(not_formattable).into_owned() }
-                    })
-                    .err();
+                let mut error = span.error(concat! {
+                    "This value cannot be interpolated into a string:"
+                    Doc::HardBreak
+                    Doc::HardBreak
+                    indent! { format_rcl_limited(not_formattable, self.loader.error_limits()).into_owned() }
+                });
+                if let Value::Secret(..) = not_formattable {
+                    error.set_help(
+                        "Secrets are opaque during evaluation, \
+                        they only get their contents when exported.",
+                    );
+                }
+                return error.err();
             }
         }
         Ok(())
     }
 
     /// Join fragments pushed by [`push_format_fragment`] into one string.
-    pub fn join_format_fragments(fragments: Vec<Rc<str>>) -> Value {
+    pub fn join_format_fragments(fragments: Vec<SmallStr>) -> Value {
         let mut result = String::with_capacity(fragments.iter().map(|s| s.len()).sum());
 
         for s in fragments {
@@ -718,9 +1118,10 @@ impl<'a> Evaluator<'a> {
 
         for fragment in fragments {
             let value = self.eval_expr(env, &fragment.body)?;
-            Evaluator::push_format_fragment(&mut results, fragment.span, &value)?;
+            self.push_format_fragment(&mut results, fragment.span, &value)?;
         }
 
+        self.count_alloc();
         Ok(Evaluator::join_format_fragments(results))
     }
 
@@ -733,17 +1134,14 @@ impl<'a> Evaluator<'a> {
         index_span: Span,
     ) -> Result<Value> {
         match collection {
-            Value::List(xs) => self.eval_index_list(&xs, index, index_span),
+            Value::List(xs) => self.eval_index_list(&xs, "list", index, index_span),
+            Value::Tuple(xs) => self.eval_index_list(&xs, "tuple", index, index_span),
             Value::Dict(dict) => self.eval_index_dict(&dict, collection_span, index, index_span),
-            // TODO: Implement indexing into strings.
-            Value::String(..) => open_span
-                .error("Indexing into a string is not yet supported.")
-                .with_note(collection_span, "This is a string.")
-                .err(),
+            Value::String(s) => self.eval_index_string(&s, index, index_span),
             not_indexable => {
                 let note = concat! {
-                    "Expected a dict or list, but found: "
-                    format_rcl(&not_indexable).into_owned()
+                    "Expected a dict, list, string, or tuple, but found: "
+                    format_rcl_limited(&not_indexable, self.loader.error_limits()).into_owned()
                     "."
                 };
                 open_span
@@ -754,9 +1152,17 @@ impl<'a> Evaluator<'a> {
         }
     }
 
-    fn eval_index_list(&mut self, list: &[Value], index: Value, index_span: Span) -> Result<Value> {
-        let i_signed = match index {
-            Value::Int(i) => i,
+    /// Index into a list or tuple, `kind` names which of the two it is.
+    fn eval_index_list(
+        &mut self,
+        list: &[Value],
+        kind: &'static str,
+        index: Value,
+        index_span: Span,
+    ) -> Result<Value> {
+        let i_signed = match (index, kind) {
+            (Value::Int(i), _) => i,
+            (_, "tuple") => return index_span.error("Tuple index must be an integer.").err(),
             _ => return index_span.error("List index must be an integer.").err(),
         };
 
@@ -769,7 +1175,9 @@ impl<'a> Evaluator<'a> {
                 let error = concat! {
                     "Index "
                     i_signed.to_string()
-                    " is out of bounds for list of length "
+                    " is out of bounds for "
+                    kind
+                    " of length "
                     list.len().to_string()
                     "."
                 };
@@ -780,9 +1188,34 @@ impl<'a> Evaluator<'a> {
         Ok(list[i].clone())
     }
 
+    /// Index into a string, counting Unicode scalar values (chars).
+    fn eval_index_string(&mut self, s: &str, index: Value, index_span: Span) -> Result<Value> {
+        let i_signed = match index {
+            Value::Int(i) => i,
+            _ => return index_span.error("String index must be an integer.").err(),
+        };
+        let len = s.chars().count();
+        let i = match i_signed {
+            _ if i_signed >= 0 && (i_signed as usize) < len => i_signed as usize,
+            _ if i_signed >= -(len as i64) && i_signed < 0 => len - (-i_signed as usize),
+            _ => {
+                let error = concat! {
+                    "Index "
+                    i_signed.to_string()
+                    " is out of bounds for string of length "
+                    len.to_string()
+                    "."
+                };
+                return index_span.error(error).err();
+            }
+        };
+        let (start, ch) = s.char_indices().nth(i).expect("Index is in bounds.");
+        Ok(Value::String(s[start..start + ch.len_utf8()].into()))
+    }
+
     fn eval_index_dict(
         &mut self,
-        dict: &BTreeMap<Value, Value>,
+        dict: &SmallDict,
         dict_span: Span,
         index: Value,
         index_span: Span,
@@ -791,14 +1224,14 @@ impl<'a> Evaluator<'a> {
             None => index_span
                 .error(concat! {
                     "Dict does not have a key "
-                    format_rcl(&index).into_owned()
+                    format_rcl_limited(&index, self.loader.error_limits()).into_owned()
                     "."
                 })
                 .with_note(
                     dict_span,
                     concat! {
                         "On value: "
-                        fmt_rcl::dict(dict.iter()).into_owned()
+                        fmt_rcl::dict(dict.iter(), Limits::default()).into_owned()
                     },
                 )
                 .err(),
@@ -825,11 +1258,11 @@ impl<'a> Evaluator<'a> {
     fn eval_binop(&mut self, op: BinOp, op_span: Span, lhs: Value, rhs: Value) -> Result<Value> {
         match (op, lhs, rhs) {
             (BinOp::Union, Value::Dict(xs), Value::Dict(ys)) => {
-                let mut result = (*xs).clone();
+                let mut result = xs;
                 for (k, v) in ys.iter() {
                     result.insert(k.clone(), v.clone());
                 }
-                Ok(Value::Dict(Rc::new(result)))
+                Ok(Value::Dict(result))
             }
             (BinOp::Union, Value::Set(xs), Value::Set(ys)) => {
                 let result = xs.union(ys.as_ref()).cloned().collect();
@@ -915,14 +1348,211 @@ impl<'a> Evaluator<'a> {
             // enabling comparing values of different types. Or do we want to allow
             // comparing arbitrary values after all? Hmm ... So far I haven't felt
             // the need to allow comparing anything but int for inequalities. So
-            // maybe it should be a type error?
-            (BinOp::Eq, x, y) => Ok(Value::Bool(x == y)),
-            (BinOp::Neq, x, y) => Ok(Value::Bool(x != y)),
+            // maybe it should be a type error? For now, strict mode rejects it.
+            (BinOp::Eq | BinOp::Neq, x, y) => {
+                self.check_comparison(op_span, &x, &y)?;
+                match op {
+                    BinOp::Eq => Ok(Value::Bool(x == y)),
+                    _ => Ok(Value::Bool(x != y)),
+                }
+            }
             _ => unreachable!("Invalid cases are prevented by the typechecker."),
         }
     }
 
+    /// Report comparisons between values of different types in strict mode.
+    ///
+    /// Such comparisons are always false. Comparing against null is exempt,
+    /// because that is how optional values are tested.
+    fn check_comparison(&mut self, op_span: Span, lhs: &Value, rhs: &Value) -> Result<()> {
+        let (lhs_type, rhs_type) = (lhs.type_name(), rhs.type_name());
+        if lhs_type == rhs_type || *lhs == Value::Null || *rhs == Value::Null {
+            return Ok(());
+        }
+        self.check_implicit(
+            op_span,
+            concat! {
+                "Comparison between "
+                Doc::highlight(lhs_type)
+                " and "
+                Doc::highlight(rhs_type)
+                " is always false."
+            },
+            "In strict mode, only values of the same type can be compared.",
+        )
+    }
+
+    /// Evaluate `f` with a frame for the debugger's backtrace, if debugging.
+    fn eval_framed<T, F>(&mut self, at: Span, frame: &'static str, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        if let Some(debugger) = self.debugger.as_mut() {
+            debugger.call_stack.push((at, frame));
+        }
+        let result = f(self);
+        if let Some(debugger) = self.debugger.as_mut() {
+            debugger.call_stack.pop();
+        }
+        result
+    }
+
+    /// Evaluate `f` in a profiler frame, if profiling.
+    fn eval_profiled<T, F>(&mut self, frame: Frame, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        if let Some(profiler) = self.loader.profiler_mut() {
+            profiler.enter(frame);
+        }
+        let result = f(self);
+        if let Some(profiler) = self.loader.profiler_mut() {
+            profiler.exit();
+        }
+        result
+    }
+
+    /// Count an allocation of a collection or string in the profile, if profiling.
+    fn count_alloc(&mut self) {
+        if let Some(profiler) = self.loader.profiler_mut() {
+            profiler.count_alloc();
+        }
+    }
+
+    /// Pause in the debugger, if we are debugging and it wants to pause here.
+    fn debug_check(&mut self, env: &mut Env, span: Span, point: PausePoint) -> Result<()> {
+        let debugger = match self.debugger.take() {
+            None => return Ok(()),
+            Some(debugger) => debugger,
+        };
+        let reason = debugger.should_pause(&self.loader.get_doc(span.doc()), span, point);
+        let result = match reason {
+            Some(reason) => self.debug_pause(debugger, env, span, reason),
+            None => Ok(()),
+        };
+        self.debugger = Some(debugger);
+        result
+    }
+
+    /// Show where we paused, and handle debugger commands until we resume.
+    ///
+    /// The debugger is taken out of the evaluator while paused, so evaluating
+    /// an expression at the prompt does not pause.
+    fn debug_pause(
+        &mut self,
+        debugger: &mut Debugger,
+        env: &mut Env,
+        span: Span,
+        reason: &str,
+    ) -> Result<()> {
+        let cfg = pprint::Config { width: 80 };
+        let render = |doc: Doc| doc.println(&cfg).to_string_no_markup();
+        let here = render(highlight_span(
+            &self.loader.as_inputs(),
+            span,
+            Markup::Highlight,
+        ));
+        debugger.print(&here);
+        debugger.println(reason);
+
+        // The variables in scope, without the prelude, and without the
+        // variables that are shadowed.
+        let prelude_len = self.loader.value_prelude().bindings().len();
+        let bindings = env.bindings();
+        let locals: Vec<(Ident, Value)> = bindings
+            .iter()
+            .enumerate()
+            .skip(prelude_len)
+            .filter(|(i, (name, _))| bindings[i + 1..].iter().all(|(other, _)| other != name))
+            .map(|(_, binding)| binding.clone())
+            .collect();
+
+        loop {
+            let cmd = debugger.read_command();
+            match cmd {
+                Command::Continue | Command::Step => {
+                    debugger.resume(matches!(cmd, Command::Step));
+                    return Ok(());
+                }
+                Command::Quit => return span.error("Evaluation aborted in the debugger.").err(),
+                Command::Help => debugger.println(debugger::USAGE_COMMANDS),
+                Command::Invalid(message) => debugger.println(message),
+                Command::Break(breakpoint) => {
+                    debugger.println(&format!(
+                        "Added breakpoint at {}:{}.",
+                        breakpoint.file, breakpoint.line
+                    ));
+                    debugger.add_breakpoint(breakpoint);
+                }
+                Command::Locals => {
+                    if locals.is_empty() {
+                        debugger.println("There are no variables in scope.");
+                    }
+                    let limits = Limits {
+                        max_depth: Some(2),
+                        max_items: Some(5),
+                    };
+                    for (name, value) in locals.iter() {
+                        let doc = concat! {
+                            name.as_ref() " = " fmt_rcl::format_rcl_limited(value, limits)
+                        };
+                        debugger.print(&render(doc));
+                    }
+                }
+                Command::Backtrace => {
+                    let doc = self.loader.get_doc(span.doc());
+                    let here = debugger.location(&doc, span);
+                    debugger.println(&format!("#0 {here}"));
+                    let frames: Vec<(Span, &str)> =
+                        debugger.call_stack.iter().rev().copied().collect();
+                    for (i, (at, frame)) in frames.into_iter().enumerate() {
+                        let doc = self.loader.get_doc(at.doc());
+                        let location = debugger.location(&doc, at);
+                        debugger.println(&format!("#{} {location} {frame}", i + 1));
+                    }
+                }
+                Command::Print(expr) => {
+                    let doc = self.loader.load_string(expr);
+                    let mut type_env = self.loader.type_prelude();
+                    for (name, _value) in locals.iter() {
+                        type_env.push(name.clone(), SourcedType::any());
+                    }
+                    let result = self
+                        .loader
+                        .get_typechecked_ast(&mut type_env, doc, self.tracer)
+                        .and_then(|expr| self.eval_expr(env, &expr));
+                    let output = match result {
+                        Ok(value) => render(format_rcl(&value)),
+                        Err(err) => render(err.report(&self.loader.as_inputs())),
+                    };
+                    debugger.print(&output);
+                }
+            }
+        }
+    }
+
     fn eval_stmt(&mut self, env: &mut Env, stmt: &Stmt) -> Result<()> {
+        if self.debugger.is_some() {
+            let span = match stmt {
+                Stmt::Let {
+                    ident_span,
+                    value_span,
+                    ..
+                } => ident_span.union(*value_span),
+                Stmt::LetTuple {
+                    idents_span,
+                    value_span,
+                    ..
+                } => idents_span.union(*value_span),
+                Stmt::Assert {
+                    condition_span,
+                    message_span,
+                    ..
+                } => condition_span.union(*message_span),
+                Stmt::Trace { message_span, .. } => *message_span,
+            };
+            self.debug_check(env, span, PausePoint::Step)?;
+        }
         match stmt {
             Stmt::Let { ident, value, .. } => {
                 // Note, this is not a recursive let, the variable is not bound
@@ -932,6 +1562,40 @@ impl<'a> Evaluator<'a> {
                 let v = self.eval_expr(env, value)?;
                 env.push(ident.clone(), v);
             }
+            Stmt::LetTuple {
+                idents_span,
+                idents,
+                value_span,
+                value,
+                ..
+            } => match self.eval_expr(env, value)? {
+                Value::Tuple(elems) if elems.len() == idents.len() => {
+                    for ((_span, ident), elem) in idents.iter().zip(elems.iter()) {
+                        env.push(ident.clone(), elem.clone());
+                    }
+                }
+                Value::Tuple(elems) => {
+                    let note = match elems.len() {
+                        1 => "This is a tuple with 1 element.".into(),
+                        n => concat! { "This is a tuple with " n.to_string() " elements." },
+                    };
+                    return idents_span
+                        .error("Number of variables does not match the tuple length.")
+                        .with_note(*value_span, note)
+                        .err();
+                }
+                not_tuple => {
+                    let note = concat! {
+                        "Expected a tuple, but found: "
+                        format_rcl_limited(&not_tuple, self.loader.error_limits()).into_owned()
+                        "."
+                    };
+                    return idents_span
+                        .error("Only tuples can be destructured.")
+                        .with_note(*value_span, note)
+                        .err();
+                }
+            },
             Stmt::Assert {
                 condition_span,
                 condition,
@@ -964,8 +1628,11 @@ impl<'a> Evaluator<'a> {
                 message: message_expr,
             } => {
                 let message = self.eval_expr(env, message_expr)?;
-                self.tracer
-                    .trace(&self.loader.as_inputs(), *message_span, &message);
+                if !self.is_muted {
+                    self.tracer
+                        .trace(&self.loader.as_inputs(), *message_span, &message);
+                }
+                self.debug_check(env, *message_span, PausePoint::Trace)?;
             }
         }
         Ok(())
@@ -975,29 +1642,56 @@ impl<'a> Evaluator<'a> {
         &mut self,
         env: &mut Env,
         seq: &Seq,
+        comprehension: Option<Span>,
         on_scalar: &mut OnScalar,
         on_assoc: &mut OnAssoc,
     ) -> Result<()>
     where
-        OnScalar: FnMut(Value),
-        OnAssoc: FnMut(Value, Value),
+        OnScalar: FnMut(YieldSite, Value),
+        OnAssoc: FnMut(YieldSite, Value, Value),
     {
         match seq {
             Seq::Yield(Yield::Elem {
-                value: value_expr, ..
+                span,
+                value: value_expr,
             }) => {
+                self.debug_check(env, *span, PausePoint::Step)?;
                 let value = self.eval_expr(env, value_expr)?;
-                on_scalar(value);
+                let site = YieldSite {
+                    span: *span,
+                    comprehension,
+                };
+                on_scalar(site, value);
+                if let Some(Some(PathElement::Index(i))) = self.value_path.last_mut() {
+                    *i += 1;
+                }
                 Ok(())
             }
             Seq::Yield(Yield::Assoc {
+                op_span,
+                key_span,
                 key: key_expr,
                 value: value_expr,
-                ..
+                value_span,
             }) => {
+                if self.debugger.is_some() {
+                    let span = key_span.union(*op_span).union(*value_span);
+                    self.debug_check(env, span, PausePoint::Step)?;
+                }
                 let key = self.eval_expr(env, key_expr)?;
-                let value = self.eval_expr(env, value_expr)?;
-                on_assoc(key, value);
+                if let (Some(shard), Some(depth)) = (self.shard, self.shard_depth) {
+                    if depth == self.eval_depth && !shard.contains(&key) {
+                        return Ok(());
+                    }
+                }
+                let value = self.with_value_path(Some(PathElement::Key(key.clone())), |this| {
+                    this.eval_expr(env, value_expr)
+                })?;
+                let site = YieldSite {
+                    span: *value_span,
+                    comprehension,
+                };
+                on_assoc(site, key, value);
                 Ok(())
             }
             Seq::For {
@@ -1007,66 +1701,26 @@ impl<'a> Evaluator<'a> {
                 collection,
                 body,
             } => {
-                let collection_value = self.eval_expr(env, collection)?;
-                match (&idents[..], collection_value) {
-                    ([name], Value::List(xs)) => {
-                        for x in xs.iter() {
-                            let ck = env.push(name.clone(), x.clone());
-                            self.eval_seq(env, body, on_scalar, on_assoc)?;
-                            env.pop(ck);
-                        }
-                        Ok(())
-                    }
-                    (_names, Value::List(..)) => {
-                        let err = idents_span.error("Expected a single variable.").with_note(
-                            *collection_span,
-                            "This is a list, it yields one element per iteration.",
-                        );
-                        Err(err.into())
-                    }
-                    ([name], Value::Set(xs)) => {
-                        for x in xs.iter() {
-                            let ck = env.push(name.clone(), x.clone());
-                            self.eval_seq(env, body, on_scalar, on_assoc)?;
-                            env.pop(ck);
-                        }
-                        Ok(())
-                    }
-                    (_names, Value::Set(..)) => {
-                        let err = idents_span.error("Expected a single variable.").with_note(
-                            *collection_span,
-                            "This is a set, it yields one element per iteration.",
-                        );
-                        Err(err.into())
-                    }
-                    ([k_name, v_name], Value::Dict(xs)) => {
-                        for (k, v) in xs.iter() {
-                            let ck = env.checkpoint();
-                            env.push(k_name.clone(), k.clone());
-                            env.push(v_name.clone(), v.clone());
-                            self.eval_seq(env, body, on_scalar, on_assoc)?;
-                            env.pop(ck);
-                        }
-                        Ok(())
-                    }
-                    (_names, Value::Dict(..)) => {
-                        let err = idents_span
-                            .error("Expected two variables in dict iteration.")
-                            .with_note(
-                                *collection_span,
-                                "This is a dict, it yields a key and value per iteration.",
-                            );
-                        Err(err.into())
-                    }
-                    _ => Err(collection_span.error("This is not iterable.").into()),
-                }
+                let span = idents_span.union(*collection_span);
+                self.eval_profiled(Frame::Comprehension(span), |this| {
+                    this.eval_for(
+                        env,
+                        (idents_span, idents),
+                        (collection_span, collection),
+                        body,
+                        on_scalar,
+                        on_assoc,
+                    )
+                })
             }
             Seq::If {
                 condition, body, ..
             } => {
                 let cond = self.eval_expr(env, condition)?;
                 match cond {
-                    Value::Bool(true) => self.eval_seq(env, body, on_scalar, on_assoc),
+                    Value::Bool(true) => {
+                        self.eval_seq(env, body, comprehension, on_scalar, on_assoc)
+                    }
                     Value::Bool(false) => Ok(()),
                     _ => unreachable!("The typechecker ensures the condition is a Bool."),
                 }
@@ -1074,10 +1728,218 @@ impl<'a> Evaluator<'a> {
             Seq::Stmt { stmt, body } => {
                 let ck = env.checkpoint();
                 self.eval_stmt(env, stmt)?;
-                self.eval_seq(env, body, on_scalar, on_assoc)?;
+                self.eval_seq(env, body, comprehension, on_scalar, on_assoc)?;
                 env.pop(ck);
                 Ok(())
             }
         }
     }
+
+    /// Evaluate a `Seq::For` comprehension.
+    fn eval_for<OnScalar, OnAssoc>(
+        &mut self,
+        env: &mut Env,
+        (idents_span, idents): (&Span, &[Ident]),
+        (collection_span, collection): (&Span, &Expr),
+        body: &Seq,
+        on_scalar: &mut OnScalar,
+        on_assoc: &mut OnAssoc,
+    ) -> Result<()>
+    where
+        OnScalar: FnMut(YieldSite, Value),
+        OnAssoc: FnMut(YieldSite, Value, Value),
+    {
+        #[cfg(feature = "arena")]
+        if let Some((open, elements)) = arena::scratch_list(collection) {
+            // The list cannot escape the loop, so we don't need to allocate
+            // it, we can iterate the elements from the scratch arena. Reset
+            // the arena on every path, also when the loop fails halfway.
+            let mark = self.arena.mark();
+            let result = self.eval_for_scratch(
+                env,
+                (idents_span, idents),
+                (collection_span, open, elements),
+                body,
+                on_scalar,
+                on_assoc,
+            );
+            self.arena.reset(mark);
+            return result;
+        }
+
+        let collection_value = self.eval_expr(env, collection)?;
+        let comprehension = Some(idents_span.union(*collection_span));
+        match (idents, collection_value) {
+            (_names, Value::List(xs)) => self.eval_for_list(
+                env,
+                (idents_span, idents),
+                (collection_span, &xs[..]),
+                body,
+                on_scalar,
+                on_assoc,
+            ),
+            ([name], Value::Set(xs)) => {
+                for x in xs.iter() {
+                    let ck = env.push(name.clone(), x.clone());
+                    self.eval_seq(env, body, comprehension, on_scalar, on_assoc)?;
+                    env.pop(ck);
+                }
+                Ok(())
+            }
+            (names, Value::Set(xs)) => {
+                for x in xs.iter() {
+                    let ck = env.checkpoint();
+                    self.push_tuple_elements(env, idents_span, collection_span, names, x)?;
+                    self.eval_seq(env, body, comprehension, on_scalar, on_assoc)?;
+                    env.pop(ck);
+                }
+                Ok(())
+            }
+            ([k_name, v_name], Value::Dict(xs)) => {
+                for (k, v) in xs.iter() {
+                    let ck = env.checkpoint();
+                    env.push(k_name.clone(), k.clone());
+                    env.push(v_name.clone(), v.clone());
+                    self.eval_seq(env, body, comprehension, on_scalar, on_assoc)?;
+                    env.pop(ck);
+                }
+                Ok(())
+            }
+            (_names, Value::Dict(..)) => {
+                let err = idents_span
+                    .error("Expected two variables in dict iteration.")
+                    .with_note(
+                        *collection_span,
+                        "This is a dict, it yields a key and value per iteration.",
+                    );
+                Err(err.into())
+            }
+            _ => Err(collection_span.error("This is not iterable.").into()),
+        }
+    }
+
+    /// Evaluate a `Seq::For` comprehension over a list literal in the scratch arena.
+    ///
+    /// The caller resets the arena afterwards.
+    #[cfg(feature = "arena")]
+    fn eval_for_scratch<OnScalar, OnAssoc>(
+        &mut self,
+        env: &mut Env,
+        idents: (&Span, &[Ident]),
+        (collection_span, open, elements): (&Span, Span, &[Seq]),
+        body: &Seq,
+        on_scalar: &mut OnScalar,
+        on_assoc: &mut OnAssoc,
+    ) -> Result<()>
+    where
+        OnScalar: FnMut(YieldSite, Value),
+        OnAssoc: FnMut(YieldSite, Value, Value),
+    {
+        let start = self.arena.mark();
+        self.eval_scratch_list(env, open, elements)?;
+        let end = self.arena.mark();
+        // Comprehensions in the body push their lists above ours, and reset
+        // before they return, so our elements stay in place.
+        for i in start..end {
+            let x = self.arena.get(i);
+            self.eval_for_list(
+                env,
+                idents,
+                (collection_span, std::slice::from_ref(&x)),
+                body,
+                on_scalar,
+                on_assoc,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Push the elements of a list literal onto the scratch arena.
+    ///
+    /// This is not generic over the callbacks like [`Evaluator::eval_for`] is,
+    /// so the closure types do not nest when comprehensions nest.
+    #[cfg(feature = "arena")]
+    fn eval_scratch_list(&mut self, env: &mut Env, open: Span, elements: &[Seq]) -> Result<()> {
+        let arena = self.arena.clone();
+        self.inc_eval_depth(open)?;
+        self.with_value_path(Some(PathElement::Index(0)), |this| {
+            for seq in elements {
+                this.eval_seq(env, seq, None, &mut |_, v| arena.push(v), &mut |_, _, _| {
+                    unreachable!("Typechecker ensures scalar elements.")
+                })?;
+            }
+            Ok(())
+        })?;
+        self.dec_eval_depth();
+        Ok(())
+    }
+
+    /// Evaluate the body of a `Seq::For` comprehension for every list element.
+    fn eval_for_list<OnScalar, OnAssoc>(
+        &mut self,
+        env: &mut Env,
+        (idents_span, idents): (&Span, &[Ident]),
+        (collection_span, xs): (&Span, &[Value]),
+        body: &Seq,
+        on_scalar: &mut OnScalar,
+        on_assoc: &mut OnAssoc,
+    ) -> Result<()>
+    where
+        OnScalar: FnMut(YieldSite, Value),
+        OnAssoc: FnMut(YieldSite, Value, Value),
+    {
+        let comprehension = Some(idents_span.union(*collection_span));
+        match idents {
+            [name] => {
+                for x in xs.iter() {
+                    let ck = env.push(name.clone(), x.clone());
+                    self.eval_seq(env, body, comprehension, on_scalar, on_assoc)?;
+                    env.pop(ck);
+                }
+            }
+            names => {
+                for x in xs.iter() {
+                    let ck = env.checkpoint();
+                    self.push_tuple_elements(env, idents_span, collection_span, names, x)?;
+                    self.eval_seq(env, body, comprehension, on_scalar, on_assoc)?;
+                    env.pop(ck);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Bind the elements of a tuple yielded by a list or set to the loop variables.
+    fn push_tuple_elements(
+        &self,
+        env: &mut Env,
+        idents_span: &Span,
+        collection_span: &Span,
+        names: &[Ident],
+        element: &Value,
+    ) -> Result<()> {
+        match element {
+            Value::Tuple(elems) if elems.len() == names.len() => {
+                for (name, elem) in names.iter().zip(elems.iter()) {
+                    env.push(name.clone(), elem.clone());
+                }
+                Ok(())
+            }
+            _ => idents_span
+                .error("Cannot unpack the element into these variables.")
+                .with_body(concat! {
+                    "Expected a tuple of "
+                    Doc::string(names.len().to_string()).with_markup(Markup::Number)
+                    " elements, but got:"
+                    Doc::HardBreak Doc::HardBreak
+                    indent! { format_rcl_limited(element, self.loader.error_limits()).into_owned() }
+                })
+                .with_note(
+                    *collection_span,
+                    "Elements come from this collection. \
+                    With a single variable, it binds the entire element.",
+                )
+                .err(),
+        }
+    }
 }
@@ -11,16 +11,52 @@
 //! pretty-printed for formatting.
 
 use crate::ast::UnOp;
-use crate::cst::{Chain, Expr, List, NonCode, Prefixed, Seq, Stmt, StringPart, Type};
-use crate::lexer::{QuoteStyle, StringPrefix};
+use crate::cst::{
+    Chain, Expr, ImportFormat, List, MatchArm, NonCode, Pattern, Prefixed, Seq, Stmt, StringPart,
+    Type,
+};
+use crate::lexer::{Escape, QuoteStyle, StringPrefix};
 use crate::markup::Markup;
 use crate::pprint::{concat, flush_indent, group, indent, Doc};
 use crate::source::Span;
 use crate::string;
 
+/// The maximum length of a variable that keeps its first field in a chain.
+const SHORT_BASE_LEN: usize = 4;
+
+/// Whether the expression is a literal, a variable, or fields of a variable.
+///
+/// These expressions cannot contain non-code, so they never need to break.
+fn is_atomic(expr: &Expr) -> bool {
+    match expr {
+        Expr::NullLit(..)
+        | Expr::BoolLit(..)
+        | Expr::NumHexadecimal(..)
+        | Expr::NumBinary(..)
+        | Expr::NumDecimal(..)
+        | Expr::Var(..) => true,
+        Expr::Chain {
+            base_expr, chain, ..
+        } => {
+            is_atomic(base_expr)
+                && chain
+                    .iter()
+                    .all(|(_, elem)| matches!(elem, Chain::Field { .. }))
+        }
+        _ => false,
+    }
+}
+
+/// Options that make the formatter rewrite more than layout.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct FormatOptions {
+    /// Rewrite string literals to use as few escape sequences as possible.
+    pub normalize_strings: bool,
+}
+
 /// Format a document.
-pub fn format_expr<'a>(input: &'a str, expr: &'a Expr) -> Doc<'a> {
-    Formatter::new(input).expr(expr)
+pub fn format_expr<'a>(input: &'a str, expr: &'a Expr, options: FormatOptions) -> Doc<'a> {
+    Formatter::new(input, options).expr(expr)
 }
 
 /// Helper so we can use methods for resolving spans against the input.
@@ -28,11 +64,12 @@ struct Formatter<'a> {
     // TODO: This could all be more efficient if we resolved on bytestrings, so
     // the code point slicing check can be omitted.
     input: &'a str,
+    options: FormatOptions,
 }
 
 impl<'a> Formatter<'a> {
-    pub fn new(input: &'a str) -> Self {
-        Self { input }
+    pub fn new(input: &'a str, options: FormatOptions) -> Self {
+        Self { input, options }
     }
 
     /// Format the span as-is. It should not contain newlines.
@@ -134,6 +171,117 @@ impl<'a> Formatter<'a> {
         }
     }
 
+    /// Format a `()`-enclosed list of identifiers, e.g. lambda arguments.
+    fn idents(&self, idents: &List<Prefixed<Span>>) -> Doc<'a> {
+        group! {
+            "("
+            self.collection_opening_sep(idents)
+            indent! {
+                Doc::join(
+                    idents.elements.iter().map(|ident| concat! {
+                        self.non_code(&ident.prefix)
+                        self.span(ident.inner)
+                    }),
+                    concat!{ "," Doc::Sep },
+                )
+                self.trailing_comma(idents)
+            }
+            ")"
+        }
+    }
+
+    /// Format an expression for which [`is_atomic`] holds, without breaks.
+    fn atomic(&self, expr: &Expr) -> Doc<'a> {
+        match expr {
+            Expr::Chain {
+                base_expr, chain, ..
+            } => {
+                let mut result = vec![self.atomic(base_expr)];
+                for (_, elem) in chain.iter() {
+                    if let Chain::Field { field } = elem {
+                        result.push(".".into());
+                        result.push(self.span(*field));
+                    }
+                }
+                Doc::Concat(result)
+            }
+            _ => self.expr(expr),
+        }
+    }
+
+    /// Format a hole in a format string, including the braces.
+    fn hole(&self, expr: &Expr) -> Doc<'a> {
+        // Breaking around a variable or field access makes the string harder
+        // to read without making it much shorter, so those stay inline.
+        if is_atomic(expr) {
+            return concat! {
+                Doc::str("{").with_markup(Markup::Escape)
+                self.atomic(expr)
+                Doc::str("}").with_markup(Markup::Escape)
+            };
+        }
+        concat! {
+            Doc::str("{").with_markup(Markup::Escape)
+            // We need soft breaks here in case the expression contains
+            // forced breaks, for example when it has non-code.
+            group! {
+                indent! {
+                    Doc::SoftBreak
+                    self.expr(expr)
+                    Doc::SoftBreak
+                }
+            }
+            Doc::str("}").with_markup(Markup::Escape)
+        }
+    }
+
+    /// Format an escape sequence, minimized if we normalize strings.
+    fn escape(&self, span: Span, escape: Escape, is_format: bool, is_triple: bool) -> Doc<'a> {
+        if !self.options.normalize_strings {
+            return self.span(span).with_markup(Markup::Escape);
+        }
+        match string::minimize_escape(self.input, span, escape, is_format, is_triple) {
+            Some(s) if s.starts_with('\\') => Doc::string(s).with_markup(Markup::Escape),
+            Some(s) => Doc::string(s).with_markup(Markup::String),
+            None => self.span(span).with_markup(Markup::Escape),
+        }
+    }
+
+    /// Format a `"` string as a raw string, if that needs no escapes.
+    ///
+    /// This is the case when the only escape sequences in the string are `\\`,
+    /// for example in Windows paths and regular expressions.
+    fn string_as_raw(&self, parts: &[StringPart]) -> Option<Doc<'a>> {
+        let mut result = vec![Doc::str("r\"").with_markup(Markup::String)];
+        let mut has_backslash = false;
+        for part in parts {
+            match part {
+                StringPart::String(span) => {
+                    result.push(self.raw_span(*span).with_markup(Markup::String));
+                }
+                StringPart::Escape(span, esc) => {
+                    let mut unescaped = String::new();
+                    string::unescape_into(self.input, *span, *esc, &mut unescaped).ok()?;
+                    match string::minimize_escape(self.input, *span, *esc, false, false) {
+                        // Printable characters need no escape in a raw string.
+                        Some(s) if !s.starts_with('\\') => {}
+                        // Only the backslash has an escape that is not needed in
+                        // a raw string. A quote cannot occur in a `"` raw string.
+                        _ if unescaped == "\\" => has_backslash = true,
+                        _ => return None,
+                    }
+                    result.push(Doc::string(unescaped).with_markup(Markup::String));
+                }
+                StringPart::Hole(..) => return None,
+            }
+        }
+        result.push(Doc::str("\"").with_markup(Markup::String));
+        match has_backslash {
+            true => Some(Doc::Concat(result)),
+            false => None,
+        }
+    }
+
     /// Format a `"` or `f"` quoted string or format string.
     fn string_double(&self, open: &'static str, parts: &[StringPart]) -> Doc<'a> {
         let mut result = vec![Doc::str(open).with_markup(Markup::String)];
@@ -143,22 +291,10 @@ impl<'a> Formatter<'a> {
                 StringPart::String(span) => {
                     result.push(self.raw_span(*span).with_markup(Markup::String));
                 }
-                StringPart::Escape(span, _esc) => {
-                    result.push(self.span(*span).with_markup(Markup::Escape));
-                }
-                StringPart::Hole(_span, expr) => {
-                    result.push(Doc::str("{").with_markup(Markup::Escape));
-                    // We need soft breaks here in case the expression contains
-                    // forced breaks, for example when it has non-code.
-                    result.push(group! {
-                        indent! {
-                            Doc::SoftBreak
-                            self.expr(expr)
-                            Doc::SoftBreak
-                        }
-                    });
-                    result.push(Doc::str("}").with_markup(Markup::Escape));
+                StringPart::Escape(span, esc) => {
+                    result.push(self.escape(*span, *esc, open == "f\"", false));
                 }
+                StringPart::Hole(_span, expr) => result.push(self.hole(expr)),
             }
         }
 
@@ -219,22 +355,10 @@ impl<'a> Formatter<'a> {
                         _ => result.push(Doc::from(line).with_markup(Markup::String)),
                     }
                 }
-                StringPart::Escape(span, _esc) => {
-                    result.push(self.span(*span).with_markup(Markup::Escape));
-                }
-                StringPart::Hole(_span, expr) => {
-                    result.push(Doc::str("{").with_markup(Markup::Escape));
-                    // We need soft breaks here in case the expression contains
-                    // forced breaks, for example when it has non-code.
-                    result.push(group! {
-                        indent! {
-                            Doc::SoftBreak
-                            self.expr(expr)
-                            Doc::SoftBreak
-                        }
-                    });
-                    result.push(Doc::str("}").with_markup(Markup::Escape));
+                StringPart::Escape(span, esc) => {
+                    result.push(self.escape(*span, *esc, open == "f\"\"\"", true));
                 }
+                StringPart::Hole(_span, expr) => result.push(self.hole(expr)),
             }
         }
 
@@ -271,6 +395,25 @@ impl<'a> Formatter<'a> {
                 result.push(";".into());
                 Doc::Concat(result)
             }
+            Stmt::LetTuple {
+                idents,
+                value,
+                type_,
+                ..
+            } => {
+                let mut result: Vec<Doc<'a>> = Vec::new();
+                result.push(Doc::str("let").with_markup(Markup::Keyword));
+                result.push(" ".into());
+                result.push(self.idents(idents));
+                if let Some(t) = type_ {
+                    result.push(": ".into());
+                    result.push(self.type_(t));
+                }
+                result.push(" = ".into());
+                result.push(self.expr(value));
+                result.push(";".into());
+                Doc::Concat(result)
+            }
             Stmt::Assert {
                 condition, message, ..
             } => {
@@ -321,13 +464,18 @@ impl<'a> Formatter<'a> {
                 group! { flush_indent! { Doc::Concat(parts) } }
             }
 
-            Expr::Import { path, .. } => {
+            Expr::Import { path, format, .. } => {
+                let format = match format {
+                    ImportFormat::Rcl => Doc::Empty,
+                    ImportFormat::Text => Doc::str(" as text"),
+                };
                 group! {
                     concat! {
                         Doc::str("import").with_markup(Markup::Keyword)
                         indent! {
                             Doc::Sep
                             self.expr(path)
+                            format
                         }
                     }
                 }
@@ -373,6 +521,31 @@ impl<'a> Formatter<'a> {
                 }
             }
 
+            Expr::TupleLit { elements, .. } => match elements.elements.len() {
+                0 if elements.suffix.is_empty() => Doc::str("()"),
+                // A tuple with one element needs the comma in wide mode too, so
+                // unlike for other collections, it does not force tall mode.
+                1 if elements.suffix.is_empty() => group! {
+                    "("
+                    Doc::SoftBreak
+                    indent! { self.expr(&elements.elements[0].1) "," }
+                    Doc::SoftBreak
+                    ")"
+                },
+                _ => group! {
+                    "("
+                    self.collection_opening_sep(elements)
+                    indent! {
+                        Doc::join(
+                            elements.elements.iter().map(|(_span, elem)| self.expr(elem)),
+                            concat!{ "," Doc::Sep },
+                        )
+                        self.trailing_comma(elements)
+                    }
+                    ")"
+                },
+            },
+
             Expr::NullLit(span) => self.span(*span).with_markup(Markup::Keyword),
 
             Expr::BoolLit(span, ..) => self.span(*span).with_markup(Markup::Keyword),
@@ -383,6 +556,9 @@ impl<'a> Formatter<'a> {
                 parts,
                 ..
             } => match style {
+                QuoteStyle::Double if self.options.normalize_strings => self
+                    .string_as_raw(parts)
+                    .unwrap_or_else(|| self.string_double("\"", parts)),
                 QuoteStyle::Double => self.string_double("\"", parts),
                 QuoteStyle::Triple => self.string_triple("\"\"\"", parts),
             },
@@ -395,6 +571,15 @@ impl<'a> Formatter<'a> {
                 QuoteStyle::Double => self.string_double("f\"", parts),
                 QuoteStyle::Triple => self.string_triple("f\"\"\"", parts),
             },
+            Expr::StringLit {
+                prefix: StringPrefix::Raw,
+                style,
+                parts,
+                ..
+            } => match style {
+                QuoteStyle::Double => self.string_double("r\"", parts),
+                QuoteStyle::Triple => self.string_triple("r\"\"\"", parts),
+            },
 
             Expr::NumHexadecimal(span) => {
                 // Normalize A-F to a-f.
@@ -410,6 +595,14 @@ impl<'a> Formatter<'a> {
                     .with_markup(Markup::Number)
             }
 
+            Expr::BytesHexadecimal(span) => {
+                // Normalize A-F to a-f, but keep the 0x prefix.
+                Doc::string(span.resolve(self.input).to_ascii_lowercase())
+                    .with_markup(Markup::String)
+            }
+
+            Expr::BytesBase64(span) => self.span(*span).with_markup(Markup::String),
+
             Expr::Var(span) => self.span(*span),
 
             Expr::IfThenElse {
@@ -433,6 +626,39 @@ impl<'a> Formatter<'a> {
                 }
             }
 
+            Expr::Tagged { tag, value } => match value {
+                None => self.span(*tag).with_markup(Markup::Type),
+                Some((_span, value)) => concat! {
+                    self.span(*tag).with_markup(Markup::Type)
+                    " "
+                    self.expr(value)
+                },
+            },
+
+            Expr::Match {
+                scrutinee, arms, ..
+            } => {
+                // Match arms are always formatted tall, one arm per line, like
+                // the branches of an `if`, arms tend to be too long for one line.
+                let mut parts = Vec::new();
+                for arm in arms.elements.iter() {
+                    parts.push(Doc::HardBreak);
+                    parts.push(self.non_code(&arm.prefix));
+                    parts.push(self.match_arm(&arm.inner));
+                    parts.push(",".into());
+                }
+                parts.push(Doc::HardBreak);
+                parts.push(self.non_code(&arms.suffix));
+                concat! {
+                    Doc::str("match").with_markup(Markup::Keyword)
+                    " "
+                    self.expr(scrutinee)
+                    " {"
+                    indent! { Doc::Concat(parts) }
+                    "}"
+                }
+            }
+
             Expr::Function { args, body, .. } => {
                 let args_doc: Doc = match args.elements.len() {
                     0 => Doc::str("()"),
@@ -443,21 +669,7 @@ impl<'a> Formatter<'a> {
                     1 if args.elements[0].prefix.is_empty() && args.suffix.is_empty() => {
                         self.span(args.elements[0].inner)
                     }
-                    _ => group! {
-                        "("
-                        self.collection_opening_sep(args)
-                        indent! {
-                            Doc::join(
-                                args.elements.iter().map(|arg| concat! {
-                                    self.non_code(&arg.prefix)
-                                    self.span(arg.inner)
-                                }),
-                                concat!{ "," Doc::Sep },
-                            )
-                            self.trailing_comma(args)
-                        }
-                        ")"
-                    },
+                    _ => self.idents(args),
                 };
                 concat! {
                     args_doc " => " self.expr(body)
@@ -491,6 +703,20 @@ impl<'a> Formatter<'a> {
                 }
             }
 
+            Expr::Pipeline { base, stages, .. } => {
+                // Unlike binary operators, which nest, all stages of the
+                // pipeline go in one group, so either they all fit on one
+                // line, or every stage goes on its own line.
+                let mut parts = vec![self.expr(base)];
+                for stage in stages {
+                    parts.push(Doc::Sep);
+                    parts.push(self.span(stage.op_span));
+                    parts.push(" ".into());
+                    parts.push(self.expr(&stage.function));
+                }
+                group! { flush_indent! { Doc::Concat(parts) } }
+            }
+
             Expr::Chain {
                 base_expr, chain, ..
             } => self.chain(base_expr, chain),
@@ -509,8 +735,20 @@ impl<'a> Formatter<'a> {
 
         group.push(self.expr(base));
 
-        for (_, chain_elem) in chain.iter() {
+        // When the base is a short name such as `std`, breaking before the
+        // first field gains hardly any width, so the first field stays on the
+        // base line, as in `std.range(0, 10)`.
+        let keep_first_field = match base {
+            Expr::Var(span) => span.len() <= SHORT_BASE_LEN,
+            _ => false,
+        };
+
+        for (i, (_, chain_elem)) in chain.iter().enumerate() {
             match chain_elem {
+                Chain::Field { field } if i == 0 && keep_first_field => {
+                    group.push(".".into());
+                    group.push(self.span(*field));
+                }
                 Chain::Field { field } => {
                     group = &mut group_next;
                     group.push(Doc::SoftBreak);
@@ -687,8 +925,36 @@ impl<'a> Formatter<'a> {
         }
     }
 
+    fn match_arm(&self, arm: &MatchArm) -> Doc<'a> {
+        concat! { self.pattern(&arm.pattern) " => " self.expr(&arm.body) }
+    }
+
+    fn pattern(&self, pattern: &Pattern) -> Doc<'a> {
+        match pattern {
+            Pattern::Wildcard(span) | Pattern::Binding(span) => self.span(*span),
+            Pattern::Literal { value, .. } => self.expr(value),
+            Pattern::Tag { tag, inner: None } => self.span(*tag).with_markup(Markup::Type),
+            Pattern::Tag {
+                tag,
+                inner: Some(inner),
+            } => concat! {
+                self.span(*tag).with_markup(Markup::Type)
+                " "
+                self.pattern(inner)
+            },
+        }
+    }
+
     pub fn type_(&self, type_: &Type) -> Doc<'a> {
         match type_ {
+            Type::Tagged { tag, value, .. } => match value {
+                None => self.span(*tag).with_markup(Markup::Type),
+                Some(value) => concat! {
+                    self.span(*tag).with_markup(Markup::Type)
+                    " "
+                    self.type_(value)
+                },
+            },
             Type::Term(span) => self.span(*span).with_markup(Markup::Type),
             Type::Apply { name, args, .. } => concat! {
                 self.span(*name).with_markup(Markup::Type)
@@ -0,0 +1,131 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Formatter that prints a list of flat dicts as comma-separated values.
+
+use std::collections::BTreeSet;
+
+use crate::error::{IntoError, PathElement, Result};
+use crate::fmt_rcl::format_rcl;
+use crate::pprint::{concat, Doc};
+use crate::runtime::Value;
+use crate::source::Span;
+
+/// Append the field to the output, quoting it if needed.
+fn push_field(out: &mut String, field: &str) {
+    if field.contains([',', '"', '\r', '\n']) {
+        out.push('"');
+        out.push_str(&field.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(field);
+    }
+}
+
+/// Render a value in CSV format.
+///
+/// The value must be a list of dicts. The columns are the union of the keys of
+/// all dicts, and the first line is a header with the column names. When a
+/// dict lacks a key, that field is left empty.
+pub fn format_csv(caller: Span, v: &Value) -> Result<Doc<'static>> {
+    let rows = match v {
+        Value::List(xs) => xs,
+        _ => {
+            return caller
+                .error("To format as CSV, the top-level value must be a list.")
+                .err()
+        }
+    };
+
+    let mut columns: BTreeSet<&Value> = BTreeSet::new();
+    for (i, row) in rows.iter().enumerate() {
+        match row {
+            Value::Dict(kvs) => {
+                for (k, v) in kvs.iter() {
+                    if !matches!(k, Value::String(..)) {
+                        return caller
+                            .error(concat! {
+                                "Expected string keys for CSV columns, but got "
+                                format_rcl(k).into_owned()
+                                "."
+                            })
+                            .with_path(vec![PathElement::Index(i)])
+                            .err();
+                    }
+                    let is_scalar = matches!(
+                        v,
+                        Value::Null
+                            | Value::Bool(..)
+                            | Value::Int(..)
+                            | Value::Float(..)
+                            | Value::String(..)
+                    );
+                    if !is_scalar {
+                        return caller
+                            .error(concat! {
+                                "Expected null, bool, int, float, or string for a CSV field, but got "
+                                v.type_name()
+                                "."
+                            })
+                            .with_path(vec![PathElement::Index(i), PathElement::Key(k.clone())])
+                            .err();
+                    }
+                    columns.insert(k);
+                }
+            }
+            _ => {
+                return caller
+                    .error(concat! {
+                        "Expected a dict for a CSV row, but got "
+                        row.type_name()
+                        "."
+                    })
+                    .with_path(vec![PathElement::Index(i)])
+                    .err()
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for (i, column) in columns.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        push_field(&mut out, column.expect_string());
+    }
+    out.push('\n');
+
+    for row in rows.iter() {
+        let kvs = row.expect_dict();
+        for (i, column) in columns.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            match kvs.get(column) {
+                None | Some(Value::Null) => {}
+                Some(Value::Bool(b)) => out.push_str(if *b { "true" } else { "false" }),
+                Some(Value::Int(i)) => out.push_str(&i.to_string()),
+                Some(Value::Float(x)) => out.push_str(&x.to_string()),
+                Some(Value::String(s)) => push_field(&mut out, s),
+                Some(_) => unreachable!("We checked the field types above."),
+            }
+        }
+        out.push('\n');
+    }
+
+    // The pretty-printer adds the final newline.
+    out.pop();
+
+    let mut lines = Vec::new();
+    for (i, line) in out.split('\n').enumerate() {
+        if i > 0 {
+            lines.push(Doc::HardBreak);
+        }
+        lines.push(Doc::from(line.to_string()));
+    }
+    Ok(Doc::Concat(lines))
+}
@@ -7,12 +7,19 @@
 
 //! Formatter that prints values as json.
 
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::bytes::to_base64;
 use crate::error::{IntoError, PathElement, Result};
+use crate::fmt_rcl::{elided_collection, elision, Limits};
 use crate::markup::Markup;
+use crate::number::{self, NumberFormat};
 use crate::pprint::{concat, group, indent, Doc};
 use crate::runtime::Value;
+use crate::small_collection::{SmallDict, SmallList};
 use crate::source::Span;
-use crate::string::escape_json;
+use crate::string::{escape_json, escape_json_ascii};
 
 /// Render a value as json.
 pub fn format_json(caller: Span, v: &Value) -> Result<Doc> {
@@ -20,23 +27,97 @@ pub fn format_json(caller: Span, v: &Value) -> Result<Doc> {
     formatter.value(v)
 }
 
+/// Render a value as json, eliding the parts that exceed the limits.
+///
+/// Elided parts are marked with `…`, so the result is no longer valid json.
+/// With `ascii_only`, strings escape all characters outside of the ASCII range.
+pub fn format_json_limited(
+    caller: Span,
+    v: &Value,
+    limits: Limits,
+    ascii_only: bool,
+) -> Result<Doc> {
+    let mut formatter = Formatter::new(caller);
+    formatter.limits = limits;
+    if ascii_only {
+        formatter.escape = escape_json_ascii;
+    }
+    formatter.value(v)
+}
+
 /// Helper for formatting values as json.
 ///
 /// The formatter tracks the path in the value that we are formatting from, such
 /// that we can report the location of an error, in case an error occurs.
-pub struct Formatter {
+pub struct Formatter<'a> {
     /// The source location where json formatting was triggered from.
     pub caller: Span,
 
     /// Where we currently are in the value to be formatted.
     pub path: Vec<PathElement>,
+
+    /// How much of the value to format, relative to the current path.
+    pub limits: Limits,
+
+    /// How to escape the contents of string literals.
+    ///
+    /// This is [`escape_json`] by default, other escapers can restrict the
+    /// output to ASCII.
+    pub escape: fn(&str, &mut String),
+
+    /// Documents for collections that we already formatted, by address.
+    ///
+    /// Values are immutable and reference-counted, so a large output often
+    /// contains the same collection many times. Formatting it once and sharing
+    /// the document avoids repeating the work for every occurrence. Keying by
+    /// address is sound because the values are borrowed for `'a`, so they
+    /// cannot be freed and their address reused while the cache lives.
+    ///
+    /// The document also depends on the remaining depth, so it is part of the key.
+    cache: HashMap<(*const (), Option<u32>), Rc<Doc<'a>>>,
 }
 
-impl Formatter {
-    pub fn new(caller: Span) -> Formatter {
+/// A reference-counted collection that [`Formatter::shared`] can cache.
+trait Shared {
+    fn as_ptr(&self) -> *const ();
+    fn is_shared(&self) -> bool;
+}
+
+impl<T> Shared for Rc<T> {
+    fn as_ptr(&self) -> *const () {
+        Rc::as_ptr(self) as *const ()
+    }
+    fn is_shared(&self) -> bool {
+        Rc::strong_count(self) > 1
+    }
+}
+
+impl Shared for SmallList {
+    fn as_ptr(&self) -> *const () {
+        SmallList::as_ptr(self)
+    }
+    fn is_shared(&self) -> bool {
+        SmallList::is_shared(self)
+    }
+}
+
+impl Shared for SmallDict {
+    fn as_ptr(&self) -> *const () {
+        SmallDict::as_ptr(self)
+    }
+    fn is_shared(&self) -> bool {
+        SmallDict::is_shared(self)
+    }
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(caller: Span) -> Formatter<'a> {
         Formatter {
             caller,
             path: Vec::new(),
+            limits: Limits::default(),
+            escape: escape_json,
+            cache: HashMap::new(),
         }
     }
 
@@ -50,24 +131,30 @@ impl Formatter {
         self.caller.error(message).with_path(path).err()
     }
 
-    fn string<'a>(&self, s: &str) -> Doc<'a> {
+    fn string(&self, s: &str) -> Doc<'a> {
         let mut into = String::with_capacity(s.len());
         // TODO: Escape into a Doc so we can highlight escape sequences.
-        escape_json(s, &mut into);
+        (self.escape)(s, &mut into);
         concat! { "\"" into "\"" }
     }
 
-    fn list<'a>(&mut self, vs: impl Iterator<Item = &'a Value>) -> Result<Doc<'a>> {
+    fn list(&mut self, vs: impl ExactSizeIterator<Item = &'a Value>) -> Result<Doc<'a>> {
+        let len = vs.len();
+        if len > 0 && self.limits.is_too_deep() {
+            return Ok(elided_collection("[", "]", len));
+        }
+        let n_shown = self.limits.take(len);
         let mut elements = Vec::new();
-        for (i, v) in vs.enumerate() {
+        for (i, v) in vs.take(n_shown).enumerate() {
             if !elements.is_empty() {
                 elements.push(",".into());
                 elements.push(Doc::Sep);
             }
             self.path.push(PathElement::Index(i));
-            elements.push(self.value(v)?);
+            elements.push(self.nested_value(v)?);
             self.path.pop().expect("Push and pop are balanced.");
         }
+        self.push_elision(&mut elements, len - n_shown);
         let result = group! {
             "["
             Doc::SoftBreak
@@ -78,9 +165,17 @@ impl Formatter {
         Ok(result)
     }
 
-    fn dict<'a>(&mut self, vs: impl Iterator<Item = (&'a Value, &'a Value)>) -> Result<Doc<'a>> {
+    fn dict(
+        &mut self,
+        vs: impl ExactSizeIterator<Item = (&'a Value, &'a Value)>,
+    ) -> Result<Doc<'a>> {
+        let len = vs.len();
+        if len > 0 && self.limits.is_too_deep() {
+            return Ok(elided_collection("{", "}", len));
+        }
+        let n_shown = self.limits.take(len);
         let mut elements = Vec::new();
-        for (k, v) in vs {
+        for (k, v) in vs.take(n_shown) {
             if !elements.is_empty() {
                 elements.push(",".into());
                 elements.push(Doc::Sep);
@@ -93,9 +188,10 @@ impl Formatter {
                 _ => return self.error("To export as json, keys must be strings."),
             };
             elements.push(": ".into());
-            elements.push(self.value(v)?);
+            elements.push(self.nested_value(v)?);
             self.path.pop().expect("Push and pop are balanced.");
         }
+        self.push_elision(&mut elements, len - n_shown);
         let result = group! {
             "{"
             Doc::SoftBreak
@@ -106,19 +202,97 @@ impl Formatter {
         Ok(result)
     }
 
-    pub fn value<'a>(&mut self, v: &'a Value) -> Result<Doc<'a>> {
+    /// Format a tagged value as an object with the tag as its only key.
+    ///
+    /// When the payload is null, the value is just the tag, as a string.
+    fn tagged(&mut self, tag: &'a str, payload: &'a Value) -> Result<Doc<'a>> {
+        if let Value::Null = payload {
+            return Ok(self.string(tag).with_markup(Markup::String));
+        }
+        if self.limits.is_too_deep() {
+            return Ok(elided_collection("{", "}", 1));
+        }
+        self.path.push(PathElement::Key(Value::String(tag.into())));
+        let inner = self.nested_value(payload)?;
+        self.path.pop().expect("Push and pop are balanced.");
+        let result = group! {
+            "{"
+            Doc::SoftBreak
+            indent! { self.string(tag).with_markup(Markup::Field) ": " inner }
+            Doc::SoftBreak
+            "}"
+        };
+        Ok(result)
+    }
+
+    /// Append a marker for the elements that exceed the item limit, if any.
+    fn push_elision(&self, elements: &mut Vec<Doc<'a>>, n_elided: usize) {
+        if n_elided == 0 {
+            return;
+        }
+        if !elements.is_empty() {
+            elements.push(",".into());
+            elements.push(Doc::Sep);
+        }
+        elements.push(elision(n_elided));
+    }
+
+    /// Format an element of a collection, one level deeper than the collection.
+    fn nested_value(&mut self, v: &'a Value) -> Result<Doc<'a>> {
+        let limits = self.limits;
+        self.limits = limits.nested();
+        let result = self.value(v);
+        self.limits = limits;
+        result
+    }
+
+    /// Format a collection, or reuse the document if we formatted it before.
+    ///
+    /// Only collections that are referenced from more than one place can
+    /// occur multiple times, so for the others we skip the cache.
+    fn shared<T: Shared>(
+        &mut self,
+        inner: &'a T,
+        format: impl FnOnce(&mut Self, &'a T) -> Result<Doc<'a>>,
+    ) -> Result<Doc<'a>> {
+        if !inner.is_shared() {
+            return format(self, inner);
+        }
+        let key = (inner.as_ptr(), self.limits.max_depth);
+        if let Some(doc) = self.cache.get(&key) {
+            return Ok(Doc::Shared(doc.clone()));
+        }
+        let doc = Rc::new(format(self, inner)?);
+        self.cache.insert(key, doc.clone());
+        Ok(Doc::Shared(doc))
+    }
+
+    pub fn value(&mut self, v: &'a Value) -> Result<Doc<'a>> {
         let result: Doc = match v {
             Value::Null => Doc::from("null").with_markup(Markup::Keyword),
             Value::Bool(true) => Doc::from("true").with_markup(Markup::Keyword),
             Value::Bool(false) => Doc::from("false").with_markup(Markup::Keyword),
-            Value::Int(i) => Doc::from(i.to_string()).with_markup(Markup::Number),
+            Value::Int(i) => {
+                let i = number::format_int(*i, &NumberFormat::default());
+                Doc::from(i).with_markup(Markup::Number)
+            }
+            Value::Float(x) => Doc::from(x.to_string()).with_markup(Markup::Number),
             Value::String(s) => self.string(s).with_markup(Markup::String),
-            Value::List(vs) => self.list(vs.iter())?,
-            Value::Set(vs) => self.list(vs.iter())?,
-            Value::Dict(vs) => self.dict(vs.iter())?,
+            // Json has no binary type, the convention is to use base64 strings.
+            Value::Bytes(b) => concat! { "\"" to_base64(b) "\"" }.with_markup(Markup::String),
+            Value::List(vs) => self.shared(vs, |f, vs| f.list(vs.iter()))?,
+            Value::Set(vs) => self.shared(vs, |f, vs| f.list(vs.iter()))?,
+            // Json has no tuple type, we format tuples as arrays.
+            Value::Tuple(vs) => self.list(vs.iter())?,
+            Value::Tagged(tag, payload) => self.tagged(tag, payload)?,
+            Value::Dict(vs) => self.shared(vs, |f, vs| f.dict(vs.iter()))?,
             Value::Function(..) => self.error("Functions cannot be exported as json.")?,
             Value::BuiltinFunction(..) => self.error("Functions cannot be exported as json.")?,
             Value::BuiltinMethod { .. } => self.error("Methods cannot be exported as json.")?,
+            Value::Partial(..) | Value::Memoized(..) => {
+                self.error("Functions cannot be exported as json.")?
+            }
+            Value::Secret(..) => self.error("Secrets must be resolved before exporting.")?,
         };
         Ok(result)
     }
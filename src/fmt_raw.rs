@@ -63,6 +63,7 @@ impl Formatter {
             Value::String(s) => Doc::lines(s),
             Value::List(vs) => self.list(vs.iter())?,
             Value::Set(vs) => self.list(vs.iter())?,
+            Value::Tuple(vs) => self.list(vs.iter())?,
             _not_str => return self.error_not_string(v).err(),
         };
         Ok(result)
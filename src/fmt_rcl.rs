@@ -9,6 +9,8 @@
 //!
 //! This formatter is very similar to the one in [`fmt_json`].
 
+use crate::bytes::encode_hex;
+use crate::fmt_type::format_tag;
 use crate::markup::Markup;
 use crate::pprint::{concat, group, indent, Doc};
 use crate::runtime::Value;
@@ -16,7 +18,78 @@ use crate::string::{escape_json, is_identifier};
 
 /// Render a value as RCL.
 pub fn format_rcl(v: &Value) -> Doc {
-    value(v)
+    value(v, Limits::default())
+}
+
+/// Render a value as RCL, eliding the parts that exceed the limits.
+pub fn format_rcl_limited(v: &Value, limits: Limits) -> Doc {
+    value(v, limits)
+}
+
+/// Limits on how much of a value to format, for inspecting large values.
+///
+/// The elided parts are replaced with a `…` marker that says how many elements
+/// were left out. The result is no longer a valid expression, it is only meant
+/// for humans to read.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Limits {
+    /// How many levels of nested collections to format.
+    pub max_depth: Option<u32>,
+
+    /// How many elements of a collection to format.
+    pub max_items: Option<u32>,
+}
+
+impl Limits {
+    /// The limits for values that we print in error messages.
+    ///
+    /// A value in an error message can be arbitrarily large, and printing all
+    /// of it would bury the error. These limits can be lifted with
+    /// `--full-errors`.
+    pub fn errors() -> Limits {
+        Limits {
+            max_depth: Some(4),
+            max_items: Some(20),
+        }
+    }
+
+    /// Return whether formatting is unrestricted.
+    pub fn is_unlimited(&self) -> bool {
+        self.max_depth.is_none() && self.max_items.is_none()
+    }
+
+    /// Return whether a collection at this level should be elided entirely.
+    pub fn is_too_deep(&self) -> bool {
+        self.max_depth == Some(0)
+    }
+
+    /// The limits that apply to the elements of a collection at this level.
+    pub fn nested(self) -> Limits {
+        Limits {
+            max_depth: self.max_depth.map(|d| d.saturating_sub(1)),
+            max_items: self.max_items,
+        }
+    }
+
+    /// The number of elements to format out of a collection of length `len`.
+    pub fn take(&self, len: usize) -> usize {
+        match self.max_items {
+            Some(n) => len.min(n as usize),
+            None => len,
+        }
+    }
+}
+
+/// The marker for elements past the item limit, e.g. `… 3 more`.
+pub fn elision<'a>(n: usize) -> Doc<'a> {
+    Doc::from(format!("… {n} more")).with_markup(Markup::Comment)
+}
+
+/// The marker for a collection that is elided entirely, e.g. `[… 3 items]`.
+pub fn elided_collection<'a>(open: &'a str, close: &'a str, len: usize) -> Doc<'a> {
+    let unit = if len == 1 { "item" } else { "items" };
+    let marker = Doc::from(format!("… {len} {unit}")).with_markup(Markup::Comment);
+    concat! { open marker close }
 }
 
 /// Format a string.
@@ -27,14 +100,32 @@ fn string<'a>(s: &str) -> Doc<'a> {
     concat! { "\"" into "\"" }
 }
 
-fn list<'a>(open: &'a str, close: &'a str, vs: impl Iterator<Item = &'a Value>) -> Doc<'a> {
+fn list<'a>(
+    open: &'a str,
+    close: &'a str,
+    vs: impl ExactSizeIterator<Item = &'a Value>,
+    limits: Limits,
+) -> Doc<'a> {
+    let len = vs.len();
+    if len > 0 && limits.is_too_deep() {
+        return elided_collection(open, close, len);
+    }
+
+    let n_shown = limits.take(len);
     let mut elements = Vec::new();
-    for v in vs {
+    for v in vs.take(n_shown) {
         if !elements.is_empty() {
             elements.push(",".into());
             elements.push(Doc::Sep);
         }
-        elements.push(value(v));
+        elements.push(value(v, limits.nested()));
+    }
+    if n_shown < len {
+        if !elements.is_empty() {
+            elements.push(",".into());
+            elements.push(Doc::Sep);
+        }
+        elements.push(elision(len - n_shown));
     }
 
     if elements.is_empty() {
@@ -54,10 +145,19 @@ fn list<'a>(open: &'a str, close: &'a str, vs: impl Iterator<Item = &'a Value>)
     }
 }
 
-pub fn dict<'a>(vs: impl Iterator<Item = (&'a Value, &'a Value)>) -> Doc<'a> {
+pub fn dict<'a>(
+    vs: impl ExactSizeIterator<Item = (&'a Value, &'a Value)>,
+    limits: Limits,
+) -> Doc<'a> {
+    let len = vs.len();
+    if len > 0 && limits.is_too_deep() {
+        return elided_collection("{", "}", len);
+    }
+
+    let n_shown = limits.take(len);
     let mut elements = Vec::new();
 
-    for (k, v) in vs {
+    for (k, v) in vs.take(n_shown) {
         if !elements.is_empty() {
             elements.push(",".into());
         }
@@ -73,11 +173,18 @@ pub fn dict<'a>(vs: impl Iterator<Item = (&'a Value, &'a Value)>) -> Doc<'a> {
                 elements.push(": ".into());
             }
             _not_string => {
-                elements.push(value(k));
+                elements.push(value(k, limits.nested()));
                 elements.push(": ".into());
             }
         };
-        elements.push(value(v));
+        elements.push(value(v, limits.nested()));
+    }
+    if n_shown < len {
+        if !elements.is_empty() {
+            elements.push(",".into());
+        }
+        elements.push(Doc::Sep);
+        elements.push(elision(len - n_shown));
     }
 
     if elements.is_empty() {
@@ -98,19 +205,43 @@ pub fn dict<'a>(vs: impl Iterator<Item = (&'a Value, &'a Value)>) -> Doc<'a> {
     }
 }
 
-fn value(v: &Value) -> Doc {
+fn value(v: &Value, limits: Limits) -> Doc {
     match v {
         Value::Null => Doc::from("null").with_markup(Markup::Keyword),
         Value::Bool(true) => Doc::from("true").with_markup(Markup::Keyword),
         Value::Bool(false) => Doc::from("false").with_markup(Markup::Keyword),
         Value::Int(i) => Doc::from(i.to_string()).with_markup(Markup::Number),
+        Value::Float(x) => Doc::from(x.to_string()).with_markup(Markup::Number),
         Value::String(s) => string(s).with_markup(Markup::String),
-        Value::List(vs) => list("[", "]", vs.iter()),
+        Value::Bytes(b) => {
+            let mut into = String::with_capacity(b.len() * 2 + 3);
+            into.push_str("0x\"");
+            encode_hex(b, &mut into);
+            into.push('"');
+            Doc::from(into).with_markup(Markup::String)
+        }
+        Value::List(vs) => list("[", "]", vs.iter(), limits),
+        // A tuple with a single element needs a trailing comma, otherwise it
+        // would read back as an expression in parentheses.
+        Value::Tuple(vs) if vs.len() == 1 && !limits.is_too_deep() && limits.take(1) == 1 => {
+            concat! { "(" value(&vs[0], limits.nested()) ",)" }
+        }
+        Value::Tuple(vs) => list("(", ")", vs.iter(), limits),
+        Value::Tagged(tag, payload) => match payload.as_ref() {
+            Value::Null => format_tag(tag),
+            // A tag only takes a term as payload, so a negative number needs
+            // parentheses, otherwise the `-` would read as subtraction.
+            Value::Int(i) if *i < 0 => concat! { format_tag(tag) " (" value(payload, limits) ")" },
+            Value::Float(x) if x.get().is_sign_negative() => {
+                concat! { format_tag(tag) " (" value(payload, limits) ")" }
+            }
+            _ => concat! { format_tag(tag) " " value(payload, limits) },
+        },
         // TODO: An empty set should print as {}, that would be a non-idempotency,
         // because {} is the empty dict. We could add a function `std.empty_set`,
         // and format it as that?
-        Value::Set(vs) => list("{", "}", vs.iter()),
-        Value::Dict(vs) => dict(vs.iter()),
+        Value::Set(vs) => list("{", "}", vs.iter(), limits),
+        Value::Dict(vs) => dict(vs.iter(), limits),
 
         Value::BuiltinFunction(b) => {
             let name = b
@@ -151,5 +282,27 @@ fn value(v: &Value) -> Doc {
             Doc::from(m.method.name).with_markup(Markup::Builtin)
             "»"
         },
+        Value::Partial(p) => concat! {
+            "«"
+            Doc::from("partial").with_markup(Markup::Keyword)
+            " "
+            value(&p.function, limits)
+            "»"
+        },
+        Value::Memoized(m) => concat! {
+            "«"
+            Doc::from("memoized").with_markup(Markup::Keyword)
+            " "
+            value(&m.function, limits)
+            "»"
+        },
+        // The name is not sensitive, the contents are never part of the value.
+        Value::Secret(name) => concat! {
+            "«"
+            Doc::from("secret").with_markup(Markup::Keyword)
+            " "
+            string(name).with_markup(Markup::String)
+            "»"
+        },
     }
 }
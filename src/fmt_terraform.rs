@@ -0,0 +1,148 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Formatter that validates the structure of Terraform JSON, and prints it.
+//!
+//! Terraform reads `.tf.json` files, which are json with a particular shape:
+//! the top-level keys are block types such as `resource` and `variable`, and
+//! the nested keys are the labels of the block. Terraform's own errors about
+//! a malformed file point into the generated json, not the RCL source, so we
+//! check the shape here, and leave the contents of the blocks to Terraform.
+//! See also <https://developer.hashicorp.com/terraform/language/syntax/json>.
+
+use crate::error::{IntoError, PathElement, Result};
+use crate::fmt_json::format_json_limited;
+use crate::fmt_rcl::Limits;
+use crate::pprint::{concat, Doc};
+use crate::runtime::Value;
+use crate::small_collection::SmallDict;
+use crate::source::Span;
+
+/// The top-level block types, with the number of labels that they take.
+const BLOCK_TYPES: [(&str, usize); 12] = [
+    ("check", 1),
+    ("data", 2),
+    ("import", 0),
+    ("locals", 0),
+    ("module", 1),
+    ("moved", 0),
+    ("output", 1),
+    ("provider", 1),
+    ("removed", 0),
+    ("resource", 2),
+    ("terraform", 0),
+    ("variable", 1),
+];
+
+/// Render a value as Terraform JSON, after checking its structure.
+pub fn format_terraform_json(caller: Span, v: &Value, ascii_only: bool) -> Result<Doc> {
+    let mut checker = Checker {
+        caller,
+        path: Vec::new(),
+    };
+    checker.top_level(v)?;
+    format_json_limited(caller, v, Limits::default(), ascii_only)
+}
+
+/// Helper for checking the structure of a Terraform JSON value.
+struct Checker {
+    /// The source location where formatting was triggered from.
+    caller: Span,
+
+    /// Where we currently are in the value to be checked.
+    path: Vec<PathElement>,
+}
+
+impl Checker {
+    /// Report an error at the current value path.
+    fn error<T>(&mut self, message: Doc<'static>, help: &'static str) -> Result<T> {
+        let path = std::mem::take(&mut self.path);
+        self.caller
+            .error(message)
+            .with_path(path)
+            .with_help(help)
+            .err()
+    }
+
+    fn expect_dict<'a>(&mut self, v: &'a Value, what: &'static str) -> Result<&'a SmallDict> {
+        match v {
+            Value::Dict(kvs) => Ok(kvs),
+            _ => self.error(
+                concat! { "Expected a dict of " what ", but got " v.type_name() "." },
+                "In Terraform JSON, block labels are keys of nested objects, for example \
+                'resource' contains dicts with resource types as keys, and those contain \
+                dicts with resource names as keys.",
+            ),
+        }
+    }
+
+    fn top_level(&mut self, v: &Value) -> Result<()> {
+        let blocks = match v {
+            Value::Dict(kvs) => kvs,
+            _ => {
+                return self.error(
+                    "To format as Terraform JSON, the top-level value must be a dict.".into(),
+                    "The keys of the top-level dict are block types, such as 'resource' \
+                    or 'variable'.",
+                )
+            }
+        };
+
+        for (k, v) in blocks.iter() {
+            self.path.push(PathElement::Key(k.clone()));
+            let num_labels = match k {
+                Value::String(block_type) => BLOCK_TYPES
+                    .iter()
+                    .find(|(name, _)| *name == block_type.as_ref())
+                    .map(|(_, n)| *n),
+                _ => None,
+            };
+            match num_labels {
+                Some(n) => self.block(v, n)?,
+                None => {
+                    return self.error(
+                        "This is not a Terraform block type.".into(),
+                        "The top-level keys must be one of: check, data, import, locals, \
+                        module, moved, output, provider, removed, resource, terraform, variable.",
+                    )
+                }
+            }
+            self.path.pop().expect("Push and pop are balanced.");
+        }
+
+        Ok(())
+    }
+
+    /// Check a block with `num_labels` labels.
+    ///
+    /// The labels are nested dict keys, and the block bodies are dicts. At any
+    /// level, a list of dicts is allowed in place of a dict, Terraform merges
+    /// them, and it is needed to configure multiple providers of the same type.
+    fn block(&mut self, v: &Value, num_labels: usize) -> Result<()> {
+        if let Value::List(elems) = v {
+            for (i, elem) in elems.iter().enumerate() {
+                self.path.push(PathElement::Index(i));
+                self.block(elem, num_labels)?;
+                self.path.pop().expect("Push and pop are balanced.");
+            }
+            return Ok(());
+        }
+
+        if num_labels == 0 {
+            self.expect_dict(v, "block arguments")?;
+            return Ok(());
+        }
+
+        let labels = self.expect_dict(v, "block labels")?;
+        for (k, v) in labels.iter() {
+            self.path.push(PathElement::Key(k.clone()));
+            self.block(v, num_labels - 1)?;
+            self.path.pop().expect("Push and pop are balanced.");
+        }
+        Ok(())
+    }
+}
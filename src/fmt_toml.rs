@@ -9,12 +9,12 @@
 //!
 //! This formatter is similar to the one in [`fmt_json`].
 
-use std::collections::BTreeMap;
-
+use crate::bytes::to_base64;
 use crate::error::{IntoError, PathElement, Result};
 use crate::markup::Markup;
 use crate::pprint::{concat, group, indent, Doc};
 use crate::runtime::Value;
+use crate::small_collection::SmallDict;
 use crate::source::Span;
 use crate::string::escape_json;
 
@@ -205,14 +205,36 @@ impl Formatter {
             Value::Bool(true) => Doc::from("true").with_markup(Markup::Keyword),
             Value::Bool(false) => Doc::from("false").with_markup(Markup::Keyword),
             Value::Int(i) => Doc::from(i.to_string()).with_markup(Markup::Number),
+            Value::Float(x) => Doc::from(x.to_string()).with_markup(Markup::Number),
             Value::String(s) => self.string(s).with_markup(Markup::String),
+            // TOML has no binary type, we format bytes as base64 strings.
+            Value::Bytes(b) => concat! { "\"" to_base64(b) "\"" }.with_markup(Markup::String),
             Value::List(vs) => self.array(vs.iter())?,
             // TOML has no set type, we format sets as arrays (lists).
             Value::Set(vs) => self.array(vs.iter())?,
+            // Neither does it have a tuple type, tuples become arrays too.
+            Value::Tuple(vs) => self.array(vs.iter())?,
+            // A tagged value becomes its tag as a string if it has no payload,
+            // or a table with the tag as its only key otherwise.
+            Value::Tagged(tag, payload) => match payload.as_ref() {
+                Value::Null => self.string(tag).with_markup(Markup::String),
+                payload => {
+                    self.path.push(PathElement::Key(Value::String(tag.into())));
+                    let result = concat! {
+                        "{ " self.key(tag).with_markup(Markup::Field) " = " self.value(payload)? " }"
+                    };
+                    self.path.pop().expect("Push and pop are balanced.");
+                    result
+                }
+            },
             Value::Dict(vs) => self.inline_table(vs.iter())?,
             Value::Function(..) => self.error("Functions cannot be exported as TOML.")?,
             Value::BuiltinFunction(..) => self.error("Functions cannot be exported as TOML.")?,
             Value::BuiltinMethod { .. } => self.error("Methods cannot be exported as TOML.")?,
+            Value::Partial(..) | Value::Memoized(..) => {
+                self.error("Functions cannot be exported as TOML.")?
+            }
+            Value::Secret(..) => self.error("Secrets must be resolved before exporting.")?,
         };
         Ok(result)
     }
@@ -242,7 +264,7 @@ impl Formatter {
         Ok(())
     }
 
-    fn top_level<'a>(&mut self, kv: &'a BTreeMap<Value, Value>) -> Result<Doc<'a>> {
+    fn top_level<'a>(&mut self, kv: &'a SmallDict) -> Result<Doc<'a>> {
         let mut values: Vec<Doc> = Vec::new();
         let mut tables: Vec<Doc> = Vec::new();
         let mut arrays: Vec<Doc> = Vec::new();
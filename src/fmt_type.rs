@@ -20,9 +20,14 @@ use crate::types::{FunctionArg, Side, SourcedType, Type};
 pub fn format_type(type_: &Type) -> Doc {
     match type_ {
         // For primitive types the short name is the full name.
-        Type::Any | Type::Void | Type::Bool | Type::Int | Type::Null | Type::String => {
-            Doc::from(type_.short_name()).with_markup(Markup::Type)
-        }
+        Type::Any
+        | Type::Void
+        | Type::Bool
+        | Type::Bytes
+        | Type::Int
+        | Type::Float
+        | Type::Null
+        | Type::String => Doc::from(type_.short_name()).with_markup(Markup::Type),
 
         // Collection types.
         Type::Dict(kv) => concat! {
@@ -37,6 +42,14 @@ pub fn format_type(type_: &Type) -> Doc {
             Doc::from("Set").with_markup(Markup::Type)
             format_types("[", [(None, &element_type.type_)], "]")
         },
+        Type::Tuple(elements) => concat! {
+            Doc::from("Tuple").with_markup(Markup::Type)
+            format_types("[", elements.iter().map(|st| (None, &st.type_)), "]")
+        },
+        Type::Tagged(tagged) => match &tagged.value.type_ {
+            Type::Null => format_tag(&tagged.tag),
+            value => concat! { format_tag(&tagged.tag) " " format_type(value) },
+        },
         Type::Union(union) => concat! {
             Doc::from("Union").with_markup(Markup::Type)
             format_types("[", union.members.iter().map(|st| (None, &st.type_)), "]")
@@ -57,6 +70,11 @@ pub fn format_type(type_: &Type) -> Doc {
     }
 }
 
+/// Render a tag, including the leading `#`.
+pub fn format_tag(tag: &str) -> Doc {
+    concat! { "#" tag }.with_markup(Markup::Type)
+}
+
 /// A list of types enclosed by opening and closing delimiters.
 fn format_types<'a, Types: IntoIterator<Item = (Option<&'a str>, &'a Type)>>(
     open: &'static str,
@@ -167,6 +185,15 @@ impl<'a> DiffFormatter<'a> {
                 Doc::from("Dict").with_markup(Markup::Type)
                 Self::format_types("[", [key.as_ref(), value.as_ref()], "]", |t| self.format_type_diff(t))
             },
+            Mismatch::Tuple(elements) => concat! {
+                Doc::from("Tuple").with_markup(Markup::Type)
+                Self::format_types("[", elements.iter(), "]", |t| self.format_type_diff(t))
+            },
+            Mismatch::Tagged(tag, value) => concat! {
+                format_tag(tag)
+                " "
+                self.format_type_diff(value)
+            },
             Mismatch::Function(args, result) => concat! {
                 Self::format_types("(", args.iter(), ")", |t| self.format_arg_diff(t))
                 " -> "
@@ -0,0 +1,393 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Formatter that prints values as block-style YAML.
+//!
+//! The output is deliberately conservative: strings are always double-quoted
+//! (so values such as `no`, `1.0`, or `{{ $value }}` are never misinterpreted),
+//! the formatter never emits anchors or aliases, and only simple keys are left
+//! unquoted. The formatter supports profiles that tweak the output for specific
+//! consumers, for example to order keys the way that people expect to read them.
+
+use std::rc::Rc;
+
+use crate::bytes::to_base64;
+use crate::error::{IntoError, PathElement, Result};
+use crate::markup::Markup;
+use crate::pprint::{concat, indent, Doc};
+use crate::runtime::Value;
+use crate::source::Span;
+use crate::string::{escape_json, escape_yaml_ascii};
+
+/// Tweaks to the YAML output for specific consumers.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Profile {
+    /// Prometheus [rule files][prom], also used by Alertmanager-based setups.
+    ///
+    /// Keys of groups and rules are ordered the way they appear in the
+    /// Prometheus documentation, rather than alphabetically.
+    ///
+    /// [prom]: https://prometheus.io/docs/prometheus/latest/configuration/recording_rules/
+    PrometheusRules,
+
+    /// GitHub Actions [workflow files][gha].
+    ///
+    /// Keys of workflows, jobs, and steps are ordered the way they appear in
+    /// the GitHub documentation, and multi-line strings such as `run` scripts
+    /// are written as literal blocks.
+    ///
+    /// [gha]: https://docs.github.com/en/actions/writing-workflows/workflow-syntax-for-github-actions
+    GithubActions,
+}
+
+/// Render a value as YAML, with the given profile.
+///
+/// With `ascii_only`, strings escape all characters outside of the ASCII range.
+pub fn format_yaml(caller: Span, profile: Profile, v: &Value, ascii_only: bool) -> Result<Doc> {
+    let mut formatter = Formatter::new(caller, profile);
+    formatter.ascii_only = ascii_only;
+    match (profile, v) {
+        (_, Value::Dict(..)) => formatter.value(v),
+        (Profile::PrometheusRules, _) => {
+            formatter.error("To format as Prometheus rules, the top-level value must be a dict.")
+        }
+        (Profile::GithubActions, _) => formatter
+            .error("To format as a GitHub Actions workflow, the top-level value must be a dict."),
+    }
+}
+
+/// Return whether a key can be written without quotes.
+///
+/// We only allow keys that look like identifiers, and we exclude words that
+/// YAML 1.1 parsers interpret as booleans or null, such as `on` and `no`.
+fn is_plain_key(key: &str) -> bool {
+    let mut bytes = key.bytes();
+    let starts_ok = match bytes.next() {
+        Some(b) => b.is_ascii_alphabetic() || b == b'_',
+        None => false,
+    };
+    let is_reserved = matches!(
+        key.to_ascii_lowercase().as_str(),
+        "y" | "n" | "yes" | "no" | "on" | "off" | "true" | "false" | "null"
+    );
+    starts_ok
+        && !is_reserved
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-' || b == b'.')
+}
+
+/// Return whether a string can be written as a literal block scalar (`|`).
+///
+/// This is the case for multi-line strings that contain only printable
+/// characters. Lines must not end in whitespace, because the pretty-printer
+/// trims it. We also exclude strings that start with whitespace, because those
+/// would need an explicit indentation indicator, and strings that end in more
+/// than one newline.
+fn is_literal_block(s: &str) -> bool {
+    s.contains('\n')
+        && !s.starts_with([' ', '\t', '\n'])
+        && !s.ends_with("\n\n")
+        && !s.contains(" \n")
+        && !s.contains("\t\n")
+        && !s.ends_with([' ', '\t'])
+        && s.chars()
+            .all(|ch| ch == '\n' || ch == '\t' || !ch.is_control())
+}
+
+/// The position of a key in the output, lower comes first.
+///
+/// Keys that are not listed come after the listed ones, in alphabetical order.
+fn key_rank(order: &[&str], key: &Value) -> usize {
+    match key {
+        Value::String(k) => order
+            .iter()
+            .position(|x| *x == k.as_ref())
+            .unwrap_or(order.len()),
+        _ => order.len(),
+    }
+}
+
+/// Helper for formatting values as YAML.
+///
+/// The formatter tracks the path in the value that we are formatting from, such
+/// that we can report the location of an error, in case an error occurs.
+struct Formatter {
+    /// The source location where YAML formatting was triggered from.
+    caller: Span,
+
+    /// The profile that tweaks the output.
+    profile: Profile,
+
+    /// Where we currently are in the value to be formatted.
+    path: Vec<PathElement>,
+
+    /// Whether to escape all characters outside of the ASCII range in strings.
+    ascii_only: bool,
+}
+
+impl Formatter {
+    pub fn new(caller: Span, profile: Profile) -> Formatter {
+        Formatter {
+            caller,
+            profile,
+            path: Vec::new(),
+            ascii_only: false,
+        }
+    }
+
+    /// Report an error at the current value path.
+    fn error<T>(&mut self, message: &'static str) -> Result<T> {
+        // Steal the path from the formatter and move it into the error. We have
+        // to leave an empty path in its place. This is fine, because returning
+        // the error prevents further formatting.
+        let mut path = Vec::new();
+        std::mem::swap(&mut self.path, &mut path);
+        self.caller.error(message).with_path(path).err()
+    }
+
+    /// Return the preferred key order for the dict at the current path.
+    fn key_order(&self) -> &'static [&'static str] {
+        use PathElement::{Index, Key};
+        let is_key = |elem: &PathElement, name: &str| match elem {
+            Key(Value::String(k)) => k.as_ref() == name,
+            _ => false,
+        };
+        match (self.profile, &self.path[..]) {
+            (Profile::PrometheusRules, []) => &["groups"],
+            (Profile::PrometheusRules, [groups, Index(_)]) if is_key(groups, "groups") => &[
+                "name",
+                "interval",
+                "limit",
+                "query_offset",
+                "labels",
+                "rules",
+            ],
+            (Profile::PrometheusRules, [groups, Index(_), rules, Index(_)])
+                if is_key(groups, "groups") && is_key(rules, "rules") =>
+            {
+                &[
+                    "record",
+                    "alert",
+                    "expr",
+                    "for",
+                    "keep_firing_for",
+                    "labels",
+                    "annotations",
+                ]
+            }
+            (Profile::GithubActions, []) => &[
+                "name",
+                "run-name",
+                "on",
+                "permissions",
+                "env",
+                "defaults",
+                "concurrency",
+                "jobs",
+            ],
+            (Profile::GithubActions, [jobs, Key(_)]) if is_key(jobs, "jobs") => &[
+                "name",
+                "runs-on",
+                "needs",
+                "if",
+                "permissions",
+                "environment",
+                "concurrency",
+                "outputs",
+                "env",
+                "defaults",
+                "strategy",
+                "container",
+                "services",
+                "timeout-minutes",
+                "continue-on-error",
+                "uses",
+                "with",
+                "secrets",
+                "steps",
+            ],
+            (Profile::GithubActions, [jobs, Key(_), steps, Index(_)])
+                if is_key(jobs, "jobs") && is_key(steps, "steps") =>
+            {
+                &[
+                    "name",
+                    "id",
+                    "if",
+                    "uses",
+                    "with",
+                    "run",
+                    "shell",
+                    "working-directory",
+                    "env",
+                    "continue-on-error",
+                    "timeout-minutes",
+                ]
+            }
+            _ => &[],
+        }
+    }
+
+    fn string<'a>(&self, s: &str) -> Doc<'a> {
+        let mut into = String::with_capacity(s.len());
+        // Json escaping produces a valid YAML double-quoted scalar, the escape
+        // sequences that json uses are a subset of the ones that YAML supports.
+        // But YAML does not allow surrogate pairs, so the ASCII-only escaping
+        // differs from json for code points beyond U+FFFF.
+        if self.ascii_only {
+            escape_yaml_ascii(s, &mut into);
+        } else {
+            escape_json(s, &mut into);
+        }
+        concat! { "\"" into "\"" }
+    }
+
+    fn key<'a>(&mut self, key: &'a Value) -> Result<Doc<'a>> {
+        match key {
+            Value::String(k) if is_plain_key(k) => Ok(Doc::from(k.as_ref())),
+            Value::String(k) => Ok(self.string(k)),
+            _ => self.error("To export as YAML, keys must be strings."),
+        }
+    }
+
+    /// Format a value in a position after a `key:` or `-`.
+    ///
+    /// Scalars and empty collections go on the same line, non-empty
+    /// collections go on the next lines. Dicts in lists start on the same line
+    /// as the `-`, but keys of dicts in dicts are indented on a new line.
+    fn nested<'a>(&mut self, v: &'a Value, in_list: bool) -> Result<Doc<'a>> {
+        let result = match v {
+            Value::List(vs) if !vs.is_empty() => {
+                let inner = self.list(vs.iter())?;
+                indent! { Doc::HardBreak inner }
+            }
+            Value::Set(vs) if !vs.is_empty() => {
+                let inner = self.list(vs.iter())?;
+                indent! { Doc::HardBreak inner }
+            }
+            Value::Tuple(vs) if !vs.is_empty() => {
+                let inner = self.list(vs.iter())?;
+                indent! { Doc::HardBreak inner }
+            }
+            Value::Dict(kvs) if !kvs.is_empty() && in_list => {
+                let inner = self.dict(v)?;
+                concat! { " " indent! { inner } }
+            }
+            Value::Tagged(tag, payload) if !matches!(payload.as_ref(), Value::Null) && in_list => {
+                let inner = self.tagged(tag, payload)?;
+                concat! { " " indent! { inner } }
+            }
+            Value::Tagged(tag, payload) if !matches!(payload.as_ref(), Value::Null) => {
+                let inner = self.tagged(tag, payload)?;
+                indent! { Doc::HardBreak inner }
+            }
+            Value::Dict(kvs) if !kvs.is_empty() => {
+                let inner = self.dict(v)?;
+                indent! { Doc::HardBreak inner }
+            }
+            Value::String(s)
+                if self.profile == Profile::GithubActions
+                    && is_literal_block(s)
+                    && (s.is_ascii() || !self.ascii_only) =>
+            {
+                let (chomp, body) = match s.strip_suffix('\n') {
+                    Some(body) => ("|", body),
+                    None => ("|-", s.as_ref()),
+                };
+                concat! {
+                    " " chomp
+                    indent! { Doc::HardBreak Doc::lines(body).with_markup(Markup::String) }
+                }
+            }
+            _ => {
+                let inner = self.value(v)?;
+                concat! { " " inner }
+            }
+        };
+        Ok(result)
+    }
+
+    fn list<'a>(&mut self, vs: impl Iterator<Item = &'a Value>) -> Result<Doc<'a>> {
+        let mut elements = Vec::new();
+        for (i, v) in vs.enumerate() {
+            if !elements.is_empty() {
+                elements.push(Doc::HardBreak);
+            }
+            self.path.push(PathElement::Index(i));
+            elements.push("-".into());
+            elements.push(self.nested(v, true)?);
+            self.path.pop().expect("Push and pop are balanced.");
+        }
+        Ok(Doc::Concat(elements))
+    }
+
+    fn dict<'a>(&mut self, v: &'a Value) -> Result<Doc<'a>> {
+        let order = self.key_order();
+        let mut kvs: Vec<(&Value, &Value)> = v.expect_dict().iter().collect();
+        // The sort is stable, so keys of the same rank remain sorted.
+        kvs.sort_by_key(|(k, _v)| key_rank(order, k));
+
+        let mut elements = Vec::new();
+        for (k, v) in kvs {
+            if !elements.is_empty() {
+                elements.push(Doc::HardBreak);
+            }
+            self.path.push(PathElement::Key(k.clone()));
+            elements.push(self.key(k)?.with_markup(Markup::Field));
+            elements.push(":".into());
+            elements.push(self.nested(v, false)?);
+            self.path.pop().expect("Push and pop are balanced.");
+        }
+        Ok(Doc::Concat(elements))
+    }
+
+    /// Format a tagged value with payload as a mapping with the tag as only key.
+    fn tagged<'a>(&mut self, tag: &'a Rc<str>, payload: &'a Value) -> Result<Doc<'a>> {
+        self.path.push(PathElement::Key(Value::String(tag.into())));
+        let key = if is_plain_key(tag) {
+            Doc::from(tag.as_ref())
+        } else {
+            self.string(tag)
+        };
+        let result = concat! { key.with_markup(Markup::Field) ":" self.nested(payload, false)? };
+        self.path.pop().expect("Push and pop are balanced.");
+        Ok(result)
+    }
+
+    fn value<'a>(&mut self, v: &'a Value) -> Result<Doc<'a>> {
+        let result: Doc = match v {
+            Value::Null => Doc::from("null").with_markup(Markup::Keyword),
+            Value::Bool(true) => Doc::from("true").with_markup(Markup::Keyword),
+            Value::Bool(false) => Doc::from("false").with_markup(Markup::Keyword),
+            Value::Int(i) => Doc::from(i.to_string()).with_markup(Markup::Number),
+            Value::Float(x) => Doc::from(x.to_string()).with_markup(Markup::Number),
+            Value::String(s) => self.string(s).with_markup(Markup::String),
+            // YAML 1.2 has no binary type, we format bytes as base64 strings.
+            Value::Bytes(b) => concat! { "\"" to_base64(b) "\"" }.with_markup(Markup::String),
+            Value::List(vs) if vs.is_empty() => "[]".into(),
+            Value::Set(vs) if vs.is_empty() => "[]".into(),
+            Value::Tuple(vs) if vs.is_empty() => "[]".into(),
+            Value::Dict(kvs) if kvs.is_empty() => "{}".into(),
+            Value::List(vs) => self.list(vs.iter())?,
+            Value::Set(vs) => self.list(vs.iter())?,
+            // YAML has no tuple type, we format tuples as sequences.
+            Value::Tuple(vs) => self.list(vs.iter())?,
+            // A tagged value without payload is just its tag, as a string.
+            Value::Tagged(tag, payload) => match payload.as_ref() {
+                Value::Null => self.string(tag).with_markup(Markup::String),
+                payload => self.tagged(tag, payload)?,
+            },
+            Value::Dict(..) => self.dict(v)?,
+            Value::Function(..) => self.error("Functions cannot be exported as YAML.")?,
+            Value::BuiltinFunction(..) => self.error("Functions cannot be exported as YAML.")?,
+            Value::BuiltinMethod { .. } => self.error("Methods cannot be exported as YAML.")?,
+            Value::Partial(..) | Value::Memoized(..) => {
+                self.error("Functions cannot be exported as YAML.")?
+            }
+            Value::Secret(..) => self.error("Secrets must be resolved before exporting.")?,
+        };
+        Ok(result)
+    }
+}
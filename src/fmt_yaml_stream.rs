@@ -13,9 +13,15 @@ use crate::markup::Markup;
 use crate::pprint::Doc;
 use crate::runtime::Value;
 use crate::source::Span;
+use crate::string::escape_yaml_ascii;
 
 /// Render a value in YAML stream format.
-pub fn format_yaml_stream(caller: Span, v: &Value) -> Result<Doc> {
+///
+/// The documents are json, which is valid YAML. With `ascii_only`, strings
+/// escape all characters outside of the ASCII range. Because the documents
+/// are read by YAML parsers, which reject surrogate pairs, code points beyond
+/// U+FFFF use YAML's `\U` escape rather than json's surrogate pairs.
+pub fn format_yaml_stream(caller: Span, v: &Value, ascii_only: bool) -> Result<Doc> {
     let elements = match v {
         Value::List(xs) => xs,
         _ => {
@@ -26,6 +32,9 @@ pub fn format_yaml_stream(caller: Span, v: &Value) -> Result<Doc> {
     };
 
     let mut formatter = Formatter::new(caller);
+    if ascii_only {
+        formatter.escape = escape_yaml_ascii;
+    }
     let mut parts = Vec::new();
 
     for (i, element) in elements.iter().enumerate() {
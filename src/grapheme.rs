@@ -0,0 +1,181 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Segmentation of strings into grapheme clusters.
+//!
+//! This implements the extended grapheme cluster boundary rules of [Unicode
+//! Standard Annex #29][uax29]. We do not ship the Unicode character database,
+//! so the grapheme cluster break property of a character is approximated: we
+//! treat characters of zero display width as combining, and we recognize emoji
+//! and Hangul by their ranges. This gets the common cases right: combining
+//! accents, emoji with modifiers and zero width joiner sequences, flags, and
+//! Hangul syllables. For some scripts that rely on spacing marks or prepended
+//! characters, a cluster may be split in more places than Unicode prescribes.
+//!
+//! [uax29]: https://www.unicode.org/reports/tr29/
+
+use unicode_width::UnicodeWidthChar;
+
+/// The grapheme cluster break property of a character, approximately.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Class {
+    Cr,
+    Lf,
+    Control,
+    Extend,
+    Zwj,
+    RegionalIndicator,
+    HangulL,
+    HangulV,
+    HangulT,
+    HangulLv,
+    HangulLvt,
+    ExtendedPictographic,
+    Other,
+}
+
+fn classify(ch: char) -> Class {
+    let c = ch as u32;
+    match c {
+        0x0d => Class::Cr,
+        0x0a => Class::Lf,
+        0x200d => Class::Zwj,
+        // Zero width non-joiner, variation selectors, emoji modifiers, and tags
+        // extend the preceding character.
+        0x200c | 0xfe00..=0xfe0f | 0x1f3fb..=0x1f3ff | 0xe0020..=0xe007f | 0xe0100..=0xe01ef => {
+            Class::Extend
+        }
+        0x1f1e6..=0x1f1ff => Class::RegionalIndicator,
+        0x1100..=0x115f | 0xa960..=0xa97c => Class::HangulL,
+        0x1160..=0x11a7 | 0xd7b0..=0xd7c6 => Class::HangulV,
+        0x11a8..=0x11ff | 0xd7cb..=0xd7fb => Class::HangulT,
+        0xac00..=0xd7a3 if (c - 0xac00) % 28 == 0 => Class::HangulLv,
+        0xac00..=0xd7a3 => Class::HangulLvt,
+        0x00a9
+        | 0x00ae
+        | 0x203c
+        | 0x2049
+        | 0x2122
+        | 0x2139
+        | 0x2194..=0x21aa
+        | 0x231a..=0x23ff
+        | 0x24c2
+        | 0x25aa..=0x25fe
+        | 0x2600..=0x27bf
+        | 0x2934..=0x2935
+        | 0x2b05..=0x2b55
+        | 0x3030
+        | 0x303d
+        | 0x3297
+        | 0x3299
+        | 0x1f000..=0x1faff => Class::ExtendedPictographic,
+        // Format characters and line and paragraph separators are controls,
+        // even though they have no width.
+        0x00ad | 0x061c | 0x180e | 0x200b | 0x200e..=0x200f | 0x2028..=0x202e | 0xfeff => {
+            Class::Control
+        }
+        _ if ch.is_control() => Class::Control,
+        _ => match ch.width() {
+            Some(0) => Class::Extend,
+            _ => Class::Other,
+        },
+    }
+}
+
+/// Iterator over the grapheme clusters of a string.
+pub struct Graphemes<'a> {
+    rest: &'a str,
+}
+
+/// Split the string into extended grapheme clusters.
+pub fn graphemes(s: &str) -> Graphemes {
+    Graphemes { rest: s }
+}
+
+impl<'a> Iterator for Graphemes<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let mut chars = self.rest.char_indices();
+        let (_, first) = chars.next()?;
+        let mut prev = classify(first);
+
+        // For emoji zero width joiner sequences, whether the cluster so far
+        // is an extended pictographic followed by extenders.
+        let mut in_pictographic = prev == Class::ExtendedPictographic;
+        // For flags, the number of regional indicators in the cluster.
+        let mut n_regional = (prev == Class::RegionalIndicator) as u32;
+
+        let mut end = self.rest.len();
+        for (i, ch) in chars {
+            let next = classify(ch);
+            let is_break = match (prev, next) {
+                (Class::Cr, Class::Lf) => false,
+                (Class::Cr | Class::Lf | Class::Control, _) => true,
+                (_, Class::Cr | Class::Lf | Class::Control) => true,
+                (
+                    Class::HangulL,
+                    Class::HangulL | Class::HangulV | Class::HangulLv | Class::HangulLvt,
+                ) => false,
+                (Class::HangulLv | Class::HangulV, Class::HangulV | Class::HangulT) => false,
+                (Class::HangulLvt | Class::HangulT, Class::HangulT) => false,
+                (_, Class::Extend | Class::Zwj) => false,
+                (Class::Zwj, Class::ExtendedPictographic) => !in_pictographic,
+                (Class::RegionalIndicator, Class::RegionalIndicator) => n_regional % 2 == 0,
+                _ => true,
+            };
+            if is_break {
+                end = i;
+                break;
+            }
+            in_pictographic = match next {
+                Class::ExtendedPictographic => true,
+                Class::Extend | Class::Zwj => in_pictographic,
+                _ => false,
+            };
+            n_regional += (next == Class::RegionalIndicator) as u32;
+            prev = next;
+        }
+
+        let (cluster, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(cluster)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::graphemes;
+
+    fn split(s: &str) -> Vec<&str> {
+        graphemes(s).collect()
+    }
+
+    #[test]
+    fn graphemes_keep_clusters_together() {
+        assert_eq!(split(""), Vec::<&str>::new());
+        assert_eq!(split("abc"), ["a", "b", "c"]);
+        assert_eq!(split("a\r\nb"), ["a", "\r\n", "b"]);
+        // "e" followed by a combining acute accent.
+        assert_eq!(split("e\u{301}x"), ["e\u{301}", "x"]);
+        // Thumbs up with a skin tone modifier.
+        assert_eq!(split("\u{1f44d}\u{1f3fd}!"), ["\u{1f44d}\u{1f3fd}", "!"]);
+        // Family: man, zero width joiner, woman, zero width joiner, girl.
+        let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+        assert_eq!(split(family), [family]);
+        // Two flags: NL and BE.
+        assert_eq!(
+            split("\u{1f1f3}\u{1f1f1}\u{1f1e7}\u{1f1ea}"),
+            ["\u{1f1f3}\u{1f1f1}", "\u{1f1e7}\u{1f1ea}"]
+        );
+        // Hangul syllable "han" written as conjoining jamo, and precomposed.
+        assert_eq!(
+            split("\u{1112}\u{1161}\u{11ab}\u{d55c}"),
+            ["\u{1112}\u{1161}\u{11ab}", "\u{d55c}"]
+        );
+    }
+}
@@ -22,6 +22,7 @@ const BUILTINS: &[&str] = &[
     "flat_map",
     "fold",
     "get",
+    "graphemes",
     "group_by",
     "join",
     "key_by",
@@ -29,17 +30,24 @@ const BUILTINS: &[&str] = &[
     "len",
     "map",
     "parse_int",
+    "parse_key_value",
+    "parse_utf8",
     "remove_prefix",
     "remove_suffix",
     "replace",
     "reverse",
+    "sorted_items",
     "split",
     "split_lines",
     "starts_with",
+    "strip_comments",
     "std",
     "sum",
+    "to_base64",
+    "to_hex",
     "to_lowercase",
     "to_uppercase",
+    "to_utf8",
     "values",
 ];
 
@@ -48,8 +56,10 @@ fn get_markup(token: &Token) -> Markup {
         Token::LineComment => Markup::Comment,
         Token::NumBinary | Token::NumHexadecimal | Token::NumDecimal => Markup::Number,
         Token::QuoteOpen(..) | Token::QuoteClose | Token::StringInner => Markup::String,
+        Token::BytesHexadecimal | Token::BytesBase64 => Markup::String,
         Token::HoleOpen | Token::HoleClose | Token::Escape(..) => Markup::Escape,
         Token::Ident => Markup::Field,
+        Token::Tag => Markup::Type,
 
         Token::KwAnd
         | Token::KwAssert
@@ -60,6 +70,7 @@ fn get_markup(token: &Token) -> Markup {
         | Token::KwImport
         | Token::KwIn
         | Token::KwLet
+        | Token::KwMatch
         | Token::KwNot
         | Token::KwNull
         | Token::KwOr
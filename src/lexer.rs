@@ -31,8 +31,12 @@ pub enum Escape {
     Single,
     /// A `\u` escape sequence followed by 4 hex digits.
     Unicode4,
+    /// Two `\u` escape sequences that form a UTF-16 surrogate pair, as in json.
+    SurrogatePair,
     /// A `\u{...}` escape sequence.
     UnicodeDelim,
+    /// A `\x` escape sequence followed by 2 hex digits.
+    Hex2,
 }
 
 /// Whether a string literal is a format string or a regular string.
@@ -42,6 +46,8 @@ pub enum StringPrefix {
     None,
     /// An `f` prefix that indicates a format string.
     Format,
+    /// An `r` prefix that indicates a raw string, without escape sequences.
+    Raw,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -95,6 +101,15 @@ pub enum Token {
     /// A decimal number literal, same as allowed by json.
     NumDecimal,
 
+    /// A bytes literal in hexadecimal notation, `0x"..."`.
+    BytesHexadecimal,
+
+    /// A bytes literal in base64 notation, `b64"..."`.
+    BytesBase64,
+
+    /// A tag that starts with `#` followed by an identifier, e.g. `#tcp`.
+    Tag,
+
     /// `and`
     KwAnd,
 
@@ -122,6 +137,9 @@ pub enum Token {
     /// `let`
     KwLet,
 
+    /// `match`
+    KwMatch,
+
     /// `not`
     KwNot,
 
@@ -179,6 +197,9 @@ pub enum Token {
     /// `=>`
     FatArrow,
 
+    /// `|>`
+    PipeGt,
+
     /// `=` (a single equals sign)
     Eq1,
 
@@ -226,6 +247,8 @@ enum State {
     String(QuoteStyle),
     /// Inside an f-string (in the string part, not the hole part).
     Format(QuoteStyle),
+    /// Inside a raw string literal.
+    Raw(QuoteStyle),
     /// Inside a hole in an f-string.
     Hole,
 }
@@ -268,6 +291,21 @@ pub fn is_keyword(ident: &str) -> bool {
     Lexer::get_keyword_or_ident(ident) != Token::Ident
 }
 
+/// Return whether the input starts with a high and low surrogate `\u` escape.
+fn is_surrogate_pair(input: &[u8]) -> bool {
+    let code_unit = |escape: Option<&[u8]>| -> Option<u16> {
+        let hex = escape?.strip_prefix(b"\\u")?;
+        if !hex.iter().all(|ch| ch.is_ascii_hexdigit()) {
+            return None;
+        }
+        u16::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+    };
+    matches!(
+        (code_unit(input.get(0..6)), code_unit(input.get(6..12))),
+        (Some(0xd800..=0xdbff), Some(0xdc00..=0xdfff))
+    )
+}
+
 struct Lexer<'a> {
     input: &'a str,
     doc: DocId,
@@ -373,6 +411,7 @@ impl<'a> Lexer<'a> {
         match self.state.last() {
             Some((_, State::String(style))) => self.next_in_string(*style),
             Some((_, State::Format(style))) => self.next_in_format(*style),
+            Some((_, State::Raw(style))) => self.next_in_raw(*style),
             _ => self.next_normal(),
         }
     }
@@ -402,6 +441,13 @@ impl<'a> Lexer<'a> {
             return Ok((Token::QuoteOpen(StringPrefix::Format, style), span));
         }
 
+        if input.starts_with(b"r\"\"\"") {
+            let style = QuoteStyle::Triple;
+            let span = self.span(4);
+            self.state.push((span, State::Raw(style)));
+            return Ok((Token::QuoteOpen(StringPrefix::Raw, style), span));
+        }
+
         if input.starts_with(b"\"\"\"") {
             let style = QuoteStyle::Triple;
             let span = self.span(3);
@@ -416,6 +462,13 @@ impl<'a> Lexer<'a> {
             return Ok((Token::QuoteOpen(StringPrefix::Format, style), span));
         }
 
+        if input.starts_with(b"r\"") {
+            let style = QuoteStyle::Double;
+            let span = self.span(2);
+            self.state.push((span, State::Raw(style)));
+            return Ok((Token::QuoteOpen(StringPrefix::Raw, style), span));
+        }
+
         if input[0] == b'"' {
             let style = QuoteStyle::Double;
             let span = self.span(1);
@@ -434,6 +487,14 @@ impl<'a> Lexer<'a> {
             // normally.
         }
 
+        if input.starts_with(b"0x\"") {
+            return self.lex_in_bytes(Token::BytesHexadecimal, 2);
+        }
+
+        if input.starts_with(b"b64\"") {
+            return self.lex_in_bytes(Token::BytesBase64, 3);
+        }
+
         if input[0].is_ascii_digit() {
             return self.lex_in_number();
         }
@@ -442,6 +503,15 @@ impl<'a> Lexer<'a> {
             return Ok(self.lex_in_ident());
         }
 
+        if input[0] == b'#'
+            && matches!(input.get(1), Some(ch) if *ch == b'_' || ch.is_ascii_alphabetic())
+        {
+            let span = self.skip_take_while(1, |ch| {
+                ch.is_ascii_alphanumeric() || ch == b'_' || ch == b'-'
+            });
+            return Ok((Token::Tag, span));
+        }
+
         if input[0].is_ascii_whitespace() {
             return Ok(self.lex_in_space());
         }
@@ -504,6 +574,7 @@ impl<'a> Lexer<'a> {
             "import" => Token::KwImport,
             "in" => Token::KwIn,
             "let" => Token::KwLet,
+            "match" => Token::KwMatch,
             "not" => Token::KwNot,
             "null" => Token::KwNull,
             "or" => Token::KwOr,
@@ -529,6 +600,45 @@ impl<'a> Lexer<'a> {
         (Token::LineComment, self.take_while(|ch| ch != b'\n'))
     }
 
+    /// Lex a bytes literal with a prefix of the given length, up to the closing quote.
+    ///
+    /// The digits are validated when the literal is decoded, here we only
+    /// ensure that the literal contains no characters that could be confused
+    /// with a string, so the entire literal can be a single token.
+    fn lex_in_bytes(&mut self, token: Token, prefix_len: usize) -> Result<Lexeme> {
+        let input = &self.input.as_bytes()[self.start..];
+        // Skip over the prefix and the opening quote.
+        let mut n = prefix_len + 1;
+        loop {
+            match input.get(n) {
+                Some(b'"') => return Ok((token, self.span(n + 1))),
+                Some(ch) if ch.is_ascii_alphanumeric() || matches!(ch, b'+' | b'/' | b'=') => {
+                    n += 1
+                }
+                Some(b'\n') | None => {
+                    let open = Span::new(self.doc, self.start, self.start + prefix_len + 1);
+                    self.start += n;
+                    return self
+                        .span(0)
+                        .error("Expected '\"' to close the bytes literal.")
+                        .with_note(open, "Bytes literal opened here.")
+                        .err();
+                }
+                Some(_) => {
+                    self.start += n;
+                    return self
+                        .span_char(1)
+                        .error("Unexpected character in bytes literal.")
+                        .with_help(
+                            "Bytes literals contain only hexadecimal digits for '0x\"', \
+                            or base64 digits for 'b64\"', without spaces.",
+                        )
+                        .err();
+                }
+            }
+        }
+    }
+
     fn lex_in_number(&mut self) -> Result<Lexeme> {
         let mut input = &self.input.as_bytes()[self.start..];
         let mut n = 0;
@@ -642,6 +752,7 @@ impl<'a> Lexer<'a> {
             b"!=" => Token::Neq,
             b"->" => Token::ThinArrow,
             b"=>" => Token::FatArrow,
+            b"|>" => Token::PipeGt,
             _ => return None,
         };
 
@@ -734,6 +845,19 @@ impl<'a> Lexer<'a> {
         Ok((Token::StringInner, span))
     }
 
+    /// Continue lexing inside a raw string.
+    ///
+    /// Raw strings have no escape sequences, so the only special thing inside
+    /// them is the closing quote.
+    fn next_in_raw(&mut self, style: QuoteStyle) -> Result<Lexeme> {
+        if let Some(lexeme) = self.lex_string_close(style) {
+            return Ok(lexeme);
+        }
+        // Same as in `next_in_string`, but `\` is not special.
+        let span = self.skip_take_while(1, |ch| !matches!(ch, b'"' | b'\n'));
+        Ok((Token::StringInner, span))
+    }
+
     /// Lex the cases in a string literal shared between strings and f-strings.
     fn lex_in_string(&mut self, style: QuoteStyle) -> Result<Option<Lexeme>> {
         let input = &self.input.as_bytes()[self.start..];
@@ -774,6 +898,9 @@ impl<'a> Lexer<'a> {
                 .take(4)
                 .count();
             if n == 4 {
+                if is_surrogate_pair(input) {
+                    return Ok(Some((Token::Escape(Escape::SurrogatePair), self.span(12))));
+                }
                 return Ok(Some((Token::Escape(Escape::Unicode4), self.span(6))));
             } else {
                 return self
@@ -786,6 +913,24 @@ impl<'a> Lexer<'a> {
                     .err();
             }
         }
+        if input.starts_with(b"\\x") {
+            // As for `\u`, take at most as many hex digits as we need.
+            let n = input
+                .iter()
+                .skip(2)
+                .take_while(|ch| ch.is_ascii_hexdigit())
+                .take(2)
+                .count();
+            if n == 2 {
+                return Ok(Some((Token::Escape(Escape::Hex2), self.span(4))));
+            } else {
+                return self
+                    .span(2 + n)
+                    .error("Expected two hex digits after '\\x' escape sequence.")
+                    .with_help("For example '\\x0a' for a line feed.")
+                    .err();
+            }
+        }
         if input[0] == b'\\' {
             if input.len() == 1 {
                 return self
@@ -819,16 +964,22 @@ impl<'a> Lexer<'a> {
             };
         }
 
+        Ok(self.lex_string_close(style))
+    }
+
+    /// Lex the closing quote of a string literal, if it is there.
+    fn lex_string_close(&mut self, style: QuoteStyle) -> Option<Lexeme> {
+        let input = &self.input.as_bytes()[self.start..];
         match style {
             QuoteStyle::Double if input[0] == b'"' => {
                 self.state.pop();
-                Ok(Some((Token::QuoteClose, self.span(1))))
+                Some((Token::QuoteClose, self.span(1)))
             }
             QuoteStyle::Triple if input.starts_with(b"\"\"\"") => {
                 self.state.pop();
-                Ok(Some((Token::QuoteClose, self.span(3))))
+                Some((Token::QuoteClose, self.span(3)))
             }
-            _ => Ok(None),
+            _ => None,
         }
     }
 
@@ -853,7 +1004,7 @@ impl<'a> Lexer<'a> {
             State::Hole => span
                 .error("Expected '}' here to close format string hole.")
                 .with_note(top.0, "Unmatched '{' opened here."),
-            State::String(..) => span
+            State::String(..) | State::Raw(..) => span
                 .error("Unexpected end of input, string literal is not closed.")
                 .with_note(top.0, "String literal opened here."),
             State::Format(..) => span
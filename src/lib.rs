@@ -14,33 +14,72 @@
 mod cli_utils;
 
 pub mod abstraction;
+#[cfg(feature = "arena")]
+pub mod arena;
 pub mod ast;
+pub mod bytes;
 pub mod cli;
 pub mod cmd_build;
+pub mod cmd_bundle;
+pub mod cmd_convert;
+pub mod cmd_crash_repro;
+pub mod cmd_doc;
 pub mod cmd_eval;
+pub mod cmd_lint;
+pub mod cmd_lock;
+pub mod cmd_serve;
+pub mod convert_json;
+#[cfg(feature = "jsonnet")]
+pub mod convert_jsonnet;
+pub mod convert_toml;
+pub mod convert_yaml;
 pub mod cst;
+pub mod csv;
+pub mod debugger;
+pub mod doc_comment;
 pub mod env;
 pub mod error;
 pub mod eval;
 pub mod fmt_cst;
+pub mod fmt_csv;
 pub mod fmt_json;
 pub mod fmt_raw;
 pub mod fmt_rcl;
+pub mod fmt_terraform;
 pub mod fmt_toml;
 pub mod fmt_type;
+pub mod fmt_yaml;
 pub mod fmt_yaml_stream;
+pub mod grapheme;
 pub mod highlight;
 pub mod lexer;
 pub mod loader;
 pub mod markup;
+pub mod native;
+pub mod net;
+pub mod number;
+pub mod optimize;
+pub mod output;
+pub mod parallel;
 pub mod parser;
 pub mod pprint;
+pub mod profile;
+pub mod regex;
+pub mod repl;
 pub mod runtime;
+pub mod secret;
+pub mod semver;
+pub mod sha256;
+pub mod small_collection;
+pub mod small_str;
 pub mod source;
+pub mod source_map;
 pub mod stdlib;
 pub mod string;
+pub mod template;
 pub mod tracer;
 pub mod type_diff;
 pub mod type_source;
 pub mod typecheck;
 pub mod types;
+pub mod url;
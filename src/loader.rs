@@ -7,26 +7,35 @@
 
 //! The loader is responsible for loading documents.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
-use std::fs::File;
 use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 use std::{env, path};
 
 use crate::abstraction;
-use crate::ast;
+use crate::ast::{self, Ident};
 use crate::cli::Target;
 use crate::cst;
+use crate::debugger::Debugger;
 use crate::error::{Error, Result};
-use crate::eval::Evaluator;
+use crate::eval::{Evaluator, StrictMode};
+use crate::fmt_rcl::Limits;
 use crate::lexer;
+use crate::optimize;
+use crate::parallel::Shard;
 use crate::parser;
 use crate::pprint::{self, concat, indent};
+use crate::profile::Profiler;
 use crate::runtime::{Env, Value};
 use crate::source::{Doc, DocId, Span};
-use crate::tracer::Tracer;
+use crate::source_map::{SourceMap, ValueSpans};
+use crate::stdlib::{self, Capabilities};
+use crate::tracer::{Tracer, Warning};
 use crate::typecheck::{self, TypeChecker};
+use crate::types::SourcedType;
 
 /// An owned document.
 ///
@@ -47,15 +56,50 @@ pub struct Document {
 }
 
 impl Document {
-    pub fn as_doc(&self) -> Doc {
+    pub fn as_doc(&self, tab_width: u32) -> Doc {
         Doc {
             name: &self.name,
             data: &self.data,
             span: self.span,
+            tab_width,
         }
     }
 }
 
+/// Decode the raw contents of a source file.
+///
+/// A leading UTF-8 byte order mark is skipped. UTF-16 inputs and invalid UTF-8
+/// are rejected, with a message that starts with `context`.
+pub fn decode_source(context: pprint::Doc<'static>, mut bytes: Vec<u8>) -> Result<String> {
+    if bytes.starts_with(b"\xef\xbb\xbf") {
+        bytes.drain(..3);
+    }
+
+    // A UTF-16 document starts with a byte order mark. Without one, documents
+    // are usually ASCII text, so one byte of the first code unit is zero.
+    let is_utf16 = match bytes.get(..2) {
+        Some([0xff, 0xfe] | [0xfe, 0xff]) => true,
+        Some([0, x] | [x, 0]) => *x != 0,
+        _ => false,
+    };
+    if is_utf16 {
+        let err = Error::new(concat! { context "the input appears to be UTF-16." })
+            .with_help("RCL documents must be UTF-8, convert the input to UTF-8 first.");
+        return err.err();
+    }
+
+    String::from_utf8(bytes).map_err(|err| {
+        let offset = err.utf8_error().valid_up_to();
+        Error::new(concat! {
+            context
+            "invalid UTF-8 at byte offset "
+            offset.to_string()
+            "."
+        })
+        .into()
+    })
+}
+
 #[derive(Debug)]
 pub struct PathLookup {
     /// A friendly name displayed to the user.
@@ -97,17 +141,23 @@ pub trait Filesystem {
     /// Load a resolved path from the filesystem.
     fn load(&self, path: PathLookup) -> Result<Document>;
 
-    /// Resolve a target output path relative to the `from` path, and open it.
+    /// Read the raw bytes of a resolved path, for files that are not documents.
+    fn read_bytes(&self, path: &PathLookup) -> Result<Vec<u8>>;
+
+    /// Resolve a target output path relative to the `from` path.
     ///
     /// This creates intermediate directories if needed, and checks the sandbox
     /// policy at every step along the way. The `from` path is relative to the
     /// working directory, just like with [`resolve`].
-    fn open_build_output(&self, out_path: &str, from: &str) -> Result<fs::File>;
+    fn resolve_build_output(&self, out_path: &str, from: &str) -> Result<PathBuf>;
 
     /// Return `path`, but relative to the working directory, if possible.
     ///
     /// If the path lies outside of the working directory, return the original.
     fn get_relative_path<'a>(&self, path: &'a Path) -> &'a Path;
+
+    /// Describe which files the filesystem allows loading, for error messages.
+    fn describe_policy(&self) -> pprint::Doc<'static>;
 }
 
 /// A dummy filesystem impl to use during initialization.
@@ -134,12 +184,18 @@ impl Filesystem for PanicFilesystem {
     fn load(&self, _: PathLookup) -> Result<Document> {
         panic!("Should have initialized the filesystem to a real one before loading.")
     }
-    fn open_build_output(&self, _: &str, _: &str) -> Result<File> {
+    fn read_bytes(&self, _: &PathLookup) -> Result<Vec<u8>> {
+        panic!("Should have initialized the filesystem to a real one before loading.")
+    }
+    fn resolve_build_output(&self, _: &str, _: &str) -> Result<PathBuf> {
         panic!("Should have initialized the filesystem to a real one before resolving.")
     }
     fn get_relative_path<'a>(&self, _: &'a Path) -> &'a Path {
         panic!("Should have initialized the filesystem to a real one before resolving.")
     }
+    fn describe_policy(&self) -> pprint::Doc<'static> {
+        panic!("Should have initialized the filesystem to a real one before resolving.")
+    }
 }
 // coverage:on
 
@@ -162,14 +218,20 @@ impl Filesystem for VoidFilesystem {
     fn load(&self, _: PathLookup) -> Result<Document> {
         Error::new("Void filesystem does not load files.").err()
     }
-    fn open_build_output(&self, _: &str, _: &str) -> Result<File> {
-        panic!("Void filesystem does not open files.")
+    fn read_bytes(&self, _: &PathLookup) -> Result<Vec<u8>> {
+        Error::new("Void filesystem does not load files.").err()
+    }
+    fn resolve_build_output(&self, _: &str, _: &str) -> Result<PathBuf> {
+        panic!("Void filesystem should not be used for output paths.")
     }
     fn get_relative_path<'a>(&self, _: &'a Path) -> &'a Path {
         // It's okay to panic here, `get_relative_path` is only used in features
         // that are not used by the fuzzer.
         panic!("Void filesystem does not relativize paths.")
     }
+    fn describe_policy(&self) -> pprint::Doc<'static> {
+        "Filesystem access is disabled.".into()
+    }
 }
 // coverage:on
 
@@ -321,14 +383,15 @@ impl Filesystem for SandboxFilesystem {
     }
 
     fn load(&self, path: PathLookup) -> Result<Document> {
-        let buf = fs::read_to_string(&path.path).map_err(|err| {
-            Error::new(concat! {
+        let context = || {
+            concat! {
                 "Failed to read from file '"
                 pprint::Doc::path(&path.path)
                 "': "
-                err.to_string()
-            })
-        })?;
+            }
+        };
+        let bytes = self.read_bytes(&path)?;
+        let buf = decode_source(context(), bytes)?;
 
         let doc = Document {
             name: path.name,
@@ -340,7 +403,19 @@ impl Filesystem for SandboxFilesystem {
         Ok(doc)
     }
 
-    fn open_build_output(&self, out_path: &str, from: &str) -> Result<File> {
+    fn read_bytes(&self, path: &PathLookup) -> Result<Vec<u8>> {
+        fs::read(&path.path).map_err(|err| {
+            Error::new(concat! {
+                "Failed to read from file '"
+                pprint::Doc::path(&path.path)
+                "': "
+                err.to_string()
+            })
+            .into()
+        })
+    }
+
+    fn resolve_build_output(&self, out_path: &str, from: &str) -> Result<PathBuf> {
         // The initial steps are similar to `resolve`, but we don't need to
         // support workdir-relative paths with `//`.
         let mut path_buf = self.workdir.clone();
@@ -437,16 +512,7 @@ impl Filesystem for SandboxFilesystem {
             }
         }
 
-        match std::fs::File::create(&path_buf) {
-            Err(err) => Error::new(concat! {
-                "Failed to create output file '"
-                pprint::Doc::path(path_buf)
-                "': "
-                err.to_string()
-            })
-            .err(),
-            Ok(f) => Ok(f),
-        }
+        Ok(path_buf)
     }
 
     fn get_relative_path<'a>(&self, path: &'a Path) -> &'a Path {
@@ -455,6 +521,104 @@ impl Filesystem for SandboxFilesystem {
             Err(..) => path,
         }
     }
+    fn describe_policy(&self) -> pprint::Doc<'static> {
+        match self.mode {
+            SandboxMode::Workdir => concat! {
+                "Sandbox policy '"
+                pprint::Doc::highlight("workdir")
+                "' allows loading files inside '"
+                pprint::Doc::path(&self.workdir)
+                "'."
+            },
+            SandboxMode::Unrestricted => concat! {
+                "Sandbox policy '"
+                pprint::Doc::highlight("unrestricted")
+                "' allows loading any file."
+            },
+        }
+    }
+}
+
+/// File contents that are shared between the loaders of worker threads.
+///
+/// Parsed documents hold `Rc`s, so they can't cross threads, and every worker
+/// parses and typechecks its own copy. But a file that many build files
+/// import is read from disk only once.
+#[derive(Clone, Default)]
+pub struct SharedSources {
+    sources: Arc<Mutex<HashMap<PathBuf, Arc<str>>>>,
+}
+
+/// A filesystem that loads through [`SharedSources`].
+///
+/// Resolving paths, and with it the sandbox policy, is left to the inner
+/// filesystem. Only successful loads are shared, errors are not cached.
+struct SharedFilesystem {
+    inner: Box<dyn Filesystem>,
+    sources: SharedSources,
+}
+
+impl Filesystem for SharedFilesystem {
+    fn resolve(&self, path: &str, from: &str) -> Result<PathLookup> {
+        self.inner.resolve(path, from)
+    }
+    fn resolve_entrypoint(&self, path: &str) -> Result<PathLookup> {
+        self.inner.resolve_entrypoint(path)
+    }
+    fn resolve_cli_output(&self, path: &str) -> PathBuf {
+        self.inner.resolve_cli_output(path)
+    }
+    fn load(&self, path: PathLookup) -> Result<Document> {
+        let cached = self
+            .sources
+            .sources
+            .lock()
+            .expect("No thread panics while holding the lock.")
+            .get(&path.path)
+            .cloned();
+        if let Some(data) = cached {
+            return Ok(Document {
+                name: path.name,
+                data: data.to_string(),
+                // This span is a placeholder that is overwritten later when we push.
+                span: Span::new(DocId(0), 0, 0),
+            });
+        }
+        let key = path.path.clone();
+        let doc = self.inner.load(path)?;
+        self.sources
+            .sources
+            .lock()
+            .expect("No thread panics while holding the lock.")
+            .insert(key, doc.data.as_str().into());
+        Ok(doc)
+    }
+    fn read_bytes(&self, path: &PathLookup) -> Result<Vec<u8>> {
+        self.inner.read_bytes(path)
+    }
+    fn resolve_build_output(&self, out_path: &str, from: &str) -> Result<PathBuf> {
+        self.inner.resolve_build_output(out_path, from)
+    }
+    fn get_relative_path<'a>(&self, path: &'a Path) -> &'a Path {
+        self.inner.get_relative_path(path)
+    }
+    fn describe_policy(&self) -> pprint::Doc<'static> {
+        self.inner.describe_policy()
+    }
+}
+
+/// A document that was parsed and typechecked in the prelude environment.
+struct CheckedDoc {
+    ast: Rc<ast::Expr>,
+
+    /// Warnings from the typechecker, to report again when we reuse the AST.
+    warnings: Vec<Warning>,
+
+    /// Whether constant folding was applied to the AST.
+    ///
+    /// The debugger needs the AST as written, so it can't reuse a folded AST
+    /// that an earlier evaluation cached, and vice versa.
+    folded: bool,
 }
 
 pub struct Loader {
@@ -465,7 +629,61 @@ pub struct Loader {
     /// This enables us to avoid loading the same file twice.
     loaded_files: HashMap<PathBuf, DocId>,
 
+    /// Files that [`Loader::forget_changed_files`] dropped, with their old id.
+    ///
+    /// When such a file is loaded again, the new contents take the place of
+    /// the old document, so reloading does not grow the list of documents.
+    forgotten_files: HashMap<PathBuf, DocId>,
+
+    /// Files that were read for `std.file_sha256`, which are not documents.
+    ///
+    /// These are dependencies of the output, so they go into the depfile.
+    hashed_files: BTreeSet<PathBuf>,
+
+    /// For every document, the documents that imported it.
+    ///
+    /// This is the import graph with its edges reversed. When a file changes,
+    /// we use it to find the documents that depend on it.
+    dependents: BTreeMap<DocId, BTreeSet<DocId>>,
+
+    /// Typechecked ASTs of imported documents.
+    ///
+    /// Imports are checked in a clean environment, so the result depends only
+    /// on the contents of the document, which never change for a given id, and
+    /// on whether we fold constants, which the cached entry records.
+    checked_imports: BTreeMap<DocId, CheckedDoc>,
+
     filesystem: Box<dyn Filesystem>,
+
+    /// The name of the document read from stdin, if any.
+    stdin_name: String,
+
+    /// Which builtins that have side effects to include in `std`.
+    capabilities: Capabilities,
+
+    /// Values that embedders added to the `std` namespace.
+    std_extensions: BTreeMap<Value, Value>,
+
+    /// Values that embedders made available by name in every document.
+    globals: Vec<(Ident, Value)>,
+
+    /// When enabled, spans of collection elements recorded during evaluation.
+    source_map: Option<SourceMap>,
+
+    /// When enabled, time and allocations attributed to frames during evaluation.
+    profiler: Option<Profiler>,
+
+    /// How much of values to print in error messages.
+    error_limits: Limits,
+
+    /// The number of columns between tab stops in diagnostics.
+    tab_width: u32,
+
+    /// Whether a `let` may shadow an outer binding without a warning.
+    allow_shadowing: bool,
+
+    /// Whether to fold constant expressions after typechecking.
+    optimize: bool,
 }
 
 impl Loader {
@@ -473,15 +691,180 @@ impl Loader {
         Loader {
             documents: Vec::new(),
             loaded_files: HashMap::new(),
+            forgotten_files: HashMap::new(),
+            hashed_files: BTreeSet::new(),
+            dependents: BTreeMap::new(),
+            checked_imports: BTreeMap::new(),
             filesystem: Box::new(PanicFilesystem),
+            stdin_name: "stdin".to_string(),
+            capabilities: Capabilities::default(),
+            std_extensions: BTreeMap::new(),
+            globals: Vec::new(),
+            source_map: None,
+            profiler: None,
+            error_limits: Limits::errors(),
+            tab_width: 8,
+            allow_shadowing: false,
+            optimize: true,
         }
     }
 
+    /// Set the name to use for the document read from stdin.
+    ///
+    /// The name is used in diagnostics, and as the base for relative imports.
+    pub fn set_stdin_name(&mut self, name: String) {
+        self.stdin_name = name;
+    }
+
+    /// Set which capabilities the builtins in `std` are allowed to use.
+    ///
+    /// Like [`Loader::register_std`], this affects the prelude.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    /// Set how much of values to print in error messages.
+    ///
+    /// By default, error messages elide the parts of values that exceed
+    /// [`Limits::errors`].
+    pub fn set_error_limits(&mut self, limits: Limits) {
+        self.error_limits = limits;
+    }
+
+    /// Return how much of values to print in error messages.
+    pub fn error_limits(&self) -> Limits {
+        self.error_limits
+    }
+
+    /// Set the number of columns between tab stops in diagnostics.
+    ///
+    /// This affects the reported column numbers, and how source lines that
+    /// contain tabs are displayed. The default is 8.
+    pub fn set_tab_width(&mut self, tab_width: u32) {
+        self.tab_width = tab_width;
+    }
+
+    /// Set whether a `let` may shadow an outer binding without a warning.
+    pub fn set_allow_shadowing(&mut self, allow_shadowing: bool) {
+        self.allow_shadowing = allow_shadowing;
+        // Cached imports include their warnings, they need to be checked again.
+        self.checked_imports.clear();
+    }
+
+    /// Set whether to fold constant expressions after typechecking.
+    ///
+    /// Folding is enabled by default. See [`crate::optimize`] for what it does.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+        // Cached imports are stored after optimization, check them again.
+        self.checked_imports.clear();
+    }
+
+    /// Record a source map for the values that subsequent evaluations build.
+    ///
+    /// Take the result with [`Loader::take_source_map`].
+    pub fn enable_source_map(&mut self) {
+        self.source_map = Some(SourceMap::default());
+        self.drop_folded_imports();
+    }
+
+    /// Drop cached imports that were folded, we can't use them with a source map.
+    fn drop_folded_imports(&mut self) {
+        self.checked_imports.retain(|_id, checked| !checked.folded);
+    }
+
+    /// Return the source map that is being recorded, if enabled.
+    pub fn source_map_mut(&mut self) -> Option<&mut SourceMap> {
+        self.source_map.as_mut()
+    }
+
+    /// Stop recording a source map, and return what was recorded so far.
+    pub fn take_source_map(&mut self) -> Option<SourceMap> {
+        self.source_map.take()
+    }
+
+    /// Profile subsequent evaluations.
+    ///
+    /// Take the result with [`Loader::take_profiler`].
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::default());
+    }
+
+    /// Return the profiler that is recording, if enabled.
+    pub fn profiler_mut(&mut self) -> Option<&mut Profiler> {
+        self.profiler.as_mut()
+    }
+
+    /// Stop profiling, and return the profile recorded so far.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// Add a value, such as a custom builtin function, to the `std` namespace.
+    ///
+    /// This affects the environments returned by [`Loader::value_prelude`],
+    /// and the environment that imported documents are evaluated in. A value
+    /// with the same name as a standard library builtin replaces the builtin.
+    pub fn register_std(&mut self, name: &str, value: Value) {
+        self.std_extensions.insert(name.into(), value);
+    }
+
+    /// Make a value available under the given name in every document.
+    ///
+    /// Registering a namespace as a dict, rather than many individual names,
+    /// avoids shadowing names that documents define themselves. Like
+    /// [`Loader::register_std`], this affects the prelude.
+    pub fn register_global(&mut self, name: &str, value: Value) {
+        self.globals.push((name.into(), value));
+        // Globals are part of the environment that imports are checked in.
+        self.checked_imports.clear();
+    }
+
+    /// Return the type environment that documents are typechecked in.
+    pub fn type_prelude(&self) -> typecheck::Env {
+        let mut env = typecheck::prelude();
+        for (name, _value) in self.globals.iter() {
+            // We don't track the types of values at runtime, so the typechecker
+            // has to treat globals as `Any`, like `std` itself.
+            env.push(name.clone(), SourcedType::any());
+        }
+        env
+    }
+
+    /// Return the value environment that documents are evaluated in.
+    ///
+    /// This includes `std`, extended with any values added by [`Loader::register_std`].
+    pub fn value_prelude(&self) -> Env {
+        let mut stdlib = match stdlib::initialize(&self.capabilities) {
+            Value::Dict(builtins) => builtins,
+            _ => unreachable!("The standard library is a dict."),
+        };
+        if !self.std_extensions.is_empty() {
+            for (name, value) in self.std_extensions.iter() {
+                stdlib.insert(name.clone(), value.clone());
+            }
+        }
+        let mut env = Env::new();
+        env.push("std".into(), Value::Dict(stdlib));
+        for (name, value) in self.globals.iter() {
+            env.push(name.clone(), value.clone());
+        }
+        env
+    }
+
     /// Set the filesystem access handler.
     pub fn set_filesystem(&mut self, filesystem: Box<dyn Filesystem>) {
         self.filesystem = filesystem;
     }
 
+    /// Load files through `sources`, which other loaders may share.
+    ///
+    /// This wraps the current filesystem, so call it after initializing that.
+    pub fn share_sources(&mut self, sources: SharedSources) {
+        let inner = std::mem::replace(&mut self.filesystem, Box::new(PanicFilesystem));
+        self.filesystem = Box::new(SharedFilesystem { inner, sources });
+    }
+
     /// Enable filesystem access with the given sandbox mode.
     pub fn initialize_filesystem(
         &mut self,
@@ -503,20 +886,23 @@ impl Loader {
         self.filesystem.resolve_cli_output(path)
     }
 
-    /// Open an output file path specified in a build file.
-    pub fn open_build_output(&self, out_path: &str, from: DocId) -> Result<File> {
+    /// Resolve an output file path specified in a build file.
+    pub fn resolve_build_output(&self, out_path: &str, from: DocId) -> Result<PathBuf> {
         let from_name = self.get_doc(from).name;
-        self.filesystem.open_build_output(out_path, from_name)
+        self.filesystem.resolve_build_output(out_path, from_name)
     }
 
     /// Borrow all documents.
     pub fn as_inputs(&self) -> Vec<Doc> {
-        self.documents.iter().map(Document::as_doc).collect()
+        self.documents
+            .iter()
+            .map(|doc| doc.as_doc(self.tab_width))
+            .collect()
     }
 
     /// Borrow a document.
     pub fn get_doc(&self, id: DocId) -> Doc {
-        self.documents[id.0 as usize].as_doc()
+        self.documents[id.0 as usize].as_doc(self.tab_width)
     }
 
     /// Return the span of the document's body expression if known.
@@ -562,7 +948,51 @@ impl Loader {
         &mut self,
         env: &mut typecheck::Env,
         id: DocId,
+        tracer: &mut dyn Tracer,
     ) -> Result<ast::Expr> {
+        let (ast, warnings) = self.check_ast(env, id)?;
+        for warning in warnings {
+            tracer.warn(&self.as_inputs(), warning);
+        }
+        Ok(ast)
+    }
+
+    /// Return the checked Abstract Syntax Tree of a document for an import.
+    ///
+    /// Imports are checked in the prelude, so we cache the result per document.
+    /// A document is only lexed, parsed, and typechecked again when it is new,
+    /// for example after [`Loader::forget_changed_files`] dropped the file
+    /// because it, or one of the files it imports, changed, or when the cached
+    /// AST was folded differently than we need now.
+    pub fn get_import_ast(&mut self, id: DocId, tracer: &mut dyn Tracer) -> Result<Rc<ast::Expr>> {
+        let fold = self.should_fold();
+        let is_cached = self
+            .checked_imports
+            .get(&id)
+            .is_some_and(|checked| checked.folded == fold);
+        if !is_cached {
+            let mut env = self.type_prelude();
+            let (ast, warnings) = self.check_ast(&mut env, id)?;
+            let checked = CheckedDoc {
+                ast: Rc::new(ast),
+                warnings,
+                folded: fold,
+            };
+            self.checked_imports.insert(id, checked);
+        }
+        let checked = &self.checked_imports[&id];
+        for warning in checked.warnings.iter() {
+            tracer.warn(&self.as_inputs(), warning.clone());
+        }
+        Ok(checked.ast.clone())
+    }
+
+    /// Parse and typecheck the document, return the AST and any warnings.
+    fn check_ast(
+        &mut self,
+        env: &mut typecheck::Env,
+        id: DocId,
+    ) -> Result<(ast::Expr, Vec<Warning>)> {
         // The typechecker needs a span to blame type errors on, we put in the
         // entire document. It is not going to blame any type errors on this
         // span, because we check `Type::Any` which any value satisfies. If we
@@ -571,8 +1001,20 @@ impl Loader {
         let span = self.get_span(id);
         let mut ast = self.get_unchecked_ast(id)?;
         let mut checker = TypeChecker::new(env);
+        checker.allow_shadowing = self.allow_shadowing;
         checker.check_expr(typecheck::type_any(), span, &mut ast)?;
-        Ok(ast)
+        let warnings = std::mem::take(&mut checker.warnings);
+        if self.should_fold() {
+            optimize::optimize(&mut ast);
+        }
+        Ok((ast, warnings))
+    }
+
+    /// Whether to fold constants in documents that we check now.
+    fn should_fold(&self) -> bool {
+        // A source map records where values come from, folding would lose
+        // the comprehensions and variables that values pass through.
+        self.optimize && self.source_map.is_none()
     }
 
     /// Evaluate the given document and return the resulting value.
@@ -582,11 +1024,78 @@ impl Loader {
         value_env: &mut Env,
         id: DocId,
         tracer: &mut dyn Tracer,
+        strict_mode: StrictMode,
+    ) -> Result<Value> {
+        let mut evaluator = Evaluator::new(self, tracer);
+        evaluator.strict_mode = strict_mode;
+        evaluator.eval_doc(type_env, value_env, id)
+    }
+
+    /// Evaluate the given document, but only the top-level keys in `shard`.
+    ///
+    /// This lets multiple threads split the targets of a build file between
+    /// them. Only dict literals are split, a document that is not a dict
+    /// literal is evaluated by the first shard.
+    pub fn evaluate_shard(
+        &mut self,
+        type_env: &mut typecheck::Env,
+        value_env: &mut Env,
+        id: DocId,
+        tracer: &mut dyn Tracer,
+        strict_mode: StrictMode,
+        shard: Shard,
     ) -> Result<Value> {
         let mut evaluator = Evaluator::new(self, tracer);
+        evaluator.strict_mode = strict_mode;
+        evaluator.shard = Some(shard);
         evaluator.eval_doc(type_env, value_env, id)
     }
 
+    /// Evaluate the given document, and return the span of every part of the result.
+    ///
+    /// This records a source map during evaluation, which makes evaluation
+    /// slower, and disables constant folding for the document and its imports.
+    /// A source map that was enabled before is not affected.
+    pub fn evaluate_spanned(
+        &mut self,
+        type_env: &mut typecheck::Env,
+        value_env: &mut Env,
+        id: DocId,
+        tracer: &mut dyn Tracer,
+        strict_mode: StrictMode,
+    ) -> Result<(Value, ValueSpans)> {
+        let outer = self.source_map.replace(SourceMap::default());
+        self.drop_folded_imports();
+        let result = self.evaluate(type_env, value_env, id, tracer, strict_mode);
+        let source_map = std::mem::replace(&mut self.source_map, outer)
+            .expect("We enabled the source map before evaluating.");
+        let value = result?;
+        let spans = source_map.spans(self.get_span(id), &value);
+        Ok((value, spans))
+    }
+
+    /// Evaluate the given document, pausing in the debugger along the way.
+    ///
+    /// The debugger steps through the source as written, so the document and
+    /// its imports are not optimized, regardless of [`Loader::set_optimize`].
+    pub fn evaluate_debug(
+        &mut self,
+        type_env: &mut typecheck::Env,
+        value_env: &mut Env,
+        id: DocId,
+        tracer: &mut dyn Tracer,
+        strict_mode: StrictMode,
+        debugger: &mut Debugger,
+    ) -> Result<Value> {
+        let optimize = std::mem::replace(&mut self.optimize, false);
+        let mut evaluator = Evaluator::new(self, tracer);
+        evaluator.strict_mode = strict_mode;
+        evaluator.debugger = Some(debugger);
+        let result = evaluator.eval_doc(type_env, value_env, id);
+        self.optimize = optimize;
+        result
+    }
+
     /// Push a document and set its span to the full document.
     ///
     /// We set the span here because the span contains the document id, which is
@@ -599,14 +1108,22 @@ impl Loader {
         id
     }
 
+    /// Replace a forgotten document, and set its span to the full document.
+    fn replace(&mut self, id: DocId, mut document: Document) -> DocId {
+        document.span = Span::new(id, 0, document.data.len());
+        self.documents[id.0 as usize] = document;
+        id
+    }
+
     /// Load stdin into a new document.
     pub fn load_stdin(&mut self) -> Result<DocId> {
-        let mut buf = String::new();
+        let mut bytes = Vec::new();
         io::stdin()
-            .read_to_string(&mut buf)
+            .read_to_end(&mut bytes)
             .map_err(|err| Error::new(format!("Failed to read from stdin: {}.", err)))?;
+        let buf = decode_source("Failed to read from stdin: ".into(), bytes)?;
         let doc = Document {
-            name: "stdin".to_string(),
+            name: self.stdin_name.clone(),
             data: buf,
             // This span is a placeholder that is overwritten by `push`.
             span: Span::new(DocId(0), 0, 0),
@@ -622,7 +1139,55 @@ impl Loader {
         };
         let resolved = self.filesystem.resolve(path, from_path)?;
         assert!(!resolved.name.is_empty());
-        self.load_file(resolved)
+        let id = self.load_file(resolved)?;
+        if let Some(from_id) = from {
+            self.dependents.entry(id).or_default().insert(from_id);
+        }
+        Ok(id)
+    }
+
+    /// Explain how `path` referenced from `from` is resolved, for errors.
+    ///
+    /// This names the directory that a relative path is relative to, and the
+    /// sandbox policy that decides whether the file can be loaded.
+    pub fn describe_path_resolution(
+        &self,
+        path: &str,
+        from: Option<DocId>,
+    ) -> pprint::Doc<'static> {
+        let from_dir = match from {
+            Some(id) if !path.starts_with("//") => Path::new(self.get_doc(id).name).parent(),
+            _ => None,
+        };
+        let base = match from_dir {
+            Some(dir) if !dir.as_os_str().is_empty() => concat! {
+                "the directory '" pprint::Doc::path(dir) "'"
+            },
+            _ => "the working directory".into(),
+        };
+        concat! {
+            "The path '"
+            pprint::Doc::path(path)
+            "' is relative to "
+            base
+            ". "
+            self.filesystem.describe_policy()
+        }
+    }
+
+    /// Return the hex-encoded SHA-256 hash of a file referenced from `from`.
+    ///
+    /// Unlike [`Loader::load_path`], this does not create a document, so the
+    /// file does not need to be UTF-8. It is subject to the same sandbox policy.
+    pub fn hash_file(&mut self, path: &str, from: Option<DocId>) -> Result<String> {
+        let from_path = match from {
+            Some(id) => self.get_doc(id).name,
+            None => "",
+        };
+        let resolved = self.filesystem.resolve(path, from_path)?;
+        let bytes = self.filesystem.read_bytes(&resolved)?;
+        self.hashed_files.insert(resolved.path);
+        Ok(crate::sha256::sha256_hex(&bytes))
     }
 
     /// Load a file into a new document.
@@ -636,12 +1201,99 @@ impl Loader {
 
         let path_buf = path.path.clone();
         let doc = self.filesystem.load(path)?;
-        let id = self.push(doc);
+        let id = match self.forgotten_files.remove(&path_buf) {
+            Some(id) => self.replace(id, doc),
+            None => self.push(doc),
+        };
         self.loaded_files.insert(path_buf, id);
 
         Ok(id)
     }
 
+    /// Load a string into a new document that acts as if it were a file.
+    ///
+    /// Like for stdin with a custom name, diagnostics report the given name,
+    /// and relative imports are resolved relative to it. This is used by
+    /// `rcl serve` for the unsaved contents of an editor buffer.
+    pub fn load_named_string(&mut self, name: String, data: String) -> DocId {
+        let doc = Document {
+            name,
+            data,
+            // This span is a placeholder that is overwritten by `push`.
+            span: Span::new(DocId(0), 0, 0),
+        };
+        self.push(doc)
+    }
+
+    /// Return the number of documents loaded so far.
+    pub fn num_documents(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Forget files whose contents changed since they were loaded.
+    ///
+    /// A long-running process such as `rcl serve` keeps loaded files in memory
+    /// between evaluations. This re-reads them, and drops the files that
+    /// changed or can no longer be read, so they get loaded again on the
+    /// next import. Documents that depend on a changed file, directly or
+    /// through other imports, are dropped too, along with their cached ASTs.
+    /// Everything else stays cached. A dropped file keeps its document id, when
+    /// it gets loaded again, the new contents replace the old document, so ids
+    /// and spans of dropped documents must not be used after this call.
+    pub fn forget_changed_files(&mut self) {
+        let mut stale = Vec::new();
+        for (path, id) in self.loaded_files.iter() {
+            let doc = &self.documents[id.0 as usize];
+            let lookup = PathLookup {
+                name: doc.name.clone(),
+                path: path.clone(),
+            };
+            let is_unchanged = self
+                .filesystem
+                .read_bytes(&lookup)
+                .and_then(|bytes| decode_source("".into(), bytes))
+                .is_ok_and(|data| data == doc.data);
+            if !is_unchanged {
+                stale.push(*id);
+            }
+        }
+
+        let mut forgotten = BTreeSet::new();
+        while let Some(id) = stale.pop() {
+            if forgotten.insert(id) {
+                if let Some(importers) = self.dependents.get(&id) {
+                    stale.extend(importers.iter().copied());
+                }
+            }
+        }
+        let forgotten_files = self
+            .loaded_files
+            .iter()
+            .filter(|(_path, id)| forgotten.contains(id))
+            .map(|(path, id)| (path.clone(), *id))
+            .collect::<Vec<_>>();
+        for (path, id) in forgotten_files {
+            self.loaded_files.remove(&path);
+            self.forgotten_files.insert(path, id);
+        }
+        for id in forgotten.iter() {
+            self.dependents.remove(id);
+            self.checked_imports.remove(id);
+        }
+    }
+
+    /// Drop all documents, but keep the configuration of the loader.
+    ///
+    /// Document ids and spans from before this call are no longer valid.
+    pub fn clear_documents(&mut self) {
+        self.documents.clear();
+        self.loaded_files.clear();
+        self.forgotten_files.clear();
+        self.hashed_files.clear();
+        self.dependents.clear();
+        self.checked_imports.clear();
+    }
+
     /// Load a string into a new document.
     pub fn load_string(&mut self, data: String) -> DocId {
         let doc = Document {
@@ -654,6 +1306,33 @@ impl Loader {
     }
 
     /// Load the file with the given name, or stdin.
+    /// Return the name that a document loaded for the target would get.
+    pub fn get_cli_target_name<'a>(&'a self, target: &'a Target) -> &'a str {
+        match target {
+            Target::File(fname) => fname,
+            Target::Stdin | Target::StdinDefault => &self.stdin_name,
+            Target::Expr(..) => "<expr>",
+        }
+    }
+
+    /// Read the raw bytes of a CLI target, without decoding it as a document.
+    pub fn read_cli_target_bytes(&self, target: &Target) -> Result<Vec<u8>> {
+        match target {
+            Target::File(fname) => {
+                let path = self.filesystem.resolve_entrypoint(fname)?;
+                self.filesystem.read_bytes(&path)
+            }
+            Target::Stdin | Target::StdinDefault => {
+                let mut bytes = Vec::new();
+                io::stdin()
+                    .read_to_end(&mut bytes)
+                    .map_err(|err| Error::new(format!("Failed to read from stdin: {}.", err)))?;
+                Ok(bytes)
+            }
+            Target::Expr(expr) => Ok(expr.as_bytes().to_vec()),
+        }
+    }
+
     pub fn load_cli_target(&mut self, target: &Target) -> Result<DocId> {
         match target {
             Target::File(fname) => {
@@ -661,6 +1340,15 @@ impl Loader {
                 self.load_file(path)
             }
             Target::Stdin => self.load_stdin(),
+            Target::Expr(expr) => {
+                let doc = Document {
+                    name: "<expr>".to_string(),
+                    data: expr.clone(),
+                    // This span is a placeholder that is overwritten by `push`.
+                    span: Span::new(DocId(0), 0, 0),
+                };
+                Ok(self.push(doc))
+            }
             Target::StdinDefault => {
                 // We are in the case where the input is stdin, but stdin was
                 // selected implitictly, through the absence of a file argument.
@@ -690,7 +1378,11 @@ impl Loader {
         let rel_target = self.filesystem.get_relative_path(target_path);
         w.write_all(rel_target.as_os_str().as_bytes())?;
         w.write_all(b":")?;
-        for (path, _doc_id) in self.loaded_files.iter() {
+        let hashed_only = self
+            .hashed_files
+            .iter()
+            .filter(|path| !self.loaded_files.contains_key(*path));
+        for path in self.loaded_files.keys().chain(hashed_only) {
             let rel_path = self.filesystem.get_relative_path(path);
             w.write_all(b" ")?;
             w.write_all(rel_path.as_os_str().as_bytes())?;
@@ -727,3 +1419,365 @@ impl Loader {
             .map_err(|err| Error::new(format!("Failed to write depfile: {}.", err)).into())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+
+    use super::{decode_source, Document, Filesystem, Loader, PathLookup, SharedSources};
+    use crate::error::{Error, Result};
+    use crate::eval::{Evaluator, StrictMode};
+    use crate::parallel::Shard;
+    use crate::runtime::{BuiltinFunction, FunctionCall, Value};
+    use crate::source::{DocId, Span};
+    use crate::stdlib::Capabilities;
+    use crate::tracer::VoidTracer;
+    use crate::types::{builtin, make_function, make_type, Function, FunctionArg, Type};
+
+    fn lookup_secret(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+        match &call.args[0].value {
+            Value::String(name) => Ok(Value::String(format!("secret-{name}").into())),
+            _ => panic!("The test only passes strings."),
+        }
+    }
+
+    static LOOKUP_SECRET: BuiltinFunction = BuiltinFunction {
+        name: "std.lookup_secret",
+        type_: || make_function!((name: String) -> String),
+        f: lookup_secret,
+    };
+
+    fn eval(loader: &mut Loader, input: &str) -> Value {
+        let doc = loader.load_string(input.to_string());
+        eval_doc(loader, doc)
+    }
+
+    fn eval_doc(loader: &mut Loader, doc: DocId) -> Value {
+        let mut type_env = loader.type_prelude();
+        let mut value_env = loader.value_prelude();
+        let mut tracer = VoidTracer;
+        loader
+            .evaluate(
+                &mut type_env,
+                &mut value_env,
+                doc,
+                &mut tracer,
+                StrictMode::Off,
+            )
+            .expect("Evaluation should succeed.")
+    }
+
+    #[test]
+    fn register_std_adds_builtin() {
+        let mut loader = Loader::new();
+        loader.register_std("lookup_secret", Value::BuiltinFunction(&LOOKUP_SECRET));
+        let result = eval(&mut loader, "std.lookup_secret(\"db\")");
+        assert_eq!(result, "secret-db".into());
+        // The regular builtins are still there.
+        let result = eval(&mut loader, "std.range(0, 1)");
+        assert_eq!(result, Value::List(vec![Value::Int(0)].into()));
+    }
+
+    #[test]
+    fn capabilities_restrict_std() {
+        let mut loader = Loader::new();
+        let has_read = "std.keys().contains(\"read_file_utf8\")";
+        assert_eq!(eval(&mut loader, has_read), Value::Bool(true));
+        loader.set_capabilities(Capabilities::pure());
+        assert_eq!(eval(&mut loader, has_read), Value::Bool(false));
+        assert_eq!(
+            eval(&mut loader, "std.range(0, 0)"),
+            Value::List(vec![].into())
+        );
+    }
+
+    #[test]
+    fn register_global_binds_name() {
+        let mut loader = Loader::new();
+        loader.register_global("region", "eu-west".into());
+        let result = eval(&mut loader, "f\"{region}-1\"");
+        assert_eq!(result, "eu-west-1".into());
+    }
+
+    /// Files in memory, that count how often every file gets loaded.
+    #[derive(Default)]
+    struct MemoryFiles {
+        files: BTreeMap<String, String>,
+        loads: BTreeMap<String, u32>,
+    }
+
+    struct MemoryFilesystem(Rc<RefCell<MemoryFiles>>);
+
+    impl Filesystem for MemoryFilesystem {
+        fn resolve(&self, path: &str, _from: &str) -> Result<PathLookup> {
+            self.resolve_entrypoint(path)
+        }
+        fn resolve_entrypoint(&self, path: &str) -> Result<PathLookup> {
+            Ok(PathLookup {
+                name: path.to_string(),
+                path: PathBuf::from(path),
+            })
+        }
+        fn resolve_cli_output(&self, _: &str) -> PathBuf {
+            unreachable!("The test does not write files.")
+        }
+        fn load(&self, path: PathLookup) -> Result<Document> {
+            let bytes = self.read_bytes(&path)?;
+            *self
+                .0
+                .borrow_mut()
+                .loads
+                .entry(path.name.clone())
+                .or_default() += 1;
+            Ok(Document {
+                name: path.name,
+                data: decode_source("".into(), bytes)?,
+                span: Span::new(DocId(0), 0, 0),
+            })
+        }
+        fn read_bytes(&self, path: &PathLookup) -> Result<Vec<u8>> {
+            match self.0.borrow().files.get(&path.name) {
+                Some(data) => Ok(data.as_bytes().to_vec()),
+                None => Error::new("File does not exist.").err(),
+            }
+        }
+        fn resolve_build_output(&self, _: &str, _: &str) -> Result<PathBuf> {
+            unreachable!("The test does not write files.")
+        }
+        fn get_relative_path<'a>(&self, path: &'a Path) -> &'a Path {
+            path
+        }
+        fn describe_policy(&self) -> crate::pprint::Doc<'static> {
+            "Files are loaded from memory.".into()
+        }
+    }
+
+    #[test]
+    fn forget_changed_files_reloads_dependents_only() {
+        let files = Rc::new(RefCell::new(MemoryFiles::default()));
+        {
+            let mut files = files.borrow_mut();
+            let mut put = |name: &str, data: &str| files.files.insert(name.into(), data.into());
+            put("a.rcl", "[\"a\", import \"b.rcl\"]");
+            put("b.rcl", "\"b\"");
+            put("c.rcl", "\"c\"");
+        }
+        let mut loader = Loader::new();
+        loader.set_filesystem(Box::new(MemoryFilesystem(files.clone())));
+        let input = "[import \"a.rcl\", import \"c.rcl\"]";
+        let loads = |files: &Rc<RefCell<MemoryFiles>>| {
+            let files = files.borrow();
+            ["a.rcl", "b.rcl", "c.rcl"].map(|name| files.loads.get(name).copied())
+        };
+
+        let before = eval(&mut loader, input);
+        assert_eq!(loads(&files), [Some(1), Some(1), Some(1)]);
+        let num_documents = loader.num_documents();
+
+        // When nothing changed, nothing is loaded again.
+        loader.forget_changed_files();
+        assert_eq!(eval(&mut loader, input), before);
+        assert_eq!(loads(&files), [Some(1), Some(1), Some(1)]);
+
+        // When b changes, a which imports it must be reloaded too, but c not.
+        files
+            .borrow_mut()
+            .files
+            .insert("b.rcl".into(), "\"b2\"".into());
+        loader.forget_changed_files();
+        let after = eval(&mut loader, input);
+        assert_ne!(after, before);
+
+        // The reloaded files take the place of the old documents, only the
+        // input strings of the two evaluations since then are new.
+        assert_eq!(loader.num_documents(), num_documents + 2);
+        assert_eq!(after, eval(&mut loader, "[[\"a\", \"b2\"], \"c\"]"));
+        assert_eq!(loads(&files), [Some(2), Some(2), Some(1)]);
+    }
+
+    /// Return a loader that loads the given files from memory.
+    fn memory_loader(files: &[(&str, &str)]) -> Loader {
+        let mut memory = MemoryFiles::default();
+        for (name, data) in files {
+            memory.files.insert(name.to_string(), data.to_string());
+        }
+        let mut loader = Loader::new();
+        loader.set_filesystem(Box::new(MemoryFilesystem(Rc::new(RefCell::new(memory)))));
+        loader
+    }
+
+    /// Output that we can still read after the debugger took ownership.
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn evaluate_debug_does_not_reuse_folded_imports() {
+        use crate::debugger::{Breakpoint, Debugger};
+        use std::io::Cursor;
+
+        let mut loader = memory_loader(&[("lib.rcl", "let x = 1;\nlet y = x + 1;\n[x, y]")]);
+        let input = "import \"lib.rcl\"";
+
+        // The first evaluation caches the import with its lets folded away.
+        assert_eq!(eval(&mut loader, input), eval(&mut loader, "[1, 2]"));
+
+        let doc = loader.load_string(input.to_string());
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut debugger = Debugger::new(
+            vec![Breakpoint {
+                file: "lib.rcl".into(),
+                line: 2,
+            }],
+            false,
+            Box::new(Cursor::new("continue\n")),
+            Box::new(SharedBuf(output.clone())),
+        );
+        let mut type_env = loader.type_prelude();
+        let mut value_env = loader.value_prelude();
+        let result = loader.evaluate_debug(
+            &mut type_env,
+            &mut value_env,
+            doc,
+            &mut VoidTracer,
+            StrictMode::Off,
+            &mut debugger,
+        );
+        assert!(result.is_ok());
+        let output = String::from_utf8(output.take()).unwrap();
+        assert!(output.contains("Paused at breakpoint."), "{output}");
+    }
+
+    #[test]
+    fn source_map_does_not_reuse_folded_imports() {
+        use crate::source_map::StepKind;
+
+        let files = [("lib.rcl", "[for y in [2]: y * 10]")];
+        let input = "import \"lib.rcl\"";
+        let trace_first = |loader: &mut Loader| {
+            let doc = loader.load_string(input.to_string());
+            let value = eval_doc(loader, doc);
+            let source_map = loader.take_source_map().unwrap();
+            let provenance = source_map.trace(loader.get_span(doc), &value, "0");
+            provenance
+                .unwrap()
+                .iter()
+                .map(|step| step.kind)
+                .collect::<Vec<_>>()
+        };
+        let expected = [StepKind::Defined, StepKind::Comprehension];
+
+        let mut loader = memory_loader(&files);
+        loader.enable_source_map();
+        assert_eq!(trace_first(&mut loader), expected);
+
+        // When an evaluation without source map cached the import first, the
+        // cached import has its comprehension unrolled, we can't use it.
+        let mut loader = memory_loader(&files);
+        eval(&mut loader, input);
+        loader.enable_source_map();
+        assert_eq!(trace_first(&mut loader), expected);
+    }
+
+    #[test]
+    fn decode_source_handles_bom_and_encodings() {
+        let decode = |bytes: &[u8]| decode_source("".into(), bytes.to_vec());
+        let message = |bytes: &[u8]| {
+            let err = decode(bytes).unwrap_err();
+            let cfg = crate::pprint::Config { width: 80 };
+            err.message.println(&cfg).to_string_no_markup()
+        };
+
+        assert_eq!(decode(b"\xef\xbb\xbf42").unwrap(), "42");
+        assert_eq!(decode(b"{}").unwrap(), "{}");
+        assert_eq!(decode(b"").unwrap(), "");
+
+        assert!(message(b"\xff\xfe4\x002\x00").contains("UTF-16"));
+        assert!(message(b"\x004\x002").contains("UTF-16"));
+        assert_eq!(
+            message(b"[1, \xc0, 2]"),
+            "invalid UTF-8 at byte offset 4.\n"
+        );
+    }
+
+    #[test]
+    fn evaluate_shard_skips_keys_of_other_shards() {
+        // Only the shard that owns "b" evaluates it, and fails.
+        let input = "let x = 1; { for k in [\"a\", \"c\", \"d\"]: k: x, \"b\": 1 / 0, \"e\": { \"a\": 2 } }";
+        let b = Value::String("b".into());
+        let count = 2;
+        let mut keys = Vec::new();
+        for index in 0..count {
+            let shard = Shard { index, count };
+            let mut loader = Loader::new();
+            let doc = loader.load_string(input.into());
+            let mut type_env = loader.type_prelude();
+            let mut value_env = loader.value_prelude();
+            let result = loader.evaluate_shard(
+                &mut type_env,
+                &mut value_env,
+                doc,
+                &mut VoidTracer,
+                StrictMode::Off,
+                shard,
+            );
+            if shard.contains(&b) {
+                assert!(result.is_err());
+                continue;
+            }
+            for (k, v) in result.unwrap().expect_dict().iter() {
+                assert!(shard.contains(k));
+                keys.push(k.clone());
+                // Only the top-level dict is split, nested dicts are complete.
+                if k == &Value::String("e".into()) {
+                    assert_eq!(v.expect_dict().len(), 1);
+                }
+            }
+        }
+        // Every key that is not in the failing shard is in exactly one other.
+        keys.sort();
+        let expected: Vec<Value> = ["a", "c", "d", "e"]
+            .into_iter()
+            .map(|k| Value::String(k.into()))
+            .filter(|k| {
+                (0..count).any(|index| {
+                    let shard = Shard { index, count };
+                    shard.contains(k) && !shard.contains(&b)
+                })
+            })
+            .collect();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn shared_sources_read_every_file_once() {
+        let files = Rc::new(RefCell::new(MemoryFiles::default()));
+        files
+            .borrow_mut()
+            .files
+            .insert("a.rcl".into(), "[1, 2]".into());
+        let sources = SharedSources::default();
+        for _ in 0..2 {
+            let mut loader = Loader::new();
+            loader.set_filesystem(Box::new(MemoryFilesystem(files.clone())));
+            loader.share_sources(sources.clone());
+            assert_eq!(
+                eval(&mut loader, "import \"a.rcl\""),
+                Value::List(vec![Value::Int(1), Value::Int(2)].into())
+            );
+        }
+        assert_eq!(files.borrow().loads.get("a.rcl"), Some(&1));
+    }
+}
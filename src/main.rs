@@ -9,15 +9,24 @@ use std::io::Write;
 use std::path::Path;
 
 use rcl::cli::{
-    self, Cmd, EvalOptions, FormatTarget, GlobalOptions, OutputTarget, StyleOptions, Target,
+    self, Cmd, EvalOptions, FormatTarget, GlobalOptions, OutputFormat, OutputTarget, StyleOptions,
+    Target,
 };
+use rcl::cmd_build::{BuildMode, BuildOutput, OutputOwners};
+use rcl::cmd_convert::Converted;
+use rcl::debugger::Debugger;
 use rcl::error::{Error, Result};
-use rcl::loader::{Loader, SandboxMode};
-use rcl::markup::{MarkupMode, MarkupString};
+use rcl::eval::StrictMode;
+use rcl::fmt_cst::FormatOptions;
+use rcl::fmt_rcl::Limits;
+use rcl::loader::{Loader, SandboxMode, SharedSources};
+use rcl::markup::{Markup, MarkupMode, MarkupString};
+use rcl::parallel::Shard;
 use rcl::pprint::{self, Doc};
-use rcl::runtime::{self, Value};
-use rcl::source::Span;
-use rcl::tracer::StderrTracer;
+use rcl::repl::Repl;
+use rcl::runtime::Value;
+use rcl::source::{DocId, Span};
+use rcl::tracer::{BufferTracer, StderrTracer, Tracer};
 use rcl::typecheck;
 
 struct App {
@@ -25,6 +34,120 @@ struct App {
     opts: GlobalOptions,
 }
 
+/// Apply the global options that affect loading and diagnostics to a loader.
+fn configure_loader(loader: &mut Loader, opts: &GlobalOptions) {
+    if let Some(name) = &opts.stdin_name {
+        loader.set_stdin_name(name.clone());
+    }
+    if opts.full_errors {
+        loader.set_error_limits(Limits::default());
+    }
+    if let Some(tab_width) = opts.tab_width {
+        loader.set_tab_width(tab_width);
+    }
+    if opts.allow_shadowing {
+        loader.set_allow_shadowing(true);
+    }
+    if opts.no_opt {
+        loader.set_optimize(false);
+    }
+}
+
+/// Create a loader for a worker thread that processes some of the inputs.
+///
+/// If initializing the filesystem fails, return the rendered error instead.
+fn new_worker_loader(
+    opts: &GlobalOptions,
+    sandbox: SandboxMode,
+    markup: MarkupMode,
+) -> std::result::Result<Loader, Vec<u8>> {
+    let mut loader = Loader::new();
+    configure_loader(&mut loader, opts);
+    match loader.initialize_filesystem(sandbox, opts.workdir.as_deref()) {
+        Ok(()) => Ok(loader),
+        Err(err) => Err(render_report(markup, err.report(&[]))),
+    }
+}
+
+/// Render a diagnostic for stderr, so it can be printed later from another thread.
+fn render_report(markup: MarkupMode, doc: Doc) -> Vec<u8> {
+    let cfg = pprint::Config { width: 80 };
+    let mut out = Vec::new();
+    doc.println(&cfg)
+        .write_bytes(markup, &mut out)
+        .expect("Writing to a Vec does not fail.");
+    out
+}
+
+/// Render the error for a build file that failed on a worker thread.
+fn render_build_error(markup: MarkupMode, name: &str, report: Doc) -> Vec<u8> {
+    let doc = Doc::Concat(vec![
+        Doc::from("Failed to build").with_markup(Markup::Error),
+        Doc::str(" "),
+        Doc::highlight(name),
+        Doc::str(":"),
+        Doc::HardBreak,
+        Doc::HardBreak,
+        report,
+    ]);
+    render_report(markup, doc)
+}
+
+/// The result of formatting one file on a worker thread.
+enum FormatOutcome {
+    Formatted {
+        name: String,
+        formatted: String,
+        did_change: bool,
+    },
+    /// Loading or parsing failed, this holds the rendered error.
+    Failed(Vec<u8>),
+}
+
+/// The result of building one shard of a build file on a worker thread.
+struct BuildOutcome {
+    /// The formatted outputs, or the error rendered for stderr.
+    result: std::result::Result<Vec<BuildOutput>, Vec<u8>>,
+    /// Trace messages and warnings, rendered for stderr.
+    traces: Vec<u8>,
+}
+
+/// If `--locked` is set, verify the imports of `doc` against its lockfile.
+fn check_lock(
+    loader: &mut Loader,
+    tracer: &mut dyn Tracer,
+    eval_opts: &EvalOptions,
+    fname: &Target,
+    doc: DocId,
+) -> Result<()> {
+    if !eval_opts.locked {
+        return Ok(());
+    }
+    let lock_path = rcl::cmd_lock::get_lockfile_path(fname, eval_opts.lockfile.as_deref())?;
+    let lock_doc = loader.load_cli_target(&Target::File(lock_path))?;
+
+    // The lockfile is evaluated in a fresh environment, with the same
+    // capabilities, but it is only data, so strictness does not matter.
+    let mut type_env = loader.type_prelude();
+    let mut value_env = loader.value_prelude();
+    let locked = loader.evaluate(
+        &mut type_env,
+        &mut value_env,
+        lock_doc,
+        tracer,
+        StrictMode::Off,
+    )?;
+    let lock_span = loader.get_span(lock_doc);
+    rcl::cmd_lock::verify_lock(loader, doc, lock_span, &locked)
+}
+
+/// The result of checking one document on a worker thread.
+struct CheckOutcome {
+    is_ok: bool,
+    /// Trace messages, warnings, and the error if any, rendered for stderr.
+    stderr: Vec<u8>,
+}
+
 impl App {
     fn print_string(&self, mode: MarkupMode, data: MarkupString, out: &mut dyn Write) {
         let res = data.write_bytes(mode, out);
@@ -99,18 +222,30 @@ impl App {
         Ok(())
     }
 
-    fn print_doc_stderr(&self, doc: Doc) {
+    fn stderr_markup(&self) -> MarkupMode {
         let stderr = std::io::stderr();
-        let markup = self
-            .opts
+        self.opts
             .markup
-            .unwrap_or_else(|| MarkupMode::default_for_fd(&stderr));
+            .unwrap_or_else(|| MarkupMode::default_for_fd(&stderr))
+    }
+
+    fn print_doc_stderr(&self, doc: Doc) {
+        let markup = self.stderr_markup();
         let cfg = pprint::Config { width: 80 };
         let result = doc.println(&cfg);
-        let mut out = stderr.lock();
+        let mut out = std::io::stderr().lock();
         self.print_string(markup, result, &mut out);
     }
 
+    /// Write output that a worker thread already rendered to stderr.
+    fn print_bytes_stderr(&self, data: &[u8]) {
+        if std::io::stderr().lock().write_all(data).is_err() {
+            // If we fail to print to stderr, there is no point in printing an
+            // error, just exit then.
+            std::process::exit(1);
+        }
+    }
+
     pub fn print_value(
         &self,
         eval_opts: &EvalOptions,
@@ -119,7 +254,14 @@ impl App {
         value_span: Span,
         value: &Value,
     ) -> Result<()> {
-        let out_doc = rcl::cmd_eval::format_value(eval_opts.format, value_span, value)?;
+        let out_doc = rcl::cmd_eval::format_value(
+            eval_opts.format,
+            style_opts.limits,
+            eval_opts.output_encoding,
+            &eval_opts.secrets,
+            value_span,
+            value,
+        )?;
 
         // Prepend the banner if the user specified one.
         let out_doc = match eval_opts.banner.as_ref() {
@@ -130,6 +272,48 @@ impl App {
         self.print_doc_target(output, style_opts, out_doc)
     }
 
+    /// With `--locked`, confirm that the imports of `doc` match the lockfile.
+    fn check_lock(&mut self, eval_opts: &EvalOptions, fname: &Target, doc: DocId) -> Result<()> {
+        let mut tracer = self.get_tracer();
+        check_lock(&mut self.loader, &mut tracer, eval_opts, fname, doc)
+    }
+
+    /// Write the doc comments of a document to a file as json.
+    fn write_doc_comments(&mut self, doc: DocId, out_path: &str) -> Result<()> {
+        let cst = self.loader.get_cst(doc)?;
+        let input = self.loader.get_doc(doc).data;
+        let docs = rcl::doc_comment::collect_doc_comments(input, &cst);
+        let value = rcl::doc_comment::doc_comments_to_value(&docs);
+        let body_span = self.loader.get_span(doc);
+        let json = rcl::fmt_json::format_json(body_span, &value)?;
+        let cfg = pprint::Config { width: 80 };
+        self.print_to_file(MarkupMode::None, json.println(&cfg), out_path)
+    }
+
+    /// Write the source map of a value to a file as json.
+    fn write_source_map(&mut self, value_span: Span, value: &Value, out_path: &str) -> Result<()> {
+        let source_map = self
+            .loader
+            .take_source_map()
+            .expect("Source map was enabled before evaluation.");
+        let locations = source_map.locate(value_span, value);
+        let map = rcl::source_map::locations_to_value(&self.loader.as_inputs(), &locations);
+        let json = rcl::fmt_json::format_json(value_span, &map)?;
+        let cfg = pprint::Config { width: 80 };
+        self.print_to_file(MarkupMode::None, json.println(&cfg), out_path)
+    }
+
+    /// Write the profile, if profiling was enabled.
+    fn write_profile(&mut self, eval_opts: &EvalOptions) -> Result<()> {
+        let (profiler, out_path) = match (self.loader.take_profiler(), &eval_opts.profile) {
+            (Some(profiler), Some(out_path)) => (profiler, out_path),
+            _ => return Ok(()),
+        };
+        let report = profiler.report(&self.loader.as_inputs(), eval_opts.profile_format);
+        let cfg = pprint::Config { width: 80 };
+        self.print_to_file(MarkupMode::None, report.println(&cfg), out_path)
+    }
+
     fn print_fatal_error(&self, err: Error) -> ! {
         let inputs = self.loader.as_inputs();
         let err_doc = err.report(&inputs);
@@ -147,6 +331,8 @@ impl App {
         &mut self,
         output: OutputTarget,
         style_opts: &StyleOptions,
+        format_opts: FormatOptions,
+        jobs: Option<u32>,
         targets: FormatTarget,
     ) -> Result<()> {
         let cfg = pprint::Config {
@@ -157,7 +343,7 @@ impl App {
                 let doc = self.loader.load_cli_target(&fname)?;
                 let cst = self.loader.get_cst(doc)?;
                 let data = self.loader.get_doc(doc).data;
-                let res = rcl::fmt_cst::format_expr(data, &cst);
+                let res = rcl::fmt_cst::format_expr(data, &cst, format_opts);
                 return self.print_doc_target(output, style_opts, res);
             }
             FormatTarget::InPlace { fnames } => (true, fnames),
@@ -172,46 +358,84 @@ impl App {
         };
 
         let mut n_changed: u32 = 0;
-        let mut n_loaded: u32 = 0;
-
-        for target in fnames {
-            n_loaded += 1;
-            let doc = self.loader.load_cli_target(&target)?;
-            let cst = self.loader.get_cst(doc)?;
-            let data = self.loader.get_doc(doc).data;
-            let fmt_doc = rcl::fmt_cst::format_expr(data, &cst);
-            let res = fmt_doc.println(&cfg);
-            let formatted = res.to_string_no_markup();
-            let did_change = data != &formatted[..];
-
-            if is_write_in_place {
-                let fname = match target {
-                    Target::File(fname) => fname,
-                    Target::Stdin => {
-                        let msg =
-                            "Formatting in-place is only possible for named files, not for stdin.";
-                        return Error::new(msg).err();
-                    }
-                    Target::StdinDefault => {
-                        unreachable!("In-place default is empty list, not stdin.")
+        let n_loaded = fnames.len() as u32;
+        let markup = self.stderr_markup();
+        let jobs = jobs.map_or_else(rcl::parallel::default_jobs, |n| n as usize);
+
+        // Files are loaded, parsed, and formatted on worker threads, but we
+        // write the results from this thread in the order of the inputs. The
+        // first error is fatal, and files after it remain untouched.
+        rcl::parallel::for_each_ordered(
+            &fnames,
+            jobs,
+            || new_worker_loader(&self.opts, SandboxMode::Unrestricted, markup),
+            |loader, target| {
+                let loader = match loader {
+                    Ok(loader) => loader,
+                    Err(report) => return FormatOutcome::Failed(report.clone()),
+                };
+                let result = loader.load_cli_target(target).and_then(|doc| {
+                    let cst = loader.get_cst(doc)?;
+                    let input = loader.get_doc(doc);
+                    let fmt_doc = rcl::fmt_cst::format_expr(input.data, &cst, format_opts);
+                    let formatted = fmt_doc.println(&cfg).to_string_no_markup();
+                    Ok(FormatOutcome::Formatted {
+                        name: input.name.to_string(),
+                        did_change: input.data != &formatted[..],
+                        formatted,
+                    })
+                });
+                result.unwrap_or_else(|err| {
+                    let inputs = loader.as_inputs();
+                    FormatOutcome::Failed(render_report(markup, err.report(&inputs)))
+                })
+            },
+            |i, outcome| {
+                let (name, formatted, did_change) = match outcome {
+                    FormatOutcome::Formatted {
+                        name,
+                        formatted,
+                        did_change,
+                    } => (name, formatted, did_change),
+                    FormatOutcome::Failed(report) => {
+                        self.print_bytes_stderr(&report);
+                        std::process::exit(1);
                     }
                 };
-                // We only write to the file if we changed anything. This ensures
-                // that we don't cause rebuilds for build systems that look at mtimes,
-                // that we don't waste space on CoW filesystems, and that we don't
-                // unnecessarily burn through SSDs in general.
-                if did_change {
-                    n_changed += 1;
-                    self.print_to_file(MarkupMode::None, res, &fname)?;
-                }
-            } else {
-                // We are in the --check case, not the --in-place case.
-                if did_change {
-                    n_changed += 1;
-                    println!("Would reformat {}", self.loader.get_doc(doc).name);
+
+                if is_write_in_place {
+                    let fname = match &fnames[i] {
+                        Target::File(fname) => fname,
+                        Target::Stdin => {
+                            let msg =
+                                "Formatting in-place is only possible for named files, not for stdin.";
+                            return Error::new(msg).err();
+                        }
+                        Target::StdinDefault => {
+                            unreachable!("In-place default is empty list, not stdin.")
+                        }
+                        Target::Expr(..) => unreachable!("Format does not accept --expr."),
+                    };
+                    // We only write to the file if we changed anything. This ensures
+                    // that we don't cause rebuilds for build systems that look at mtimes,
+                    // that we don't waste space on CoW filesystems, and that we don't
+                    // unnecessarily burn through SSDs in general.
+                    if did_change {
+                        n_changed += 1;
+                        let mut out = MarkupString::new();
+                        out.push(&formatted, Markup::None);
+                        self.print_to_file(MarkupMode::None, out, fname)?;
+                    }
+                } else {
+                    // We are in the --check case, not the --in-place case.
+                    if did_change {
+                        n_changed += 1;
+                        println!("Would reformat {}", name);
+                    }
                 }
-            }
-        }
+                Ok(())
+            },
+        )?;
 
         if is_write_in_place {
             println!("Reformatted {} of {} files.", n_changed, n_loaded);
@@ -234,10 +458,274 @@ impl App {
         }
     }
 
+    /// Evaluate every target, and report errors for those that fail.
+    fn main_check(
+        &mut self,
+        sandbox: SandboxMode,
+        strict: StrictMode,
+        jobs: Option<u32>,
+        fnames: Vec<Target>,
+    ) -> Result<()> {
+        let mut n_failed: u32 = 0;
+        let n_total = fnames.len();
+        let markup = self.stderr_markup();
+        let jobs = jobs.map_or_else(rcl::parallel::default_jobs, |n| n as usize);
+
+        // Documents are evaluated on worker threads. Their output is buffered,
+        // and printed from this thread in the order of the inputs.
+        let result: Result<()> = rcl::parallel::for_each_ordered(
+            &fnames,
+            jobs,
+            || new_worker_loader(&self.opts, sandbox, markup),
+            |loader, target| {
+                let loader = match loader {
+                    Ok(loader) => loader,
+                    Err(report) => {
+                        return CheckOutcome {
+                            is_ok: false,
+                            stderr: report.clone(),
+                        }
+                    }
+                };
+                let mut tracer = BufferTracer::new(markup);
+                let result = loader.load_cli_target(target).and_then(|doc| {
+                    // Every document gets a fresh environment, but the loader is
+                    // shared within a worker, so documents imported by multiple
+                    // inputs are usually loaded and parsed only once.
+                    let mut type_env = loader.type_prelude();
+                    let mut value_env = loader.value_prelude();
+                    loader.evaluate(&mut type_env, &mut value_env, doc, &mut tracer, strict)
+                });
+                let mut stderr = tracer.take();
+                let is_ok = result.is_ok();
+                if let Err(err) = result {
+                    let inputs = loader.as_inputs();
+                    let name = loader.get_cli_target_name(target);
+                    let report = Doc::Concat(vec![
+                        Doc::from("Failed to evaluate").with_markup(Markup::Error),
+                        Doc::str(" "),
+                        Doc::highlight(name),
+                        Doc::str(":"),
+                        Doc::HardBreak,
+                        Doc::HardBreak,
+                        err.report(&inputs),
+                    ]);
+                    stderr.extend(render_report(markup, report));
+                    stderr.push(b'\n');
+                }
+                CheckOutcome { is_ok, stderr }
+            },
+            |_i, outcome| {
+                self.print_bytes_stderr(&outcome.stderr);
+                if !outcome.is_ok {
+                    n_failed += 1;
+                }
+                Ok(())
+            },
+        );
+        result?;
+
+        match (n_failed, n_total) {
+            (0, 1) => {
+                println!("The document is valid.");
+                Ok(())
+            }
+            (0, n) => {
+                println!("All {} documents are valid.", n);
+                Ok(())
+            }
+            (k, n) => {
+                let parts = vec![
+                    k.to_string().into(),
+                    Doc::str(" of "),
+                    n.to_string().into(),
+                    Doc::str(if n == 1 {
+                        " document failed to evaluate."
+                    } else {
+                        " documents failed to evaluate."
+                    }),
+                ];
+                Error::new(Doc::Concat(parts)).err()
+            }
+        }
+    }
+
+    /// Execute build files in parallel.
+    ///
+    /// When there are fewer build files than jobs, the targets of every build
+    /// file are split into shards, and every worker evaluates one shard of one
+    /// build file. Workers only format the outputs, the main thread writes
+    /// them, in the order of the inputs, so output does not depend on thread
+    /// scheduling. A build file that fails does not stop the others.
+    fn main_build_many(
+        &mut self,
+        eval_opts: EvalOptions,
+        build_mode: BuildMode,
+        file_mode: Option<u32>,
+        jobs: Option<u32>,
+        fnames: Vec<Target>,
+    ) -> Result<()> {
+        // The profiler lives in the loader, and every worker has its own.
+        if eval_opts.profile.is_some() {
+            return Error::new("Profiling is not supported for parallel builds.")
+                .with_help("Build with '--jobs=1' to profile.")
+                .err();
+        }
+
+        let markup = self.stderr_markup();
+        let jobs = jobs.map_or_else(rcl::parallel::default_jobs, |n| n as usize);
+
+        // Stdin can be read only once, so we read it before the workers start,
+        // and they all evaluate the same copy.
+        let is_stdin = |target: &Target| matches!(target, Target::Stdin | Target::StdinDefault);
+        let stdin = match fnames.iter().find(|t| is_stdin(t)) {
+            Some(target) => {
+                let doc = self.loader.load_cli_target(target)?;
+                Some(self.loader.get_doc(doc).data.to_string())
+            }
+            None => None,
+        };
+
+        let count = ((jobs + fnames.len() - 1) / fnames.len()).max(1);
+        let shards: Vec<(usize, Shard)> = (0..fnames.len())
+            .flat_map(|i| (0..count).map(move |index| (i, Shard { index, count })))
+            .collect();
+        let sources = SharedSources::default();
+
+        // Outputs of the shards of the build file that we are currently
+        // collecting, and the first error among them. Every shard evaluates
+        // the statements before the targets, so if those fail, all shards
+        // report the same error, and we only print it once.
+        let mut outputs = Vec::new();
+        let mut error = None;
+        let mut owners = OutputOwners::default();
+        let mut n_failed = 0;
+
+        rcl::parallel::for_each_ordered(
+            &shards,
+            jobs,
+            || {
+                new_worker_loader(&self.opts, eval_opts.sandbox, markup).map(|mut loader| {
+                    loader.set_capabilities(eval_opts.capabilities());
+                    loader.share_sources(sources.clone());
+                    loader
+                })
+            },
+            |loader, (i, shard)| {
+                let loader = match loader {
+                    Ok(loader) => loader,
+                    Err(report) => {
+                        return BuildOutcome {
+                            result: Err(report.clone()),
+                            traces: Vec::new(),
+                        }
+                    }
+                };
+                let target = &fnames[*i];
+                let mut tracer = BufferTracer::new(markup);
+                let doc = match (&stdin, is_stdin(target)) {
+                    (Some(data), true) => {
+                        let name = loader.get_cli_target_name(target).to_string();
+                        Ok(loader.load_named_string(name, data.clone()))
+                    }
+                    _ => loader.load_cli_target(target),
+                };
+                let result = doc.and_then(|doc| {
+                    // The lockfile applies to the build file as a whole.
+                    if shard.index == 0 {
+                        check_lock(loader, &mut tracer, &eval_opts, target, doc)?;
+                    }
+                    let mut type_env = loader.type_prelude();
+                    let mut value_env = loader.value_prelude();
+                    let val = loader.evaluate_shard(
+                        &mut type_env,
+                        &mut value_env,
+                        doc,
+                        &mut tracer,
+                        eval_opts.strict,
+                        *shard,
+                    )?;
+                    rcl::cmd_build::render_targets(
+                        loader,
+                        build_mode,
+                        &eval_opts.secrets,
+                        doc,
+                        loader.get_span(doc),
+                        val,
+                    )
+                });
+                BuildOutcome {
+                    result: result.map_err(|err| {
+                        let name = loader.get_cli_target_name(target);
+                        render_build_error(markup, name, err.report(&loader.as_inputs()))
+                    }),
+                    traces: tracer.take(),
+                }
+            },
+            |k, outcome| -> Result<()> {
+                let (i, shard) = shards[k];
+                self.print_bytes_stderr(&outcome.traces);
+                match outcome.result {
+                    Ok(shard_outputs) => outputs.extend(shard_outputs),
+                    Err(report) => {
+                        error.get_or_insert(report);
+                    }
+                }
+                if shard.index + 1 < shard.count {
+                    return Ok(());
+                }
+
+                // We have all shards of this build file now. Shards return
+                // their targets in key order, merging them by name restores
+                // the order of the build file.
+                let mut build_outputs = std::mem::take(&mut outputs);
+                build_outputs.sort_by(|a, b| a.name.cmp(&b.name));
+                let name = self.loader.get_cli_target_name(&fnames[i]).to_string();
+                let result = match error.take() {
+                    Some(report) => Err(report),
+                    None => owners
+                        .claim(&name, &build_outputs)
+                        .and_then(|()| {
+                            rcl::cmd_build::write_outputs(
+                                &build_outputs,
+                                file_mode,
+                                &mut std::io::stdout().lock(),
+                            )
+                        })
+                        .map_err(|err| render_build_error(markup, &name, err.report(&[]))),
+                };
+                if let Err(report) = result {
+                    self.print_bytes_stderr(&report);
+                    n_failed += 1;
+                }
+                Ok(())
+            },
+        )?;
+
+        match (n_failed, fnames.len()) {
+            (0, _) => Ok(()),
+            (k, n) => {
+                let parts = vec![
+                    k.to_string().into(),
+                    Doc::str(" of "),
+                    n.to_string().into(),
+                    Doc::str(if n == 1 {
+                        " build file failed."
+                    } else {
+                        " build files failed."
+                    }),
+                ];
+                Error::new(Doc::Concat(parts)).err()
+            }
+        }
+    }
+
     fn main(&mut self) -> Result<()> {
         let (opts, cmd) = cli::parse(std::env::args().collect())?;
         self.opts = opts;
 
+        configure_loader(&mut self.loader, &self.opts);
+
         match cmd {
             Cmd::Help { usage } => {
                 println!("{}", usage.trim());
@@ -247,7 +735,9 @@ impl App {
             Cmd::Build {
                 eval_opts,
                 build_mode,
-                fname,
+                file_mode,
+                jobs,
+                fnames,
             } => {
                 // Evaluation options support a depfile, but this is not implemented
                 // for builds, we'd have to put multiple output filenames in there
@@ -256,24 +746,304 @@ impl App {
                     return Error::new("Generating depfiles is not supported for 'rcl build'.")
                         .err();
                 }
+                if eval_opts.output_docs.is_some() {
+                    return Error::new("Writing doc comments is not supported for 'rcl build'.")
+                        .err();
+                }
+                if eval_opts.source_map.is_some() {
+                    return Error::new("Writing source maps is not supported for 'rcl build'.")
+                        .err();
+                }
+
+                // A single build file is only split over threads when asked
+                // to, because every thread evaluates the statements before the
+                // targets again, and traces are printed only after a shard is
+                // done.
+                if fnames.len() > 1 || jobs.is_some_and(|n| n > 1) {
+                    return self.main_build_many(eval_opts, build_mode, file_mode, jobs, fnames);
+                }
+                let fname = fnames
+                    .into_iter()
+                    .next()
+                    .expect("Targets default to build.rcl.");
 
                 self.loader
                     .initialize_filesystem(eval_opts.sandbox, self.opts.workdir.as_deref())?;
+                self.loader.set_capabilities(eval_opts.capabilities());
 
                 // TODO: We can make these members, then we can share a lot of code between commands!
                 let mut tracer = self.get_tracer();
-                let mut type_env = typecheck::prelude();
-                let mut value_env = runtime::prelude();
+                let mut type_env = self.loader.type_prelude();
+                let mut value_env = self.loader.value_prelude();
                 let doc = self.loader.load_cli_target(&fname)?;
+                self.check_lock(&eval_opts, &fname, doc)?;
+                if eval_opts.profile.is_some() {
+                    self.loader.enable_profiler();
+                }
 
                 // TODO: Would be nice to be able to feed in an expected type.
-                let val = self
-                    .loader
-                    .evaluate(&mut type_env, &mut value_env, doc, &mut tracer)?;
+                let val = self.loader.evaluate(
+                    &mut type_env,
+                    &mut value_env,
+                    doc,
+                    &mut tracer,
+                    eval_opts.strict,
+                )?;
 
+                self.write_profile(&eval_opts)?;
                 let full_span = self.loader.get_span(doc);
 
-                rcl::cmd_build::execute_build(&self.loader, build_mode, doc, full_span, val)
+                rcl::cmd_build::execute_build(
+                    &self.loader,
+                    build_mode,
+                    file_mode,
+                    &eval_opts.secrets,
+                    doc,
+                    full_span,
+                    val,
+                    &mut std::io::stdout().lock(),
+                )
+            }
+
+            Cmd::TraceOutput {
+                eval_opts,
+                fname,
+                path,
+            } => {
+                self.loader
+                    .initialize_filesystem(eval_opts.sandbox, self.opts.workdir.as_deref())?;
+                self.loader.set_capabilities(eval_opts.capabilities());
+
+                let mut tracer = self.get_tracer();
+                let mut type_env = self.loader.type_prelude();
+                let mut value_env = self.loader.value_prelude();
+                let doc = self.loader.load_cli_target(&fname)?;
+                self.loader.enable_source_map();
+                let val = self.loader.evaluate(
+                    &mut type_env,
+                    &mut value_env,
+                    doc,
+                    &mut tracer,
+                    eval_opts.strict,
+                )?;
+
+                let body_span = self.loader.get_span(doc);
+                let source_map = self
+                    .loader
+                    .take_source_map()
+                    .expect("Source map was enabled before evaluation.");
+                let provenance = source_map.trace(body_span, &val, &path)?;
+                let inputs = self.loader.as_inputs();
+                let report = rcl::source_map::format_provenance(&inputs, &provenance);
+                self.print_doc_target(OutputTarget::Stdout, &StyleOptions::default(), report)
+            }
+
+            Cmd::Debug {
+                eval_opts,
+                style_opts,
+                fname,
+                breakpoints,
+                break_on_trace,
+            } => {
+                if !matches!(fname, Target::File(..)) {
+                    return Error::new(
+                        "The debugger reads commands from stdin, it can't read the input from there.",
+                    )
+                    .err();
+                }
+                self.loader
+                    .initialize_filesystem(eval_opts.sandbox, self.opts.workdir.as_deref())?;
+                self.loader.set_capabilities(eval_opts.capabilities());
+
+                let mut tracer = self.get_tracer();
+                let mut type_env = self.loader.type_prelude();
+                let mut value_env = self.loader.value_prelude();
+                let doc = self.loader.load_cli_target(&fname)?;
+                let mut debugger = Debugger::new(
+                    breakpoints,
+                    break_on_trace,
+                    Box::new(std::io::stdin().lock()),
+                    Box::new(std::io::stdout()),
+                );
+                let val = self.loader.evaluate_debug(
+                    &mut type_env,
+                    &mut value_env,
+                    doc,
+                    &mut tracer,
+                    eval_opts.strict,
+                    &mut debugger,
+                )?;
+
+                let body_span = self.loader.get_span(doc);
+                self.print_value(
+                    &eval_opts,
+                    &style_opts,
+                    OutputTarget::Stdout,
+                    body_span,
+                    &val,
+                )
+            }
+
+            Cmd::Convert {
+                eval_opts,
+                style_opts,
+                from,
+                fname,
+                output,
+            } => {
+                self.loader.initialize_filesystem(
+                    SandboxMode::Unrestricted,
+                    self.opts.workdir.as_deref(),
+                )?;
+                let doc = self.loader.load_cli_target(&fname)?;
+                let input = self.loader.get_doc(doc);
+                match rcl::cmd_convert::convert(from, doc, input.name, input.data)? {
+                    Converted::Value(val) => {
+                        let body_span = self.loader.get_span(doc);
+                        self.print_value(&eval_opts, &style_opts, output, body_span, &val)
+                    }
+                    Converted::Source(source) => {
+                        if eval_opts.format != OutputFormat::Rcl {
+                            return Error::new(
+                                "Jsonnet is translated into RCL source code, it can only be converted to 'rcl'.",
+                            )
+                            .err();
+                        }
+                        // Formatting the translation also confirms that it is valid RCL.
+                        let translated = self.loader.load_string(source);
+                        let cst = self.loader.get_cst(translated)?;
+                        let data = self.loader.get_doc(translated).data;
+                        let res = rcl::fmt_cst::format_expr(data, &cst, FormatOptions::default());
+                        self.print_doc_target(output, &style_opts, res)
+                    }
+                }
+            }
+
+            Cmd::Check {
+                sandbox,
+                strict,
+                jobs,
+                fnames,
+            } => {
+                self.loader
+                    .initialize_filesystem(sandbox, self.opts.workdir.as_deref())?;
+                self.main_check(sandbox, strict, jobs, fnames)
+            }
+
+            Cmd::Bundle {
+                sandbox,
+                style_opts,
+                fname,
+                output,
+            } => {
+                self.loader
+                    .initialize_filesystem(sandbox, self.opts.workdir.as_deref())?;
+                let entry = self.loader.load_cli_target(&fname)?;
+                let bundled = rcl::cmd_bundle::bundle(&mut self.loader, entry)?;
+
+                // Formatting the bundle also confirms that it parses.
+                let doc = self.loader.load_string(bundled);
+                let cst = self.loader.get_cst(doc)?;
+                let data = self.loader.get_doc(doc).data;
+                let res = rcl::fmt_cst::format_expr(data, &cst, FormatOptions::default());
+                self.print_doc_target(output, &style_opts, res)
+            }
+
+            Cmd::Lint {
+                sandbox,
+                entries,
+                fnames,
+            } => {
+                self.loader
+                    .initialize_filesystem(sandbox, self.opts.workdir.as_deref())?;
+                let entries = entries
+                    .iter()
+                    .map(|target| self.loader.load_cli_target(target))
+                    .collect::<Result<Vec<_>>>()?;
+                let files = fnames
+                    .iter()
+                    .map(|target| self.loader.load_cli_target(target))
+                    .collect::<Result<Vec<_>>>()?;
+                let unused =
+                    rcl::cmd_lint::find_unused_exports(&mut self.loader, &entries, &files)?;
+
+                let mut tracer = self.get_tracer();
+                let inputs = self.loader.as_inputs();
+                let (n_exports, n_files) = (unused.exports.len(), unused.files.len());
+                for warning in unused.exports.into_iter().chain(unused.files) {
+                    tracer.warn(&inputs, warning);
+                }
+
+                match (n_exports, n_files) {
+                    (0, 0) => {
+                        println!("All exports and files are used.");
+                        Ok(())
+                    }
+                    (n_exports, n_files) => {
+                        let plural = |n: usize, noun: &str| match n {
+                            1 => format!("1 {noun}"),
+                            n => format!("{n} {noun}s"),
+                        };
+                        Error::new(format!(
+                            "Found {} and {}.",
+                            plural(n_exports, "unused export"),
+                            plural(n_files, "unused file"),
+                        ))
+                        .err()
+                    }
+                }
+            }
+
+            Cmd::Lock {
+                sandbox,
+                build_mode,
+                fname,
+                lockfile,
+            } => {
+                self.loader
+                    .initialize_filesystem(sandbox, self.opts.workdir.as_deref())?;
+                let output = match build_mode {
+                    BuildMode::DryRun => OutputTarget::Stdout,
+                    BuildMode::WriteFilesystem => OutputTarget::File(
+                        rcl::cmd_lock::get_lockfile_path(&fname, lockfile.as_deref())?,
+                    ),
+                };
+                let entry = self.loader.load_cli_target(&fname)?;
+                let lock = rcl::cmd_lock::compute_lock(&mut self.loader, entry)?;
+                let lock_doc = rcl::cmd_lock::format_lockfile(&lock);
+                self.print_doc_target(output, &StyleOptions::default(), lock_doc)
+            }
+
+            Cmd::Doc {
+                sandbox,
+                format,
+                fname,
+                output,
+            } => {
+                self.loader
+                    .initialize_filesystem(sandbox, self.opts.workdir.as_deref())?;
+
+                let mut tracer = self.get_tracer();
+                let mut type_env = self.loader.type_prelude();
+                let mut value_env = self.loader.value_prelude();
+                let doc = self.loader.load_cli_target(&fname)?;
+                let val = self.loader.evaluate(
+                    &mut type_env,
+                    &mut value_env,
+                    doc,
+                    &mut tracer,
+                    StrictMode::Off,
+                )?;
+
+                let cst = self.loader.get_cst(doc)?;
+                let body_span = self.loader.get_span(doc);
+                let input = self.loader.get_doc(doc);
+                let entries = rcl::cmd_doc::collect_entries(input.data, &cst, body_span, &val)?;
+                let rendered = rcl::cmd_doc::render_docs(format, input.name, &entries);
+
+                // The rendered docs are not RCL, so the width does not matter.
+                let style_opts = StyleOptions::default();
+                self.print_doc_target(output, &style_opts, Doc::lines(&rendered))
             }
 
             Cmd::Evaluate {
@@ -284,20 +1054,40 @@ impl App {
             } => {
                 self.loader
                     .initialize_filesystem(eval_opts.sandbox, self.opts.workdir.as_deref())?;
+                self.loader.set_capabilities(eval_opts.capabilities());
 
                 let mut tracer = self.get_tracer();
-                let mut type_env = typecheck::prelude();
-                let mut value_env = runtime::prelude();
+                let mut type_env = self.loader.type_prelude();
+                let mut value_env = self.loader.value_prelude();
                 let doc = self.loader.load_cli_target(&fname)?;
-                let val = self
-                    .loader
-                    .evaluate(&mut type_env, &mut value_env, doc, &mut tracer)?;
+                self.check_lock(&eval_opts, &fname, doc)?;
+                if eval_opts.source_map.is_some() {
+                    self.loader.enable_source_map();
+                }
+                if eval_opts.profile.is_some() {
+                    self.loader.enable_profiler();
+                }
+                let val = self.loader.evaluate(
+                    &mut type_env,
+                    &mut value_env,
+                    doc,
+                    &mut tracer,
+                    eval_opts.strict,
+                )?;
+                self.write_profile(&eval_opts)?;
 
                 if let Some(depfile_path) = eval_opts.output_depfile.as_ref() {
                     self.loader.write_depfile(&output, depfile_path)?;
                 }
 
+                if let Some(docs_path) = eval_opts.output_docs.as_ref() {
+                    self.write_doc_comments(doc, docs_path)?;
+                }
+
                 let body_span = self.loader.get_span(doc);
+                if let Some(map_path) = eval_opts.source_map.as_ref() {
+                    self.write_source_map(body_span, &val, map_path)?;
+                }
                 self.print_value(&eval_opts, &style_opts, output, body_span, &val)
             }
 
@@ -310,37 +1100,108 @@ impl App {
             } => {
                 self.loader
                     .initialize_filesystem(eval_opts.sandbox, self.opts.workdir.as_deref())?;
+                self.loader.set_capabilities(eval_opts.capabilities());
 
                 let input = self.loader.load_cli_target(&fname)?;
+                self.check_lock(&eval_opts, &fname, input)?;
                 let query = self.loader.load_string(expr);
+                if eval_opts.source_map.is_some() {
+                    self.loader.enable_source_map();
+                }
+                if eval_opts.profile.is_some() {
+                    self.loader.enable_profiler();
+                }
 
                 // First we evaluate the input document.
                 let mut tracer = self.get_tracer();
-                let mut type_env = typecheck::prelude();
-                let mut value_env = runtime::prelude();
-                let val_input =
-                    self.loader
-                        .evaluate(&mut type_env, &mut value_env, input, &mut tracer)?;
+                let mut type_env = self.loader.type_prelude();
+                let mut value_env = self.loader.value_prelude();
+                let val_input = self.loader.evaluate(
+                    &mut type_env,
+                    &mut value_env,
+                    input,
+                    &mut tracer,
+                    eval_opts.strict,
+                )?;
 
                 // Then we bind that to the variable `input`, and in that context,
                 // we evaluate the query expression. The environments should be
                 // clean at this point, so we can reuse them.
                 type_env.push("input".into(), typecheck::type_any().clone());
                 value_env.push("input".into(), val_input);
-                let val_result =
-                    self.loader
-                        .evaluate(&mut type_env, &mut value_env, query, &mut tracer)?;
+                let val_result = self.loader.evaluate(
+                    &mut type_env,
+                    &mut value_env,
+                    query,
+                    &mut tracer,
+                    eval_opts.strict,
+                )?;
+                self.write_profile(&eval_opts)?;
 
                 if let Some(depfile_path) = eval_opts.output_depfile.as_ref() {
                     self.loader.write_depfile(&output, depfile_path)?;
                 }
 
+                if let Some(docs_path) = eval_opts.output_docs.as_ref() {
+                    self.write_doc_comments(input, docs_path)?;
+                }
+
                 let body_span = self.loader.get_span(query);
+                if let Some(map_path) = eval_opts.source_map.as_ref() {
+                    self.write_source_map(body_span, &val_result, map_path)?;
+                }
                 self.print_value(&eval_opts, &style_opts, output, body_span, &val_result)
             }
 
+            Cmd::Repl {
+                eval_opts,
+                style_opts,
+                fname,
+            } => {
+                self.loader
+                    .initialize_filesystem(eval_opts.sandbox, self.opts.workdir.as_deref())?;
+                self.loader.set_capabilities(eval_opts.capabilities());
+
+                let input = self.loader.load_cli_target(&fname)?;
+                self.check_lock(&eval_opts, &fname, input)?;
+
+                // Evaluate the input document once, every query reuses the result.
+                let mut tracer = self.get_tracer();
+                let mut type_env = self.loader.type_prelude();
+                let mut value_env = self.loader.value_prelude();
+                let val_input = self.loader.evaluate(
+                    &mut type_env,
+                    &mut value_env,
+                    input,
+                    &mut tracer,
+                    eval_opts.strict,
+                )?;
+
+                let mut repl = Repl::new(
+                    &mut self.loader,
+                    &mut tracer,
+                    eval_opts.strict,
+                    val_input,
+                    Box::new(std::io::stdin().lock()),
+                    Box::new(std::io::stdout()),
+                );
+                #[cfg(feature = "line-editor")]
+                {
+                    use std::io::IsTerminal;
+                    // If the terminal does not support editing, we can still
+                    // read whole lines, so ignore the error.
+                    if std::io::stdin().is_terminal() {
+                        let _ = repl.enable_line_editor();
+                    }
+                }
+                repl.run(&eval_opts, &style_opts);
+                Ok(())
+            }
+
             Cmd::Format {
                 style_opts,
+                format_opts,
+                jobs,
                 target,
                 output,
             } => {
@@ -348,7 +1209,7 @@ impl App {
                     SandboxMode::Unrestricted,
                     self.opts.workdir.as_deref(),
                 )?;
-                self.main_fmt(output, &style_opts, target)
+                self.main_fmt(output, &style_opts, format_opts, jobs, target)
             }
 
             Cmd::Highlight { fname } => {
@@ -365,6 +1226,54 @@ impl App {
                 Ok(())
             }
 
+            Cmd::CrashRepro { fname, width } => {
+                self.loader.initialize_filesystem(
+                    SandboxMode::Unrestricted,
+                    self.opts.workdir.as_deref(),
+                )?;
+                let name = self.loader.get_cli_target_name(&fname).to_string();
+                let bytes = self.loader.read_cli_target_bytes(&fname)?;
+                rcl::cmd_crash_repro::install_panic_hook();
+                let repro = rcl::cmd_crash_repro::run(name, &bytes, width);
+                let style_opts = StyleOptions::default();
+                self.print_doc_target(OutputTarget::Stdout, &style_opts, repro.report)?;
+                match repro.failed {
+                    None => Ok(()),
+                    Some(stage) => {
+                        let parts = vec![
+                            Doc::str("The input fails in the '"),
+                            Doc::highlight(stage.name()),
+                            Doc::str("' stage, but it does not crash."),
+                        ];
+                        Error::new(Doc::Concat(parts)).err()
+                    }
+                }
+            }
+
+            Cmd::Serve { sandbox, socket } => {
+                self.loader
+                    .initialize_filesystem(sandbox, self.opts.workdir.as_deref())?;
+                match socket {
+                    None => rcl::cmd_serve::serve_stdio(&mut self.loader),
+                    #[cfg(unix)]
+                    Some(path) => {
+                        let path = self.loader.resolve_cli_output_path(&path);
+                        rcl::cmd_serve::serve_unix_socket(&mut self.loader, &path)
+                    }
+                    #[cfg(not(unix))]
+                    Some(..) => Error::new("Serving on a socket is only supported on Unix.").err(),
+                }
+            }
+
+            Cmd::Formats => {
+                let registry = rcl::output::FormatRegistry::new();
+                self.print_doc_target(
+                    OutputTarget::Stdout,
+                    &StyleOptions::default(),
+                    registry.list(),
+                )
+            }
+
             Cmd::Version => {
                 println!("RCL version {}", env!("CARGO_PKG_VERSION"));
                 Ok(())
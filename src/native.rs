@@ -0,0 +1,461 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Conversion between native Rust values and RCL values.
+//!
+//! Applications that embed RCL can convert their own data into a [`Value`]
+//! with [`ToRcl`], for example to bind it with [`Loader::register_global`],
+//! and extract evaluation results into typed structs with [`FromRcl`]. With
+//! the `derive` feature, both traits can be derived for structs with named
+//! fields, which convert to and from dicts keyed by field name.
+//!
+//! [`Loader::register_global`]: crate::loader::Loader::register_global
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::error::{Error, IntoError, PathElement};
+use crate::runtime::Value;
+use crate::small_collection::SmallDict;
+use crate::source_map::ValueSpans;
+
+#[cfg(feature = "derive")]
+pub use rcl_derive::{FromRcl, ToRcl};
+
+/// A value that can be converted into an RCL value.
+pub trait ToRcl {
+    fn to_rcl(&self) -> Value;
+}
+
+/// A value that can be extracted from an RCL value.
+pub trait FromRcl: Sized {
+    fn from_rcl(value: &Value) -> Result<Self>;
+
+    /// The value to use for a struct field when its key is absent.
+    ///
+    /// By default a missing key is an error, but e.g. `Option` uses `None`.
+    fn from_missing() -> Option<Self> {
+        None
+    }
+}
+
+pub type Result<T> = std::result::Result<T, FromRclError>;
+
+/// The reason a value could not be converted, and where in the value it is.
+#[derive(Debug)]
+pub struct FromRclError {
+    /// Path from the outermost value to the offending one, outermost first.
+    pub path: Vec<PathElement>,
+    pub message: String,
+}
+
+impl FromRclError {
+    pub fn new(message: impl Into<String>) -> FromRclError {
+        FromRclError {
+            path: Vec::new(),
+            message: message.into(),
+        }
+    }
+
+    /// Record that the error occurred inside the given element.
+    pub fn inside(mut self, element: PathElement) -> FromRclError {
+        self.path.insert(0, element);
+        self
+    }
+
+    /// Turn the error into a diagnostic at the source of the offending value.
+    ///
+    /// `value` is the value that failed to convert, and `spans` its spans, as
+    /// returned by [`Loader::evaluate_spanned`].
+    ///
+    /// [`Loader::evaluate_spanned`]: crate::loader::Loader::evaluate_spanned
+    pub fn to_error(&self, value: &Value, spans: &ValueSpans) -> Box<Error> {
+        let span = spans.at(value, &self.path).span;
+        let error = span
+            .error(self.message.clone())
+            .with_path(self.path.clone());
+        Box::new(error)
+    }
+}
+
+impl fmt::Display for FromRclError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "At ")?;
+            for element in self.path.iter() {
+                match element {
+                    PathElement::Key(Value::String(k)) => write!(f, ".{k}")?,
+                    PathElement::Key(Value::Int(i)) => write!(f, "[{i}]")?,
+                    PathElement::Key(k) => write!(f, "[<{}>]", k.type_name())?,
+                    PathElement::Index(i) => write!(f, "[{i}]")?,
+                }
+            }
+            write!(f, ": ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for FromRclError {}
+
+fn expected<T>(type_name: &str, value: &Value) -> Result<T> {
+    Err(FromRclError::new(format!(
+        "Expected {type_name}, but found {}.",
+        value.type_name()
+    )))
+}
+
+/// Extract the field `name` from a dict. Used by the `FromRcl` derive.
+pub fn field<T: FromRcl>(fields: &SmallDict, name: &str) -> Result<T> {
+    let key = Value::from(name);
+    match fields.get(&key) {
+        Some(value) => T::from_rcl(value).map_err(|err| err.inside(PathElement::Key(key))),
+        None => {
+            T::from_missing().ok_or_else(|| FromRclError::new(format!("Missing field '{name}'.")))
+        }
+    }
+}
+
+/// Extract the fields of a dict. Used by the `FromRcl` derive.
+pub fn fields(value: &Value) -> Result<&SmallDict> {
+    match value {
+        Value::Dict(fields) => Ok(fields),
+        _ => expected("Dict", value),
+    }
+}
+
+/// Reject keys that are not fields of the struct. Used by the `FromRcl` derive.
+pub fn check_fields(fields: &SmallDict, names: &[&str]) -> Result<()> {
+    for key in fields.keys() {
+        match key {
+            Value::String(k) if names.contains(&k.as_ref()) => continue,
+            Value::String(k) => {
+                return Err(FromRclError::new(format!("Unknown field '{k}'.")));
+            }
+            _ => return expected("String keys", key),
+        }
+    }
+    Ok(())
+}
+
+impl ToRcl for Value {
+    fn to_rcl(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl FromRcl for Value {
+    fn from_rcl(value: &Value) -> Result<Self> {
+        Ok(value.clone())
+    }
+}
+
+impl ToRcl for () {
+    fn to_rcl(&self) -> Value {
+        Value::Null
+    }
+}
+
+impl FromRcl for () {
+    fn from_rcl(value: &Value) -> Result<Self> {
+        match value {
+            Value::Null => Ok(()),
+            _ => expected("Null", value),
+        }
+    }
+}
+
+impl ToRcl for bool {
+    fn to_rcl(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+impl FromRcl for bool {
+    fn from_rcl(value: &Value) -> Result<Self> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            _ => expected("Bool", value),
+        }
+    }
+}
+
+macro_rules! impl_int {
+    ($($t:ty),*) => {
+        $(
+            impl ToRcl for $t {
+                fn to_rcl(&self) -> Value {
+                    Value::Int(i64::try_from(*self).expect("Value fits in an RCL Int."))
+                }
+            }
+
+            impl FromRcl for $t {
+                fn from_rcl(value: &Value) -> Result<Self> {
+                    match value {
+                        Value::Int(i) => <$t>::try_from(*i).map_err(|_| {
+                            FromRclError::new(format!(
+                                "Expected {}, but {i} is out of range.",
+                                stringify!($t),
+                            ))
+                        }),
+                        _ => expected("Int", value),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+// Unsigned 64-bit integers and usize are excluded because they do not fit
+// in an RCL Int. They can be extracted though.
+impl_int!(i8, i16, i32, i64, u8, u16, u32);
+
+impl FromRcl for u64 {
+    fn from_rcl(value: &Value) -> Result<Self> {
+        match value {
+            Value::Int(i) => u64::try_from(*i)
+                .map_err(|_| FromRclError::new(format!("Expected u64, but {i} is negative."))),
+            _ => expected("Int", value),
+        }
+    }
+}
+
+impl FromRcl for usize {
+    fn from_rcl(value: &Value) -> Result<Self> {
+        match value {
+            Value::Int(i) => usize::try_from(*i).map_err(|_| {
+                FromRclError::new(format!("Expected usize, but {i} is out of range."))
+            }),
+            _ => expected("Int", value),
+        }
+    }
+}
+
+impl ToRcl for str {
+    fn to_rcl(&self) -> Value {
+        Value::from(self)
+    }
+}
+
+impl ToRcl for String {
+    fn to_rcl(&self) -> Value {
+        Value::from(&self[..])
+    }
+}
+
+impl FromRcl for String {
+    fn from_rcl(value: &Value) -> Result<Self> {
+        match value {
+            Value::String(s) => Ok(s.to_string()),
+            _ => expected("String", value),
+        }
+    }
+}
+
+impl ToRcl for Rc<str> {
+    fn to_rcl(&self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl FromRcl for Rc<str> {
+    fn from_rcl(value: &Value) -> Result<Self> {
+        match value {
+            Value::String(s) => Ok(s.to_rc()),
+            _ => expected("String", value),
+        }
+    }
+}
+
+impl<T: ToRcl + ?Sized> ToRcl for &T {
+    fn to_rcl(&self) -> Value {
+        (**self).to_rcl()
+    }
+}
+
+impl<T: ToRcl> ToRcl for Box<T> {
+    fn to_rcl(&self) -> Value {
+        (**self).to_rcl()
+    }
+}
+
+impl<T: FromRcl> FromRcl for Box<T> {
+    fn from_rcl(value: &Value) -> Result<Self> {
+        T::from_rcl(value).map(Box::new)
+    }
+}
+
+/// `None` converts to `null`, and a missing struct field is `None`.
+impl<T: ToRcl> ToRcl for Option<T> {
+    fn to_rcl(&self) -> Value {
+        match self {
+            None => Value::Null,
+            Some(x) => x.to_rcl(),
+        }
+    }
+}
+
+impl<T: FromRcl> FromRcl for Option<T> {
+    fn from_rcl(value: &Value) -> Result<Self> {
+        match value {
+            Value::Null => Ok(None),
+            _ => T::from_rcl(value).map(Some),
+        }
+    }
+
+    fn from_missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+impl<T: ToRcl> ToRcl for Vec<T> {
+    fn to_rcl(&self) -> Value {
+        Value::List(self.iter().map(ToRcl::to_rcl).collect())
+    }
+}
+
+impl<T: FromRcl> FromRcl for Vec<T> {
+    fn from_rcl(value: &Value) -> Result<Self> {
+        let elements: Box<dyn Iterator<Item = &Value>> = match value {
+            Value::List(xs) => Box::new(xs.iter()),
+            Value::Set(xs) => Box::new(xs.iter()),
+            _ => return expected("List", value),
+        };
+        elements
+            .enumerate()
+            .map(|(i, x)| T::from_rcl(x).map_err(|err| err.inside(PathElement::Index(i))))
+            .collect()
+    }
+}
+
+impl<T: ToRcl> ToRcl for BTreeSet<T> {
+    fn to_rcl(&self) -> Value {
+        Value::Set(Rc::new(self.iter().map(ToRcl::to_rcl).collect()))
+    }
+}
+
+impl<T: FromRcl + Ord> FromRcl for BTreeSet<T> {
+    fn from_rcl(value: &Value) -> Result<Self> {
+        match value {
+            Value::Set(xs) => xs
+                .iter()
+                .map(|x| T::from_rcl(x).map_err(|err| err.inside(PathElement::Key(x.clone()))))
+                .collect(),
+            _ => expected("Set", value),
+        }
+    }
+}
+
+impl<K: ToRcl, V: ToRcl> ToRcl for BTreeMap<K, V> {
+    fn to_rcl(&self) -> Value {
+        let kvs = self.iter().map(|(k, v)| (k.to_rcl(), v.to_rcl()));
+        Value::Dict(kvs.collect())
+    }
+}
+
+impl<K: FromRcl + Ord, V: FromRcl> FromRcl for BTreeMap<K, V> {
+    fn from_rcl(value: &Value) -> Result<Self> {
+        let mut result = BTreeMap::new();
+        for (k, v) in fields(value)?.iter() {
+            let in_key = |err: FromRclError| err.inside(PathElement::Key(k.clone()));
+            result.insert(
+                K::from_rcl(k).map_err(in_key)?,
+                V::from_rcl(v).map_err(in_key)?,
+            );
+        }
+        Ok(result)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($n:literal, $($t:ident: $i:tt),*) => {
+        impl<$($t: ToRcl),*> ToRcl for ($($t,)*) {
+            fn to_rcl(&self) -> Value {
+                Value::Tuple(Rc::new([$(self.$i.to_rcl()),*]))
+            }
+        }
+
+        impl<$($t: FromRcl),*> FromRcl for ($($t,)*) {
+            fn from_rcl(value: &Value) -> Result<Self> {
+                match value {
+                    Value::Tuple(xs) if xs.len() == $n => Ok(($(
+                        $t::from_rcl(&xs[$i])
+                            .map_err(|err| err.inside(PathElement::Index($i)))?,
+                    )*)),
+                    _ => expected(concat!("Tuple of ", $n, " elements"), value),
+                }
+            }
+        }
+    };
+}
+
+impl_tuple!(2, A: 0, B: 1);
+impl_tuple!(3, A: 0, B: 1, C: 2);
+impl_tuple!(4, A: 0, B: 1, C: 2, D: 3);
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{FromRcl, ToRcl};
+    use crate::error::PathElement;
+    use crate::runtime::Value;
+
+    #[test]
+    fn to_rcl_from_rcl_round_trips() {
+        let mut hosts = BTreeMap::new();
+        hosts.insert("web".to_string(), vec![(80_u16, true), (443, false)]);
+        let value = hosts.to_rcl();
+        assert_eq!(BTreeMap::from_rcl(&value).unwrap(), hosts);
+        assert_eq!(Option::<i64>::from_rcl(&Value::Null).unwrap(), None);
+    }
+
+    #[test]
+    fn from_rcl_reports_path() {
+        let value = vec![vec![1_i64], vec![2, -3]].to_rcl();
+        let err = Vec::<Vec<u32>>::from_rcl(&value).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "At [1][1]: Expected u32, but -3 is out of range."
+        );
+        let err = String::from_rcl(&value).unwrap_err();
+        assert_eq!(err.to_string(), "Expected String, but found List.");
+    }
+
+    #[test]
+    fn from_rcl_error_points_at_source() {
+        use crate::eval::StrictMode;
+        use crate::loader::Loader;
+        use crate::tracer::VoidTracer;
+
+        let input = "let web = { ports = [80, 70000] };\n{ web = web }";
+        let mut loader = Loader::new();
+        let doc = loader.load_string(input.to_string());
+        let mut type_env = loader.type_prelude();
+        let mut value_env = loader.value_prelude();
+        let (value, spans) = loader
+            .evaluate_spanned(
+                &mut type_env,
+                &mut value_env,
+                doc,
+                &mut VoidTracer,
+                StrictMode::Off,
+            )
+            .unwrap();
+
+        let err = BTreeMap::<String, BTreeMap<String, Vec<u16>>>::from_rcl(&value).unwrap_err();
+        let err = err.to_error(&value, &spans);
+        let span = err.origin.expect("The error has a source location.");
+        assert_eq!(span.resolve(input), "70000");
+        assert_eq!(err.json_pointer().as_deref(), Some("/web/ports/1"));
+
+        // A path that does not exist resolves to the deepest one that does.
+        let missing = [PathElement::Key(Value::from("db"))];
+        assert_eq!(spans.at(&value, &missing).span, spans.span);
+    }
+}
@@ -0,0 +1,216 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Parsing and arithmetic on IP addresses and CIDR ranges, for `std.net`.
+//!
+//! Both IPv4 and IPv6 addresses are handled as a `u128` internally, together
+//! with the address family that determines how many of those bits are used.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// A range of addresses in CIDR notation, such as `10.0.0.0/8`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Cidr {
+    /// The first address in the range, with all bits after the prefix zero.
+    pub network: IpAddr,
+    /// The number of leading bits that all addresses in the range share.
+    pub prefix_len: u32,
+}
+
+/// Return the number of bits in an address of the same family as `ip`.
+fn address_bits(ip: IpAddr) -> u32 {
+    match ip {
+        IpAddr::V4(..) => 32,
+        IpAddr::V6(..) => 128,
+    }
+}
+
+fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u32::from(ip) as u128,
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+/// Build an address of the same family as `like` from its numeric value.
+///
+/// The value must fit in the address family.
+fn from_u128(like: IpAddr, x: u128) -> IpAddr {
+    match like {
+        IpAddr::V4(..) => IpAddr::V4(Ipv4Addr::from(x as u32)),
+        IpAddr::V6(..) => IpAddr::V6(Ipv6Addr::from(x)),
+    }
+}
+
+/// Return the largest numeric value of an address in the family of `ip`.
+fn max_u128(ip: IpAddr) -> u128 {
+    u128::MAX >> (128 - address_bits(ip))
+}
+
+/// Return the IP version, 4 or 6.
+pub fn version(ip: IpAddr) -> i64 {
+    match ip {
+        IpAddr::V4(..) => 4,
+        IpAddr::V6(..) => 6,
+    }
+}
+
+/// Parse an IPv4 or IPv6 address.
+pub fn parse_ip(ip: &str) -> Result<IpAddr, String> {
+    ip.parse().map_err(|_| {
+        format!(
+            "Invalid IP address '{ip}'. Expected an IPv4 address such as \
+            '10.0.0.1', or an IPv6 address such as 'fd00::1'."
+        )
+    })
+}
+
+/// Parse a range in CIDR notation, such as `10.0.0.0/8` or `fd00::/64`.
+pub fn parse_cidr(range: &str) -> Result<Cidr, String> {
+    let (ip_str, len_str) = match range.split_once('/') {
+        Some(parts) => parts,
+        None => {
+            return Err(format!(
+                "Invalid CIDR range '{range}'. Expected an address followed by \
+                a prefix length, such as '10.0.0.0/8'."
+            ))
+        }
+    };
+    let ip = parse_ip(ip_str)?;
+    let bits = address_bits(ip);
+    let prefix_len = match len_str.parse::<u32>() {
+        Ok(n) if n <= bits && !len_str.starts_with('+') => n,
+        _ => {
+            return Err(format!(
+                "Invalid prefix length '{len_str}' in '{range}'. \
+                Expected a number from 0 to {bits}."
+            ))
+        }
+    };
+    let host_mask = max_u128(ip) >> prefix_len;
+    let x = to_u128(ip);
+    if x & host_mask != 0 {
+        let network = from_u128(ip, x & !host_mask);
+        return Err(format!(
+            "Invalid CIDR range '{range}', the address has bits set after the \
+            prefix. The network address of this range is '{network}/{prefix_len}'."
+        ));
+    }
+    Ok(Cidr {
+        network: ip,
+        prefix_len,
+    })
+}
+
+impl Cidr {
+    /// Return whether `ip` lies in this range.
+    ///
+    /// An address of a different family is never in the range.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        if version(ip) != version(self.network) {
+            return false;
+        }
+        let host_mask = max_u128(ip) >> self.prefix_len;
+        to_u128(ip) & !host_mask == to_u128(self.network)
+    }
+
+    /// Return the first and last address in the range that are usable for hosts.
+    ///
+    /// For IPv4, the network address and broadcast address are excluded,
+    /// except for /31 and /32 ranges, which consist of only hosts. For IPv6,
+    /// the network address (the subnet-router anycast address) is excluded,
+    /// except for /127 and /128 ranges.
+    fn host_bounds(&self) -> (u128, u128) {
+        let bits = address_bits(self.network);
+        let first = to_u128(self.network);
+        let last = first | (max_u128(self.network) >> self.prefix_len);
+        match (self.network, bits - self.prefix_len) {
+            (_, 0 | 1) => (first, last),
+            (IpAddr::V4(..), _) => (first + 1, last - 1),
+            (IpAddr::V6(..), _) => (first + 1, last),
+        }
+    }
+
+    /// Return the first `count` host addresses in the range.
+    pub fn hosts(&self, count: u64) -> Result<Vec<IpAddr>, String> {
+        let (first, last) = self.host_bounds();
+        let available = last - first;
+        // The number of hosts is `available + 1`, but that can overflow for
+        // an IPv6 /0 range, so compare against `count - 1` instead.
+        if count > 0 && (count - 1) as u128 > available {
+            return Err(format!(
+                "The range '{}/{}' has only {} host addresses, but {count} were requested.",
+                self.network,
+                self.prefix_len,
+                available + 1,
+            ));
+        }
+        let result = (0..count as u128)
+            .map(|i| from_u128(self.network, first + i))
+            .collect();
+        Ok(result)
+    }
+}
+
+/// Return the address `n` positions after `ip`, or before it for negative `n`.
+pub fn add(ip: IpAddr, n: i64) -> Result<IpAddr, String> {
+    let x = to_u128(ip);
+    let result = if n >= 0 {
+        x.checked_add(n as u128)
+    } else {
+        x.checked_sub(n.unsigned_abs() as u128)
+    };
+    match result {
+        Some(y) if y <= max_u128(ip) => Ok(from_u128(ip, y)),
+        _ => Err(format!(
+            "Adding {n} to '{ip}' does not result in a valid IPv{} address.",
+            version(ip),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{add, parse_cidr, parse_ip};
+
+    #[test]
+    fn parse_cidr_rejects_host_bits() {
+        assert!(parse_cidr("10.0.0.0/8").is_ok());
+        assert!(parse_cidr("fd00::/8").is_ok());
+        assert!(parse_cidr("0.0.0.0/0").is_ok());
+        assert!(parse_cidr("10.0.0.1/8").is_err());
+        assert!(parse_cidr("10.0.0.0/33").is_err());
+        assert!(parse_cidr("10.0.0.0/+8").is_err());
+        assert!(parse_cidr("10.0.0.0").is_err());
+    }
+
+    #[test]
+    fn hosts_excludes_network_and_broadcast() {
+        let show = |range: &str, n: u64| -> Vec<String> {
+            let hosts = parse_cidr(range).unwrap().hosts(n);
+            hosts.unwrap().iter().map(|ip| ip.to_string()).collect()
+        };
+        assert_eq!(show("10.0.0.0/30", 2), ["10.0.0.1", "10.0.0.2"]);
+        assert_eq!(show("10.0.0.0/31", 2), ["10.0.0.0", "10.0.0.1"]);
+        assert_eq!(show("10.0.0.7/32", 1), ["10.0.0.7"]);
+        assert_eq!(show("fd00::/127", 2), ["fd00::", "fd00::1"]);
+        assert_eq!(show("::/0", 1), ["::1"]);
+        assert!(parse_cidr("10.0.0.0/30").unwrap().hosts(3).is_err());
+        assert!(parse_cidr("10.0.0.0/32").unwrap().hosts(2).is_err());
+    }
+
+    #[test]
+    fn add_stays_in_address_family() {
+        let ip = |s: &str| parse_ip(s).unwrap();
+        assert_eq!(add(ip("10.0.0.255"), 1), Ok(ip("10.0.1.0")));
+        assert_eq!(add(ip("10.0.1.0"), -1), Ok(ip("10.0.0.255")));
+        assert_eq!(add(ip("fd00::ffff"), 1), Ok(ip("fd00::1:0")));
+        assert!(add(ip("255.255.255.255"), 1).is_err());
+        assert!(add(ip("0.0.0.0"), -1).is_err());
+        assert!(add(ip("::"), i64::MIN).is_err());
+    }
+}
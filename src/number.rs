@@ -0,0 +1,308 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Floats, and formatting of numbers as strings.
+//!
+//! The output depends only on the inputs, never on the locale or platform:
+//! digits are ASCII, the minus sign is `-`, and fractional values are rounded
+//! with integer arithmetic, half to even.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// The largest supported scale and precision for fixed-point formatting.
+pub const MAX_DIGITS: u32 = 18;
+
+/// Options that control how a number is laid out.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NumberFormat {
+    /// Minimum length of the result in characters, reached by padding.
+    pub width: usize,
+
+    /// The padding character. When it is `0`, the padding goes between the
+    /// sign and the digits, otherwise it goes in front of the number.
+    pub fill: char,
+
+    /// Separator to insert between groups of three digits in the integer part.
+    pub separator: String,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            width: 0,
+            fill: ' ',
+            separator: String::new(),
+        }
+    }
+}
+
+/// A finite 64-bit floating point number.
+///
+/// Floats exist so that numbers like `1.0` in json inputs keep their meaning
+/// when they pass through RCL. They are a separate type from `Int`: `1.0` is
+/// not equal to `1`, and converting between the two is explicit and exact.
+/// NaN and the infinities are excluded, so floats are totally ordered, and
+/// every float can be exported to json. Negative zero is stored as zero, so
+/// `-0.0` and `0.0` are the same value, like they are equal in IEEE 754.
+#[derive(Copy, Clone)]
+pub struct Float(f64);
+
+impl Float {
+    /// Wrap the float if it is finite. Negative zero becomes zero.
+    pub fn new(x: f64) -> Option<Float> {
+        if x == 0.0 {
+            Some(Float(0.0))
+        } else if x.is_finite() {
+            Some(Float(x))
+        } else {
+            None
+        }
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+
+    /// Parse a decimal float as written in json, or `None` if it is too large.
+    ///
+    /// The input should already be validated, e.g. by the lexer.
+    pub fn parse(s: &str) -> Option<Float> {
+        s.parse().ok().and_then(Float::new)
+    }
+
+    /// Convert an integer to a float, if the float represents it exactly.
+    pub fn from_int(n: i64) -> Option<Float> {
+        let x = n as f64;
+        // Compare in i128, because a cast back to i64 would saturate, and
+        // `i64::MAX` would round-trip through 2^63 without being exact.
+        if x as i128 == n as i128 {
+            Some(Float(x))
+        } else {
+            None
+        }
+    }
+
+    /// Convert to an integer, if the float has no fractional part and fits.
+    pub fn to_int(self) -> Option<i64> {
+        let x = self.0;
+        // 2^63 is exactly representable, `i64::MAX` is not.
+        if x.fract() == 0.0 && x >= -9_223_372_036_854_775_808.0 && x < 9_223_372_036_854_775_808.0
+        {
+            Some(x as i64)
+        } else {
+            None
+        }
+    }
+}
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Float) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Float {}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Float) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Float {
+    fn cmp(&self, other: &Float) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Consistent with `total_cmp`, which is equality of the bits.
+        self.0.to_bits().hash(state)
+    }
+}
+
+impl fmt::Debug for Float {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for Float {
+    /// Format the shortest representation that parses back to the same float.
+    ///
+    /// The result always has a fraction or an exponent, so it does not read
+    /// back as an integer, and it is valid RCL, json, toml, and yaml.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+/// Format an integer in decimal.
+pub fn format_int(n: i64, format: &NumberFormat) -> String {
+    layout(n < 0, &n.unsigned_abs().to_string(), "", format)
+}
+
+/// Format the fixed-point number `n / 10^scale` with `precision` decimals.
+///
+/// When the precision is less than the scale, the number is rounded half to
+/// even. Both `scale` and `precision` must be at most [`MAX_DIGITS`].
+pub fn format_fixed(n: i64, scale: u32, precision: u32, format: &NumberFormat) -> String {
+    debug_assert!(scale <= MAX_DIGITS && precision <= MAX_DIGITS);
+    let mut m = n.unsigned_abs() as u128;
+    if precision < scale {
+        let d = 10_u128.pow(scale - precision);
+        let (q, r) = (m / d, m % d);
+        let round_up = r > d / 2 || (r == d / 2 && q % 2 == 1);
+        m = q + round_up as u128;
+    } else {
+        m *= 10_u128.pow(precision - scale);
+    }
+    let unit = 10_u128.pow(precision);
+    let int_digits = (m / unit).to_string();
+    let frac_digits = match precision {
+        0 => String::new(),
+        p => format!(".{:0width$}", m % unit, width = p as usize),
+    };
+    // When the number rounds to zero, we print no sign, there is no `-0`.
+    layout(n < 0 && m != 0, &int_digits, &frac_digits, format)
+}
+
+/// Format a float in decimal notation with `precision` decimals.
+///
+/// The float is rounded to the nearest decimal, and exact ties round half to
+/// even. Without precision, we print as many decimals as needed to read back
+/// the same float, but at least one, like `Float` itself formats.
+pub fn format_float(x: Float, precision: Option<u32>, format: &NumberFormat) -> String {
+    let digits = match precision {
+        Some(p) => format!("{:.*}", p as usize, x.get().abs()),
+        None => format!("{}", x.get().abs()),
+    };
+    let (int_digits, frac) = match digits.find('.') {
+        Some(i) => digits.split_at(i),
+        None if precision.is_none() => (&digits[..], ".0"),
+        None => (&digits[..], ""),
+    };
+    // Like for fixed-point numbers, when the number rounds to zero, we print no sign.
+    let is_zero = digits.bytes().all(|b| matches!(b, b'0' | b'.'));
+    layout(x.get() < 0.0 && !is_zero, int_digits, frac, format)
+}
+
+/// Combine sign, grouped integer digits, and fraction, and pad to the width.
+fn layout(is_negative: bool, int_digits: &str, frac: &str, format: &NumberFormat) -> String {
+    let mut digits = String::with_capacity(int_digits.len() * 2 + frac.len());
+    for (i, ch) in int_digits.chars().enumerate() {
+        if i > 0 && (int_digits.len() - i) % 3 == 0 {
+            digits.push_str(&format.separator);
+        }
+        digits.push(ch);
+    }
+    digits.push_str(frac);
+
+    let sign = if is_negative { "-" } else { "" };
+    let len = sign.len() + digits.chars().count();
+    let padding: String = std::iter::repeat(format.fill)
+        .take(format.width.saturating_sub(len))
+        .collect();
+
+    if format.fill == '0' {
+        format!("{sign}{padding}{digits}")
+    } else {
+        format!("{padding}{sign}{digits}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_fixed, format_float, format_int, Float, NumberFormat};
+
+    #[test]
+    fn format_int_groups_and_pads() {
+        let plain = NumberFormat::default();
+        assert_eq!(format_int(0, &plain), "0");
+        assert_eq!(format_int(i64::MIN, &plain), i64::MIN.to_string());
+
+        let grouped = NumberFormat {
+            separator: "_".to_string(),
+            ..NumberFormat::default()
+        };
+        assert_eq!(format_int(999, &grouped), "999");
+        assert_eq!(format_int(-1234567, &grouped), "-1_234_567");
+
+        let zeros = NumberFormat {
+            width: 5,
+            fill: '0',
+            ..NumberFormat::default()
+        };
+        assert_eq!(format_int(-42, &zeros), "-0042");
+        let spaces = NumberFormat {
+            width: 5,
+            ..NumberFormat::default()
+        };
+        assert_eq!(format_int(-42, &spaces), "  -42");
+        assert_eq!(format_int(1234567, &spaces), "1234567");
+    }
+
+    #[test]
+    fn format_fixed_rounds_half_to_even() {
+        let plain = NumberFormat::default();
+        assert_eq!(format_fixed(12345, 2, 2, &plain), "123.45");
+        assert_eq!(format_fixed(12345, 2, 1, &plain), "123.4");
+        assert_eq!(format_fixed(12355, 2, 1, &plain), "123.6");
+        assert_eq!(format_fixed(12346, 2, 0, &plain), "123");
+        assert_eq!(format_fixed(-5, 3, 4, &plain), "-0.0050");
+        assert_eq!(format_fixed(-4, 1, 0, &plain), "0");
+        assert_eq!(format_fixed(7, 0, 2, &plain), "7.00");
+        assert_eq!(
+            format_fixed(i64::MIN, 18, 18, &plain),
+            "-9.223372036854775808"
+        );
+    }
+
+    #[test]
+    fn format_float_rounds_to_nearest() {
+        let plain = NumberFormat::default();
+        let f = |s: &str, p: Option<u32>| format_float(Float::parse(s).unwrap(), p, &plain);
+        assert_eq!(f("12.5", None), "12.5");
+        assert_eq!(f("-3.0", None), "-3.0");
+        assert_eq!(f("1e20", None), "100000000000000000000.0");
+        assert_eq!(f("0.125", Some(2)), "0.12");
+        assert_eq!(f("0.375", Some(2)), "0.38");
+        assert_eq!(f("-0.04", Some(1)), "0.0");
+        assert_eq!(f("-2.5", Some(0)), "-2");
+    }
+
+    #[test]
+    fn float_formats_round_trip() {
+        for s in ["1.0", "-0.0", "0.1", "1e100", "1.5e-7", "123456.789"] {
+            let x = Float::parse(s).unwrap();
+            assert_eq!(Float::parse(&x.to_string()), Some(x));
+        }
+        assert_eq!(Float::parse("1.0").unwrap().to_string(), "1.0");
+        assert_eq!(Float::parse("1e400"), None);
+        // Negative zero is the same value as zero, and prints as zero.
+        assert_eq!(Float::parse("-0.0"), Float::parse("0.0"));
+        assert_eq!(Float::parse("-0.0").unwrap().to_string(), "0.0");
+    }
+
+    #[test]
+    fn float_converts_to_int_exactly() {
+        let f = |s: &str| Float::parse(s).unwrap();
+        assert_eq!(f("42.0").to_int(), Some(42));
+        assert_eq!(f("-1e3").to_int(), Some(-1000));
+        assert_eq!(f("0.5").to_int(), None);
+        assert_eq!(f("9223372036854775808.0").to_int(), None);
+        assert_eq!(f("-9223372036854775808.0").to_int(), Some(i64::MIN));
+
+        assert_eq!(Float::from_int(-7), Some(f("-7.0")));
+        assert_eq!(Float::from_int(i64::MIN), Some(f("-9223372036854775808.0")));
+        assert_eq!(Float::from_int(i64::MAX), None);
+        assert_eq!(Float::from_int((1 << 53) + 1), None);
+    }
+}
@@ -0,0 +1,532 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Constant folding of the typechecked AST.
+//!
+//! Large configurations are often mostly static: literal ports, names, and
+//! lists, with a few expressions in between. This pass evaluates the parts of
+//! the AST that do not depend on any input ahead of time, so the evaluator does
+//! less work. It folds operators on literals, format strings whose holes are
+//! literals, conditions that are literals, and it unrolls comprehensions over
+//! list literals. A `let` bound to a literal is substituted into its body.
+//!
+//! The pass only folds expressions that succeed at runtime in every mode. An
+//! expression that would fail, such as an overflowing addition, or one that
+//! would warn in strict mode, is left for the evaluator to report. The
+//! debugger and source maps need the AST as written, so they skip this pass,
+//! and `--no-opt` disables it entirely.
+
+use crate::ast::{BinOp, Expr, FormatFragment, Ident, Seq, Stmt, UnOp, Yield};
+
+/// Do not unroll comprehensions over list literals with more elements than this.
+///
+/// Every element gets a copy of the loop body, so a limit keeps the AST small.
+const MAX_UNROLL: usize = 64;
+
+/// Fold the constant parts of a typechecked expression, in place.
+pub fn optimize(expr: &mut Expr) {
+    let mut optimizer = Optimizer {
+        consts: Vec::new(),
+        functions: 0,
+    };
+    optimizer.expr(expr);
+}
+
+/// Whether the expression is a literal of a primitive type.
+fn is_literal(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::NullLit
+            | Expr::BoolLit(..)
+            | Expr::StringLit(..)
+            | Expr::IntegerLit(..)
+            | Expr::FloatLit(..)
+            | Expr::BytesLit(..)
+    )
+}
+
+fn fold_unop(op: UnOp, body: &Expr) -> Option<Expr> {
+    match (op, body) {
+        (UnOp::Not, Expr::BoolLit(x)) => Some(Expr::BoolLit(!x)),
+        (UnOp::Neg, Expr::IntegerLit(x)) => x.checked_neg().map(Expr::IntegerLit),
+        _ => None,
+    }
+}
+
+fn fold_binop(op: BinOp, lhs: &Expr, rhs: &Expr) -> Option<Expr> {
+    use Expr::{BoolLit, IntegerLit, NullLit, StringLit};
+    let result = match (op, lhs, rhs) {
+        (BinOp::Add, IntegerLit(x), IntegerLit(y)) => IntegerLit(x.checked_add(*y)?),
+        (BinOp::Sub, IntegerLit(x), IntegerLit(y)) => IntegerLit(x.checked_sub(*y)?),
+        (BinOp::Mul, IntegerLit(x), IntegerLit(y)) => IntegerLit(x.checked_mul(*y)?),
+        // Division is only supported when it is exact, see the evaluator.
+        (BinOp::Div, IntegerLit(x), IntegerLit(y)) => match x.checked_rem(*y)? {
+            0 => IntegerLit(x / y),
+            _ => return None,
+        },
+        (BinOp::Lt, IntegerLit(x), IntegerLit(y)) => BoolLit(x < y),
+        (BinOp::LtEq, IntegerLit(x), IntegerLit(y)) => BoolLit(x <= y),
+        (BinOp::Gt, IntegerLit(x), IntegerLit(y)) => BoolLit(x > y),
+        (BinOp::GtEq, IntegerLit(x), IntegerLit(y)) => BoolLit(x >= y),
+        (BinOp::And, BoolLit(x), BoolLit(y)) => BoolLit(*x && *y),
+        (BinOp::Or, BoolLit(x), BoolLit(y)) => BoolLit(*x || *y),
+        // Comparisons between different types warn in strict mode, so we only
+        // fold comparisons between values of the same type.
+        (BinOp::Eq | BinOp::Neq, x, y) => {
+            let is_eq = match (x, y) {
+                (NullLit, NullLit) => true,
+                (BoolLit(x), BoolLit(y)) => x == y,
+                (IntegerLit(x), IntegerLit(y)) => x == y,
+                (StringLit(x), StringLit(y)) => x == y,
+                _ => return None,
+            };
+            BoolLit(is_eq == (op == BinOp::Eq))
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Join a format string into a string literal, if all fragments are literals.
+///
+/// Only strings and integers qualify, formatting other values into a string
+/// warns in strict mode.
+fn fold_format(fragments: &[FormatFragment]) -> Option<Expr> {
+    let mut result = String::new();
+    for fragment in fragments {
+        match &fragment.body {
+            Expr::StringLit(s) => result.push_str(s),
+            Expr::IntegerLit(i) => result.push_str(&i.to_string()),
+            _ => return None,
+        }
+    }
+    Some(Expr::StringLit(result.into()))
+}
+
+/// Return the elements of a list literal, if they are all literals.
+fn literal_elements(collection: &Expr) -> Option<Vec<(crate::source::Span, Expr)>> {
+    let elements = match collection {
+        Expr::BracketLit { elements, .. } => elements,
+        _ => return None,
+    };
+    if elements.len() > MAX_UNROLL {
+        return None;
+    }
+    elements
+        .iter()
+        .map(|seq| match seq {
+            Seq::Yield(Yield::Elem { span, value }) if is_literal(value) => {
+                Some((*span, value.as_ref().clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+struct Optimizer {
+    /// The names in scope, innermost last.
+    ///
+    /// A name bound to a literal has the literal, which we substitute for the
+    /// variable. Other bindings are tracked too, as they shadow outer names.
+    consts: Vec<(Ident, Option<Expr>)>,
+
+    /// The number of function expressions visited so far.
+    ///
+    /// A function captures the entire environment, and two closures from the
+    /// same place are only equal when their environments are. Therefore we
+    /// keep a `let` whose body defines a function, even when we substitute it.
+    functions: usize,
+}
+
+impl Optimizer {
+    fn lookup(&self, name: &Ident) -> Option<&Expr> {
+        self.consts
+            .iter()
+            .rev()
+            .find(|(k, _v)| k == name)
+            .and_then(|(_k, v)| v.as_ref())
+    }
+
+    fn bind_opaque(&mut self, name: &Ident) {
+        self.consts.push((name.clone(), None));
+    }
+
+    /// Optimize the statement, and bind the names it defines.
+    ///
+    /// Returns whether the statement can be dropped after its body has been
+    /// optimized: a `let` of a literal is substituted into the body, and an
+    /// assertion that is always true has no effect.
+    fn stmt(&mut self, stmt: &mut Stmt) -> bool {
+        match stmt {
+            Stmt::Let { ident, value, .. } => {
+                self.expr(value);
+                if is_literal(value) {
+                    self.consts
+                        .push((ident.clone(), Some(value.as_ref().clone())));
+                    true
+                } else {
+                    self.bind_opaque(ident);
+                    false
+                }
+            }
+            Stmt::LetTuple { idents, value, .. } => {
+                self.expr(value);
+                for (_span, ident) in idents.iter() {
+                    self.bind_opaque(ident);
+                }
+                false
+            }
+            Stmt::Assert {
+                condition, message, ..
+            } => {
+                self.expr(condition);
+                self.expr(message);
+                matches!(condition.as_ref(), Expr::BoolLit(true))
+            }
+            Stmt::Trace { message, .. } => {
+                self.expr(message);
+                false
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        let n = self.consts.len();
+        let replacement = match expr {
+            Expr::Stmt { stmt, body, .. } => {
+                let functions = self.functions;
+                let is_redundant = self.stmt(stmt);
+                self.expr(body);
+                let is_captured = self.functions != functions && matches!(stmt, Stmt::Let { .. });
+                (is_redundant && !is_captured)
+                    .then(|| std::mem::replace(body.as_mut(), Expr::NullLit))
+            }
+            // The import path must be a string literal, the evaluator checks
+            // that before it evaluates anything, so we leave it untouched.
+            Expr::Import { .. } => None,
+            Expr::BraceLit { elements, .. }
+            | Expr::BracketLit { elements, .. }
+            | Expr::SetLit { elements, .. }
+            | Expr::DictLit { elements, .. } => {
+                self.seqs(elements);
+                None
+            }
+            Expr::TupleLit { elements } => {
+                for (_span, element) in elements.iter_mut() {
+                    self.expr(element);
+                }
+                None
+            }
+            Expr::NullLit
+            | Expr::BoolLit(..)
+            | Expr::StringLit(..)
+            | Expr::IntegerLit(..)
+            | Expr::FloatLit(..)
+            | Expr::BytesLit(..) => None,
+            Expr::Format(fragments) => {
+                for fragment in fragments.iter_mut() {
+                    self.expr(&mut fragment.body);
+                }
+                fold_format(fragments)
+            }
+            Expr::IfThenElse {
+                condition,
+                body_then,
+                body_else,
+                ..
+            } => {
+                self.expr(condition);
+                self.expr(body_then);
+                self.expr(body_else);
+                match condition.as_ref() {
+                    Expr::BoolLit(true) => {
+                        Some(std::mem::replace(body_then.as_mut(), Expr::NullLit))
+                    }
+                    Expr::BoolLit(false) => {
+                        Some(std::mem::replace(body_else.as_mut(), Expr::NullLit))
+                    }
+                    _ => None,
+                }
+            }
+            Expr::Var { ident, .. } => self.lookup(ident).cloned(),
+            Expr::TaggedLit { value, .. } => {
+                self.expr(value);
+                None
+            }
+            Expr::Match {
+                scrutinee, arms, ..
+            } => {
+                self.expr(scrutinee);
+                let mut names = Vec::new();
+                for arm in arms.iter_mut() {
+                    names.clear();
+                    arm.pattern.bound_names(&mut names);
+                    names.iter().for_each(|name| self.bind_opaque(name));
+                    self.expr(&mut arm.body);
+                    self.consts.truncate(n);
+                }
+                None
+            }
+            Expr::Field { inner, .. } => {
+                self.expr(inner);
+                None
+            }
+            Expr::Function { args, body, .. } => {
+                self.functions += 1;
+                args.iter().for_each(|(_span, name)| self.bind_opaque(name));
+                self.expr(body);
+                None
+            }
+            Expr::TypedFunction { body, type_, .. } => {
+                self.functions += 1;
+                for arg in type_.args.iter() {
+                    if let Some(name) = &arg.name {
+                        self.bind_opaque(name);
+                    }
+                }
+                self.expr(body);
+                None
+            }
+            Expr::Call { function, args, .. } => {
+                self.expr(function);
+                for arg in args.iter_mut() {
+                    self.expr(&mut arg.value);
+                }
+                None
+            }
+            Expr::Index {
+                collection, index, ..
+            } => {
+                self.expr(collection);
+                self.expr(index);
+                None
+            }
+            Expr::UnOp { op, body, .. } => {
+                self.expr(body);
+                fold_unop(*op, body)
+            }
+            Expr::BinOp { op, lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+                fold_binop(*op, lhs, rhs)
+            }
+            Expr::CheckType { body, .. } => {
+                self.expr(body);
+                None
+            }
+        };
+        self.consts.truncate(n);
+        if let Some(replacement) = replacement {
+            *expr = replacement;
+        }
+    }
+
+    /// Optimize the elements of a collection literal.
+    ///
+    /// At this level, an element can turn into zero or more elements, so this
+    /// is where we unroll loops and drop `if`s that are always false.
+    fn seqs(&mut self, elements: &mut Vec<Seq>) {
+        let mut result = Vec::with_capacity(elements.len());
+        for seq in elements.drain(..) {
+            self.seq_into(seq, &mut result);
+        }
+        *elements = result;
+    }
+
+    fn seq_into(&mut self, seq: Seq, out: &mut Vec<Seq>) {
+        let n = self.consts.len();
+        match seq {
+            Seq::For {
+                idents_span,
+                idents,
+                collection_span,
+                mut collection,
+                mut body,
+            } => {
+                self.expr(&mut collection);
+                match literal_elements(&collection) {
+                    // Unroll the loop into one `let` per element, which the
+                    // optimizer then substitutes into the copies of the body.
+                    Some(elements) if idents.len() == 1 => {
+                        for (span, value) in elements {
+                            let stmt = Stmt::Let {
+                                ident_span: idents_span,
+                                ident: idents[0].clone(),
+                                type_: None,
+                                value_span: span,
+                                value: Box::new(value),
+                            };
+                            let body = body.clone();
+                            self.seq_into(Seq::Stmt { stmt, body }, out);
+                        }
+                    }
+                    _ => {
+                        idents.iter().for_each(|name| self.bind_opaque(name));
+                        self.seq(&mut body);
+                        out.push(Seq::For {
+                            idents_span,
+                            idents,
+                            collection_span,
+                            collection,
+                            body,
+                        });
+                    }
+                }
+            }
+            Seq::If {
+                condition_span,
+                mut condition,
+                mut body,
+            } => {
+                self.expr(&mut condition);
+                match condition.as_ref() {
+                    Expr::BoolLit(true) => self.seq_into(*body, out),
+                    Expr::BoolLit(false) => {}
+                    _ => {
+                        self.seq(&mut body);
+                        out.push(Seq::If {
+                            condition_span,
+                            condition,
+                            body,
+                        });
+                    }
+                }
+            }
+            Seq::Stmt { mut stmt, mut body } => {
+                if self.stmt(&mut stmt) {
+                    let (start, functions) = (out.len(), self.functions);
+                    self.seq_into(*body, out);
+                    if self.functions != functions && matches!(stmt, Stmt::Let { .. }) {
+                        // The body may have been unrolled into multiple
+                        // elements, they each need their own copy of the let.
+                        let elements: Vec<Seq> = out.drain(start..).collect();
+                        out.extend(elements.into_iter().map(|body| Seq::Stmt {
+                            stmt: stmt.clone(),
+                            body: Box::new(body),
+                        }));
+                    }
+                } else {
+                    self.seq(&mut body);
+                    out.push(Seq::Stmt { stmt, body });
+                }
+            }
+            mut seq => {
+                self.seq(&mut seq);
+                out.push(seq);
+            }
+        }
+        self.consts.truncate(n);
+    }
+
+    /// Optimize a sequence in place, without changing its structure.
+    fn seq(&mut self, seq: &mut Seq) {
+        let n = self.consts.len();
+        match seq {
+            Seq::Yield(Yield::Elem { value, .. }) => self.expr(value),
+            Seq::Yield(Yield::Assoc { key, value, .. }) => {
+                self.expr(key);
+                self.expr(value);
+            }
+            Seq::Stmt { stmt, body } => {
+                self.stmt(stmt);
+                self.seq(body);
+            }
+            Seq::For {
+                idents,
+                collection,
+                body,
+                ..
+            } => {
+                self.expr(collection);
+                idents.iter().for_each(|name| self.bind_opaque(name));
+                self.seq(body);
+            }
+            Seq::If {
+                condition, body, ..
+            } => {
+                self.expr(condition);
+                self.seq(body);
+            }
+        }
+        self.consts.truncate(n);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{Expr, Seq, Yield};
+    use crate::loader::Loader;
+    use crate::tracer::VoidTracer;
+
+    fn optimized(input: &str) -> Expr {
+        let mut loader = Loader::new();
+        let doc = loader.load_string(input.to_string());
+        let mut type_env = loader.type_prelude();
+        loader
+            .get_typechecked_ast(&mut type_env, doc, &mut VoidTracer)
+            .expect("Typechecking should succeed.")
+    }
+
+    fn list_ints(expr: &Expr) -> Vec<i64> {
+        let elements = match expr {
+            Expr::BracketLit { elements, .. } => elements,
+            _ => panic!("Expected a list literal, got {expr:?}"),
+        };
+        elements
+            .iter()
+            .map(|seq| match seq {
+                Seq::Yield(Yield::Elem { value, .. }) => match value.as_ref() {
+                    Expr::IntegerLit(i) => *i,
+                    _ => panic!("Expected an integer, got {value:?}"),
+                },
+                _ => panic!("Expected an element, got {seq:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn optimize_folds_literals() {
+        assert!(matches!(
+            optimized("let x = 3; (x * 2) + 1"),
+            Expr::IntegerLit(7)
+        ));
+        assert!(matches!(
+            optimized("if (1 < 2) and (not false): 10 else 20"),
+            Expr::IntegerLit(10)
+        ));
+        match optimized("let port = 80; f\"localhost:{port}\"") {
+            Expr::StringLit(s) => assert_eq!(s.as_ref(), "localhost:80"),
+            other => panic!("Expected a string literal, got {other:?}"),
+        }
+        // Operations that fail at runtime are left for the evaluator.
+        assert!(matches!(
+            optimized("9223372036854775807 + 1"),
+            Expr::BinOp { .. }
+        ));
+        assert!(matches!(optimized("7 / 2"), Expr::BinOp { .. }));
+    }
+
+    #[test]
+    fn optimize_unrolls_comprehensions() {
+        let expr = optimized("[for x in [1, 2, 3]: if x != 2: x * 10, if false: 0]");
+        assert_eq!(list_ints(&expr), vec![10, 30]);
+
+        // Inner bindings shadow the constant.
+        let expr = optimized("let x = 1; [for x in std.range(0, 2): x, x]");
+        assert!(matches!(&expr, Expr::BracketLit { elements, .. } if elements.len() == 2));
+
+        // A let around a function is kept, because the closure captures it.
+        let expr = optimized("[for x in [1, 2]: _ => x]");
+        match &expr {
+            Expr::BracketLit { elements, .. } => {
+                assert_eq!(elements.len(), 2);
+                assert!(elements.iter().all(|seq| matches!(seq, Seq::Stmt { .. })));
+            }
+            _ => panic!("Expected a list literal, got {expr:?}"),
+        }
+    }
+}
@@ -0,0 +1,337 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Serialization of values into byte sinks, for applications that embed RCL.
+//!
+//! Every output format implements [`FormatBackend`], which turns a value into a
+//! [`Doc`]. [`write_value`] lays out that document and writes it to any
+//! [`Write`], such as a socket or a compression writer, without first building
+//! the output as a `String`. A [`FormatRegistry`] looks up formats by name,
+//! and applications can register their own formats next to the built-in ones.
+//! Formats describe what they can represent through [`FormatCapabilities`],
+//! which `rcl formats` lists, and which `rcl evaluate` uses to reject options
+//! that a format does not support.
+
+use std::io::Write;
+
+use crate::cli::OutputFormat;
+use crate::error::{Error, Result};
+use crate::fmt_yaml::Profile;
+use crate::markup::MarkupMode;
+use crate::pprint::{self, concat, Doc};
+use crate::runtime::Value;
+use crate::source::Span;
+
+/// A format that values can be serialized to, such as json or toml.
+pub trait FormatBackend {
+    /// The name that selects this format, e.g. `json` or `yaml-stream`.
+    fn name(&self) -> &str;
+
+    /// Render the value as a document in this format.
+    ///
+    /// Errors about the value, e.g. a value that the format cannot represent,
+    /// are reported at `value_span`.
+    fn format<'a>(&self, value_span: Span, value: &'a Value) -> Result<Doc<'a>>;
+
+    /// A one-line description of the format, for `rcl formats`.
+    fn description(&self) -> &str {
+        ""
+    }
+
+    /// What the format can represent, and which output options it supports.
+    fn capabilities(&self) -> FormatCapabilities {
+        FormatCapabilities::default()
+    }
+}
+
+/// What a format can represent, and which output options it supports.
+///
+/// The defaults are the most permissive for values, and the most conservative
+/// for options: a format supports any value, but none of the options.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct FormatCapabilities {
+    /// Whether dict keys must be strings.
+    pub string_keys_only: bool,
+
+    /// Whether the format supports `--max-depth` and `--max-items`.
+    pub limits: bool,
+
+    /// Whether the format supports `--output-encoding=ascii`.
+    pub ascii_output: bool,
+}
+
+/// The output formats that `--format` accepts are format backends.
+impl FormatBackend for OutputFormat {
+    fn name(&self) -> &str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::GithubActions => "github-actions",
+            OutputFormat::Json => "json",
+            OutputFormat::PrometheusRules => "prometheus-rules",
+            OutputFormat::Raw => "raw",
+            OutputFormat::Rcl => "rcl",
+            OutputFormat::TerraformJson => "terraform-json",
+            OutputFormat::Toml => "toml",
+            OutputFormat::YamlStream => "yaml-stream",
+        }
+    }
+
+    fn format<'a>(&self, value_span: Span, value: &'a Value) -> Result<Doc<'a>> {
+        use crate::{fmt_csv, fmt_json, fmt_raw, fmt_rcl, fmt_terraform, fmt_toml, fmt_yaml};
+        match self {
+            OutputFormat::Csv => fmt_csv::format_csv(value_span, value),
+            OutputFormat::GithubActions => {
+                fmt_yaml::format_yaml(value_span, Profile::GithubActions, value, false)
+            }
+            OutputFormat::Json => fmt_json::format_json(value_span, value),
+            OutputFormat::PrometheusRules => {
+                fmt_yaml::format_yaml(value_span, Profile::PrometheusRules, value, false)
+            }
+            OutputFormat::Raw => fmt_raw::format_raw(value_span, value),
+            OutputFormat::Rcl => Ok(fmt_rcl::format_rcl(value)),
+            OutputFormat::TerraformJson => {
+                fmt_terraform::format_terraform_json(value_span, value, false)
+            }
+            OutputFormat::Toml => fmt_toml::format_toml(value_span, value),
+            OutputFormat::YamlStream => {
+                crate::fmt_yaml_stream::format_yaml_stream(value_span, value, false)
+            }
+        }
+    }
+
+    fn description(&self) -> &str {
+        match self {
+            OutputFormat::Csv => "A header line, and one line per dict in a list.",
+            OutputFormat::GithubActions => "YAML for GitHub Actions workflows.",
+            OutputFormat::Json => "Pretty-printed JSON.",
+            OutputFormat::PrometheusRules => "YAML for Prometheus rule files.",
+            OutputFormat::Raw => "A string, or one line per string in a list or set.",
+            OutputFormat::Rcl => "Pretty-printed RCL.",
+            OutputFormat::TerraformJson => "JSON for Terraform '.tf.json' files.",
+            OutputFormat::Toml => "TOML.",
+            OutputFormat::YamlStream => "A YAML document per list element.",
+        }
+    }
+
+    fn capabilities(&self) -> FormatCapabilities {
+        let yaml = FormatCapabilities {
+            string_keys_only: true,
+            limits: false,
+            ascii_output: true,
+        };
+        match self {
+            OutputFormat::Csv | OutputFormat::Toml => FormatCapabilities {
+                string_keys_only: true,
+                ..FormatCapabilities::default()
+            },
+            OutputFormat::Json => FormatCapabilities {
+                limits: true,
+                ..yaml
+            },
+            OutputFormat::GithubActions
+            | OutputFormat::PrometheusRules
+            | OutputFormat::TerraformJson
+            | OutputFormat::YamlStream => yaml,
+            OutputFormat::Raw => FormatCapabilities::default(),
+            OutputFormat::Rcl => FormatCapabilities {
+                limits: true,
+                ..FormatCapabilities::default()
+            },
+        }
+    }
+}
+
+/// All built-in formats, in alphabetical order of their names.
+pub const BUILTIN_FORMATS: [OutputFormat; 9] = [
+    OutputFormat::Csv,
+    OutputFormat::GithubActions,
+    OutputFormat::Json,
+    OutputFormat::PrometheusRules,
+    OutputFormat::Raw,
+    OutputFormat::Rcl,
+    OutputFormat::TerraformJson,
+    OutputFormat::Toml,
+    OutputFormat::YamlStream,
+];
+
+/// A set of format backends that can be looked up by name.
+///
+/// Built-in formats that depend on a cargo feature belong in [`BUILTIN_FORMATS`]
+/// only when that feature is enabled, so the registry, `--format`, and
+/// `rcl formats` all agree on which formats exist.
+pub struct FormatRegistry {
+    formats: Vec<Box<dyn FormatBackend>>,
+}
+
+impl FormatRegistry {
+    /// Return a registry with all the built-in formats.
+    pub fn new() -> FormatRegistry {
+        let formats = BUILTIN_FORMATS
+            .iter()
+            .map(|f| Box::new(*f) as Box<dyn FormatBackend>)
+            .collect();
+        FormatRegistry { formats }
+    }
+
+    /// Add a format, replacing any existing format with the same name.
+    pub fn register(&mut self, format: Box<dyn FormatBackend>) {
+        self.formats.retain(|f| f.name() != format.name());
+        self.formats.push(format);
+    }
+
+    /// Look up a format by name.
+    pub fn get(&self, name: &str) -> Option<&dyn FormatBackend> {
+        self.formats
+            .iter()
+            .find(|f| f.name() == name)
+            .map(|f| f.as_ref())
+    }
+
+    /// Return the names of all registered formats, in registration order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.formats.iter().map(|f| f.name())
+    }
+
+    /// Return all registered formats, in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn FormatBackend> {
+        self.formats.iter().map(|f| f.as_ref())
+    }
+
+    /// Render a table of the registered formats and their capabilities.
+    pub fn list(&self) -> Doc<'static> {
+        let mut lines = vec![Doc::from(
+            "FORMAT            KEYS    OPTIONS       DESCRIPTION",
+        )];
+        for format in self.iter() {
+            let caps = format.capabilities();
+            let keys = if caps.string_keys_only {
+                "string"
+            } else {
+                "any"
+            };
+            let mut options = Vec::new();
+            if caps.limits {
+                options.push("limits");
+            }
+            if caps.ascii_output {
+                options.push("ascii");
+            }
+            if options.is_empty() {
+                options.push("-");
+            }
+            let rest = format!(
+                "{:pad$} {:<7} {:<13} {}",
+                "",
+                keys,
+                options.join(","),
+                format.description(),
+                pad = 17_usize.saturating_sub(format.name().len()),
+            );
+            lines.push(concat! {
+                Doc::highlight(format.name()).into_owned()
+                Doc::string(rest.trim_end().to_string())
+            });
+        }
+        Doc::join(lines.into_iter(), Doc::HardBreak)
+    }
+}
+
+/// Serialize the value with the given format, and write it to `out`.
+///
+/// Lines are broken to fit in `config.width` where the format allows it. The
+/// output ends in a newline, and does not contain markup. Secrets should be
+/// resolved before, see [`crate::secret::resolve_secrets`].
+pub fn write_value(
+    format: &dyn FormatBackend,
+    config: &pprint::Config,
+    value_span: Span,
+    value: &Value,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let doc = format.format(value_span, value)?;
+    doc.println(config)
+        .write_bytes(MarkupMode::None, out)
+        .and_then(|()| out.flush())
+        .or_else(|err| {
+            Error::new(concat! {
+                "Failed to write " Doc::highlight(format.name()).into_owned()
+                " output: " err.to_string()
+            })
+            .err()
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_value, FormatBackend, FormatRegistry};
+    use crate::error::Result;
+    use crate::pprint::{self, Doc};
+    use crate::runtime::Value;
+    use crate::source::{DocId, Span};
+
+    /// A format that prints only the number of elements in a list.
+    struct CountFormat;
+
+    impl FormatBackend for CountFormat {
+        fn name(&self) -> &str {
+            "count"
+        }
+        fn format<'a>(&self, _value_span: Span, value: &'a Value) -> Result<Doc<'a>> {
+            match value {
+                Value::List(xs) => Ok(Doc::string(xs.len().to_string())),
+                _ => Ok(Doc::from("0")),
+            }
+        }
+    }
+
+    fn write_to_string(format: &dyn FormatBackend, value: &Value) -> String {
+        let span = Span::new(DocId(0), 0, 0);
+        let config = pprint::Config { width: 80 };
+        let mut out = Vec::new();
+        write_value(format, &config, span, value, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn write_value_writes_builtin_formats() {
+        let value = Value::List(vec![Value::Int(1), Value::Bool(true)].into());
+        let registry = FormatRegistry::new();
+        let json = registry.get("json").unwrap();
+        assert_eq!(write_to_string(json, &value), "[1, true]\n");
+        let toml = registry.get("toml").unwrap();
+        assert!(toml.format(Span::new(DocId(0), 0, 0), &value).is_err());
+    }
+
+    #[test]
+    fn format_registry_lists_capabilities() {
+        let mut registry = FormatRegistry::new();
+        registry.register(Box::new(CountFormat));
+        let json = registry.get("json").unwrap().capabilities();
+        assert!(json.string_keys_only && json.limits && json.ascii_output);
+        let config = pprint::Config { width: 80 };
+        let table = registry.list().println(&config).to_string_no_markup();
+        let mut lines = table.lines();
+        assert!(lines.next().unwrap().starts_with("FORMAT "));
+        assert_eq!(
+            lines.next(),
+            Some("csv               string  -             A header line, and one line per dict in a list.")
+        );
+        assert_eq!(lines.last(), Some("count             any     -"));
+    }
+
+    #[test]
+    fn format_registry_registers_custom_formats() {
+        let mut registry = FormatRegistry::new();
+        assert!(registry.get("count").is_none());
+        registry.register(Box::new(CountFormat));
+        let value = Value::List(vec![Value::Null; 3].into());
+        let count = registry.get("count").unwrap();
+        assert_eq!(write_to_string(count, &value), "3\n");
+        assert_eq!(registry.names().last(), Some("count"));
+        assert_eq!(registry.names().filter(|n| *n == "json").count(), 1);
+    }
+}
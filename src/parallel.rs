@@ -0,0 +1,184 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Process independent items on multiple threads, with results in order.
+//!
+//! This is used by `rcl check`, `rcl format`, and `rcl build` to handle many
+//! files at once.
+//! The loader is not thread-safe, so every worker thread builds its own state
+//! with `init`. Workers take the next unprocessed item until none are left.
+//! The calling thread receives the results, and passes them to `emit` in the
+//! order of the input, so output does not depend on thread scheduling.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::runtime::Value;
+
+/// Return the default number of worker threads, the number of available CPUs.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// One of `count` parts of the top-level keys of a document.
+///
+/// `rcl build` uses this to split the targets of a build file over threads.
+/// Keys are assigned by a hash of the key, so every worker can tell which keys
+/// belong to it without evaluating the others.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Shard {
+    /// Return whether the top-level key belongs to this shard.
+    pub fn contains(&self, key: &Value) -> bool {
+        match key {
+            Value::String(s) => {
+                // FNV-1a, it only needs to be stable and spread keys evenly.
+                let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+                for b in s.as_bytes() {
+                    hash = (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3);
+                }
+                hash % self.count as u64 == self.index as u64
+            }
+            // Other keys are not valid build targets. Keep them in the first
+            // shard, so the error about them is reported exactly once.
+            _ => self.index == 0,
+        }
+    }
+}
+
+/// Apply `work` to all items on up to `jobs` threads, then `emit` in order.
+///
+/// When `emit` returns an error, workers stop after their current item, and
+/// the error is returned. Items after the failing one are not emitted.
+pub fn for_each_ordered<T, S, R, E>(
+    items: &[T],
+    jobs: usize,
+    init: impl Fn() -> S + Sync,
+    work: impl Fn(&mut S, &T) -> R + Sync,
+    mut emit: impl FnMut(usize, R) -> Result<(), E>,
+) -> Result<(), E>
+where
+    T: Sync,
+    R: Send,
+{
+    let n_threads = jobs.clamp(1, items.len().max(1));
+    let next_item = AtomicUsize::new(0);
+    let is_stopped = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..n_threads {
+            let sender = sender.clone();
+            let (next_item, is_stopped) = (&next_item, &is_stopped);
+            let (init, work) = (&init, &work);
+            scope.spawn(move || {
+                let mut state = init();
+                while !is_stopped.load(Ordering::Relaxed) {
+                    let i = next_item.fetch_add(1, Ordering::Relaxed);
+                    if i >= items.len() {
+                        break;
+                    }
+                    let result = work(&mut state, &items[i]);
+                    if sender.send((i, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // Drop our own sender, so the receiver ends when all workers are done.
+        drop(sender);
+
+        // Results can arrive out of order, we buffer them until it's their turn.
+        let mut pending = BTreeMap::new();
+        let mut next_emit = 0;
+        for (i, result) in receiver.iter() {
+            pending.insert(i, result);
+            while let Some(result) = pending.remove(&next_emit) {
+                if let Err(err) = emit(next_emit, result) {
+                    is_stopped.store(true, Ordering::Relaxed);
+                    return Err(err);
+                }
+                next_emit += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{for_each_ordered, Shard};
+    use crate::runtime::Value;
+
+    #[test]
+    fn for_each_ordered_emits_in_input_order() {
+        let items: Vec<u32> = (0..100).collect();
+        for jobs in [1, 3, 16] {
+            let mut emitted = Vec::new();
+            let result: Result<(), ()> = for_each_ordered(
+                &items,
+                jobs,
+                || (),
+                |_, x| x * 2,
+                |i, y| {
+                    emitted.push((i, y));
+                    Ok(())
+                },
+            );
+            assert_eq!(result, Ok(()));
+            let expected: Vec<_> = (0..100).map(|i| (i as usize, i * 2)).collect();
+            assert_eq!(emitted, expected);
+        }
+    }
+
+    #[test]
+    fn for_each_ordered_stops_at_first_error() {
+        let items: Vec<u32> = (0..100).collect();
+        let mut emitted = Vec::new();
+        let result = for_each_ordered(
+            &items,
+            4,
+            || (),
+            |_, x| *x,
+            |_, x| {
+                if x == 10 {
+                    return Err(x);
+                }
+                emitted.push(x);
+                Ok(())
+            },
+        );
+        assert_eq!(result, Err(10));
+        assert_eq!(emitted, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn shard_assigns_every_key_to_exactly_one_shard() {
+        let count = 3;
+        let mut sizes = vec![0; count];
+        for i in 0..300 {
+            let key = Value::String(format!("out/{i}.json").into());
+            let owners: Vec<usize> = (0..count)
+                .filter(|&index| Shard { index, count }.contains(&key))
+                .collect();
+            assert_eq!(owners.len(), 1);
+            sizes[owners[0]] += 1;
+        }
+        // The split does not need to be exact, but no shard should be idle.
+        assert!(sizes.iter().all(|&n| n > 50), "{sizes:?}");
+        assert!(Shard { index: 0, count }.contains(&Value::Int(1)));
+        assert!(!Shard { index: 1, count }.contains(&Value::Int(1)));
+    }
+}
@@ -7,7 +7,10 @@
 
 //! The parser converts a sequence of tokens into a Concrete Syntax Tree.
 
-use crate::cst::{BinOp, Chain, Expr, List, NonCode, Prefixed, Seq, Stmt, StringPart, Type, UnOp};
+use crate::cst::{
+    BinOp, Chain, Expr, ImportFormat, List, MatchArm, NonCode, Pattern, PipelineStage, Prefixed,
+    Seq, Stmt, StringPart, Type, UnOp,
+};
 use crate::error::{Error, IntoError, Result};
 use crate::lexer::{Lexeme, QuoteStyle, StringPrefix, Token};
 use crate::pprint::{concat, Doc};
@@ -21,9 +24,35 @@ pub fn parse(doc: DocId, input: &str, tokens: &[Lexeme]) -> Result<(Span, Expr)>
     // should not start with blank lines, those we drop.
     parser.skip_blanks();
 
-    let (span, result) = parser.parse_expr()?;
-    parser.parse_eof()?;
-    Ok((span, result))
+    let result = parser.parse_expr().and_then(|(span, result)| {
+        parser.parse_eof()?;
+        Ok((span, result))
+    });
+
+    // If we recovered from errors along the way, report all of them, in the
+    // order that we encountered them.
+    let mut errors = std::mem::take(&mut parser.errors);
+    if errors.is_empty() {
+        return result;
+    }
+    if let Err(err) = result {
+        errors.push(*err);
+    }
+    let mut first = errors.remove(0);
+    first.further = errors;
+    Err(Box::new(first))
+}
+
+/// The maximum number of syntax errors to recover from in a single document.
+const MAX_ERRORS: usize = 10;
+
+/// Parser state to restore when recovering from an error.
+#[derive(Copy, Clone)]
+struct Checkpoint {
+    cursor: usize,
+    brackets_len: usize,
+    brackets_top: Option<(Token, Span)>,
+    depth: u32,
 }
 
 fn to_unop(token: Token) -> Option<UnOp> {
@@ -34,6 +63,23 @@ fn to_unop(token: Token) -> Option<UnOp> {
     }
 }
 
+/// Return whether the token can be the start of a pattern.
+fn starts_pattern(token: Token) -> bool {
+    matches!(
+        token,
+        Token::Tag
+            | Token::Ident
+            | Token::KwNull
+            | Token::KwTrue
+            | Token::KwFalse
+            | Token::NumHexadecimal
+            | Token::NumBinary
+            | Token::NumDecimal
+            | Token::QuoteOpen(..)
+            | Token::Minus
+    )
+}
+
 fn to_binop(token: Token) -> Option<BinOp> {
     match token {
         Token::KwAnd => Some(BinOp::And),
@@ -71,6 +117,9 @@ struct Parser<'a> {
     /// The depth of parsing expressions and sequences, to prevent stack
     /// overflow.
     depth: u32,
+
+    /// Errors that we recovered from, and continued parsing after.
+    errors: Vec<Error>,
 }
 
 impl<'a> Parser<'a> {
@@ -83,9 +132,103 @@ impl<'a> Parser<'a> {
             bracket_stack: Vec::new(),
             comment_anchor: Span::new(doc, 0, 0),
             depth: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            cursor: self.cursor,
+            brackets_len: self.bracket_stack.len(),
+            brackets_top: self.bracket_stack.last().copied(),
+            depth: self.depth,
+        }
+    }
+
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        // Everything above the top of the stack at the checkpoint was pushed
+        // after it. The top itself may have been popped, so we restore it.
+        self.bracket_stack
+            .truncate(checkpoint.brackets_len.saturating_sub(1));
+        self.bracket_stack.extend(checkpoint.brackets_top);
+        self.cursor = checkpoint.cursor;
+        self.depth = checkpoint.depth;
+    }
+
+    /// Skip tokens up to the next `boundary` token that is not nested.
+    ///
+    /// Boundaries before `error_at` do not count, because the bad part of the
+    /// input comes after them. Stops at a closing bracket that closes the
+    /// enclosing collection, and returns the token under the cursor.
+    fn skip_to_boundary(&mut self, error_at: usize, boundary: Token) -> Option<Token> {
+        let mut depth = 0_u32;
+        loop {
+            let (token, span) = *self.tokens.get(self.cursor)?;
+            match token {
+                Token::LParen
+                | Token::LBrace
+                | Token::LBracket
+                | Token::QuoteOpen(..)
+                | Token::HoleOpen => depth += 1,
+                Token::RParen
+                | Token::RBrace
+                | Token::RBracket
+                | Token::QuoteClose
+                | Token::HoleClose => match depth {
+                    0 => return Some(token),
+                    _ => depth -= 1,
+                },
+                _ if depth == 0 && token == boundary && span.start() >= error_at => {
+                    return Some(token)
+                }
+                _ => {}
+            }
+            self.cursor += 1;
+        }
+    }
+
+    /// Record the error, and skip to where we can continue parsing.
+    ///
+    /// We skip everything since the checkpoint, up to the `boundary` token. If
+    /// we can't find it, or when there are too many errors already, this
+    /// returns the error instead, so an enclosing construct can recover.
+    fn recover(&mut self, err: Box<Error>, from: Checkpoint, boundary: Token) -> Result<()> {
+        if self.errors.len() >= MAX_ERRORS {
+            return Err(err);
+        }
+        let error_at = err.origin.map_or(0, |span| span.start());
+        self.restore(from);
+        match self.skip_to_boundary(error_at, boundary) {
+            // A statement must end in its boundary, but a collection element
+            // can also end at the end of the collection.
+            Some(token) if token == boundary || boundary == Token::Comma => {
+                self.errors.push(*err);
+                Ok(())
+            }
+            _ => {
+                self.restore(from);
+                Err(err)
+            }
         }
     }
 
+    /// Return the error for when we expect a separator or the end of a collection.
+    ///
+    /// This pops the bracket of the collection, but only to report an error.
+    fn error_after_element(&mut self) -> Box<Error> {
+        let message = match self.bracket_stack.last().map(|t| t.0) {
+            Some(Token::LParen) => "Expected ',' or ')'.",
+            Some(Token::LBrace) => "Expected ',' or '}'.",
+            Some(Token::LBracket) => "Expected ',' or ']'.",
+            _ => unreachable!("We are in a collection."),
+        };
+        let mut err = self
+            .pop_bracket()
+            .expect_err("We did not find the closing bracket.");
+        err.message = message.into();
+        err
+    }
+
     /// Return the token under the cursor, if there is one.
     fn peek(&self) -> Option<Token> {
         self.peek_n(0)
@@ -93,11 +236,15 @@ impl<'a> Parser<'a> {
 
     /// Return the next code token, ignoring whitespace and non-code.
     fn peek_past_non_code(&self) -> Option<Token> {
+        self.peek_lexeme_past_non_code().map(|t| t.0)
+    }
+
+    /// Return the next code token and its span, ignoring whitespace and non-code.
+    fn peek_lexeme_past_non_code(&self) -> Option<Lexeme> {
         self.tokens[self.cursor..]
             .iter()
-            .filter(|t| !matches!(t.0, Token::Blank | Token::LineComment | Token::Shebang))
-            .map(|t| t.0)
-            .next()
+            .find(|t| !matches!(t.0, Token::Blank | Token::LineComment | Token::Shebang))
+            .copied()
     }
 
     /// Return the token `offset` tokens after the cursor, if there is one.
@@ -325,13 +472,25 @@ impl<'a> Parser<'a> {
 
             match self.peek() {
                 Some(Token::KwAssert | Token::KwLet | Token::KwTrace) => {
-                    let stmt = self.parse_stmt()?;
-                    let prefixed = Prefixed {
-                        prefix,
-                        inner: stmt,
-                    };
-                    let span = self.span_from(begin);
-                    statements.push((span, prefixed));
+                    // If the statement is malformed, we skip past its `;`, so
+                    // we can report errors in later statements too.
+                    let checkpoint = self.checkpoint();
+                    match self.parse_stmt() {
+                        Ok(stmt) => {
+                            let prefixed = Prefixed {
+                                prefix,
+                                inner: stmt,
+                            };
+                            let span = self.span_from(begin);
+                            statements.push((span, prefixed));
+                        }
+                        Err(err) => {
+                            self.recover(err, checkpoint, Token::Semicolon)?;
+                            while self.peek() == Some(Token::Semicolon) {
+                                self.consume();
+                            }
+                        }
+                    }
                 }
                 _ => {
                     let expr = self.parse_expr_no_stmt()?;
@@ -426,11 +585,44 @@ impl<'a> Parser<'a> {
         // Consume the `import` keyword.
         let import_span = self.consume();
         let (path_span, path) = self.parse_expr()?;
+        let mut span = import_span.union(path_span);
+
+        // The path can be followed by `as text`. These are not keywords, so
+        // they remain available as identifiers elsewhere.
+        let mut format = ImportFormat::Rcl;
+        let is_as = match self.peek_lexeme_past_non_code() {
+            Some((Token::Ident, as_span)) => as_span.resolve(self.input) == "as",
+            _ => false,
+        };
+        if is_as {
+            self.skip_non_code()?;
+            self.consume();
+            self.skip_non_code()?;
+            let format_span = self.parse_token(
+                Token::Ident,
+                "Expected an import format here, such as 'text'.",
+            )?;
+            format = match format_span.resolve(self.input) {
+                "text" => ImportFormat::Text,
+                _ => {
+                    return format_span
+                        .error("Unknown import format.")
+                        .with_help(concat! {
+                            "The only supported format is '" Doc::highlight("text") "', "
+                            "which imports the file contents as a string."
+                        })
+                        .err()
+                }
+            };
+            span = span.union(format_span);
+        }
+
         let result = Expr::Import {
             path_span,
             path: Box::new(path),
+            format,
         };
-        Ok((import_span.union(path_span), result))
+        Ok((span, result))
     }
 
     /// Parse the statement under the cursor.
@@ -444,6 +636,23 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse the `;` that closes a statement.
+    ///
+    /// Redundant semicolons directly after it are accepted, the formatter
+    /// removes them.
+    fn parse_stmt_end(
+        &mut self,
+        message: &'static str,
+        open: Span,
+        note: &'static str,
+    ) -> Result<()> {
+        self.parse_token_with_note(Token::Semicolon, message, open, note)?;
+        while let Some(Token::Semicolon) = self.peek() {
+            self.consume();
+        }
+        Ok(())
+    }
+
     fn parse_stmt_assert(&mut self) -> Result<Stmt> {
         // Consume the `assert` keyword.
         let assert_span = self.consume();
@@ -475,9 +684,13 @@ impl<'a> Parser<'a> {
         self.skip_non_code()?;
         let (message_span, message) = self.parse_expr()?;
 
+        // Like in collections, we accept a trailing comma after the message.
         self.skip_non_code()?;
-        self.parse_token_with_note(
-            Token::Semicolon,
+        if let Some(Token::Comma) = self.peek() {
+            self.consume();
+            self.skip_non_code()?;
+        }
+        self.parse_stmt_end(
             "Expected ';' here to close the assertion.",
             assert_span,
             "Assertion opened here.",
@@ -497,8 +710,22 @@ impl<'a> Parser<'a> {
         // Consume the `let` keyword.
         let let_ = self.consume();
 
+        // The bound name is either an identifier, or a tuple of identifiers
+        // to destructure the value into.
         self.skip_non_code()?;
-        let ident = self.parse_ident()?;
+        let tuple = match self.peek() {
+            Some(Token::LParen) => {
+                let open = self.push_bracket()?;
+                let idents = self.parse_function_args()?;
+                let close = self.pop_bracket()?;
+                Some((open, close, idents))
+            }
+            _ => None,
+        };
+        let ident = match tuple {
+            Some(..) => None,
+            None => Some(self.parse_ident()?),
+        };
 
         // Parse the optional type signature, and then the '='.
         self.skip_non_code()?;
@@ -542,18 +769,28 @@ impl<'a> Parser<'a> {
         let (value_span, value) = self.parse_expr()?;
 
         self.skip_non_code()?;
-        self.parse_token_with_note(
-            Token::Semicolon,
+        self.parse_stmt_end(
             "Expected ';' here to close the let-binding.",
             let_,
             "Let-binding opened here.",
         )?;
 
-        let result = Stmt::Let {
-            ident,
-            type_,
-            value_span,
-            value: Box::new(value),
+        let result = match (ident, tuple) {
+            (Some(ident), _) => Stmt::Let {
+                ident,
+                type_,
+                value_span,
+                value: Box::new(value),
+            },
+            (None, Some((open, close, idents))) => Stmt::LetTuple {
+                open,
+                close,
+                idents,
+                type_,
+                value_span,
+                value: Box::new(value),
+            },
+            (None, None) => unreachable!("We parsed either an identifier or a tuple."),
         };
 
         Ok(result)
@@ -567,8 +804,7 @@ impl<'a> Parser<'a> {
         let (message_span, message) = self.parse_expr()?;
 
         self.skip_non_code()?;
-        self.parse_token_with_note(
-            Token::Semicolon,
+        self.parse_stmt_end(
             "Expected ';' here to close the trace expression.",
             trace_span,
             "Trace opened here.",
@@ -704,41 +940,75 @@ impl<'a> Parser<'a> {
         // We might have binary operators following. If we find one, then
         // all the other ones must be of the same type, to avoid unclear
         // situations like whether "a and b or c" means "(a and b) or c"
-        // or "a and (b or c)".
+        // or "a and (b or c)". The same holds for the pipeline operator.
         let mut allowed_op = None;
         let mut allowed_span = None;
         loop {
             self.skip_non_code()?;
-            match self.peek().and_then(to_binop) {
-                Some(op) if allowed_op.is_none() || allowed_op == Some(op) => {
-                    let span = self.consume();
-                    self.skip_non_code()?;
-                    let (rhs_span, rhs) = self.parse_expr_not_op()?;
-                    allowed_span = Some(span);
-                    allowed_op = Some(op);
-                    result = Expr::BinOp {
-                        op,
-                        op_span: span,
-                        lhs_span,
-                        lhs: Box::new(result),
-                        rhs_span,
-                        rhs: Box::new(rhs),
-                    };
-                    lhs_span = lhs_span.union(rhs_span);
-                }
-                Some(_op) => {
+            let token = self.peek();
+            let op = token.and_then(to_binop);
+            let is_pipe = token == Some(Token::PipeGt);
+            match allowed_span {
+                Some(allowed_span) if (op.is_some() || is_pipe) && op != allowed_op => {
                     return self.error(
                         "Parentheses are needed to clarify the precedence of this operator.",
                     ).with_note(
-                        allowed_span.expect("If we are here, allowed_span must be set."),
+                        allowed_span,
                         "Without parentheses, it is not clear whether this operator should take precedence.",
                     ).err();
                 }
-                _ => return Ok((lhs_span, result)),
+                _ if is_pipe => {
+                    allowed_span = Some(self.peek_span());
+                    (lhs_span, result) = self.parse_expr_pipeline(lhs_span, result)?;
+                }
+                _ => match op {
+                    Some(op) => {
+                        let span = self.consume();
+                        self.skip_non_code()?;
+                        let (rhs_span, rhs) = self.parse_expr_not_op()?;
+                        allowed_span = Some(span);
+                        allowed_op = Some(op);
+                        result = Expr::BinOp {
+                            op,
+                            op_span: span,
+                            lhs_span,
+                            lhs: Box::new(result),
+                            rhs_span,
+                            rhs: Box::new(rhs),
+                        };
+                        lhs_span = lhs_span.union(rhs_span);
+                    }
+                    None => return Ok((lhs_span, result)),
+                },
             }
         }
     }
 
+    /// Parse the stages of a pipeline, after the base expression.
+    ///
+    fn parse_expr_pipeline(&mut self, base_span: Span, base: Expr) -> Result<(Span, Expr)> {
+        let mut span = base_span;
+        let mut stages = Vec::new();
+        while self.peek() == Some(Token::PipeGt) {
+            let op_span = self.consume();
+            self.skip_non_code()?;
+            let (function_span, function) = self.parse_expr_not_op()?;
+            span = span.union(function_span);
+            stages.push(PipelineStage {
+                op_span,
+                function_span,
+                function,
+            });
+            self.skip_non_code()?;
+        }
+        let result = Expr::Pipeline {
+            base_span,
+            base: Box::new(base),
+            stages,
+        };
+        Ok((span, result))
+    }
+
     fn parse_expr_unop(&mut self) -> Result<(Span, Expr)> {
         let op = self
             .peek()
@@ -768,7 +1038,7 @@ impl<'a> Parser<'a> {
         // parse error is confusing, about unexpected content after the end
         // of the expression/document.
         self.skip_non_code()?;
-        if self.peek().and_then(to_binop).is_some() {
+        if self.peek().and_then(to_binop).is_some() || self.peek() == Some(Token::PipeGt) {
             return self
                 .error("Parentheses are needed to clarify the precedence of this operator.")
                 .with_note(
@@ -867,13 +1137,48 @@ impl<'a> Parser<'a> {
             }
             Some(Token::LParen) => {
                 let open = self.push_bracket()?;
+
+                // `()` is the empty tuple.
+                if self.peek_past_non_code() == Some(Token::RParen) {
+                    let elements = self.parse_call_args()?;
+                    let close = self.pop_bracket()?;
+                    return Ok(Expr::TupleLit {
+                        open,
+                        close,
+                        elements,
+                    });
+                }
+
                 let (body_span, body) = self.parse_expr()?;
+
+                // A single expression in parens is just that expression, but
+                // when a comma follows, it is a tuple, e.g. `(x,)` or `(x, y)`.
+                if self.peek() != Some(Token::Comma) {
+                    let close = self.pop_bracket()?;
+                    let result = Expr::Parens {
+                        open,
+                        close,
+                        body_span,
+                        body: Box::new(body),
+                    };
+                    return Ok(result);
+                }
+
+                self.consume();
+                let rest = self.parse_call_args()?;
                 let close = self.pop_bracket()?;
-                let result = Expr::Parens {
+                let mut elements = Vec::with_capacity(rest.elements.len() + 1);
+                elements.push((body_span, body));
+                elements.extend(rest.elements.into_vec());
+                let result = Expr::TupleLit {
                     open,
                     close,
-                    body_span,
-                    body: Box::new(body),
+                    elements: List {
+                        // If nothing follows the first comma, it is trailing.
+                        trailing_comma: rest.trailing_comma || elements.len() == 1,
+                        elements: elements.into_boxed_slice(),
+                        suffix: rest.suffix,
+                    },
                 };
                 Ok(result)
             }
@@ -884,7 +1189,11 @@ impl<'a> Parser<'a> {
             Some(Token::NumHexadecimal) => Ok(Expr::NumHexadecimal(self.consume())),
             Some(Token::NumBinary) => Ok(Expr::NumBinary(self.consume())),
             Some(Token::NumDecimal) => Ok(Expr::NumDecimal(self.consume())),
+            Some(Token::BytesHexadecimal) => Ok(Expr::BytesHexadecimal(self.consume())),
+            Some(Token::BytesBase64) => Ok(Expr::BytesBase64(self.consume())),
             Some(Token::Ident) => Ok(Expr::Var(self.consume())),
+            Some(Token::Tag) => self.parse_expr_tagged(),
+            Some(Token::KwMatch) => self.parse_expr_match(),
 
             // Some tokens are valid starts of an expression, but just not at
             // the term level. For those, we can recommend the user to wrap
@@ -897,6 +1206,186 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse a `#tag`, optionally followed by a payload.
+    ///
+    /// The tag takes a payload when the next token starts a term. Tags without
+    /// payload are common in collections and call arguments, where the next
+    /// token is a separator.
+    fn parse_expr_tagged(&mut self) -> Result<Expr> {
+        let tag = self.consume();
+        let has_value = matches!(
+            self.peek_past_non_code(),
+            Some(
+                Token::LBrace
+                    | Token::LBracket
+                    | Token::LParen
+                    | Token::QuoteOpen(..)
+                    | Token::KwNull
+                    | Token::KwTrue
+                    | Token::KwFalse
+                    | Token::NumHexadecimal
+                    | Token::NumBinary
+                    | Token::NumDecimal
+                    | Token::BytesHexadecimal
+                    | Token::BytesBase64
+                    | Token::Ident
+                    | Token::Tag
+            )
+        );
+        let value = if has_value {
+            self.skip_non_code()?;
+            let (value_span, value) = self.parse_expr_not_op()?;
+            Some((value_span, Box::new(value)))
+        } else {
+            None
+        };
+        Ok(Expr::Tagged { tag, value })
+    }
+
+    fn parse_expr_match(&mut self) -> Result<Expr> {
+        // Consume the `match` keyword.
+        let match_span = self.consume();
+
+        // Like the condition of an `if`, the scrutinee cannot contain
+        // statements or non-code, to keep formatting manageable.
+        self.skip_non_code()?;
+        let (scrutinee_span, scrutinee) = self.parse_expr_op()?;
+
+        self.skip_non_code()?;
+        if self.peek() != Some(Token::LBrace) {
+            return self
+                .error("Expected '{' here to open the match arms.")
+                .with_note(match_span, "To match this 'match'.")
+                .err();
+        }
+        let open = self.push_bracket()?;
+
+        let mut arms = Vec::new();
+        let mut trailing_comma = false;
+
+        loop {
+            let prefix = self.parse_non_code();
+            if self.peek() == Some(Token::RBrace) {
+                let close = self.pop_bracket()?;
+                let result = Expr::Match {
+                    scrutinee_span,
+                    scrutinee: Box::new(scrutinee),
+                    open,
+                    close,
+                    arms: List {
+                        elements: arms.into_boxed_slice(),
+                        suffix: prefix,
+                        trailing_comma,
+                    },
+                };
+                return Ok(result);
+            }
+
+            let arm = self.parse_match_arm()?;
+            arms.push(Prefixed { prefix, inner: arm });
+            trailing_comma = false;
+
+            self.skip_non_code()?;
+            match self.peek() {
+                Some(Token::RBrace) => continue,
+                Some(Token::Comma) => {
+                    self.consume();
+                    trailing_comma = true;
+                    continue;
+                }
+                // If we don't find a separator, nor the end of the arms,
+                // that's an error.
+                _ => return Err(self.error_after_element()),
+            }
+        }
+    }
+
+    /// Parse a `pattern => body` arm of a match expression.
+    fn parse_match_arm(&mut self) -> Result<MatchArm> {
+        let pattern = self.parse_pattern()?;
+        self.skip_non_code()?;
+        self.parse_token(Token::FatArrow, "Expected '=>' here after the pattern.")?;
+        let (body_span, body) = self.parse_expr()?;
+        let result = MatchArm {
+            pattern,
+            body_span,
+            body: Box::new(body),
+        };
+        Ok(result)
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern> {
+        let begin = self.peek_span();
+        match self.peek() {
+            Some(Token::Tag) => {
+                let tag = self.consume();
+                let inner = if self.peek_past_non_code().map_or(false, starts_pattern) {
+                    self.skip_non_code()?;
+                    Some(Box::new(self.parse_pattern()?))
+                } else {
+                    None
+                };
+                Ok(Pattern::Tag { tag, inner })
+            }
+            Some(Token::Ident) => {
+                let span = self.consume();
+                match span.resolve(self.input) {
+                    "_" => Ok(Pattern::Wildcard(span)),
+                    _ => Ok(Pattern::Binding(span)),
+                }
+            }
+            Some(Token::QuoteOpen(StringPrefix::Format, _)) => self
+                .error("Format strings cannot be used as patterns.")
+                .with_help("Use a regular string instead.")
+                .err(),
+            Some(
+                Token::KwNull
+                | Token::KwTrue
+                | Token::KwFalse
+                | Token::NumHexadecimal
+                | Token::NumBinary
+                | Token::NumDecimal
+                | Token::QuoteOpen(..),
+            ) => {
+                let value = self.parse_expr_term()?;
+                let result = Pattern::Literal {
+                    span: self.span_from(begin),
+                    value: Box::new(value),
+                };
+                Ok(result)
+            }
+            Some(Token::Minus) => {
+                let op_span = self.consume();
+                self.skip_non_code()?;
+                let body_span = self.peek_span();
+                let body = match self.peek() {
+                    Some(Token::NumHexadecimal | Token::NumBinary | Token::NumDecimal) => {
+                        self.parse_expr_term()?
+                    }
+                    _ => return self.error("Expected a number here.").err(),
+                };
+                let value = Expr::UnOp {
+                    op_span,
+                    op: UnOp::Neg,
+                    body_span,
+                    body: Box::new(body),
+                };
+                let result = Pattern::Literal {
+                    span: self.span_from(begin),
+                    value: Box::new(value),
+                };
+                Ok(result)
+            }
+            _ => self
+                .error("Expected a pattern here.")
+                .with_help(
+                    "A pattern is a literal, a name, '_', or a tag \
+                    optionally followed by a pattern, such as '#tcp port'.",
+                )
+                .err(),
+        }
+    }
+
     /// Consume a string inner span, ensuring that multiline strings start with a newline.
     ///
     /// If we allowed content between the opening quote and the first line break,
@@ -944,6 +1433,31 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
+    /// Reject trailing spaces on lines of a raw `"""` string.
+    ///
+    /// The formatter removes trailing whitespace, and in regular strings it
+    /// escapes trailing spaces to preserve them. Raw strings do not have
+    /// escape sequences, so the formatter could not preserve those spaces.
+    fn check_raw_trailing_spaces(&self, parts: &[StringPart]) -> Result<()> {
+        for pair in parts.windows(2) {
+            if let [StringPart::String(line), StringPart::String(..)] = pair {
+                let line_str = line.resolve(self.input);
+                let n = line_str.len() - line_str.trim_end_matches(' ').len();
+                if n > 0 {
+                    return line
+                        .trim_start(line.len() - n)
+                        .error("Raw strings cannot contain trailing spaces.")
+                        .with_help(
+                            "Use a regular string, where trailing spaces can be \
+                            written as '\\u0020'.",
+                        )
+                        .err();
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn parse_string(&mut self, prefix: StringPrefix, style: QuoteStyle) -> Result<Expr> {
         let open = self.consume();
         let mut parts = Vec::new();
@@ -978,6 +1492,9 @@ impl<'a> Parser<'a> {
                             .error("This format string has no holes, it can be a regular string.")
                             .err();
                     }
+                    if prefix == StringPrefix::Raw && style == QuoteStyle::Triple {
+                        self.check_raw_trailing_spaces(&parts)?;
+                    }
                     let result = Expr::StringLit {
                         prefix,
                         style,
@@ -1024,13 +1541,9 @@ impl<'a> Parser<'a> {
                     trailing_comma = true;
                     continue;
                 }
-                _ => {
-                    // If we don't find a separator, nor the end of the args,
-                    // that's an error. We can report an unmatched bracket
-                    // as the problem, because it is.
-                    self.pop_bracket()?;
-                    unreachable!("pop_bracket should have failed.");
-                }
+                // If we don't find a separator, nor the end of the args,
+                // that's an error.
+                _ => return Err(self.error_after_element()),
             }
         }
     }
@@ -1062,13 +1575,9 @@ impl<'a> Parser<'a> {
                     trailing_comma = true;
                     continue;
                 }
-                _ => {
-                    // If we don't find a separator, nor the end of the args,
-                    // that's an error. We can report an unmatched bracket
-                    // as the problem, because it is.
-                    self.pop_bracket()?;
-                    unreachable!("pop_bracket should have failed.");
-                }
+                // If we don't find a separator, nor the end of the args,
+                // that's an error.
+                _ => return Err(self.error_after_element()),
             }
         }
     }
@@ -1092,66 +1601,71 @@ impl<'a> Parser<'a> {
                 return Ok(final_result);
             }
 
-            let (_span, seq) = self.parse_seq()?;
-            let prefixed = Prefixed { prefix, inner: seq };
-            result.push(prefixed);
-            trailing_comma = false;
-
-            self.skip_non_code()?;
-            match self.peek() {
-                Some(Token::RBrace | Token::RBracket) => continue,
-                tok if tok == Some(Token::Comma) => {
-                    self.consume();
-                    trailing_comma = true;
-                    continue;
-                }
-                // All of the next tokens are unexpected, but we add special
-                // errors for them to help the user along.
-                Some(Token::Semicolon) => {
-                    return self.error("Expected ',' instead of ';' here.").err();
-                }
-                Some(Token::KwElse) => {
-                    return self
-                        .pop_bracket()
-                        .expect_err("We are in a seq.")
-                        .with_help(concat! {
-                            "Inside a comprehension, '"
-                            Doc::highlight("if")
-                            "' controls the loop, there is no '" Doc::highlight("else") "' part."
-                            Doc::Sep
-                            "To use an if-else expression inside a comprehension, "
-                            "enclose the expression in parentheses."
-                        })
-                        .err();
-                }
-                // If we don't find a separator, nor the end of the collection
-                // literal, that's an error. We can report an unmatched bracket
-                // as the problem, because it is. The pop will fail. If we see
-                // an '=' maybe the user tried to make a key-value mapping and
-                // we can report a better error.
-                Some(Token::Eq1) => {
-                    return self
-                        .pop_bracket()
-                        .expect_err("We are in a seq.")
-                        .with_help(concat! {
-                            "To use '"
-                            Doc::highlight("key = value")
-                            "' record notation, the left-hand side must be an identifier."
-                            Doc::Sep
-                            "When that is not possible, use json-style '"
-                            Doc::highlight("\"key\": value")
-                            "' instead."
-                        })
-                        .err();
+            // If the element is malformed, we skip to the next one, so we can
+            // report errors in later elements too.
+            let checkpoint = self.checkpoint();
+            match self.parse_seq_and_separator() {
+                Ok((seq, has_comma)) => {
+                    result.push(Prefixed { prefix, inner: seq });
+                    trailing_comma = has_comma;
                 }
-                _ => {
-                    self.pop_bracket()?;
-                    unreachable!("pop_bracket should have failed.");
+                Err(err) => {
+                    self.recover(err, checkpoint, Token::Comma)?;
+                    if self.peek() == Some(Token::Comma) {
+                        self.consume();
+                    }
                 }
             }
         }
     }
 
+    /// Parse a sequence element, and the separator after it, if there is one.
+    ///
+    /// Returns whether the element was followed by a comma.
+    fn parse_seq_and_separator(&mut self) -> Result<(Seq, bool)> {
+        let (_span, seq) = self.parse_seq()?;
+
+        self.skip_non_code()?;
+        match self.peek() {
+            Some(Token::RBrace | Token::RBracket) => Ok((seq, false)),
+            Some(Token::Comma) => {
+                self.consume();
+                Ok((seq, true))
+            }
+            // All of the next tokens are unexpected, but we add special
+            // errors for them to help the user along.
+            Some(Token::Semicolon) => self.error("Expected ',' instead of ';' here.").err(),
+            Some(Token::KwElse) => self
+                .error_after_element()
+                .with_help(concat! {
+                    "Inside a comprehension, '"
+                    Doc::highlight("if")
+                    "' controls the loop, there is no '" Doc::highlight("else") "' part."
+                    Doc::Sep
+                    "To use an if-else expression inside a comprehension, "
+                    "enclose the expression in parentheses."
+                })
+                .err(),
+            // If we see an '=' maybe the user tried to make a key-value
+            // mapping and we can report a better error.
+            Some(Token::Eq1) => self
+                .error_after_element()
+                .with_help(concat! {
+                    "To use '"
+                    Doc::highlight("key = value")
+                    "' record notation, the left-hand side must be an identifier."
+                    Doc::Sep
+                    "When that is not possible, use json-style '"
+                    Doc::highlight("\"key\": value")
+                    "' instead."
+                })
+                .err(),
+            // If we don't find a separator, nor the end of the collection
+            // literal, that's an error.
+            _ => Err(self.error_after_element()),
+        }
+    }
+
     pub fn parse_prefixed_seq(&mut self) -> Result<(Span, Prefixed<Seq>)> {
         let ps = self.parse_prefixed(|s| s.parse_seq())?;
         Ok((
@@ -1299,6 +1813,11 @@ impl<'a> Parser<'a> {
 
     /// Parse a type expression.
     fn parse_type_expr(&mut self) -> Result<Type> {
+        // A tag can be followed by the type of its payload.
+        if let Some(Token::Tag) = self.peek() {
+            return self.parse_type_tagged();
+        }
+
         // If it starts with a `(`, then that is the start of an argument list,
         // and we are parsing a function type.
         if let Some(Token::LParen) = self.peek() {
@@ -1329,6 +1848,24 @@ impl<'a> Parser<'a> {
         Ok(term)
     }
 
+    /// Parse a tagged type, with optional payload type.
+    fn parse_type_tagged(&mut self) -> Result<Type> {
+        let tag = self.consume();
+        let value = match self.peek_past_non_code() {
+            Some(Token::Ident | Token::Tag | Token::LParen) => {
+                self.skip_non_code()?;
+                Some(Box::new(self.parse_type_expr()?))
+            }
+            _ => None,
+        };
+        let type_tagged = Type::Tagged {
+            span: self.span_from(tag),
+            tag,
+            value,
+        };
+        Ok(type_tagged)
+    }
+
     /// Parse a function type that starts with a `(`.
     fn parse_type_function(&mut self) -> Result<Type> {
         let begin = self.peek_span();
@@ -1378,13 +1915,9 @@ impl<'a> Parser<'a> {
                     trailing_comma = true;
                     continue;
                 }
-                _ => {
-                    // If we don't find a separator, nor the end of the list,
-                    // that's an error. We can report an unmatched bracket
-                    // as the problem, because it is.
-                    self.pop_bracket()?;
-                    unreachable!("pop_bracket should have failed.");
-                }
+                // If we don't find a separator, nor the end of the list,
+                // that's an error.
+                _ => return Err(self.error_after_element()),
             }
         }
     }
@@ -16,6 +16,8 @@
 //!
 //! [wadler2003]: https://homepages.inf.ed.ac.uk/wadler/papers/prettier/prettier.pdf
 
+use std::rc::Rc;
+
 use crate::markup::{Markup, MarkupString};
 use crate::pprint::printer::{PrintResult, Printer};
 
@@ -143,6 +145,12 @@ pub enum Doc<'a> {
 
     /// Apply markup to the inner document.
     Markup(Markup, Box<Doc<'a>>),
+
+    /// A fragment that may occur in multiple places in the document.
+    ///
+    /// This enables formatters to build the document for a value once, and
+    /// reference it everywhere the value occurs.
+    Shared(Rc<Doc<'a>>),
 }
 
 impl<'a> Doc<'a> {
@@ -282,6 +290,10 @@ impl<'a> Doc<'a> {
             Doc::Indent(inner) => Doc::Indent(Box::new(inner.into_owned())),
             Doc::FlushIndent(inner) => Doc::FlushIndent(Box::new(inner.into_owned())),
             Doc::Markup(m, inner) => Doc::Markup(m, Box::new(inner.into_owned())),
+            Doc::Shared(inner) => match Rc::try_unwrap(inner) {
+                Ok(inner) => inner.into_owned(),
+                Err(inner) => inner.as_ref().clone().into_owned(),
+            },
         }
     }
 
@@ -297,6 +309,7 @@ impl<'a> Doc<'a> {
             Doc::Indent(inner) => inner.is_forced_tall(),
             Doc::FlushIndent(inner) => inner.is_forced_tall(),
             Doc::Markup(_, inner) => inner.is_forced_tall(),
+            Doc::Shared(inner) => inner.is_forced_tall(),
             _ => false,
         }
     }
@@ -381,6 +394,7 @@ impl<'a> Doc<'a> {
                 }
             },
             Doc::Markup(markup, inner) => printer.with_markup(*markup, |p| inner.print_to(p, mode)),
+            Doc::Shared(inner) => inner.print_to(printer, mode),
         }
     }
 
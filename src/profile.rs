@@ -0,0 +1,228 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! An instrumenting profiler that attributes evaluation time to source spans.
+//!
+//! The evaluator enters a frame when it starts evaluating a document, calling
+//! a function, or running a comprehension, and exits it when done. For every
+//! frame we measure the time spent in the frame itself, excluding nested
+//! frames, and the number of collections and strings that it allocated.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::pprint::Doc;
+use crate::source::{DocId, Inputs, Span};
+use crate::source_map::line_column;
+
+/// Output formats for the profile.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ProfileFormat {
+    /// A table of frames, the ones with the most self time first.
+    #[default]
+    Hotspots,
+
+    /// One line per stack, as consumed by `flamegraph.pl` and compatible tools.
+    Folded,
+}
+
+/// A unit of evaluation that the profiler attributes time to.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Frame {
+    /// Evaluation of a document, the entry point or an import.
+    Document(DocId),
+
+    /// A call to the function defined at the span.
+    Function(Span),
+
+    /// A comprehension, identified by the span of its `for` clause.
+    Comprehension(Span),
+}
+
+/// Measurements for one frame, summed over all of its activations.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct FrameStats {
+    pub calls: u64,
+
+    /// Time spent in the frame, including nested frames.
+    ///
+    /// For recursive frames, only the outermost activation counts.
+    pub total: Duration,
+
+    /// Time spent in the frame, excluding nested frames.
+    pub self_time: Duration,
+
+    /// The number of collections and strings that the frame built.
+    pub allocs: u64,
+}
+
+struct Activation {
+    frame: Frame,
+    start: Instant,
+    nested: Duration,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    stack: Vec<Activation>,
+    stats: BTreeMap<Frame, FrameStats>,
+
+    /// Self time per call stack, outermost frame first.
+    stacks: BTreeMap<Vec<Frame>, Duration>,
+}
+
+impl Profiler {
+    pub fn enter(&mut self, frame: Frame) {
+        self.enter_at(frame, Instant::now());
+    }
+
+    pub fn exit(&mut self) {
+        self.exit_at(Instant::now());
+    }
+
+    fn enter_at(&mut self, frame: Frame, now: Instant) {
+        self.stats.entry(frame).or_default().calls += 1;
+        self.stack.push(Activation {
+            frame,
+            start: now,
+            nested: Duration::ZERO,
+        });
+    }
+
+    fn exit_at(&mut self, now: Instant) {
+        let activation = self.stack.pop().expect("Enter/exit are balanced.");
+        let elapsed = now.saturating_duration_since(activation.start);
+        let self_time = elapsed.saturating_sub(activation.nested);
+        let is_recursive = self.stack.iter().any(|a| a.frame == activation.frame);
+
+        let stats = self.stats.entry(activation.frame).or_default();
+        stats.self_time += self_time;
+        if !is_recursive {
+            stats.total += elapsed;
+        }
+
+        let mut path: Vec<Frame> = self.stack.iter().map(|a| a.frame).collect();
+        path.push(activation.frame);
+        *self.stacks.entry(path).or_default() += self_time;
+
+        if let Some(parent) = self.stack.last_mut() {
+            parent.nested += elapsed;
+        }
+    }
+
+    /// Count an allocation against the innermost frame.
+    pub fn count_alloc(&mut self) {
+        if let Some(activation) = self.stack.last() {
+            self.stats.entry(activation.frame).or_default().allocs += 1;
+        }
+    }
+
+    /// Format the profile in the given format.
+    pub fn report(&self, inputs: &Inputs, format: ProfileFormat) -> Doc<'static> {
+        let lines = match format {
+            ProfileFormat::Hotspots => self.report_hotspots(inputs),
+            ProfileFormat::Folded => self.report_folded(inputs),
+        };
+        let mut result = Vec::with_capacity(lines.len() * 2);
+        for line in lines {
+            if !result.is_empty() {
+                result.push(Doc::HardBreak);
+            }
+            result.push(Doc::from(line));
+        }
+        Doc::Concat(result)
+    }
+
+    fn report_hotspots(&self, inputs: &Inputs) -> Vec<String> {
+        let mut frames: Vec<(&Frame, &FrameStats)> = self.stats.iter().collect();
+        frames.sort_by(|(fa, sa), (fb, sb)| sb.self_time.cmp(&sa.self_time).then(fa.cmp(fb)));
+
+        let mut result = vec!["  self ms  total ms     calls    allocs  frame".to_string()];
+        for (frame, stats) in frames {
+            result.push(format!(
+                "{:>9.3} {:>9.3} {:>9} {:>9}  {}",
+                stats.self_time.as_secs_f64() * 1e3,
+                stats.total.as_secs_f64() * 1e3,
+                stats.calls,
+                stats.allocs,
+                frame_label(inputs, frame),
+            ));
+        }
+        result
+    }
+
+    fn report_folded(&self, inputs: &Inputs) -> Vec<String> {
+        let mut result = Vec::with_capacity(self.stacks.len());
+        for (path, self_time) in self.stacks.iter() {
+            let labels: Vec<String> = path.iter().map(|f| frame_label(inputs, f)).collect();
+            result.push(format!("{} {}", labels.join(";"), self_time.as_micros()));
+        }
+        result
+    }
+}
+
+/// Describe the frame as its location and kind, e.g. `main.rcl:3:14 function`.
+fn frame_label(inputs: &Inputs, frame: &Frame) -> String {
+    let (span, kind) = match frame {
+        Frame::Document(id) => return format!("{} document", inputs[id.0 as usize].name),
+        Frame::Function(span) => (span, "function"),
+        Frame::Comprehension(span) => (span, "comprehension"),
+    };
+    let doc = &inputs[span.doc().0 as usize];
+    let (line, column) = line_column(doc.data, span.start());
+    format!("{}:{line}:{column} {kind}", doc.name)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{Frame, ProfileFormat, Profiler};
+    use crate::pprint::Config;
+    use crate::source::{Doc, DocId, Span};
+
+    #[test]
+    fn profiler_attributes_self_time_to_innermost_frame() {
+        let data = "let f = x => x;\n[for i in [1]: f(i)]";
+        let inputs = [Doc {
+            name: "main.rcl",
+            data,
+            span: Span::new(DocId(0), 0, data.len()),
+            tab_width: 8,
+        }];
+        let document = Frame::Document(DocId(0));
+        let comprehension = Frame::Comprehension(Span::new(DocId(0), 21, 32));
+        let function = Frame::Function(Span::new(DocId(0), 8, 14));
+
+        let ms = Duration::from_millis;
+        let t0 = Instant::now();
+        let mut profiler = Profiler::default();
+        profiler.enter_at(document, t0);
+        profiler.enter_at(comprehension, t0 + ms(1));
+        profiler.count_alloc();
+        profiler.enter_at(function, t0 + ms(2));
+        profiler.exit_at(t0 + ms(5));
+        profiler.exit_at(t0 + ms(6));
+        profiler.exit_at(t0 + ms(10));
+
+        assert_eq!(profiler.stats[&comprehension].total, ms(5));
+        assert_eq!(profiler.stats[&comprehension].self_time, ms(2));
+        assert_eq!(profiler.stats[&comprehension].allocs, 1);
+        assert_eq!(profiler.stats[&document].self_time, ms(5));
+
+        let cfg = Config { width: 80 };
+        assert_eq!(
+            profiler
+                .report(&inputs, ProfileFormat::Folded)
+                .println(&cfg)
+                .to_string_no_markup(),
+            "main.rcl document 5000\n\
+             main.rcl document;main.rcl:2:6 comprehension 2000\n\
+             main.rcl document;main.rcl:2:6 comprehension;main.rcl:1:9 function 3000\n"
+        );
+    }
+}
@@ -0,0 +1,505 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! A small regular expression engine, for `std.check.matches`.
+//!
+//! The supported syntax is a common subset: literals, `.`, character classes
+//! such as `[a-z_]` and `[^0-9]`, the escapes `\d`, `\w`, `\s` and their
+//! negations, groups `(...)` and `(?:...)`, alternation `|`, and the
+//! quantifiers `*`, `+`, `?`, `{n}`, `{n,}`, and `{n,m}`. Patterns always match
+//! the entire string, so there are no anchors.
+//!
+//! Patterns compile to a program for a Pike VM, which runs in time linear in
+//! the length of the input, so a pattern cannot make evaluation hang.
+
+/// Patterns that compile to more instructions than this are rejected.
+///
+/// This bounds the work per input character, repetitions like `(a{100}){100}`
+/// could otherwise expand to a huge program.
+const MAX_INSTRUCTIONS: usize = 10_000;
+
+/// A set of characters, matched by a character class or escape.
+#[derive(Clone, Debug)]
+struct Class {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl Class {
+    fn new(ranges: &[(char, char)], negated: bool) -> Class {
+        Class {
+            ranges: ranges.to_vec(),
+            negated,
+        }
+    }
+
+    fn contains(&self, ch: char) -> bool {
+        let in_ranges = self.ranges.iter().any(|(lo, hi)| *lo <= ch && ch <= *hi);
+        in_ranges != self.negated
+    }
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        inner: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+    },
+}
+
+struct Parser<'a> {
+    pattern: &'a str,
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, detail: &str) -> Result<T, String> {
+        Err(format!("Invalid pattern '{}'. {detail}", self.pattern))
+    }
+
+    fn parse_alt(&mut self, depth: u32) -> Result<Node, String> {
+        let mut alternatives = vec![self.parse_concat(depth)?];
+        while self.chars.next_if_eq(&'|').is_some() {
+            alternatives.push(self.parse_concat(depth)?);
+        }
+        match alternatives.len() {
+            1 => Ok(alternatives.pop().expect("Has one element.")),
+            _ => Ok(Node::Alt(alternatives)),
+        }
+    }
+
+    fn parse_concat(&mut self, depth: u32) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(&ch) = self.chars.peek() {
+            let atom = match ch {
+                '|' | ')' => break,
+                '*' | '+' | '?' | '{' => {
+                    return self.error("A quantifier must follow something to repeat.");
+                }
+                '^' | '$' => {
+                    return self
+                        .error("Anchors are not supported, patterns match the entire string.");
+                }
+                _ => self.parse_atom(depth)?,
+            };
+            let atom = self.parse_quantifiers(atom)?;
+            nodes.push(atom);
+        }
+        match nodes.len() {
+            0 => Ok(Node::Empty),
+            1 => Ok(nodes.pop().expect("Has one element.")),
+            _ => Ok(Node::Concat(nodes)),
+        }
+    }
+
+    fn parse_atom(&mut self, depth: u32) -> Result<Node, String> {
+        match self
+            .chars
+            .next()
+            .expect("Caller checked that there is a char.")
+        {
+            '.' => Ok(Node::Any),
+            '\\' => self.parse_escape(),
+            '[' => self.parse_class(),
+            '(' => {
+                if depth >= 32 {
+                    return self.error("Groups are nested too deeply.");
+                }
+                if self.chars.next_if_eq(&'?').is_some() && self.chars.next_if_eq(&':').is_none() {
+                    return self.error("Only non-capturing groups '(?:' are supported after '(?'.");
+                }
+                let inner = self.parse_alt(depth + 1)?;
+                match self.chars.next() {
+                    Some(')') => Ok(inner),
+                    _ => self.error("Expected ')' to close the group."),
+                }
+            }
+            ch => Ok(Node::Char(ch)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, String> {
+        let class = |ranges, negated| Ok(Node::Class(Class::new(ranges, negated)));
+        match self.chars.next() {
+            Some('d') => class(DIGIT, false),
+            Some('D') => class(DIGIT, true),
+            Some('w') => class(WORD, false),
+            Some('W') => class(WORD, true),
+            Some('s') => class(SPACE, false),
+            Some('S') => class(SPACE, true),
+            Some(ch) => self.escaped_char(ch).map(Node::Char),
+            None => self.error("Expected a character after '\\'."),
+        }
+    }
+
+    /// Resolve an escaped character that stands for itself.
+    fn escaped_char(&self, ch: char) -> Result<char, String> {
+        match ch {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            _ if ch.is_ascii_punctuation() => Ok(ch),
+            _ => self.error(&format!("Unsupported escape sequence '\\{ch}'.")),
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<Node, String> {
+        let negated = self.chars.next_if_eq(&'^').is_some();
+        let mut ranges = Vec::new();
+        // A `]` directly after the opening bracket is a literal.
+        let mut is_first = true;
+        loop {
+            let lo = match self.chars.next() {
+                None => return self.error("Expected ']' to close the character class."),
+                Some(']') if !is_first => break,
+                Some('\\') => match self.chars.next() {
+                    Some('d') => {
+                        ranges.extend_from_slice(DIGIT);
+                        is_first = false;
+                        continue;
+                    }
+                    Some('w') => {
+                        ranges.extend_from_slice(WORD);
+                        is_first = false;
+                        continue;
+                    }
+                    Some('s') => {
+                        ranges.extend_from_slice(SPACE);
+                        is_first = false;
+                        continue;
+                    }
+                    Some(ch) => self.escaped_char(ch)?,
+                    None => return self.error("Expected a character after '\\'."),
+                },
+                Some(ch) => ch,
+            };
+            is_first = false;
+            let is_range = {
+                let mut lookahead = self.chars.clone();
+                lookahead.next() == Some('-') && !matches!(lookahead.next(), Some(']') | None)
+            };
+            if !is_range {
+                ranges.push((lo, lo));
+                continue;
+            }
+            self.chars.next();
+            let hi = match self.chars.next() {
+                Some('\\') => match self.chars.next() {
+                    Some(ch) => self.escaped_char(ch)?,
+                    None => return self.error("Expected a character after '\\'."),
+                },
+                Some(ch) => ch,
+                None => unreachable!("Checked by the lookahead above."),
+            };
+            if hi < lo {
+                return self.error(&format!("Invalid range '{lo}-{hi}' in character class."));
+            }
+            ranges.push((lo, hi));
+        }
+        Ok(Node::Class(Class { ranges, negated }))
+    }
+
+    fn parse_quantifiers(&mut self, mut atom: Node) -> Result<Node, String> {
+        loop {
+            let (min, max) = match self.chars.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => {
+                    self.chars.next();
+                    let bounds = self.parse_bounds()?;
+                    atom = Node::Repeat {
+                        inner: Box::new(atom),
+                        min: bounds.0,
+                        max: bounds.1,
+                    };
+                    continue;
+                }
+                _ => return Ok(atom),
+            };
+            self.chars.next();
+            atom = Node::Repeat {
+                inner: Box::new(atom),
+                min,
+                max,
+            };
+        }
+    }
+
+    /// Parse the bounds of `{n}`, `{n,}`, or `{n,m}`, after the opening brace.
+    fn parse_bounds(&mut self) -> Result<(u32, Option<u32>), String> {
+        let mut body = String::new();
+        loop {
+            match self.chars.next() {
+                Some('}') => break,
+                Some(ch) => body.push(ch),
+                None => return self.error("Expected '}' to close the repetition."),
+            }
+        }
+        let parse = |s: &str| s.parse::<u32>().ok().filter(|n| *n <= 1000);
+        let bounds = match body.split_once(',') {
+            None => parse(&body).map(|n| (n, Some(n))),
+            Some((lo, "")) => parse(lo).map(|n| (n, None)),
+            Some((lo, hi)) => match (parse(lo), parse(hi)) {
+                (Some(lo), Some(hi)) if lo <= hi => Some((lo, Some(hi))),
+                _ => None,
+            },
+        };
+        match bounds {
+            Some(bounds) => Ok(bounds),
+            None => self.error(&format!(
+                "Invalid repetition '{{{body}}}'. Expected '{{n}}', '{{n,}}', or '{{n,m}}' \
+                with n ≤ m ≤ 1000."
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// A compiled regular expression.
+#[derive(Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> Result<usize, ()> {
+        if self.program.len() >= MAX_INSTRUCTIONS {
+            return Err(());
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), ()> {
+        match node {
+            Node::Empty => {}
+            Node::Char(ch) => {
+                self.emit(Inst::Char(*ch))?;
+            }
+            Node::Any => {
+                self.emit(Inst::Any)?;
+            }
+            Node::Class(class) => {
+                self.emit(Inst::Class(class.clone()))?;
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alt(nodes) => {
+                let mut jumps = Vec::new();
+                for (i, node) in nodes.iter().enumerate() {
+                    if i + 1 == nodes.len() {
+                        self.compile(node)?;
+                        break;
+                    }
+                    let split = self.emit(Inst::Split(0, 0))?;
+                    self.compile(node)?;
+                    jumps.push(self.emit(Inst::Jump(0))?);
+                    self.program[split] = Inst::Split(split + 1, self.program.len());
+                }
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat { inner, min, max } => {
+                for _ in 0..*min {
+                    self.compile(inner)?;
+                }
+                match max {
+                    None => {
+                        // Zero or more: split between the body and the exit.
+                        let split = self.emit(Inst::Split(0, 0))?;
+                        self.compile(inner)?;
+                        self.emit(Inst::Jump(split))?;
+                        self.program[split] = Inst::Split(split + 1, self.program.len());
+                    }
+                    Some(max) => {
+                        // Every optional copy may be skipped to the very end.
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Split(0, 0))?);
+                            self.compile(inner)?;
+                        }
+                        let end = self.program.len();
+                        for split in splits {
+                            self.program[split] = Inst::Split(split + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Regex {
+    /// Parse and compile a pattern.
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let mut parser = Parser {
+            pattern,
+            chars: pattern.chars().peekable(),
+        };
+        let node = parser.parse_alt(0)?;
+        if parser.chars.next().is_some() {
+            return parser.error("Found ')' without matching '('.");
+        }
+        let mut compiler = Compiler {
+            program: Vec::new(),
+        };
+        match compiler
+            .compile(&node)
+            .and_then(|()| compiler.emit(Inst::Match))
+        {
+            Ok(_) => Ok(Regex {
+                program: compiler.program,
+            }),
+            Err(()) => parser.error("The pattern is too large."),
+        }
+    }
+
+    /// Add the thread at `pc` to `threads`, following jumps and splits.
+    fn add_thread(&self, threads: &mut Vec<usize>, is_added: &mut [bool], pc: usize) {
+        let mut pending = vec![pc];
+        while let Some(pc) = pending.pop() {
+            if is_added[pc] {
+                continue;
+            }
+            is_added[pc] = true;
+            match self.program[pc] {
+                Inst::Jump(target) => pending.push(target),
+                Inst::Split(first, second) => {
+                    // Push the second one first, so we visit the first one first.
+                    pending.push(second);
+                    pending.push(first);
+                }
+                _ => threads.push(pc),
+            }
+        }
+    }
+
+    /// Return whether the pattern matches the entire input.
+    pub fn is_match(&self, input: &str) -> bool {
+        let n = self.program.len();
+        let mut threads = Vec::new();
+        let mut is_added = vec![false; n];
+        self.add_thread(&mut threads, &mut is_added, 0);
+
+        for ch in input.chars() {
+            let mut next = Vec::new();
+            let mut is_next_added = vec![false; n];
+            for &pc in threads.iter() {
+                let is_step = match &self.program[pc] {
+                    Inst::Char(expected) => *expected == ch,
+                    Inst::Any => true,
+                    Inst::Class(class) => class.contains(ch),
+                    Inst::Match => false,
+                    Inst::Split(..) | Inst::Jump(..) => unreachable!("Followed in add_thread."),
+                };
+                if is_step {
+                    self.add_thread(&mut next, &mut is_next_added, pc + 1);
+                }
+            }
+            if next.is_empty() {
+                return false;
+            }
+            threads = next;
+        }
+
+        threads
+            .iter()
+            .any(|pc| matches!(self.program[*pc], Inst::Match))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Regex;
+
+    fn is_match(pattern: &str, input: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(input)
+    }
+
+    #[test]
+    fn regex_matches_entire_input() {
+        assert!(is_match("abc", "abc"));
+        assert!(!is_match("abc", "abcd"));
+        assert!(!is_match("bc", "abc"));
+        assert!(is_match("", ""));
+        assert!(is_match("a.c", "a☕c"));
+        assert!(is_match("[a-z][a-z0-9-]*", "web-01"));
+        assert!(!is_match("[a-z][a-z0-9-]*", "01-web"));
+        assert!(is_match("[^-]+", "abc"));
+        assert!(!is_match("[^-]+", "a-c"));
+        assert!(is_match("[]a]+", "]a]"));
+        assert!(is_match("[a-]+", "a-a"));
+        assert!(is_match("\\d{1,3}(\\.\\d{1,3}){3}", "192.168.1.1"));
+        assert!(!is_match("\\d{1,3}(\\.\\d{1,3}){3}", "192.168.1"));
+        assert!(is_match("(?:http|https)://\\S+", "https://example.com"));
+        assert!(is_match("colou?r", "color"));
+        assert!(is_match("colou?r", "colour"));
+        assert!(is_match("(ab)+", "ababab"));
+        assert!(!is_match("(ab)+", ""));
+        assert!(is_match("a{2,}", "aaaa"));
+        assert!(!is_match("a{2,}", "a"));
+        assert!(is_match("(a*)*b", "aaab"));
+    }
+
+    #[test]
+    fn regex_does_not_backtrack_exponentially() {
+        let input = "a".repeat(100);
+        assert!(!is_match("(a|a)*(a|a)*(a|a)*b", &input));
+        assert!(is_match("(a*)*", &input));
+    }
+
+    #[test]
+    fn regex_rejects_invalid_patterns() {
+        for pattern in [
+            "(",
+            ")",
+            "[a",
+            "*a",
+            "a{",
+            "a{2,1}",
+            "a{1001}",
+            "^a$",
+            "\\q",
+            "[z-a]",
+            "(?=a)",
+            "((a{1000}){1000})",
+        ] {
+            assert!(Regex::new(pattern).is_err(), "Should reject {pattern:?}.");
+        }
+    }
+}
@@ -0,0 +1,470 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! An interactive prompt for querying an evaluated document.
+//!
+//! `rcl query --repl` evaluates the input document once, and then evaluates
+//! every line entered at the prompt as a query, with the variable `input`
+//! bound to the cached result. With the `line-editor` feature and a terminal,
+//! the prompt edits lines with rustyline, and tab completes the dict key at the
+//! cursor in place. Otherwise the prompt reads whole lines, and a line that
+//! ends in a tab (press tab, then enter) lists the keys that complete the path
+//! before it.
+
+use std::cell::RefCell;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use crate::cli::{EvalOptions, StyleOptions};
+use crate::error::Result;
+use crate::eval::StrictMode;
+use crate::loader::Loader;
+use crate::pprint::{self, Doc};
+use crate::runtime::Value;
+use crate::source::Span;
+use crate::tracer::{Tracer, VoidTracer};
+use crate::typecheck;
+
+pub const USAGE_COMMANDS: &str = "\
+Enter an RCL expression to evaluate it, with 'input' bound to the document.
+A query that starts with '.' or '[' is a path into 'input', '.a' means 'input.a'.
+
+Commands:
+  <query><tab>   Complete the dict key at the end of the query, for example
+                 'input.servers.we<tab>'. When the prompt cannot edit lines,
+                 press enter after tab to list the keys instead.
+  :h, :help      Show this help.
+  :q, :quit      Exit, end of input (Ctrl+D) exits as well.";
+
+/// A line entered at the prompt.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    /// Evaluate the query and print the result.
+    Query(String),
+    /// List the keys that complete the path at the end of the query.
+    Complete(String),
+    Help,
+    Quit,
+    /// An empty line, which does nothing.
+    Empty,
+    /// Input that is not a valid command, with an explanation.
+    Invalid(&'static str),
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Command {
+        // Only strip the newline, we need to see a trailing tab.
+        let line = line.trim_end_matches(['\n', '\r']);
+        if let Some(query) = line.strip_suffix('\t') {
+            return Command::Complete(expand_path(query.trim_start()));
+        }
+        match line.trim() {
+            "" => Command::Empty,
+            ":h" | ":help" => Command::Help,
+            ":q" | ":quit" => Command::Quit,
+            cmd if cmd.starts_with(':') => {
+                Command::Invalid("Unknown command, enter ':help' for a list of commands.")
+            }
+            query => Command::Query(expand_path(query)),
+        }
+    }
+}
+
+/// Turn a path shorthand such as `.a.b` or `[0]` into a query on `input`.
+fn expand_path(query: &str) -> String {
+    if query.starts_with('.') || query.starts_with('[') {
+        format!("input{query}")
+    } else {
+        query.to_string()
+    }
+}
+
+/// Split a query into the expression that precedes the final field access, and
+/// the partial field name after it.
+///
+/// For example, `input.servers.we` splits into `input.servers` and `we`. When
+/// the query does not end in a field access, there is nothing to complete.
+fn split_completion(query: &str) -> Option<(&str, &str)> {
+    let is_ident = |ch: char| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-';
+    let partial_start = query.trim_end_matches(is_ident).len();
+    let base = query[..partial_start].strip_suffix('.')?;
+
+    // The base is the path expression that ends at the dot. We don't parse
+    // here, we take the longest suffix that looks like a chain of field
+    // accesses and indexes, and let evaluation decide whether it makes sense.
+    let is_path = |ch: char| is_ident(ch) || matches!(ch, '.' | '[' | ']' | '"');
+    let base_start = base.trim_end_matches(is_path).len();
+    let base = &base[base_start..];
+    if base.is_empty() {
+        return None;
+    }
+    Some((base, &query[partial_start..]))
+}
+
+/// The evaluated document, and what we need to run queries against it.
+///
+/// Both the prompt and the line editor's completer evaluate queries, so they
+/// share the session.
+struct Session<'a> {
+    loader: &'a mut Loader,
+    tracer: &'a mut dyn Tracer,
+    strict_mode: StrictMode,
+
+    /// The result of the input document, bound to `input` in every query.
+    input: Value,
+}
+
+impl<'a> Session<'a> {
+    /// Evaluate a query against the input. With `quiet`, discard traces.
+    fn eval_query(&mut self, query: String, quiet: bool) -> Result<(Span, Value)> {
+        let doc = self.loader.load_named_string("query".to_string(), query);
+        let mut type_env = self.loader.type_prelude();
+        let mut value_env = self.loader.value_prelude();
+        type_env.push("input".into(), typecheck::type_any().clone());
+        value_env.push("input".into(), self.input.clone());
+        let mut void_tracer = VoidTracer;
+        let tracer: &mut dyn Tracer = match quiet {
+            true => &mut void_tracer,
+            false => &mut *self.tracer,
+        };
+        let value =
+            self.loader
+                .evaluate(&mut type_env, &mut value_env, doc, tracer, self.strict_mode)?;
+        Ok((self.loader.get_span(doc), value))
+    }
+
+    /// Return the dict keys that complete the field access at the end of the query.
+    pub fn complete(&mut self, query: &str) -> Vec<String> {
+        let (base, partial) = match split_completion(query) {
+            Some(split) => split,
+            None => return Vec::new(),
+        };
+        let kvs = match self.eval_query(base.to_string(), true) {
+            Ok((_span, Value::Dict(kvs))) => kvs,
+            _ => return Vec::new(),
+        };
+        kvs.keys()
+            .filter_map(|k| match k {
+                Value::String(k) if k.starts_with(partial) => Some(k.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Completes dict keys in the line editor.
+#[cfg(feature = "line-editor")]
+struct KeyCompleter<'a>(Rc<RefCell<Session<'a>>>);
+
+#[cfg(feature = "line-editor")]
+impl rustyline::completion::Completer for KeyCompleter<'_> {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let query = expand_path(line[..pos].trim_start());
+        let partial_len = match split_completion(&query) {
+            Some((_base, partial)) => partial.len(),
+            None => return Ok((pos, Vec::new())),
+        };
+        let keys = self.0.borrow_mut().complete(&query);
+        Ok((pos - partial_len, keys))
+    }
+}
+
+#[cfg(feature = "line-editor")]
+impl rustyline::hint::Hinter for KeyCompleter<'_> {
+    type Hint = String;
+}
+
+#[cfg(feature = "line-editor")]
+impl rustyline::highlight::Highlighter for KeyCompleter<'_> {}
+
+#[cfg(feature = "line-editor")]
+impl rustyline::validate::Validator for KeyCompleter<'_> {}
+
+#[cfg(feature = "line-editor")]
+impl rustyline::Helper for KeyCompleter<'_> {}
+
+/// The state of the prompt, and where it reads queries from and writes to.
+pub struct Repl<'a> {
+    session: Rc<RefCell<Session<'a>>>,
+    reader: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+
+    /// When set, read lines from the terminal with this editor instead of from `reader`.
+    #[cfg(feature = "line-editor")]
+    editor: Option<rustyline::Editor<KeyCompleter<'a>, rustyline::history::DefaultHistory>>,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(
+        loader: &'a mut Loader,
+        tracer: &'a mut dyn Tracer,
+        strict_mode: StrictMode,
+        input: Value,
+        reader: Box<dyn BufRead>,
+        output: Box<dyn Write>,
+    ) -> Repl<'a> {
+        let session = Session {
+            loader,
+            tracer,
+            strict_mode,
+            input,
+        };
+        Repl {
+            session: Rc::new(RefCell::new(session)),
+            reader,
+            output,
+            #[cfg(feature = "line-editor")]
+            editor: None,
+        }
+    }
+
+    /// Read queries from the terminal with a line editor, instead of from the reader.
+    ///
+    /// In the editor, tab completes the dict key at the cursor, and up and
+    /// down recall earlier queries. Only use this when stdin is a terminal.
+    #[cfg(feature = "line-editor")]
+    pub fn enable_line_editor(&mut self) -> rustyline::Result<()> {
+        use rustyline::{CompletionType, Config, Editor};
+        // List the candidates like a shell does, rather than cycling through them.
+        let config = Config::builder()
+            .completion_type(CompletionType::List)
+            .build();
+        let mut editor = Editor::with_config(config)?;
+        editor.set_helper(Some(KeyCompleter(self.session.clone())));
+        self.editor = Some(editor);
+        Ok(())
+    }
+
+    /// Return the dict keys that complete the field access at the end of the query.
+    pub fn complete(&mut self, query: &str) -> Vec<String> {
+        self.session.borrow_mut().complete(query)
+    }
+
+    /// Print the prompt and read the next command.
+    fn read_command(&mut self) -> Command {
+        #[cfg(feature = "line-editor")]
+        if let Some(editor) = self.editor.as_mut() {
+            return match editor.readline("> ") {
+                Ok(line) => {
+                    let _ = editor.add_history_entry(line.as_str());
+                    Command::parse(&line)
+                }
+                // Ctrl+C abandons the line, like it does in a shell. The editor
+                // already ends the line on end of input.
+                Err(rustyline::error::ReadlineError::Interrupted) => Command::Empty,
+                Err(..) => Command::Quit,
+            };
+        }
+        self.print("> ");
+        let _ = self.output.flush();
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) | Err(..) => {
+                // End the line with the prompt, like a shell does.
+                self.print("\n");
+                Command::Quit
+            }
+            Ok(_) => Command::parse(&line),
+        }
+    }
+
+    /// Read queries until the end of the input, and print their results.
+    pub fn run(&mut self, eval_opts: &EvalOptions, style_opts: &StyleOptions) {
+        let cfg = pprint::Config {
+            width: style_opts.width,
+        };
+        let render = |doc: Doc| doc.println(&cfg).to_string_no_markup();
+        loop {
+            match self.read_command() {
+                Command::Query(query) => {
+                    let mut session = self.session.borrow_mut();
+                    let result = session.eval_query(query, false).and_then(|(span, value)| {
+                        crate::cmd_eval::format_value(
+                            eval_opts.format,
+                            style_opts.limits,
+                            eval_opts.output_encoding,
+                            &eval_opts.secrets,
+                            span,
+                            &value,
+                        )
+                    });
+                    let output = match result {
+                        Ok(doc) => render(doc),
+                        Err(err) => render(err.report(&session.loader.as_inputs())),
+                    };
+                    drop(session);
+                    self.print(&output);
+                }
+                Command::Complete(query) => {
+                    let keys = self.complete(&query);
+                    if keys.is_empty() {
+                        self.print("No completions.\n");
+                    }
+                    for key in keys {
+                        self.print(&key);
+                        self.print("\n");
+                    }
+                }
+                Command::Help => {
+                    self.print(USAGE_COMMANDS);
+                    self.print("\n");
+                }
+                Command::Quit => return,
+                Command::Empty => {}
+                Command::Invalid(message) => {
+                    self.print(message);
+                    self.print("\n");
+                }
+            }
+        }
+    }
+
+    fn print(&mut self, message: &str) {
+        // If we can't write to the output, there is nothing better to do than
+        // to carry on.
+        let _ = self.output.write_all(message.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::io::{Cursor, Write};
+    use std::rc::Rc;
+
+    #[cfg(feature = "line-editor")]
+    use super::KeyCompleter;
+    use super::{split_completion, Command, Repl};
+    use crate::cli::{EvalOptions, StyleOptions};
+    use crate::eval::StrictMode;
+    use crate::loader::Loader;
+    use crate::runtime::Value;
+    use crate::tracer::VoidTracer;
+
+    /// Output that we can still read after the prompt took ownership.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn command_parse_handles_paths_and_completion() {
+        assert_eq!(Command::parse(".a.b\n"), Command::Query("input.a.b".into()));
+        assert_eq!(
+            Command::parse("  [0] \n"),
+            Command::Query("input[0]".into())
+        );
+        assert_eq!(
+            Command::parse("input.servers.we\t\n"),
+            Command::Complete("input.servers.we".into())
+        );
+        assert_eq!(Command::parse(".\t\n"), Command::Complete("input.".into()));
+        assert_eq!(Command::parse(":q\n"), Command::Quit);
+        assert_eq!(Command::parse("\n"), Command::Empty);
+        assert!(matches!(Command::parse(":x"), Command::Invalid(..)));
+    }
+
+    #[test]
+    fn split_completion_finds_path_before_partial_field() {
+        assert_eq!(split_completion("input.a.b"), Some(("input.a", "b")));
+        assert_eq!(split_completion("input."), Some(("input", "")));
+        assert_eq!(
+            split_completion("1 + input[\"x\"].y-"),
+            Some(("input[\"x\"]", "y-"))
+        );
+        assert_eq!(split_completion("input"), None);
+        assert_eq!(split_completion(" .a"), None);
+    }
+
+    /// Evaluate the document that the prompt tests query.
+    fn eval_servers(loader: &mut Loader) -> Value {
+        let input = loader.load_string("{ servers = { web = 1, worker = 2, db = 3 } }".into());
+        let mut type_env = loader.type_prelude();
+        let mut value_env = loader.value_prelude();
+        loader
+            .evaluate(
+                &mut type_env,
+                &mut value_env,
+                input,
+                &mut VoidTracer,
+                StrictMode::Off,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn repl_evaluates_queries_against_cached_input() {
+        let mut loader = Loader::new();
+        let value = eval_servers(&mut loader);
+
+        let queries = ".servers.web + 10\n.servers.w\t\nnope\n:quit\n.servers\n";
+        let output = SharedBuf::default();
+        let mut tracer = VoidTracer;
+        let mut repl = Repl::new(
+            &mut loader,
+            &mut tracer,
+            StrictMode::Off,
+            value,
+            Box::new(Cursor::new(queries)),
+            Box::new(output.clone()),
+        );
+        assert_eq!(repl.complete("input.servers.d"), vec!["db".to_string()]);
+        repl.run(&EvalOptions::default(), &StyleOptions::default());
+
+        let output = String::from_utf8(output.0.take()).unwrap();
+        assert!(output.starts_with("> 11\n> web\nworker\n> "), "{output}");
+        assert!(output.contains("Unknown variable"), "{output}");
+        assert!(!output.contains("db"), "{output}");
+    }
+
+    #[cfg(feature = "line-editor")]
+    #[test]
+    fn key_completer_replaces_partial_key() {
+        use rustyline::completion::Completer;
+        use rustyline::history::DefaultHistory;
+
+        let mut loader = Loader::new();
+        let value = eval_servers(&mut loader);
+        let mut tracer = VoidTracer;
+        let repl = Repl::new(
+            &mut loader,
+            &mut tracer,
+            StrictMode::Off,
+            value,
+            Box::new(Cursor::new("")),
+            Box::new(std::io::sink()),
+        );
+        let completer = KeyCompleter(repl.session.clone());
+        let history = DefaultHistory::new();
+        let ctx = rustyline::Context::new(&history);
+        let complete = |line: &str| completer.complete(line, line.len(), &ctx).unwrap();
+
+        // The candidates replace the partial key, which starts after the dot.
+        assert_eq!(
+            complete(".servers.w"),
+            (9, vec!["web".to_string(), "worker".to_string()])
+        );
+        assert_eq!(complete("  input.servers.d"), (16, vec!["db".to_string()]));
+        assert_eq!(complete("input"), (5, Vec::new()));
+    }
+}
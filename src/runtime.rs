@@ -8,15 +8,18 @@
 //! Representations of values and scopes at runtime.
 
 use std::cmp::Ordering;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeSet;
 use std::rc::Rc;
 
 use crate::ast::{CallArg, Expr};
 use crate::error::{IntoError, PathElement, Result};
 use crate::eval::Evaluator;
-use crate::fmt_rcl::format_rcl;
+use crate::fmt_rcl::{format_rcl_limited, Limits};
 use crate::fmt_type::format_type;
+use crate::number::Float;
 use crate::pprint::{concat, indent, Doc};
+use crate::small_collection::{SmallDict, SmallList};
+use crate::small_str::SmallStr;
 use crate::source::Span;
 use crate::type_diff::{Mismatch, TypeDiff};
 use crate::type_source::Source;
@@ -83,6 +86,30 @@ pub struct MethodInstance {
     pub receiver: Value,
 }
 
+/// A function with its first argument bound, created by `std.partial`.
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct PartialApplication {
+    /// The span of the function argument to `std.partial`.
+    pub function_span: Span,
+    /// The function to call.
+    pub function: Value,
+    /// The span of the bound argument.
+    pub arg_span: Span,
+    /// The value of the bound argument, passed as the first argument.
+    pub arg: Value,
+    /// The type of the function, which excludes the bound argument.
+    pub type_: Rc<types::Function>,
+}
+
+/// A function whose results are cached, created by `std.memoize`.
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct MemoizedFunction {
+    /// The span of the function argument to `std.memoize`.
+    pub function_span: Span,
+    /// The function to call on a cache miss.
+    pub function: Value,
+}
+
 impl std::fmt::Debug for BuiltinFunction {
     // coverage:off -- Debug is needed for assert, not expected to be called.
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -156,27 +183,44 @@ pub enum Value {
     // TODO: Should be a bigint.
     Int(i64),
 
-    String(Rc<str>),
+    /// A finite float, distinct from `Int`, see [`Float`].
+    Float(Float),
+
+    String(SmallStr),
+
+    Bytes(Rc<[u8]>),
 
-    List(Rc<Vec<Value>>),
+    List(SmallList),
+
+    Tuple(Rc<[Value]>),
+
+    /// A value with a tag, e.g. `#tcp { port = 80 }`. The tag excludes the `#`.
+    Tagged(Rc<str>, Rc<Value>),
 
     // TODO: Should preserve insertion order.
     Set(Rc<BTreeSet<Value>>),
 
     // TODO: Should preserve insertion order.
-    Dict(Rc<BTreeMap<Value, Value>>),
+    Dict(SmallDict),
 
     Function(Rc<Function>),
 
     BuiltinFunction(&'static BuiltinFunction),
 
     BuiltinMethod(Rc<MethodInstance>),
+
+    Partial(Rc<PartialApplication>),
+
+    Memoized(Rc<MemoizedFunction>),
+
+    /// An opaque secret, identified by its name, see [`crate::secret`].
+    Secret(Rc<str>),
 }
 
 impl Value {
     /// Extract the dict if it is one, panic otherwise.
     #[inline]
-    pub fn expect_dict(&self) -> &BTreeMap<Value, Value> {
+    pub fn expect_dict(&self) -> &SmallDict {
         match self {
             Value::Dict(inner) => inner,
             other => panic!("Expected Dict but got {other:?}."),
@@ -210,17 +254,66 @@ impl Value {
         }
     }
 
+    /// Extract the bytes if it is one, panic otherwise.
+    #[inline]
+    pub fn expect_bytes(&self) -> &[u8] {
+        match self {
+            Value::Bytes(inner) => inner.as_ref(),
+            other => panic!("Expected Bytes but got {other:?}."),
+        }
+    }
+
     /// As [`expect_string`], but make an owned `Rc` copy instead of borrowing.
     #[inline]
     pub fn expect_string_clone(&self) -> Rc<str> {
         match self {
-            Value::String(inner) => inner.clone(),
+            Value::String(inner) => inner.to_rc(),
             other => panic!("Expected String but got {other:?}."),
         }
     }
 
+    /// Return the name of the type of this value, e.g. `Int` or `List`.
+    ///
+    /// For collections this does not include the element type. All kinds of
+    /// functions (lambdas, builtins, and methods) are named `Function`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "Null",
+            Value::Bool(..) => "Bool",
+            Value::Int(..) => "Int",
+            Value::Float(..) => "Float",
+            Value::String(..) => "String",
+            Value::Bytes(..) => "Bytes",
+            Value::List(..) => "List",
+            Value::Tuple(..) => "Tuple",
+            Value::Tagged(..) => "Tagged",
+            Value::Set(..) => "Set",
+            Value::Dict(..) => "Dict",
+            Value::Function(..)
+            | Value::BuiltinFunction(..)
+            | Value::BuiltinMethod(..)
+            | Value::Partial(..)
+            | Value::Memoized(..) => "Function",
+            Value::Secret(..) => "Secret",
+        }
+    }
+
+    /// Return the type of the function, if the value is a function.
+    pub fn function_type(&self) -> Option<Rc<types::Function>> {
+        match self {
+            Value::Function(f) => Some(f.type_.clone()),
+            Value::BuiltinFunction(f) => Some(Rc::new((f.type_)())),
+            Value::BuiltinMethod(m) => Some(Rc::new((m.method.type_)())),
+            Value::Partial(p) => Some(p.type_.clone()),
+            Value::Memoized(m) => m.function.function_type(),
+            _ => None,
+        }
+    }
+
     /// Dynamically check that the value fits the required type.
-    pub fn is_instance_of(&self, at: Span, type_: &SourcedType) -> Result<()> {
+    ///
+    /// On mismatch, the error includes the value, elided to `limits`.
+    pub fn is_instance_of(&self, at: Span, type_: &SourcedType, limits: Limits) -> Result<()> {
         let req_type = match &type_.type_ {
             Type::Any => return Ok(()),
             t => t,
@@ -230,19 +323,31 @@ impl Value {
             (Type::Null, Value::Null) => return Ok(()),
             (Type::Bool, Value::Bool(..)) => return Ok(()),
             (Type::Int, Value::Int(..)) => return Ok(()),
+            (Type::Float, Value::Float(..)) => return Ok(()),
             (Type::String, Value::String(..)) => return Ok(()),
+            (Type::Bytes, Value::Bytes(..)) => return Ok(()),
 
             // For compound types, we descend into them to check.
             (Type::List(elem_type), Value::List(elems)) => {
                 for (i, elem) in elems.iter().enumerate() {
-                    elem.is_instance_of(at, elem_type)
+                    elem.is_instance_of(at, elem_type, limits)
+                        .map_err(|err| err.with_path_element(PathElement::Index(i)))?;
+                }
+                return Ok(());
+            }
+            (Type::Tuple(elem_types), Value::Tuple(elems)) if elem_types.len() == elems.len() => {
+                for (i, (elem, elem_type)) in elems.iter().zip(elem_types.iter()).enumerate() {
+                    elem.is_instance_of(at, elem_type, limits)
                         .map_err(|err| err.with_path_element(PathElement::Index(i)))?;
                 }
                 return Ok(());
             }
+            (Type::Tagged(tagged), Value::Tagged(tag, value)) if tagged.tag == *tag => {
+                return value.is_instance_of(at, &tagged.value, limits);
+            }
             (Type::Set(elem_type), Value::Set(elems)) => {
                 for (i, elem) in elems.iter().enumerate() {
-                    elem.is_instance_of(at, elem_type).map_err(|err|
+                    elem.is_instance_of(at, elem_type, limits).map_err(|err|
                         // Even though sets don't strictly have indexes,
                         // they do have an order, so report the index to
                         // clarify that this is a nested error.
@@ -252,9 +357,9 @@ impl Value {
             }
             (Type::Dict(dict), Value::Dict(kvs)) => {
                 for (k, v) in kvs.iter() {
-                    k.is_instance_of(at, &dict.key)
+                    k.is_instance_of(at, &dict.key, limits)
                         .map_err(|err| err.with_path_element(PathElement::Key(k.clone())))?;
-                    v.is_instance_of(at, &dict.value)
+                    v.is_instance_of(at, &dict.value, limits)
                         .map_err(|err| err.with_path_element(PathElement::Key(k.clone())))?;
                 }
                 return Ok(());
@@ -264,7 +369,7 @@ impl Value {
                 // For a union, if it's an instance of any member, then it's
                 // okay, if not, we fall through to the generic error at the end.
                 for member in types.members.iter() {
-                    match value.is_instance_of(at, member) {
+                    match value.is_instance_of(at, member, limits) {
                         Ok(()) => return Ok(()),
                         Err(..) => continue,
                     }
@@ -304,7 +409,7 @@ impl Value {
             Doc::HardBreak Doc::HardBreak
             "But got this value:"
             Doc::HardBreak Doc::HardBreak
-            indent! { format_rcl(self).into_owned() }
+            indent! { format_rcl_limited(self, limits).into_owned() }
         });
         type_.explain_error(Side::Expected, &mut error);
         error.err()
@@ -321,10 +426,11 @@ impl<'a> From<&'a str> for Value {
 /// An environment binds names to values.
 pub type Env = crate::env::Env<Value>;
 
-/// Create a new environment with an initialized standard library.
+/// Create a new environment with a standard library with default capabilities.
 pub fn prelude() -> Env {
     let mut env = Env::new();
-    env.push("std".into(), crate::stdlib::initialize());
+    let capabilities = crate::stdlib::Capabilities::default();
+    env.push("std".into(), crate::stdlib::initialize(&capabilities));
     env
 }
 
@@ -0,0 +1,224 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Secrets, opaque values that only get their contents when exported.
+//!
+//! A secret created with `std.secret` holds only its name. Evaluation never
+//! sees the secret contents, so they cannot leak into traces, error messages,
+//! or computed values. Only when a value gets exported, we replace every secret
+//! with either a placeholder, or its contents from the configured source.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::pprint::{concat, Doc};
+use crate::runtime::Value;
+use crate::small_collection::{SmallDict, SmallList};
+use crate::source::Span;
+
+/// The placeholder that secrets export as when they are not resolved.
+pub const DEFAULT_PLACEHOLDER: &str = "${SECRET:{name}}";
+
+/// Where to get the contents of secrets from when exporting a value.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum SecretSource {
+    /// Do not resolve secrets, export them as a placeholder instead.
+    #[default]
+    Placeholder,
+
+    /// Read secret `name` from the environment variable `name`.
+    Env,
+
+    /// Read secret `name` from the file `name` in the given directory.
+    Dir(String),
+}
+
+impl FromStr for SecretSource {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s {
+            "placeholder" => Ok(SecretSource::Placeholder),
+            "env" => Ok(SecretSource::Env),
+            _ => match s.strip_prefix("file:") {
+                Some(dir) if !dir.is_empty() => Ok(SecretSource::Dir(dir.to_string())),
+                _ => Err(()),
+            },
+        }
+    }
+}
+
+/// How to export secrets.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SecretOptions {
+    /// Where to get the contents of secrets from.
+    pub source: SecretSource,
+
+    /// The placeholder for unresolved secrets, `{name}` expands to the name.
+    pub placeholder: String,
+}
+
+impl Default for SecretOptions {
+    fn default() -> Self {
+        SecretOptions {
+            source: SecretSource::default(),
+            placeholder: DEFAULT_PLACEHOLDER.to_string(),
+        }
+    }
+}
+
+/// Return whether `name` is a valid secret name.
+///
+/// Names are used as environment variable names and file names, so we only
+/// allow a conservative set of characters.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '-' | '.'))
+}
+
+/// Replace every secret in the value with a string, as configured.
+///
+/// The span is the span of the value, errors about missing secrets are
+/// reported there.
+pub fn resolve_secrets(value_span: Span, value: &Value, opts: &SecretOptions) -> Result<Value> {
+    let resolved = Resolver { value_span, opts }.value(value)?;
+    Ok(resolved.unwrap_or_else(|| value.clone()))
+}
+
+struct Resolver<'a> {
+    value_span: Span,
+    opts: &'a SecretOptions,
+}
+
+impl<'a> Resolver<'a> {
+    /// Resolve the secrets in `v`, return `None` if `v` contains no secrets.
+    fn value(&self, v: &Value) -> Result<Option<Value>> {
+        let result = match v {
+            Value::Secret(name) => Value::String(self.secret(name)?.into()),
+            Value::List(vs) => match self.values(vs.iter())? {
+                Some(vs) => Value::List(SmallList::from(vs)),
+                None => return Ok(None),
+            },
+            Value::Tuple(vs) => match self.values(vs.iter())? {
+                Some(vs) => Value::Tuple(vs.into()),
+                None => return Ok(None),
+            },
+            Value::Set(vs) => match self.values(vs.iter())? {
+                Some(vs) => Value::Set(Rc::new(vs.into_iter().collect::<BTreeSet<_>>())),
+                None => return Ok(None),
+            },
+            Value::Tagged(tag, payload) => match self.value(payload)? {
+                Some(payload) => Value::Tagged(tag.clone(), Rc::new(payload)),
+                None => return Ok(None),
+            },
+            Value::Dict(kvs) => {
+                let keys = self.values(kvs.keys())?;
+                let values = self.values(kvs.values())?;
+                if keys.is_none() && values.is_none() {
+                    return Ok(None);
+                }
+                let keys = keys.unwrap_or_else(|| kvs.keys().cloned().collect());
+                let values = values.unwrap_or_else(|| kvs.values().cloned().collect());
+                let result: BTreeMap<Value, Value> = keys.into_iter().zip(values).collect();
+                Value::Dict(SmallDict::from(result))
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(result))
+    }
+
+    /// Resolve the secrets in all values, return `None` if there are none.
+    fn values<'v, I: Iterator<Item = &'v Value>>(&self, vs: I) -> Result<Option<Vec<Value>>> {
+        let mut result = Vec::new();
+        let mut any_secret = false;
+        for v in vs {
+            match self.value(v)? {
+                Some(resolved) => {
+                    any_secret = true;
+                    result.push(resolved);
+                }
+                None => result.push(v.clone()),
+            }
+        }
+        Ok(if any_secret { Some(result) } else { None })
+    }
+
+    /// Return the string that the secret with the given name exports as.
+    fn secret(&self, name: &str) -> Result<String> {
+        match &self.opts.source {
+            SecretSource::Placeholder => Ok(self.opts.placeholder.replace("{name}", name)),
+            SecretSource::Env => match std::env::var(name) {
+                Ok(contents) => Ok(contents),
+                Err(..) => Error::new(concat! {
+                    "Secret '" Doc::highlight(name).into_owned() "' is not set."
+                })
+                .with_origin(self.value_span)
+                .with_help(concat! {
+                    "Set the environment variable '" Doc::highlight(name).into_owned() "'."
+                })
+                .err(),
+            },
+            SecretSource::Dir(dir) => {
+                let path = Path::new(dir).join(name);
+                match std::fs::read_to_string(&path) {
+                    // Files usually end in a newline, but the secret itself
+                    // does not include it.
+                    Ok(contents) => Ok(contents
+                        .strip_suffix('\n')
+                        .map(|s| s.strip_suffix('\r').unwrap_or(s))
+                        .unwrap_or(&contents)
+                        .to_string()),
+                    Err(err) => Error::new(concat! {
+                        "Failed to read secret '" Doc::highlight(name).into_owned() "': "
+                        err.to_string()
+                        "."
+                    })
+                    .with_origin(self.value_span)
+                    .with_help(concat! {
+                        "Expected a file at '"
+                        Doc::highlight(&path.to_string_lossy()).into_owned()
+                        "'."
+                    })
+                    .err(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_valid_name, SecretSource};
+    use std::str::FromStr;
+
+    #[test]
+    fn secret_source_from_str() {
+        assert_eq!(SecretSource::from_str("env"), Ok(SecretSource::Env));
+        assert_eq!(
+            SecretSource::from_str("file:/run/secrets"),
+            Ok(SecretSource::Dir("/run/secrets".into()))
+        );
+        assert_eq!(SecretSource::from_str("file:"), Err(()));
+        assert_eq!(SecretSource::from_str("vault"), Err(()));
+    }
+
+    #[test]
+    fn is_valid_name_rejects_paths() {
+        assert!(is_valid_name("db_password"));
+        assert!(is_valid_name("tls-key.pem"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name(".."));
+        assert!(!is_valid_name("../etc/passwd"));
+        assert!(!is_valid_name("a/b"));
+    }
+}
@@ -0,0 +1,411 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Parsing and comparison of semantic versions, for `std.semver`.
+//!
+//! Versions follow [Semantic Versioning 2.0.0](https://semver.org/). Ranges
+//! use the syntax of npm and Cargo: comparators such as `>=1.2.3`, `~1.2`, or
+//! `^0.4` that all need to hold, separated by commas or spaces, and
+//! alternatives separated by `||`.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// One dot-separated identifier in the pre-release part of a version.
+///
+/// The order of the variants matters: numeric identifiers have lower
+/// precedence than alphanumeric ones.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Identifier {
+    Numeric(u64),
+    Alphanumeric(String),
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Identifier::Numeric(n) => write!(f, "{n}"),
+            Identifier::Alphanumeric(s) => f.write_str(s),
+        }
+    }
+}
+
+/// A parsed semantic version.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub pre: Vec<Identifier>,
+    /// Build metadata, without the leading `+`. It does not affect precedence.
+    pub build: String,
+}
+
+impl Version {
+    fn new(major: u64, minor: u64, patch: u64) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+            pre: Vec::new(),
+            build: String::new(),
+        }
+    }
+
+    /// Return the pre-release identifiers joined with dots.
+    pub fn pre_string(&self) -> String {
+        let parts: Vec<String> = self.pre.iter().map(|id| id.to_string()).collect();
+        parts.join(".")
+    }
+
+    /// Compare by precedence, as defined by the semver specification.
+    pub fn cmp_precedence(&self, other: &Version) -> Ordering {
+        let core = (self.major, self.minor, self.patch);
+        let other_core = (other.major, other.minor, other.patch);
+        core.cmp(&other_core)
+            .then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+                (true, true) => Ordering::Equal,
+                // A pre-release has lower precedence than the release itself.
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => self.pre.cmp(&other.pre),
+            })
+    }
+}
+
+/// Parse a numeric version component. Like RCL integers, it must fit an `i64`.
+fn parse_number(s: &str) -> Option<u64> {
+    let is_digits = !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let has_leading_zero = s.len() > 1 && s.starts_with('0');
+    if !is_digits || has_leading_zero {
+        return None;
+    }
+    s.parse::<i64>().ok().map(|n| n as u64)
+}
+
+fn is_identifier(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+fn parse_pre(s: &str) -> Option<Vec<Identifier>> {
+    s.split('.')
+        .map(|part| {
+            if !is_identifier(part) {
+                None
+            } else if part.bytes().all(|b| b.is_ascii_digit()) {
+                parse_number(part).map(Identifier::Numeric)
+            } else {
+                Some(Identifier::Alphanumeric(part.to_string()))
+            }
+        })
+        .collect()
+}
+
+fn invalid_version(version: &str) -> String {
+    format!(
+        "Invalid version '{version}'. Expected a semantic version such as \
+        '1.2.3' or '1.2.3-rc.1'."
+    )
+}
+
+/// Parse a version such as `1.2.3`, `1.2.3-rc.1`, or `1.2.3+build.5`.
+pub fn parse_version(version: &str) -> Result<Version, String> {
+    let invalid = || invalid_version(version);
+    let (rest, build) = match version.split_once('+') {
+        Some((rest, build)) if build.split('.').all(is_identifier) => (rest, build),
+        Some(..) => return Err(invalid()),
+        None => (version, ""),
+    };
+    let (core, pre) = match rest.split_once('-') {
+        Some((core, pre)) => (core, parse_pre(pre).ok_or_else(invalid)?),
+        None => (rest, Vec::new()),
+    };
+    let mut parts = core.split('.').map(parse_number);
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => Ok(Version {
+            major,
+            minor,
+            patch,
+            pre,
+            build: build.to_string(),
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Op {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// A primitive comparison that a version must satisfy.
+#[derive(Clone, Debug)]
+struct Comparator {
+    op: Op,
+    version: Version,
+}
+
+impl Comparator {
+    fn matches(&self, version: &Version) -> bool {
+        let ord = version.cmp_precedence(&self.version);
+        match self.op {
+            Op::Eq => ord == Ordering::Equal,
+            Op::Gt => ord == Ordering::Greater,
+            Op::Ge => ord != Ordering::Less,
+            Op::Lt => ord == Ordering::Less,
+            Op::Le => ord != Ordering::Greater,
+        }
+    }
+}
+
+/// A version range: a version satisfies it if it satisfies all comparators of
+/// at least one of the alternatives.
+#[derive(Clone, Debug)]
+pub struct Range {
+    alternatives: Vec<Vec<Comparator>>,
+}
+
+/// A version where trailing components may be missing or wildcards, as used
+/// in ranges, e.g. `1.2` or `1.x`.
+struct Partial {
+    major: Option<u64>,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre: Vec<Identifier>,
+}
+
+fn parse_partial(s: &str) -> Option<Partial> {
+    let (core, pre) = match s.split_once('-') {
+        Some((core, pre)) => (core, parse_pre(pre)?),
+        None => (s, Vec::new()),
+    };
+    let mut components = Vec::new();
+    for part in core.split('.') {
+        match part {
+            "x" | "X" | "*" => components.push(None),
+            _ => components.push(Some(parse_number(part)?)),
+        }
+    }
+    // After the first wildcard, all components must be wildcards too.
+    let n_exact = components.iter().take_while(|c| c.is_some()).count();
+    if components.len() > 3 || components[n_exact..].iter().any(|c| c.is_some()) {
+        return None;
+    }
+    // A pre-release is only meaningful on a full version.
+    if !pre.is_empty() && n_exact < 3 {
+        return None;
+    }
+    let get = |i: usize| components.get(i).copied().flatten();
+    Some(Partial {
+        major: get(0),
+        minor: get(1),
+        patch: get(2),
+        pre,
+    })
+}
+
+/// Desugar a comparator as written in a range into primitive comparators.
+fn desugar(op: &str, partial: Partial, out: &mut Vec<Comparator>) {
+    let major = match partial.major {
+        Some(major) => major,
+        // A wildcard major version matches everything.
+        None => return,
+    };
+    let lower = Version {
+        pre: partial.pre,
+        ..Version::new(
+            major,
+            partial.minor.unwrap_or(0),
+            partial.patch.unwrap_or(0),
+        )
+    };
+    // For a partial version, the first version that is no longer covered by it.
+    let next = match (partial.minor, partial.patch) {
+        (None, _) => Some(Version::new(major + 1, 0, 0)),
+        (Some(minor), None) => Some(Version::new(major, minor + 1, 0)),
+        (Some(_), Some(_)) => None,
+    };
+    let mut push = |op: Op, version: Version| out.push(Comparator { op, version });
+    match (op, next) {
+        ("" | "=", None) => push(Op::Eq, lower),
+        ("" | "=", Some(next)) => {
+            push(Op::Ge, lower);
+            push(Op::Lt, next);
+        }
+        (">", None) => push(Op::Gt, lower),
+        (">", Some(next)) => push(Op::Ge, next),
+        (">=", _) => push(Op::Ge, lower),
+        ("<", _) => push(Op::Lt, lower),
+        ("<=", None) => push(Op::Le, lower),
+        ("<=", Some(next)) => push(Op::Lt, next),
+        ("~", _) => {
+            let upper = match partial.minor {
+                None => Version::new(major + 1, 0, 0),
+                Some(minor) => Version::new(major, minor + 1, 0),
+            };
+            push(Op::Ge, lower);
+            push(Op::Lt, upper);
+        }
+        ("^", _) => {
+            // The upper bound increments the first nonzero component.
+            let upper = match (major, partial.minor, partial.patch) {
+                (0, Some(0), Some(patch)) => Version::new(0, 0, patch + 1),
+                (0, Some(minor), _) => Version::new(0, minor + 1, 0),
+                _ => Version::new(major + 1, 0, 0),
+            };
+            push(Op::Ge, lower);
+            push(Op::Lt, upper);
+        }
+        _ => unreachable!("Operators are validated by the caller."),
+    }
+}
+
+/// Parse a range such as `>=1.2.0, <2.0.0` or `^1.4 || ~2.0.1`.
+pub fn parse_range(range: &str) -> Result<Range, String> {
+    let invalid = |detail: String| format!("Invalid version range '{range}'. {detail}");
+    let mut alternatives = Vec::new();
+
+    for alternative in range.split("||") {
+        let mut comparators = Vec::new();
+        // Operators may be separated from their version by spaces, so we join
+        // a token that consists of only an operator with the next one.
+        let mut tokens = alternative
+            .split(|ch: char| ch == ',' || ch.is_whitespace())
+            .filter(|t| !t.is_empty());
+        let mut n_written = 0;
+
+        while let Some(token) = tokens.next() {
+            let op_len = token
+                .find(|ch: char| !matches!(ch, '=' | '<' | '>' | '~' | '^'))
+                .unwrap_or(token.len());
+            let (op, version) = match token.split_at(op_len) {
+                (op, "") => (op, tokens.next().unwrap_or("")),
+                split => split,
+            };
+            if !matches!(op, "" | "=" | ">" | ">=" | "<" | "<=" | "~" | "^") {
+                return Err(invalid(format!(
+                    "Unknown operator '{op}', expected one of \
+                    '=', '>', '>=', '<', '<=', '~', or '^'."
+                )));
+            }
+            let partial = parse_partial(version).ok_or_else(|| {
+                invalid(format!(
+                    "Expected a version such as '1.2.3', '1.2', or '1.x', \
+                    but found '{version}'."
+                ))
+            })?;
+            desugar(op, partial, &mut comparators);
+            n_written += 1;
+        }
+
+        if n_written == 0 {
+            return Err(invalid(
+                "Every alternative must contain at least one comparator, \
+                use '*' to match any version."
+                    .to_string(),
+            ));
+        }
+        alternatives.push(comparators);
+    }
+
+    Ok(Range { alternatives })
+}
+
+impl Range {
+    /// Return whether `version` lies in this range.
+    ///
+    /// Like npm and Cargo, a pre-release version only satisfies an alternative
+    /// when one of its comparators has a pre-release of the same
+    /// `major.minor.patch`. This prevents e.g. `^1.2` from matching `2.0.0-rc.1`.
+    pub fn contains(&self, version: &Version) -> bool {
+        self.alternatives.iter().any(|comparators| {
+            let allows_pre = version.pre.is_empty()
+                || comparators.iter().any(|c| {
+                    !c.version.pre.is_empty()
+                        && (c.version.major, c.version.minor, c.version.patch)
+                            == (version.major, version.minor, version.patch)
+                });
+            allows_pre && comparators.iter().all(|c| c.matches(version))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_range, parse_version};
+    use std::cmp::Ordering;
+
+    #[test]
+    fn parse_version_is_strict() {
+        assert!(parse_version("1.2.3").is_ok());
+        assert!(parse_version("1.2.3-rc.1+build.5").is_ok());
+        assert!(parse_version("1.2").is_err());
+        assert!(parse_version("v1.2.3").is_err());
+        assert!(parse_version("01.2.3").is_err());
+        assert!(parse_version("1.2.3-rc.01").is_err());
+        assert!(parse_version("1.2.3-").is_err());
+        assert!(parse_version("1.2.3+").is_err());
+        assert!(parse_version("1.2.99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn precedence_follows_spec() {
+        // This is the example order from the semver specification.
+        let versions = [
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+            "1.0.1+build",
+        ];
+        for pair in versions.windows(2) {
+            let a = parse_version(pair[0]).unwrap();
+            let b = parse_version(pair[1]).unwrap();
+            assert_eq!(a.cmp_precedence(&b), Ordering::Less, "{pair:?}");
+        }
+        let a = parse_version("1.0.0+a").unwrap();
+        let b = parse_version("1.0.0+b").unwrap();
+        assert_eq!(a.cmp_precedence(&b), Ordering::Equal);
+    }
+
+    #[test]
+    fn range_contains() {
+        let check = |range: &str, version: &str| -> bool {
+            let range = parse_range(range).unwrap();
+            range.contains(&parse_version(version).unwrap())
+        };
+        assert!(check("^1.2.3", "1.9.0"));
+        assert!(!check("^1.2.3", "2.0.0"));
+        assert!(!check("^0.2.3", "0.3.0"));
+        assert!(!check("^0.0.3", "0.0.4"));
+        assert!(check("~1.2", "1.2.9"));
+        assert!(!check("~1.2", "1.3.0"));
+        assert!(check("1.x", "1.7.0"));
+        assert!(check("*", "0.0.1"));
+        assert!(check(">= 1.2, < 1.4", "1.3.5"));
+        assert!(!check(">1.2", "1.2.7"));
+        assert!(check("<=1.2", "1.2.7"));
+        assert!(check("<1.0 || >=2.0", "2.1.0"));
+        assert!(!check("<1.0 || >=2.0", "1.5.0"));
+        assert!(!check("^1.2", "2.0.0-rc.1"));
+        assert!(!check(">=1.2.0", "1.3.0-rc.1"));
+        assert!(check(">=1.3.0-rc.1", "1.3.0-rc.2"));
+        assert!(parse_range("").is_err());
+        assert!(parse_range("1.2 ||").is_err());
+        assert!(parse_range("=>1.2").is_err());
+        assert!(parse_range("1.x.3").is_err());
+        assert!(parse_range("1.2-rc.1").is_err());
+    }
+}
@@ -0,0 +1,479 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Immutable lists and dicts that store few elements inline.
+//!
+//! Like strings, most collections in configuration are small: a list of two
+//! tags, a dict with a name and a port. A `Vec` or `BTreeMap` behind an `Rc`
+//! costs two allocations for those, one for the reference counts and one for
+//! the elements. [`SmallList`] and [`SmallDict`] store up to
+//! [`MAX_INLINE_LEN`] elements next to the reference counts, in a single
+//! allocation, and switch to a `Vec` or `BTreeMap` when they grow beyond it.
+//!
+//! Unlike [`SmallStr`](crate::small_str::SmallStr), the elements cannot be
+//! stored inside the [`Value`] itself: a single element is already as large as
+//! a value. Both types are one pointer, so values do not get larger.
+
+use std::cmp::Ordering;
+use std::collections::{btree_map, BTreeMap};
+use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
+
+use crate::runtime::Value;
+
+/// Collections up to this number of elements are stored inline.
+pub const MAX_INLINE_LEN: usize = 4;
+
+/// The unused slots of an inline collection hold null, which needs no drop.
+const EMPTY: Value = Value::Null;
+const EMPTY_ENTRY: (Value, Value) = (Value::Null, Value::Null);
+
+#[derive(Clone)]
+enum ListRepr {
+    /// Invariant: only `elements[..len]` are part of the list.
+    Inline {
+        len: u8,
+        elements: [Value; MAX_INLINE_LEN],
+    },
+    Heap(Vec<Value>),
+}
+
+/// An immutable list that is cheap to clone.
+#[derive(Clone)]
+pub struct SmallList(Rc<ListRepr>);
+
+impl SmallList {
+    pub fn new() -> SmallList {
+        SmallList(Rc::new(ListRepr::Inline {
+            len: 0,
+            elements: [EMPTY; MAX_INLINE_LEN],
+        }))
+    }
+
+    pub fn as_slice(&self) -> &[Value] {
+        match self.0.as_ref() {
+            ListRepr::Inline { len, elements } => &elements[..*len as usize],
+            ListRepr::Heap(elements) => elements,
+        }
+    }
+
+    /// Return whether the elements are stored inline, rather than in a `Vec`.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0.as_ref(), ListRepr::Inline { .. })
+    }
+
+    /// Append an element, copying the list first if it is shared.
+    pub fn push(&mut self, value: Value) {
+        let repr = Rc::make_mut(&mut self.0);
+        match repr {
+            ListRepr::Inline { len, elements } if (*len as usize) < MAX_INLINE_LEN => {
+                elements[*len as usize] = value;
+                *len += 1;
+            }
+            ListRepr::Inline { elements, .. } => {
+                let mut heap = Vec::with_capacity(MAX_INLINE_LEN * 2);
+                heap.extend(elements.iter_mut().map(|v| std::mem::replace(v, EMPTY)));
+                heap.push(value);
+                // Reuse the allocation that held the inline elements.
+                *repr = ListRepr::Heap(heap);
+            }
+            ListRepr::Heap(elements) => elements.push(value),
+        }
+    }
+
+    /// Return the address of the allocation, which identifies shared lists.
+    pub fn as_ptr(&self) -> *const () {
+        Rc::as_ptr(&self.0) as *const ()
+    }
+
+    /// Return whether other values hold a reference to this list too.
+    pub fn is_shared(&self) -> bool {
+        Rc::strong_count(&self.0) > 1
+    }
+}
+
+impl Default for SmallList {
+    fn default() -> SmallList {
+        SmallList::new()
+    }
+}
+
+impl Deref for SmallList {
+    type Target = [Value];
+
+    #[inline]
+    fn deref(&self) -> &[Value] {
+        self.as_slice()
+    }
+}
+
+impl AsRef<[Value]> for SmallList {
+    fn as_ref(&self) -> &[Value] {
+        self.as_slice()
+    }
+}
+
+impl From<Vec<Value>> for SmallList {
+    fn from(elements: Vec<Value>) -> SmallList {
+        if elements.len() > MAX_INLINE_LEN {
+            // The elements are already on the heap, we can keep them there.
+            return SmallList(Rc::new(ListRepr::Heap(elements)));
+        }
+        elements.into_iter().collect()
+    }
+}
+
+impl FromIterator<Value> for SmallList {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> SmallList {
+        let mut result = SmallList::new();
+        for value in iter {
+            result.push(value);
+        }
+        result
+    }
+}
+
+impl<'a> IntoIterator for &'a SmallList {
+    type Item = &'a Value;
+    type IntoIter = std::slice::Iter<'a, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_slice().iter()
+    }
+}
+
+impl PartialEq for SmallList {
+    fn eq(&self, other: &SmallList) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for SmallList {}
+
+impl PartialOrd for SmallList {
+    fn partial_cmp(&self, other: &SmallList) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SmallList {
+    fn cmp(&self, other: &SmallList) -> Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+impl fmt::Debug for SmallList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_slice(), f)
+    }
+}
+
+#[derive(Clone)]
+enum DictRepr {
+    /// Invariant: `entries[..len]` are sorted by key, and the keys are unique.
+    Inline {
+        len: u8,
+        entries: [(Value, Value); MAX_INLINE_LEN],
+    },
+    Heap(BTreeMap<Value, Value>),
+}
+
+/// An immutable dict, ordered by key, that is cheap to clone.
+#[derive(Clone)]
+pub struct SmallDict(Rc<DictRepr>);
+
+impl SmallDict {
+    pub fn new() -> SmallDict {
+        SmallDict(Rc::new(DictRepr::Inline {
+            len: 0,
+            entries: [EMPTY_ENTRY; MAX_INLINE_LEN],
+        }))
+    }
+
+    /// Return whether the entries are stored inline, rather than in a `BTreeMap`.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0.as_ref(), DictRepr::Inline { .. })
+    }
+
+    pub fn len(&self) -> usize {
+        match self.0.as_ref() {
+            DictRepr::Inline { len, .. } => *len as usize,
+            DictRepr::Heap(entries) => entries.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        match self.0.as_ref() {
+            DictRepr::Inline { len, entries } => {
+                let entries = &entries[..*len as usize];
+                match entries.binary_search_by(|(k, _)| k.cmp(key)) {
+                    Ok(i) => Some(&entries[i].1),
+                    Err(..) => None,
+                }
+            }
+            DictRepr::Heap(entries) => entries.get(key),
+        }
+    }
+
+    pub fn contains_key(&self, key: &Value) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Insert an entry, copying the dict first if it is shared.
+    ///
+    /// Like for `BTreeMap`, a new value replaces the value of an existing key.
+    pub fn insert(&mut self, key: Value, value: Value) {
+        let repr = Rc::make_mut(&mut self.0);
+        match repr {
+            DictRepr::Inline { len, entries } => {
+                let n = *len as usize;
+                let i = match entries[..n].binary_search_by(|(k, _)| k.cmp(&key)) {
+                    Ok(i) => {
+                        entries[i].1 = value;
+                        return;
+                    }
+                    Err(i) => i,
+                };
+                if n < MAX_INLINE_LEN {
+                    entries[i..n + 1].rotate_right(1);
+                    entries[i] = (key, value);
+                    *len += 1;
+                    return;
+                }
+                // Insert one by one, collecting would buffer the entries in a `Vec`.
+                let mut heap = BTreeMap::new();
+                for kv in entries.iter_mut() {
+                    let (k, v) = std::mem::replace(kv, EMPTY_ENTRY);
+                    heap.insert(k, v);
+                }
+                heap.insert(key, value);
+                *repr = DictRepr::Heap(heap);
+            }
+            DictRepr::Heap(entries) => {
+                entries.insert(key, value);
+            }
+        }
+    }
+
+    /// Remove an entry if it exists, copying the dict first if it is shared.
+    pub fn remove(&mut self, key: &Value) {
+        if !self.contains_key(key) {
+            return;
+        }
+        match Rc::make_mut(&mut self.0) {
+            DictRepr::Inline { len, entries } => {
+                let n = *len as usize;
+                if let Ok(i) = entries[..n].binary_search_by(|(k, _)| k.cmp(key)) {
+                    entries[i..n].rotate_left(1);
+                    entries[n - 1] = EMPTY_ENTRY;
+                    *len -= 1;
+                }
+            }
+            DictRepr::Heap(entries) => {
+                entries.remove(key);
+            }
+        }
+    }
+
+    /// Iterate the entries in ascending order of the keys.
+    pub fn iter(&self) -> DictIter<'_> {
+        match self.0.as_ref() {
+            DictRepr::Inline { len, entries } => DictIter::Inline(entries[..*len as usize].iter()),
+            DictRepr::Heap(entries) => DictIter::Heap(entries.iter()),
+        }
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &Value> + ExactSizeIterator {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &Value> + ExactSizeIterator {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// Return the address of the allocation, which identifies shared dicts.
+    pub fn as_ptr(&self) -> *const () {
+        Rc::as_ptr(&self.0) as *const ()
+    }
+
+    /// Return whether other values hold a reference to this dict too.
+    pub fn is_shared(&self) -> bool {
+        Rc::strong_count(&self.0) > 1
+    }
+}
+
+impl Default for SmallDict {
+    fn default() -> SmallDict {
+        SmallDict::new()
+    }
+}
+
+impl From<BTreeMap<Value, Value>> for SmallDict {
+    fn from(entries: BTreeMap<Value, Value>) -> SmallDict {
+        if entries.len() > MAX_INLINE_LEN {
+            // The entries are already in a map, we can keep them there.
+            return SmallDict(Rc::new(DictRepr::Heap(entries)));
+        }
+        entries.into_iter().collect()
+    }
+}
+
+impl FromIterator<(Value, Value)> for SmallDict {
+    fn from_iter<I: IntoIterator<Item = (Value, Value)>>(iter: I) -> SmallDict {
+        let mut result = SmallDict::new();
+        for (k, v) in iter {
+            result.insert(k, v);
+        }
+        result
+    }
+}
+
+/// An iterator over the entries of a [`SmallDict`].
+pub enum DictIter<'a> {
+    Inline(std::slice::Iter<'a, (Value, Value)>),
+    Heap(btree_map::Iter<'a, Value, Value>),
+}
+
+impl<'a> Iterator for DictIter<'a> {
+    type Item = (&'a Value, &'a Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            DictIter::Inline(iter) => iter.next().map(|(k, v)| (k, v)),
+            DictIter::Heap(iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            DictIter::Inline(iter) => iter.size_hint(),
+            DictIter::Heap(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for DictIter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            DictIter::Inline(iter) => iter.next_back().map(|(k, v)| (k, v)),
+            DictIter::Heap(iter) => iter.next_back(),
+        }
+    }
+}
+
+impl<'a> ExactSizeIterator for DictIter<'a> {}
+
+impl<'a> IntoIterator for &'a SmallDict {
+    type Item = (&'a Value, &'a Value);
+    type IntoIter = DictIter<'a>;
+
+    fn into_iter(self) -> DictIter<'a> {
+        self.iter()
+    }
+}
+
+impl PartialEq for SmallDict {
+    fn eq(&self, other: &SmallDict) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for SmallDict {}
+
+impl PartialOrd for SmallDict {
+    fn partial_cmp(&self, other: &SmallDict) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SmallDict {
+    /// Compare the entries lexicographically, like `BTreeMap` does.
+    fn cmp(&self, other: &SmallDict) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl fmt::Debug for SmallDict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SmallDict, SmallList, MAX_INLINE_LEN};
+    use crate::runtime::Value;
+
+    fn int(i: i64) -> Value {
+        Value::Int(i)
+    }
+
+    #[test]
+    fn small_list_moves_to_heap_when_it_grows() {
+        let mut xs = SmallList::new();
+        for i in 0..MAX_INLINE_LEN as i64 {
+            xs.push(int(i));
+            assert!(xs.is_inline());
+        }
+        let shared = xs.clone();
+        xs.push(int(9));
+        assert!(!xs.is_inline());
+        assert_eq!(&xs[..], &[int(0), int(1), int(2), int(3), int(9)]);
+        // The clone was not affected by the push.
+        assert_eq!(shared.len(), MAX_INLINE_LEN);
+        assert_eq!(SmallList::from(xs.to_vec()), xs);
+    }
+
+    #[test]
+    fn small_dict_keeps_keys_sorted_and_unique() {
+        let mut d = SmallDict::new();
+        for i in [3, 1, 2, 1] {
+            d.insert(int(i), int(i * 10));
+        }
+        assert!(d.is_inline());
+        assert_eq!(d.len(), 3);
+        assert_eq!(
+            d.keys().cloned().collect::<Vec<_>>(),
+            [int(1), int(2), int(3)]
+        );
+        assert_eq!(d.get(&int(2)), Some(&int(20)));
+        assert_eq!(d.get(&int(4)), None);
+
+        let inline = d.clone();
+        for i in [5, 0, 4] {
+            d.insert(int(i), int(i * 10));
+        }
+        assert!(!d.is_inline());
+        assert_eq!(d.len(), 6);
+        assert_eq!(d.iter().next_back(), Some((&int(5), &int(50))));
+        // Like for `BTreeMap`, dicts are ordered by their first differing entry.
+        assert!(d < inline);
+        assert_eq!(inline.len(), 3);
+
+        let mut removed = inline.clone();
+        removed.remove(&int(2));
+        removed.remove(&int(7));
+        assert_eq!(
+            removed.keys().cloned().collect::<Vec<_>>(),
+            [int(1), int(3)]
+        );
+        assert_eq!(inline.len(), 3);
+
+        // The representation does not affect equality.
+        let heap: SmallDict = d.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        assert_eq!(heap, d);
+        assert_eq!(
+            format!("{inline:?}"),
+            "{Int(1): Int(10), Int(2): Int(20), Int(3): Int(30)}"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! An immutable string that stores short contents inline.
+//!
+//! Most strings in configuration are short: keys, names, hostnames, enum-like
+//! values. Storing each one in its own reference-counted allocation is most of
+//! the allocation work of an evaluation. [`SmallStr`] stores strings of up to
+//! [`MAX_INLINE_LEN`] bytes inside the value itself, and longer strings behind
+//! an `Rc<str>` like before. It is no larger than the other variants of
+//! [`Value`](crate::runtime::Value), so it does not make values larger.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::Rc;
+
+/// Strings up to this length in bytes are stored inline.
+///
+/// This makes a [`SmallStr`] three pointers in size: the size of the largest
+/// variant of `Value`. On 64-bit platforms, that is 22 bytes.
+pub const MAX_INLINE_LEN: usize = 3 * std::mem::size_of::<usize>() - 2;
+
+#[derive(Clone)]
+enum Repr {
+    /// A string stored inline.
+    ///
+    /// Invariant: `data[..len]` is valid UTF-8. Only [`SmallStr::try_inline`]
+    /// constructs this variant, and it copies the bytes from a `str`.
+    Inline {
+        len: u8,
+        data: [u8; MAX_INLINE_LEN],
+    },
+    Heap(Rc<str>),
+}
+
+/// An immutable string that is cheap to clone.
+#[derive(Clone)]
+pub struct SmallStr(Repr);
+
+impl SmallStr {
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            // Validating is cheap for strings this short, and it keeps the
+            // crate free of unsafe code.
+            Repr::Inline { len, data } => std::str::from_utf8(&data[..*len as usize])
+                .expect("Inline strings are copied from a str."),
+            Repr::Heap(s) => s,
+        }
+    }
+
+    /// Return whether the string is stored inline, rather than on the heap.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline { .. })
+    }
+
+    /// Return the string as a reference-counted `str`.
+    ///
+    /// This allocates for inline strings, and is cheap for heap strings.
+    pub fn to_rc(&self) -> Rc<str> {
+        match &self.0 {
+            Repr::Inline { .. } => self.as_str().into(),
+            Repr::Heap(s) => s.clone(),
+        }
+    }
+
+    /// Build an inline string if it fits.
+    fn try_inline(s: &str) -> Option<SmallStr> {
+        if s.len() > MAX_INLINE_LEN {
+            return None;
+        }
+        let mut data = [0_u8; MAX_INLINE_LEN];
+        data[..s.len()].copy_from_slice(s.as_bytes());
+        let repr = Repr::Inline {
+            len: s.len() as u8,
+            data,
+        };
+        Some(SmallStr(repr))
+    }
+}
+
+impl Deref for SmallStr {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for SmallStr {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for SmallStr {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<'a> From<&'a str> for SmallStr {
+    fn from(s: &'a str) -> SmallStr {
+        SmallStr::try_inline(s).unwrap_or_else(|| SmallStr(Repr::Heap(s.into())))
+    }
+}
+
+impl From<String> for SmallStr {
+    fn from(s: String) -> SmallStr {
+        SmallStr::try_inline(&s).unwrap_or_else(|| SmallStr(Repr::Heap(s.into())))
+    }
+}
+
+impl<'a> From<&'a String> for SmallStr {
+    fn from(s: &'a String) -> SmallStr {
+        SmallStr::from(&s[..])
+    }
+}
+
+impl From<Rc<str>> for SmallStr {
+    fn from(s: Rc<str>) -> SmallStr {
+        // The string is already on the heap, we can share it rather than copy.
+        SmallStr(Repr::Heap(s))
+    }
+}
+
+impl<'a> From<&'a Rc<str>> for SmallStr {
+    fn from(s: &'a Rc<str>) -> SmallStr {
+        SmallStr(Repr::Heap(s.clone()))
+    }
+}
+
+impl PartialEq for SmallStr {
+    fn eq(&self, other: &SmallStr) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SmallStr {}
+
+impl PartialOrd for SmallStr {
+    fn partial_cmp(&self, other: &SmallStr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SmallStr {
+    fn cmp(&self, other: &SmallStr) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for SmallStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for SmallStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for SmallStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SmallStr, MAX_INLINE_LEN};
+    use std::rc::Rc;
+
+    #[test]
+    fn small_str_stores_short_strings_inline() {
+        let short = SmallStr::from("localhost");
+        assert!(short.is_inline());
+        assert_eq!(&*short, "localhost");
+
+        let max = "x".repeat(MAX_INLINE_LEN);
+        assert!(SmallStr::from(&max[..]).is_inline());
+        let long = SmallStr::from(format!("{max}y"));
+        assert!(!long.is_inline());
+        assert_eq!(long.len(), MAX_INLINE_LEN + 1);
+
+        // Non-ASCII strings are stored inline too, if the bytes fit.
+        let accents = SmallStr::from("café ☕");
+        assert!(accents.is_inline());
+        assert_eq!(&*accents, "café ☕");
+
+        // Strings that are already reference-counted are shared, not copied.
+        let rc: Rc<str> = "shared".into();
+        assert!(!SmallStr::from(rc).is_inline());
+    }
+
+    #[test]
+    fn small_str_compares_by_contents() {
+        let inline = SmallStr::from("abc");
+        let heap = SmallStr::from(Rc::<str>::from("abc"));
+        assert_eq!(inline, heap);
+        assert!(SmallStr::from("abd") > heap);
+        assert!(SmallStr::from("ab") < inline);
+        assert_eq!(format!("{inline:?}"), "\"abc\"");
+    }
+
+    #[test]
+    fn small_str_fits_in_value() {
+        use crate::runtime::Value;
+        // A small string is not larger than the name and pointer of a tagged
+        // value, so it does not make `Value` any larger.
+        let ptr_size = std::mem::size_of::<usize>();
+        assert_eq!(std::mem::size_of::<SmallStr>(), 3 * ptr_size);
+        assert_eq!(std::mem::size_of::<Value>(), 4 * ptr_size);
+    }
+}
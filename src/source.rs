@@ -19,6 +19,9 @@ pub struct Doc<'a> {
 
     /// The span of the main expression, if known.
     pub span: Span,
+
+    /// The number of columns between tab stops, for rendering diagnostics.
+    pub tab_width: u32,
 }
 
 /// A list of input documents.
@@ -117,6 +120,18 @@ impl Span {
         Span::new(self.doc(), self.start() + n_trim, self.end())
     }
 
+    /// Delete n bytes from the end of the span.
+    pub fn trim_end(&self, n: usize) -> Span {
+        let n_trim = self.len().min(n);
+        Span::new(self.doc(), self.start(), self.end() - n_trim)
+    }
+
+    /// Keep only the first n bytes of the span.
+    pub fn take(&self, n: usize) -> Span {
+        let n_keep = self.len().min(n);
+        Span::new(self.doc(), self.start(), self.start() + n_keep)
+    }
+
     /// Return a span that runs from self up to but not including `other`.
     pub fn until(&self, other: Span) -> Span {
         debug_assert_eq!(self.doc(), other.doc());
@@ -0,0 +1,493 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! Source maps that relate locations in the output to spans in the source.
+//!
+//! Values do not carry spans, so during evaluation we record, for every
+//! collection that a literal, comprehension, or union builds, the provenance
+//! of its elements: the expressions that contributed to them. Afterwards we
+//! walk the output value and look up the elements of every collection that we
+//! encounter.
+
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use crate::error::{highlight_span, Error, PathElement, Result};
+use crate::markup::Markup;
+use crate::pprint::{concat, Doc};
+use crate::runtime::Value;
+use crate::small_collection::SmallDict;
+use crate::source::{Inputs, Span};
+
+/// How an expression contributed to a value.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StepKind {
+    /// The expression that produced the value.
+    Defined,
+
+    /// A comprehension that yielded the value, once per iteration.
+    Comprehension,
+
+    /// A union `|` where the value replaced a value from the left-hand side.
+    Override,
+}
+
+/// One expression that contributed to a value.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Step {
+    pub kind: StepKind,
+    pub span: Span,
+}
+
+/// The expressions that contributed to a value, most significant first.
+///
+/// The first step is the expression that produced the value. When the value
+/// overrides a different value, the steps of that value follow the override.
+pub type Provenance = Vec<Step>;
+
+/// Where a collection literal yielded an element.
+#[derive(Copy, Clone, Debug)]
+pub struct YieldSite {
+    /// The span of the element, or of the value for key-value pairs.
+    pub span: Span,
+
+    /// The innermost comprehension that the element is in, if any.
+    pub comprehension: Option<Span>,
+}
+
+impl YieldSite {
+    pub fn provenance(&self) -> Provenance {
+        let mut result = vec![Step {
+            kind: StepKind::Defined,
+            span: self.span,
+        }];
+        if let Some(span) = self.comprehension {
+            result.push(Step {
+                kind: StepKind::Comprehension,
+                span,
+            });
+        }
+        result
+    }
+}
+
+/// The provenance of the elements of a collection.
+pub enum ElementSpans {
+    /// For lists, the provenance of every element, in order.
+    Seq(Vec<Provenance>),
+
+    /// For sets and dicts, the provenance of every element or value, by element or key.
+    Keyed(BTreeMap<Value, Provenance>),
+}
+
+/// Provenance of the elements of collections that were built during evaluation.
+#[derive(Default)]
+pub struct SourceMap {
+    /// Collections by the address of their allocation.
+    ///
+    /// We hold on to the value itself, so that its allocation cannot be reused
+    /// by a different collection while the map is alive.
+    collections: HashMap<usize, (Value, ElementSpans)>,
+}
+
+/// The spans of a value and of everything inside it, in the shape of the value.
+///
+/// Applications that embed RCL can use this to report problems with a value
+/// that only they can detect, such as an invalid port number, at the place in
+/// the source where the value was defined. See [`Loader::evaluate_spanned`].
+///
+/// [`Loader::evaluate_spanned`]: crate::loader::Loader::evaluate_spanned
+#[derive(Clone, Debug)]
+pub struct ValueSpans {
+    /// The expression that produced the value.
+    pub span: Span,
+
+    /// The spans of the elements, in the order in which the value iterates.
+    ///
+    /// For lists, tuples, and sets, these are the elements. For dicts, these
+    /// are the values, in the order of their keys. Other values have none.
+    pub elements: Vec<ValueSpans>,
+}
+
+impl ValueSpans {
+    /// Return the spans of the value at `path` inside `value`.
+    ///
+    /// `value` must be the value that these spans belong to. When the path
+    /// does not exist, this returns the spans of the deepest value that does,
+    /// so there is always a span to report an error at.
+    pub fn at<'a>(&'a self, value: &Value, path: &[PathElement]) -> &'a ValueSpans {
+        let (element, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return self,
+        };
+        let child = match (value, element) {
+            (Value::List(xs), PathElement::Index(i)) => xs.get(*i).map(|x| (*i, x)),
+            (Value::Tuple(xs), PathElement::Index(i)) => xs.get(*i).map(|x| (*i, x)),
+            (Value::Set(xs), PathElement::Key(k)) => xs.iter().position(|x| x == k).map(|i| (i, k)),
+            (Value::Dict(kvs), PathElement::Key(k)) => kvs
+                .iter()
+                .enumerate()
+                .find(|(_, (key, _))| *key == k)
+                .map(|(i, (_, v))| (i, v)),
+            _ => None,
+        };
+        match child.and_then(|(i, x)| self.elements.get(i).map(|spans| (spans, x))) {
+            Some((spans, x)) => spans.at(x, rest),
+            None => self,
+        }
+    }
+}
+
+/// Return the address of the allocation that backs a collection.
+fn collection_address(value: &Value) -> Option<usize> {
+    match value {
+        Value::List(xs) => Some(xs.as_ptr() as usize),
+        Value::Set(xs) => Some(Rc::as_ptr(xs) as *const u8 as usize),
+        Value::Dict(xs) => Some(xs.as_ptr() as usize),
+        _ => None,
+    }
+}
+
+/// Return the JSON pointer segment for a dict key, if it can be exported.
+pub fn key_segment(key: &Value) -> Option<String> {
+    match key {
+        Value::String(s) => Some(s.to_string()),
+        Value::Int(i) => Some(i.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        // Other keys can not be exported to json, so they have no pointer.
+        _ => None,
+    }
+}
+
+/// Append a segment to a JSON pointer, escaped as specified by RFC 6901.
+pub fn push_segment(pointer: &mut String, segment: &str) {
+    pointer.push('/');
+    pointer.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+}
+
+/// The element of a collection at one segment of a path, if it exists.
+fn get_child<'a>(value: &'a Value, segment: &str) -> Option<(usize, &'a Value, &'a Value)> {
+    let index = || segment.parse::<usize>().ok();
+    match value {
+        Value::List(xs) => index().and_then(|i| xs.get(i).map(|x| (i, x, x))),
+        Value::Tuple(xs) => index().and_then(|i| xs.get(i).map(|x| (i, x, x))),
+        Value::Set(xs) => index().and_then(|i| xs.iter().nth(i).map(|x| (i, x, x))),
+        Value::Dict(kvs) => kvs
+            .iter()
+            .enumerate()
+            .find(|(_, (k, _))| key_segment(k).as_deref() == Some(segment))
+            .map(|(i, (k, v))| (i, k, v)),
+        _ => None,
+    }
+}
+
+impl SourceMap {
+    /// Record the provenance of the elements of a newly built collection.
+    pub fn record(&mut self, collection: &Value, spans: ElementSpans) {
+        if let Some(address) = collection_address(collection) {
+            self.collections
+                .insert(address, (collection.clone(), spans));
+        }
+    }
+
+    /// Record the provenance of the elements of the union `lhs | rhs`.
+    ///
+    /// The spans are those of the operands, for elements whose provenance we
+    /// do not know otherwise.
+    pub fn record_union(
+        &mut self,
+        op_span: Span,
+        (lhs_span, lhs): (Span, &Value),
+        (rhs_span, rhs): (Span, &Value),
+        result: &Value,
+    ) {
+        let spans: BTreeMap<Value, Provenance> = match result {
+            Value::Dict(kvs) => kvs
+                .keys()
+                .map(|k| {
+                    let in_lhs = matches!(lhs, Value::Dict(xs) if xs.contains_key(k));
+                    let in_rhs = matches!(rhs, Value::Dict(ys) if ys.contains_key(k));
+                    let mut steps = Vec::new();
+                    if in_rhs {
+                        steps.extend(self.element_provenance(rhs_span, rhs, k));
+                    }
+                    if in_lhs && in_rhs {
+                        steps.push(Step {
+                            kind: StepKind::Override,
+                            span: op_span,
+                        });
+                    }
+                    if in_lhs {
+                        steps.extend(self.element_provenance(lhs_span, lhs, k));
+                    }
+                    (k.clone(), steps)
+                })
+                .collect(),
+            Value::Set(xs) => xs
+                .iter()
+                .map(|x| {
+                    let steps = match lhs {
+                        Value::Set(ys) if ys.contains(x) => {
+                            self.element_provenance(lhs_span, lhs, x)
+                        }
+                        _ => self.element_provenance(rhs_span, rhs, x),
+                    };
+                    (x.clone(), steps)
+                })
+                .collect(),
+            _ => return,
+        };
+        self.record(result, ElementSpans::Keyed(spans));
+    }
+
+    /// Return the provenance of an element of a set, or a value of a dict.
+    fn element_provenance(&self, span: Span, collection: &Value, key: &Value) -> Provenance {
+        let recorded = collection_address(collection)
+            .and_then(|address| self.collections.get(&address))
+            .and_then(|(_, spans)| match spans {
+                ElementSpans::Keyed(spans) => spans.get(key).cloned(),
+                ElementSpans::Seq(..) => None,
+            });
+        recorded.unwrap_or_else(|| {
+            vec![Step {
+                kind: StepKind::Defined,
+                span,
+            }]
+        })
+    }
+
+    /// Return the provenance of the element at `index` with key `key`.
+    ///
+    /// When we don't know the provenance of an element, for example because a
+    /// builtin function built the collection, it inherits the provenance of the
+    /// collection itself.
+    fn child_provenance(
+        &self,
+        parent: &Provenance,
+        collection: &Value,
+        index: usize,
+        key: &Value,
+    ) -> Provenance {
+        let recorded = collection_address(collection)
+            .and_then(|address| self.collections.get(&address))
+            .and_then(|(_, spans)| match spans {
+                ElementSpans::Seq(spans) => spans.get(index),
+                ElementSpans::Keyed(spans) => spans.get(key),
+            });
+        match recorded {
+            Some(steps) if !steps.is_empty() => steps.clone(),
+            _ => parent.clone(),
+        }
+    }
+
+    /// Return the span that produced every location in the value.
+    ///
+    /// Locations are JSON pointers relative to `value`.
+    pub fn locate(&self, value_span: Span, value: &Value) -> BTreeMap<String, Span> {
+        let mut result = BTreeMap::new();
+        let mut pointer = String::new();
+        let root = vec![Step {
+            kind: StepKind::Defined,
+            span: value_span,
+        }];
+        self.locate_inner(&mut result, &mut pointer, &root, value);
+        result
+    }
+
+    fn locate_inner(
+        &self,
+        result: &mut BTreeMap<String, Span>,
+        pointer: &mut String,
+        provenance: &Provenance,
+        value: &Value,
+    ) {
+        result.insert(pointer.clone(), provenance[0].span);
+        let len = pointer.len();
+        let mut visit = |i: usize, segment: &str, key: &Value, child: &Value| {
+            let child_provenance = self.child_provenance(provenance, value, i, key);
+            push_segment(pointer, segment);
+            self.locate_inner(result, pointer, &child_provenance, child);
+            pointer.truncate(len);
+        };
+
+        match value {
+            Value::List(xs) => {
+                for (i, x) in xs.iter().enumerate() {
+                    visit(i, &i.to_string(), x, x);
+                }
+            }
+            Value::Tuple(xs) => {
+                for (i, x) in xs.iter().enumerate() {
+                    visit(i, &i.to_string(), x, x);
+                }
+            }
+            Value::Set(xs) => {
+                for (i, x) in xs.iter().enumerate() {
+                    visit(i, &i.to_string(), x, x);
+                }
+            }
+            Value::Dict(kvs) => {
+                for (i, (k, v)) in kvs.iter().enumerate() {
+                    if let Some(segment) = key_segment(k) {
+                        visit(i, &segment, k, v);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Return the span that produced the value and everything inside it.
+    pub fn spans(&self, value_span: Span, value: &Value) -> ValueSpans {
+        let root = vec![Step {
+            kind: StepKind::Defined,
+            span: value_span,
+        }];
+        self.spans_inner(&root, value)
+    }
+
+    fn spans_inner(&self, provenance: &Provenance, value: &Value) -> ValueSpans {
+        let child = |i: usize, key: &Value, child: &Value| {
+            let child_provenance = self.child_provenance(provenance, value, i, key);
+            self.spans_inner(&child_provenance, child)
+        };
+        let elements = match value {
+            Value::List(xs) => xs.iter().enumerate().map(|(i, x)| child(i, x, x)).collect(),
+            Value::Tuple(xs) => xs.iter().enumerate().map(|(i, x)| child(i, x, x)).collect(),
+            Value::Set(xs) => xs.iter().enumerate().map(|(i, x)| child(i, x, x)).collect(),
+            Value::Dict(kvs) => kvs
+                .iter()
+                .enumerate()
+                .map(|(i, (k, v))| child(i, k, v))
+                .collect(),
+            _ => Vec::new(),
+        };
+        ValueSpans {
+            span: provenance[0].span,
+            elements,
+        }
+    }
+
+    /// Return the provenance of the value at `path` inside `value`.
+    ///
+    /// The path is a JSON pointer if it starts with a slash, and otherwise a
+    /// sequence of keys and indices separated by dots, such as `spec.ports.0`.
+    pub fn trace(&self, value_span: Span, value: &Value, path: &str) -> Result<Provenance> {
+        let segments: Vec<String> = match path.strip_prefix('/') {
+            Some(pointer) => pointer
+                .split('/')
+                .map(|s| s.replace("~1", "/").replace("~0", "~"))
+                .collect(),
+            None if path.is_empty() => Vec::new(),
+            None => path.split('.').map(|s| s.to_string()).collect(),
+        };
+
+        let mut provenance = vec![Step {
+            kind: StepKind::Defined,
+            span: value_span,
+        }];
+        let mut value = value;
+        for (n, segment) in segments.iter().enumerate() {
+            match get_child(value, segment) {
+                Some((i, key, child)) => {
+                    provenance = self.child_provenance(&provenance, value, i, key);
+                    value = child;
+                }
+                None => {
+                    let parent = match n {
+                        0 => Doc::str("the top level"),
+                        _ => concat! {
+                            "'" Doc::highlight(&segments[..n].join(".")).into_owned() "'"
+                        },
+                    };
+                    return Error::new(concat! {
+                        "The output has no '" Doc::highlight(segment).into_owned() "' at "
+                        parent "."
+                    })
+                    .with_help(
+                        "Separate keys and list indices with dots, for example 'spec.ports.0'.",
+                    )
+                    .err();
+                }
+            }
+        }
+        Ok(provenance)
+    }
+}
+
+/// Print the expressions that contributed to a value, with their source.
+pub fn format_provenance<'a>(inputs: &'a Inputs, provenance: &Provenance) -> Doc<'a> {
+    let mut result = Vec::new();
+    let mut is_overridden = false;
+    for (i, step) in provenance.iter().enumerate() {
+        let message = match step.kind {
+            StepKind::Defined if is_overridden => "The replaced value is defined here.",
+            StepKind::Defined => "The value is defined here.",
+            StepKind::Comprehension => "It is yielded by this comprehension.",
+            StepKind::Override => "This union replaces a value from the left-hand side.",
+        };
+        is_overridden = is_overridden || step.kind == StepKind::Override;
+        if i > 0 {
+            result.push(Doc::HardBreak);
+            result.push(Doc::HardBreak);
+        }
+        result.push(highlight_span(inputs, step.span, Markup::Trace));
+        result.push(Doc::str(message));
+    }
+    Doc::Concat(result)
+}
+
+/// Return the 1-based line and column of the byte offset in the input.
+///
+/// Like diagnostics, columns count bytes.
+pub fn line_column(input: &str, offset: usize) -> (usize, usize) {
+    let before = &input[..offset];
+    let line = 1 + before.bytes().filter(|b| *b == b'\n').count();
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, 1 + offset - line_start)
+}
+
+/// Convert located spans into a value that can be exported as json.
+///
+/// Every location maps to a dict with the document name, and the line and
+/// column where the span starts and ends.
+pub fn locations_to_value(inputs: &Inputs, locations: &BTreeMap<String, Span>) -> Value {
+    let mut result = BTreeMap::new();
+    for (pointer, span) in locations.iter() {
+        let doc = &inputs[span.doc().0 as usize];
+        let (line, column) = line_column(doc.data, span.start());
+        let (end_line, end_column) = line_column(doc.data, span.end());
+        let location: BTreeMap<Value, Value> = [
+            ("file", Value::String(doc.name.into())),
+            ("line", Value::Int(line as i64)),
+            ("column", Value::Int(column as i64)),
+            ("end_line", Value::Int(end_line as i64)),
+            ("end_column", Value::Int(end_column as i64)),
+        ]
+        .into_iter()
+        .map(|(k, v)| (Value::String(k.into()), v))
+        .collect();
+        result.insert(
+            Value::String(pointer[..].into()),
+            Value::Dict(SmallDict::from(location)),
+        );
+    }
+    Value::Dict(SmallDict::from(result))
+}
+
+#[cfg(test)]
+mod test {
+    use super::line_column;
+
+    #[test]
+    fn line_column_counts_from_one() {
+        let input = "{\n  a = 1,\n}";
+        assert_eq!(line_column(input, 0), (1, 1));
+        assert_eq!(line_column(input, 4), (2, 3));
+        assert_eq!(line_column(input, input.len()), (3, 2));
+    }
+}
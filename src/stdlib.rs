@@ -11,110 +11,1844 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::rc::Rc;
 
 use crate::ast::CallArg;
-use crate::error::{IntoError, Result};
+use crate::bytes::{self, DecodeError};
+use crate::error::Error;
+use crate::error::{IntoError, PathElement, Result};
 use crate::eval::Evaluator;
-use crate::fmt_rcl::format_rcl;
+use crate::fmt_rcl::{format_rcl, format_rcl_limited, Limits};
 use crate::markup::Markup;
-use crate::pprint::{concat, indent, Doc};
-use crate::runtime::{builtin_function, builtin_method, FunctionCall, MethodCall, Value};
-use crate::types::AsTypeName;
+use crate::net;
+use crate::number::{self, Float, NumberFormat};
+use crate::pprint::{self, concat, indent, Doc};
+use crate::regex::Regex;
+use crate::runtime::{
+    builtin_function, builtin_method, BuiltinFunction, FunctionCall, MemoizedFunction, MethodCall,
+    PartialApplication, Value,
+};
+use crate::semver;
+use crate::small_collection::{SmallDict, SmallList};
+use crate::small_str::SmallStr;
+use crate::source::{DocId, Span};
+use crate::types::{self, AsTypeName};
+use crate::url;
+
+/// Point a file access error at the path argument, and explain the resolution.
+///
+/// The error from the filesystem only says which absolute path failed. Unless
+/// it already has a hint, we add where relative paths are resolved from, and
+/// which sandbox policy is in effect, to make the failure easier to diagnose.
+fn file_access_error(
+    eval: &Evaluator,
+    arg_span: Span,
+    path: &str,
+    from: Option<DocId>,
+    err: Box<Error>,
+) -> Box<Error> {
+    let mut err = err.with_origin(arg_span);
+    if err.help.is_none() {
+        err.set_help(eval.loader.describe_path_resolution(path, from));
+    }
+    Box::new(err)
+}
+
+builtin_function!(
+    "std.read_file_utf8",
+    (path: String) -> String,
+    const STD_READ_FILE_UTF8,
+    builtin_std_read_file_utf8
+);
+fn builtin_std_read_file_utf8(eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let arg_span = call.args[0].span;
+    let path = match &call.args[0].value {
+        Value::String(s) => s.as_ref(),
+        _not_string => {
+            // TODO: Add proper typechecking and a proper type error.
+            return arg_span
+                .error("Expected a String here, but got a different type.")
+                .err();
+        }
+    };
+    let from = eval.import_stack.last().map(|ctx| ctx.doc);
+    let doc = eval
+        .loader
+        .load_path(path, from)
+        .map_err(|err| file_access_error(eval, arg_span, path, from, err))?;
+    Ok(eval.loader.get_doc(doc).data.into())
+}
+
+builtin_function!(
+    "std.file_sha256",
+    (path: String) -> String,
+    const STD_FILE_SHA256,
+    builtin_std_file_sha256
+);
+fn builtin_std_file_sha256(eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let arg_span = call.args[0].span;
+    let path = match &call.args[0].value {
+        Value::String(s) => s.as_ref(),
+        _not_string => {
+            // TODO: Add proper typechecking and a proper type error.
+            return arg_span
+                .error("Expected a String here, but got a different type.")
+                .err();
+        }
+    };
+    let from = eval.import_stack.last().map(|ctx| ctx.doc);
+    let hash = eval
+        .loader
+        .hash_file(path, from)
+        .map_err(|err| file_access_error(eval, arg_span, path, from, err))?;
+    Ok(Value::String(hash.into()))
+}
+
+builtin_function!(
+    "std.fetch_url",
+    (url: String) -> String,
+    const STD_FETCH_URL,
+    builtin_std_fetch_url
+);
+fn builtin_std_fetch_url(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    use std::process::Command;
+
+    let arg_span = call.args[0].span;
+    let url = match &call.args[0].value {
+        Value::String(s) => s.as_ref(),
+        _not_string => {
+            // TODO: Add proper typechecking and a proper type error.
+            return arg_span
+                .error("Expected a String here, but got a different type.")
+                .err();
+        }
+    };
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return arg_span
+            .error("Only 'http://' and 'https://' URLs can be fetched.")
+            .err();
+    }
+
+    // We delegate to curl rather than implementing HTTP and TLS ourselves. It
+    // is widely available, and it keeps RCL free of dependencies for a feature
+    // that most documents should not need.
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(["--proto", "=http,https", "--proto-redir", "=http,https"])
+        .args(["--max-time", "30", "--max-filesize", "10000000"])
+        .arg("--url")
+        .arg(url)
+        .output();
+
+    let output = match output {
+        Ok(output) => output,
+        Err(err) => {
+            return arg_span
+                .error(format!("Failed to run 'curl': {err}."))
+                .with_help("Fetching URLs requires 'curl' to be installed.")
+                .err()
+        }
+    };
+
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return arg_span
+            .error("Failed to fetch the URL.")
+            .with_body(Doc::lines(message.trim()).into_owned())
+            .err();
+    }
+
+    match String::from_utf8(output.stdout) {
+        Ok(body) => Ok(Value::String(body.into())),
+        Err(..) => arg_span.error("The response is not valid UTF-8.").err(),
+    }
+}
+
+builtin_function!(
+    "std.parse_csv",
+    (text: String, options: {String: Any}) -> [{String: String}],
+    const STD_PARSE_CSV,
+    builtin_std_parse_csv
+);
+fn builtin_std_parse_csv(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let text_span = call.args[0].span;
+    let options_span = call.args[1].span;
+    let text = match &call.args[0].value {
+        Value::String(text) => text,
+        _not_string => {
+            // TODO: Add proper typechecking and a proper type error.
+            return text_span
+                .error("Expected a String here, but got a different type.")
+                .err();
+        }
+    };
+    let options = match &call.args[1].value {
+        Value::Dict(options) => options,
+        _not_dict => {
+            // TODO: Add proper typechecking and a proper type error.
+            return options_span
+                .error("Expected a Dict here, but got a different type.")
+                .err();
+        }
+    };
+
+    let mut separator = ',';
+    let mut columns: Option<Vec<SmallStr>> = None;
+
+    for (k, v) in options.iter() {
+        match (k, v) {
+            (Value::String(k), Value::String(sep)) if k.as_ref() == "separator" => {
+                let mut chars = sep.chars();
+                separator = match (chars.next(), chars.next()) {
+                    (Some(ch), None) if ch != '"' && ch != '\r' && ch != '\n' => ch,
+                    _ => {
+                        return options_span
+                            .error("Expected the separator to be a single character.")
+                            .with_help("Quotes and line breaks cannot be used as separator.")
+                            .err()
+                    }
+                };
+            }
+            (Value::String(k), Value::List(names)) if k.as_ref() == "columns" => {
+                let mut result = Vec::with_capacity(names.len());
+                for name in names.iter() {
+                    match name {
+                        Value::String(name) => result.push(name.clone()),
+                        _not_string => {
+                            return options_span
+                                .error("Expected the columns to be a list of strings.")
+                                .err()
+                        }
+                    }
+                }
+                columns = Some(result);
+            }
+            _ => {
+                return options_span
+                    .error(concat! {
+                        "Invalid option " format_rcl(k).into_owned() "."
+                    })
+                    .with_help(
+                        "The supported options are 'separator', a single-character \
+                        string, and 'columns', a list of strings.",
+                    )
+                    .err()
+            }
+        }
+    }
+
+    let mut records = match crate::csv::parse_csv(text, separator) {
+        Ok(records) => records.into_iter(),
+        Err(err) => {
+            return text_span
+                .error(concat! {
+                    "Invalid CSV on line "
+                    Doc::string(err.line.to_string()).with_markup(Markup::Number)
+                    ". "
+                    err.message
+                })
+                .err()
+        }
+    };
+
+    // If the columns are not given explicitly, the first record is the header.
+    let columns: Vec<SmallStr> = match columns {
+        Some(columns) => columns,
+        None => match records.next() {
+            Some((_line, header)) => header.into_iter().map(|name| name.into()).collect(),
+            None => return Ok(Value::List(SmallList::new())),
+        },
+    };
+    let mut seen = BTreeSet::new();
+    for name in columns.iter() {
+        if !seen.insert(name) {
+            return text_span
+                .error(concat! {
+                    "Column " format_rcl(&Value::String(name.clone())).into_owned()
+                    " occurs multiple times, column names must be unique."
+                })
+                .err();
+        }
+    }
+
+    let mut result = Vec::with_capacity(records.len());
+    for (line, record) in records {
+        if record.len() != columns.len() {
+            return text_span
+                .error(concat! {
+                    "Invalid CSV on line "
+                    Doc::string(line.to_string()).with_markup(Markup::Number)
+                    ". Expected "
+                    Doc::string(columns.len().to_string()).with_markup(Markup::Number)
+                    " fields, but found "
+                    Doc::string(record.len().to_string()).with_markup(Markup::Number)
+                    "."
+                })
+                .err();
+        }
+        let row: SmallDict = columns
+            .iter()
+            .zip(record)
+            .map(|(k, v)| (Value::String(k.clone()), Value::String(v.into())))
+            .collect();
+        result.push(Value::Dict(row));
+    }
+
+    Ok(Value::List(SmallList::from(result)))
+}
+
+builtin_function!(
+    "std.range",
+    (lower: Int, upper: Int) -> [Int],
+    const STD_RANGE,
+    builtin_std_range
+);
+fn builtin_std_range(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let lower: i64 = match &call.args[0].value {
+        Value::Int(i) => *i,
+        _not_string => {
+            // TODO: Add proper typechecking and a proper type error.
+            return call.args[0]
+                .span
+                .error("Expected an Int here, but got a different type.")
+                .err();
+        }
+    };
+    let upper: i64 = match &call.args[1].value {
+        Value::Int(i) => *i,
+        _not_string => {
+            // TODO: Add proper typechecking and a proper type error.
+            return call.args[1]
+                .span
+                .error("Expected an Int here, but got a different type.")
+                .err();
+        }
+    };
+
+    let range = lower..upper;
+
+    // Because we materialize the entire list, it's easy to cause out of memory
+    // with a single call to `range`. To prevent that, put an upper limit on the
+    // size. We use a lower limit when fuzzing because it runs with less memory,
+    // and also to keep the fuzzer fast. For production, empirically, a limit of
+    // 1e6 takes about a second to evaluate, so calling `std.range` with much
+    // larger values makes little sense anyway.
+    #[cfg(fuzzing)]
+    let max_len = 500;
+    #[cfg(not(fuzzing))]
+    let max_len = 1_000_000;
+
+    if upper.saturating_sub(lower) > max_len {
+        let args_span = call.args[0].span.union(call.args[1].span);
+        return args_span
+            .error(concat! {
+                "Range "
+                Doc::string(lower.to_string()).with_markup(Markup::Number)
+                ".."
+                Doc::string(upper.to_string()).with_markup(Markup::Number)
+                " exceeds the maximum length of "
+                Doc::string(max_len.to_string()).with_markup(Markup::Number)
+                ". The list would require too much memory."
+            })
+            .err();
+    }
+
+    let values: Vec<_> = range.map(Value::Int).collect();
+    Ok(Value::List(SmallList::from(values)))
+}
+
+/// Build the error for a string that could not be decoded as bytes.
+fn decode_error(arg: &CallArg<Value>, encoding: &'static str, err: DecodeError) -> Error {
+    let help = match err {
+        DecodeError::InvalidDigit(i) => {
+            format!("The character at byte offset {i} is not a valid {encoding} digit.")
+        }
+        DecodeError::InvalidLength => format!("The length is not valid for {encoding} data."),
+    };
+    arg.span
+        .error(format!("Failed to decode as {encoding}:"))
+        .with_body(format_rcl(&arg.value).into_owned())
+        .with_help(help)
+}
+
+builtin_function!(
+    "std.bytes_from_hex",
+    (hex: String) -> Bytes,
+    const STD_BYTES_FROM_HEX,
+    builtin_std_bytes_from_hex
+);
+fn builtin_std_bytes_from_hex(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let arg = &call.args[0];
+    let hex = match &arg.value {
+        Value::String(s) => s.as_ref(),
+        _not_string => {
+            // TODO: Add proper typechecking and a proper type error.
+            return arg
+                .span
+                .error("Expected a String here, but got a different type.")
+                .err();
+        }
+    };
+    match bytes::decode_hex(hex) {
+        Ok(b) => Ok(Value::Bytes(b.into())),
+        Err(err) => decode_error(arg, "hexadecimal", err).err(),
+    }
+}
+
+builtin_function!(
+    "std.bytes_from_base64",
+    (base64: String) -> Bytes,
+    const STD_BYTES_FROM_BASE64,
+    builtin_std_bytes_from_base64
+);
+fn builtin_std_bytes_from_base64(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let arg = &call.args[0];
+    let base64 = match &arg.value {
+        Value::String(s) => s.as_ref(),
+        _not_string => {
+            // TODO: Add proper typechecking and a proper type error.
+            return arg
+                .span
+                .error("Expected a String here, but got a different type.")
+                .err();
+        }
+    };
+    match bytes::decode_base64(base64) {
+        Ok(b) => Ok(Value::Bytes(b.into())),
+        Err(err) => decode_error(arg, "base64", err).err(),
+    }
+}
+
+builtin_function!(
+    "std.char_from_codepoint",
+    (codepoint: Int) -> String,
+    const STD_CHAR_FROM_CODEPOINT,
+    builtin_std_char_from_codepoint
+);
+fn builtin_std_char_from_codepoint(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let arg_span = call.args[0].span;
+    let codepoint: i64 = match &call.args[0].value {
+        Value::Int(i) => *i,
+        _not_int => {
+            // TODO: Add proper typechecking and a proper type error.
+            return arg_span
+                .error("Expected an Int here, but got a different type.")
+                .err();
+        }
+    };
+    match u32::try_from(codepoint).ok().and_then(char::from_u32) {
+        Some(ch) => Ok(Value::String(ch.to_string().into())),
+        None => arg_span
+            .error(concat! {
+                "Expected a Unicode scalar value, but "
+                Doc::string(codepoint.to_string()).with_markup(Markup::Number)
+                " is not one."
+            })
+            .with_help(
+                "Unicode scalar values are the code points 0x0 through 0x10ffff, \
+                excluding the surrogates 0xd800 through 0xdfff.",
+            )
+            .err(),
+    }
+}
+
+builtin_function!(
+    "std.fail",
+    (message: String) -> Void,
+    const STD_FAIL,
+    builtin_std_fail
+);
+fn builtin_std_fail(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let message = match &call.args[0].value {
+        Value::String(message) => message.clone(),
+        _not_string => {
+            // TODO: Add proper typechecking and a proper type error.
+            return call.args[0]
+                .span
+                .error("Expected a String here, but got a different type.")
+                .err();
+        }
+    };
+    // The error is blamed on the call itself, the evaluator adds the call
+    // frames that lead up to it.
+    call.call_open
+        .union(call.call_close)
+        .error(message.to_string())
+        .err()
+}
+
+/// Number formatting options, as accepted by `std.format_int` and `std.format_float`.
+struct NumberOptions {
+    format: NumberFormat,
+    scale: u32,
+    precision: Option<u32>,
+}
+
+/// Parse the options dict for `std.format_int` or `std.format_float`.
+///
+/// The `scale` and `precision` options are only accepted for floats.
+fn parse_number_options(arg: &CallArg<Value>, is_float: bool) -> Result<NumberOptions> {
+    let options_span = arg.span;
+    let options = match &arg.value {
+        Value::Dict(options) => options,
+        _not_dict => {
+            // TODO: Add proper typechecking and a proper type error.
+            return options_span
+                .error("Expected a Dict here, but got a different type.")
+                .err();
+        }
+    };
+
+    let mut result = NumberOptions {
+        format: NumberFormat::default(),
+        scale: 0,
+        precision: None,
+    };
+    let digits = |name: &str, n: i64, max: i64| -> Result<i64> {
+        if (0..=max).contains(&n) {
+            return Ok(n);
+        }
+        options_span
+            .error(concat! {
+                "Expected '" name.to_string() "' to be between "
+                Doc::from("0").with_markup(Markup::Number)
+                " and "
+                Doc::string(max.to_string()).with_markup(Markup::Number)
+                ", but got "
+                Doc::string(n.to_string()).with_markup(Markup::Number)
+                "."
+            })
+            .err()
+    };
+
+    for (k, v) in options.iter() {
+        match (k, v) {
+            (Value::String(k), Value::Int(n)) if k.as_ref() == "width" => {
+                result.format.width = digits("width", *n, 1_000)? as usize;
+            }
+            (Value::String(k), Value::String(fill)) if k.as_ref() == "fill" => {
+                let mut chars = fill.chars();
+                result.format.fill = match (chars.next(), chars.next()) {
+                    (Some(ch), None) => ch,
+                    _ => {
+                        return options_span
+                            .error("Expected the fill to be a single character.")
+                            .err()
+                    }
+                };
+            }
+            (Value::String(k), Value::String(sep)) if k.as_ref() == "separator" => {
+                result.format.separator = sep.to_string();
+            }
+            (Value::String(k), Value::Int(n)) if is_float && k.as_ref() == "scale" => {
+                result.scale = digits("scale", *n, number::MAX_DIGITS as i64)? as u32;
+            }
+            (Value::String(k), Value::Int(n)) if is_float && k.as_ref() == "precision" => {
+                result.precision = Some(digits("precision", *n, number::MAX_DIGITS as i64)? as u32);
+            }
+            _ => {
+                let help = if is_float {
+                    "The supported options are 'width', 'scale', and 'precision', \
+                    which are integers, and 'fill' and 'separator', which are strings."
+                } else {
+                    "The supported options are 'width', an integer, \
+                    and 'fill' and 'separator', which are strings."
+                };
+                return options_span
+                    .error(concat! {
+                        "Invalid option " format_rcl(k).into_owned() "."
+                    })
+                    .with_help(help)
+                    .err();
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+builtin_function!(
+    "std.format_float",
+    (value: (Union[Int, Float]), options: {String: Any}) -> String,
+    const STD_FORMAT_FLOAT,
+    builtin_std_format_float
+);
+fn builtin_std_format_float(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let options = parse_number_options(&call.args[1], true)?;
+    let result = match &call.args[0].value {
+        Value::Int(i) => {
+            let precision = options.precision.unwrap_or(options.scale);
+            number::format_fixed(*i, options.scale, precision, &options.format)
+        }
+        Value::Float(_) if options.scale != 0 => {
+            return call.args[1]
+                .span
+                .error("The 'scale' option only applies to an Int value.")
+                .with_help("A Float value is formatted as is, remove the 'scale'.")
+                .err();
+        }
+        Value::Float(x) => number::format_float(*x, options.precision, &options.format),
+        _not_number => {
+            // TODO: Add proper typechecking and a proper type error.
+            return call.args[0]
+                .span
+                .error("Expected an Int or Float here, but got a different type.")
+                .err();
+        }
+    };
+    Ok(Value::String(result.into()))
+}
+
+builtin_function!(
+    "std.format_int",
+    (value: Int, options: {String: Any}) -> String,
+    const STD_FORMAT_INT,
+    builtin_std_format_int
+);
+fn builtin_std_format_int(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let value: i64 = match &call.args[0].value {
+        Value::Int(i) => *i,
+        _not_int => {
+            // TODO: Add proper typechecking and a proper type error.
+            return call.args[0]
+                .span
+                .error("Expected an Int here, but got a different type.")
+                .err();
+        }
+    };
+    let options = parse_number_options(&call.args[1], false)?;
+    let result = number::format_int(value, &options.format);
+    Ok(Value::String(result.into()))
+}
+
+builtin_function!(
+    "std.to_float",
+    (value: Int) -> Float,
+    const STD_TO_FLOAT,
+    builtin_std_to_float
+);
+fn builtin_std_to_float(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let arg = &call.args[0];
+    let value: i64 = match &arg.value {
+        Value::Int(i) => *i,
+        _not_int => {
+            // TODO: Add proper typechecking and a proper type error.
+            return arg
+                .span
+                .error("Expected an Int here, but got a different type.")
+                .err();
+        }
+    };
+    match Float::from_int(value) {
+        Some(x) => Ok(Value::Float(x)),
+        None => arg
+            .span
+            .error(concat! {
+                "Cannot convert " Doc::from(value.to_string()).with_markup(Markup::Number)
+                " to a float exactly."
+            })
+            .with_help("Not every integer beyond 2^53 in magnitude can be represented as a float.")
+            .err(),
+    }
+}
+
+builtin_function!(
+    "std.to_int",
+    (value: Float) -> Int,
+    const STD_TO_INT,
+    builtin_std_to_int
+);
+fn builtin_std_to_int(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let arg = &call.args[0];
+    let value: Float = match &arg.value {
+        Value::Float(x) => *x,
+        _not_float => {
+            // TODO: Add proper typechecking and a proper type error.
+            return arg
+                .span
+                .error("Expected a Float here, but got a different type.")
+                .err();
+        }
+    };
+    match value.to_int() {
+        Some(i) => Ok(Value::Int(i)),
+        None => {
+            let help = if value.get().fract() != 0.0 {
+                "Only floats without a fractional part can be converted to Int."
+            } else {
+                "The float is outside of the range of Int."
+            };
+            arg.span
+                .error(concat! {
+                    "Cannot convert " Doc::from(value.to_string()).with_markup(Markup::Number)
+                    " to an integer exactly."
+                })
+                .with_help(help)
+                .err()
+        }
+    }
+}
+
+builtin_function!(
+    "std.format_rcl",
+    (value: Any) -> String,
+    const STD_FORMAT_RCL,
+    builtin_std_format_rcl
+);
+fn builtin_std_format_rcl(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    // Use the same width as the default for `rcl evaluate`, so that the result
+    // matches what the command-line would print for the value.
+    let cfg = pprint::Config { width: 80 };
+    let mut result = format_rcl(&call.args[0].value)
+        .println(&cfg)
+        .to_string_no_markup();
+    result.truncate(result.trim_end().len());
+    Ok(Value::String(result.into()))
+}
+
+builtin_function!(
+    "std.template",
+    (text: String, bindings: {String: Any}) -> String,
+    const STD_TEMPLATE,
+    builtin_std_template
+);
+fn builtin_std_template(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let text_span = call.args[0].span;
+    let text = match &call.args[0].value {
+        Value::String(text) => text,
+        _not_string => {
+            // TODO: Add proper typechecking and a proper type error.
+            return text_span
+                .error("Expected a String here, but got a different type.")
+                .err();
+        }
+    };
+    if !matches!(call.args[1].value, Value::Dict(..)) {
+        // TODO: Add proper typechecking and a proper type error.
+        return call.args[1]
+            .span
+            .error("Expected a Dict here, but got a different type.")
+            .err();
+    }
+    match crate::template::render(text, &call.args[1].value) {
+        Ok(result) => Ok(Value::String(result.into())),
+        Err(err) => text_span
+            .error(concat! {
+                "Template error on line "
+                Doc::string(err.line.to_string()).with_markup(Markup::Number)
+                ". "
+                err.message
+            })
+            .err(),
+    }
+}
+
+/// Extract a string argument, and parse it with `parse`.
+///
+/// This is used by the `std.net` and `std.semver` functions, which take
+/// addresses and versions as strings.
+fn parsed_string_arg<T>(
+    call: &FunctionCall,
+    index: usize,
+    parse: fn(&str) -> std::result::Result<T, String>,
+) -> Result<T> {
+    let arg = &call.args[index];
+    match &arg.value {
+        Value::String(s) => parse(s).or_else(|msg| arg.span.error(msg).err()),
+        _not_string => {
+            // TODO: Add proper typechecking and a proper type error.
+            arg.span
+                .error("Expected a String here, but got a different type.")
+                .err()
+        }
+    }
+}
+
+builtin_function!(
+    "std.net.parse_ip",
+    (ip: String) -> {String: Any},
+    const STD_NET_PARSE_IP,
+    builtin_std_net_parse_ip
+);
+fn builtin_std_net_parse_ip(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let ip = parsed_string_arg(&call, 0, net::parse_ip)?;
+    let mut result = BTreeMap::new();
+    result.insert("address".into(), Value::String(ip.to_string().into()));
+    result.insert("version".into(), Value::Int(net::version(ip)));
+    Ok(Value::Dict(SmallDict::from(result)))
+}
+
+builtin_function!(
+    "std.net.cidr_contains",
+    (range: String, ip: String) -> Bool,
+    const STD_NET_CIDR_CONTAINS,
+    builtin_std_net_cidr_contains
+);
+fn builtin_std_net_cidr_contains(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let range = parsed_string_arg(&call, 0, net::parse_cidr)?;
+    let ip = parsed_string_arg(&call, 1, net::parse_ip)?;
+    Ok(Value::Bool(range.contains(ip)))
+}
+
+builtin_function!(
+    "std.net.cidr_hosts",
+    (range: String, count: Int) -> [String],
+    const STD_NET_CIDR_HOSTS,
+    builtin_std_net_cidr_hosts
+);
+fn builtin_std_net_cidr_hosts(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let range = parsed_string_arg(&call, 0, net::parse_cidr)?;
+    let count_arg = &call.args[1];
+    let count = match &count_arg.value {
+        Value::Int(n) => *n,
+        _not_int => {
+            // TODO: Add proper typechecking and a proper type error.
+            return count_arg
+                .span
+                .error("Expected an Int here, but got a different type.")
+                .err();
+        }
+    };
+
+    // Like `std.range`, we materialize the list, so put a limit on its size.
+    #[cfg(fuzzing)]
+    let max_len = 500;
+    #[cfg(not(fuzzing))]
+    let max_len = 1_000_000;
+
+    if !(0..=max_len).contains(&count) {
+        return count_arg
+            .span
+            .error(concat! {
+                "Host count must be between 0 and "
+                Doc::string(max_len.to_string()).with_markup(Markup::Number)
+                ", but got "
+                Doc::string(count.to_string()).with_markup(Markup::Number)
+                "."
+            })
+            .err();
+    }
+    let hosts = range
+        .hosts(count as u64)
+        .or_else(|msg| call.args[0].span.error(msg).err())?;
+    let result: SmallList = hosts
+        .iter()
+        .map(|ip| Value::String(ip.to_string().into()))
+        .collect();
+    Ok(Value::List(result))
+}
+
+builtin_function!(
+    "std.net.ip_add",
+    (ip: String, n: Int) -> String,
+    const STD_NET_IP_ADD,
+    builtin_std_net_ip_add
+);
+fn builtin_std_net_ip_add(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let ip = parsed_string_arg(&call, 0, net::parse_ip)?;
+    let n = match &call.args[1].value {
+        Value::Int(n) => *n,
+        _not_int => {
+            // TODO: Add proper typechecking and a proper type error.
+            return call.args[1]
+                .span
+                .error("Expected an Int here, but got a different type.")
+                .err();
+        }
+    };
+    let args_span = call.args[0].span.union(call.args[1].span);
+    let result = net::add(ip, n).or_else(|msg| args_span.error(msg).err())?;
+    Ok(Value::String(result.to_string().into()))
+}
+
+builtin_function!(
+    "std.semver.parse",
+    (version: String) -> {String: Any},
+    const STD_SEMVER_PARSE,
+    builtin_std_semver_parse
+);
+fn builtin_std_semver_parse(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let version = parsed_string_arg(&call, 0, semver::parse_version)?;
+    let mut result = BTreeMap::new();
+    result.insert("major".into(), Value::Int(version.major as i64));
+    result.insert("minor".into(), Value::Int(version.minor as i64));
+    result.insert("patch".into(), Value::Int(version.patch as i64));
+    result.insert(
+        "prerelease".into(),
+        Value::String(version.pre_string().into()),
+    );
+    result.insert("build".into(), Value::String(version.build.into()));
+    Ok(Value::Dict(SmallDict::from(result)))
+}
+
+builtin_function!(
+    "std.semver.compare",
+    (a: String, b: String) -> Int,
+    const STD_SEMVER_COMPARE,
+    builtin_std_semver_compare
+);
+fn builtin_std_semver_compare(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let a = parsed_string_arg(&call, 0, semver::parse_version)?;
+    let b = parsed_string_arg(&call, 1, semver::parse_version)?;
+    Ok(Value::Int(a.cmp_precedence(&b) as i64))
+}
+
+builtin_function!(
+    "std.semver.satisfies",
+    (version: String, range: String) -> Bool,
+    const STD_SEMVER_SATISFIES,
+    builtin_std_semver_satisfies
+);
+fn builtin_std_semver_satisfies(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let version = parsed_string_arg(&call, 0, semver::parse_version)?;
+    let range = parsed_string_arg(&call, 1, semver::parse_range)?;
+    Ok(Value::Bool(range.contains(&version)))
+}
+
+/// Bind the arguments of a `std.check` call to `validator`, as a new function.
+///
+/// The validator takes the bound arguments as its first argument, and the
+/// value to check as its second argument.
+fn make_validator(call: &FunctionCall, validator: &'static BuiltinFunction, arg: Value) -> Value {
+    let arg_span = match (call.args.first(), call.args.last()) {
+        (Some(first), Some(last)) => first.span.union(last.span),
+        _ => call.call_open.union(call.call_close),
+    };
+    let mut type_ = (validator.type_)();
+    type_.args.remove(0);
+    let result = PartialApplication {
+        function_span: call.call_open,
+        function: Value::BuiltinFunction(validator),
+        arg_span,
+        arg,
+        type_: Rc::new(type_),
+    };
+    Value::Partial(Rc::new(result))
+}
+
+/// Report that the value passed to a validator does not satisfy the check.
+///
+/// The error is reported at the path of the dict field being evaluated, if any.
+fn check_failed(eval: &Evaluator, call: &FunctionCall, message: Doc<'static>) -> Result<Value> {
+    call.args[1]
+        .span
+        .error(message)
+        .with_note(call.args[0].span, "The check is defined here.")
+        .with_path(eval.value_path())
+        .err()
+}
+
+builtin_function!(
+    "std.check.range",
+    (min: Int, max: Int) -> Any,
+    const STD_CHECK_RANGE,
+    builtin_std_check_range
+);
+fn builtin_std_check_range(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let mut bounds = [0_i64; 2];
+    for (bound, arg) in bounds.iter_mut().zip(call.args) {
+        *bound = match &arg.value {
+            Value::Int(i) => *i,
+            _not_int => {
+                // TODO: Add proper typechecking and a proper type error.
+                return arg
+                    .span
+                    .error("Expected an Int here, but got a different type.")
+                    .err();
+            }
+        };
+    }
+    let [min, max] = bounds;
+    if min > max {
+        return call.args[1]
+            .span
+            .error(concat! {
+                "The upper bound " Doc::from(max.to_string()).with_markup(Markup::Number)
+                " is less than the lower bound " Doc::from(min.to_string()).with_markup(Markup::Number)
+                "."
+            })
+            .err();
+    }
+    let range = Value::Tuple(Rc::new([Value::Int(min), Value::Int(max)]));
+    Ok(make_validator(&call, &STD_CHECK_RANGE_VALIDATOR, range))
+}
+
+builtin_function!(
+    "std.check.range",
+    (range: Any, value: Int) -> Int,
+    const STD_CHECK_RANGE_VALIDATOR,
+    builtin_std_check_range_validator
+);
+fn builtin_std_check_range_validator(eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let (min, max) = match &call.args[0].value {
+        Value::Tuple(bounds) => match bounds[..] {
+            [Value::Int(min), Value::Int(max)] => (min, max),
+            _ => unreachable!("The range is constructed by std.check.range."),
+        },
+        _ => unreachable!("The range is constructed by std.check.range."),
+    };
+    let value = &call.args[1].value;
+    match value {
+        Value::Int(i) if min <= *i && *i <= max => Ok(value.clone()),
+        _ => check_failed(
+            eval,
+            &call,
+            concat! {
+                "Expected an Int from "
+                Doc::from(min.to_string()).with_markup(Markup::Number)
+                " to "
+                Doc::from(max.to_string()).with_markup(Markup::Number)
+                " inclusive, but got "
+                format_rcl_limited(value, eval.loader.error_limits()).into_owned()
+                "."
+            },
+        ),
+    }
+}
+
+builtin_function!(
+    "std.check.matches",
+    (pattern: String) -> Any,
+    const STD_CHECK_MATCHES,
+    builtin_std_check_matches
+);
+fn builtin_std_check_matches(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    // Compile the pattern once here, so an invalid pattern is reported where
+    // the check is defined, rather than only when it is used.
+    parsed_string_arg(&call, 0, Regex::new)?;
+    let pattern = call.args[0].value.clone();
+    Ok(make_validator(&call, &STD_CHECK_MATCHES_VALIDATOR, pattern))
+}
+
+builtin_function!(
+    "std.check.matches",
+    (pattern: String, value: String) -> String,
+    const STD_CHECK_MATCHES_VALIDATOR,
+    builtin_std_check_matches_validator
+);
+fn builtin_std_check_matches_validator(eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let regex = parsed_string_arg(&call, 0, Regex::new)?;
+    let value = &call.args[1].value;
+    match value {
+        Value::String(s) if regex.is_match(s) => Ok(value.clone()),
+        _ => check_failed(
+            eval,
+            &call,
+            concat! {
+                "Expected a String that matches "
+                format_rcl(&call.args[0].value).into_owned()
+                ", but got "
+                format_rcl_limited(value, eval.loader.error_limits()).into_owned()
+                "."
+            },
+        ),
+    }
+}
+
+builtin_function!(
+    "std.check.one_of",
+    (options: Any) -> Any,
+    const STD_CHECK_ONE_OF,
+    builtin_std_check_one_of
+);
+fn builtin_std_check_one_of(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let arg = &call.args[0];
+    let options: BTreeSet<Value> = match &arg.value {
+        Value::List(xs) => xs.iter().cloned().collect(),
+        Value::Set(xs) => xs.as_ref().clone(),
+        _not_collection => {
+            // TODO: Add proper typechecking and a proper type error.
+            return arg
+                .span
+                .error("Expected a List or Set here, but got a different type.")
+                .err();
+        }
+    };
+    let options = Value::Set(Rc::new(options));
+    Ok(make_validator(&call, &STD_CHECK_ONE_OF_VALIDATOR, options))
+}
+
+builtin_function!(
+    "std.check.one_of",
+    (options: Any, value: Any) -> Any,
+    const STD_CHECK_ONE_OF_VALIDATOR,
+    builtin_std_check_one_of_validator
+);
+fn builtin_std_check_one_of_validator(eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let options = match &call.args[0].value {
+        Value::Set(options) => options,
+        _ => unreachable!("The options are constructed by std.check.one_of."),
+    };
+    let value = &call.args[1].value;
+    if options.contains(value) {
+        return Ok(value.clone());
+    }
+    let limits = eval.loader.error_limits();
+    check_failed(
+        eval,
+        &call,
+        concat! {
+            "Expected one of "
+            format_rcl_limited(&call.args[0].value, limits).into_owned()
+            ", but got "
+            format_rcl_limited(value, limits).into_owned()
+            "."
+        },
+    )
+}
+
+/// Return the list argument at the given index of a `std.list` call.
+fn list_arg<'a>(call: &'a FunctionCall, index: usize) -> Result<&'a [Value]> {
+    let arg = &call.args[index];
+    match &arg.value {
+        Value::List(xs) => Ok(xs),
+        _not_list => {
+            // TODO: Add proper typechecking and a proper type error.
+            arg.span
+                .error("Expected a List here, but got a different type.")
+                .err()
+        }
+    }
+}
+
+/// Return the size argument at the given index of a `std.list` call.
+///
+/// The size must be at least 1, `what` describes it in the error message.
+fn size_arg(call: &FunctionCall, index: usize, what: &'static str) -> Result<usize> {
+    let arg = &call.args[index];
+    match &arg.value {
+        Value::Int(n) if *n >= 1 => Ok(*n as usize),
+        Value::Int(n) => arg
+            .span
+            .error(concat! {
+                what
+                " must be at least 1, but got "
+                Doc::string(n.to_string()).with_markup(Markup::Number)
+                "."
+            })
+            .err(),
+        _not_int => {
+            // TODO: Add proper typechecking and a proper type error.
+            arg.span
+                .error("Expected an Int here, but got a different type.")
+                .err()
+        }
+    }
+}
+
+builtin_function!(
+    "std.list.chunks",
+    (xs: [Any], n: Int) -> [[Any]],
+    const STD_LIST_CHUNKS,
+    builtin_std_list_chunks
+);
+fn builtin_std_list_chunks(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let xs = list_arg(&call, 0)?;
+    let n = size_arg(&call, 1, "Chunk size")?;
+    let result: SmallList = xs
+        .chunks(n)
+        .map(|chunk| Value::List(SmallList::from(chunk.to_vec())))
+        .collect();
+    Ok(Value::List(result))
+}
+
+builtin_function!(
+    "std.list.windows",
+    (xs: [Any], n: Int) -> [[Any]],
+    const STD_LIST_WINDOWS,
+    builtin_std_list_windows
+);
+fn builtin_std_list_windows(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let xs = list_arg(&call, 0)?;
+    let n = size_arg(&call, 1, "Window size")?;
+
+    // Unlike chunks, windows overlap, so the result can hold many more
+    // elements than the input. Like `std.range`, limit the size of the result.
+    #[cfg(fuzzing)]
+    let max_len = 500;
+    #[cfg(not(fuzzing))]
+    let max_len = 1_000_000;
+
+    let n_windows = (xs.len() + 1).saturating_sub(n);
+    let total_len = n_windows.saturating_mul(n);
+    if total_len > max_len {
+        let args_span = call.args[0].span.union(call.args[1].span);
+        return args_span
+            .error(concat! {
+                "The windows would hold "
+                Doc::string(total_len.to_string()).with_markup(Markup::Number)
+                " elements in total, but the limit is "
+                Doc::string(max_len.to_string()).with_markup(Markup::Number)
+                "."
+            })
+            .err();
+    }
+
+    let result: SmallList = xs
+        .windows(n)
+        .map(|window| Value::List(SmallList::from(window.to_vec())))
+        .collect();
+    Ok(Value::List(result))
+}
+
+builtin_function!(
+    "std.list.transpose",
+    (rows: [[Any]]) -> [[Any]],
+    const STD_LIST_TRANSPOSE,
+    builtin_std_list_transpose
+);
+fn builtin_std_list_transpose(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let arg = &call.args[0];
+    let mut rows = Vec::new();
+    for (i, row) in list_arg(&call, 0)?.iter().enumerate() {
+        match row {
+            Value::List(xs) => rows.push(xs),
+            _not_list => {
+                // TODO: Add proper typechecking and a proper type error.
+                return arg
+                    .span
+                    .error("Expected a List of Lists, but an element is not a List.")
+                    .with_path(vec![PathElement::Index(i)])
+                    .err();
+            }
+        }
+    }
+
+    let n_columns = rows.first().map(|row| row.len()).unwrap_or(0);
+    for (i, row) in rows.iter().enumerate() {
+        if row.len() != n_columns {
+            return arg
+                .span
+                .error(concat! {
+                    "All lists must have the same length to transpose, but the first has length "
+                    Doc::string(n_columns.to_string()).with_markup(Markup::Number)
+                    ", and this one has length "
+                    Doc::string(row.len().to_string()).with_markup(Markup::Number)
+                    "."
+                })
+                .with_path(vec![PathElement::Index(i)])
+                .err();
+        }
+    }
+
+    let result: SmallList = (0..n_columns)
+        .map(|j| Value::List(rows.iter().map(|row| row[j].clone()).collect()))
+        .collect();
+    Ok(Value::List(result))
+}
+
+/// Convert the query parameters in an `std.url` call from a dict to pairs.
+///
+/// Values can be strings, integers, and booleans, or a list or set of those
+/// to repeat the parameter. Parameters that are `null` are omitted.
+fn url_query_pairs(query: &Value, span: Span) -> Result<Vec<(String, String)>> {
+    let dict = match query {
+        Value::Dict(dict) => dict,
+        _not_dict => {
+            return span
+                .error(concat! {
+                    "Expected the query to be a Dict, but got "
+                    Doc::from(query.type_name()).with_markup(Markup::Type)
+                    "."
+                })
+                .err();
+        }
+    };
+    let mut pairs = Vec::new();
+    for (key, value) in dict.iter() {
+        let key = match key {
+            Value::String(key) => key,
+            _not_string => {
+                return span
+                    .error(concat! {
+                        "Query parameter names must be strings, but found "
+                        Doc::from(key.type_name()).with_markup(Markup::Type)
+                        "."
+                    })
+                    .err();
+            }
+        };
+        let values: Vec<&Value> = match value {
+            Value::Null => Vec::new(),
+            Value::List(vs) => vs.iter().collect(),
+            Value::Set(vs) => vs.iter().collect(),
+            single => vec![single],
+        };
+        for v in values {
+            let v_str = match v {
+                Value::String(s) => s.to_string(),
+                Value::Int(i) => i.to_string(),
+                Value::Bool(b) => b.to_string(),
+                _ => {
+                    return span
+                        .error(concat! {
+                            "Invalid value for query parameter "
+                            format_rcl(&Value::String(key.clone())).into_owned()
+                            ". Expected a String, Int, or Bool, or a List of those, but got "
+                            Doc::from(v.type_name()).with_markup(Markup::Type)
+                            "."
+                        })
+                        .err();
+                }
+            };
+            pairs.push((key.to_string(), v_str));
+        }
+    }
+    Ok(pairs)
+}
+
+/// Convert decoded query pairs into a dict, the inverse of [`url_query_pairs`].
+fn url_query_dict(pairs: Vec<(String, String)>) -> Value {
+    let mut result: BTreeMap<Value, Value> = BTreeMap::new();
+    for (key, value) in pairs {
+        let key = Value::String(key.into());
+        let value = Value::String(value.into());
+        match result.get_mut(&key) {
+            None => {
+                result.insert(key, value);
+            }
+            Some(Value::List(vs)) => vs.push(value),
+            Some(prev) => *prev = Value::List(SmallList::from(vec![prev.clone(), value])),
+        }
+    }
+    Value::Dict(SmallDict::from(result))
+}
+
+builtin_function!(
+    "std.url.parse",
+    (url: String) -> {String: Any},
+    const STD_URL_PARSE,
+    builtin_std_url_parse
+);
+fn builtin_std_url_parse(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let url = parsed_string_arg(&call, 0, url::parse)?;
+    let opt_string = |s: Option<String>| match s {
+        Some(s) => Value::String(s.into()),
+        None => Value::Null,
+    };
+    let mut result = BTreeMap::new();
+    result.insert("scheme".into(), Value::String(url.scheme.into()));
+    result.insert("user".into(), opt_string(url.user));
+    result.insert("password".into(), opt_string(url.password));
+    result.insert("host".into(), Value::String(url.host.into()));
+    result.insert(
+        "port".into(),
+        match url.port {
+            Some(port) => Value::Int(port as i64),
+            None => Value::Null,
+        },
+    );
+    result.insert("path".into(), Value::String(url.path.into()));
+    result.insert("query".into(), url_query_dict(url.query));
+    result.insert("fragment".into(), opt_string(url.fragment));
+    Ok(Value::Dict(SmallDict::from(result)))
+}
 
 builtin_function!(
-    "std.read_file_utf8",
-    (path: String) -> String,
-    const STD_READ_FILE_UTF8,
-    builtin_std_read_file_utf8
+    "std.url.build",
+    (parts: {String: Any}) -> String,
+    const STD_URL_BUILD,
+    builtin_std_url_build
 );
-fn builtin_std_read_file_utf8(eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
-    let arg_span = call.args[0].span;
-    let path = match &call.args[0].value {
-        Value::String(s) => s.as_ref(),
-        _not_string => {
+fn builtin_std_url_build(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let span = call.args[0].span;
+    let parts = match &call.args[0].value {
+        Value::Dict(parts) => parts,
+        _not_dict => {
             // TODO: Add proper typechecking and a proper type error.
-            return arg_span
-                .error("Expected a String here, but got a different type.")
+            return span
+                .error("Expected a Dict here, but got a different type.")
                 .err();
         }
     };
-    let from = eval.import_stack.last().map(|ctx| ctx.doc);
-    let doc = eval
-        .loader
-        .load_path(path, from)
-        .map_err(|err| err.with_origin(arg_span))?;
-    Ok(eval.loader.get_doc(doc).data.into())
+    for required in ["scheme", "host"] {
+        if !parts.contains_key(&required.into()) {
+            return span
+                .error(concat! { "Missing key " Doc::highlight(required) "." })
+                .with_help("A URL needs at least a 'scheme' and a 'host'.")
+                .err();
+        }
+    }
+
+    let mut result = url::Url::default();
+    for (key, value) in parts.iter() {
+        let name = match key {
+            Value::String(name) => name.as_ref(),
+            _ => "",
+        };
+        // We treat null as absent, so the result of `std.url.parse` can be
+        // modified and passed back in.
+        let string = match value {
+            Value::Null => continue,
+            Value::String(s) => Some(s.to_string()),
+            _ => None,
+        };
+        match (name, string) {
+            ("scheme", Some(s)) => result.scheme = s,
+            ("host", Some(s)) => result.host = s,
+            ("path", Some(s)) => result.path = s,
+            ("user", Some(s)) => result.user = Some(s),
+            ("password", Some(s)) => result.password = Some(s),
+            ("fragment", Some(s)) => result.fragment = Some(s),
+            ("query", _) => result.query = url_query_pairs(value, span)?,
+            ("port", _) => match value {
+                Value::Int(port) if (0..=65535).contains(port) => result.port = Some(*port as u16),
+                _ => {
+                    return span
+                        .error(concat! {
+                            "Invalid port "
+                            format_rcl(value).into_owned()
+                            ". Expected an Int between 0 and 65535."
+                        })
+                        .err()
+                }
+            },
+            ("scheme" | "host" | "path" | "user" | "password" | "fragment", None) => {
+                return span
+                    .error(concat! {
+                        "Expected " Doc::highlight(name).into_owned() " to be a String, but got "
+                        Doc::from(value.type_name()).with_markup(Markup::Type)
+                        "."
+                    })
+                    .err()
+            }
+            _ => {
+                return span
+                    .error(concat! { "Unknown key " format_rcl(key).into_owned() "." })
+                    .with_help(
+                        "The known keys are 'scheme', 'user', 'password', 'host', 'port', \
+                        'path', 'query', and 'fragment'.",
+                    )
+                    .err()
+            }
+        }
+    }
+    if result.password.is_some() && result.user.is_none() {
+        return span
+            .error("A URL with a 'password' must also have a 'user'.")
+            .err();
+    }
+
+    let url = url::build(&result).or_else(|msg| span.error(msg).err())?;
+    Ok(Value::String(url.into()))
 }
 
 builtin_function!(
-    "std.range",
-    (lower: Int, upper: Int) -> [Int],
-    const STD_RANGE,
-    builtin_std_range
+    "std.url.encode_query",
+    (query: {String: Any}) -> String,
+    const STD_URL_ENCODE_QUERY,
+    builtin_std_url_encode_query
 );
-fn builtin_std_range(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
-    let lower: i64 = match &call.args[0].value {
-        Value::Int(i) => *i,
-        _not_string => {
-            // TODO: Add proper typechecking and a proper type error.
-            return call.args[0]
+fn builtin_std_url_encode_query(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let pairs = url_query_pairs(&call.args[0].value, call.args[0].span)?;
+    Ok(Value::String(url::encode_query(&pairs).into()))
+}
+
+builtin_function!(
+    "std.type_of",
+    (value: Any) -> String,
+    const STD_TYPE_OF,
+    builtin_std_type_of
+);
+fn builtin_std_type_of(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    Ok(call.args[0].value.type_name().into())
+}
+
+builtin_function!(
+    "std.is_null",
+    (value: Any) -> Bool,
+    const STD_IS_NULL,
+    builtin_std_is_null
+);
+fn builtin_std_is_null(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let is_null = matches!(call.args[0].value, Value::Null);
+    Ok(Value::Bool(is_null))
+}
+
+builtin_function!(
+    "std.is_bool",
+    (value: Any) -> Bool,
+    const STD_IS_BOOL,
+    builtin_std_is_bool
+);
+fn builtin_std_is_bool(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let is_bool = matches!(call.args[0].value, Value::Bool(..));
+    Ok(Value::Bool(is_bool))
+}
+
+builtin_function!(
+    "std.is_int",
+    (value: Any) -> Bool,
+    const STD_IS_INT,
+    builtin_std_is_int
+);
+fn builtin_std_is_int(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let is_int = matches!(call.args[0].value, Value::Int(..));
+    Ok(Value::Bool(is_int))
+}
+
+builtin_function!(
+    "std.is_float",
+    (value: Any) -> Bool,
+    const STD_IS_FLOAT,
+    builtin_std_is_float
+);
+fn builtin_std_is_float(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let is_float = matches!(call.args[0].value, Value::Float(..));
+    Ok(Value::Bool(is_float))
+}
+
+builtin_function!(
+    "std.is_string",
+    (value: Any) -> Bool,
+    const STD_IS_STRING,
+    builtin_std_is_string
+);
+fn builtin_std_is_string(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let is_string = matches!(call.args[0].value, Value::String(..));
+    Ok(Value::Bool(is_string))
+}
+
+builtin_function!(
+    "std.is_bytes",
+    (value: Any) -> Bool,
+    const STD_IS_BYTES,
+    builtin_std_is_bytes
+);
+fn builtin_std_is_bytes(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let is_bytes = matches!(call.args[0].value, Value::Bytes(..));
+    Ok(Value::Bool(is_bytes))
+}
+
+builtin_function!(
+    "std.is_list",
+    (value: Any) -> Bool,
+    const STD_IS_LIST,
+    builtin_std_is_list
+);
+fn builtin_std_is_list(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let is_list = matches!(call.args[0].value, Value::List(..));
+    Ok(Value::Bool(is_list))
+}
+
+builtin_function!(
+    "std.is_set",
+    (value: Any) -> Bool,
+    const STD_IS_SET,
+    builtin_std_is_set
+);
+fn builtin_std_is_set(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let is_set = matches!(call.args[0].value, Value::Set(..));
+    Ok(Value::Bool(is_set))
+}
+
+builtin_function!(
+    "std.is_dict",
+    (value: Any) -> Bool,
+    const STD_IS_DICT,
+    builtin_std_is_dict
+);
+fn builtin_std_is_dict(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let is_dict = matches!(call.args[0].value, Value::Dict(..));
+    Ok(Value::Bool(is_dict))
+}
+
+builtin_function!(
+    "std.is_function",
+    (value: Any) -> Bool,
+    const STD_IS_FUNCTION,
+    builtin_std_is_function
+);
+fn builtin_std_is_function(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let is_function = matches!(
+        call.args[0].value,
+        Value::Function(..)
+            | Value::BuiltinFunction(..)
+            | Value::BuiltinMethod(..)
+            | Value::Partial(..)
+            | Value::Memoized(..)
+    );
+    Ok(Value::Bool(is_function))
+}
+
+/// Return the type of the function argument, or an error if it is not a function.
+fn expect_function(arg: &CallArg<Value>) -> Result<Rc<types::Function>> {
+    match arg.value.function_type() {
+        Some(fn_type) => Ok(fn_type),
+        None => arg
+            .span
+            .error(concat! {
+                "Expected a function, but got a value of type "
+                Doc::highlight(arg.value.type_name())
+                "."
+            })
+            .err(),
+    }
+}
+
+builtin_function!(
+    "std.partial",
+    (function: Any, arg: Any) -> Any,
+    const STD_PARTIAL,
+    builtin_std_partial
+);
+fn builtin_std_partial(eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let function = &call.args[0];
+    let arg = &call.args[1];
+    let fn_type = expect_function(function)?;
+    let (first_arg, other_args) = match fn_type.args.split_first() {
+        Some(args) => args,
+        None => {
+            return function
                 .span
-                .error("Expected an Int here, but got a different type.")
-                .err();
+                .error("This function takes no arguments, there is no argument to bind.")
+                .err()
         }
     };
-    let upper: i64 = match &call.args[1].value {
-        Value::Int(i) => *i,
+    arg.value
+        .is_instance_of(arg.span, &first_arg.type_, eval.loader.error_limits())?;
+    let type_ = types::Function {
+        args: other_args.to_vec(),
+        result: fn_type.result.clone(),
+    };
+    let result = PartialApplication {
+        function_span: function.span,
+        function: function.value.clone(),
+        arg_span: arg.span,
+        arg: arg.value.clone(),
+        type_: Rc::new(type_),
+    };
+    Ok(Value::Partial(Rc::new(result)))
+}
+
+builtin_function!(
+    "std.memoize",
+    (function: Any) -> Any,
+    const STD_MEMOIZE,
+    builtin_std_memoize
+);
+fn builtin_std_memoize(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let function = &call.args[0];
+    expect_function(function)?;
+    let result = MemoizedFunction {
+        function_span: function.span,
+        function: function.value.clone(),
+    };
+    Ok(Value::Memoized(Rc::new(result)))
+}
+
+builtin_function!(
+    "std.secret",
+    (name: String) -> Any,
+    const STD_SECRET,
+    builtin_std_secret
+);
+fn builtin_std_secret(_eval: &mut Evaluator, call: FunctionCall) -> Result<Value> {
+    let arg = &call.args[0];
+    let name = match &arg.value {
+        Value::String(s) => s,
         _not_string => {
             // TODO: Add proper typechecking and a proper type error.
-            return call.args[1]
+            return arg
                 .span
-                .error("Expected an Int here, but got a different type.")
+                .error("Expected a String here, but got a different type.")
                 .err();
         }
     };
+    if !crate::secret::is_valid_name(name) {
+        return arg
+            .span
+            .error("Invalid secret name.")
+            .with_help(
+                "Secret names can contain ASCII letters, digits, '_', '-', and '.', \
+                and they cannot start with '.'.",
+            )
+            .err();
+    }
+    Ok(Value::Secret(name.to_rc()))
+}
 
-    let range = lower..upper;
+/// An effect beyond pure computation that a builtin needs.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Capability {
+    /// The builtin only computes a result from its arguments.
+    Pure,
+    /// The builtin reads files, subject to the sandbox of the loader.
+    FsRead,
+    /// The builtin reads environment variables.
+    Env,
+    /// The builtin accesses the network.
+    Network,
+}
 
-    // Because we materialize the entire list, it's easy to cause out of memory
-    // with a single call to `range`. To prevent that, put an upper limit on the
-    // size. We use a lower limit when fuzzing because it runs with less memory,
-    // and also to keep the fuzzer fast. For production, empirically, a limit of
-    // 1e6 takes about a second to evaluate, so calling `std.range` with much
-    // larger values makes little sense anyway.
-    #[cfg(fuzzing)]
-    let max_len = 500;
-    #[cfg(not(fuzzing))]
-    let max_len = 1_000_000;
+impl Capability {
+    /// Explain why a builtin that needs this capability is absent from `std`.
+    pub fn disabled_help(&self) -> &'static str {
+        match self {
+            Capability::Pure => "This function is not available here.",
+            Capability::FsRead => "This function reads files, which is not enabled here.",
+            Capability::Env => {
+                "This function reads environment variables, which is not enabled here."
+            }
+            Capability::Network => {
+                "This function accesses the network, which is disabled by default. \
+                Enable it with '--allow-network'."
+            }
+        }
+    }
+}
 
-    if upper.saturating_sub(lower) > max_len {
-        let args_span = call.args[0].span.union(call.args[1].span);
-        return args_span
-            .error(concat! {
-                "Range "
-                Doc::string(lower.to_string()).with_markup(Markup::Number)
-                ".."
-                Doc::string(upper.to_string()).with_markup(Markup::Number)
-                " exceeds the maximum length of "
-                Doc::string(max_len.to_string()).with_markup(Markup::Number)
-                ". The list would require too much memory."
-            })
-            .err();
+/// The set of capabilities that builtins in `std` are allowed to use.
+///
+/// Builtins that need a capability outside of this set are not included in
+/// `std`. Pure builtins are always included.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Capabilities {
+    pub fs_read: bool,
+    pub env: bool,
+    pub network: bool,
+}
+
+impl Capabilities {
+    /// Allow only pure builtins, for fully hermetic evaluation.
+    pub fn pure() -> Capabilities {
+        Capabilities {
+            fs_read: false,
+            env: false,
+            network: false,
+        }
     }
 
-    let values: Vec<_> = range.map(Value::Int).collect();
-    Ok(Value::List(Rc::new(values)))
+    /// Return whether builtins that need the capability are allowed.
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Pure => true,
+            Capability::FsRead => self.fs_read,
+            Capability::Env => self.env,
+            Capability::Network => self.network,
+        }
+    }
 }
 
-/// Initialize the standard library.
-pub fn initialize() -> Value {
-    let mut builtins: BTreeMap<Value, Value> = BTreeMap::new();
+impl Default for Capabilities {
+    /// By default we allow reading files, which the sandbox already restricts.
+    fn default() -> Capabilities {
+        Capabilities {
+            fs_read: true,
+            env: false,
+            network: false,
+        }
+    }
+}
 
-    builtins.insert("range".into(), Value::BuiltinFunction(&STD_RANGE));
-    builtins.insert(
-        "read_file_utf8".into(),
-        Value::BuiltinFunction(&STD_READ_FILE_UTF8),
-    );
+/// All builtin functions in `std`, with the capability that they need.
+const BUILTINS: &[(&str, Capability, &BuiltinFunction)] = &[
+    (
+        "bytes_from_base64",
+        Capability::Pure,
+        &STD_BYTES_FROM_BASE64,
+    ),
+    ("bytes_from_hex", Capability::Pure, &STD_BYTES_FROM_HEX),
+    (
+        "char_from_codepoint",
+        Capability::Pure,
+        &STD_CHAR_FROM_CODEPOINT,
+    ),
+    ("fail", Capability::Pure, &STD_FAIL),
+    ("fetch_url", Capability::Network, &STD_FETCH_URL),
+    ("file_sha256", Capability::FsRead, &STD_FILE_SHA256),
+    ("format_float", Capability::Pure, &STD_FORMAT_FLOAT),
+    ("format_int", Capability::Pure, &STD_FORMAT_INT),
+    ("format_rcl", Capability::Pure, &STD_FORMAT_RCL),
+    ("is_null", Capability::Pure, &STD_IS_NULL),
+    ("is_bool", Capability::Pure, &STD_IS_BOOL),
+    ("is_int", Capability::Pure, &STD_IS_INT),
+    ("is_float", Capability::Pure, &STD_IS_FLOAT),
+    ("is_string", Capability::Pure, &STD_IS_STRING),
+    ("is_bytes", Capability::Pure, &STD_IS_BYTES),
+    ("is_list", Capability::Pure, &STD_IS_LIST),
+    ("is_set", Capability::Pure, &STD_IS_SET),
+    ("is_dict", Capability::Pure, &STD_IS_DICT),
+    ("is_function", Capability::Pure, &STD_IS_FUNCTION),
+    ("memoize", Capability::Pure, &STD_MEMOIZE),
+    ("parse_csv", Capability::Pure, &STD_PARSE_CSV),
+    ("partial", Capability::Pure, &STD_PARTIAL),
+    ("range", Capability::Pure, &STD_RANGE),
+    ("read_file_utf8", Capability::FsRead, &STD_READ_FILE_UTF8),
+    ("secret", Capability::Pure, &STD_SECRET),
+    ("template", Capability::Pure, &STD_TEMPLATE),
+    ("to_float", Capability::Pure, &STD_TO_FLOAT),
+    ("to_int", Capability::Pure, &STD_TO_INT),
+    ("type_of", Capability::Pure, &STD_TYPE_OF),
+];
+
+/// Namespaces in `std` that group related builtins, such as `std.net`.
+///
+/// The builtins in these namespaces are all pure.
+const NAMESPACES: &[(&str, &[(&str, &BuiltinFunction)])] = &[
+    (
+        "check",
+        &[
+            ("matches", &STD_CHECK_MATCHES),
+            ("one_of", &STD_CHECK_ONE_OF),
+            ("range", &STD_CHECK_RANGE),
+        ],
+    ),
+    (
+        "list",
+        &[
+            ("chunks", &STD_LIST_CHUNKS),
+            ("transpose", &STD_LIST_TRANSPOSE),
+            ("windows", &STD_LIST_WINDOWS),
+        ],
+    ),
+    (
+        "net",
+        &[
+            ("cidr_contains", &STD_NET_CIDR_CONTAINS),
+            ("cidr_hosts", &STD_NET_CIDR_HOSTS),
+            ("ip_add", &STD_NET_IP_ADD),
+            ("parse_ip", &STD_NET_PARSE_IP),
+        ],
+    ),
+    (
+        "semver",
+        &[
+            ("compare", &STD_SEMVER_COMPARE),
+            ("parse", &STD_SEMVER_PARSE),
+            ("satisfies", &STD_SEMVER_SATISFIES),
+        ],
+    ),
+    (
+        "url",
+        &[
+            ("build", &STD_URL_BUILD),
+            ("encode_query", &STD_URL_ENCODE_QUERY),
+            ("parse", &STD_URL_PARSE),
+        ],
+    ),
+];
+
+/// Return the capability that the builtin `std.{name}` needs, if it exists.
+pub fn builtin_capability(name: &str) -> Option<Capability> {
+    BUILTINS
+        .iter()
+        .find(|(builtin_name, _capability, _f)| *builtin_name == name)
+        .map(|(_name, capability, _f)| *capability)
+}
 
-    Value::Dict(Rc::new(builtins))
+/// Initialize the standard library with the builtins that the capabilities allow.
+pub fn initialize(capabilities: &Capabilities) -> Value {
+    let mut builtins: BTreeMap<Value, Value> = BUILTINS
+        .iter()
+        .filter(|(_name, capability, _f)| capabilities.allows(*capability))
+        .map(|(name, _capability, f)| ((*name).into(), Value::BuiltinFunction(f)))
+        .collect();
+    for (namespace, functions) in NAMESPACES {
+        let functions: BTreeMap<Value, Value> = functions
+            .iter()
+            .map(|(name, f)| ((*name).into(), Value::BuiltinFunction(f)))
+            .collect();
+        builtins.insert((*namespace).into(), Value::Dict(SmallDict::from(functions)));
+    }
+    Value::Dict(SmallDict::from(builtins))
 }
 
 builtin_method!("Dict.len", () -> Int, const DICT_LEN, builtin_dict_len);
@@ -211,8 +1945,26 @@ builtin_method!(
     builtin_dict_values
 );
 fn builtin_dict_values(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
-    let result = call.receiver.expect_dict().values().cloned().collect();
-    Ok(Value::List(Rc::new(result)))
+    let result: SmallList = call.receiver.expect_dict().values().cloned().collect();
+    Ok(Value::List(result))
+}
+
+builtin_method!(
+    "Dict.sorted_items",
+    () -> [Any],
+    const DICT_SORTED_ITEMS,
+    builtin_dict_sorted_items
+);
+fn builtin_dict_sorted_items(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
+    // Dicts iterate in key order, so the items are sorted already. If dicts
+    // ever preserve insertion order, this needs to sort explicitly.
+    let result: SmallList = call
+        .receiver
+        .expect_dict()
+        .iter()
+        .map(|(k, v)| Value::Tuple(Rc::new([k.clone(), v.clone()])))
+        .collect();
+    Ok(Value::List(result))
 }
 
 builtin_method!(
@@ -225,7 +1977,7 @@ fn builtin_dict_except(_eval: &mut Evaluator, call: MethodCall) -> Result<Value>
     let mut result = call.receiver.expect_dict().clone();
     let key = &call.call.args[0].value;
     result.remove(key);
-    Ok(Value::Dict(Rc::new(result)))
+    Ok(Value::Dict(result))
 }
 
 builtin_method!(
@@ -292,11 +2044,11 @@ builtin_method!(
 );
 fn builtin_list_group_by(eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
     let list = call.receiver.expect_list();
-    let result = builtin_group_by_impl(eval, call, "List.group_by", list)?
+    let result: SmallDict = builtin_group_by_impl(eval, call, "List.group_by", list)?
         .into_iter()
-        .map(|(k, vs)| (k, Value::List(Rc::new(vs))))
+        .map(|(k, vs)| (k, Value::List(SmallList::from(vs))))
         .collect();
-    Ok(Value::Dict(Rc::new(result)))
+    Ok(Value::Dict(result))
 }
 
 builtin_method!(
@@ -308,11 +2060,11 @@ builtin_method!(
 );
 fn builtin_set_group_by(eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
     let set = call.receiver.expect_set();
-    let result = builtin_group_by_impl(eval, call, "Set.group_by", set)?
+    let result: SmallDict = builtin_group_by_impl(eval, call, "Set.group_by", set)?
         .into_iter()
         .map(|(k, vs)| (k, Value::Set(Rc::new(vs.into_iter().collect()))))
         .collect();
-    Ok(Value::Dict(Rc::new(result)))
+    Ok(Value::Dict(result))
 }
 
 fn builtin_key_by_impl<'a, I: IntoIterator<Item = &'a Value>>(
@@ -322,13 +2074,14 @@ fn builtin_key_by_impl<'a, I: IntoIterator<Item = &'a Value>>(
     elements: I,
 ) -> Result<Value> {
     let method_span = call.method_span;
+    let limits = eval.loader.error_limits();
     let groups = builtin_group_by_impl(eval, call, name, elements)?;
     let mut result = BTreeMap::new();
     for (k, mut vs) in groups.into_iter() {
         if vs.len() > 1 {
             return method_span
                 .error(concat! {
-                    "The key " format_rcl(&k).into_owned() " is not unique."
+                    "The key " format_rcl_limited(&k, limits).into_owned() " is not unique."
                 })
                 .with_body(concat! {
                     "The following values use this key:"
@@ -336,7 +2089,7 @@ fn builtin_key_by_impl<'a, I: IntoIterator<Item = &'a Value>>(
                     Doc::HardBreak
                     indent! {
                         Doc::join(
-                            vs.iter().map(|v| format_rcl(v).into_owned()),
+                            vs.iter().map(|v| format_rcl_limited(v, limits).into_owned()),
                             Doc::HardBreak,
                         )
                     }
@@ -346,7 +2099,7 @@ fn builtin_key_by_impl<'a, I: IntoIterator<Item = &'a Value>>(
         result.insert(k, vs.pop().expect("Groups have at least one element."));
     }
 
-    Ok(Value::Dict(Rc::new(result)))
+    Ok(Value::Dict(SmallDict::from(result)))
 }
 
 builtin_method!(
@@ -449,6 +2202,7 @@ fn builtin_filter_impl<'a, I: IntoIterator<Item = &'a Value>, F: FnMut(Value)>(
     mut accept: F,
 ) -> Result<()> {
     let predicate_span = call.call.args[0].span;
+    let limits = eval.loader.error_limits();
     builtin_generic_map_impl(eval, call, "predicate", name, elements, |orig, result| {
         match result {
             Value::Bool(true) => accept(orig.clone()),
@@ -460,7 +2214,7 @@ fn builtin_filter_impl<'a, I: IntoIterator<Item = &'a Value>, F: FnMut(Value)>(
                         "Expected the predicate to return "
                         "Bool".format_type()
                         ", but it returned "
-                        format_rcl(&not_bool).into_owned()
+                        format_rcl_limited(&not_bool, limits).into_owned()
                         "."
                     })
                     .err();
@@ -478,6 +2232,7 @@ fn builtin_flat_map_impl<'a, I: IntoIterator<Item = &'a Value>, F: FnMut(Value)>
     mut accept: F,
 ) -> Result<()> {
     let predicate_span = call.call.args[0].span;
+    let limits = eval.loader.error_limits();
     builtin_generic_map_impl(
         eval,
         call,
@@ -501,7 +2256,7 @@ fn builtin_flat_map_impl<'a, I: IntoIterator<Item = &'a Value>, F: FnMut(Value)>
                         .error("Type mismatch.")
                         .with_body(concat! {
                         "Expected the mapping function to return a list or set, but it returned "
-                        format_rcl(&not_collection).into_owned()
+                        format_rcl_limited(&not_collection, limits).into_owned()
                         "."
                     })
                         .err();
@@ -523,7 +2278,7 @@ fn builtin_list_map(eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
     let list = call.receiver.expect_list();
     let mut result = Vec::with_capacity(list.len());
     builtin_map_impl(eval, call, "List.map", list, |v| result.push(v))?;
-    Ok(Value::List(Rc::new(result)))
+    Ok(Value::List(SmallList::from(result)))
 }
 
 builtin_method!(
@@ -537,7 +2292,7 @@ fn builtin_list_flat_map(eval: &mut Evaluator, call: MethodCall) -> Result<Value
     let list = call.receiver.expect_list();
     let mut result = Vec::with_capacity(list.len());
     builtin_flat_map_impl(eval, call, "List.flat_map", list, |v| result.push(v))?;
-    Ok(Value::List(Rc::new(result)))
+    Ok(Value::List(SmallList::from(result)))
 }
 
 builtin_method!(
@@ -551,7 +2306,7 @@ fn builtin_list_filter(eval: &mut Evaluator, call: MethodCall) -> Result<Value>
     let list = call.receiver.expect_list();
     let mut result = Vec::new();
     builtin_filter_impl(eval, call, "List.filter", list, |v| result.push(v))?;
-    Ok(Value::List(Rc::new(result)))
+    Ok(Value::List(SmallList::from(result)))
 }
 
 builtin_method!(
@@ -605,6 +2360,7 @@ fn builtin_set_filter(eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
 fn builtin_sum_impl<'a>(
     call: MethodCall,
     xs: impl IntoIterator<Item = &'a Value>,
+    limits: Limits,
 ) -> Result<Value> {
     let mut acc: i64 = 0;
     for x in xs {
@@ -620,7 +2376,9 @@ fn builtin_sum_impl<'a>(
             },
             not_int => {
                 let err = concat! {
-                    "Expected integers to add, but found " format_rcl(not_int).into_owned() "."
+                    "Expected integers to add, but found "
+                    format_rcl_limited(not_int, limits).into_owned()
+                    "."
                 };
                 return call.receiver_span.error(err).err();
             }
@@ -631,15 +2389,15 @@ fn builtin_sum_impl<'a>(
 }
 
 builtin_method!("List.sum", () -> Int, const LIST_SUM, builtin_list_sum);
-fn builtin_list_sum(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
+fn builtin_list_sum(eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
     let list = call.receiver.expect_list();
-    builtin_sum_impl(call, list)
+    builtin_sum_impl(call, list, eval.loader.error_limits())
 }
 
 builtin_method!("Set.sum", () -> Int, const SET_SUM, builtin_set_sum);
-fn builtin_set_sum(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
+fn builtin_set_sum(eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
     let set = call.receiver.expect_set();
-    builtin_sum_impl(call, set)
+    builtin_sum_impl(call, set, eval.loader.error_limits())
 }
 
 builtin_method!(
@@ -663,7 +2421,7 @@ fn builtin_string_split(_eval: &mut Evaluator, call: MethodCall) -> Result<Value
 
     let result: Vec<Value> = string.split(sep).map(Value::from).collect();
 
-    Ok(Value::List(Rc::new(result)))
+    Ok(Value::List(SmallList::from(result)))
 }
 
 builtin_method!(
@@ -677,7 +2435,98 @@ fn builtin_string_split_lines(_eval: &mut Evaluator, call: MethodCall) -> Result
 
     let result: Vec<Value> = string.lines().map(Value::from).collect();
 
-    Ok(Value::List(Rc::new(result)))
+    Ok(Value::List(SmallList::from(result)))
+}
+
+builtin_method!(
+    "String.strip_comments",
+    (prefix: String) -> String,
+    const STRING_STRIP_COMMENTS,
+    builtin_string_strip_comments
+);
+fn builtin_string_strip_comments(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
+    let string = call.receiver.expect_string();
+    let prefix_arg = &call.call.args[0];
+    let prefix = match &prefix_arg.value {
+        Value::String(s) if !s.is_empty() => s.as_ref(),
+        Value::String(..) => {
+            return prefix_arg
+                .span
+                .error("The comment prefix must not be empty.")
+                .err()
+        }
+        _ => return prefix_arg.span.error("Prefix must be a string.").err(),
+    };
+
+    let mut result = String::with_capacity(string.len());
+    for line in string.lines() {
+        let content = match line.find(prefix) {
+            Some(i) => &line[..i],
+            None => line,
+        };
+        let content = content.trim();
+        if !content.is_empty() {
+            result.push_str(content);
+            result.push('\n');
+        }
+    }
+
+    Ok(Value::String(result.into()))
+}
+
+builtin_method!(
+    "String.parse_key_value",
+    (separator: String) -> {String: String},
+    const STRING_PARSE_KEY_VALUE,
+    builtin_string_parse_key_value
+);
+fn builtin_string_parse_key_value(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
+    let string = call.receiver.expect_string();
+    let sep_arg = &call.call.args[0];
+    let separator = match &sep_arg.value {
+        Value::String(s) if !s.is_empty() => s.as_ref(),
+        Value::String(..) => return sep_arg.span.error("The separator must not be empty.").err(),
+        _ => return sep_arg.span.error("Separator must be a string.").err(),
+    };
+
+    let mut result = BTreeMap::new();
+    for (i, line) in string.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_error = || {
+            call.receiver_span
+                .error(concat! {
+                    "Invalid key-value pair on line "
+                    Doc::string((i + 1).to_string()).with_markup(Markup::Number)
+                    ":"
+                })
+                .with_body(format_rcl(&Value::from(line)).into_owned())
+        };
+        let (key, value) =
+            match line.split_once(separator) {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => return line_error()
+                    .with_help(concat! {
+                        "The line does not contain the separator. Expected lines of the form key"
+                        separator.to_string()
+                        "value. To skip comments, use 'String.strip_comments' first."
+                    })
+                    .err(),
+            };
+        if key.is_empty() {
+            return line_error().with_help("The key is empty.").err();
+        }
+        let key = Value::from(key);
+        if result.contains_key(&key) {
+            return line_error()
+                .with_help("The key occurs on an earlier line already, keys must be unique.")
+                .err();
+        }
+        result.insert(key, Value::from(value));
+    }
+
+    Ok(Value::Dict(SmallDict::from(result)))
 }
 
 builtin_method!(
@@ -771,7 +2620,21 @@ fn builtin_string_chars(_eval: &mut Evaluator, call: MethodCall) -> Result<Value
         result.push(Value::from(&string[i..]));
     }
 
-    Ok(Value::List(Rc::new(result)))
+    Ok(Value::List(SmallList::from(result)))
+}
+
+builtin_method!(
+    "String.graphemes",
+    () -> [String],
+    const STRING_GRAPHEMES,
+    builtin_string_graphemes
+);
+fn builtin_string_graphemes(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
+    let string = call.receiver.expect_string();
+    let result: SmallList = crate::grapheme::graphemes(string)
+        .map(Value::from)
+        .collect();
+    Ok(Value::List(result))
 }
 
 builtin_method!(
@@ -878,6 +2741,69 @@ fn builtin_string_to_uppercase(_eval: &mut Evaluator, call: MethodCall) -> Resul
     Ok(Value::String(string.to_uppercase().into()))
 }
 
+builtin_method!(
+    "String.to_utf8",
+    () -> Bytes,
+    const STRING_TO_UTF8,
+    builtin_string_to_utf8
+);
+fn builtin_string_to_utf8(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
+    let string = call.receiver.expect_string();
+    Ok(Value::Bytes(string.as_bytes().into()))
+}
+
+builtin_method!("Bytes.len", () -> Int, const BYTES_LEN, builtin_bytes_len);
+fn builtin_bytes_len(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
+    let bytes = call.receiver.expect_bytes();
+    Ok(Value::Int(bytes.len() as _))
+}
+
+builtin_method!(
+    "Bytes.parse_utf8",
+    () -> String,
+    const BYTES_PARSE_UTF8,
+    builtin_bytes_parse_utf8
+);
+fn builtin_bytes_parse_utf8(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
+    let bytes = call.receiver.expect_bytes();
+    match std::str::from_utf8(bytes) {
+        Ok(s) => Ok(Value::String(s.into())),
+        Err(err) => call
+            .receiver_span
+            .error("Failed to parse as UTF-8:")
+            .with_body(format_rcl(call.receiver).into_owned())
+            .with_help(format!(
+                "The byte at offset {} is not part of a valid UTF-8 sequence.",
+                err.valid_up_to()
+            ))
+            .err(),
+    }
+}
+
+builtin_method!(
+    "Bytes.to_base64",
+    () -> String,
+    const BYTES_TO_BASE64,
+    builtin_bytes_to_base64
+);
+fn builtin_bytes_to_base64(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
+    let bytes = call.receiver.expect_bytes();
+    Ok(Value::String(bytes::to_base64(bytes).into()))
+}
+
+builtin_method!(
+    "Bytes.to_hex",
+    () -> String,
+    const BYTES_TO_HEX,
+    builtin_bytes_to_hex
+);
+fn builtin_bytes_to_hex(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
+    let bytes = call.receiver.expect_bytes();
+    let mut result = String::with_capacity(bytes.len() * 2);
+    bytes::encode_hex(bytes, &mut result);
+    Ok(Value::String(result.into()))
+}
+
 builtin_method!(
     "List.fold",
     (
@@ -938,7 +2864,7 @@ builtin_method!(
     const LIST_JOIN,
     builtin_list_join
 );
-fn builtin_list_join(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
+fn builtin_list_join(eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
     let list = call.receiver.expect_list();
     let separator = &call.call.args[0];
 
@@ -947,10 +2873,10 @@ fn builtin_list_join(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
     let mut fragments = Vec::new();
 
     for (i, elem) in list.iter().enumerate() {
-        Evaluator::push_format_fragment(&mut fragments, call.receiver_span, elem)?;
+        eval.push_format_fragment(&mut fragments, call.receiver_span, elem)?;
 
         if i + 1 < list.len() {
-            Evaluator::push_format_fragment(&mut fragments, separator.span, &separator.value)?;
+            eval.push_format_fragment(&mut fragments, separator.span, &separator.value)?;
         }
     }
 
@@ -965,8 +2891,8 @@ builtin_method!(
 );
 fn builtin_list_reverse(_eval: &mut Evaluator, call: MethodCall) -> Result<Value> {
     let list = call.receiver.expect_list();
-    let reversed = list.iter().rev().cloned().collect();
-    Ok(Value::List(Rc::new(reversed)))
+    let reversed: SmallList = list.iter().rev().cloned().collect();
+    Ok(Value::List(reversed))
 }
 
 builtin_method!(
@@ -982,5 +2908,5 @@ fn builtin_list_enumerate(_eval: &mut Evaluator, call: MethodCall) -> Result<Val
         .zip(0..)
         .map(|(v, i)| (Value::Int(i), v.clone()))
         .collect();
-    Ok(Value::Dict(Rc::new(kv)))
+    Ok(Value::Dict(SmallDict::from(kv)))
 }
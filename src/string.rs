@@ -10,6 +10,7 @@
 use crate::cst::StringPart;
 use crate::error::{IntoError, Result};
 use crate::lexer::Escape;
+use crate::pprint::concat;
 use crate::source::Span;
 
 /// Convert an escape sequence into the string it represents.
@@ -28,12 +29,31 @@ pub fn unescape_into(input: &str, span: Span, escape: Escape, output: &mut Strin
             debug_assert_eq!(span_bytes.len(), 6);
             unescape_unicode(span, &span_str[2..], output)
         }
+        Escape::SurrogatePair => {
+            debug_assert_eq!(&span_str[..2], "\\u");
+            debug_assert_eq!(&span_str[6..8], "\\u");
+            debug_assert_eq!(span_bytes.len(), 12);
+            let high = u32::from_str_radix(&span_str[2..6], 16);
+            let low = u32::from_str_radix(&span_str[8..12], 16);
+            let (high, low) = (
+                high.expect("The lexer only admits hex digit bytes."),
+                low.expect("The lexer only admits hex digit bytes."),
+            );
+            let u = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+            output.push(char::from_u32(u).expect("Surrogate pairs encode valid code points."));
+            Ok(())
+        }
         Escape::UnicodeDelim => {
             let n = span_str.len();
             debug_assert_eq!(&span_str[..3], "\\u{");
             debug_assert_eq!(&span_str[n - 1..], "}");
             unescape_unicode(span, &span_str[3..n - 1], output)
         }
+        Escape::Hex2 => {
+            debug_assert_eq!(&span_str[..2], "\\x");
+            debug_assert_eq!(span_bytes.len(), 4);
+            unescape_hex(span, &span_str[2..], output)
+        }
     }
 }
 
@@ -56,13 +76,21 @@ fn unescape_single(span: Span, ch: u8, output: &mut String) -> Result<()> {
         // } never needs to be escaped, but for symmetry with { we allow it.
         b'}' => output.push('}'),
 
-        ch if ch.is_ascii_uppercase() => {
+        b'B' | b'F' | b'N' | b'R' | b'T' | b'U' | b'X' => {
             return span
                 .error("Invalid escape sequence.")
                 .with_help("Escape sequences are written lowercase.")
                 .err()
         }
-        _ => return span.error("Invalid escape sequence.").err(),
+        _ => {
+            return span
+                .error("Invalid escape sequence.")
+                .with_help(
+                    "To include a backslash, escape it as '\\\\', \
+                    or use a raw string such as r\"C:\\Users\".",
+                )
+                .err()
+        }
     }
     Ok(())
 }
@@ -86,11 +114,88 @@ fn unescape_unicode(span: Span, hex: &str, output: &mut String) -> Result<()> {
         }
         None => span
             .error("Invalid escape sequence: not a Unicode scalar value.")
-            .with_help("For code points beyond U+FFFF, use '\\u{...}' instead of a surrogate pair.")
+            .with_help(
+                "A surrogate must be part of a high-low pair. \
+                For code points beyond U+FFFF, you can also use '\\u{...}'.",
+            )
             .err(),
     }
 }
 
+fn unescape_hex(span: Span, hex: &str, output: &mut String) -> Result<()> {
+    let u = u8::from_str_radix(hex, 16).expect("The lexer only admits two hex digits.");
+    // Like in Rust, `\x` can only express ASCII. Above that, a single byte
+    // would not be valid UTF-8 on its own, and it would be ambiguous whether
+    // `\xff` means the byte or the code point U+00FF.
+    if u > 0x7f {
+        return span
+            .error("Invalid escape sequence: '\\x' escapes must be at most '\\x7f'.")
+            .with_help(concat! {
+                "For non-ASCII characters, use a Unicode escape sequence, e.g. '\\u{"
+                hex.to_ascii_lowercase()
+                "}'."
+            })
+            .err();
+    }
+    output.push(u as char);
+    Ok(())
+}
+
+/// Return whether a character can be written literally in a string literal.
+///
+/// Control characters, whitespace other than space, and characters that
+/// render invisibly are clearer as escape sequences, so they are not printable.
+fn is_printable(ch: char) -> bool {
+    use unicode_width::UnicodeWidthChar;
+    match ch {
+        ' ' => true,
+        // Soft hyphen and private use characters have a width, but they do
+        // not render as anything recognizable.
+        '\u{ad}' | '\u{e000}'..='\u{f8ff}' => false,
+        _ if ch.is_control() || ch.is_whitespace() => false,
+        _ => matches!(ch.width(), Some(w) if w > 0),
+    }
+}
+
+/// Return the shortest way to write an escape sequence, if it is shorter.
+///
+/// Escapes of printable characters become the character itself, and other
+/// characters get their single-character escape if they have one. Escapes
+/// that are needed, or that are already minimal, return `None`. In `"""`
+/// strings, quotes and spaces keep their escapes, because there they can be
+/// needed to end the string or to preserve trailing whitespace.
+pub fn minimize_escape(
+    input: &str,
+    span: Span,
+    escape: Escape,
+    is_format: bool,
+    is_triple: bool,
+) -> Option<String> {
+    let mut unescaped = String::new();
+    unescape_into(input, span, escape, &mut unescaped).ok()?;
+    let ch = unescaped.chars().next()?;
+
+    let minimal = match ch {
+        '"' if is_triple => return None,
+        ' ' if is_triple => return None,
+        '"' => r#"\""#.to_string(),
+        '\\' => r"\\".to_string(),
+        '{' if is_format => r"\{".to_string(),
+        '\n' => r"\n".to_string(),
+        '\r' => r"\r".to_string(),
+        '\t' => r"\t".to_string(),
+        '\x08' => r"\b".to_string(),
+        '\x0c' => r"\f".to_string(),
+        ch if is_printable(ch) => ch.to_string(),
+        _ => return None,
+    };
+
+    match minimal == span.resolve(input) {
+        true => None,
+        false => Some(minimal),
+    }
+}
+
 /// Return whether the string is a valid RCL identifier.
 pub fn is_identifier(s: &str) -> bool {
     let bytes = s.as_bytes();
@@ -117,8 +222,42 @@ pub fn is_identifier(s: &str) -> bool {
     !crate::lexer::is_keyword(s)
 }
 
+/// How to write characters outside of the ASCII range in a quoted string.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum NonAscii {
+    /// Write the character as-is, the output is UTF-8.
+    Verbatim,
+
+    /// Escape as `\uXXXX`, and code points beyond U+FFFF as a surrogate pair.
+    Utf16,
+
+    /// Escape as `\uXXXX`, and code points beyond U+FFFF as `\UXXXXXXXX`.
+    Utf32,
+}
+
 /// Escape a string for use inside a json string literal.
 pub fn escape_json(str: &str, into: &mut String) {
+    escape_quoted(str, NonAscii::Verbatim, into)
+}
+
+/// Escape a string for use inside a json string literal, with ASCII-only output.
+///
+/// Code points beyond U+FFFF are written as a high-low surrogate pair, the only
+/// way json can express them with `\u` escapes. Rust strings cannot contain
+/// lone surrogates, so the output never contains one either.
+pub fn escape_json_ascii(str: &str, into: &mut String) {
+    escape_quoted(str, NonAscii::Utf16, into)
+}
+
+/// Escape a string for use inside a YAML double-quoted scalar, with ASCII-only output.
+///
+/// YAML does not allow surrogates in `\u` escapes, so code points beyond
+/// U+FFFF are written as a single `\U` escape instead.
+pub fn escape_yaml_ascii(str: &str, into: &mut String) {
+    escape_quoted(str, NonAscii::Utf32, into)
+}
+
+fn escape_quoted(str: &str, non_ascii: NonAscii, into: &mut String) {
     use std::fmt::Write;
 
     into.reserve(str.len());
@@ -134,7 +273,18 @@ pub fn escape_json(str: &str, into: &mut String) {
             '\\' => into.push_str(r#"\\"#),
             ch if ch.is_ascii_control() => write!(into, "\\u{:04x}", ch as u32)
                 .expect("Writing into &mut String does not fail."),
-            ch => into.push(ch),
+            ch if ch.is_ascii() || non_ascii == NonAscii::Verbatim => into.push(ch),
+            ch if (ch as u32) <= 0xffff => write!(into, "\\u{:04x}", ch as u32)
+                .expect("Writing into &mut String does not fail."),
+            ch if non_ascii == NonAscii::Utf32 => write!(into, "\\U{:08x}", ch as u32)
+                .expect("Writing into &mut String does not fail."),
+            ch => {
+                let mut units = [0_u16; 2];
+                for unit in ch.encode_utf16(&mut units) {
+                    write!(into, "\\u{:04x}", unit)
+                        .expect("Writing into &mut String does not fail.");
+                }
+            }
         }
     }
 }
@@ -242,6 +392,23 @@ mod test {
         assert_eq!(unescape(r#""a\u{0008}\u{8}c""#).unwrap(), "a\x08\x08c");
     }
 
+    #[test]
+    fn unescape_handles_surrogate_pairs() {
+        assert_eq!(unescape(r#""\ud83d\ude00""#).unwrap(), "\u{1f600}");
+        assert_eq!(unescape(r#""a\uD834\uDD1Eb""#).unwrap(), "a\u{1d11e}b");
+        assert!(unescape(r#""\ud83d""#).is_err());
+        assert!(unescape(r#""\ude00\ud83d""#).is_err());
+        assert!(unescape(r#""\ud83d\u0041""#).is_err());
+    }
+
+    #[test]
+    fn unescape_handles_hex_escape_sequences() {
+        assert_eq!(unescape(r#""\x41\x0a\x7F""#).unwrap(), "A\n\x7f");
+        assert!(unescape(r#""\x80""#).is_err());
+        assert!(unescape(r#""\x4""#).is_err());
+        assert!(unescape(r#""\x""#).is_err());
+    }
+
     #[test]
     fn unescape_does_not_crash_on_early_end() {
         // This is a regression test.
@@ -274,6 +441,32 @@ mod test {
         )
     }
 
+    #[test]
+    fn escape_json_preserves_astral_characters() {
+        // Json strings are UTF-8, so there is no need for surrogate pairs,
+        // and code points beyond U+FFFF must come out as a single character.
+        assert_eq!(escape_json("\u{1f600}\u{1d11e}"), "\u{1f600}\u{1d11e}");
+        assert_eq!(escape_json("\u{fe0f}\u{1f3fd}\n"), "\u{fe0f}\u{1f3fd}\\n");
+    }
+
+    #[test]
+    fn escape_json_ascii_escapes_non_ascii() {
+        let mut out = String::new();
+        super::escape_json_ascii("a\u{e9}\u{fffd}\u{1f600}\n", &mut out);
+        assert_eq!(out, r#"a\u00e9\ufffd\ud83d\ude00\n"#);
+        assert_eq!(
+            unescape(&format!("\"{out}\"")).unwrap(),
+            "a\u{e9}\u{fffd}\u{1f600}\n"
+        );
+    }
+
+    #[test]
+    fn escape_yaml_ascii_escapes_astral_characters_without_surrogates() {
+        let mut out = String::new();
+        super::escape_yaml_ascii("\u{e9}\u{1d11e}\"", &mut out);
+        assert_eq!(out, r#"\u00e9\U0001d11e\""#);
+    }
+
     // Note, the main test for json escaping is the `escapes` fuzzer.
 
     #[test]
@@ -0,0 +1,398 @@
+// RCL -- A reasonable configuration language.
+// Copyright 2024 Ruud van Asseldonk
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! A mustache-like template renderer, used by `std.template`.
+//!
+//! Templates are text with tags enclosed in `{{` and `}}`:
+//!
+//!  * `{{name}}` inserts the value of `name`. The name can be a dotted path
+//!    into dicts, such as `{{server.host}}`, and `{{.}}` refers to the current
+//!    element in a section. A tag can select an escaping mode, such as
+//!    `{{name | shell}}`, see [`Escape`].
+//!  * `{{#name}}…{{/name}}` renders its body once for every element of a list,
+//!    or once when the value is a dict or `true`. It renders nothing for an
+//!    empty list, `false`, or `null`. `{{^name}}…{{/name}}` is the inverse.
+//!  * `{{! comment}}` renders nothing.
+//!
+//! Section and comment tags that are alone on their line do not leave a blank
+//! line behind.
+//!
+//! Unlike mustache, referring to an unknown name is an error, and so is
+//! inserting a value that is not a string, integer, or boolean.
+
+use std::rc::Rc;
+
+use crate::runtime::Value;
+
+/// An error in a template, together with the 1-based line number of the tag.
+#[derive(Debug, Eq, PartialEq)]
+pub struct TemplateError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// How to escape a value that gets inserted into the template.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Escape {
+    /// Insert the value as-is. This is the default.
+    Raw,
+    /// Insert the value as a single-quoted shell word.
+    Shell,
+    /// Insert the value as a json string literal, including the quotes.
+    Json,
+}
+
+#[derive(Debug)]
+enum Node<'a> {
+    Text(&'a str),
+    Var {
+        line: usize,
+        path: &'a str,
+        escape: Escape,
+    },
+    Section {
+        line: usize,
+        path: &'a str,
+        inverted: bool,
+        body: Vec<Node<'a>>,
+    },
+}
+
+/// Render the template with the given bindings, which must be a dict.
+pub fn render(template: &str, bindings: &Value) -> Result<String, TemplateError> {
+    let nodes = parse(template)?;
+    let mut out = String::with_capacity(template.len());
+    let mut scopes = vec![bindings];
+    render_nodes(&nodes, &mut scopes, &mut out)?;
+    Ok(out)
+}
+
+fn error<T>(line: usize, message: impl Into<String>) -> Result<T, TemplateError> {
+    Err(TemplateError {
+        line,
+        message: message.into(),
+    })
+}
+
+/// Parse the template into a tree of nodes, where sections hold their body.
+fn parse(template: &str) -> Result<Vec<Node>, TemplateError> {
+    // The stack of sections that are currently open, with the nodes that
+    // precede them. The bottom entry holds the top-level nodes.
+    let mut stack: Vec<(usize, &str, bool, Vec<Node>)> = vec![(0, "", false, Vec::new())];
+    let mut rest = template;
+    let mut line = 1;
+    // Whether `rest` starts at the start of a line.
+    let mut at_line_start = true;
+
+    while let Some(start) = rest.find("{{") {
+        let (mut text, after_open) = (&rest[..start], &rest[start + 2..]);
+        line += text.matches('\n').count();
+        let end = match after_open.find("}}") {
+            Some(end) => end,
+            None => return error(line, "Unclosed '{{', expected '}}'."),
+        };
+        let tag = &after_open[..end];
+        let tag_line = line;
+        line += tag.matches('\n').count();
+        rest = &after_open[end + 2..];
+
+        let (sigil, name) = match tag.trim_start().chars().next() {
+            Some(ch @ ('#' | '^' | '/' | '!')) => (Some(ch), tag.trim_start()[1..].trim()),
+            _ => (None, tag.trim()),
+        };
+
+        // Like in mustache, a section or comment tag that is alone on its line
+        // removes the line entirely, so it doesn't leave blank lines behind.
+        let indent = match text.rfind('\n') {
+            Some(i) => Some(&text[i + 1..]),
+            None if at_line_start => Some(text),
+            None => None,
+        };
+        let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        at_line_start = false;
+        if let (Some(_), Some(indent)) = (sigil, indent) {
+            if indent.trim().is_empty() && rest[..line_end].trim().is_empty() {
+                text = &text[..text.len() - indent.len()];
+                line += rest[..line_end].matches('\n').count();
+                rest = &rest[line_end..];
+                at_line_start = true;
+            }
+        }
+
+        if !text.is_empty() {
+            stack.last_mut().unwrap().3.push(Node::Text(text));
+        }
+        match sigil {
+            Some('!') => continue,
+            Some('#' | '^') => {
+                let path = parse_path(tag_line, name)?;
+                stack.push((tag_line, path, sigil == Some('^'), Vec::new()));
+            }
+            Some(_close) => {
+                let path = parse_path(tag_line, name)?;
+                if stack.len() == 1 {
+                    return error(
+                        tag_line,
+                        format!("Found '{{{{/{path}}}}}', but no section is open."),
+                    );
+                }
+                let (open_line, open_path, inverted, body) = stack.pop().unwrap();
+                if open_path != path {
+                    return error(
+                        tag_line,
+                        format!(
+                            "Found '{{{{/{path}}}}}', but the section '{open_path}' \
+                            opened on line {open_line} is still open."
+                        ),
+                    );
+                }
+                stack.last_mut().unwrap().3.push(Node::Section {
+                    line: open_line,
+                    path,
+                    inverted,
+                    body,
+                });
+            }
+            None => {
+                let (path, escape) = match name.split_once('|') {
+                    None => (name, Escape::Raw),
+                    Some((path, mode)) => {
+                        let escape = match mode.trim() {
+                            "raw" => Escape::Raw,
+                            "shell" => Escape::Shell,
+                            "json" => Escape::Json,
+                            mode => {
+                                return error(
+                                    tag_line,
+                                    format!(
+                                        "Unknown escaping mode '{mode}', \
+                                        expected 'raw', 'shell', or 'json'."
+                                    ),
+                                )
+                            }
+                        };
+                        (path.trim(), escape)
+                    }
+                };
+                let path = parse_path(tag_line, path)?;
+                stack.last_mut().unwrap().3.push(Node::Var {
+                    line: tag_line,
+                    path,
+                    escape,
+                });
+            }
+        }
+    }
+
+    if !rest.is_empty() {
+        stack.last_mut().unwrap().3.push(Node::Text(rest));
+    }
+
+    let (open_line, open_path, _, nodes) = stack.pop().unwrap();
+    match stack.is_empty() {
+        true => Ok(nodes),
+        false => error(
+            open_line,
+            format!("Section '{open_path}' is not closed, expected '{{{{/{open_path}}}}}'."),
+        ),
+    }
+}
+
+/// Validate the name in a tag. It is either `.` or a dotted path of names.
+fn parse_path(line: usize, path: &str) -> Result<&str, TemplateError> {
+    if path == "." {
+        return Ok(path);
+    }
+    let is_valid = path.split('.').all(|part| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|ch| ch.is_alphanumeric() || ch == '_' || ch == '-')
+    });
+    match is_valid {
+        true => Ok(path),
+        false => error(line, format!("Invalid name '{path}' in tag.")),
+    }
+}
+
+/// Resolve a path against the innermost scope that contains its first name.
+fn lookup<'a>(line: usize, scopes: &[&'a Value], path: &str) -> Result<&'a Value, TemplateError> {
+    let top = *scopes.last().expect("There is always a scope.");
+    if path == "." {
+        return Ok(top);
+    }
+    let mut parts = path.split('.');
+    let first = parts.next().expect("Split yields at least one part.");
+    let key = Value::String(first.into());
+    let mut value = match scopes.iter().rev().find_map(|scope| match scope {
+        Value::Dict(dict) => dict.get(&key),
+        _ => None,
+    }) {
+        Some(value) => value,
+        None => return error(line, format!("Unknown name '{first}'.")),
+    };
+    for part in parts {
+        let key = Value::String(part.into());
+        value = match value {
+            Value::Dict(dict) => match dict.get(&key) {
+                Some(v) => v,
+                None => return error(line, format!("Unknown name '{part}' in '{path}'.")),
+            },
+            other => {
+                return error(
+                    line,
+                    format!(
+                        "Cannot look up '{part}' in '{path}', the value is a {}, not a Dict.",
+                        other.type_name()
+                    ),
+                )
+            }
+        };
+    }
+    Ok(value)
+}
+
+fn render_nodes<'a>(
+    nodes: &[Node],
+    scopes: &mut Vec<&'a Value>,
+    out: &mut String,
+) -> Result<(), TemplateError> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Var { line, path, escape } => {
+                let value = lookup(*line, scopes, path)?;
+                let text: Rc<str> = match value {
+                    Value::String(s) => s.to_rc(),
+                    Value::Int(i) => i.to_string().into(),
+                    Value::Float(x) => x.to_string().into(),
+                    Value::Bool(b) => (if *b { "true" } else { "false" }).into(),
+                    other => {
+                        return error(
+                            *line,
+                            format!(
+                                "Cannot insert '{path}', the value is a {}. \
+                                Only strings, numbers, and booleans can be inserted.",
+                                other.type_name()
+                            ),
+                        )
+                    }
+                };
+                match escape {
+                    Escape::Raw => out.push_str(&text),
+                    Escape::Shell => {
+                        out.push('\'');
+                        out.push_str(&text.replace('\'', "'\\''"));
+                        out.push('\'');
+                    }
+                    Escape::Json => {
+                        out.push('"');
+                        crate::string::escape_json(&text, out);
+                        out.push('"');
+                    }
+                }
+            }
+            Node::Section {
+                line,
+                path,
+                inverted,
+                body,
+            } => {
+                let value = lookup(*line, scopes, path)?;
+                let elements: Vec<&'a Value> = match value {
+                    Value::Null | Value::Bool(false) => Vec::new(),
+                    Value::Bool(true) => vec![scopes[scopes.len() - 1]],
+                    Value::List(xs) => xs.iter().collect(),
+                    Value::Set(xs) => xs.iter().collect(),
+                    other => vec![other],
+                };
+                if *inverted {
+                    if elements.is_empty() {
+                        render_nodes(body, scopes, out)?;
+                    }
+                    continue;
+                }
+                for element in elements {
+                    scopes.push(element);
+                    let result = render_nodes(body, scopes, out);
+                    scopes.pop();
+                    result?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::render;
+    use crate::runtime::Value;
+    use crate::small_collection::{SmallDict, SmallList};
+    use std::collections::BTreeMap;
+
+    fn dict(pairs: Vec<(&str, Value)>) -> Value {
+        let dict: BTreeMap<Value, Value> = pairs
+            .into_iter()
+            .map(|(k, v)| (Value::String(k.into()), v))
+            .collect();
+        Value::Dict(SmallDict::from(dict))
+    }
+
+    #[test]
+    fn render_inserts_and_escapes_values() {
+        let bindings = dict(vec![
+            ("name", "it's \"web\"".into()),
+            ("port", Value::Int(80)),
+            ("server", dict(vec![("tls", Value::Bool(true))])),
+        ]);
+        let render = |t: &str| render(t, &bindings).unwrap();
+        assert_eq!(render("listen {{ port }};"), "listen 80;");
+        assert_eq!(render("{{name}}"), "it's \"web\"");
+        assert_eq!(render("echo {{name | shell}}"), "echo 'it'\\''s \"web\"'");
+        assert_eq!(render("{{ name|json }}"), "\"it's \\\"web\\\"\"");
+        assert_eq!(render("tls={{server.tls}}{{! ignored }}"), "tls=true");
+    }
+
+    #[test]
+    fn render_handles_sections() {
+        let hosts = Value::List(SmallList::from(vec![
+            dict(vec![("host", "a".into())]),
+            dict(vec![("host", "b".into())]),
+        ]));
+        let names = Value::List(SmallList::from(vec!["x".into(), "y".into()]));
+        let bindings = dict(vec![
+            ("hosts", hosts),
+            ("names", names),
+            ("none", Value::List(SmallList::new())),
+            ("port", Value::Int(1)),
+        ]);
+        let render = |t: &str| render(t, &bindings).unwrap();
+        assert_eq!(render("{{#hosts}}{{host}}:{{port}} {{/hosts}}"), "a:1 b:1 ");
+        assert_eq!(render("{{#names}}[{{.}}]{{/names}}"), "[x][y]");
+        assert_eq!(
+            render("{{#none}}x{{/none}}{{^none}}empty{{/none}}"),
+            "empty"
+        );
+        assert_eq!(
+            render("{{! comment }}\n  {{#names}}\n  - {{.}}\n  {{/names}}\nend"),
+            "  - x\n  - y\nend"
+        );
+    }
+
+    #[test]
+    fn render_reports_errors_with_line() {
+        let bindings = dict(vec![("list", Value::List(SmallList::new()))]);
+        let err = |t: &str| render(t, &bindings).unwrap_err();
+        assert_eq!(err("a\n{{missing}}").line, 2);
+        assert_eq!(err("a\n\n{{#list}}\n").line, 3);
+        assert_eq!(err("{{list}}").line, 1);
+        assert_eq!(err("{{#list}}{{/other}}").line, 1);
+        assert_eq!(err("x\n{{list | html}}").line, 2);
+        assert_eq!(err("x\ny {{ list").line, 2);
+    }
+}
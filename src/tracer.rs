@@ -5,7 +5,7 @@
 // you may not use this file except in compliance with the License.
 // A copy of the License has been included in the root of the repository.
 
-//! Runtime configurable behavior for trace messages.
+//! Runtime configurable behavior for trace messages and warnings.
 
 use crate::error::highlight_span;
 use crate::fmt_rcl::format_rcl;
@@ -14,6 +14,37 @@ use crate::pprint::{self, concat, Doc};
 use crate::runtime::Value;
 use crate::source::{Inputs, Span};
 
+/// A warning about a location in a document.
+#[derive(Clone)]
+pub struct Warning {
+    pub span: Span,
+    pub message: Doc<'static>,
+
+    /// Related locations, such as an earlier definition, to print after it.
+    pub notes: Vec<(Span, Doc<'static>)>,
+}
+
+impl Warning {
+    pub fn new<M>(span: Span, message: M) -> Warning
+    where
+        Doc<'static>: From<M>,
+    {
+        Warning {
+            span,
+            message: message.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_note<M>(mut self, at: Span, note: M) -> Warning
+    where
+        Doc<'static>: From<M>,
+    {
+        self.notes.push((at, note.into()));
+        self
+    }
+}
+
 /// Configurable behavior for trace messages.
 ///
 /// In pure contexts (such as the fuzzer, tests, or possibly when using RCL as a
@@ -27,6 +58,9 @@ use crate::source::{Inputs, Span};
 /// tracer that ignores trace messages.
 pub trait Tracer {
     fn trace(&mut self, inputs: &Inputs, span: Span, message: &Value);
+
+    /// Report a warning, such as an implicit conversion in strict warn mode.
+    fn warn(&mut self, inputs: &Inputs, warning: Warning);
 }
 
 /// Tracer that writes messages to stderr.
@@ -43,18 +77,8 @@ impl StderrTracer {
             markup: markup.unwrap_or_else(|| MarkupMode::default_for_fd(&stderr)),
         }
     }
-}
 
-impl Tracer for StderrTracer {
-    fn trace(&mut self, inputs: &Inputs, span: Span, message: &Value) {
-        let doc = concat! {
-            highlight_span(inputs, span, Markup::Trace)
-            Doc::from("Trace:").with_markup(Markup::Trace)
-            " "
-            format_rcl(message)
-            Doc::HardBreak
-            Doc::HardBreak
-        };
+    fn print(&self, doc: Doc) {
         let doc_str = doc.println(&self.config);
         let mut out = std::io::stderr().lock();
         let res = doc_str.write_bytes(self.markup, &mut out);
@@ -66,6 +90,91 @@ impl Tracer for StderrTracer {
     }
 }
 
+/// Format a trace message the way it is reported on the command line.
+fn trace_doc<'a>(inputs: &'a Inputs, span: Span, message: &'a Value) -> Doc<'a> {
+    concat! {
+        highlight_span(inputs, span, Markup::Trace)
+        Doc::from("Trace:").with_markup(Markup::Trace)
+        " "
+        format_rcl(message)
+        Doc::HardBreak
+        Doc::HardBreak
+    }
+}
+
+/// Format a warning the way it is reported on the command line.
+fn warn_doc<'a>(inputs: &'a Inputs, warning: Warning) -> Doc<'a> {
+    let mut result = vec![
+        highlight_span(inputs, warning.span, Markup::Warning),
+        Doc::from("Warning:").with_markup(Markup::Warning),
+        " ".into(),
+        warning.message,
+        Doc::HardBreak,
+        Doc::HardBreak,
+    ];
+    for (note_span, note_message) in warning.notes {
+        result.push(highlight_span(inputs, note_span, Markup::Warning));
+        result.push(Doc::from("Note:").with_markup(Markup::Warning));
+        result.push(" ".into());
+        result.push(note_message);
+        result.push(Doc::HardBreak);
+        result.push(Doc::HardBreak);
+    }
+    Doc::Concat(result)
+}
+
+impl Tracer for StderrTracer {
+    fn trace(&mut self, inputs: &Inputs, span: Span, message: &Value) {
+        self.print(trace_doc(inputs, span, message));
+    }
+
+    fn warn(&mut self, inputs: &Inputs, warning: Warning) {
+        self.print(warn_doc(inputs, warning));
+    }
+}
+
+/// Tracer that renders messages into a buffer, to be printed later.
+///
+/// When `rcl check` evaluates documents on multiple threads, messages are
+/// buffered per document, so they can be printed in the order of the inputs.
+pub struct BufferTracer {
+    config: pprint::Config,
+    markup: MarkupMode,
+    buffer: Vec<u8>,
+}
+
+impl BufferTracer {
+    pub fn new(markup: MarkupMode) -> BufferTracer {
+        BufferTracer {
+            config: pprint::Config { width: 80 },
+            markup,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Return the rendered messages, and leave the buffer empty.
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buffer)
+    }
+
+    fn print(&mut self, doc: Doc) {
+        let doc_str = doc.println(&self.config);
+        doc_str
+            .write_bytes(self.markup, &mut self.buffer)
+            .expect("Writing to a Vec does not fail.");
+    }
+}
+
+impl Tracer for BufferTracer {
+    fn trace(&mut self, inputs: &Inputs, span: Span, message: &Value) {
+        self.print(trace_doc(inputs, span, message));
+    }
+
+    fn warn(&mut self, inputs: &Inputs, warning: Warning) {
+        self.print(warn_doc(inputs, warning));
+    }
+}
+
 /// Tracer that ignores its messages.
 ///
 /// Intended for use by the fuzzer.
@@ -74,5 +183,6 @@ pub struct VoidTracer;
 // coverage:off -- The void tracer is only used by the fuzzer, not production code.
 impl Tracer for VoidTracer {
     fn trace(&mut self, _inputs: &Inputs, _span: Span, _message: &Value) {}
+    fn warn(&mut self, _inputs: &Inputs, _warning: Warning) {}
 }
 // coverage:on
@@ -8,6 +8,8 @@
 //! A type diff is the result of a subtype check.
 //!
 //! This module contains the definitions, and machinery for printing type diffs.
+use std::rc::Rc;
+
 use crate::error::{IntoError, Result};
 use crate::pprint::{concat, indent, Doc};
 use crate::source::Span;
@@ -31,6 +33,12 @@ pub enum Mismatch {
     /// Both sides are a dict, but the key or value (or both) have issues.
     Dict(Box<TypeDiff<SourcedType>>, Box<TypeDiff<SourcedType>>),
 
+    /// Both sides are tuples of the same length, but some elements have issues.
+    Tuple(Vec<TypeDiff<SourcedType>>),
+
+    /// Both sides are tagged with the same tag, but the payload has an issue.
+    Tagged(Rc<str>, Box<TypeDiff<SourcedType>>),
+
     /// Both sides are functions of the same arity, but args or result have issues.
     Function(Vec<TypeDiff<FunctionArg>>, Box<TypeDiff<SourcedType>>),
 }
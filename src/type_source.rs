@@ -40,6 +40,12 @@ pub enum Source {
     /// An integer is required due to indexing into a list.
     IndexList,
 
+    /// An integer is required due to indexing into a tuple.
+    IndexTuple,
+
+    /// An integer is required due to indexing into a string.
+    IndexString,
+
     /// The type is part of the expected type for build files for `rcl build`.
     BuildFile(&'static str),
 }
@@ -58,6 +64,8 @@ impl Source {
             Source::Builtin => None,
             Source::Condition => None,
             Source::IndexList => None,
+            Source::IndexTuple => None,
+            Source::IndexString => None,
             Source::BuildFile(..) => None,
         }
     }
@@ -12,16 +12,22 @@
 //! but also for the type `List[String]`. Therefore we check whether a value
 //! _fits_ a particular type, and that same value may fit multiple types.
 
+use std::collections::BTreeMap;
 use std::rc::Rc;
 
-use crate::ast::{BinOp, Expr, Ident, Seq, Stmt, Type as AType, UnOp, Yield};
+use crate::ast::{
+    BinOp, Expr, Ident, ImportFormat, Pattern, Seq, Stmt, Type as AType, UnOp, Yield,
+};
 use crate::error::{IntoError, Result};
-use crate::fmt_type::format_type;
+use crate::fmt_rcl::format_rcl;
+use crate::fmt_type::{format_tag, format_type};
 use crate::pprint::{concat, indent, Doc};
+use crate::runtime::Value;
 use crate::source::Span;
-use crate::type_diff::{report_type_mismatch, Typed};
+use crate::tracer::Warning;
+use crate::type_diff::{report_type_mismatch, TypeDiff, Typed};
 use crate::type_source::Source;
-use crate::types::{Dict, Function, FunctionArg, Side, SourcedType, Type, Union};
+use crate::types::{Dict, Function, FunctionArg, Side, SourcedType, Tagged, Type, Union};
 
 pub type Env = crate::env::Env<SourcedType>;
 
@@ -38,7 +44,12 @@ fn get_primitive_type(name: &str) -> Option<Type> {
     match name {
         "Any" => Some(Type::Any),
         "Bool" => Some(Type::Bool),
+        "Bytes" => Some(Type::Bytes),
         "Int" => Some(Type::Int),
+        "Float" => Some(Type::Float),
+        // `Never` is an alias for `Void`, for people coming from languages
+        // that use that name for the bottom type.
+        "Never" => Some(Type::Void),
         "Null" => Some(Type::Null),
         "String" => Some(Type::String),
         "Void" => Some(Type::Void),
@@ -88,6 +99,16 @@ fn eval_type_expr(expr: &AType) -> Result<SourcedType> {
                         })
                         .err()
                 },
+                "Tuple" => {
+                    span
+                        .error("Expected a concrete type, but found uninstantiated generic type.")
+                        .with_help(concat! {
+                            "'" Doc::highlight("Tuple") "' without type parameters cannot be used directly."
+                            Doc::SoftBreak
+                            "Specify the element types, e.g. '" Doc::highlight("Tuple[Int, String]") "'."
+                        })
+                        .err()
+                },
                 "Union" => {
                     span
                         .error("Expected a concrete type, but found uninstantiated union type.")
@@ -101,6 +122,24 @@ fn eval_type_expr(expr: &AType) -> Result<SourcedType> {
                 _ => span.error("Unknown type.").err(),
             }
         }
+        AType::Tagged { span, tag, value } => {
+            let value_type = match value {
+                Some(value) => eval_type_expr(value)?,
+                None => SourcedType {
+                    type_: Type::Null,
+                    source: Source::Annotation(*span),
+                },
+            };
+            let tagged = Tagged {
+                tag: tag.clone(),
+                value: value_type,
+            };
+            let styp = SourcedType {
+                type_: Type::Tagged(Rc::new(tagged)),
+                source: Source::Annotation(*span),
+            };
+            Ok(styp)
+        }
         AType::Function { span, args, result } => {
             let args_types = args
                 .iter()
@@ -181,6 +220,7 @@ fn eval_type_apply(name_span: Span, name: &str, args: &[SourcedType]) -> Result<
                 })
                 .err(),
         },
+        "Tuple" => Ok(Type::Tuple(args.into())),
         "Union" => {
             let members = match args.len() {
                 0 => {
@@ -239,7 +279,141 @@ fn type_int_index() -> &'static SourcedType {
     }
 }
 
+/// Construct a `SourcedType` for a `Int` for tuple indexing.
+fn type_int_index_tuple() -> &'static SourcedType {
+    &SourcedType {
+        type_: Type::Int,
+        source: Source::IndexTuple,
+    }
+}
+
+/// Construct a `SourcedType` for a `Int` for string indexing.
+fn type_int_index_string() -> &'static SourcedType {
+    &SourcedType {
+        type_: Type::Int,
+        source: Source::IndexString,
+    }
+}
+
+/// Return the type of a literal in a pattern.
+fn literal_type(literal: &Value) -> Type {
+    match literal {
+        Value::Null => Type::Null,
+        Value::Bool(..) => Type::Bool,
+        Value::Int(..) => Type::Int,
+        Value::Float(..) => Type::Float,
+        Value::String(..) => Type::String,
+        _ => unreachable!("Patterns only contain null, bool, int, and string literals."),
+    }
+}
+
+/// Return the payload type of values of type `value_type` tagged `tag`.
+///
+/// Returns `None` if values of this type can never have the tag.
+fn narrow_tag(value_type: &SourcedType, tag: &str) -> Option<SourcedType> {
+    match &value_type.type_ {
+        Type::Any => Some(type_any().clone()),
+        Type::Tagged(t) if t.tag.as_ref() == tag => Some(t.value.clone()),
+        Type::Union(u) => u.members.iter().find_map(|m| narrow_tag(m, tag)),
+        _ => None,
+    }
+}
+
+/// Return the cases of `value_type` that none of the patterns match.
+///
+/// Returns `None` if the type has too many values to enumerate the cases,
+/// for example `Int`, unless an irrefutable pattern matches all values.
+fn missing_cases(value_type: &SourcedType, patterns: &[&Pattern]) -> Option<Vec<String>> {
+    if patterns.iter().any(|p| p.is_irrefutable()) {
+        return Some(Vec::new());
+    }
+    let has_literal = |v: &Value| {
+        patterns
+            .iter()
+            .any(|p| matches!(p, Pattern::Literal(lit) if lit == v))
+    };
+    let mut missing = Vec::new();
+    match &value_type.type_ {
+        Type::Void => {}
+        Type::Null => {
+            if !has_literal(&Value::Null) {
+                missing.push("null".to_string());
+            }
+        }
+        Type::Bool => {
+            for b in [true, false] {
+                if !has_literal(&Value::Bool(b)) {
+                    missing.push(b.to_string());
+                }
+            }
+        }
+        Type::Tagged(t) => {
+            let mut inners = Vec::new();
+            for pattern in patterns {
+                match pattern {
+                    Pattern::Tag { tag, inner: None } if *tag == t.tag => return Some(missing),
+                    Pattern::Tag {
+                        tag,
+                        inner: Some(inner),
+                    } if *tag == t.tag => inners.push(inner.as_ref()),
+                    _ => continue,
+                }
+            }
+            if inners.is_empty() {
+                missing.push(format!("#{}", t.tag));
+            } else {
+                // If we can't enumerate the payload cases, then there are
+                // payloads that we don't match, but we can't list them.
+                match missing_cases(&t.value, &inners) {
+                    None => missing.push(format!("#{} _", t.tag)),
+                    Some(inner_missing) => missing.extend(
+                        inner_missing
+                            .into_iter()
+                            .map(|m| format!("#{} {}", t.tag, m)),
+                    ),
+                }
+            }
+        }
+        Type::Union(u) => {
+            for member in u.members.iter() {
+                missing.extend(missing_cases(member, patterns)?);
+            }
+        }
+        _ => return None,
+    }
+    Some(missing)
+}
+
+/// Return the expected tagged type with the given tag, if we expect one.
+///
+/// This is the case when the expected type is that tagged type, or a union that
+/// contains a tagged type with that tag.
+fn expected_tag<'t>(expected: &'t SourcedType, tag: &str) -> Option<&'t Tagged> {
+    match &expected.type_ {
+        Type::Tagged(t) if t.tag.as_ref() == tag => Some(t),
+        Type::Union(u) => u.members.iter().find_map(|m| match &m.type_ {
+            Type::Tagged(t) if t.tag.as_ref() == tag => Some(t.as_ref()),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
 /// Construct a `SourcedType` for an operator.
+/// For a union of collections of the same kind, return that kind of collection.
+///
+/// The element type is the join of the member element types, so iterating a
+/// `Union[List[Int], List[String]]` yields elements of `Union[Int, String]`.
+fn join_collection_union(union: &Union) -> Option<SourcedType> {
+    let mut members = union.members.iter();
+    let first = members.next()?.clone();
+    let joined = members.fold(first, |acc, member| acc.join(member));
+    match joined.type_ {
+        Type::List(..) | Type::Set(..) | Type::Dict(..) => Some(joined),
+        _ => None,
+    }
+}
+
 fn type_operator(at: Span, type_: Type) -> SourcedType {
     SourcedType {
         type_,
@@ -247,15 +421,278 @@ fn type_operator(at: Span, type_: Type) -> SourcedType {
     }
 }
 
+/// Report keys that a dict literal defines more than once.
+///
+/// Only literal keys that the dict defines unconditionally are compared. A key
+/// in a comprehension or under an `if` may not be defined at all, and the
+/// pattern `{ a = 1, if c: a = 2 }` is a legitimate way to override a default.
+fn check_duplicate_keys(seqs: &[Seq]) -> Result<()> {
+    let mut keys: BTreeMap<Value, Span> = BTreeMap::new();
+    for seq in seqs {
+        let mut seq = seq;
+        while let Seq::Stmt { body, .. } = seq {
+            seq = body;
+        }
+        let (key_span, key) = match seq {
+            Seq::Yield(Yield::Assoc { key_span, key, .. }) => (*key_span, key),
+            _ => continue,
+        };
+        let key = match key.as_ref() {
+            Expr::NullLit => Value::Null,
+            Expr::BoolLit(b) => Value::Bool(*b),
+            Expr::IntegerLit(i) => Value::Int(*i),
+            Expr::FloatLit(x) => Value::Float(*x),
+            Expr::StringLit(s) => Value::String(s.into()),
+            _ => continue,
+        };
+        if let Some(first_span) = keys.get(&key) {
+            return key_span
+                .error(concat! {
+                    "Duplicate key "
+                    format_rcl(&key).into_owned()
+                    " in dict."
+                })
+                .with_note(*first_span, "The key is first defined here.")
+                .err();
+        }
+        keys.insert(key, key_span);
+    }
+    Ok(())
+}
+
+/// Evaluate a condition statically, if it does not depend on any variables.
+///
+/// This is deliberately limited to boolean and integer literals combined with
+/// operators. It exists to find dead code, not to optimize the program.
+fn fold_condition(expr: &Expr) -> Option<bool> {
+    fn fold_int(expr: &Expr) -> Option<i64> {
+        match expr {
+            Expr::IntegerLit(i) => Some(*i),
+            _ => None,
+        }
+    }
+    match expr {
+        Expr::BoolLit(b) => Some(*b),
+        Expr::UnOp {
+            op: UnOp::Not,
+            body,
+            ..
+        } => fold_condition(body).map(|b| !b),
+        // Like at runtime, `and` and `or` short-circuit, so `false and x` is
+        // false even when `x` is not constant.
+        Expr::BinOp {
+            op: BinOp::And,
+            lhs,
+            rhs,
+            ..
+        } => match fold_condition(lhs)? {
+            false => Some(false),
+            true => fold_condition(rhs),
+        },
+        Expr::BinOp {
+            op: BinOp::Or,
+            lhs,
+            rhs,
+            ..
+        } => match fold_condition(lhs)? {
+            true => Some(true),
+            false => fold_condition(rhs),
+        },
+        Expr::BinOp { op, lhs, rhs, .. } => match (fold_int(lhs), fold_int(rhs)) {
+            (Some(x), Some(y)) => match op {
+                BinOp::Eq => Some(x == y),
+                BinOp::Neq => Some(x != y),
+                BinOp::Lt => Some(x < y),
+                BinOp::LtEq => Some(x <= y),
+                BinOp::Gt => Some(x > y),
+                BinOp::GtEq => Some(x >= y),
+                _ => None,
+            },
+            _ => match (fold_condition(lhs), fold_condition(rhs), op) {
+                (Some(x), Some(y), BinOp::Eq) => Some(x == y),
+                (Some(x), Some(y), BinOp::Neq) => Some(x != y),
+                _ => None,
+            },
+        },
+        _ => None,
+    }
+}
+
+/// What the typechecker knows about a binding in its environment.
+#[derive(Clone, Default)]
+struct BindingSite {
+    /// Where the binding was defined, `None` for bindings from the prelude.
+    span: Option<Span>,
+
+    /// Whether any variable so far refers to this binding.
+    is_used: bool,
+
+    /// The first `let` that shadows this binding, if any.
+    shadowed_by: Option<Span>,
+}
+
 pub struct TypeChecker<'a> {
     // TODO: Do I really need to borrow it?
     // Could also move it into and out of the checker.
     env: &'a mut Env,
+
+    /// Where each binding in `env` was defined, and whether it is used.
+    ///
+    /// Bindings from the prelude have a default entry. Entries past the end of
+    /// `env` are stale, they belong to bindings that were popped.
+    binding_sites: Vec<BindingSite>,
+
+    /// Warnings found during typechecking, such as non-exhaustive matches.
+    pub warnings: Vec<Warning>,
+
+    /// Whether a `let` may shadow an outer binding without a warning.
+    pub allow_shadowing: bool,
 }
 
 impl<'a> TypeChecker<'a> {
     pub fn new(env: &'a mut Env) -> TypeChecker<'a> {
-        TypeChecker { env }
+        TypeChecker {
+            env,
+            binding_sites: Vec::new(),
+            warnings: Vec::new(),
+            allow_shadowing: false,
+        }
+    }
+
+    /// Bind a name in the environment, and remember where it was defined.
+    fn bind(&mut self, span: Span, name: Ident, type_: SourcedType) {
+        let n = self.env.bindings().len();
+        self.binding_sites.truncate(n);
+        self.binding_sites.resize(n, BindingSite::default());
+        self.binding_sites.push(BindingSite {
+            span: Some(span),
+            ..BindingSite::default()
+        });
+        self.env.push(name, type_);
+    }
+
+    /// Bind the name of a `let`, and warn if it shadows an outer binding.
+    ///
+    /// In a long document, a `let` far away from its use can silently change
+    /// which value a name refers to, so we point at both definitions.
+    fn bind_let(&mut self, span: Span, name: Ident, type_: SourcedType) {
+        let bindings = self.env.bindings();
+        let outer = bindings.iter().rposition(|(k, _v)| *k == name);
+        if let Some(site) = outer.and_then(|i| self.binding_sites.get_mut(i)) {
+            // Pops only remove bindings at the end, so below the length of
+            // `env`, the sites still belong to the same bindings.
+            site.shadowed_by.get_or_insert(span);
+        }
+        if let (Some(i), false) = (outer, self.allow_shadowing) {
+            let outer_span = self.binding_sites.get(i).and_then(|site| site.span);
+            let message = concat! {
+                "This " Doc::highlight("let") " shadows an outer binding of "
+                Doc::highlight(name.as_ref()).into_owned() "."
+            };
+            let warning = match outer_span {
+                Some(outer_span) => Warning::new(span, message)
+                    .with_note(outer_span, "The outer binding is defined here."),
+                None => Warning::new(span, message),
+            };
+            self.warnings.push(warning);
+        }
+        self.bind(span, name, type_);
+    }
+
+    /// Look up the type of a variable, and record that the binding is used.
+    fn lookup_var(&mut self, name: &Ident) -> Option<&SourcedType> {
+        let i = self.env.bindings().iter().rposition(|(k, _v)| k == name)?;
+        if let Some(site) = self.binding_sites.get_mut(i) {
+            site.is_used = true;
+        }
+        Some(&self.env.bindings()[i].1)
+    }
+
+    /// At the end of the scope of a `let`, warn if it was shadowed before use.
+    ///
+    /// `index` is the position of the binding in the environment. A binding
+    /// that is shadowed before anything refers to it can never affect the
+    /// result, which is usually a mistake in the name of one of the two.
+    fn check_let_used(&mut self, stmt: &Stmt, index: usize) {
+        let ident_span = match stmt {
+            Stmt::Let { ident_span, .. } => *ident_span,
+            _ => return,
+        };
+        let site = match self.binding_sites.get(index) {
+            Some(site) if !site.is_used => site,
+            _ => return,
+        };
+        if let Some(shadow_span) = site.shadowed_by {
+            let warning = Warning::new(
+                ident_span,
+                "This binding is never used, it is shadowed before any use.",
+            )
+            .with_note(shadow_span, "It is shadowed here.");
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Check that the pattern can match a value of the given type, and bind names.
+    ///
+    /// A pattern that can never match is a type error, because the arm would be
+    /// dead code, which is likely a mistake.
+    fn check_pattern(
+        &mut self,
+        value_type: &SourcedType,
+        value_span: Span,
+        pattern_span: Span,
+        pattern: &Pattern,
+    ) -> Result<()> {
+        match pattern {
+            Pattern::Wildcard => Ok(()),
+            Pattern::Binding(span, name) => {
+                self.bind(*span, name.clone(), value_type.clone());
+                Ok(())
+            }
+            Pattern::Literal(literal) => {
+                let literal_type = type_literal(pattern_span, literal_type(literal));
+                match literal_type.is_subtype_of(value_type) {
+                    TypeDiff::Ok(..) | TypeDiff::Defer(..) => Ok(()),
+                    TypeDiff::Error(..) => {
+                        let mut error =
+                            pattern_span
+                                .error("This arm can never match.")
+                                .with_body(concat! {
+                                    "Expected a pattern for this type:"
+                                    Doc::HardBreak Doc::HardBreak
+                                    indent! { format_type(&value_type.type_).into_owned() }
+                                });
+                        value_type.explain_error(Side::Expected, &mut error);
+                        error.err()
+                    }
+                }
+            }
+            Pattern::Tag { tag, inner } => {
+                let payload_type = match narrow_tag(value_type, tag) {
+                    Some(t) => t,
+                    None => {
+                        let mut error =
+                            pattern_span
+                                .error("This arm can never match.")
+                                .with_body(concat! {
+                                    "The value has no tag " format_tag(tag).into_owned()
+                                    ", it has this type:"
+                                    Doc::HardBreak Doc::HardBreak
+                                    indent! { format_type(&value_type.type_).into_owned() }
+                                });
+                        value_type.explain_error(Side::Expected, &mut error);
+                        error.add_note(value_span, "This is the value being matched.");
+                        return error.err();
+                    }
+                };
+                match inner {
+                    None => Ok(()),
+                    Some(inner) => {
+                        self.check_pattern(&payload_type, value_span, pattern_span, inner)
+                    }
+                }
+            }
+        }
     }
 
     /// Check that an expression fits the type requirements.
@@ -276,12 +713,21 @@ impl<'a> TypeChecker<'a> {
                 body,
             } => {
                 let ck = self.env.checkpoint();
+                let index = self.env.bindings().len();
                 self.check_stmt(stmt)?;
                 let t = self.check_expr(expected, *body_span, body)?;
+                self.check_let_used(stmt, index);
                 self.env.pop(ck);
                 Typed::Type(t)
             }
 
+            Expr::Import {
+                format: ImportFormat::Text,
+                ..
+            } => type_literal(expr_span, Type::String)
+                .is_subtype_of(expected)
+                .check(expr_span)?,
+
             Expr::Import { .. } => {
                 // TODO: Confirm that the path is a string literal,
                 // we can do that here!
@@ -329,6 +775,9 @@ impl<'a> TypeChecker<'a> {
                 }
 
                 let seq_type = seq_type.into_type(expr_span);
+                if let Type::Dict(..) = seq_type.type_ {
+                    check_duplicate_keys(seqs)?;
+                }
 
                 // Replace the BraceLit node where we don't know if it's a dict
                 // or set with a node where we do know the type. This simplifies
@@ -374,10 +823,35 @@ impl<'a> TypeChecker<'a> {
                 }
             }
 
+            Expr::TupleLit { elements } => {
+                // If we expect a tuple of the same length, then we can check
+                // the elements against their requirements directly. If not,
+                // we infer the tuple type first and report any error after.
+                let elem_reqs = match &expected.type_ {
+                    Type::Tuple(ts) if ts.len() == elements.len() => Some(ts.clone()),
+                    _ => None,
+                };
+                let mut elem_types = Vec::with_capacity(elements.len());
+                for (i, (elem_span, elem)) in elements.iter_mut().enumerate() {
+                    let elem_req = match &elem_reqs {
+                        Some(ts) => &ts[i],
+                        None => type_any(),
+                    };
+                    elem_types.push(self.check_expr(elem_req, *elem_span, elem)?);
+                }
+                let tuple_type = type_literal(expr_span, Type::Tuple(elem_types.into()));
+                match elem_reqs {
+                    Some(..) => Typed::Type(tuple_type),
+                    None => tuple_type.is_subtype_of(expected).check(expr_span)?,
+                }
+            }
+
             Expr::NullLit => type_literal(expr_span, Type::Null).is_subtype_of(expected).check(expr_span)?,
             Expr::BoolLit(..) => type_literal(expr_span, Type::Bool).is_subtype_of(expected).check(expr_span)?,
             Expr::IntegerLit(..) => type_literal(expr_span, Type::Int).is_subtype_of(expected).check(expr_span)?,
+            Expr::FloatLit(..) => type_literal(expr_span, Type::Float).is_subtype_of(expected).check(expr_span)?,
             Expr::StringLit(..) => type_literal(expr_span, Type::String).is_subtype_of(expected).check(expr_span)?,
+            Expr::BytesLit(..) => type_literal(expr_span, Type::Bytes).is_subtype_of(expected).check(expr_span)?,
 
             Expr::Format(fragments) => {
                 // Typecheck the fragments. For now we don't demand statically
@@ -400,16 +874,68 @@ impl<'a> TypeChecker<'a> {
                 ..
             } => {
                 self.check_expr(type_bool_condition(), *condition_span, condition)?;
+                if let Some(value) = fold_condition(condition) {
+                    let (dead_span, branch) = match value {
+                        true => (*span_else, "else"),
+                        false => (*span_then, "then"),
+                    };
+                    let message = concat! {
+                        "This condition is always " Doc::highlight(if value { "true" } else { "false" })
+                        ", the " Doc::highlight(branch) " branch is never evaluated."
+                    };
+                    let warning = Warning::new(*condition_span, message)
+                        .with_note(dead_span, "This branch is dead code.");
+                    self.warnings.push(warning);
+                }
 
                 let type_then = self.check_expr(expected, *span_then, body_then)?;
                 let type_else = self.check_expr(expected, *span_else, body_else)?;
 
-                // The inferred type is the meet of the two sides, which may be
+                // The inferred type is the join of the two sides, which may be
                 // more specific than the requirement (which they satisfy).
-                Typed::Type(type_then.meet(&type_else))
+                Typed::Type(type_then.join(&type_else))
+            }
+
+            Expr::TaggedLit { tag, value_span, value, .. } => {
+                // If we expect this tag, either directly or as one of the
+                // members of a union, then push the payload requirement down.
+                let value_req = expected_tag(expected, tag).map(|t| &t.value).unwrap_or(type_any());
+                let value_type = self.check_expr(value_req, *value_span, value)?;
+                let tagged = Tagged { tag: tag.clone(), value: value_type };
+                let tagged_type = type_literal(expr_span, Type::Tagged(Rc::new(tagged)));
+                tagged_type.is_subtype_of(expected).check(expr_span)?
+            }
+
+            Expr::Match { scrutinee_span, scrutinee, arms } => {
+                let scrutinee_type = self.check_expr(type_any(), *scrutinee_span, scrutinee)?;
+
+                let mut result_type = SourcedType { type_: Type::Void, source: Source::None };
+                for arm in arms.iter_mut() {
+                    let ck = self.env.checkpoint();
+                    self.check_pattern(&scrutinee_type, *scrutinee_span, arm.pattern_span, &arm.pattern)?;
+                    let arm_type = self.check_expr(expected, arm.body_span, &mut arm.body)?;
+                    self.env.pop(ck);
+                    result_type = result_type.join(&arm_type);
+                }
+
+                // When the value can only be one of finitely many cases, warn
+                // about cases that no arm covers, they would fail at runtime.
+                let patterns: Vec<&Pattern> = arms.iter().map(|arm| &arm.pattern).collect();
+                if let Some(missing) = missing_cases(&scrutinee_type, &patterns) {
+                    if !missing.is_empty() {
+                        let message = concat! {
+                            "This match is not exhaustive, no arm matches: "
+                            Doc::join(missing.into_iter().map(Doc::from), ", ".into())
+                            "."
+                        };
+                        self.warnings.push(Warning::new(*scrutinee_span, message));
+                    }
+                }
+
+                Typed::Type(result_type)
             }
 
-            Expr::Var { span, ident } => match self.env.lookup(ident) {
+            Expr::Var { span, ident } => match self.lookup_var(ident) {
                 None => return span.error("Unknown variable.").err(),
                 Some(t) => t.is_subtype_of(expected).check(*span)?,
             },
@@ -467,7 +993,9 @@ impl<'a> TypeChecker<'a> {
 
                         &f.result
                     },
-                    Type::Any => {
+                    // For a union, some members may be functions, but we
+                    // don't try to call them statically, we treat it as Any.
+                    Type::Any | Type::Union(..) => {
                         // If we don't know the function type, then we don't have
                         // any expectations on the arguments, but we still need
                         // to typecheck them.
@@ -493,18 +1021,37 @@ impl<'a> TypeChecker<'a> {
                 let (index_type, result_type) = match &collection_type.type_ {
                     Type::List(t) => (type_int_index(), (**t).clone()),
                     Type::Dict(kv) => (&kv.key, kv.value.clone()),
-                    Type::Any => (type_any(), type_any().clone()),
-                    Type::String => {
-                        return open
-                            .error("Indexing into a string is not yet supported.")
-                            .with_note(*collection_span, "This is a string.")
-                            .err()
+                    Type::Tuple(ts) => {
+                        let result_type = match index.as_ref() {
+                            // With a literal index, we know which element we get.
+                            Expr::IntegerLit(i) => match ts.len() as i64 {
+                                n if (0..n).contains(i) => ts[*i as usize].clone(),
+                                n if (-n..0).contains(i) => ts[(n + i) as usize].clone(),
+                                n => {
+                                    let error = concat! {
+                                        "Index " i.to_string()
+                                        " is out of bounds for tuple of length "
+                                        n.to_string() "."
+                                    };
+                                    return index_span.error(error).err();
+                                }
+                            },
+                            // Otherwise, it can be any of the elements.
+                            _ => ts.iter().fold(SourcedType::void(*collection_span), |acc, t| acc.join(t)),
+                        };
+                        (type_int_index_tuple(), result_type)
                     }
+                    Type::Any | Type::Union(..) => (type_any(), type_any().clone()),
+                    // Indexing a string yields a string with a single character.
+                    Type::String => (type_int_index_string(), SourcedType {
+                        type_: Type::String,
+                        source: Source::None,
+                    }),
                     not_indexable => {
                         let mut error = open
                             .error("Indexing is not supported here.")
                             .with_body(concat!{
-                                "Expected a dict or list, but got:"
+                                "Expected a dict, list, string, or tuple, but got:"
                                 Doc::HardBreak Doc::HardBreak
                                 indent! { format_type(not_indexable).into_owned() }
                             });
@@ -584,7 +1131,7 @@ impl<'a> TypeChecker<'a> {
                         type_: arg_type.type_.clone(),
                     };
                     arg_types.push(fn_arg);
-                    self.env.push(arg_name.clone(), arg_type.type_.clone());
+                    self.bind(*arg_span, arg_name.clone(), arg_type.type_.clone());
                 }
                 &fn_req.result
             }
@@ -602,7 +1149,7 @@ impl<'a> TypeChecker<'a> {
                         type_: type_any().clone(),
                     };
                     arg_types.push(fn_arg);
-                    self.env.push(arg_name.clone(), type_any().clone());
+                    self.bind(*arg_span, arg_name.clone(), type_any().clone());
                 }
                 type_any()
             }
@@ -692,17 +1239,17 @@ impl<'a> TypeChecker<'a> {
         let result_type = match (&lhs_type.type_, &rhs_type.type_) {
             // TODO: There rules are a bit ad-hoc. Maybe don't allow | with
             // list? Or do allow, but allow it on the left-hand side too?
-            (Type::Dict(..), Type::Dict(..)) => lhs_type.meet(&rhs_type),
-            (Type::Set(..), Type::Set(..)) => lhs_type.meet(&rhs_type),
+            (Type::Dict(..), Type::Dict(..)) => lhs_type.join(&rhs_type),
+            (Type::Set(..), Type::Set(..)) => lhs_type.join(&rhs_type),
             (Type::Set(tl), Type::List(tr)) => SourcedType {
-                type_: Type::Set(Rc::new(tl.meet(tr.as_ref()))),
+                type_: Type::Set(Rc::new(tl.join(tr.as_ref()))),
                 source: Source::None,
             },
             // TODO: Because of this case, we still have to handle the case at
             // runtime. But we would need a way to express as type requirement
             // "Set or Dict". That gets messy, I think I prefer to delete the
             // union operator and add interpolation instead.
-            (Type::Any | Type::Dict(..) | Type::Set(..), _) => type_any().clone(),
+            (Type::Any | Type::Union(..) | Type::Dict(..) | Type::Set(..), _) => type_any().clone(),
             (not_collection, _) => {
                 let mut error = op_span.error(concat! {
                     "Expected Dict or Set as the left-hand side of "
@@ -724,8 +1271,10 @@ impl<'a> TypeChecker<'a> {
             Seq::Yield(yield_) => self.check_yield(yield_, seq_type),
             Seq::Stmt { stmt, body } => {
                 let ck = self.env.checkpoint();
+                let index = self.env.bindings().len();
                 self.check_stmt(stmt)?;
                 let t = self.check_seq(body, seq_type)?;
+                self.check_let_used(stmt, index);
                 self.env.pop(ck);
                 Ok(t)
             }
@@ -738,14 +1287,20 @@ impl<'a> TypeChecker<'a> {
                 ..
             } => {
                 let collection_type = self.check_expr(type_any(), *collection_span, collection)?;
+                let collection_type = match &collection_type.type_ {
+                    Type::Union(union) => join_collection_union(union).unwrap_or(collection_type),
+                    _ => collection_type,
+                };
                 let ck = self.env.checkpoint();
 
                 match &collection_type.type_ {
                     // If we don't know the type, we can't verify the number of
-                    // loop variables, and we don't know their types.
-                    Type::Any => {
+                    // loop variables, and we don't know their types. For a union
+                    // of different kinds of collections, we would need to know
+                    // which member we have at runtime.
+                    Type::Any | Type::Union(..) => {
                         for ident in idents {
-                            self.env.push(ident.clone(), type_any().clone());
+                            self.bind(*idents_span, ident.clone(), type_any().clone());
                         }
                     }
                     Type::Dict(dict) => {
@@ -760,32 +1315,14 @@ impl<'a> TypeChecker<'a> {
                                 )
                                 .err();
                         }
-                        self.env.push(idents[0].clone(), dict.key.clone());
-                        self.env.push(idents[1].clone(), dict.value.clone());
+                        self.bind(*idents_span, idents[0].clone(), dict.key.clone());
+                        self.bind(*idents_span, idents[1].clone(), dict.value.clone());
                     }
                     Type::List(element_type) => {
-                        if idents.len() != 1 {
-                            return idents_span
-                                .error("Expected a single variable.")
-                                .with_note(
-                                    *collection_span,
-                                    "This is a list, it yields one element per iteration.",
-                                )
-                                .err();
-                        }
-                        self.env.push(idents[0].clone(), (**element_type).clone());
+                        self.push_elements(idents_span, collection_span, idents, element_type)?;
                     }
                     Type::Set(element_type) => {
-                        if idents.len() != 1 {
-                            return idents_span
-                                .error("Expected a single variable.")
-                                .with_note(
-                                    *collection_span,
-                                    "This is a set, it yields one element per iteration.",
-                                )
-                                .err();
-                        }
-                        self.env.push(idents[0].clone(), (**element_type).clone());
+                        self.push_elements(idents_span, collection_span, idents, element_type)?;
                     }
                     not_collection => {
                         return collection_span
@@ -809,11 +1346,75 @@ impl<'a> TypeChecker<'a> {
                 body,
             } => {
                 self.check_expr(type_bool_condition(), *condition_span, condition)?;
+                // A condition that is always true is redundant, but harmless.
+                // One that is always false makes the body dead code.
+                if fold_condition(condition) == Some(false) {
+                    let message = concat! {
+                        "This condition is always " Doc::highlight("false")
+                        ", the body of the " Doc::highlight("if") " is never evaluated."
+                    };
+                    self.warnings.push(Warning::new(*condition_span, message));
+                }
                 self.check_seq(body, seq_type)
             }
         }
     }
 
+    /// Bind the loop variables of a comprehension over a list or set.
+    ///
+    /// With a single variable, it binds the element. With multiple variables,
+    /// the elements must be tuples of that length, and we unpack them.
+    fn push_elements(
+        &mut self,
+        idents_span: &Span,
+        collection_span: &Span,
+        idents: &[Ident],
+        element_type: &SourcedType,
+    ) -> Result<()> {
+        if let [ident] = idents {
+            self.bind(*idents_span, ident.clone(), element_type.clone());
+            return Ok(());
+        }
+        match &element_type.type_ {
+            Type::Tuple(elems) if elems.len() == idents.len() => {
+                for (ident, elem) in idents.iter().zip(elems.iter()) {
+                    self.bind(*idents_span, ident.clone(), elem.clone());
+                }
+            }
+            // If we don't know the element type statically, the evaluator
+            // checks every element at runtime.
+            Type::Any | Type::Union(..) => {
+                for ident in idents {
+                    self.bind(*idents_span, ident.clone(), type_any().clone());
+                }
+            }
+            // For an empty collection, the variables are never bound.
+            Type::Void => {
+                for ident in idents {
+                    self.bind(*idents_span, ident.clone(), element_type.clone());
+                }
+            }
+            not_tuple => {
+                return idents_span
+                    .error("Cannot unpack the elements into these variables.")
+                    .with_body(concat! {
+                        "Expected a tuple of "
+                        idents.len().to_string()
+                        " elements, but the elements have type:"
+                        Doc::HardBreak Doc::HardBreak
+                        indent! { format_type(not_tuple).into_owned() }
+                    })
+                    .with_note(
+                        *collection_span,
+                        "Elements come from this collection. \
+                        With a single variable, it binds the entire element.",
+                    )
+                    .err();
+            }
+        }
+        Ok(())
+    }
+
     /// Visit a yield inside a sequence literal.
     fn check_yield(&mut self, yield_: &mut Yield, mut seq_type: SeqType) -> Result<SeqType> {
         match yield_ {
@@ -827,7 +1428,7 @@ impl<'a> TypeChecker<'a> {
                     // That gives us an inferred type that can be more precise.
                     // Meet it with what we have so far.
                     let elem_type = self.check_expr(elem_super, *span, value)?;
-                    *elem_infer = elem_infer.meet(&elem_type);
+                    *elem_infer = elem_infer.join(&elem_type);
                     Ok(seq_type)
                 }
                 SeqType::TypedDict { dict_source, .. } => {
@@ -837,9 +1438,9 @@ impl<'a> TypeChecker<'a> {
                     dict_source.explain_error(Side::Expected, &mut error);
                     error.err()
                 }
-                SeqType::UntypedList(elem_type_meet) | SeqType::UntypedSet(.., elem_type_meet) => {
+                SeqType::UntypedList(elem_type_join) | SeqType::UntypedSet(.., elem_type_join) => {
                     let elem_type = self.check_expr(type_any(), *span, value)?;
-                    *elem_type_meet = elem_type_meet.meet(&elem_type);
+                    *elem_type_join = elem_type_join.join(&elem_type);
                     Ok(seq_type)
                 }
                 SeqType::UntypedDict(first, _k, _v) => {
@@ -861,8 +1462,8 @@ impl<'a> TypeChecker<'a> {
                 SeqType::TypedDict { key_super, key_infer, value_super, value_infer, .. } => {
                     let k = self.check_expr(key_super, *key_span, key)?;
                     let v = self.check_expr(value_super, *value_span, value)?;
-                    *key_infer = key_infer.meet(&k);
-                    *value_infer = value_infer.meet(&v);
+                    *key_infer = key_infer.join(&k);
+                    *value_infer = value_infer.join(&v);
                     Ok(seq_type)
                 }
                 SeqType::TypedList { .. } | SeqType::UntypedList(..) => op_span
@@ -884,11 +1485,11 @@ impl<'a> TypeChecker<'a> {
                         "The collection is a set and not a dict, because it starts with a scalar value.",
                     )
                     .err(),
-                SeqType::UntypedDict(_first, key_meet, value_meet) => {
+                SeqType::UntypedDict(_first, key_join, value_join) => {
                     let k = self.check_expr(type_any(), *key_span, key)?;
                     let v = self.check_expr(type_any(), *value_span, value)?;
-                    *key_meet = key_meet.meet(&k);
-                    *value_meet = value_meet.meet(&v);
+                    *key_join = key_join.join(&k);
+                    *value_join = value_join.join(&v);
                     Ok(seq_type)
                 }
             }
@@ -898,7 +1499,7 @@ impl<'a> TypeChecker<'a> {
     fn check_stmt(&mut self, stmt: &mut Stmt) -> Result<()> {
         match stmt {
             Stmt::Let {
-                ident_span: _,
+                ident_span,
                 ident,
                 type_,
                 value_span,
@@ -918,7 +1519,63 @@ impl<'a> TypeChecker<'a> {
                     None => inferred,
                     Some(_) => required_type,
                 };
-                self.env.push(ident.clone(), bound_type);
+                self.bind_let(*ident_span, ident.clone(), bound_type);
+
+                Ok(())
+            }
+            Stmt::LetTuple {
+                idents_span,
+                idents,
+                type_,
+                value_span,
+                value,
+            } => {
+                // This follows the same structure as `Let`, see comments above.
+                let required_type = match type_ {
+                    None => type_any().clone(),
+                    Some(type_expr) => eval_type_expr(type_expr)?,
+                };
+                let inferred = self.check_expr(&required_type, *value_span, value)?;
+                let bound_type = match type_ {
+                    None => inferred,
+                    Some(_) => required_type,
+                };
+
+                match &bound_type.type_ {
+                    Type::Tuple(ts) if ts.len() == idents.len() => {
+                        for ((span, ident), t) in idents.iter().zip(ts.iter()) {
+                            self.bind_let(*span, ident.clone(), t.clone());
+                        }
+                    }
+                    Type::Tuple(ts) => {
+                        let note = match ts.len() {
+                            1 => "This is a tuple with 1 element.".into(),
+                            n => concat! { "This is a tuple with " n.to_string() " elements." },
+                        };
+                        return idents_span
+                            .error("Number of variables does not match the tuple length.")
+                            .with_note(*value_span, note)
+                            .err();
+                    }
+                    // If we don't know the type, the evaluator checks the
+                    // shape of the value at runtime.
+                    Type::Any | Type::Union(..) => {
+                        for (span, ident) in idents.iter() {
+                            self.bind_let(*span, ident.clone(), type_any().clone());
+                        }
+                    }
+                    not_tuple => {
+                        let mut error = idents_span
+                            .error("Only tuples can be destructured.")
+                            .with_body(concat! {
+                                "Expected a tuple, but got:"
+                                Doc::HardBreak Doc::HardBreak
+                                indent! { format_type(not_tuple).into_owned() }
+                            });
+                        bound_type.explain_error(Side::Actual, &mut error);
+                        return error.err();
+                    }
+                }
 
                 Ok(())
             }
@@ -965,7 +1622,7 @@ enum SeqType {
     TypedList {
         /// The required element type. Supertype of the inferred type.
         elem_super: SourcedType,
-        /// The inferred element type, the `meet` of all elements.
+        /// The inferred element type, the `join` of all elements.
         elem_infer: SourcedType,
     },
 
@@ -975,7 +1632,7 @@ enum SeqType {
         set_source: SourcedType,
         /// The required element type. Supertype of the inferred type.
         elem_super: SourcedType,
-        /// The inferred element type, the `meet` of all elements.
+        /// The inferred element type, the `join` of all elements.
         elem_infer: SourcedType,
     },
 
@@ -985,7 +1642,7 @@ enum SeqType {
         dict_source: SourcedType,
         /// The required key type. Supertype of the inferred type.
         key_super: SourcedType,
-        /// The inferred key type, the `meet` of all keys.
+        /// The inferred key type, the `join` of all keys.
         key_infer: SourcedType,
         /// The required value type. Supertype of the inferred type.
         value_super: SourcedType,
@@ -993,17 +1650,17 @@ enum SeqType {
         value_infer: SourcedType,
     },
 
-    /// We found a list, and the meet of the elements is as follows.
+    /// We found a list, and the join of the elements is as follows.
     UntypedList(SourcedType),
 
     /// We found a set, as evidenced by the span of the first scalar.
     ///
-    /// We also track the `meet` of all the elements.
+    /// We also track the `join` of all the elements.
     UntypedSet(Span, SourcedType),
 
     /// We found a dict, as evidenced by the span of the first key-value.
     ///
-    /// We also track the `meet` of the key and value types.
+    /// We also track the `join` of the key and value types.
     UntypedDict(Span, SourcedType, SourcedType),
 }
 
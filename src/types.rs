@@ -41,9 +41,15 @@ pub enum Type {
     /// The primitive type `Int`.
     Int,
 
+    /// The primitive type `Float`.
+    Float,
+
     /// The primitive type `String`.
     String,
 
+    /// The primitive type `Bytes`.
+    Bytes,
+
     /// A dict with the given key and value types.
     Dict(Rc<Dict>),
 
@@ -53,6 +59,12 @@ pub enum Type {
     /// A set with the given element type.
     Set(Rc<SourcedType>),
 
+    /// A tuple with a fixed number of elements, each with its own type.
+    Tuple(Rc<[SourcedType]>),
+
+    /// A tagged value with a particular tag.
+    Tagged(Rc<Tagged>),
+
     /// A function.
     Function(Rc<Function>),
 
@@ -65,7 +77,14 @@ impl Type {
     pub fn is_atom(&self) -> bool {
         matches!(
             self,
-            Type::Bool | Type::Int | Type::Null | Type::String | Type::Void | Type::Any,
+            Type::Bool
+                | Type::Bytes
+                | Type::Int
+                | Type::Float
+                | Type::Null
+                | Type::String
+                | Type::Void
+                | Type::Any,
         )
     }
 
@@ -80,10 +99,14 @@ impl Type {
             Type::Null => "Null",
             Type::Bool => "Bool",
             Type::Int => "Int",
+            Type::Float => "Float",
             Type::String => "String",
+            Type::Bytes => "Bytes",
             Type::Dict(..) => "Dict",
             Type::List(..) => "List",
             Type::Set(..) => "Set",
+            Type::Tuple(..) => "Tuple",
+            Type::Tagged(..) => "Tagged",
             Type::Function(..) => "Function",
             Type::Union(..) => "Union",
         }
@@ -97,6 +120,14 @@ pub struct Dict {
     pub value: SourcedType,
 }
 
+/// The tag and payload type of a `Tagged` type.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Tagged {
+    /// The tag, without the leading `#`.
+    pub tag: Rc<str>,
+    pub value: SourcedType,
+}
+
 /// An argument in a function type.
 ///
 /// The names are ignored for equality and comparison purposes, but we track
@@ -341,12 +372,6 @@ impl Union {
         source: Source,
         other: &SourcedType,
     ) -> TypeDiff<SourcedType> {
-        if let Type::Union(_) = &other.type_ {
-            // For now, union/union typechecks are out of scope, we defer to a
-            // runtime check.
-            return TypeDiff::Defer(other.clone());
-        }
-
         let mut n_ok: u32 = 0;
         let mut n_err: u32 = 0;
 
@@ -362,7 +387,7 @@ impl Union {
 
         if all_ok {
             // If all of the candidates are a subtype, then the entire union is
-            // a subtype. We could meet all the candidates and that might be a
+            // a subtype. We could join all the candidates and that might be a
             // more precise type than the expected type, but it's also expensive
             // to do, so we just return the expected type as the upper bound.
             TypeDiff::Ok(other.clone())
@@ -387,6 +412,26 @@ impl Union {
     }
 }
 
+/// Add a type to the members of a union under construction.
+///
+/// If there is already a member that the new type can be joined with without
+/// producing a union, then we join it into that member instead.
+fn push_union_member(members: &mut Vec<SourcedType>, new: &SourcedType) {
+    for member in members.iter_mut() {
+        let is_same_kind = match (&member.type_, &new.type_) {
+            (Type::Function(f1), Type::Function(f2)) => f1 == f2,
+            (Type::Tuple(t1), Type::Tuple(t2)) => t1.len() == t2.len(),
+            (Type::Tagged(t1), Type::Tagged(t2)) => t1.tag == t2.tag,
+            (t1, t2) => std::mem::discriminant(t1) == std::mem::discriminant(t2),
+        };
+        if is_same_kind {
+            *member = member.join(new);
+            return;
+        }
+    }
+    members.push(new.clone());
+}
+
 /// What side to explain the source of a type for.
 pub enum Side {
     Expected,
@@ -419,10 +464,11 @@ impl SourcedType {
 
     /// Return the least possible supertype of the two types.
     ///
-    /// The meet is a type `T` such that `self` and `other` are both subtypes
-    /// of `T`.
+    /// The join is a type `T` such that `self` and `other` are both subtypes
+    /// of `T`. When the two types have no common structure, the join is the
+    /// union of the two.
     /// TODO: This should take self and other by value.
-    pub fn meet(&self, other: &SourcedType) -> SourcedType {
+    pub fn join(&self, other: &SourcedType) -> SourcedType {
         let src_meet = self.source.meet(&other.source);
         let (type_, source) = match (&self.type_, &other.type_) {
             // Anything involving any becomes any, anything involving
@@ -436,45 +482,89 @@ impl SourcedType {
             // If we have matching primitive types, they are preserved.
             (Type::Bool, Type::Bool) => (Type::Bool, src_meet),
             (Type::Int, Type::Int) => (Type::Int, src_meet),
+            (Type::Float, Type::Float) => (Type::Float, src_meet),
             (Type::Null, Type::Null) => (Type::Null, src_meet),
             (Type::String, Type::String) => (Type::String, src_meet),
+            (Type::Bytes, Type::Bytes) => (Type::Bytes, src_meet),
 
-            // For composite types, we meet on their elements.
+            // For composite types, we join on their elements.
             (Type::Dict(d1), Type::Dict(d2)) => {
-                // TODO: If the meets don't change the key and value type,
+                // TODO: If the joins don't change the key and value type,
                 // we can recycle the original instead of making a new one.
                 let dm = Rc::new(Dict {
-                    key: d1.key.meet(&d2.key),
-                    value: d1.value.meet(&d2.value),
+                    key: d1.key.join(&d2.key),
+                    value: d1.value.join(&d2.value),
                 });
                 // TODO: If the types are the same on both sides, we can meet the sources.
                 (Type::Dict(dm), Source::None)
             }
             (Type::List(l1), Type::List(l2)) => {
-                let type_ = Type::List(Rc::new(l1.meet(l2)));
+                let type_ = Type::List(Rc::new(l1.join(l2)));
                 // TODO: If the types are the same on both sides, we can meet the sources.
                 (type_, Source::None)
             }
             (Type::Set(s1), Type::Set(s2)) => {
-                let type_ = Type::Set(Rc::new(s1.meet(s2)));
+                let type_ = Type::Set(Rc::new(s1.join(s2)));
                 // TODO: If the types are the same on both sides, we can meet the sources.
                 (type_, Source::None)
             }
+            // Tuples of the same length join element-wise. Tuples of different
+            // lengths have no common structure, they become a union below.
+            (Type::Tuple(t1), Type::Tuple(t2)) if t1.len() == t2.len() => {
+                let elems = t1.iter().zip(t2.iter()).map(|(e1, e2)| e1.join(e2));
+                (Type::Tuple(elems.collect()), Source::None)
+            }
+            // Tagged types with the same tag join on their payload. Different
+            // tags become a union, which is how we represent variants.
+            (Type::Tagged(t1), Type::Tagged(t2)) if t1.tag == t2.tag => {
+                let tagged = Tagged {
+                    tag: t1.tag.clone(),
+                    value: t1.value.join(&t2.value),
+                };
+                (Type::Tagged(Rc::new(tagged)), Source::None)
+            }
 
-            // TODO: Support meeting functions.
-            (Type::Function(_), Type::Function(_)) => (Type::Any, Source::None),
+            // Identical functions join to themselves. For functions with
+            // different types we could join the results and take the meet of
+            // the arguments, but for now we make them a union.
+            (Type::Function(f1), Type::Function(f2)) if f1 == f2 => {
+                (Type::Function(f1.clone()), src_meet)
+            }
 
             // Any two values that mismatch, we can't describe with a single
-            // static type, but that doesn't mean it's a type error, the program
-            // may still be valid at runtime. E.g, I have a list with a function
-            // and an int. If the program only ever calls `list[0]` and performs
-            // integer addition on `list[1]`, that is fine. We can type the list
-            // as `List[Any]`.
-            _ => (Type::Any, Source::None),
+            // non-union type, but that doesn't mean it's a type error, the
+            // program may still be valid at runtime. E.g, I have a list with a
+            // function and an int. If the program only ever calls `list[0]` and
+            // performs integer addition on `list[1]`, that is fine. We can type
+            // the list as `List[Union[Function, Int]]`.
+            _ => return self.join_union(other),
         };
         SourcedType { type_, source }
     }
 
+    /// Join two types that have no common structure into a union.
+    ///
+    /// If either side is a union already, the result is a flat union. Members
+    /// of the same kind (e.g. two lists) are joined rather than added as
+    /// separate members, so a union contains at most one list, one set, etc.
+    fn join_union(&self, other: &SourcedType) -> SourcedType {
+        let mut members: Vec<SourcedType> = Vec::new();
+        for side in [self, other] {
+            match &side.type_ {
+                Type::Union(u) => {
+                    for member in u.members.iter() {
+                        push_union_member(&mut members, member);
+                    }
+                }
+                _ => push_union_member(&mut members, side),
+            }
+        }
+        SourcedType {
+            type_: Type::Union(Rc::new(Union { members })),
+            source: Source::None,
+        }
+    }
+
     /// Return whether `T` (`self`) is a subtype of `U` (`other`).
     ///
     /// What it means to be a subtype: if we take an arbitrary instance `t` of
@@ -518,8 +608,10 @@ impl SourcedType {
             // or will it work fine like this?
             (Type::Bool, Type::Bool) => TypeDiff::Ok(other.clone()),
             (Type::Int, Type::Int) => TypeDiff::Ok(other.clone()),
+            (Type::Float, Type::Float) => TypeDiff::Ok(other.clone()),
             (Type::Null, Type::Null) => TypeDiff::Ok(other.clone()),
             (Type::String, Type::String) => TypeDiff::Ok(other.clone()),
+            (Type::Bytes, Type::Bytes) => TypeDiff::Ok(other.clone()),
 
             // The collection types are covariant in their argument.
             // E.g. `List[Int] < List[Any]`.
@@ -533,6 +625,55 @@ impl SourcedType {
                 TypeDiff::Defer(..) => TypeDiff::Defer(other.clone()),
                 error => TypeDiff::Error(Mismatch::Set(error.into())),
             },
+            (Type::Tuple(t1), Type::Tuple(t2)) => {
+                if t1.len() != t2.len() {
+                    // Like for functions, report a length mismatch directly,
+                    // so we preserve the sources of the types.
+                    return TypeDiff::Error(Mismatch::Atom {
+                        actual: self.clone(),
+                        expected: other.clone(),
+                    });
+                }
+                let diffs: Vec<_> = t1
+                    .iter()
+                    .zip(t2.iter())
+                    .map(|(e1, e2)| e1.is_subtype_of(e2))
+                    .collect();
+                if diffs.iter().all(|d| matches!(d, TypeDiff::Ok(..))) {
+                    return TypeDiff::Ok(self.clone());
+                }
+                if diffs.iter().any(|d| matches!(d, TypeDiff::Error(..))) {
+                    return TypeDiff::Error(Mismatch::Tuple(diffs));
+                }
+                let elems = diffs.into_iter().map(|d| match d {
+                    TypeDiff::Ok(t) | TypeDiff::Defer(t) => t,
+                    TypeDiff::Error(..) => unreachable!("Errors are handled above."),
+                });
+                let styp = SourcedType {
+                    type_: Type::Tuple(elems.collect()),
+                    source: Source::None,
+                };
+                TypeDiff::Defer(styp)
+            }
+            // Tagged types are covariant in their payload, but only if the
+            // tags match. Different tags are incompatible.
+            (Type::Tagged(t1), Type::Tagged(t2)) if t1.tag == t2.tag => {
+                match t1.value.is_subtype_of(&t2.value) {
+                    TypeDiff::Ok(..) => TypeDiff::Ok(self.clone()),
+                    TypeDiff::Defer(value) => {
+                        let tagged = Tagged {
+                            tag: t1.tag.clone(),
+                            value,
+                        };
+                        let styp = SourcedType {
+                            type_: Type::Tagged(Rc::new(tagged)),
+                            source: Source::None,
+                        };
+                        TypeDiff::Defer(styp)
+                    }
+                    error => TypeDiff::Error(Mismatch::Tagged(t1.tag.clone(), error.into())),
+                }
+            }
             (Type::Dict(d1), Type::Dict(d2)) => {
                 let dk = d1.key.is_subtype_of(&d2.key);
                 let dv = d1.value.is_subtype_of(&d2.value);
@@ -673,6 +814,8 @@ impl SourcedType {
             // can only be one help per error. Either extend that, but probably
             // better, add spans to the sources?
             Source::IndexList => error.set_help("List indices must be integers."),
+            Source::IndexTuple => error.set_help("Tuple indices must be integers."),
+            Source::IndexString => error.set_help("String indices must be integers."),
 
             Source::BuildFile(reason) => error.set_help(*reason),
         }
@@ -726,11 +869,15 @@ pub fn builtin(type_: Type) -> SourcedType {
 /// * `Set[T]` is written `{T}`.
 /// * `Dict[K, V]` is written `{K: V}`.
 /// * `(P, Q) -> R` is written `(fn (P, Q) -> R)`
+/// * `Union[P, Q]` is written `(Union[P, Q])`
 macro_rules! make_type {
     (Any) => { builtin(Type::Any) };
+    (Void) => { builtin(Type::Void) };
     (Int) => { builtin(Type::Int) };
+    (Float) => { builtin(Type::Float) };
     (Bool) => { builtin(Type::Bool) };
     (String) => { builtin(Type::String) };
+    (Bytes) => { builtin(Type::Bytes) };
     ([$elem:tt]) => { builtin(Type::List(Rc::new(make_type!($elem)))) };
     ({$elem:tt}) => { builtin(Type::Set(Rc::new(make_type!($elem)))) };
     ({$k:tt: $v:tt}) => {{
@@ -746,6 +893,11 @@ macro_rules! make_type {
             make_function!(($( $arg_name:$arg_type ),*) -> $result)
         )))
     };
+    ((Union[$( $member:tt ),*])) => {
+        builtin(Type::Union(Rc::new(crate::types::Union {
+            members: vec![$( make_type!($member) ),*],
+        })))
+    };
 }
 pub(crate) use make_type;
 
@@ -771,8 +923,9 @@ pub(crate) use make_function;
 
 #[cfg(test)]
 mod test {
-    use super::{Function, FunctionArg, Source, SourcedType, Type};
+    use super::{Function, FunctionArg, Source, SourcedType, Tagged, Type, Union};
     use crate::source::{DocId, Span};
+    use std::rc::Rc;
 
     fn mk_type(type_: Type) -> SourcedType {
         SourcedType {
@@ -834,4 +987,79 @@ mod test {
         f1.args.pop();
         assert!(f1 < f2);
     }
+
+    #[test]
+    fn join_mismatch_builds_flat_union() {
+        let int = mk_type(Type::Int);
+        let string = mk_type(Type::String);
+        let list_int = mk_type(Type::List(Rc::new(int.clone())));
+        let list_string = mk_type(Type::List(Rc::new(string.clone())));
+
+        let u1 = int.join(&string);
+        let expected = Type::Union(Rc::new(Union {
+            members: vec![int.clone(), string.clone()],
+        }));
+        assert_eq!(u1.type_, expected);
+
+        // Joining a union with one of its members does not change it.
+        assert_eq!(u1.join(&int).type_, expected);
+        assert_eq!(u1.join(&string).type_, expected);
+
+        // Members of the same kind get joined, rather than added.
+        let u2 = u1.join(&list_int).join(&list_string);
+        let expected = Type::Union(Rc::new(Union {
+            members: vec![int, string, mk_type(Type::List(Rc::new(u1)))],
+        }));
+        assert_eq!(u2.type_, expected);
+
+        // Any still absorbs everything, also unions.
+        assert_eq!(u2.join(&SourcedType::any()).type_, Type::Any);
+    }
+
+    #[test]
+    fn join_tuples_by_length() {
+        let int = mk_type(Type::Int);
+        let string = mk_type(Type::String);
+        let t_int = mk_type(Type::Tuple(Rc::from([int.clone(), int.clone()])));
+        let t_string = mk_type(Type::Tuple(Rc::from([string.clone(), int.clone()])));
+        let t_short = mk_type(Type::Tuple(Rc::from([int.clone()])));
+
+        // Tuples of the same length join element-wise.
+        let joined = t_int.join(&t_string);
+        let expected = Type::Tuple(Rc::from([int.join(&string), int]));
+        assert_eq!(joined.type_, expected);
+
+        // Tuples of different lengths become a union, also when joined again.
+        let u = joined.join(&t_short).join(&t_int);
+        let expected = Type::Union(Rc::new(Union {
+            members: vec![joined, t_short],
+        }));
+        assert_eq!(u.type_, expected);
+    }
+
+    #[test]
+    fn join_tagged_by_tag() {
+        let int = mk_type(Type::Int);
+        let string = mk_type(Type::String);
+        let mk_tagged = |tag: &str, value: &SourcedType| {
+            mk_type(Type::Tagged(Rc::new(Tagged {
+                tag: tag.into(),
+                value: value.clone(),
+            })))
+        };
+        let tcp_int = mk_tagged("tcp", &int);
+        let tcp_string = mk_tagged("tcp", &string);
+        let udp_int = mk_tagged("udp", &int);
+
+        // The same tag joins on the payload.
+        let joined = tcp_int.join(&tcp_string);
+        assert_eq!(joined.type_, mk_tagged("tcp", &int.join(&string)).type_);
+
+        // Different tags become a union, with one member per tag.
+        let u = joined.join(&udp_int).join(&tcp_int);
+        let expected = Type::Union(Rc::new(Union {
+            members: vec![joined, udp_int],
+        }));
+        assert_eq!(u.type_, expected);
+    }
 }
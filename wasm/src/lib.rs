@@ -98,9 +98,9 @@ fn rcl_evaluate_json_impl<'a>(
     loader.set_filesystem(Box::new(VoidFilesystem));
     let id = loader.load_string(input.to_string());
     let mut tracer = VoidTracer;
+    let mut type_env = loader.type_prelude();
+    let mut value_env = loader.value_prelude();
     let mut evaluator = Evaluator::new(loader, &mut tracer);
-    let mut type_env = rcl::typecheck::prelude();
-    let mut value_env = rcl::runtime::prelude();
     let value = evaluator.eval_doc(&mut type_env, &mut value_env, id)?;
     let body_span = loader.get_span(id);
     let doc = rcl::fmt_json::format_json(body_span, &value)?;
@@ -128,9 +128,9 @@ fn rcl_evaluate_value_impl(input: &str) -> Result<Value> {
     loader.set_filesystem(Box::new(VoidFilesystem));
     let id = loader.load_string(input.to_string());
     let mut tracer = VoidTracer;
+    let mut type_env = loader.type_prelude();
+    let mut value_env = loader.value_prelude();
     let mut evaluator = Evaluator::new(&mut loader, &mut tracer);
-    let mut type_env = rcl::typecheck::prelude();
-    let mut value_env = rcl::runtime::prelude();
     let value = evaluator.eval_doc(&mut type_env, &mut value_env, id)?;
     Ok(value)
 }
@@ -153,9 +153,9 @@ fn rcl_evaluate_query_impl<'a>(
     loader.set_filesystem(Box::new(VoidFilesystem));
     let id = loader.load_string(query.to_string());
     let mut tracer = VoidTracer;
+    let mut type_env = loader.type_prelude();
+    let mut value_env = loader.value_prelude();
     let mut evaluator = Evaluator::new(loader, &mut tracer);
-    let mut type_env = rcl::typecheck::prelude();
-    let mut value_env = rcl::runtime::prelude();
 
     // Provide the value that we previously set in the environment.
     type_env.push("input".into(), rcl::typecheck::type_any().clone());